@@ -11,6 +11,8 @@ fn build_windows() {
 #[cfg(target_os = "macos")]
 fn build_mac() {
     let file = "src/platform/macos.mm";
+    let file_sck = "src/platform/macos_sck.mm";
+    let file_privacy = "src/platform/macos_privacy.mm";
     let mut b = cc::Build::new();
     if let Ok(os_version::OsVersion::MacOS(v)) = os_version::detect() {
         let v = v.version;
@@ -18,8 +20,17 @@ fn build_mac() {
             b.flag("-DNO_InputMonitoringAuthStatus=1");
         }
     }
-    b.file(file).compile("macos");
+    b.file(file)
+        .file(file_sck)
+        .file(file_privacy)
+        .compile("macos");
+    // ScreenCaptureKit is only available on macOS 12.3+.
+    println!("cargo:rustc-link-arg=-Wl,-weak_framework,ScreenCaptureKit");
+    println!("cargo:rustc-link-lib=framework=CoreMedia");
+    println!("cargo:rustc-link-lib=framework=CoreVideo");
     println!("cargo:rerun-if-changed={}", file);
+    println!("cargo:rerun-if-changed={}", file_sck);
+    println!("cargo:rerun-if-changed={}", file_privacy);
 }
 
 #[cfg(all(windows, feature = "inline"))]
@@ -417,6 +417,9 @@ class InputService : AccessibilityService() {
             } else if (tryHandlePowerKeyEvent(event)) {
                 return
             }
+            if (isCopyOrCutKeyEvent(event)) {
+                syncSelectedText()
+            }
         }
 
         if (Build.VERSION.SDK_INT >= 33) {
@@ -495,6 +498,31 @@ class InputService : AccessibilityService() {
         return false
     }
 
+    private fun isCopyOrCutKeyEvent(event: KeyEventAndroid): Boolean {
+        return event.action == KeyEventAndroid.ACTION_DOWN && event.isCtrlPressed &&
+            (event.keyCode == KeyEventAndroid.KEYCODE_C || event.keyCode == KeyEventAndroid.KEYCODE_X)
+    }
+
+    // The clipboard can't be read in the background on Android 10+,
+    // so the selected text is sent to the remote as the host clipboard.
+    // It's read before the key is dispatched, because cut removes the selection.
+    private fun syncSelectedText() {
+        for (node in possibleAccessibiltyNodes()) {
+            node.refresh()
+            if (node.isPassword()) {
+                continue
+            }
+            val text = node.getText() ?: continue
+            val start = minOf(node.textSelectionStart, node.textSelectionEnd)
+            val end = maxOf(node.textSelectionStart, node.textSelectionEnd)
+            if (start < 0 || start == end || end > text.length) {
+                continue
+            }
+            MainActivity.rdClipboardManager?.syncHostText(text.subSequence(start, end).toString())
+            return
+        }
+    }
+
     private fun insertAccessibilityNode(list: LinkedList<AccessibilityNodeInfo>, node: AccessibilityNodeInfo) {
         if (node == null) {
             return
@@ -40,6 +40,7 @@ import androidx.core.content.ContextCompat
 import io.flutter.embedding.android.FlutterActivity
 import java.util.concurrent.Executors
 import kotlin.concurrent.thread
+import org.json.JSONArray
 import org.json.JSONException
 import org.json.JSONObject
 import java.nio.ByteBuffer
@@ -106,6 +107,9 @@ class MainService : Service() {
             "is_start" -> {
                 isStart.toString()
             }
+            "storage_volumes" -> {
+                JSONArray(getStorageVolumePaths(this)).toString()
+            }
             else -> ""
         }
     }
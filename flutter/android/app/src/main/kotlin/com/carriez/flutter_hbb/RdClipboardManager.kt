@@ -68,33 +68,54 @@ class RdClipboardManager(private val clipboardManager: ClipboardManager) {
                 text = clip?.text
                 html = clip?.htmlText
             }
-            var count = 0
-            val clips = MultiClipboards.newBuilder()
-            if (text != null) {
-                val content = com.google.protobuf.ByteString.copyFromUtf8(text.toString())
-                    clips.addClipboards(Clipboard.newBuilder().setFormat(ClipboardFormat.Text).setContent(content).build())
-                    count++
-                }
-            if (html != null) {
-                val content = com.google.protobuf.ByteString.copyFromUtf8(html)
-                clips.addClipboards(Clipboard.newBuilder().setFormat(ClipboardFormat.Html).setContent(content).build())
-                count++
-            }
-            if (count > 0) {
-                val clipsBytes = clips.build().toByteArray()
-                val isClientFlag = if (isClient) 1 else 0
-                val clipsBuf = ByteBuffer.allocateDirect(clipsBytes.size + 1).apply {
-                    put(isClientFlag.toByte())
-                    put(clipsBytes)
-                }
-                clipsBuf.flip()
+            if (sendClipboard(text?.toString(), html, isClient)) {
                 lastUpdatedClipData = clipData
-                Log.d(logTag, "${if (isClient) "client" else "host"}, send clipboard data to the remote")
-                FFI.onClipboardUpdate(clipsBuf)
             }
         }
     }
 
+    // Android 10+ denies reading the clipboard in the background, so the text copied by the
+    // controlling side is taken from the selection of the focused node, see `InputService`.
+    // It's also set to the local clipboard, which is allowed in the background.
+    fun syncHostText(text: String) {
+        Log.d(logTag, "syncHostText: isCaptureStarted: $_isCaptureStarted")
+        if (!_isCaptureStarted || !FFI.isServiceClipboardEnabled()) {
+            return
+        }
+        val clipData = ClipData.newPlainText("clipboard", text)
+        lastUpdatedClipData = clipData
+        clipboardManager.setPrimaryClip(clipData)
+        sendClipboard(text, null, false)
+    }
+
+    private fun sendClipboard(text: String?, html: String?, isClient: Boolean): Boolean {
+        var count = 0
+        val clips = MultiClipboards.newBuilder()
+        if (text != null) {
+            val content = com.google.protobuf.ByteString.copyFromUtf8(text)
+            clips.addClipboards(Clipboard.newBuilder().setFormat(ClipboardFormat.Text).setContent(content).build())
+            count++
+        }
+        if (html != null) {
+            val content = com.google.protobuf.ByteString.copyFromUtf8(html)
+            clips.addClipboards(Clipboard.newBuilder().setFormat(ClipboardFormat.Html).setContent(content).build())
+            count++
+        }
+        if (count == 0) {
+            return false
+        }
+        val clipsBytes = clips.build().toByteArray()
+        val isClientFlag = if (isClient) 1 else 0
+        val clipsBuf = ByteBuffer.allocateDirect(clipsBytes.size + 1).apply {
+            put(isClientFlag.toByte())
+            put(clipsBytes)
+        }
+        clipsBuf.flip()
+        Log.d(logTag, "${if (isClient) "client" else "host"}, send clipboard data to the remote")
+        FFI.onClipboardUpdate(clipsBuf)
+        return true
+    }
+
     private fun isSupportedMimeType(mimeType: String): Boolean {
         return supportedMimeTypes.contains(mimeType)
     }
@@ -10,9 +10,11 @@ import android.media.MediaCodecList
 import android.media.MediaFormat
 import android.net.Uri
 import android.os.Build
+import android.os.Environment
 import android.os.Handler
 import android.os.Looper
 import android.os.PowerManager
+import android.os.storage.StorageManager
 import android.provider.Settings
 import android.provider.Settings.*
 import android.util.DisplayMetrics
@@ -151,6 +153,31 @@ fun getScreenSize(windowManager: WindowManager) : Pair<Int, Int>{
     return Pair(w, h)
 }
 
+// The mount points of the shared storage volumes, including SD cards and USB drives.
+// They are accessed by path with the all files access (MANAGE_EXTERNAL_STORAGE).
+fun getStorageVolumePaths(context: Context): List<String> {
+    val paths = mutableListOf<String>()
+    if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.R) {
+        val storageManager = context.getSystemService(Context.STORAGE_SERVICE) as StorageManager
+        for (volume in storageManager.storageVolumes) {
+            if (volume.state != Environment.MEDIA_MOUNTED) {
+                continue
+            }
+            volume.directory?.let { paths.add(it.absolutePath) }
+        }
+    } else {
+        // The app specific dirs are "<volume>/Android/data/<package>/files".
+        for (dir in context.getExternalFilesDirs(null)) {
+            val path = dir?.absolutePath ?: continue
+            val index = path.indexOf("/Android/data/")
+            if (index > 0) {
+                paths.add(path.substring(0, index))
+            }
+        }
+    }
+    return paths
+}
+
  fun translate(input: String): String {
     Log.d("common", "translate:$LOCAL_NAME")
     return FFI.translateLocale(LOCAL_NAME, input)
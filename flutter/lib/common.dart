@@ -33,6 +33,7 @@ import 'mobile/pages/file_manager_page.dart';
 import 'mobile/pages/remote_page.dart';
 import 'mobile/pages/view_camera_page.dart';
 import 'mobile/pages/terminal_page.dart';
+import 'mobile/pages/port_forward_page.dart';
 import 'desktop/pages/remote_page.dart' as desktop_remote;
 import 'desktop/pages/file_manager_page.dart' as desktop_file_manager;
 import 'desktop/pages/view_camera_page.dart' as desktop_view_camera;
@@ -1646,6 +1647,11 @@ Future<bool> matchPeer(
   if (peer.alias.toLowerCase().contains(searchText)) {
     return true;
   }
+  // e.g. "1.3." to find the peers of an old version
+  if (peer.version.toLowerCase().startsWith(searchText) ||
+      peer.osVersion.toLowerCase().contains(searchText)) {
+    return true;
+  }
   if (peerTabShowNote(peerTabIndex) &&
       peer.note.toLowerCase().contains(searchText)) {
     return true;
@@ -2458,6 +2464,7 @@ connectMainDesktop(String id,
     required bool isTerminal,
     required bool isTcpTunneling,
     required bool isRDP,
+    bool isAudioOnly = false,
     bool? forceRelay,
     String? password,
     String? connToken,
@@ -2490,7 +2497,8 @@ connectMainDesktop(String id,
     await rustDeskWinManager.newRemoteDesktop(id,
         password: password,
         isSharedPassword: isSharedPassword,
-        forceRelay: forceRelay);
+        forceRelay: forceRelay,
+        isAudioOnly: isAudioOnly);
   }
 }
 
@@ -2499,12 +2507,14 @@ connectMainDesktop(String id,
 /// If [isViewCamera], starts a session only for view camera.
 /// If [isTcpTunneling], starts a session only for tcp tunneling.
 /// If [isRDP], starts a session only for rdp.
+/// If [isAudioOnly], starts a session only for the audio of the peer.
 connect(BuildContext context, String id,
     {bool isFileTransfer = false,
     bool isViewCamera = false,
     bool isTerminal = false,
     bool isTcpTunneling = false,
     bool isRDP = false,
+    bool isAudioOnly = false,
     bool forceRelay = false,
     String? password,
     String? connToken,
@@ -2538,6 +2548,7 @@ connect(BuildContext context, String id,
         isTerminal: isTerminal,
         isTcpTunneling: isTcpTunneling,
         isRDP: isRDP,
+        isAudioOnly: isAudioOnly,
         password: password,
         isSharedPassword: isSharedPassword,
         forceRelay: forceRelay,
@@ -2550,6 +2561,7 @@ connect(BuildContext context, String id,
         'isTerminal': isTerminal,
         'isTcpTunneling': isTcpTunneling,
         'isRDP': isRDP,
+        'isAudioOnly': isAudioOnly,
         'password': password,
         'isSharedPassword': isSharedPassword,
         'forceRelay': forceRelay,
@@ -2627,6 +2639,17 @@ connect(BuildContext context, String id,
           ),
         ),
       );
+    } else if (isTcpTunneling && isIOS) {
+      Navigator.push(
+        context,
+        MaterialPageRoute(
+          builder: (BuildContext context) => PortForwardPage(
+              id: id,
+              password: password,
+              isSharedPassword: isSharedPassword,
+              forceRelay: forceRelay),
+        ),
+      );
     } else {
       if (isWeb) {
         Navigator.push(
@@ -3,6 +3,8 @@ import 'package:flutter_hbb/common.dart';
 import 'package:flutter_hbb/models/platform_model.dart';
 
 const _kSystemSound = 'System Sound';
+// Prefix of the audio input capturing one application on Windows.
+const _kProcessPrefix = 'process:';
 
 typedef AudioINputSetDevice = void Function(String device);
 typedef AudioInputBuilder = Widget Function(
@@ -20,6 +22,13 @@ class AudioInput extends StatelessWidget {
       required this.isVoiceCall})
       : super(key: key);
 
+  static String displayName(String device) {
+    if (device.startsWith(_kProcessPrefix)) {
+      return '${translate('Application')}: ${device.substring(_kProcessPrefix.length)}';
+    }
+    return device;
+  }
+
   static String getDefault() {
     if (bind.mainAudioSupportLoopback()) return translate(_kSystemSound);
     return '';
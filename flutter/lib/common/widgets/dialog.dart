@@ -2,6 +2,7 @@ import 'dart:async';
 import 'dart:convert';
 
 import 'package:bot_toast/bot_toast.dart';
+import 'package:file_picker/file_picker.dart';
 import 'package:flutter/material.dart';
 import 'package:flutter/services.dart';
 import 'package:flutter_hbb/common/shared_state.dart';
@@ -14,9 +15,11 @@ import 'package:get/get.dart';
 import 'package:qr_flutter/qr_flutter.dart';
 
 import '../../common.dart';
+import '../../models/folder_sync_model.dart';
 import '../../models/model.dart';
 import '../../models/platform_model.dart';
 import 'address_book.dart';
+import 'audio_input.dart';
 
 void clientClose(SessionID sessionId, OverlayDialogManager dialogManager) {
   msgBox(sessionId, 'info', 'Close', 'Are you sure to close the connection?',
@@ -273,6 +276,60 @@ void changeWhiteList({Function()? callback}) async {
   });
 }
 
+void changeAccessControlList() async {
+  final controller = TextEditingController(
+      text: (await bind.mainGetOption(key: kOptionAccessControlList))
+          .split(';')
+          .join('\n'));
+  var msg = "";
+  gFFI.dialogManager.show((setState, close, context) {
+    submit() async {
+      final rules = controller.text
+          .split('\n')
+          .map((e) => e.trim())
+          .where((e) => e.isNotEmpty)
+          .join('\n');
+      final invalid = bind.mainCheckAccessControlList(rules: rules);
+      if (invalid.isNotEmpty) {
+        setState(() => msg = "${translate("Invalid rule")} $invalid");
+        return;
+      }
+      await bind.mainSetOption(key: kOptionAccessControlList, value: rules);
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate("Access control list")),
+      content: Column(
+        crossAxisAlignment: CrossAxisAlignment.start,
+        children: [
+          Text(translate("access-control-list-sep")),
+          const SizedBox(
+            height: 8.0,
+          ),
+          TextField(
+                  maxLines: null,
+                  minLines: 5,
+                  decoration: InputDecoration(
+                    hintText: 'deny 203.0.113.0/24\nallow 10.0.0.0/8\n'
+                        'allow 192.168.1.0/24 mon-fri 08:00-18:00',
+                    errorText: msg.isEmpty ? null : msg,
+                  ),
+                  controller: controller,
+                  autofocus: true)
+              .workaroundFreezeLinuxMint(),
+        ],
+      ),
+      actions: [
+        dialogButton("Cancel", onPressed: close, isOutline: true),
+        dialogButton("OK", onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
 Future<String> changeDirectAccessPort(
     String currentIP, String currentPort) async {
   final controller = TextEditingController(text: currentPort);
@@ -1569,6 +1626,116 @@ showAuditDialog(FFI ffi) async {
   });
 }
 
+// Plug in a virtual display with a custom mode, or change the mode of it.
+void showVirtualDisplayModeDialog(FFI ffi) {
+  const presets = [
+    [1920, 1080],
+    [2560, 1440],
+    [3840, 2160],
+    [2560, 1080],
+    [3440, 1440],
+    [1080, 1920],
+    [1440, 2560],
+  ];
+  final plugged = ffi.ffiModel.pi.RustDeskVirtualDisplays;
+  var index = 1;
+  for (var i = 1; i <= kMaxVirtualDisplayCount; i++) {
+    if (!plugged.contains(i)) {
+      index = i;
+      break;
+    }
+  }
+  final widthController = TextEditingController(text: '1920');
+  final heightController = TextEditingController(text: '1080');
+  final refreshController = TextEditingController(text: '60');
+  String? errorText;
+
+  ffi.dialogManager.show((setState, close, context) {
+    submit() {
+      final w = int.tryParse(widthController.text);
+      final h = int.tryParse(heightController.text);
+      final r = int.tryParse(refreshController.text);
+      bool inRange(int? v, int min, int max) =>
+          v != null && v >= min && v <= max;
+      if (!inRange(w, 320, 8192) ||
+          !inRange(h, 320, 8192) ||
+          !inRange(r, 24, 240)) {
+        setState(() =>
+            errorText = translate('virtual_display_mode_invalid_tip'));
+        return;
+      }
+      bind.sessionPlugInVirtualDisplayMode(
+          sessionId: ffi.sessionId,
+          index: index,
+          width: w!,
+          height: h!,
+          refreshRate: r!);
+      close();
+    }
+
+    Widget numberField(TextEditingController controller, String label) {
+      return Expanded(
+        child: TextField(
+          controller: controller,
+          decoration: InputDecoration(labelText: translate(label)),
+          keyboardType: TextInputType.number,
+          inputFormatters: [FilteringTextInputFormatter.digitsOnly],
+        ).workaroundFreezeLinuxMint(),
+      );
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Custom virtual display')),
+      content: Column(
+        mainAxisSize: MainAxisSize.min,
+        crossAxisAlignment: CrossAxisAlignment.start,
+        children: [
+          DropdownButton<int>(
+            value: index,
+            items: List.generate(
+                kMaxVirtualDisplayCount,
+                (i) => DropdownMenuItem(
+                    value: i + 1,
+                    child: Text('${translate('Virtual display')} ${i + 1}'))),
+            onChanged: (v) {
+              if (v != null) setState(() => index = v);
+            },
+          ),
+          Wrap(
+            spacing: 8,
+            children: presets
+                .map((p) => ActionChip(
+                      label: Text('${p[0]}x${p[1]}'),
+                      onPressed: () => setState(() {
+                        widthController.text = p[0].toString();
+                        heightController.text = p[1].toString();
+                      }),
+                    ))
+                .toList(),
+          ),
+          Row(
+            children: [
+              numberField(widthController, 'Width'),
+              const SizedBox(width: 8),
+              numberField(heightController, 'Height'),
+              const SizedBox(width: 8),
+              numberField(refreshController, 'Refresh rate'),
+            ],
+          ),
+          if (errorText != null)
+            Text(errorText!, style: TextStyle(color: Colors.red)),
+        ],
+      ),
+      actions: [
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
 void showConfirmSwitchSidesDialog(
     SessionID sessionId, String id, OverlayDialogManager dialogManager) async {
   dialogManager.show((setState, close, context) {
@@ -2097,7 +2264,9 @@ void showWindowsSessionsDialog(
     OverlayDialogManager dialogManager,
     SessionID sessionId,
     String peerId,
-    String sessions) {
+    String sessions,
+    {String? initialSid,
+    bool cancelable = false}) {
   List<dynamic> sessionsList = [];
   try {
     sessionsList = json.decode(sessions);
@@ -2110,7 +2279,8 @@ void showWindowsSessionsDialog(
     sids.add(session['sid']);
     names.add(session['name']);
   }
-  String selectedUserValue = sids.first;
+  String selectedUserValue =
+      sids.contains(initialSid) ? initialSid! : sids.first;
   dialogManager.dismissAll();
   dialogManager.show((setState, close, context) {
     submit() {
@@ -2135,12 +2305,32 @@ void showWindowsSessionsDialog(
         ],
       ),
       actions: [
+        if (cancelable)
+          dialogButton('Cancel', onPressed: close, isOutline: true),
         dialogButton('Connect', onPressed: submit, isOutline: false),
       ],
+      onCancel: cancelable ? close : null,
     );
   });
 }
 
+// Switch to another user session of the Windows peer, the remote server is restarted in it.
+void showSwitchWindowsSessionDialog(FFI ffi, String peerId) {
+  final sessions =
+      ffi.ffiModel.pi.platformAdditions[kPlatformAdditionsWindowsSessions];
+  if (sessions is! List) return;
+  showWindowsSessionsDialog(
+      '',
+      translate('Switch Windows session'),
+      translate('Please select the session you want to connect to'),
+      ffi.dialogManager,
+      ffi.sessionId,
+      peerId,
+      json.encode(sessions),
+      initialSid: ffi.ffiModel.pi.windowsSessionsCurrentSid,
+      cancelable: true);
+}
+
 void addPeersToAbDialog(
   List<Peer> peers,
 ) async {
@@ -2471,6 +2661,219 @@ void checkUnlockPinDialog(String correctPin, Function() passCallback) {
   });
 }
 
+void unlockProtectedSettingsDialog(Function() passCallback) {
+  final controller = TextEditingController();
+  String? errorText;
+  gFFI.dialogManager.show((setState, close, context) {
+    submit() async {
+      final code = controller.text.trim();
+      if (!await bind.mainUnlockSettings(code: code)) {
+        final status = jsonDecode(await bind.mainGetSettingsProtection());
+        final lockedOut = status['locked_out'] ?? 0;
+        setState(() {
+          errorText = lockedOut > 0
+              ? '${translate('unlock-locked-out-tip')} ${lockedOut}s'
+              : translate('Wrong code or password');
+        });
+        return;
+      }
+      passCallback.call();
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Protected settings')),
+      content: Column(
+        mainAxisSize: MainAxisSize.min,
+        crossAxisAlignment: CrossAxisAlignment.start,
+        children: [
+          Text(translate('protect-settings-tip')).marginOnly(bottom: 8),
+          PasswordWidget(
+            title: '2FA code or admin password',
+            controller: controller,
+            errorText: errorText,
+            hintText: '',
+          ),
+        ],
+      ).marginOnly(bottom: 12),
+      actions: [
+        dialogButton(translate("Cancel"), onPressed: close, isOutline: true),
+        dialogButton(translate("OK"), onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
+void setSettingsAdminPasswordDialog() {
+  final p0 = TextEditingController();
+  final p1 = TextEditingController();
+  String? errorText;
+  gFFI.dialogManager.show((setState, close, context) {
+    submit() async {
+      if (p0.text != p1.text) {
+        setState(() {
+          errorText = translate('The confirmation is not identical.');
+        });
+        return;
+      }
+      await bind.mainSetSettingsAdminPassword(password: p0.text);
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Set admin password')),
+      content: Column(
+        mainAxisSize: MainAxisSize.min,
+        children: [
+          PasswordWidget(controller: p0, autoFocus: true),
+          PasswordWidget(
+            title: 'Confirmation',
+            controller: p1,
+            autoFocus: false,
+            errorText: errorText,
+          ),
+        ],
+      ).marginOnly(bottom: 12),
+      actions: [
+        dialogButton(translate("Cancel"), onPressed: close, isOutline: true),
+        dialogButton(translate("OK"), onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
+void serverKeyPinsDialog(OverlayDialogManager dialogManager) async {
+  final controller = TextEditingController(
+      text: (await bind.mainGetOption(key: kOptionServerKeyPins))
+          .split(',')
+          .where((e) => e.trim().isNotEmpty)
+          .join('\n'));
+  List<dynamic> servers = [];
+  bool fetching = false;
+  dialogManager.show((setState, close, context) {
+    fetch() async {
+      setState(() => fetching = true);
+      try {
+        final v = jsonDecode(await bind.mainGetServerKeyPins());
+        servers = v['servers'] ?? [];
+      } catch (e) {
+        debugPrint('Failed to get the server key pins: $e');
+      }
+      setState(() => fetching = false);
+    }
+
+    addPin(String pin) {
+      final pins = controller.text.split('\n').map((e) => e.trim()).toList();
+      if (!pins.contains(pin)) {
+        setState(() => controller.text =
+            [...pins.where((e) => e.isNotEmpty), pin].join('\n'));
+      }
+    }
+
+    submit() async {
+      final pins = controller.text
+          .split('\n')
+          .map((e) => e.trim())
+          .where((e) => e.isNotEmpty)
+          .join(',');
+      await bind.mainSetOption(key: kOptionServerKeyPins, value: pins);
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Server key pins')),
+      content: Column(
+        mainAxisSize: MainAxisSize.min,
+        crossAxisAlignment: CrossAxisAlignment.start,
+        children: [
+          Text(translate('server-key-pins-tip')),
+          TextField(
+            controller: controller,
+            maxLines: 4,
+            decoration: InputDecoration(hintText: 'sha256/...'),
+          ).marginSymmetric(vertical: 8),
+          Row(children: [
+            dialogButton(translate('Get current keys'),
+                onPressed: fetching ? null : fetch, isOutline: true),
+            if (fetching)
+              SizedBox(
+                      width: 16,
+                      height: 16,
+                      child: CircularProgressIndicator(strokeWidth: 2))
+                  .marginOnly(left: 8),
+          ]),
+          ...servers.map((e) {
+            final String pin = e['pin'] ?? '';
+            final pinned = pin.isNotEmpty &&
+                controller.text.split('\n').map((e) => e.trim()).contains(pin);
+            return ListTile(
+              contentPadding: EdgeInsets.zero,
+              title: Text(e['server'] ?? ''),
+              subtitle: SelectableText(pin.isEmpty ? (e['error'] ?? '') : pin),
+              trailing: pin.isEmpty || pinned
+                  ? (pinned ? Icon(Icons.check, color: Colors.green) : null)
+                  : IconButton(
+                      icon: Icon(Icons.add),
+                      tooltip: translate('Add'),
+                      onPressed: () => addPin(pin),
+                    ),
+            );
+          }),
+        ],
+      ),
+      actions: [
+        dialogButton(translate("Cancel"), onPressed: close, isOutline: true),
+        dialogButton(translate("OK"), onPressed: submit),
+      ],
+      onCancel: close,
+    );
+  });
+}
+
+void setMinClientVersionDialog() async {
+  final controller = TextEditingController(
+      text: await bind.mainGetOption(key: kOptionMinClientVersion));
+  String? errorText;
+  gFFI.dialogManager.show((setState, close, context) {
+    submit() async {
+      final v = controller.text.trim();
+      if (v.isNotEmpty && !RegExp(r'^\d+(\.\d+)*$').hasMatch(v)) {
+        setState(() => errorText = translate('Invalid format'));
+        return;
+      }
+      await bind.mainSetOption(key: kOptionMinClientVersion, value: v);
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Minimum client version')),
+      content: Column(
+        mainAxisSize: MainAxisSize.min,
+        crossAxisAlignment: CrossAxisAlignment.start,
+        children: [
+          Text(translate('min-client-version-tip')),
+          TextField(
+            controller: controller,
+            autofocus: true,
+            decoration: InputDecoration(
+                hintText: '1.3.0', errorText: errorText),
+          ).marginOnly(top: 8),
+        ],
+      ),
+      actions: [
+        dialogButton(translate("Cancel"), onPressed: close, isOutline: true),
+        dialogButton(translate("OK"), onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
 void confrimDeleteTrustedDevicesDialog(
     RxList<TrustedDevice> trustedDevices, RxList<Uint8List> selectedDevices) {
   CommonConfirmDialog(gFFI.dialogManager, '${translate('Confirm Delete')}?',
@@ -2624,3 +3027,1294 @@ Widget trustedDevicesTable(
         )),
   );
 }
+
+void manageLockoutsDialog() async {
+  // source -> {failures, lockouts, locked_until, last_failure}
+  Future<Map<String, dynamic>> load() async {
+    try {
+      final raw = await bind.mainGetLockouts();
+      if (raw.isNotEmpty) return json.decode(raw) as Map<String, dynamic>;
+    } catch (e) {
+      print(e.toString());
+    }
+    return {};
+  }
+
+  RxMap<String, dynamic> lockouts = (await load()).obs;
+  clear(List<String> sources) async {
+    await bind.mainClearLockouts(json: jsonEncode(sources));
+    lockouts.value = await load();
+  }
+
+  String lockedUntil(dynamic record) {
+    final until = record['locked_until'] as int? ?? 0;
+    if (until <= DateTime.now().millisecondsSinceEpoch) return '-';
+    return DateTime.fromMillisecondsSinceEpoch(until)
+        .toLocal()
+        .toString()
+        .split('.')[0];
+  }
+
+  gFFI.dialogManager.show((setState, close, context) {
+    return CustomAlertDialog(
+      title: Text(translate("Login lockouts")),
+      content: Obx(() => lockouts.isEmpty
+          ? Text(translate("No lockouts"))
+          : FittedBox(
+              child: DataTable(
+                columns: [
+                  DataColumn(label: Text(translate('Source'))),
+                  DataColumn(label: Text(translate('Failed attempts'))),
+                  DataColumn(label: Text(translate('Locked until'))),
+                  DataColumn(label: Container()),
+                ],
+                rows: lockouts.entries.map((e) {
+                  return DataRow(cells: [
+                    DataCell(Text(e.key)),
+                    DataCell(Text('${e.value['failures'] ?? 0}')),
+                    DataCell(Text(lockedUntil(e.value))),
+                    DataCell(IconButton(
+                        icon: const Icon(Icons.delete_outline),
+                        onPressed: () => clear([e.key]))),
+                  ]);
+                }).toList(),
+              ),
+            )),
+      actions: [
+        Obx(() => dialogButton(translate("Clear"),
+                onPressed: lockouts.isEmpty ? null : () => clear([]),
+                isOutline: false)
+            .marginOnly(top: 12)),
+        dialogButton(translate("Close"), onPressed: close, isOutline: true)
+            .marginOnly(top: 12),
+      ],
+      onCancel: close,
+    );
+  });
+}
+
+void showTransferHistoryDialog() async {
+  // [{time, peer_id, upload, from, to, files, bytes, duration, error}]
+  List<dynamic> records = [];
+  try {
+    final raw = await bind.mainGetTransferHistory();
+    if (raw.isNotEmpty) records = json.decode(raw) as List<dynamic>;
+  } catch (e) {
+    print(e.toString());
+  }
+
+  export() async {
+    final ts = DateTime.now().millisecondsSinceEpoch ~/ 1000;
+    final path = await FilePicker.platform.saveFile(
+      dialogTitle: '${translate('Save as')}...',
+      fileName: 'transfer_history_$ts.csv',
+      allowedExtensions: ['csv', 'json'],
+      type: FileType.custom,
+    );
+    if (path == null) return;
+    final err = await bind.mainExportTransferHistory(path: path);
+    showToast(translate(err.isEmpty ? 'Successful' : err));
+  }
+
+  String time(dynamic r) =>
+      DateTime.fromMillisecondsSinceEpoch(r['time'] as int? ?? 0)
+          .toLocal()
+          .toString()
+          .split('.')[0];
+
+  Widget path(String p) => Tooltip(
+        message: p,
+        child: ConstrainedBox(
+          constraints: const BoxConstraints(maxWidth: 200),
+          child: Text(p, overflow: TextOverflow.ellipsis),
+        ),
+      );
+
+  gFFI.dialogManager.show((setState, close, context) {
+    return CustomAlertDialog(
+      title: Text(translate("Transfer history")),
+      contentBoxConstraints: BoxConstraints(maxWidth: 1000),
+      content: records.isEmpty
+          ? Text(translate("Empty"))
+          : SizedBox(
+              height: 400,
+              child: SingleChildScrollView(
+                child: SingleChildScrollView(
+                  scrollDirection: Axis.horizontal,
+                  child: DataTable(
+                    columns: [
+                      DataColumn(label: Text(translate('Time'))),
+                      DataColumn(label: Text(translate('ID'))),
+                      DataColumn(label: Text(translate('Path'))),
+                      DataColumn(label: Text(translate('Size'))),
+                      DataColumn(label: Text(translate('Duration'))),
+                      DataColumn(label: Text(translate('Result'))),
+                    ],
+                    rows: records.map((r) {
+                      final error = r['error'] as String?;
+                      return DataRow(cells: [
+                        DataCell(Text(time(r))),
+                        DataCell(Text(r['peer_id'] ?? '')),
+                        DataCell(Row(children: [
+                          Icon(
+                              r['upload'] == true
+                                  ? Icons.upload_rounded
+                                  : Icons.download_rounded,
+                              size: 16),
+                          path(r['from'] ?? ''),
+                          const Text(' -> '),
+                          path(r['to'] ?? ''),
+                        ])),
+                        DataCell(Text(
+                            '${r['files'] ?? 0} ${translate('files')}, ${readableFileSize((r['bytes'] as int? ?? 0).toDouble())}')),
+                        DataCell(Text(
+                            '${((r['duration'] as int? ?? 0) / 1000).toStringAsFixed(1)}s')),
+                        DataCell(error == null
+                            ? Text(translate('Successful'))
+                            : Text(translate(error),
+                                style: const TextStyle(color: Colors.red))),
+                      ]);
+                    }).toList(),
+                  ),
+                ),
+              ),
+            ),
+      actions: [
+        dialogButton(translate("Export"),
+                onPressed: records.isEmpty ? null : export, isOutline: false)
+            .marginOnly(top: 12),
+        dialogButton(translate("Close"), onPressed: close, isOutline: true)
+            .marginOnly(top: 12),
+      ],
+      onCancel: close,
+    );
+  });
+}
+
+void verifyConnectionDialog(
+    SessionID sessionId, OverlayDialogManager dialogManager) {
+  final sas = bind.sessionGetSas(sessionId: sessionId);
+  final verified = bind.sessionIsPeerVerified(sessionId: sessionId);
+  dialogManager.show((setState, close, context) {
+    return CustomAlertDialog(
+      title: Text(translate('Verify connection')),
+      content: Column(
+        mainAxisSize: MainAxisSize.min,
+        crossAxisAlignment: CrossAxisAlignment.start,
+        children: [
+          Text(translate('sas_tip')),
+          Center(
+            child: SelectableText(sas, style: const TextStyle(fontSize: 32)),
+          ).marginSymmetric(vertical: 16),
+          if (verified)
+            Row(children: [
+              const Icon(Icons.verified_user, color: Colors.green, size: 18),
+              Text(translate('Verified')).marginOnly(left: 6),
+            ]),
+        ],
+      ),
+      actions: [
+        dialogButton(translate('Cancel'), onPressed: close, isOutline: true),
+        dialogButton(translate('They match'), onPressed: () {
+          bind.sessionMarkPeerVerified(sessionId: sessionId);
+          close();
+        }),
+      ],
+      onCancel: close,
+    );
+  });
+}
+
+void manageSingleUsePasswordsDialog() async {
+  // [{id, note, created, expires, profile}]
+  Future<List<dynamic>> load() async {
+    try {
+      final raw = await bind.mainGetOneTimePasswords();
+      if (raw.isNotEmpty) return json.decode(raw) as List<dynamic>;
+    } catch (e) {
+      print(e.toString());
+    }
+    return [];
+  }
+
+  RxList<dynamic> entries = (await load()).obs;
+  revoke(List<String> ids) async {
+    await bind.mainRevokeOneTimePasswords(json: jsonEncode(ids));
+    entries.value = await load();
+  }
+
+  String time(int ms) => ms == 0
+      ? '-'
+      : DateTime.fromMillisecondsSinceEpoch(ms)
+          .toLocal()
+          .toString()
+          .split('.')[0];
+
+  String scope(dynamic profile) {
+    final List<String> v = [];
+    if (profile?['view_only'] == true) v.add(translate('View Mode'));
+    for (final e in [
+      ['file', 'Enable file transfer'],
+      ['clipboard', 'Enable clipboard'],
+      ['audio', 'Enable audio'],
+    ]) {
+      if (profile?[e[0]] == false) v.add('✗ ${translate(e[1])}');
+    }
+    return v.join(', ');
+  }
+
+  gFFI.dialogManager.show((setState, close, context) {
+    return CustomAlertDialog(
+      title: Text(translate('Single-use passwords')),
+      content: Obx(() => entries.isEmpty
+          ? Text(translate('single-use-passwords-tip'))
+          : FittedBox(
+              child: DataTable(
+                columns: [
+                  DataColumn(label: Text(translate('Note'))),
+                  DataColumn(label: Text(translate('Permissions'))),
+                  DataColumn(label: Text(translate('Expires'))),
+                  DataColumn(label: Container()),
+                ],
+                rows: entries.map((e) {
+                  return DataRow(cells: [
+                    DataCell(Text(e['note'] ?? '')),
+                    DataCell(Text(scope(e['profile']))),
+                    DataCell(Text(time(e['expires'] ?? 0))),
+                    DataCell(IconButton(
+                        icon: const Icon(Icons.delete_outline),
+                        onPressed: () => revoke([e['id']]))),
+                  ]);
+                }).toList(),
+              ),
+            )),
+      actions: [
+        dialogButton(translate("Generate"),
+                onPressed: () => generateSingleUsePasswordDialog(
+                    () async => entries.value = await load()),
+                isOutline: false)
+            .marginOnly(top: 12),
+        dialogButton(translate("Close"), onPressed: close, isOutline: true)
+            .marginOnly(top: 12),
+      ],
+      onCancel: close,
+    );
+  });
+}
+
+void generateSingleUsePasswordDialog(Function() onGenerated) {
+  final note = TextEditingController();
+  final ttls = {
+    0: 'No expiry',
+    600: '10 minutes',
+    3600: '1 hour',
+    86400: '1 day',
+  };
+  var ttl = 3600;
+  var viewOnly = false;
+  var file = true;
+  var clipboard = true;
+  var audio = true;
+  var password = '';
+  gFFI.dialogManager.show((setState, close, context) {
+    generate() async {
+      final profile = <String, dynamic>{'view_only': viewOnly};
+      if (!file) profile['file'] = false;
+      if (!clipboard) profile['clipboard'] = false;
+      if (!audio) profile['audio'] = false;
+      final res = await bind.mainAddOneTimePassword(
+          json: jsonEncode({'ttl': ttl, 'note': note.text, 'profile': profile}));
+      if (res.isEmpty) {
+        showToast(translate('settings-locked-tip'));
+        return;
+      }
+      setState(() => password = res);
+      onGenerated();
+    }
+
+    Widget check(String label, bool value, Function(bool) onChanged) {
+      return CheckboxListTile(
+        contentPadding: EdgeInsets.zero,
+        dense: true,
+        controlAffinity: ListTileControlAffinity.leading,
+        title: Text(translate(label)),
+        value: value,
+        onChanged: password.isNotEmpty
+            ? null
+            : (v) => setState(() => onChanged(v == true)),
+      );
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Single-use passwords')),
+      content: ConstrainedBox(
+        constraints: const BoxConstraints(minWidth: 400),
+        child: Column(
+          mainAxisSize: MainAxisSize.min,
+          crossAxisAlignment: CrossAxisAlignment.start,
+          children: [
+            TextField(
+              controller: note,
+              enabled: password.isEmpty,
+              decoration: InputDecoration(labelText: translate('Note')),
+            ).workaroundFreezeLinuxMint(),
+            Row(children: [
+              Text('${translate('Expires')}:'),
+              DropdownButton<int>(
+                value: ttl,
+                items: ttls.entries
+                    .map((e) => DropdownMenuItem(
+                        value: e.key, child: Text(translate(e.value))))
+                    .toList(),
+                onChanged: password.isNotEmpty
+                    ? null
+                    : (v) => setState(() => ttl = v ?? ttl),
+              ).marginOnly(left: 8),
+            ]).marginOnly(top: 8),
+            check('View Mode', viewOnly, (v) => viewOnly = v),
+            check('Enable file transfer', file, (v) => file = v),
+            check('Enable clipboard', clipboard, (v) => clipboard = v),
+            check('Enable audio', audio, (v) => audio = v),
+            if (password.isNotEmpty)
+              Row(children: [
+                SelectableText(password,
+                    style: const TextStyle(
+                        fontSize: 22, fontWeight: FontWeight.bold)),
+                IconButton(
+                    icon: const Icon(Icons.copy),
+                    onPressed: () {
+                      Clipboard.setData(ClipboardData(text: password));
+                      showToast(translate('Copied'));
+                    }),
+              ]).marginOnly(top: 8),
+          ],
+        ),
+      ),
+      actions: [
+        if (password.isEmpty)
+          dialogButton('Cancel', onPressed: close, isOutline: true),
+        if (password.isEmpty) dialogButton('Generate', onPressed: generate),
+        if (password.isNotEmpty) dialogButton('Close', onPressed: close),
+      ],
+      onCancel: close,
+    );
+  });
+}
+
+void audioDevicesDialog(SessionID sessionId, List<String> devices,
+    String current, OverlayDialogManager dialogManager) {
+  dialogManager.show((setState, close, context) {
+    onChanged(String? value) {
+      if (value == null) return;
+      bind.sessionSelectAudioDevice(sessionId: sessionId, device: value);
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Remote audio device')),
+      content: Column(
+        mainAxisSize: MainAxisSize.min,
+        children: [
+          getRadio(Text(translate('Default')), '', current, onChanged),
+          ...devices.map((d) => getRadio(
+              Text(AudioInput.displayName(d)), d, current, onChanged)),
+        ],
+      ),
+      actions: [
+        dialogButton(translate('Close'), onPressed: close, isOutline: true),
+      ],
+      onCancel: close,
+    );
+  }, tag: 'audio-devices');
+}
+
+// One step per line: `text <text>`, `keys <Ctrl+Alt+VK_DELETE>` or `delay <ms>`.
+List<Map<String, dynamic>>? _parseMacroSteps(String text) {
+  final steps = <Map<String, dynamic>>[];
+  for (final line in text.split('\n')) {
+    final l = line.trimLeft();
+    if (l.trim().isEmpty) continue;
+    final i = l.indexOf(' ');
+    final kind = i < 0 ? l.trim() : l.substring(0, i);
+    final arg = i < 0 ? '' : l.substring(i + 1);
+    switch (kind) {
+      case 'text':
+        steps.add({'text': arg});
+        break;
+      case 'keys':
+        if (arg.trim().isEmpty) return null;
+        steps.add({'keys': arg.trim()});
+        break;
+      case 'delay':
+        final ms = int.tryParse(arg.trim());
+        if (ms == null || ms < 0) return null;
+        steps.add({'delay': ms});
+        break;
+      default:
+        return null;
+    }
+  }
+  return steps.isEmpty ? null : steps;
+}
+
+void showKeyRemapDialog(FFI ffi) async {
+  final sessionId = ffi.sessionId;
+  Map<String, dynamic> config = {};
+  try {
+    config = jsonDecode(await bind.sessionGetOption(
+                sessionId: sessionId, arg: kOptionKeyRemap) ??
+            '{}') ??
+        {};
+  } catch (_) {}
+  final remaps = List<Map<String, dynamic>>.from(config['remap'] ?? []);
+  final macros = List<Map<String, dynamic>>.from(config['macros'] ?? []);
+  final focusNode = FocusNode();
+  final stepsController = TextEditingController();
+  // The key being captured, 'from', 'to' or 'trigger'.
+  String? capturing;
+  final captured = <String, Map<String, dynamic>>{};
+  String? errorText;
+
+  ffi.dialogManager.show((setState, close, context) {
+    KeyEventResult onKeyEvent(FocusNode node, KeyEvent e) {
+      if (capturing == null || e is! KeyDownEvent) {
+        return KeyEventResult.ignored;
+      }
+      final label = e.logicalKey.keyLabel;
+      setState(() {
+        captured[capturing!] = {
+          'code': e.physicalKey.usbHidUsage,
+          'label': label.isNotEmpty
+              ? label
+              : e.logicalKey.debugName ?? '${e.physicalKey.usbHidUsage}',
+        };
+        capturing = null;
+      });
+      return KeyEventResult.handled;
+    }
+
+    Widget keyButton(String name, String hint) => OutlinedButton(
+          onPressed: () {
+            setState(() => capturing = name);
+            focusNode.requestFocus();
+          },
+          child: Text(capturing == name
+              ? translate('Press a key')
+              : captured[name]?['label'] ?? translate(hint)),
+        );
+
+    addRemap() {
+      final from = captured['from'];
+      final to = captured['to'];
+      if (from == null || to == null) return;
+      setState(() {
+        remaps.removeWhere((r) => r['from'] == from['code']);
+        remaps.add({
+          'from': from['code'],
+          'to': to['code'],
+          'label': '${from['label']} → ${to['label']}',
+        });
+        captured.remove('from');
+        captured.remove('to');
+      });
+    }
+
+    addMacro() {
+      final trigger = captured['trigger'];
+      if (trigger == null) return;
+      final steps = _parseMacroSteps(stepsController.text);
+      if (steps == null) {
+        setState(() => errorText = translate('key_macro_steps_tip'));
+        return;
+      }
+      setState(() {
+        macros.removeWhere((m) => m['key'] == trigger['code']);
+        macros.add({
+          'key': trigger['code'],
+          'label': trigger['label'],
+          'steps': steps,
+        });
+        captured.remove('trigger');
+        stepsController.clear();
+        errorText = null;
+      });
+    }
+
+    submit() {
+      bind.sessionPeerOption(
+          sessionId: sessionId,
+          name: kOptionKeyRemap,
+          value: remaps.isEmpty && macros.isEmpty
+              ? ''
+              : jsonEncode({'remap': remaps, 'macros': macros}));
+      close();
+    }
+
+    Widget item(String text, VoidCallback onDelete) => Row(
+          children: [
+            Expanded(child: Text(text, overflow: TextOverflow.ellipsis)),
+            IconButton(
+              icon: const Icon(Icons.delete_outline, size: 18),
+              onPressed: () => setState(onDelete),
+            ),
+          ],
+        );
+
+    return CustomAlertDialog(
+      title: Text(translate('Key remapping and macros')),
+      content: Focus(
+        focusNode: focusNode,
+        onKeyEvent: onKeyEvent,
+        child: SizedBox(
+          width: 420,
+          child: Column(
+            mainAxisSize: MainAxisSize.min,
+            crossAxisAlignment: CrossAxisAlignment.start,
+            children: [
+              ...remaps.map((r) => item(r['label'] ?? '', () => remaps.remove(r))),
+              Row(
+                children: [
+                  keyButton('from', 'From key'),
+                  const Text(' → '),
+                  keyButton('to', 'To key'),
+                  const Spacer(),
+                  TextButton(onPressed: addRemap, child: Text(translate('Add'))),
+                ],
+              ),
+              const Divider(),
+              ...macros.map((m) => item(
+                  '${m['label']}: ${(m['steps'] as List).length} ${translate('steps')}',
+                  () => macros.remove(m))),
+              Row(
+                children: [
+                  keyButton('trigger', 'Trigger key'),
+                  const Spacer(),
+                  TextButton(onPressed: addMacro, child: Text(translate('Add'))),
+                ],
+              ),
+              TextField(
+                controller: stepsController,
+                minLines: 3,
+                maxLines: 6,
+                decoration: InputDecoration(
+                  hintText: 'text user@example.com\nkeys VK_TAB\ndelay 200',
+                  helperText: translate('key_macro_steps_tip'),
+                  helperMaxLines: 3,
+                  errorText: errorText,
+                ),
+              ).workaroundFreezeLinuxMint(),
+            ],
+          ),
+        ),
+      ),
+      actions: [
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onCancel: close,
+    );
+  }, tag: 'key-remap');
+}
+
+// The labels of the special keys of the peer, see `src/special_keys.rs`.
+const Map<String, String> _kSpecialKeyLabels = {
+  'print_screen': 'PrintScreen',
+  'alt_print_screen': 'Alt + PrintScreen',
+  'alt_tab': 'Alt + Tab',
+  'alt_f4': 'Alt + F4',
+  'win': 'Win',
+  'win_d': 'Win + D',
+  'win_r': 'Win + R',
+  'task_manager': 'Ctrl + Shift + Esc',
+  'super': 'Super',
+  'play_pause': 'Play / Pause',
+  'stop': 'Stop',
+  'previous_track': 'Previous track',
+  'next_track': 'Next track',
+  'volume_mute': 'Mute',
+  'volume_down': 'Volume down',
+  'volume_up': 'Volume up',
+  'switch_vt_1': 'Ctrl + Alt + F1',
+  'switch_vt_2': 'Ctrl + Alt + F2',
+  'switch_vt_3': 'Ctrl + Alt + F3',
+  'switch_vt_7': 'Ctrl + Alt + F7',
+  'screenshot': 'Cmd + Shift + 3',
+  'screenshot_selection': 'Cmd + Shift + 4',
+  'cmd_tab': 'Cmd + Tab',
+  'cmd_q': 'Cmd + Q',
+  'spotlight': 'Cmd + Space',
+  'mission_control': 'Ctrl + Up',
+  'force_quit': 'Cmd + Option + Esc',
+};
+
+void showSpecialKeysDialog(FFI ffi) {
+  final sessionId = ffi.sessionId;
+  final keys = ffi.ffiModel.pi.specialKeys;
+  ffi.dialogManager.show((setState, close, context) {
+    return CustomAlertDialog(
+      title: Text(translate('Special keys')),
+      content: Wrap(
+        spacing: 8,
+        runSpacing: 8,
+        children: keys
+            .map((name) => OutlinedButton(
+                  onPressed: () => bind.sessionSendSpecialKey(
+                      sessionId: sessionId, name: name),
+                  child: Text(translate(_kSpecialKeyLabels[name] ?? name)),
+                ))
+            .toList(),
+      ),
+      actions: [
+        dialogButton('Close', onPressed: close),
+      ],
+      onCancel: close,
+    );
+  }, tag: 'special-keys');
+}
+
+void showPassthroughHotkeysDialog() {
+  final controller =
+      TextEditingController(text: bind.mainGetPassthroughHotkeys());
+  String? errorText;
+
+  gFFI.dialogManager.show((setState, close, context) {
+    submit() {
+      final invalid =
+          bind.mainSetPassthroughHotkeys(hotkeys: controller.text);
+      if (invalid.isNotEmpty) {
+        setState(() => errorText = '${translate('Invalid shortcuts')}: '
+            '${invalid.split('\n').join(', ')}');
+        return;
+      }
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Pass shortcuts to the remote')),
+      content: SizedBox(
+        width: 360,
+        child: TextField(
+          controller: controller,
+          minLines: 6,
+          maxLines: 12,
+          decoration: InputDecoration(
+            helperText: translate('passthrough_hotkeys_tip'),
+            helperMaxLines: 4,
+            errorText: errorText,
+            errorMaxLines: 3,
+          ),
+        ).workaroundFreezeLinuxMint(),
+      ),
+      actions: [
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onCancel: close,
+    );
+  }, tag: 'passthrough-hotkeys');
+}
+
+/// The limits of the clipboard synced with the peers, see
+/// `src/clipboard_policy.rs`.
+void showClipboardPolicyDialog({required bool isServer}) {
+  String get(String key) => isServer
+      ? bind.mainGetOptionSync(key: key)
+      : bind.mainGetLocalOption(key: key);
+  final maxSize = TextEditingController(text: get(kOptionClipboardMaxSize));
+  final filters = TextEditingController(text: get(kOptionClipboardFilters));
+  var direction = get(kOptionClipboardDirection);
+  const directions = {
+    '': 'Both directions',
+    'send': 'Send only',
+    'receive': 'Receive only',
+  };
+  if (!directions.containsKey(direction)) direction = '';
+
+  gFFI.dialogManager.show((setState, close, context) {
+    submit() async {
+      final options = {
+        kOptionClipboardMaxSize: maxSize.text.trim(),
+        kOptionClipboardDirection: direction,
+        kOptionClipboardFilters: filters.text.trim(),
+      };
+      for (final e in options.entries) {
+        if (isServer) {
+          await bind.mainSetOption(key: e.key, value: e.value);
+        } else {
+          await bind.mainSetLocalOption(key: e.key, value: e.value);
+        }
+      }
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Clipboard limits')),
+      content: SizedBox(
+        width: 360,
+        child: Column(
+          mainAxisSize: MainAxisSize.min,
+          crossAxisAlignment: CrossAxisAlignment.start,
+          children: [
+            DropdownButton<String>(
+              value: direction,
+              isExpanded: true,
+              items: directions.entries
+                  .map((e) => DropdownMenuItem(
+                      value: e.key, child: Text(translate(e.value))))
+                  .toList(),
+              onChanged: (v) {
+                if (v != null) setState(() => direction = v);
+              },
+            ),
+            DialogTextField(
+              title: translate('Clipboard size limit (KB)'),
+              controller: maxSize,
+              inputFormatters: [FilteringTextInputFormatter.digitsOnly],
+            ),
+            TextField(
+              controller: filters,
+              minLines: 4,
+              maxLines: 8,
+              decoration: InputDecoration(
+                labelText: translate('Clipboard filters'),
+                helperText: translate('clipboard_filters_tip'),
+                helperMaxLines: 4,
+              ),
+            ).workaroundFreezeLinuxMint(),
+          ],
+        ),
+      ),
+      actions: [
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onCancel: close,
+    );
+  }, tag: 'clipboard-policy');
+}
+
+/// The user logged into the headless session by the service and its resolution,
+/// see `src/platform/linux_desktop_manager.rs`.
+void showLinuxHeadlessDialog() {
+  final user = TextEditingController(
+      text: bind.mainGetOptionSync(key: kOptionLinuxHeadlessUser));
+  final resolution = TextEditingController(
+      text: bind.mainGetOptionSync(key: kOptionLinuxHeadlessResolution));
+  String? resolutionError;
+
+  gFFI.dialogManager.show((setState, close, context) {
+    submit() async {
+      final r = resolution.text.trim();
+      if (r.isNotEmpty && !RegExp(r'^\d+x\d+$').hasMatch(r)) {
+        setState(() => resolutionError = translate('Invalid format'));
+        return;
+      }
+      await bind.mainSetOption(
+          key: kOptionLinuxHeadlessUser, value: user.text.trim());
+      await bind.mainSetOption(key: kOptionLinuxHeadlessResolution, value: r);
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Headless session')),
+      content: SizedBox(
+        width: 360,
+        child: Column(
+          mainAxisSize: MainAxisSize.min,
+          crossAxisAlignment: CrossAxisAlignment.start,
+          children: [
+            DialogTextField(
+              title: translate('Auto login user'),
+              helperText: translate('linux_headless_user_tip'),
+              controller: user,
+            ),
+            DialogTextField(
+              title: translate('Resolution'),
+              hintText: '1920x1080',
+              controller: resolution,
+              errorText: resolutionError,
+            ),
+          ],
+        ),
+      ),
+      actions: [
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  }, tag: 'linux-headless');
+}
+
+/// The recent copies of both sides in the manual clipboard sync, to push to
+/// or pull from the peer, see `src/clipboard_history.rs`.
+void showClipboardHistoryDialog(FFI ffi) {
+  final sessionId = ffi.sessionId;
+  // [{id, time, preview}]
+  List<dynamic> clips(bool remote) {
+    try {
+      return json.decode(bind.sessionGetClipboardHistory(
+          sessionId: sessionId, remote: remote)) as List<dynamic>;
+    } catch (e) {
+      print(e.toString());
+      return [];
+    }
+  }
+
+  String time(dynamic c) =>
+      DateTime.fromMillisecondsSinceEpoch(c['time'] as int? ?? 0)
+          .toLocal()
+          .toString()
+          .split('.')[0]
+          .split(' ')
+          .last;
+
+  ffi.dialogManager.show((setState, close, context) {
+    Widget list(bool remote) {
+      final items = clips(remote);
+      return Column(
+        crossAxisAlignment: CrossAxisAlignment.start,
+        children: [
+          Text(translate(remote ? 'Remote' : 'Local'),
+              style: Theme.of(context).textTheme.titleSmall),
+          SizedBox(
+            height: 180,
+            child: items.isEmpty
+                ? Center(child: Text(translate('Empty')))
+                : ListView(
+                    children: items
+                        .map((c) => ListTile(
+                              dense: true,
+                              title: Text(c['preview'] ?? '',
+                                  maxLines: 2,
+                                  overflow: TextOverflow.ellipsis),
+                              subtitle: Text(time(c)),
+                              trailing: IconButton(
+                                tooltip: translate(remote ? 'Pull' : 'Push'),
+                                icon: Icon(remote
+                                    ? Icons.download_rounded
+                                    : Icons.upload_rounded),
+                                onPressed: () {
+                                  final id = c['id'] as int;
+                                  if (remote) {
+                                    bind.sessionPullClip(
+                                        sessionId: sessionId, id: id);
+                                  } else {
+                                    bind.sessionPushClip(
+                                        sessionId: sessionId, id: id);
+                                  }
+                                  showToast(translate(
+                                      remote ? 'Pulled' : 'Pushed'));
+                                },
+                              ),
+                            ))
+                        .toList(),
+                  ),
+          ),
+        ],
+      );
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Clipboard history')),
+      content: SizedBox(
+        width: 420,
+        child: Column(
+          mainAxisSize: MainAxisSize.min,
+          children: [list(false), const Divider(), list(true)],
+        ),
+      ),
+      actions: [
+        dialogButton('Refresh', onPressed: () => setState(() {})),
+        dialogButton('Close', onPressed: close, isOutline: true),
+      ],
+      onCancel: close,
+    );
+  }, tag: 'clipboard-history');
+}
+
+/// Compares the local and the remote folders open in the file manager and
+/// applies the plan after a preview.
+void showFolderSyncDialog(FFI ffi) {
+  final sync = FolderSync(ffi.fileModel);
+  final dialogManager = ffi.dialogManager;
+  var mode = FolderSyncMode.twoWay;
+  var policy = FolderSyncPolicy.newest;
+  var mirrorDeletes = false;
+
+  showPreview(List<FolderSyncAction> actions) {
+    final changes = actions.where((a) => !a.isSkip).length;
+    dialogManager.show((setState, close, context) {
+      Widget icon(FolderSyncAction a) {
+        switch (a.action) {
+          case 'upload':
+            return const Icon(Icons.upload_rounded);
+          case 'download':
+            return const Icon(Icons.download_rounded);
+          case 'delete_local':
+          case 'delete_remote':
+            return const Icon(Icons.delete_outline, color: Colors.red);
+          case 'rename_local':
+          case 'rename_remote':
+            return const Icon(Icons.drive_file_rename_outline);
+          default:
+            return const Icon(Icons.block, color: Colors.orange);
+        }
+      }
+
+      String side(FolderSyncAction a) => a.action.endsWith('_local')
+          ? translate('Local')
+          : a.action.endsWith('_remote')
+              ? translate('Remote')
+              : '';
+
+      submit() {
+        close();
+        sync.apply(actions);
+      }
+
+      return CustomAlertDialog(
+        title: Text(translate('Synchronize folders')),
+        content: SizedBox(
+          width: 480,
+          height: 360,
+          child: actions.isEmpty
+              ? Center(child: Text(translate('The folders are in sync')))
+              : ListView(
+                  children: actions
+                      .map((a) => ListTile(
+                            dense: true,
+                            leading: icon(a),
+                            title: Text(a.to == null
+                                ? a.name
+                                : '${a.name} -> ${a.to}'),
+                            trailing: Text(side(a)),
+                          ))
+                      .toList(),
+                ),
+        ),
+        actions: [
+          dialogButton('Cancel', onPressed: close, isOutline: true),
+          dialogButton('Apply', onPressed: changes > 0 ? submit : null),
+        ],
+        onSubmit: changes > 0 ? submit : null,
+        onCancel: close,
+      );
+    }, tag: 'folder-sync-preview');
+  }
+
+  showLog() {
+    final log = sync.getLog();
+    dialogManager.show((setState, close, context) {
+      return CustomAlertDialog(
+        title: Text(translate('Sync log')),
+        content: SizedBox(
+          width: 560,
+          height: 360,
+          child: SingleChildScrollView(
+            reverse: true,
+            child: SelectableText(log.isEmpty ? translate('Empty') : log,
+                style: const TextStyle(fontFamily: 'monospace', fontSize: 12)),
+          ),
+        ),
+        actions: [dialogButton('Close', onPressed: close)],
+        onCancel: close,
+      );
+    }, tag: 'folder-sync-log');
+  }
+
+  dialogManager.show((setState, close, context) {
+    compare() async {
+      close();
+      final tag = dialogManager.showLoading(translate('Comparing'));
+      try {
+        final actions = await sync.plan(mode, policy, mirrorDeletes);
+        dialogManager.dismissByTag(tag);
+        showPreview(actions);
+      } catch (e) {
+        dialogManager.dismissByTag(tag);
+        msgBox(ffi.sessionId, 'custom-nocancel', 'Error', e.toString(), '',
+            dialogManager);
+      }
+    }
+
+    Widget modeTile(FolderSyncMode value, String label) =>
+        RadioListTile<FolderSyncMode>(
+          dense: true,
+          contentPadding: EdgeInsets.zero,
+          value: value,
+          groupValue: mode,
+          title: Text(translate(label)),
+          onChanged: (v) {
+            if (v != null) setState(() => mode = v);
+          },
+        );
+
+    return CustomAlertDialog(
+      title: Text(translate('Synchronize folders')),
+      content: SizedBox(
+        width: 420,
+        child: Column(
+          mainAxisSize: MainAxisSize.min,
+          crossAxisAlignment: CrossAxisAlignment.start,
+          children: [
+            Text('${translate('Local')}: ${sync.localPath}'),
+            Text('${translate('Remote')}: ${sync.remotePath}'),
+            const SizedBox(height: 8),
+            modeTile(FolderSyncMode.twoWay, 'Two-way'),
+            modeTile(FolderSyncMode.upload, 'Local to remote'),
+            modeTile(FolderSyncMode.download, 'Remote to local'),
+            Row(
+              children: [
+                Text(translate('On conflict')),
+                const SizedBox(width: 8),
+                DropdownButton<FolderSyncPolicy>(
+                  value: policy,
+                  items: const [
+                    (FolderSyncPolicy.newest, 'Newest wins'),
+                    (FolderSyncPolicy.skip, 'Skip'),
+                    (FolderSyncPolicy.rename, 'Keep both'),
+                  ]
+                      .map((e) => DropdownMenuItem(
+                          value: e.$1, child: Text(translate(e.$2))))
+                      .toList(),
+                  onChanged: (v) {
+                    if (v != null) setState(() => policy = v);
+                  },
+                ),
+              ],
+            ),
+            CheckboxListTile(
+              dense: true,
+              contentPadding: EdgeInsets.zero,
+              controlAffinity: ListTileControlAffinity.leading,
+              value: mirrorDeletes && mode != FolderSyncMode.twoWay,
+              title: Text(translate('Delete the files missing in the source')),
+              onChanged: mode == FolderSyncMode.twoWay
+                  ? null
+                  : (v) => setState(() => mirrorDeletes = v == true),
+            ),
+          ],
+        ),
+      ),
+      actions: [
+        dialogButton('Sync log', onPressed: showLog, isOutline: true),
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('Compare', onPressed: compare),
+      ],
+      onSubmit: compare,
+      onCancel: close,
+    );
+  }, tag: 'folder-sync');
+}
+
+/// The limit of the uploads of the file transfer session in KB/s, empty for
+/// the global one only.
+void showUploadRateLimitDialog(FFI ffi) async {
+  final controller = TextEditingController(
+      text: await bind.sessionGetPeerOption(
+          sessionId: ffi.sessionId, name: kOptionFileTransferRateLimit));
+  ffi.dialogManager.show((setState, close, context) {
+    submit() async {
+      await bind.sessionPeerOption(
+          sessionId: ffi.sessionId,
+          name: kOptionFileTransferRateLimit,
+          value: controller.text);
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Upload rate limit')),
+      content: TextField(
+        controller: controller,
+        autofocus: true,
+        inputFormatters: [FilteringTextInputFormatter.digitsOnly],
+        decoration: InputDecoration(
+          labelText: 'KB/s',
+          helperText: translate('upload_rate_limit_tip'),
+          helperMaxLines: 3,
+        ),
+      ).workaroundFreezeLinuxMint(),
+      actions: [
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  }, tag: 'upload-rate-limit');
+}
+
+void showConcurrentTransfersDialog(FFI ffi) {
+  final controller = TextEditingController(
+      text: bind.mainGetLocalOption(key: kOptionFileTransferMaxJobs));
+  ffi.dialogManager.show((setState, close, context) {
+    submit() async {
+      await bind.mainSetLocalOption(
+          key: kOptionFileTransferMaxJobs, value: controller.text);
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Concurrent transfers')),
+      content: TextField(
+        controller: controller,
+        autofocus: true,
+        inputFormatters: [FilteringTextInputFormatter.digitsOnly],
+        decoration: InputDecoration(
+          helperText: translate('concurrent_transfers_tip'),
+          helperMaxLines: 3,
+        ),
+      ).workaroundFreezeLinuxMint(),
+      actions: [
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  }, tag: 'concurrent-transfers');
+}
+
+/// The tunnels of the remote session, started again on the next connections
+/// to the peer.
+void showSessionTunnelsDialog(FFI ffi) {
+  final sessionId = ffi.sessionId;
+  var kind = 'local';
+  final localPort = TextEditingController();
+  final remoteHost = TextEditingController();
+  final remotePort = TextEditingController();
+  const kinds = [
+    ('local', 'Local forward'),
+    ('reverse', 'Reverse forward'),
+    ('socks', 'SOCKS5 proxy'),
+  ];
+  String kindLabel(String k) =>
+      translate(kinds.firstWhere((e) => e.$1 == k, orElse: () => (k, k)).$2);
+  String describe(Map<String, dynamic> t) {
+    final local = 'localhost:${t['local_port']}';
+    final host = t['remote_host'] == '' ? 'localhost' : t['remote_host'];
+    final remote = '$host:${t['remote_port']}';
+    switch (t['kind']) {
+      case 'socks':
+        return local;
+      case 'reverse':
+        return '$remote -> $local';
+      default:
+        return '$local -> $remote';
+    }
+  }
+
+  List<Map<String, dynamic>> getTunnels() {
+    try {
+      return List<Map<String, dynamic>>.from(
+          jsonDecode(bind.sessionGetTunnels(sessionId: sessionId)));
+    } catch (e) {
+      debugPrint('Failed to parse tunnels: $e');
+      return [];
+    }
+  }
+
+  ffi.dialogManager.show((setState, close, context) {
+    final tunnels = getTunnels();
+    final isSocks = kind == 'socks';
+
+    add() async {
+      final port = int.tryParse(localPort.text) ?? 0;
+      final rport = int.tryParse(remotePort.text) ?? 0;
+      if (port <= 0 || (!isSocks && rport <= 0)) {
+        return;
+      }
+      await bind.sessionAddTunnel(
+          sessionId: sessionId,
+          kind: kind,
+          localPort: port,
+          remoteHost: isSocks ? '' : remoteHost.text.trim(),
+          remotePort: isSocks ? 0 : rport);
+      localPort.clear();
+      remoteHost.clear();
+      remotePort.clear();
+      setState(() {});
+    }
+
+    remove(Map<String, dynamic> t) async {
+      await bind.sessionRemoveTunnel(
+          sessionId: sessionId,
+          kind: t['kind'],
+          localPort: t['local_port'],
+          remoteHost: t['remote_host'],
+          remotePort: t['remote_port']);
+      setState(() {});
+    }
+
+    Widget portField(TextEditingController controller, String label) =>
+        Expanded(
+          child: TextField(
+            controller: controller,
+            inputFormatters: [FilteringTextInputFormatter.digitsOnly],
+            decoration: InputDecoration(labelText: translate(label)),
+          ).workaroundFreezeLinuxMint(),
+        );
+
+    return CustomAlertDialog(
+      title: Text(translate('Session tunnels')),
+      content: SizedBox(
+        width: 480,
+        child: Column(
+          mainAxisSize: MainAxisSize.min,
+          crossAxisAlignment: CrossAxisAlignment.start,
+          children: [
+            ...tunnels.map((t) => ListTile(
+                  dense: true,
+                  contentPadding: EdgeInsets.zero,
+                  title: Text(describe(t)),
+                  subtitle: Text(kindLabel(t['kind'])),
+                  trailing: IconButton(
+                    icon: const Icon(Icons.delete_outline),
+                    tooltip: translate('Delete'),
+                    onPressed: () => remove(t),
+                  ),
+                )),
+            if (tunnels.isNotEmpty) const Divider(),
+            DropdownButton<String>(
+              value: kind,
+              items: kinds
+                  .map((e) => DropdownMenuItem(
+                      value: e.$1, child: Text(translate(e.$2))))
+                  .toList(),
+              onChanged: (v) {
+                if (v != null) setState(() => kind = v);
+              },
+            ),
+            Row(
+              children: [
+                portField(localPort, 'Local Port'),
+                if (!isSocks) ...[
+                  const SizedBox(width: 8),
+                  Expanded(
+                    flex: 2,
+                    child: TextField(
+                      controller: remoteHost,
+                      decoration: InputDecoration(
+                          labelText: translate('Remote Host'),
+                          hintText: 'localhost'),
+                    ).workaroundFreezeLinuxMint(),
+                  ),
+                  const SizedBox(width: 8),
+                  portField(remotePort, 'Remote Port'),
+                ],
+              ],
+            ),
+          ],
+        ),
+      ),
+      actions: [
+        dialogButton('Close', onPressed: close, isOutline: true),
+        dialogButton('Add', onPressed: add),
+      ],
+      onSubmit: add,
+      onCancel: close,
+    );
+  }, tag: 'session-tunnels');
+}
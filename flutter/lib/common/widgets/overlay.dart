@@ -596,6 +596,9 @@ class QualityMonitor extends StatelessWidget {
                       _row(
                           "Codec", qualityMonitorModel.data.codecFormat ?? '-'),
                       _row("Chroma", qualityMonitorModel.data.chroma ?? '-'),
+                      if (qualityMonitorModel.data.bweState != null)
+                        _row("Bandwidth",
+                            "${qualityMonitorModel.data.bandwidth ?? '-'}kb ${qualityMonitorModel.data.bweState}"),
                     ],
                   ),
                 )
@@ -1,3 +1,5 @@
+import 'dart:convert';
+
 import 'package:bot_toast/bot_toast.dart';
 import 'package:flutter/material.dart';
 import 'package:flutter/services.dart';
@@ -254,9 +256,13 @@ class _PeerCardState extends State<_PeerCard>
     return Tooltip(
       message: !(isDesktop || isWebDesktop)
           ? ''
-          : peer.tags.isNotEmpty
-              ? '${translate('Tags')}: ${peer.tags.join(', ')}'
-              : '',
+          : [
+              if (peer.tags.isNotEmpty)
+                '${translate('Tags')}: ${peer.tags.join(', ')}',
+              if (peer.osVersion.isNotEmpty) peer.osVersion,
+              if (peer.version.isNotEmpty)
+                '${translate('Version')}: ${peer.version}',
+            ].join('\n'),
       child: Stack(children: [
         Obx(
           () => deco == null
@@ -547,6 +553,7 @@ abstract class BasePeerCard extends StatelessWidget {
     bool isRDP = false,
     bool isTerminal = false,
     bool isTerminalRunAsAdmin = false,
+    bool isAudioOnly = false,
   }) {
     return MenuEntryButton<String>(
       childBuilder: (TextStyle? style) => Text(
@@ -566,6 +573,7 @@ abstract class BasePeerCard extends StatelessWidget {
           isTcpTunneling: isTcpTunneling,
           isRDP: isRDP,
           isTerminal: isTerminal || isTerminalRunAsAdmin,
+          isAudioOnly: isAudioOnly,
         );
       },
       padding: menuPadding,
@@ -619,6 +627,15 @@ abstract class BasePeerCard extends StatelessWidget {
     );
   }
 
+  @protected
+  MenuEntryBase<String> _audioOnlyAction(BuildContext context) {
+    return _connectCommonAction(
+      context,
+      translate('Audio only'),
+      isAudioOnly: true,
+    );
+  }
+
   @protected
   MenuEntryBase<String> _tcpTunnelingAction(BuildContext context) {
     return _connectCommonAction(
@@ -674,7 +691,13 @@ abstract class BasePeerCard extends StatelessWidget {
         style: style,
       ),
       proc: () {
-        bind.mainWol(id: id);
+        final relayPeers =
+            List<String>.from(jsonDecode(bind.mainGetWolRelayPeers(id: id)));
+        if (relayPeers.isEmpty) {
+          bind.mainWol(id: id);
+        } else {
+          _wolDialog(peer, tab, relayPeers);
+        }
       },
       padding: menuPadding,
       dismissOnClicked: true,
@@ -752,6 +775,21 @@ abstract class BasePeerCard extends StatelessWidget {
     );
   }
 
+  @protected
+  MenuEntryBase<String> _networkSettingsAction(String id) {
+    return MenuEntryButton<String>(
+      childBuilder: (TextStyle? style) => Text(
+        translate('Network settings'),
+        style: style,
+      ),
+      proc: () {
+        _networkSettingsDialog(id);
+      },
+      padding: menuPadding,
+      dismissOnClicked: true,
+    );
+  }
+
   @protected
   MenuEntryBase<String> _renameAction(String id) {
     return MenuEntryButton<String>(
@@ -979,12 +1017,19 @@ class RecentPeerCard extends BasePeerCard {
 
     final List favs = (await bind.mainGetFav()).toList();
 
-    if (isDesktop && peer.platform != kPeerPlatformAndroid) {
+    if ((isDesktop || isIOS) && peer.platform != kPeerPlatformAndroid) {
       menuItems.add(_tcpTunnelingAction(context));
     }
+    if (isDesktop) {
+      menuItems.add(_audioOnlyAction(context));
+    }
     // menuItems.add(await _openNewConnInOptAction(peer.id));
     if (!isWeb) {
       menuItems.add(await _forceAlwaysRelayAction(peer.id));
+      menuItems.add(_networkSettingsAction(peer.id));
+    }
+    if (!isWeb && !peer.online && tab != PeerTabIndex.lan) {
+      menuItems.add(_wolAction(peer.id));
     }
     if (isWindows && peer.platform == kPeerPlatformWindows) {
       menuItems.add(_rdpAction(context, peer.id));
@@ -1042,12 +1087,19 @@ class FavoritePeerCard extends BasePeerCard {
       menuItems.add(_terminalRunAsAdminAction(context));
     }
 
-    if (isDesktop && peer.platform != kPeerPlatformAndroid) {
+    if ((isDesktop || isIOS) && peer.platform != kPeerPlatformAndroid) {
       menuItems.add(_tcpTunnelingAction(context));
     }
+    if (isDesktop) {
+      menuItems.add(_audioOnlyAction(context));
+    }
     // menuItems.add(await _openNewConnInOptAction(peer.id));
     if (!isWeb) {
       menuItems.add(await _forceAlwaysRelayAction(peer.id));
+      menuItems.add(_networkSettingsAction(peer.id));
+    }
+    if (!isWeb && !peer.online && tab != PeerTabIndex.lan) {
+      menuItems.add(_wolAction(peer.id));
     }
     if (isWindows && peer.platform == kPeerPlatformWindows) {
       menuItems.add(_rdpAction(context, peer.id));
@@ -1104,12 +1156,19 @@ class DiscoveredPeerCard extends BasePeerCard {
 
     final List favs = (await bind.mainGetFav()).toList();
 
-    if (isDesktop && peer.platform != kPeerPlatformAndroid) {
+    if ((isDesktop || isIOS) && peer.platform != kPeerPlatformAndroid) {
       menuItems.add(_tcpTunnelingAction(context));
     }
+    if (isDesktop) {
+      menuItems.add(_audioOnlyAction(context));
+    }
     // menuItems.add(await _openNewConnInOptAction(peer.id));
     if (!isWeb) {
       menuItems.add(await _forceAlwaysRelayAction(peer.id));
+      menuItems.add(_networkSettingsAction(peer.id));
+    }
+    if (!isWeb && !peer.online && tab != PeerTabIndex.lan) {
+      menuItems.add(_wolAction(peer.id));
     }
     if (isWindows && peer.platform == kPeerPlatformWindows) {
       menuItems.add(_rdpAction(context, peer.id));
@@ -1161,12 +1220,19 @@ class AddressBookPeerCard extends BasePeerCard {
       menuItems.add(_terminalRunAsAdminAction(context));
     }
 
-    if (isDesktop && peer.platform != kPeerPlatformAndroid) {
+    if ((isDesktop || isIOS) && peer.platform != kPeerPlatformAndroid) {
       menuItems.add(_tcpTunnelingAction(context));
     }
+    if (isDesktop) {
+      menuItems.add(_audioOnlyAction(context));
+    }
     // menuItems.add(await _openNewConnInOptAction(peer.id));
     if (!isWeb) {
       menuItems.add(await _forceAlwaysRelayAction(peer.id));
+      menuItems.add(_networkSettingsAction(peer.id));
+    }
+    if (!isWeb && !peer.online && tab != PeerTabIndex.lan) {
+      menuItems.add(_wolAction(peer.id));
     }
     if (isWindows && peer.platform == kPeerPlatformWindows) {
       menuItems.add(_rdpAction(context, peer.id));
@@ -1318,12 +1384,19 @@ class MyGroupPeerCard extends BasePeerCard {
       menuItems.add(_terminalRunAsAdminAction(context));
     }
 
-    if (isDesktop && peer.platform != kPeerPlatformAndroid) {
+    if ((isDesktop || isIOS) && peer.platform != kPeerPlatformAndroid) {
       menuItems.add(_tcpTunnelingAction(context));
     }
+    if (isDesktop) {
+      menuItems.add(_audioOnlyAction(context));
+    }
     // menuItems.add(await _openNewConnInOptAction(peer.id));
     if (!isWeb) {
       menuItems.add(await _forceAlwaysRelayAction(peer.id));
+      menuItems.add(_networkSettingsAction(peer.id));
+    }
+    if (!isWeb && !peer.online && tab != PeerTabIndex.lan) {
+      menuItems.add(_wolAction(peer.id));
     }
     if (isWindows && peer.platform == kPeerPlatformWindows) {
       menuItems.add(_rdpAction(context, peer.id));
@@ -1464,6 +1537,172 @@ void _rdpDialog(String id) async {
   });
 }
 
+void _networkSettingsDialog(String id) async {
+  final relayServerController = TextEditingController(
+      text: await bind.mainGetPeerOption(id: id, key: kOptionPeerRelayServer));
+  final portController = TextEditingController(
+      text: await bind.mainGetPeerOption(id: id, key: kOptionDirectAccessPort));
+  final policy = (option2bool(kOptionForceAlwaysRelay,
+              await bind.mainGetPeerOption(id: id, key: kOptionForceAlwaysRelay))
+          ? kOptionForceAlwaysRelay
+          : option2bool(kOptionDirectOnly,
+                  await bind.mainGetPeerOption(id: id, key: kOptionDirectOnly))
+              ? kOptionDirectOnly
+              : '')
+      .obs;
+
+  gFFI.dialogManager.show((setState, close, context) {
+    submit() async {
+      await bind.mainSetPeerOption(
+          id: id,
+          key: kOptionForceAlwaysRelay,
+          value: bool2option(
+              kOptionForceAlwaysRelay, policy.value == kOptionForceAlwaysRelay));
+      await bind.mainSetPeerOption(
+          id: id,
+          key: kOptionDirectOnly,
+          value: bool2option(kOptionDirectOnly, policy.value == kOptionDirectOnly));
+      await bind.mainSetPeerOption(
+          id: id,
+          key: kOptionPeerRelayServer,
+          value: relayServerController.text.trim());
+      await bind.mainSetPeerOption(
+          id: id,
+          key: kOptionDirectAccessPort,
+          value: portController.text.trim());
+      showToast(translate('Successful'));
+      close();
+    }
+
+    Widget policyRadio(String value, String label) {
+      return Obx(() => RadioListTile<String>(
+            value: value,
+            groupValue: policy.value,
+            title: Text(translate(label)),
+            dense: true,
+            contentPadding: EdgeInsets.zero,
+            onChanged: (v) {
+              if (v != null) policy.value = v;
+            },
+          ));
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Network settings')),
+      content: ConstrainedBox(
+        constraints: const BoxConstraints(minWidth: 500),
+        child: Column(
+          crossAxisAlignment: CrossAxisAlignment.start,
+          children: [
+            policyRadio('', 'Auto'),
+            policyRadio(kOptionForceAlwaysRelay, 'Always connect via relay'),
+            policyRadio(kOptionDirectOnly, 'Direct connection only'),
+            TextField(
+              decoration: InputDecoration(
+                  labelText: translate('Relay Server'),
+                  hintText: translate('peer_relay_server_tip')),
+              controller: relayServerController,
+            ).workaroundFreezeLinuxMint().marginOnly(bottom: 8),
+            TextField(
+              inputFormatters: [
+                FilteringTextInputFormatter.allow(RegExp(
+                    r'^([0-9]|[1-9]\d|[1-9]\d{2}|[1-9]\d{3}|[1-5]\d{4}|6[0-4]\d{3}|65[0-4]\d{2}|655[0-2]\d|6553[0-5])$'))
+              ],
+              decoration: InputDecoration(
+                  labelText: translate('Port'),
+                  hintText: '21118'),
+              controller: portController,
+            ).workaroundFreezeLinuxMint(),
+          ],
+        ),
+      ),
+      actions: [
+        dialogButton("Cancel", onPressed: close, isOutline: true),
+        dialogButton("OK", onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
+void _wolDialog(Peer peer, PeerTabIndex tab, List<String> relayPeers) {
+  final delayController = TextEditingController(
+      text: bind.mainGetLocalOption(key: kOptionWolRetryDelay).isEmpty
+          ? '$kDefaultWolRetryDelay'
+          : bind.mainGetLocalOption(key: kOptionWolRetryDelay));
+  final emitter = ''.obs;
+
+  gFFI.dialogManager.show((setState, close, context) {
+    submit() async {
+      final delay =
+          int.tryParse(delayController.text.trim()) ?? kDefaultWolRetryDelay;
+      await bind.mainSetLocalOption(
+          key: kOptionWolRetryDelay, value: delay.toString());
+      if (emitter.value.isEmpty) {
+        await bind.mainWol(id: peer.id);
+      } else {
+        final err =
+            await bind.mainWolVia(id: peer.id, relayId: emitter.value);
+        if (err.isNotEmpty) {
+          showToast(translate(err));
+          return;
+        }
+      }
+      close();
+      if (delay > 0) {
+        showToast(translate('wol_retry_tip').replaceAll('{}', '$delay'));
+        Future.delayed(Duration(seconds: delay), () {
+          final ctx = globalKey.currentContext;
+          if (ctx != null) {
+            connectInPeerTab(ctx, peer, tab);
+          }
+        });
+      }
+    }
+
+    Widget emitterRadio(String value, String label) {
+      return Obx(() => RadioListTile<String>(
+            value: value,
+            groupValue: emitter.value,
+            title: Text(label),
+            dense: true,
+            contentPadding: EdgeInsets.zero,
+            onChanged: (v) {
+              if (v != null) emitter.value = v;
+            },
+          ));
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('WOL')),
+      content: ConstrainedBox(
+        constraints: const BoxConstraints(minWidth: 500),
+        child: Column(
+          crossAxisAlignment: CrossAxisAlignment.start,
+          children: [
+            Text(translate('Send the magic packet from')),
+            emitterRadio('', translate('This device')),
+            ...relayPeers.map((id) => emitterRadio(id, id)),
+            TextField(
+              inputFormatters: [FilteringTextInputFormatter.digitsOnly],
+              decoration: InputDecoration(
+                  labelText: translate('Reconnect after (seconds)')),
+              controller: delayController,
+            ).workaroundFreezeLinuxMint(),
+          ],
+        ),
+      ),
+      actions: [
+        dialogButton("Cancel", onPressed: close, isOutline: true),
+        dialogButton("OK", onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
 Widget getOnline(double rightPadding, bool online) {
   return Tooltip(
       message: translate(online ? 'Online' : 'Offline'),
@@ -1543,7 +1782,8 @@ void connectInPeerTab(BuildContext context, Peer peer, PeerTabIndex tab,
     bool isViewCamera = false,
     bool isTcpTunneling = false,
     bool isRDP = false,
-    bool isTerminal = false}) async {
+    bool isTerminal = false,
+    bool isAudioOnly = false}) async {
   var password = '';
   bool isSharedPassword = false;
   if (tab == PeerTabIndex.ab) {
@@ -1577,5 +1817,6 @@ void connectInPeerTab(BuildContext context, Peer peer, PeerTabIndex tab,
       isTerminal: isTerminal,
       isViewCamera: isViewCamera,
       isTcpTunneling: isTcpTunneling,
-      isRDP: isRDP);
+      isRDP: isRDP,
+      isAudioOnly: isAudioOnly);
 }
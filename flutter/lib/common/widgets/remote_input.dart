@@ -4,6 +4,7 @@ import 'dart:math';
 import 'package:flutter/material.dart';
 import 'package:flutter/services.dart';
 import 'package:flutter/gestures.dart';
+import 'package:get/get.dart';
 
 import 'package:flutter_hbb/models/platform_model.dart';
 import 'package:flutter_hbb/common.dart';
@@ -116,10 +117,13 @@ class _RawTouchGestureDetectorRegionState
 
   @override
   Widget build(BuildContext context) {
-    return RawGestureDetector(
-      child: widget.child,
-      gestures: makeGestures(context),
-    );
+    // The fingers are sent to the peer as they are when the touch is forwarded.
+    return Obx(() => RawGestureDetector(
+          child: widget.child,
+          gestures: inputModel.touchForwarding.value
+              ? const <Type, GestureRecognizerFactory>{}
+              : makeGestures(context),
+        ));
   }
 
   bool isNotTouchBasedDevice() {
@@ -587,6 +591,7 @@ class RawPointerMouseRegion extends StatelessWidget {
         inputModel.onPointUpImage(evt);
       },
       onPointerMove: inputModel.onPointMoveImage,
+      onPointerCancel: inputModel.onPointCancelImage,
       onPointerSignal: inputModel.onPointerSignalImage,
       onPointerPanZoomStart: inputModel.onPointerPanZoomStart,
       onPointerPanZoomUpdate: inputModel.onPointerPanZoomUpdate,
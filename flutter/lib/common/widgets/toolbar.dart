@@ -1,6 +1,7 @@
 import 'dart:async';
 import 'dart:convert';
 
+import 'package:file_picker/file_picker.dart';
 import 'package:flutter/material.dart';
 import 'package:flutter/services.dart';
 import 'package:flutter_hbb/common.dart';
@@ -192,6 +193,14 @@ List<TTextMenu> toolbarControls(BuildContext context, String id, FFI ffi) {
           onPressed: () => connectWithToken(isTcpTunneling: true)),
     );
   }
+  // session tunnels
+  if (isDefaultConn && isDesktop && pi.isSupportTunnel) {
+    v.add(
+      TTextMenu(
+          child: Text(translate('Session tunnels')),
+          onPressed: () => showSessionTunnelsDialog(ffi)),
+    );
+  }
   // note
   if (isDefaultConn &&
       bind
@@ -218,6 +227,65 @@ List<TTextMenu> toolbarControls(BuildContext context, String id, FFI ffi) {
           onPressed: () => bind.sessionCtrlAltDel(sessionId: sessionId)),
     );
   }
+  // special keys
+  if (isDefaultConn &&
+      !ffiModel.viewOnly &&
+      ffiModel.keyboard &&
+      pi.specialKeys.isNotEmpty) {
+    v.add(
+      TTextMenu(
+          child: Text(translate('Special keys')),
+          onPressed: () => showSpecialKeysDialog(ffi)),
+    );
+  }
+  // input script
+  if (isDefaultConn && isDesktop && !ffiModel.viewOnly && ffiModel.keyboard) {
+    final recording = bind.sessionIsInputRecording(sessionId: sessionId);
+    v.add(
+      TTextMenu(
+          child: Text(translate(
+              recording ? 'Stop recording input' : 'Record input')),
+          onPressed: () async {
+            String res;
+            if (recording) {
+              final ts = DateTime.now().millisecondsSinceEpoch ~/ 1000;
+              final path = await FilePicker.platform.saveFile(
+                dialogTitle: '${translate('Save as')}...',
+                fileName: 'input_$ts.json',
+                allowedExtensions: ['json'],
+                type: FileType.custom,
+              );
+              if (path == null) return;
+              res = await bind.sessionStopInputRecording(
+                  sessionId: sessionId, path: path);
+            } else {
+              res = await bind.sessionStartInputRecording(sessionId: sessionId);
+            }
+            if (res.isNotEmpty) {
+              msgBox(sessionId, 'custom-nook-nocancel-hasclose-error',
+                  'Record input', res, '', ffi.dialogManager);
+            }
+          }),
+    );
+    if (!recording) {
+      v.add(
+        TTextMenu(
+            child: Text(translate('Replay input')),
+            onPressed: () async {
+              final result = await FilePicker.platform.pickFiles(
+                  allowedExtensions: ['json'], type: FileType.custom);
+              final path = result?.files.single.path;
+              if (path == null) return;
+              final res =
+                  await bind.sessionReplayInput(sessionId: sessionId, path: path);
+              if (res.isNotEmpty) {
+                msgBox(sessionId, 'custom-nook-nocancel-hasclose-error',
+                    'Replay input', res, '', ffi.dialogManager);
+              }
+            }),
+      );
+    }
+  }
   // restart
   if (isDefaultConn &&
       perms['restart'] != false &&
@@ -231,6 +299,19 @@ List<TTextMenu> toolbarControls(BuildContext context, String id, FFI ffi) {
               showRestartRemoteDevice(pi, id, sessionId, ffi.dialogManager)),
     );
   }
+  // switch windows session
+  final windowsSessions =
+      pi.platformAdditions[kPlatformAdditionsWindowsSessions];
+  if (isDefaultConn &&
+      !ffiModel.viewOnly &&
+      windowsSessions is List &&
+      windowsSessions.length > 1) {
+    v.add(
+      TTextMenu(
+          child: Text(translate('Switch Windows session')),
+          onPressed: () => showSwitchWindowsSessionDialog(ffi, id)),
+    );
+  }
   // insertLock
   if (isDefaultConn && !ffiModel.viewOnly && ffi.ffiModel.keyboard) {
     v.add(
@@ -239,11 +320,22 @@ List<TTextMenu> toolbarControls(BuildContext context, String id, FFI ffi) {
           onPressed: () => bind.sessionLockScreen(sessionId: sessionId)),
     );
   }
+  // clipboard history
+  if (isDefaultConn &&
+      isDesktop &&
+      ffiModel.keyboard &&
+      perms['clipboard'] != false &&
+      bind.sessionGetToggleOptionSync(
+          sessionId: sessionId, arg: kOptionManualClipboardSync)) {
+    v.add(TTextMenu(
+        child: Text(translate('Clipboard history')),
+        onPressed: () => showClipboardHistoryDialog(ffi)));
+  }
   // blockUserInput
   if (isDefaultConn &&
       ffi.ffiModel.keyboard &&
       ffi.ffiModel.permissions['block_input'] != false &&
-      pi.platform == kPeerPlatformWindows) // privacy-mode != true ??
+      pi.isSupportBlockInput) // privacy-mode != true ??
   {
     v.add(TTextMenu(
         child: Obx(() => Text(translate(
@@ -332,6 +424,20 @@ List<TTextMenu> toolbarControls(BuildContext context, String id, FFI ffi) {
       ));
     }
   }
+  // audio device of the peer
+  if (isDefaultConn && pi.isSupportAudioDevice && perms['audio'] != false) {
+    v.add(TTextMenu(
+      child: Text(translate('Remote audio device')),
+      onPressed: () => bind.sessionQueryAudioDevices(sessionId: sessionId),
+    ));
+  }
+  // short authentication string
+  if (bind.sessionGetSas(sessionId: sessionId).isNotEmpty) {
+    v.add(TTextMenu(
+      child: Text(translate('Verify connection')),
+      onPressed: () => verifyConnectionDialog(sessionId, ffi.dialogManager),
+    ));
+  }
   // fingerprint
   if (!(isDesktop || isWebDesktop)) {
     v.add(TTextMenu(
@@ -654,6 +760,25 @@ Future<List<TToggleMenu>> toolbarDisplayToggle(
             : null,
         child: Text(translate('Disable clipboard'))));
   }
+  // manual clipboard sync
+  if (isDefaultConn &&
+      isDesktop &&
+      ffiModel.keyboard &&
+      perms['clipboard'] != false) {
+    final enabled = !ffiModel.viewOnly;
+    final value = bind.sessionGetToggleOptionSync(
+        sessionId: sessionId, arg: kOptionManualClipboardSync);
+    v.add(TToggleMenu(
+        value: value,
+        onChanged: enabled
+            ? (value) {
+                if (value == null) return;
+                bind.sessionToggleOption(
+                    sessionId: sessionId, value: kOptionManualClipboardSync);
+              }
+            : null,
+        child: Text(translate('Manual clipboard sync'))));
+  }
   // lock after session end
   if (isDefaultConn && ffiModel.keyboard && !ffiModel.isPeerAndroid) {
     final enabled = !ffiModel.viewOnly;
@@ -874,11 +999,85 @@ List<TToggleMenu> toolbarKeyboardToggles(FFI ffi) {
         onChanged: enabled ? onChanged : null,
         child: Text(translate('swap-left-right-mouse'))));
   }
+
+  // relative mouse mode
+  if (ffiModel.keyboard && pi.isSupportRelativeMouse && isDesktop) {
+    final option = kOptionRelativeMouseMode;
+    final value =
+        bind.sessionGetToggleOptionSync(sessionId: sessionId, arg: option);
+    onChanged(bool? value) {
+      if (value == null) return;
+      bind.sessionToggleOption(sessionId: sessionId, value: option);
+      if (!value) ffi.inputModel.unlockRelativeMouse();
+    }
+
+    final enabled = !ffi.ffiModel.viewOnly;
+    v.add(TToggleMenu(
+        value: value,
+        onChanged: enabled ? onChanged : null,
+        child: Text(translate('Relative mouse mode'))));
+  }
+
+  // forward touch
+  if (ffiModel.keyboard && pi.isSupportTouch) {
+    final option = kOptionForwardTouch;
+    final value =
+        bind.sessionGetToggleOptionSync(sessionId: sessionId, arg: option);
+    onChanged(bool? value) {
+      if (value == null) return;
+      bind.sessionToggleOption(sessionId: sessionId, value: option);
+      ffi.inputModel.updateTouchForwarding();
+    }
+
+    final enabled = !ffi.ffiModel.viewOnly;
+    v.add(TToggleMenu(
+        value: value,
+        onChanged: enabled ? onChanged : null,
+        child: Text(translate('Forward touch'))));
+  }
+
+  // local echo
+  if (ffiModel.keyboard && isDesktop) {
+    final option = kOptionLocalEcho;
+    final value =
+        bind.sessionGetToggleOptionSync(sessionId: sessionId, arg: option);
+    onChanged(bool? value) {
+      if (value == null) return;
+      bind.sessionToggleOption(sessionId: sessionId, value: option);
+      ffi.localEchoModel.updateEnabled();
+    }
+
+    final enabled = !ffi.ffiModel.viewOnly;
+    v.add(TToggleMenu(
+        value: value,
+        onChanged: enabled ? onChanged : null,
+        child: Tooltip(
+            message: translate('local_echo_tip'),
+            child: Text(translate('Local echo')))));
+  }
+
+  // forward gamepads
+  if (ffiModel.keyboard && pi.isSupportGamepad && isDesktop) {
+    final option = 'forward-gamepad';
+    final value =
+        bind.sessionGetToggleOptionSync(sessionId: sessionId, arg: option);
+    onChanged(bool? value) {
+      if (value == null) return;
+      bind.sessionToggleOption(sessionId: sessionId, value: option);
+    }
+
+    final enabled = !ffi.ffiModel.viewOnly;
+    v.add(TToggleMenu(
+        value: value,
+        onChanged: enabled ? onChanged : null,
+        child: Text(translate('Forward gamepads'))));
+  }
   return v;
 }
 
 bool showVirtualDisplayMenu(FFI ffi) {
-  if (ffi.ffiModel.pi.platform != kPeerPlatformWindows) {
+  if (![kPeerPlatformWindows, kPeerPlatformLinux, kPeerPlatformMacOS]
+      .contains(ffi.ffiModel.pi.platform)) {
     return false;
   }
   if (!ffi.ffiModel.pi.isInstalled) {
@@ -916,6 +1115,18 @@ List<Widget> getVirtualDisplayMenuChildren(
             ffi: ffi,
           )));
     }
+    if (pi.isSupportVirtualDisplayModes) {
+      children.add(Obx(() => MenuButton(
+            onPressed: privacyModeState.isNotEmpty
+                ? null
+                : () {
+                    showVirtualDisplayModeDialog(ffi);
+                    clickCallBack?.call();
+                  },
+            ffi: ffi,
+            child: Text('${translate('Custom virtual display')}...'),
+          )));
+    }
     children.add(Divider());
     children.add(Obx(() => MenuButton(
           onPressed: privacyModeState.isNotEmpty
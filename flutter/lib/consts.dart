@@ -28,6 +28,23 @@ const String kPlatformAdditionsAmyuniVirtualDisplays =
 const String kPlatformAdditionsHasFileClipboard = "has_file_clipboard";
 const String kPlatformAdditionsSupportedPrivacyModeImpl =
     "supported_privacy_mode_impl";
+const String kPlatformAdditionsVirtualDisplayModes = "virtual_display_modes";
+const String kPlatformAdditionsSupportAudioDevice = "support_audio_device";
+const String kPlatformAdditionsSupportMicPassthrough =
+    "support_mic_passthrough";
+const String kPlatformAdditionsSupportGamepad = "support_gamepad";
+const String kPlatformAdditionsSupportPen = "support_pen";
+const String kPlatformAdditionsSupportTouch = "support_touch";
+const String kPlatformAdditionsSupportBlockInput = "support_block_input";
+const String kPlatformAdditionsSpecialKeys = "special_keys";
+const String kPlatformAdditionsSupportRelativeMouse = "support_relative_mouse";
+const String kPlatformAdditionsSupportTunnel = "support_tunnel";
+const String kPlatformAdditionsSupportFileOps = "support_file_ops";
+const String kPlatformAdditionsSupportFileDrop = "support_file_drop";
+const String kPlatformAdditionsSupportTransferBroker =
+    "support_transfer_broker";
+const String kPlatformAdditionsWindowsSessions = "windows_sessions";
+const String kPlatformAdditionsWindowsCurrentSid = "windows_current_sid";
 
 const String kPeerPlatformWindows = "Windows";
 const String kPeerPlatformLinux = "Linux";
@@ -85,18 +102,27 @@ const String kOptionD3DRender = "allow-d3d-render";
 const String kOptionOpenInTabs = "allow-open-in-tabs";
 const String kOptionOpenInWindows = "allow-open-in-windows";
 const String kOptionForceAlwaysRelay = "force-always-relay";
+const String kOptionDirectOnly = "direct-only";
+const String kOptionPeerRelayServer = "peer-relay-server";
+const String kOptionWolRetryDelay = "wol-retry-delay";
+const String kOptionVirtualDisplayMatchWindow = "virtual-display-match-window";
+const int kDefaultWolRetryDelay = 30;
 const String kOptionViewOnly = "view_only";
 const String kOptionEnableLanDiscovery = "enable-lan-discovery";
 const String kOptionWhitelist = "whitelist";
 const String kOptionEnableAbr = "enable-abr";
+const String kOptionEnableBwe = "enable-bwe";
 const String kOptionEnableRecordSession = "enable-record-session";
 const String kOptionDirectServer = "direct-server";
 const String kOptionDirectAccessPort = "direct-access-port";
 const String kOptionAllowAutoDisconnect = "allow-auto-disconnect";
 const String kOptionAutoDisconnectTimeout = "auto-disconnect-timeout";
+const String kOptionAllowMaxSessionDuration = "allow-max-session-duration";
+const String kOptionMaxSessionDuration = "max-session-duration";
 const String kOptionEnableHwcodec = "enable-hwcodec";
 const String kOptionAllowAutoRecordIncoming = "allow-auto-record-incoming";
 const String kOptionAllowAutoRecordOutgoing = "allow-auto-record-outgoing";
+const String kOptionAllowRecordAudio = "allow-record-audio";
 const String kOptionVideoSaveDirectory = "video-save-directory";
 const String kOptionAccessMode = "access-mode";
 const String kOptionEnableKeyboard = "enable-keyboard";
@@ -109,6 +135,11 @@ const String kOptionEnableCamera = "enable-camera";
 const String kOptionEnableTerminal = "enable-terminal";
 const String kOptionTerminalPersistent = "terminal-persistent";
 const String kOptionEnableTunnel = "enable-tunnel";
+const String kOptionAllowRemoteTunnel = "allow-remote-tunnel";
+const String kOptionAllowTunnelPublicListen = "allow-tunnel-public-listen";
+const String kOptionAllowWolRelay = "allow-wol-relay";
+const String kOptionAllowMicPassthrough = "allow-mic-passthrough";
+const String kOptionAllowRemoteGamepad = "allow-remote-gamepad";
 const String kOptionEnableRemoteRestart = "enable-remote-restart";
 const String kOptionEnableBlockInput = "enable-block-input";
 const String kOptionAllowRemoteConfigModification =
@@ -132,6 +163,22 @@ const String kOptionPrivacyMode = "privacy_mode";
 const String kOptionTouchMode = "touch-mode";
 const String kOptionI444 = "i444";
 const String kOptionSwapLeftRightMouse = "swap-left-right-mouse";
+const String kOptionRelativeMouseMode = "relative-mouse-mode";
+const String kOptionForwardTouch = "forward-touch";
+const String kOptionLocalEcho = "local-echo";
+const String kOptionDisableAutoKeyboardMode = "disable-auto-keyboard-mode";
+const String kOptionKeyRemap = "key-remap";
+const String kOptionAllowHotkeyPassthrough = "allow-hotkey-passthrough";
+const String kOptionPauseFileTransferForVideo = "pause-file-transfer-for-video";
+const String kOptionFileTransferRateLimit = "file-transfer-rate-limit";
+const String kOptionFileTransferMaxJobs = "file-transfer-max-jobs";
+const String kOptionTransferXattrs = "transfer-xattrs";
+const String kOptionEnableTransferNotification =
+    "enable-transfer-notification";
+const String kOptionClipboardMaxSize = "clipboard-max-size";
+const String kOptionClipboardDirection = "clipboard-direction";
+const String kOptionClipboardFilters = "clipboard-filters";
+const String kOptionManualClipboardSync = "manual-clipboard-sync";
 const String kOptionCodecPreference = "codec-preference";
 const String kOptionRemoteMenubarDragLeft = "remote-menubar-drag-left";
 const String kOptionRemoteMenubarDragRight = "remote-menubar-drag-right";
@@ -148,13 +195,30 @@ const String kOptionAllowAlwaysSoftwareRender = "allow-always-software-render";
 const String kOptionEnableCheckUpdate = "enable-check-update";
 const String kOptionAllowAutoUpdate = "allow-auto-update";
 const String kOptionAllowLinuxHeadless = "allow-linux-headless";
+const String kOptionLinuxHeadlessUser = "linux-headless-user";
+const String kOptionLinuxHeadlessResolution = "linux-headless-resolution";
 const String kOptionAllowRemoveWallpaper = "allow-remove-wallpaper";
 const String kOptionStopService = "stop-service";
 const String kOptionDirectxCapture = "enable-directx-capture";
+const String kOptionEnablePowerSavingCapture = "enable-power-saving-capture";
 const String kOptionAllowRemoteCmModification = "allow-remote-cm-modification";
 const String kOptionEnableUdpPunch = "enable-udp-punch";
 const String kOptionEnableIpv6Punch = "enable-ipv6-punch";
 const String kOptionEnableTrustedDevices = "enable-trusted-devices";
+const String kOptionRequireSecurityKey = "allow-require-security-key";
+const String kOptionSecurityKeys = "security-keys";
+const String kOptionPeerProfiles = "peer-profiles";
+const String kOptionAccessControlList = "access-control-list";
+const String kOptionAllowAuditLog = "allow-audit-log";
+const String kOptionAllowPushAuditLog = "allow-push-audit-log";
+const String kOptionProtectSettings = "protect-settings";
+const String kOptionServerKeyPins = "server-key-pins";
+const String kOptionAllowReportPatchLevel = "allow-report-patch-level";
+const String kOptionMinClientVersion = "min-client-version";
+const String kOptionAllowPrivilegeSeparation =
+    "allow-privilege-separation";
+const String kOptionAllowSignIncomingRecording =
+    "allow-sign-incoming-recording";
 const String kOptionShowVirtualMouse = "show-virtual-mouse";
 const String kOptionVirtualMouseScale = "virtual-mouse-scale";
 const String kOptionShowVirtualJoystick = "show-virtual-joystick";
@@ -193,6 +257,7 @@ const int kWindowMainId = 0;
 
 const String kPointerEventKindTouch = "touch";
 const String kPointerEventKindMouse = "mouse";
+const int kPenMaxPressure = 1024;
 
 const String kMouseEventTypeDefault = "";
 const String kMouseEventTypePanStart = "pan_start";
@@ -200,6 +265,7 @@ const String kMouseEventTypePanUpdate = "pan_update";
 const String kMouseEventTypePanEnd = "pan_end";
 const String kMouseEventTypeDown = "down";
 const String kMouseEventTypeUp = "up";
+const String kMouseEventTypeMoveRelative = "move_relative";
 
 const String kKeyFlutterKey = "flutter_key";
 
@@ -310,6 +376,10 @@ const kMouseControlDistance = 12;
 /// [kMouseControlTimeoutMSec] indicates the timeout (in milliseconds) that self-side can get control of mouse.
 const kMouseControlTimeoutMSec = 1000;
 
+/// The remote cursor jumps to the new position if the positions are sparser or farther than these.
+const kCursorSmoothMaxInterval = Duration(milliseconds: 100);
+const kCursorSmoothMaxDistance = 300.0;
+
 /// [kRemoteViewStyleOriginal] Show remote image without scaling.
 const kRemoteViewStyleOriginal = 'original';
 
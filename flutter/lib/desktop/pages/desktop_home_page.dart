@@ -789,6 +789,7 @@ class _DesktopHomePageState extends State<DesktopHomePage>
           isTerminal: call.arguments['isTerminal'],
           isTcpTunneling: call.arguments['isTcpTunneling'],
           isRDP: call.arguments['isRDP'],
+          isAudioOnly: call.arguments['isAudioOnly'] ?? false,
           password: call.arguments['password'],
           forceRelay: call.arguments['forceRelay'],
           connToken: call.arguments['connToken'],
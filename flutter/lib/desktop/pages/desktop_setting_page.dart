@@ -480,6 +480,7 @@ class _GeneralState extends State<_General> {
             kOptionEnableConfirmClosingTabs,
             isServer: false),
       _OptionCheckBox(context, 'Adaptive bitrate', kOptionEnableAbr),
+      _OptionCheckBox(context, 'Bandwidth estimation', kOptionEnableBwe),
       if (!isWeb) wallpaper(),
       if (!isWeb && !bind.isIncomingOnly()) ...[
         _OptionCheckBox(
@@ -520,6 +521,50 @@ class _GeneralState extends State<_General> {
               isServer: false,
             ),
           ),
+        if (!isWeb && !(isLinux && bind.mainCurrentIsWayland()))
+          Tooltip(
+            message: translate('hotkey_passthrough_tip'),
+            child: Row(
+              children: [
+                Expanded(
+                  child: _OptionCheckBox(
+                    context,
+                    'Pass shortcuts to the remote',
+                    kOptionAllowHotkeyPassthrough,
+                    isServer: false,
+                  ),
+                ),
+                TextButton(
+                  onPressed: showPassthroughHotkeysDialog,
+                  child: Text(translate('Edit')),
+                ),
+              ],
+            ),
+          ),
+        if (!isWeb) ...[
+          _OptionCheckBox(
+            context,
+            'Pause file transfers while a remote session lags',
+            kOptionPauseFileTransferForVideo,
+            isServer: false,
+          ),
+          _OptionCheckBox(
+            context,
+            'Transfer the extended attributes of the files',
+            kOptionTransferXattrs,
+            isServer: false,
+          ),
+          _OptionCheckBox(
+            context,
+            'Notify when a file transfer is done or failed',
+            kOptionEnableTransferNotification,
+            isServer: false,
+          ),
+          _transferRateLimit(context, isServer: false),
+          _Button('Transfer history', showTransferHistoryDialog),
+          _Button('Clipboard limits',
+              () => showClipboardPolicyDialog(isServer: false)),
+        ],
         if (!isWeb && !bind.isCustomClient())
           _OptionCheckBox(
             context,
@@ -540,6 +585,12 @@ class _GeneralState extends State<_General> {
             'Capture screen using DirectX',
             kOptionDirectxCapture,
           ),
+        if (!bind.isOutgoingOnly())
+          _OptionCheckBox(
+            context,
+            'Reduce the frame rate on battery or when overheated',
+            kOptionEnablePowerSavingCapture,
+          ),
         if (!bind.isIncomingOnly()) ...[
           _OptionCheckBox(
             context,
@@ -559,6 +610,7 @@ class _GeneralState extends State<_General> {
     if (!isWeb && bind.mainShowOption(key: kOptionAllowLinuxHeadless)) {
       children.add(_OptionCheckBox(
           context, 'Allow linux headless', kOptionAllowLinuxHeadless));
+      children.add(_Button('Headless session', showLinuxHeadlessDialog));
     }
     return _Card(title: 'Other', children: children);
   }
@@ -630,7 +682,7 @@ class _GeneralState extends State<_General> {
     builder(devices, currentDevice, setDevice) {
       final child = ComboBox(
         keys: devices,
-        values: devices,
+        values: devices.map(AudioInput.displayName).toList(),
         initialKey: currentDevice,
         onChanged: (key) async {
           setDevice(key);
@@ -668,10 +720,20 @@ class _GeneralState extends State<_General> {
         if (!bind.isOutgoingOnly())
           _OptionCheckBox(context, 'Automatically record incoming sessions',
               kOptionAllowAutoRecordIncoming),
+        if (!bind.isOutgoingOnly())
+          _OptionCheckBox(context, 'Record and sign all incoming sessions',
+              kOptionAllowSignIncomingRecording),
+        if (!bind.isOutgoingOnly())
+          _Button('Verify a recording', verifyRecording,
+              tip: 'sign-incoming-recording-tip'),
         if (!bind.isIncomingOnly())
           _OptionCheckBox(context, 'Automatically record outgoing sessions',
               kOptionAllowAutoRecordOutgoing,
               isServer: false),
+        if (!bind.isIncomingOnly())
+          _OptionCheckBox(context, 'Record the remote audio',
+              kOptionAllowRecordAudio,
+              isServer: false),
         if (showRootDir && !bind.isOutgoingOnly())
           Row(
             children: [
@@ -809,6 +871,7 @@ class _SafetyState extends State<_Safety> with AutomaticKeepAliveClientMixin {
                 permissions(context),
                 password(context),
                 _Card(title: '2FA', children: [tfa()]),
+                _Card(title: 'Security key', children: [securityKey()]),
                 _Card(title: 'ID', children: [changeId()]),
                 more(context),
               ]),
@@ -936,6 +999,27 @@ class _SafetyState extends State<_Safety> with AutomaticKeepAliveClientMixin {
     return tmpWrapper();
   }
 
+  Widget securityKey() {
+    return Row(
+      children: [
+        Flexible(
+          child: Tooltip(
+            waitDuration: Duration(milliseconds: 300),
+            message: translate("require-security-key-tip"),
+            child: _OptionCheckBox(context, "Require security key",
+                kOptionRequireSecurityKey,
+                enabled: !locked, update: (v) {
+              setState(() {});
+            }),
+          ),
+        ),
+        ElevatedButton(
+            onPressed: locked ? null : manageSecurityKeysDialog,
+            child: Text(translate('Manage security keys')))
+      ],
+    );
+  }
+
   Widget changeId() {
     return ChangeNotifierProvider.value(
         value: gFFI.serverModel,
@@ -1002,23 +1086,60 @@ class _SafetyState extends State<_Safety> with AutomaticKeepAliveClientMixin {
               _OptionCheckBox(
                   context, 'Enable remote printer', kOptionEnableRemotePrinter,
                   enabled: enabled, fakeValue: fakeValue),
-            _OptionCheckBox(context, 'Enable clipboard', kOptionEnableClipboard,
-                enabled: enabled, fakeValue: fakeValue),
+            Row(
+              children: [
+                Expanded(
+                  child: _OptionCheckBox(
+                      context, 'Enable clipboard', kOptionEnableClipboard,
+                      enabled: enabled, fakeValue: fakeValue),
+                ),
+                TextButton(
+                  onPressed: enabled
+                      ? () => showClipboardPolicyDialog(isServer: true)
+                      : null,
+                  child: Text(translate('Clipboard limits')),
+                ),
+              ],
+            ),
             _OptionCheckBox(
                 context, 'Enable file transfer', kOptionEnableFileTransfer,
                 enabled: enabled, fakeValue: fakeValue),
+            _OptionCheckBox(
+                context,
+                'Pause file transfers while a remote session lags',
+                kOptionPauseFileTransferForVideo,
+                enabled: enabled),
+            _transferRateLimit(context, isServer: true, enabled: enabled),
             _OptionCheckBox(context, 'Enable audio', kOptionEnableAudio,
                 enabled: enabled, fakeValue: fakeValue),
             _OptionCheckBox(context, 'Enable camera', kOptionEnableCamera,
                 enabled: enabled, fakeValue: fakeValue),
+            _OptionCheckBox(
+                context,
+                'Allow playing the microphone of the peer',
+                kOptionAllowMicPassthrough,
+                enabled: enabled, fakeValue: fakeValue),
+            if (isWindows || isLinux)
+              _OptionCheckBox(context, 'Allow remote gamepads',
+                  kOptionAllowRemoteGamepad,
+                  enabled: enabled, fakeValue: fakeValue),
             _OptionCheckBox(context, 'Enable terminal', kOptionEnableTerminal,
                 enabled: enabled, fakeValue: fakeValue),
             _OptionCheckBox(
                 context, 'Enable TCP tunneling', kOptionEnableTunnel,
                 enabled: enabled, fakeValue: fakeValue),
+            _OptionCheckBox(context, 'Allow session tunnels and SOCKS5 proxy',
+                kOptionAllowRemoteTunnel,
+                enabled: enabled, fakeValue: fakeValue),
+            _OptionCheckBox(context, 'allow-tunnel-public-listen-tip',
+                kOptionAllowTunnelPublicListen,
+                enabled: enabled, fakeValue: fakeValue),
             _OptionCheckBox(
                 context, 'Enable remote restart', kOptionEnableRemoteRestart,
                 enabled: enabled, fakeValue: fakeValue),
+            _OptionCheckBox(
+                context, 'Allow Wake-on-LAN relaying', kOptionAllowWolRelay,
+                enabled: enabled, fakeValue: fakeValue),
             _OptionCheckBox(
                 context, 'Enable recording session', kOptionEnableRecordSession,
                 enabled: enabled, fakeValue: fakeValue),
@@ -1029,6 +1150,8 @@ class _SafetyState extends State<_Safety> with AutomaticKeepAliveClientMixin {
             _OptionCheckBox(context, 'Enable remote configuration modification',
                 kOptionAllowRemoteConfigModification,
                 enabled: enabled, fakeValue: fakeValue),
+            _Button('Peer permission profiles', peerProfilesDialog,
+                enabled: !locked, tip: 'peer-profiles-tip'),
           ],
         ),
       ]);
@@ -1182,6 +1305,9 @@ class _SafetyState extends State<_Safety> with AutomaticKeepAliveClientMixin {
             if (usePassword)
               _SubButton('Set permanent password', setPasswordDialog,
                   permEnabled && !locked),
+            if (usePassword)
+              _Button('Single-use passwords', manageSingleUsePasswordsDialog,
+                  enabled: !locked, tip: 'single-use-passwords-tip'),
             // if (usePassword)
             //   hide_cm(!locked).marginOnly(left: _kContentHSubMargin - 6),
             if (usePassword) radios[2],
@@ -1197,11 +1323,36 @@ class _SafetyState extends State<_Safety> with AutomaticKeepAliveClientMixin {
           reverse: true, enabled: enabled),
       ...directIp(context),
       whitelist(),
+      _Button('Access control list', changeAccessControlList,
+          enabled: enabled && !isOptionFixed(kOptionAccessControlList),
+          tip: 'access-control-list-tip'),
+      _Button('Login lockouts', manageLockoutsDialog,
+          enabled: enabled, tip: 'login-lockouts-tip'),
+      _OptionCheckBox(context, 'Protect security settings',
+          kOptionProtectSettings,
+          enabled: enabled),
+      _Button('Set admin password', setSettingsAdminPasswordDialog,
+          enabled: enabled, tip: 'protect-settings-tip'),
+      _OptionCheckBox(context, 'Enable audit log', kOptionAllowAuditLog,
+          enabled: enabled),
+      _OptionCheckBox(
+          context, 'Push audit log to the server', kOptionAllowPushAuditLog,
+          enabled: enabled),
+      _OptionCheckBox(context, 'report-patch-level-tip',
+          kOptionAllowReportPatchLevel,
+          enabled: enabled),
+      _Button('Minimum client version', setMinClientVersionDialog,
+          enabled: enabled, tip: 'min-client-version-tip'),
       ...autoDisconnect(context),
+      ...maxSessionDuration(context),
       if (bind.mainIsInstalled())
         _OptionCheckBox(context, 'allow-only-conn-window-open-tip',
             'allow-only-conn-window-open',
             reverse: false, enabled: enabled),
+      if (isWindows && bind.mainIsInstalled())
+        _OptionCheckBox(context, 'privilege-separation-tip',
+            kOptionAllowPrivilegeSeparation,
+            enabled: enabled),
       if (bind.mainIsInstalled()) unlockPin()
     ]);
   }
@@ -1392,20 +1543,29 @@ class _SafetyState extends State<_Safety> with AutomaticKeepAliveClientMixin {
   }
 
   List<Widget> autoDisconnect(BuildContext context) {
+    return _timeoutOption(context, 'auto_disconnect_option_tip',
+        kOptionAllowAutoDisconnect, kOptionAutoDisconnectTimeout, '10');
+  }
+
+  List<Widget> maxSessionDuration(BuildContext context) {
+    return _timeoutOption(context, 'max_session_duration_option_tip',
+        kOptionAllowMaxSessionDuration, kOptionMaxSessionDuration, '60');
+  }
+
+  List<Widget> _timeoutOption(BuildContext context, String label,
+      String allowKey, String timeoutKey, String hint) {
     TextEditingController controller = TextEditingController();
     update(bool v) => setState(() {});
     RxBool applyEnabled = false.obs;
     return [
-      _OptionCheckBox(
-          context, 'auto_disconnect_option_tip', kOptionAllowAutoDisconnect,
+      _OptionCheckBox(context, label, allowKey,
           update: update, enabled: !locked),
       () {
-        bool enabled = option2bool(kOptionAllowAutoDisconnect,
-            bind.mainGetOptionSync(key: kOptionAllowAutoDisconnect));
+        bool enabled =
+            option2bool(allowKey, bind.mainGetOptionSync(key: allowKey));
         if (!enabled) applyEnabled.value = false;
-        controller.text =
-            bind.mainGetOptionSync(key: kOptionAutoDisconnectTimeout);
-        final isOptFixed = isOptionFixed(kOptionAutoDisconnectTimeout);
+        controller.text = bind.mainGetOptionSync(key: timeoutKey);
+        final isOptFixed = isOptionFixed(timeoutKey);
         return Offstage(
           offstage: !enabled,
           child: _SubLabeledWidget(
@@ -1422,8 +1582,8 @@ class _SafetyState extends State<_Safety> with AutomaticKeepAliveClientMixin {
                     FilteringTextInputFormatter.allow(RegExp(
                         r'^([0-9]|[1-9]\d|[1-9]\d{2}|[1-9]\d{3}|[1-5]\d{4}|6[0-4]\d{3}|65[0-4]\d{2}|655[0-2]\d|6553[0-5])$')),
                   ],
-                  decoration: const InputDecoration(
-                    hintText: '10',
+                  decoration: InputDecoration(
+                    hintText: hint,
                     contentPadding:
                         EdgeInsets.symmetric(vertical: 12, horizontal: 12),
                   ),
@@ -1435,8 +1595,7 @@ class _SafetyState extends State<_Safety> with AutomaticKeepAliveClientMixin {
                             ? () async {
                                 applyEnabled.value = false;
                                 await bind.mainSetOption(
-                                    key: kOptionAutoDisconnectTimeout,
-                                    value: controller.text);
+                                    key: timeoutKey, value: controller.text);
                               }
                             : null,
                     child: Text(
@@ -1499,6 +1658,9 @@ class _NetworkState extends State<_Network> with AutomaticKeepAliveClientMixin {
   bool locked = !isWeb && bind.mainIsInstalled();
 
   final scrollController = ScrollController();
+  Map<String, dynamic> relayLatencies =
+      isWeb ? {} : jsonDecode(bind.mainGetRelayLatencies());
+  bool probingRelays = false;
 
   @override
   Widget build(BuildContext context) {
@@ -1517,6 +1679,13 @@ class _NetworkState extends State<_Network> with AutomaticKeepAliveClientMixin {
     ]).marginOnly(bottom: _kListViewBottomMargin);
   }
 
+  probeRelays() async {
+    setState(() => probingRelays = true);
+    await bind.mainProbeRelayServers();
+    relayLatencies = jsonDecode(bind.mainGetRelayLatencies());
+    if (mounted) setState(() => probingRelays = false);
+  }
+
   Widget network(BuildContext context) {
     final hideServer =
         bind.mainGetBuildinOption(key: kOptionHideServerSetting) == 'Y';
@@ -1524,6 +1693,14 @@ class _NetworkState extends State<_Network> with AutomaticKeepAliveClientMixin {
         isWeb || bind.mainGetBuildinOption(key: kOptionHideProxySetting) == 'Y';
     final hideWebSocket = isWeb ||
         bind.mainGetBuildinOption(key: kOptionHideWebSocketSetting) == 'Y';
+    final relayServers = bind
+        .mainGetOptionSync(key: 'relay-server')
+        .split(',')
+        .map((e) => e.trim())
+        .where((e) => e.isNotEmpty)
+        .toSet()
+        .toList();
+    final showRelayLatency = !hideServer && !isWeb && relayServers.length > 1;
 
     if (hideServer && hideProxy && hideWebSocket) {
       return Offstage();
@@ -1598,6 +1775,45 @@ class _NetworkState extends State<_Network> with AutomaticKeepAliveClientMixin {
                   title: 'ID/Relay Server',
                   onTap: () => showServerSettings(gFFI.dialogManager),
                 ),
+              if (!hideServer)
+                listTile(
+                  icon: Icons.verified_user_outlined,
+                  title: 'Server key pins',
+                  showTooltip: true,
+                  tooltipMessage: 'server-key-pins-tip',
+                  onTap: () => serverKeyPinsDialog(gFFI.dialogManager),
+                ),
+              if (showRelayLatency)
+                listTile(
+                  icon: Icons.speed_outlined,
+                  title: 'Relay server latency',
+                  showTooltip: true,
+                  tooltipMessage: 'relay_latency_tip',
+                  trailing: probingRelays
+                      ? SizedBox(
+                          width: 16,
+                          height: 16,
+                          child: CircularProgressIndicator(strokeWidth: 2))
+                      : IconButton(
+                          icon: Icon(Icons.refresh),
+                          onPressed: locked ? null : probeRelays,
+                        ),
+                ),
+              if (showRelayLatency)
+                ...relayServers.map((server) {
+                  final latency = relayLatencies[server];
+                  final text = latency == null
+                      ? '-'
+                      : latency < 0
+                          ? translate('Unreachable')
+                          : '$latency ms';
+                  return Row(children: [
+                    Expanded(
+                        child: Text(server,
+                            style: TextStyle(fontSize: _kContentFontSize))),
+                    Text(text, style: TextStyle(fontSize: _kContentFontSize)),
+                  ]).marginSymmetric(horizontal: 48, vertical: 2);
+                }),
               if (!hideServer && (!hideProxy || !hideWebSocket))
                 Divider(height: 1, indent: 16, endIndent: 16),
               if (!hideProxy)
@@ -1766,11 +1982,15 @@ class _DisplayState extends State<_Display> {
     final groupValue =
         bind.mainGetUserDefaultOption(key: kOptionCodecPreference);
     var hwRadios = [];
+    var av1Label = 'AV1';
     final isOptFixed = isOptionFixed(kOptionCodecPreference);
     try {
       final Map codecsJson = jsonDecode(bind.mainSupportedHwdecodings());
       final h264 = codecsJson['h264'] ?? false;
       final h265 = codecsJson['h265'] ?? false;
+      if (codecsJson['av1'] ?? false) {
+        av1Label = 'AV1 (${translate('Hardware')})';
+      }
       if (h264) {
         hwRadios.add(_Radio(context,
             value: 'h264',
@@ -1807,7 +2027,7 @@ class _DisplayState extends State<_Display> {
       _Radio(context,
           value: 'av1',
           groupValue: groupValue,
-          label: 'AV1',
+          label: av1Label,
           onChanged: isOptFixed ? null : onChanged),
       ...hwRadios,
     ]);
@@ -2534,6 +2754,36 @@ Widget _SubLabeledWidget(BuildContext context, String label, Widget child,
   ).marginOnly(left: _kContentHSubMargin);
 }
 
+/// The limit of the file transfers in KB/s, empty for none.
+Widget _transferRateLimit(BuildContext context,
+    {required bool isServer, bool enabled = true}) {
+  const key = kOptionFileTransferRateLimit;
+  final controller = TextEditingController(
+      text: isServer
+          ? bind.mainGetOptionSync(key: key)
+          : bind.mainGetLocalOption(key: key));
+  enabled = enabled && !isOptionFixed(key);
+  return _SubLabeledWidget(
+    context,
+    'Transfer rate limit (KB/s)',
+    SizedBox(
+      width: 95,
+      child: TextField(
+        controller: controller,
+        enabled: enabled,
+        inputFormatters: [FilteringTextInputFormatter.digitsOnly],
+        decoration: const InputDecoration(
+          contentPadding: EdgeInsets.symmetric(vertical: 12, horizontal: 12),
+        ),
+        onChanged: (value) => isServer
+            ? bind.mainSetOption(key: key, value: value)
+            : bind.mainSetLocalOption(key: key, value: value),
+      ).workaroundFreezeLinuxMint(),
+    ),
+    enabled: enabled,
+  );
+}
+
 Widget _lock(
   bool locked,
   String label,
@@ -2560,6 +2810,13 @@ Widget _lock(
                             Text(translate(label)).marginOnly(left: 5),
                           ]).marginSymmetric(vertical: 2)),
                   onPressed: () async {
+                    final settingsProtection =
+                        jsonDecode(await bind.mainGetSettingsProtection());
+                    if (settingsProtection['locked'] == true) {
+                      final onUnlockPassed = onUnlock;
+                      onUnlock = () =>
+                          unlockProtectedSettingsDialog(onUnlockPassed);
+                    }
                     final unlockPin = bind.mainGetUnlockPin();
                     if (unlockPin.isEmpty) {
                       bool checked = await callMainCheckSuperUserPermission();
@@ -2690,6 +2947,210 @@ class _CountDownButtonState extends State<_CountDownButton> {
 
 //#region dialogs
 
+void peerProfilesDialog() {
+  // peer id -> {"view_only": bool, "keyboard": bool?, ...}, unset follows the global permission
+  Map<String, Map<String, dynamic>> profiles = {};
+  try {
+    final raw = bind.mainGetOptionSync(key: kOptionPeerProfiles);
+    if (raw.isNotEmpty) {
+      (jsonDecode(raw) as Map<String, dynamic>).forEach((id, p) {
+        profiles[id] = Map<String, dynamic>.from(p);
+      });
+    }
+  } catch (e) {
+    debugPrint('Failed to parse peer profiles: $e');
+  }
+  const capabilities = [
+    ['keyboard', 'Enable keyboard/mouse'],
+    ['clipboard', 'Enable clipboard'],
+    ['file', 'Enable file transfer'],
+    ['audio', 'Enable audio'],
+    ['tunnel', 'Enable TCP tunneling'],
+    ['gamepad', 'Allow remote gamepads'],
+  ];
+  final idController = TextEditingController();
+  gFFI.dialogManager.show((setState, close, context) {
+    add() {
+      final id = idController.text.replaceAll(' ', '');
+      if (id.isEmpty || profiles.containsKey(id)) return;
+      setState(() {
+        profiles[id] = {};
+        idController.clear();
+      });
+    }
+
+    submit() async {
+      profiles.forEach((_, p) => p.removeWhere((_, v) => v == null));
+      await bind.mainSetOption(
+          key: kOptionPeerProfiles,
+          value: profiles.isEmpty ? '' : jsonEncode(profiles));
+      close();
+    }
+
+    Widget checkbox(Map<String, dynamic> profile, String key, String label,
+        {bool tristate = true}) {
+      return Row(
+        mainAxisSize: MainAxisSize.min,
+        children: [
+          Checkbox(
+              tristate: tristate,
+              value: tristate ? profile[key] : profile[key] == true,
+              onChanged: (v) => setState(() => profile[key] = v)),
+          Text(translate(label)),
+        ],
+      ).marginOnly(right: 8);
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Peer permission profiles')),
+      content: ConstrainedBox(
+        constraints: const BoxConstraints(minWidth: 600, maxHeight: 500),
+        child: SingleChildScrollView(
+          child: Column(
+            crossAxisAlignment: CrossAxisAlignment.start,
+            children: [
+              Text(translate('peer-profiles-tip')).marginOnly(bottom: 8),
+              ...profiles.entries.map((e) => Column(
+                    crossAxisAlignment: CrossAxisAlignment.start,
+                    children: [
+                      Row(children: [
+                        Expanded(
+                            child: Text(e.key,
+                                style: const TextStyle(
+                                    fontWeight: FontWeight.bold))),
+                        IconButton(
+                            icon: const Icon(Icons.delete_outline),
+                            onPressed: () =>
+                                setState(() => profiles.remove(e.key))),
+                      ]),
+                      Wrap(children: [
+                        checkbox(e.value, 'view_only', 'View Mode',
+                            tristate: false),
+                        ...capabilities
+                            .map((c) => checkbox(e.value, c[0], c[1])),
+                      ]),
+                      const Divider(),
+                    ],
+                  )),
+              Row(children: [
+                Expanded(
+                  child: TextField(
+                    controller: idController,
+                    decoration: InputDecoration(labelText: translate('ID')),
+                    onSubmitted: (_) => add(),
+                  ).workaroundFreezeLinuxMint(),
+                ),
+                dialogButton('Add', onPressed: add).marginOnly(left: 8),
+              ]),
+            ],
+          ),
+        ),
+      ),
+      actions: [
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
+void manageSecurityKeysDialog() {
+  final controller = TextEditingController(
+      text: bind
+          .mainGetOptionSync(key: kOptionSecurityKeys)
+          .split(',')
+          .where((e) => e.trim().isNotEmpty)
+          .join('\n'));
+  String errMsg = '';
+  var isInProgress = false;
+  gFFI.dialogManager.show((setState, close, context) {
+    register() async {
+      setState(() {
+        errMsg = '';
+        isInProgress = true;
+      });
+      final res = jsonDecode(await bind.mainRegisterSecurityKey());
+      final String credential = res['credential'] ?? '';
+      setState(() {
+        isInProgress = false;
+        if (credential.isNotEmpty) {
+          final text = controller.text.trim();
+          controller.text = text.isEmpty ? credential : '$text\n$credential';
+        } else {
+          errMsg = res['error'] ?? '';
+        }
+      });
+    }
+
+    submit() async {
+      final keys = controller.text
+          .split('\n')
+          .map((e) => e.trim())
+          .where((e) => e.isNotEmpty)
+          .join(',');
+      await bind.mainSetOption(key: kOptionSecurityKeys, value: keys);
+      close();
+    }
+
+    return CustomAlertDialog(
+      title: Text(translate('Manage security keys')),
+      content: ConstrainedBox(
+        constraints: const BoxConstraints(minWidth: 500),
+        child: Column(
+          crossAxisAlignment: CrossAxisAlignment.start,
+          children: [
+            Text(translate('security-keys-tip')).marginOnly(bottom: 8),
+            TextField(
+              controller: controller,
+              maxLines: 6,
+              decoration: InputDecoration(
+                errorText: errMsg.isNotEmpty ? translate(errMsg) : null,
+              ),
+            ).workaroundFreezeLinuxMint(),
+            // NOT use Offstage to wrap LinearProgressIndicator
+            if (isInProgress)
+              const LinearProgressIndicator().marginOnly(top: 8),
+          ],
+        ),
+      ),
+      actions: [
+        dialogButton('Register a security key',
+            onPressed: isInProgress ? null : register, isOutline: true),
+        dialogButton('Cancel', onPressed: close, isOutline: true),
+        dialogButton('OK', onPressed: submit),
+      ],
+      onSubmit: submit,
+      onCancel: close,
+    );
+  });
+}
+
+void verifyRecording() async {
+  final result = await FilePicker.platform.pickFiles(
+      initialDirectory: bind.mainVideoSaveDirectory(root: true));
+  final path = result?.files.single.path;
+  if (path == null) return;
+  final res = jsonDecode(await bind.mainVerifyRecording(path: path));
+  final String fingerprint = res['fingerprint'] ?? '';
+  if (fingerprint.isNotEmpty) {
+    final partial = res['complete'] == false
+        ? '\n\n${translate('recording-partially-signed-tip')}'
+        : '';
+    msgBox(
+        gFFI.sessionId,
+        'custom-nocancel-success',
+        'Verify a recording',
+        '${translate('recording-verified-tip')}\n\n$fingerprint$partial',
+        '',
+        gFFI.dialogManager);
+  } else {
+    msgBox(gFFI.sessionId, 'custom-nocancel-error', 'Verify a recording',
+        res['error'] ?? '', '', gFFI.dialogManager);
+  }
+}
+
 void changeSocks5Proxy() async {
   var socks = await bind.mainGetSocks();
 
@@ -3,6 +3,7 @@ import 'dart:io';
 import 'dart:math';
 
 import 'package:extended_text/extended_text.dart';
+import 'package:flutter_hbb/common/widgets/dialog.dart';
 import 'package:flutter_hbb/desktop/widgets/dragable_divider.dart';
 import 'package:percent_indicator/percent_indicator.dart';
 import 'package:desktop_drop/desktop_drop.dart';
@@ -276,6 +277,43 @@ class _FileManagerPageState extends State<FileManagerPage>
                         Row(
                           mainAxisAlignment: MainAxisAlignment.end,
                           children: [
+                            if (!isWeb &&
+                                item.type == JobType.transfer &&
+                                item.state == JobState.none) ...[
+                              MenuButton(
+                                tooltip: translate("Move up"),
+                                onPressed: () =>
+                                    jobController.moveJob(item.id, true),
+                                child: Icon(Icons.arrow_upward_rounded,
+                                    color: Colors.white),
+                                color: MyTheme.accent,
+                                hoverColor: MyTheme.accent80,
+                              ),
+                              MenuButton(
+                                tooltip: translate("Move down"),
+                                onPressed: () =>
+                                    jobController.moveJob(item.id, false),
+                                child: Icon(Icons.arrow_downward_rounded,
+                                    color: Colors.white),
+                                color: MyTheme.accent,
+                                hoverColor: MyTheme.accent80,
+                              ),
+                            ],
+                            Offstage(
+                              offstage: isWeb ||
+                                  item.type != JobType.transfer ||
+                                  (item.state != JobState.inProgress &&
+                                      item.state != JobState.none),
+                              child: MenuButton(
+                                tooltip: translate("Pause"),
+                                onPressed: () =>
+                                    jobController.pauseJob(item.id),
+                                child: Icon(Icons.pause_rounded,
+                                    color: Colors.white),
+                                color: MyTheme.accent,
+                                hoverColor: MyTheme.accent80,
+                              ),
+                            ),
                             Offstage(
                               offstage: item.state != JobState.paused,
                               child: MenuButton(
@@ -814,6 +852,24 @@ class _FileManagerViewState extends State<FileManagerView> {
                           color: Theme.of(context).cardColor,
                           hoverColor: Theme.of(context).hoverColor,
                         )),
+                    if (controller.fileOps.isSupported && !isWeb)
+                      MenuButton(
+                        tooltip: translate('Search'),
+                        onPressed: () => controller.fileOps.searchAction(),
+                        child: Icon(Icons.manage_search_rounded,
+                            color: Theme.of(context).tabBarTheme.labelColor),
+                        color: Theme.of(context).cardColor,
+                        hoverColor: Theme.of(context).hoverColor,
+                      ),
+                    if (isLocal && !isWeb)
+                      MenuButton(
+                        tooltip: translate('Synchronize folders'),
+                        onPressed: () => showFolderSyncDialog(_ffi),
+                        child: Icon(Icons.sync_rounded,
+                            color: Theme.of(context).tabBarTheme.labelColor),
+                        color: Theme.of(context).cardColor,
+                        hoverColor: Theme.of(context).hoverColor,
+                      ),
                     menu(isLocal: isLocal),
                   ],
                 ),
@@ -983,7 +1039,21 @@ class _FileManagerViewState extends State<FileManagerView> {
               Text(translate("Unselect All"), style: style),
           proc: () => selectedItems.clear(),
           padding: kDesktopMenuPadding,
-          dismissOnClicked: true)
+          dismissOnClicked: true),
+      if (isLocal && !isWeb)
+        MenuEntryButton(
+            childBuilder: (style) =>
+                Text(translate("Upload rate limit"), style: style),
+            proc: () => showUploadRateLimitDialog(_ffi),
+            padding: kDesktopMenuPadding,
+            dismissOnClicked: true),
+      if (isLocal && !isWeb)
+        MenuEntryButton(
+            childBuilder: (style) =>
+                Text(translate("Concurrent transfers"), style: style),
+            proc: () => showConcurrentTransfersDialog(_ffi),
+            padding: kDesktopMenuPadding,
+            dismissOnClicked: true),
     ];
 
     return Listener(
@@ -1106,7 +1176,33 @@ class _FileManagerViewState extends State<FileManagerView> {
                   onTap: () {
                     controller.renameAction(entry, isLocal);
                   },
-                )
+                ),
+              if (!entry.isDrive && controller.fileOps.isSupported) ...[
+                mod_menu.PopupMenuItem(
+                  child: Text(translate("Copy to")),
+                  height: CustomPopupMenuTheme.height,
+                  onTap: () => controller.fileOps
+                      .moveOrCopyAction(_menuItems(entry), copy: true),
+                ),
+                mod_menu.PopupMenuItem(
+                  child: Text(translate("Move to")),
+                  height: CustomPopupMenuTheme.height,
+                  onTap: () => controller.fileOps
+                      .moveOrCopyAction(_menuItems(entry), copy: false),
+                ),
+                mod_menu.PopupMenuItem(
+                  child: Text(translate("Properties")),
+                  height: CustomPopupMenuTheme.height,
+                  onTap: () => controller.fileOps.propertiesAction(entry),
+                ),
+              ],
+              if (!entry.isDrive && controller.transferBroker.isSupported)
+                mod_menu.PopupMenuItem(
+                  child: Text(translate("Send to another device")),
+                  height: CustomPopupMenuTheme.height,
+                  onTap: () => controller.transferBroker
+                      .sendAction(_menuItems(entry)),
+                ),
             ];
             if (items.isNotEmpty) {
               rightClickEntry.value = entry;
@@ -1314,6 +1410,11 @@ class _FileManagerViewState extends State<FileManagerView> {
     debugPrint("focused on ${searchResult.first.name}");
   }
 
+  // The selection if the entry right clicked is in it, else the entry.
+  List<Entry> _menuItems(Entry entry) => selectedItems.items.contains(entry)
+      ? selectedItems.items.toList()
+      : [entry];
+
   void _onSelectedChanged(SelectedItems selectedItems, List<Entry> entries,
       Entry entry, bool isLocal) {
     final isCtrlDown = RawKeyboard.instance.keysPressed
@@ -1,5 +1,6 @@
 import 'dart:async';
 
+import 'package:desktop_drop/desktop_drop.dart';
 import 'package:desktop_multi_window/desktop_multi_window.dart';
 import 'package:flutter/material.dart';
 import 'package:flutter/services.dart';
@@ -14,6 +15,7 @@ import '../../common/widgets/remote_input.dart';
 import '../../common.dart';
 import '../../common/widgets/dialog.dart';
 import '../../common/widgets/toolbar.dart';
+import '../../models/local_echo_model.dart';
 import '../../models/model.dart';
 import '../../models/platform_model.dart';
 import '../../common/shared_state.dart';
@@ -44,6 +46,7 @@ class RemotePage extends StatefulWidget {
     this.switchUuid,
     this.forceRelay,
     this.isSharedPassword,
+    this.isAudioOnly = false,
   }) : super(key: key) {
     initSharedStates(id);
   }
@@ -58,6 +61,7 @@ class RemotePage extends StatefulWidget {
   final String? switchUuid;
   final bool? forceRelay;
   final bool? isSharedPassword;
+  final bool isAudioOnly;
   final SimpleWrapper<State<RemotePage>?> _lastState = SimpleWrapper(null);
   final DesktopTabController? tabController;
 
@@ -126,6 +130,7 @@ class _RemotePageState extends State<RemotePage>
       tabWindowId: widget.tabWindowId,
       display: widget.display,
       displays: widget.displays,
+      isAudioOnly: widget.isAudioOnly,
     );
     WidgetsBinding.instance.addPostFrameCallback((_) {
       SystemChrome.setEnabledSystemUIMode(SystemUiMode.manual, overlays: []);
@@ -404,6 +409,7 @@ class _RemotePageState extends State<RemotePage>
           ChangeNotifierProvider.value(value: _ffi.cursorModel),
           ChangeNotifierProvider.value(value: _ffi.canvasModel),
           ChangeNotifierProvider.value(value: _ffi.recordingModel),
+          ChangeNotifierProvider.value(value: _ffi.localEchoModel),
         ], child: buildBody(context)));
   }
 
@@ -485,7 +491,24 @@ class _RemotePageState extends State<RemotePage>
     );
   }
 
+  // No video in an audio only session, only the peer being listened to.
+  Widget _buildAudioOnlyBody(BuildContext context) {
+    return Center(
+      child: Column(
+        mainAxisSize: MainAxisSize.min,
+        children: [
+          Icon(Icons.volume_up, size: 64, color: MyTheme.accent),
+          const SizedBox(height: 12),
+          Text('${translate('Audio only')}: ${widget.id}'),
+        ],
+      ),
+    );
+  }
+
   Widget getBodyForDesktop(BuildContext context) {
+    if (widget.isAudioOnly) {
+      return _buildAudioOnlyBody(context);
+    }
     var paints = <Widget>[
       MouseRegion(onEnter: (evt) {
         if (!isWeb) bind.hostStopSystemKeyPropagate(stopped: false);
@@ -525,6 +548,7 @@ class _RemotePageState extends State<RemotePage>
                   zoomCursor: _zoomCursor,
                 )));
     }
+    paints.add(LocalEchoPaint());
     paints.add(
       Positioned(
         top: 10,
@@ -533,9 +557,15 @@ class _RemotePageState extends State<RemotePage>
             QualityMonitor(_ffi.qualityMonitorModel), null, null),
       ),
     );
-    return Stack(
+    final body = Stack(
       children: paints,
     );
+    if (isWeb) return body;
+    return DropTarget(
+      onDragDone: (detail) => _ffi.fileModel.fileDrop.drop(
+          detail.files.map((f) => f.path).toList(), detail.globalPosition),
+      child: body,
+    );
   }
 
   @override
@@ -604,7 +634,8 @@ class _ImagePaintState extends State<ImagePaint> {
 
           return MouseRegion(
               cursor: cursorOverImage.isTrue
-                  ? c.cursorEmbedded
+                  ? c.cursorEmbedded ||
+                          widget.ffi.inputModel.relativeMouseLocked.isTrue
                       ? SystemMouseCursors.none
                       : keyboardEnabled.isTrue
                           ? (() {
@@ -825,6 +856,45 @@ class _ImagePaintState extends State<ImagePaint> {
   }
 }
 
+class LocalEchoPaint extends StatelessWidget {
+  const LocalEchoPaint({Key? key}) : super(key: key);
+
+  @override
+  Widget build(BuildContext context) {
+    final m = Provider.of<LocalEchoModel>(context);
+    final c = Provider.of<CanvasModel>(context);
+    final caret = m.caret;
+    final text = m.text;
+    if (caret == null || text.isEmpty) return Offstage();
+
+    double cx = c.x;
+    double cy = c.y;
+    final rect = c.parent.target?.ffiModel.rect;
+    if (c.viewStyle.style == kRemoteViewStyleOriginal &&
+        c.scrollStyle == ScrollStyle.scrollbar &&
+        rect != null) {
+      if (cx < 0) cx = -rect.width * c.scale * c.scrollX;
+      if (cy < 0) cy = -rect.height * c.scale * c.scrollY;
+    }
+    final style = LocalEchoModel.style;
+    final fontSize = style.fontSize! * c.scale;
+    return Positioned(
+      left: caret.dx * c.scale + cx,
+      top: caret.dy * c.scale + cy - fontSize * 0.6,
+      child: IgnorePointer(
+        child: Container(
+          color: Colors.black54,
+          child: Text(text,
+              style: style.copyWith(
+                  fontSize: fontSize,
+                  color: Colors.white,
+                  decoration: TextDecoration.underline)),
+        ),
+      ),
+    );
+  }
+}
+
 class CursorPaint extends StatelessWidget {
   final String id;
   final RxBool zoomCursor;
@@ -868,13 +938,13 @@ class CursorPaint extends StatelessWidget {
       }
     }
 
-    double x = (m.x - hotx) * c.scale + cx;
-    double y = (m.y - hoty) * c.scale + cy;
+    double x = (m.paintX - hotx) * c.scale + cx;
+    double y = (m.paintY - hoty) * c.scale + cy;
     double scale = 1.0;
     final isViewOriginal = c.viewStyle.style == kRemoteViewStyleOriginal;
     if (zoomCursor.value || isViewOriginal) {
-      x = m.x - hotx + cx / c.scale;
-      y = m.y - hoty + cy / c.scale;
+      x = m.paintX - hotx + cx / c.scale;
+      y = m.paintY - hoty + cy / c.scale;
       scale = c.scale;
     }
 
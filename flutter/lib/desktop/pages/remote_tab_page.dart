@@ -94,6 +94,7 @@ class _ConnectionTabPageState extends State<ConnectionTabPage> {
           switchUuid: params['switch_uuid'],
           forceRelay: params['forceRelay'],
           isSharedPassword: params['isSharedPassword'],
+          isAudioOnly: params['isAudioOnly'] ?? false,
         ),
       ));
       _update_remote_count();
@@ -437,6 +438,7 @@ class _ConnectionTabPageState extends State<ConnectionTabPage> {
           switchUuid: switchUuid,
           forceRelay: args['forceRelay'],
           isSharedPassword: args['isSharedPassword'],
+          isAudioOnly: args['isAudioOnly'] ?? false,
         ),
       ));
     } else if (call.method == kWindowDisableGrabKeyboard) {
@@ -522,6 +522,16 @@ class _CmHeaderState extends State<_CmHeader>
                       style: TextStyle(color: Colors.white70, fontSize: 12),
                     ),
                   ),
+                if (client.sas.isNotEmpty)
+                  Tooltip(
+                    message: translate("sas_tip"),
+                    child: FittedBox(
+                      child: Text(
+                        "${translate("Verification code")}: ${client.sas}",
+                        style: TextStyle(color: Colors.white70, fontSize: 12),
+                      ),
+                    ),
+                  ),
                 if (client.portForward.isNotEmpty)
                   FittedBox(
                     child: Text(
@@ -826,6 +836,28 @@ class _CmControlPanel extends StatelessWidget {
     return Column(
       mainAxisAlignment: MainAxisAlignment.end,
       children: [
+        Offstage(
+          offstage: !client.inputBlocked,
+          child: Container(
+            margin: EdgeInsets.symmetric(vertical: 4),
+            padding: EdgeInsets.all(8),
+            decoration: BoxDecoration(
+              color: Colors.orange.withOpacity(0.2),
+              borderRadius: BorderRadius.circular(4),
+            ),
+            child: Row(
+              children: [
+                Icon(Icons.lock_outline_rounded,
+                    color: Colors.orange, size: 16),
+                SizedBox(width: 8),
+                Expanded(
+                  child: Text(translate('input_blocked_by_peer_tip'),
+                      style: TextStyle(fontSize: 12)),
+                ),
+              ],
+            ),
+          ),
+        ),
         Offstage(
           offstage: !client.inVoiceCall,
           child: Row(
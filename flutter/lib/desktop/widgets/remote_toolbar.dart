@@ -495,6 +495,7 @@ class _RemoteToolbarState extends State<RemoteToolbar> {
       toolbarItems.add(_VoiceCallMenu(id: widget.id, ffi: widget.ffi));
     }
     if (!isWeb) toolbarItems.add(_RecordMenu());
+    toolbarItems.add(_PowerStateMenu(ffi: widget.ffi));
     toolbarItems.add(_CloseMenu(id: widget.id, ffi: widget.ffi));
     final toolbarBorderRadius = BorderRadius.all(Radius.circular(4.0));
     return Column(
@@ -1673,6 +1674,7 @@ class _ResolutionsMenuState extends State<_ResolutionsMenu> {
             _OriginalResolutionMenuButton(context, showOriginalBtn),
             _FitLocalResolutionMenuButton(context, showFitLocalBtn),
             _customResolutionMenuButton(context, isVirtualDisplay),
+            _matchWindowSizeMenuButton(context, isVirtualDisplay),
             _menuDivider(showOriginalBtn, showFitLocalBtn, isVirtualDisplay),
           ] +
           _supportedResolutionMenuButtons(),
@@ -1858,6 +1860,25 @@ class _ResolutionsMenuState extends State<_ResolutionsMenu> {
     );
   }
 
+  Widget _matchWindowSizeMenuButton(BuildContext context, isVirtualDisplay) {
+    return Offstage(
+      offstage: !isVirtualDisplay,
+      child: CkbMenuButton(
+        value: bind.sessionGetToggleOptionSync(
+            sessionId: ffi.sessionId, arg: kOptionVirtualDisplayMatchWindow),
+        onChanged: (bool? value) async {
+          if (value == null) return;
+          await bind.sessionToggleOption(
+              sessionId: ffi.sessionId,
+              value: kOptionVirtualDisplayMatchWindow);
+          ffi.canvasModel.updateViewStyle();
+        },
+        ffi: widget.ffi,
+        child: Text(translate('Match window size')),
+      ),
+    );
+  }
+
   Widget _resolutionInput(TextEditingController controller) {
     return TextField(
       decoration: InputDecoration(
@@ -1952,11 +1973,23 @@ class _KeyboardMenu extends StatelessWidget {
                 showMyCursor(),
               Divider(),
               ...toolbarToggles(),
+              keyRemap(),
               ...mouseSpeed(),
               ...mobileActions(),
             ]);
   }
 
+  keyRemap() {
+    if (!isDesktop) return Offstage();
+    final enabled = !ffi.ffiModel.viewOnly;
+    return MenuButton(
+      child:
+          Text(translate('Key remapping and macros')).paddingOnly(left: 26.0),
+      onPressed: enabled ? () => showKeyRemapDialog(ffi) : null,
+      ffi: ffi,
+    );
+  }
+
   mouseSpeed() {
     final speedWidgets = [];
     final sessionId = ffi.sessionId;
@@ -1987,6 +2020,9 @@ class _KeyboardMenu extends StatelessWidget {
       ];
       List<RdoMenuButton> list = [];
       final enabled = !ffi.ffiModel.viewOnly;
+      // The mode follows the keyboard layouts of the two sides.
+      final auto = !bind.sessionGetToggleOptionSync(
+          sessionId: ffi.sessionId, arg: kOptionDisableAutoKeyboardMode);
       onChanged(String? value) async {
         if (value == null) return;
         await bind.sessionSetKeyboardMode(
@@ -1994,6 +2030,13 @@ class _KeyboardMenu extends StatelessWidget {
         await ffi.inputModel.updateKeyboardMode();
       }
 
+      onAutoChanged(bool? value) async {
+        if (value == null) return;
+        await bind.sessionToggleOption(
+            sessionId: ffi.sessionId, value: kOptionDisableAutoKeyboardMode);
+        await ffi.inputModel.updateKeyboardMode();
+      }
+
       // If use flutter to grab keys, we can only use one mode.
       // Map mode and Legacy mode, at least one of them is supported.
       String? modeOnly;
@@ -2029,11 +2072,19 @@ class _KeyboardMenu extends StatelessWidget {
           child: Text(text),
           value: mode.key,
           groupValue: groupValue,
-          onChanged: enabled ? onChanged : null,
+          onChanged: enabled && !auto ? onChanged : null,
           ffi: ffi,
         ));
       }
-      return Column(children: list);
+      return Column(children: [
+        CkbMenuButton(
+          value: auto,
+          onChanged: enabled ? onAutoChanged : null,
+          child: Text(translate('Auto keyboard mode')),
+          ffi: ffi,
+        ),
+        ...list,
+      ]);
     });
   }
 
@@ -2206,7 +2257,14 @@ class _ChatMenuState extends State<_ChatMenu> {
           ffi: widget.ffi,
           color: _ToolbarTheme.blueColor,
           hoverColor: _ToolbarTheme.hoverBlueColor,
-          menuChildrenGetter: () => [textChat(), voiceCall()]);
+          menuChildrenGetter: () => [
+                textChat(),
+                voiceCall(),
+                if (widget.ffi.ffiModel.pi.isSupportMicPassthrough &&
+                    widget.ffi.chatModel.voiceCallStatus.value !=
+                        VoiceCallStatus.connected)
+                  micPassthrough(),
+              ]);
     }
   }
 
@@ -2249,6 +2307,19 @@ class _ChatMenuState extends State<_ChatMenu> {
           bind.sessionRequestVoiceCall(sessionId: widget.ffi.sessionId),
     );
   }
+
+  micPassthrough() {
+    final sessionId = widget.ffi.sessionId;
+    return CkbMenuButton(
+      value: bind.sessionGetMicPassthrough(sessionId: sessionId),
+      onChanged: (v) {
+        if (v == null) return;
+        bind.sessionSetMicPassthrough(sessionId: sessionId, on: v);
+      },
+      child: Text(translate('Send my microphone')),
+      ffi: widget.ffi,
+    );
+  }
 }
 
 class _VoiceCallMenu extends StatelessWidget {
@@ -2270,7 +2341,7 @@ class _VoiceCallMenu extends StatelessWidget {
                 .map((d) => RdoMenuButton<String>(
                       child: Container(
                         child: Text(
-                          d,
+                          AudioInput.displayName(d),
                           overflow: TextOverflow.ellipsis,
                         ),
                         constraints: BoxConstraints(maxWidth: 250),
@@ -2357,6 +2428,29 @@ class _RecordMenu extends StatelessWidget {
   }
 }
 
+// Shown while the peer reduces the frame rate for its power state.
+class _PowerStateMenu extends StatelessWidget {
+  final FFI ffi;
+  const _PowerStateMenu({Key? key, required this.ffi}) : super(key: key);
+
+  @override
+  Widget build(BuildContext context) {
+    return Obx(() {
+      final state = ffi.ffiModel.peerPowerState.value;
+      if (state['throttled'] != true) return Offstage();
+      final thermal = state['thermal_throttled'] == true;
+      return _IconMenuButton(
+        icon: Icon(thermal ? Icons.thermostat : Icons.battery_alert,
+            color: Colors.white, size: _ToolbarTheme.buttonSize - 6),
+        tooltip: thermal ? 'remote-overheated-tip' : 'remote-on-battery-tip',
+        onPressed: null,
+        color: _ToolbarTheme.blueColor,
+        hoverColor: _ToolbarTheme.hoverBlueColor,
+      );
+    });
+  }
+}
+
 class _CloseMenu extends StatelessWidget {
   final String id;
   final FFI ffi;
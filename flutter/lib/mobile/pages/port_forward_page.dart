@@ -0,0 +1,194 @@
+import 'dart:convert';
+
+import 'package:flutter/material.dart';
+import 'package:flutter/services.dart';
+import 'package:flutter_hbb/models/platform_model.dart';
+import 'package:get/get.dart';
+import 'package:wakelock_plus/wakelock_plus.dart';
+
+import '../../common.dart';
+import '../../common/widgets/dialog.dart';
+
+class _PortForward {
+  int localPort;
+  String remoteHost;
+  int remotePort;
+
+  _PortForward.fromJson(List<dynamic> json)
+      : localPort = json[0] as int,
+        remoteHost = json[1] as String,
+        remotePort = json[2] as int;
+}
+
+class PortForwardPage extends StatefulWidget {
+  PortForwardPage(
+      {Key? key,
+      required this.id,
+      required this.password,
+      required this.isSharedPassword,
+      this.forceRelay})
+      : super(key: key);
+  final String id;
+  final String? password;
+  final bool? isSharedPassword;
+  final bool? forceRelay;
+
+  @override
+  State<StatefulWidget> createState() => _PortForwardPageState();
+}
+
+class _PortForwardPageState extends State<PortForwardPage> {
+  final localPortController = TextEditingController();
+  final remoteHostController = TextEditingController();
+  final remotePortController = TextEditingController();
+  final pfs = RxList<_PortForward>.empty(growable: true);
+
+  @override
+  void initState() {
+    super.initState();
+    gFFI.start(widget.id,
+        isPortForward: true,
+        password: widget.password,
+        isSharedPassword: widget.isSharedPassword,
+        forceRelay: widget.forceRelay);
+    gFFI.ffiModel.updateEventListener(gFFI.sessionId, widget.id);
+    refreshTunnelConfig();
+    // The listeners are closed by iOS once the app is in the background.
+    WakelockPlus.enable();
+  }
+
+  @override
+  void dispose() {
+    gFFI.close();
+    gFFI.dialogManager.dismissAll();
+    WakelockPlus.disable();
+    localPortController.dispose();
+    remoteHostController.dispose();
+    remotePortController.dispose();
+    super.dispose();
+  }
+
+  @override
+  Widget build(BuildContext context) => Scaffold(
+        appBar: AppBar(
+          leading: IconButton(
+              icon: Icon(Icons.close),
+              onPressed: () => clientClose(gFFI.sessionId, gFFI.dialogManager)),
+          centerTitle: true,
+          title: Text(translate('TCP tunneling')),
+        ),
+        body: Column(
+          crossAxisAlignment: CrossAxisAlignment.stretch,
+          children: [
+            buildPrompt(),
+            buildAddForm(),
+            Expanded(
+              child: Obx(() => ListView.builder(
+                    itemCount: pfs.length,
+                    itemBuilder: (context, index) {
+                      final pf = pfs[index];
+                      return ListTile(
+                        leading: Icon(Icons.swap_horiz),
+                        title: Text(
+                            '${pf.localPort} → ${pf.remoteHost}:${pf.remotePort}'),
+                        trailing: IconButton(
+                          icon: Icon(Icons.close),
+                          onPressed: () async {
+                            await bind.sessionRemovePortForward(
+                                sessionId: gFFI.sessionId,
+                                localPort: pf.localPort);
+                            refreshTunnelConfig();
+                          },
+                        ),
+                      );
+                    },
+                  )),
+            ),
+          ],
+        ),
+      );
+
+  Widget buildPrompt() {
+    return Obx(() => Offstage(
+          offstage: pfs.isEmpty,
+          child: Container(
+              padding: EdgeInsets.symmetric(vertical: 6),
+              color: const Color(0xFF007F00),
+              child: Column(children: [
+                Text(
+                  translate('Listening ...'),
+                  style: const TextStyle(fontSize: 16, color: Colors.white),
+                ),
+                Text(
+                  translate('not_close_tcp_tip'),
+                  textAlign: TextAlign.center,
+                  style: const TextStyle(
+                      fontSize: 10, color: Color(0xFFDDDDDD), height: 1.2),
+                )
+              ])),
+        ));
+  }
+
+  Widget buildAddForm() {
+    final portInputFormatter = [
+      FilteringTextInputFormatter.allow(RegExp(
+          r'^([0-9]|[1-9]\d|[1-9]\d{2}|[1-9]\d{3}|[1-5]\d{4}|6[0-4]\d{3}|65[0-4]\d{2}|655[0-2]\d|6553[0-5])$'))
+    ];
+    field(TextEditingController controller, String label,
+            {List<TextInputFormatter>? inputFormatters, String? hint}) =>
+        Expanded(
+          child: TextField(
+            controller: controller,
+            inputFormatters: inputFormatters,
+            keyboardType:
+                inputFormatters != null ? TextInputType.number : null,
+            decoration: InputDecoration(
+                labelText: translate(label), hintText: hint),
+          ).marginSymmetric(horizontal: 6),
+        );
+
+    return Card(
+      margin: EdgeInsets.all(8),
+      child: Padding(
+        padding: EdgeInsets.all(8),
+        child: Row(children: [
+          field(localPortController, 'Local Port',
+              inputFormatters: portInputFormatter),
+          field(remoteHostController, 'Remote Host', hint: 'localhost'),
+          field(remotePortController, 'Remote Port',
+              inputFormatters: portInputFormatter),
+          IconButton(
+            icon: Icon(Icons.add),
+            tooltip: translate('Add'),
+            onPressed: addPortForward,
+          ),
+        ]),
+      ),
+    );
+  }
+
+  void addPortForward() async {
+    int? localPort = int.tryParse(localPortController.text);
+    int? remotePort = int.tryParse(remotePortController.text);
+    if (localPort == null || remotePort == null) {
+      return;
+    }
+    final remoteHost = remoteHostController.text.trim();
+    await bind.sessionAddPortForward(
+        sessionId: gFFI.sessionId,
+        localPort: localPort,
+        remoteHost: remoteHost.isEmpty ? 'localhost' : remoteHost,
+        remotePort: remotePort);
+    localPortController.clear();
+    remoteHostController.clear();
+    remotePortController.clear();
+    refreshTunnelConfig();
+  }
+
+  void refreshTunnelConfig() {
+    String peer = bind.mainGetPeerSync(id: widget.id);
+    Map<String, dynamic> config = jsonDecode(peer);
+    List<dynamic> infos = config['port_forwards'] as List? ?? [];
+    pfs.value = infos.map((e) => _PortForward.fromJson(e)).toList();
+  }
+}
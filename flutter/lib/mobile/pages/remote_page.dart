@@ -1111,8 +1111,8 @@ class CursorPaint extends StatelessWidget {
     return CustomPaint(
       painter: ImagePainter(
           image: image,
-          x: (m.x - hotx) * factor + c.x / s2,
-          y: (m.y - hoty) * factor + (c.y + adjust) / s2,
+          x: (m.paintX - hotx) * factor + c.x / s2,
+          y: (m.paintY - hoty) * factor + (c.y + adjust) / s2,
           scale: s2),
     );
   }
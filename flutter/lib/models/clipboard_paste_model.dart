@@ -0,0 +1,91 @@
+import 'dart:convert';
+
+import 'package:flutter/material.dart';
+import 'package:flutter_hbb/common.dart';
+import 'package:flutter_hbb/common/widgets/dialog.dart';
+import 'package:get/get.dart';
+
+import 'file_model.dart';
+
+/// Shows the progress of the files pasted from the clipboard of the peer,
+/// which is a transfer job of the session, see `src/clipboard_paste.rs`.
+class ClipboardPaste {
+  final FileModel model;
+
+  ClipboardPaste(this.model);
+
+  void onStarted(Map<String, dynamic> evt) {
+    final res = jsonDecode(evt['content']);
+    final int id = res['id'];
+    model.jobController.jobTable.add(JobProgress()
+      ..type = JobType.transfer
+      ..fileName = translate('Clipboard')
+      ..jobName = translate('Clipboard')
+      ..fileCount = res['files']
+      ..totalSize = res['total']
+      ..state = JobState.inProgress
+      ..id = id
+      ..isRemoteToLocal = true);
+    _showProgress(id);
+  }
+
+  void _showProgress(int id) {
+    final dialogManager = model.parent.target?.dialogManager;
+    if (dialogManager == null) return;
+    final jobs = model.jobController;
+    final tag = 'clipboard-paste-$id';
+    late final Worker worker;
+    worker = ever(jobs.jobTable, (_) {
+      final i = jobs.getJob(id);
+      if (i == -1 ||
+          jobs.jobTable[i].state == JobState.done ||
+          jobs.jobTable[i].state == JobState.error) {
+        worker.dispose();
+        dialogManager.dismissByTag(tag);
+        if (i != -1 && jobs.jobTable[i].state == JobState.error) {
+          showToast(translate(jobs.jobTable[i].err));
+        }
+      }
+    });
+    dialogManager.show((setState, close, context) {
+      cancel() {
+        jobs.cancelJob(id);
+        close();
+      }
+
+      return CustomAlertDialog(
+        title: Text(translate('Pasting files')),
+        content: SizedBox(
+          width: 360,
+          child: Obx(() {
+            final i = jobs.getJob(id);
+            if (i == -1) return const Offstage();
+            final job = jobs.jobTable[i];
+            return Column(
+              mainAxisSize: MainAxisSize.min,
+              crossAxisAlignment: CrossAxisAlignment.start,
+              children: [
+                LinearProgressIndicator(
+                  value: job.totalSize > 0
+                      ? job.finishedSize / job.totalSize
+                      : null,
+                ),
+                Row(
+                  children: [
+                    Expanded(child: Text(job.getStatus())),
+                    Text('${readableFileSize(job.speed)}/s'),
+                  ],
+                ).marginOnly(top: 8),
+              ],
+            );
+          }),
+        ),
+        actions: [
+          dialogButton('Cancel', onPressed: cancel, isOutline: true),
+          dialogButton('Hide', onPressed: close),
+        ],
+        onCancel: close,
+      );
+    }, tag: tag);
+  }
+}
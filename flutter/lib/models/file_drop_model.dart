@@ -0,0 +1,93 @@
+import 'dart:async';
+import 'dart:convert';
+import 'dart:io';
+
+import 'package:flutter/material.dart';
+import 'package:flutter_hbb/common.dart';
+
+import '../consts.dart';
+import 'file_model.dart';
+import 'platform_model.dart';
+
+/// Uploads the files dropped onto the remote desktop to the folder under the
+/// remote cursor, with the transfer jobs of the session, see
+/// `src/file_drop.rs`.
+class FileDrop {
+  final FileModel model;
+  final _pending = <int, Completer<Map<String, dynamic>>>{};
+
+  FileDrop(this.model);
+
+  static const _queryTimeout = Duration(seconds: 10);
+
+  bool get isSupported {
+    final ffiModel = model.parent.target?.ffiModel;
+    return ffiModel?.pi.platformAdditions[kPlatformAdditionsSupportFileDrop] ==
+            true &&
+        ffiModel?.permissions['file'] != false;
+  }
+
+  void onTarget(Map<String, dynamic> evt) {
+    final res = jsonDecode(evt['content']);
+    _pending.remove(res['id'])?.complete(res);
+  }
+
+  Future<void> drop(List<String> paths, Offset position) async {
+    if (paths.isEmpty) return;
+    if (!isSupported) {
+      showToast(translate('No permission of file transfer'));
+      return;
+    }
+    // The peer finds the folder under its cursor.
+    model.parent.target?.inputModel.moveMouseTo(position);
+    await Future.delayed(const Duration(milliseconds: 100));
+    final id = JobController.jobID.next();
+    final completer = Completer<Map<String, dynamic>>();
+    _pending[id] = completer;
+    bind.sessionQueryFileDrop(sessionId: model.sessionId, id: id);
+    final Map<String, dynamic> res;
+    try {
+      res = await completer.future.timeout(_queryTimeout);
+    } catch (e) {
+      _pending.remove(id);
+      showToast(translate('Timeout'));
+      return;
+    }
+    if (res['error'] != null) {
+      showToast(translate(res['error']));
+      return;
+    }
+    final String dir = res['dir'];
+    final isWindows =
+        model.parent.target?.ffiModel.pi.platform == kPeerPlatformWindows;
+    final jobs = model.jobController;
+    final ids = paths.map((p) {
+      final isDir = FileSystemEntity.isDirectorySync(p);
+      final from = Entry()
+        ..entryType = isDir ? 1 : 4
+        ..path = p
+        ..name = PathUtil.split(p, Platform.isWindows).last
+        ..size = isDir ? 0 : File(p).lengthSync();
+      return jobs.sendFiles(
+          from, PathUtil.join(dir, from.name, isWindows), false, false);
+    }).toList();
+    showToast('${translate('Sending to')} $dir');
+    final errors = <String>[];
+    while (ids.isNotEmpty) {
+      await Future.delayed(const Duration(seconds: 1));
+      ids.removeWhere((id) {
+        final i = jobs.getJob(id);
+        if (i == -1) return true;
+        final job = jobs.jobTable[i];
+        if (job.state == JobState.error) {
+          errors.add('${job.fileName}: ${translate(job.err)}');
+          return true;
+        }
+        return job.state == JobState.done;
+      });
+    }
+    showToast(errors.isEmpty
+        ? '${translate('Sent to')} $dir'
+        : errors.join('\n'));
+  }
+}
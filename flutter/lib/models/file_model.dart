@@ -11,8 +11,12 @@ import 'package:flutter_hbb/web/dummy.dart'
     if (dart.library.html) 'package:flutter_hbb/web/web_unique.dart';
 
 import '../consts.dart';
+import 'clipboard_paste_model.dart';
+import 'file_drop_model.dart';
+import 'file_ops_model.dart';
 import 'model.dart';
 import 'platform_model.dart';
+import 'transfer_broker_model.dart';
 
 enum SortBy {
   name,
@@ -60,6 +64,8 @@ class FileModel {
   late final GetDialogManager getDialogManager;
   SessionID get sessionId => getSessionID();
   late final FileDialogEventLoop evtLoop;
+  late final FileDrop fileDrop = FileDrop(this);
+  late final ClipboardPaste clipboardPaste = ClipboardPaste(this);
 
   FileModel(this.parent) {
     getSessionID = () => parent.target!.sessionId;
@@ -164,6 +170,91 @@ class FileModel {
     }
   }
 
+  void onFileOp(Map<String, dynamic> evt) {
+    final controller =
+        evt['is_local'] == 'true' ? localController : remoteController;
+    controller.fileOps.onResponse(jsonDecode(evt['content']));
+  }
+
+  /// The files of the transfer job done whose copies differ, offers to
+  /// transfer them again.
+  Future<void> onIntegrityFailed(Map<String, dynamic> evt) async {
+    final id = int.parse(evt['id']);
+    final jobIndex = jobController.getJob(id);
+    if (jobIndex == -1) return;
+    final isRemoteToLocal = jobController.jobTable[jobIndex].isRemoteToLocal;
+    jobController.updateJobStatus(id, error: _kIntegrityError);
+    final files = (jsonDecode(evt['files']) as List)
+        .map((e) => (from: e['from'] as String, to: e['to'] as String))
+        .toList();
+    final retry = await parent.target?.dialogManager.show<bool>(
+        (setState, Function(bool v) close, context) {
+      cancel() => close(false);
+      submit() => close(true);
+      return CustomAlertDialog(
+        title: Row(
+          children: [
+            const Icon(Icons.warning_rounded, color: Colors.red),
+            Text(translate("Corrupted files")).paddingOnly(left: 10),
+          ],
+        ),
+        contentBoxConstraints:
+            BoxConstraints(minHeight: 100, minWidth: 400, maxWidth: 400),
+        content: Column(
+          crossAxisAlignment: CrossAxisAlignment.start,
+          mainAxisSize: MainAxisSize.min,
+          children: [
+            Text(translate("corrupted_files_tip")),
+            const SizedBox(height: 5),
+            ConstrainedBox(
+              constraints: const BoxConstraints(maxHeight: 200),
+              child: SingleChildScrollView(
+                child: Text(files.map((f) => f.to).join('\n')),
+              ),
+            ),
+          ],
+        ),
+        actions: [
+          dialogButton(
+            "Cancel",
+            icon: Icon(Icons.close_rounded),
+            onPressed: cancel,
+            isOutline: true,
+          ),
+          dialogButton(
+            "Retry",
+            icon: Icon(Icons.refresh_rounded),
+            onPressed: submit,
+          ),
+        ],
+        onSubmit: submit,
+        onCancel: cancel,
+      );
+    }, useAnimation: false);
+    if (retry != true) return;
+    for (final f in files) {
+      // The corrupted copy has the size and the time of the source, it would
+      // be skipped as identical.
+      final deleteId = jobController.addDeleteFileJob(
+          Entry()..path = f.to, !isRemoteToLocal);
+      bind.sessionRemoveFile(
+          sessionId: sessionId,
+          actId: deleteId,
+          path: f.to,
+          isRemote: !isRemoteToLocal,
+          fileNum: 0);
+      try {
+        await jobController.jobResultListener.start();
+      } catch (e) {
+        debugPrint("Failed to remove the corrupted file ${f.to}: $e");
+      }
+      final from = Entry()
+        ..entryType = 4
+        ..path = f.from;
+      jobController.sendFiles(from, f.to, isRemoteToLocal, true);
+    }
+  }
+
   bool fileConfirmCheckboxRemember = false;
 
   Future<bool?> showFileConfirmDialog(
@@ -317,6 +408,8 @@ class FileController {
 
   final DirectoryData Function() getOtherSideDirectoryData;
   late final SelectedItems selectedItems = SelectedItems(isLocal: isLocal);
+  late final FileOps fileOps = FileOps(this);
+  late final TransferBroker transferBroker = TransferBroker(this);
 
   FileController(
       {required this.isLocal,
@@ -518,16 +611,8 @@ class FileController {
     final isWindows = otherSideData.options.isWindows;
     final showHidden = otherSideData.options.showHidden;
     for (var from in items.items) {
-      final jobID = jobController.addTransferJob(from, isRemoteToLocal);
-      bind.sessionSendFiles(
-          sessionId: sessionId,
-          actId: jobID,
-          path: from.path,
-          to: PathUtil.join(toPath, from.name, isWindows),
-          fileNum: 0,
-          includeHidden: showHidden,
-          isRemote: isRemoteToLocal,
-          isDir: from.isDirectory);
+      jobController.sendFiles(from, PathUtil.join(toPath, from.name, isWindows),
+          isRemoteToLocal, showHidden);
       debugPrint(
           "path: ${from.path}, toPath: $toPath, to: ${PathUtil.join(toPath, from.name, isWindows)}");
     }
@@ -846,6 +931,7 @@ class FileController {
 }
 
 const _kOneWayFileTransferError = 'one-way-file-transfer-tip';
+const _kIntegrityError = 'corrupted';
 
 class JobController {
   static final JobID jobID = JobID();
@@ -877,6 +963,94 @@ class JobController {
     return jobID;
   }
 
+  /// Sends the files now if the queue has room, else adds the job waiting for
+  /// its turn. The session keeps the waiting jobs with the paused ones, they
+  /// are restored with the last jobs after a reconnection.
+  int sendFiles(Entry from, String to, bool isRemoteToLocal, bool showHidden) {
+    final jobID = addTransferJob(from, isRemoteToLocal);
+    final job = jobTable[getJob(jobID)]
+      ..remote = isRemoteToLocal ? from.path : to
+      ..to = isRemoteToLocal ? to : from.path
+      ..showHidden = showHidden;
+    if (isWeb || _hasRoom(excluded: job)) {
+      bind.sessionSendFiles(
+          sessionId: sessionId,
+          actId: jobID,
+          path: from.path,
+          to: to,
+          fileNum: 0,
+          includeHidden: showHidden,
+          isRemote: isRemoteToLocal,
+          isDir: from.isDirectory);
+    } else {
+      job.state = JobState.none;
+      bind.sessionAddJob(
+          sessionId: sessionId,
+          actId: jobID,
+          path: from.path,
+          to: to,
+          fileNum: 0,
+          includeHidden: showHidden,
+          isRemote: isRemoteToLocal);
+      jobTable.refresh();
+    }
+    return jobID;
+  }
+
+  // Whether another transfer can run, 0 for no limit.
+  bool _hasRoom({JobProgress? excluded}) {
+    final max = int.tryParse(
+            bind.mainGetLocalOption(key: kOptionFileTransferMaxJobs)) ??
+        0;
+    if (max <= 0) return true;
+    final running = jobTable
+        .where((e) =>
+            e != excluded &&
+            e.type == JobType.transfer &&
+            e.state == JobState.inProgress)
+        .length;
+    return running < max;
+  }
+
+  /// Starts the waiting jobs in the order of the table while the queue has
+  /// room.
+  void _schedule() {
+    if (isWeb) return;
+    for (final job in jobTable.toList()) {
+      if (job.type != JobType.transfer || job.state != JobState.none) continue;
+      if (!_hasRoom()) break;
+      bind.sessionResumeJob(
+          sessionId: sessionId, actId: job.id, isRemote: job.isRemoteToLocal);
+      job.state = JobState.inProgress;
+    }
+    jobTable.refresh();
+  }
+
+  void pauseJob(int jobId) {
+    final jobIndex = getJob(jobId);
+    if (jobIndex == -1) return;
+    final job = jobTable[jobIndex];
+    if (job.state == JobState.inProgress) {
+      bind.sessionPauseJob(
+          sessionId: sessionId, actId: job.id, isRemote: job.isRemoteToLocal);
+    } else if (job.state != JobState.none) {
+      return;
+    }
+    job.state = JobState.paused;
+    job.speed = 0;
+    _schedule();
+  }
+
+  /// Moves the job up or down the queue, the waiting jobs start in the order
+  /// of the table.
+  void moveJob(int jobId, bool up) {
+    final i = getJob(jobId);
+    final j = up ? i - 1 : i + 1;
+    if (i == -1 || j < 0 || j >= jobTable.length) return;
+    final job = jobTable.removeAt(i);
+    jobTable.insert(j, job);
+  }
+
   int addDeleteFileJob(Entry file, bool isRemote) {
     final jobID = JobController.jobID.next();
     jobTable.add(JobProgress()
@@ -959,7 +1133,7 @@ class JobController {
       if (speed != null) job.speed = speed;
       job.state = JobState.done;
     }
-    jobTable.refresh();
+    _schedule();
     if (job.type == JobType.deleteDir) {
       return job.state == JobState.done;
     } else {
@@ -993,7 +1167,7 @@ class JobController {
           jobResultListener.complete(evt);
         }
       }
-      jobTable.refresh();
+      _schedule();
     }
     if (err == _kOneWayFileTransferError) {
       if (DateTime.now().millisecondsSinceEpoch - _lastTimeShowMsgbox > 3000) {
@@ -1031,6 +1205,7 @@ class JobController {
 
   Future<void> cancelJob(int id) async {
     await bind.sessionCancelJob(sessionId: sessionId, actId: id);
+    _schedule();
   }
 
   void loadLastJob(Map<String, dynamic> evt) {
@@ -1070,11 +1245,9 @@ class JobController {
   void resumeJob(int jobId) {
     final jobIndex = getJob(jobId);
     if (jobIndex != -1) {
-      final job = jobTable[jobIndex];
-      bind.sessionResumeJob(
-          sessionId: sessionId, actId: job.id, isRemote: job.isRemoteToLocal);
-      job.state = JobState.inProgress;
-      jobTable.refresh();
+      // Waits for its turn if the queue is full.
+      jobTable[jobIndex].state = JobState.none;
+      _schedule();
     } else {
       debugPrint("jobId $jobId is not exists");
     }
@@ -1183,7 +1356,8 @@ class FileFetcher {
     return c.future;
   }
 
-  Future<FileDirectory> registerReadRecursiveTask(int actID) {
+  Future<FileDirectory> registerReadRecursiveTask(int actID,
+      {Duration timeout = const Duration(seconds: 2)}) {
     final tasks = readRecursiveTasks;
     if (tasks.containsKey(actID)) {
       throw "Failed to registerRemoveTask, already have same ReadRecursive job";
@@ -1191,7 +1365,7 @@ class FileFetcher {
     final c = Completer<FileDirectory>();
     tasks[actID] = c;
 
-    Timer(Duration(seconds: 2), () {
+    Timer(timeout, () {
       tasks.remove(actID);
       if (c.isCompleted) return;
       c.completeError("Failed to read dir, timeout");
@@ -1282,7 +1456,8 @@ class FileFetcher {
   }
 
   Future<FileDirectory> fetchDirectoryRecursiveToRemove(
-      int actID, String path, bool isLocal, bool showHidden) async {
+      int actID, String path, bool isLocal, bool showHidden,
+      {Duration timeout = const Duration(seconds: 2)}) async {
     // TODO test Recursive is show hidden default?
     try {
       await bind.sessionReadDirToRemoveRecursive(
@@ -1291,7 +1466,7 @@ class FileFetcher {
           path: path,
           isRemote: !isLocal,
           showHidden: showHidden);
-      return registerReadRecursiveTask(actID);
+      return registerReadRecursiveTask(actID, timeout: timeout);
     } catch (e) {
       return Future.error(e);
     }
@@ -1313,6 +1488,12 @@ class FileDirectory {
     });
   }
 
+  Map<String, dynamic> toJson() => {
+        'id': id,
+        'path': path,
+        'entries': entries.map((e) => e.toJson()).toList(),
+      };
+
   // generate full path for every entry , init sort style if need.
   format(bool isWindows, {SortBy? sort}) {
     for (var entry in entries) {
@@ -1350,6 +1531,13 @@ class Entry {
     size = json['size'];
   }
 
+  Map<String, dynamic> toJson() => {
+        'entry_type': entryType,
+        'modified_time': modifiedTime,
+        'name': name,
+        'size': size,
+      };
+
   bool get isFile => entryType > 3;
 
   bool get isDirectory => entryType < 3;
@@ -1425,6 +1613,9 @@ class JobProgress {
       if (state == JobState.done && err == "skipped") {
         return translate("Skipped");
       }
+      if (state == JobState.error && err == _kIntegrityError) {
+        return translate("Corrupted files");
+      }
     } else if (type == JobType.deleteFile) {
       if (err == "cancel") {
         return translate("Cancel");
@@ -0,0 +1,334 @@
+import 'dart:async';
+import 'dart:convert';
+
+import 'package:flutter/material.dart';
+import 'package:flutter_hbb/common.dart';
+import 'package:flutter_hbb/common/widgets/dialog.dart';
+import 'package:get/get.dart';
+
+import '../consts.dart';
+import 'file_model.dart';
+import 'platform_model.dart';
+
+class FileProperties {
+  final Map<String, dynamic> _json;
+
+  FileProperties.fromJson(this._json);
+
+  String get path => _json['path'];
+  bool get isDir => _json['is_dir'];
+  bool get isSymlink => _json['is_symlink'];
+  int get size => _json['size'];
+  int get files => _json['files'];
+  int get dirs => _json['dirs'];
+  String get owner => _json['owner'];
+  String get group => _json['group'];
+  int? get mode => _json['mode'];
+  bool get readonly => _json['readonly'];
+  DateTime? get created => _time('created');
+  DateTime? get modified => _time('modified');
+  DateTime? get accessed => _time('accessed');
+
+  DateTime? _time(String key) => _json[key] == null
+      ? null
+      : DateTime.fromMillisecondsSinceEpoch(_json[key] * 1000);
+}
+
+class SearchEntry {
+  final String path;
+  final bool isDir;
+  final int size;
+
+  SearchEntry.fromJson(Map<String, dynamic> json)
+      : path = json['path'],
+        isDir = json['is_dir'],
+        size = json['size'];
+}
+
+/// The move, the copy, the properties and the search of the files of a side,
+/// run by that side without a transfer, see `src/file_ops.rs`.
+class FileOps {
+  final FileController controller;
+  final _pending = <int, Completer<Map<String, dynamic>>>{};
+  final _searches =
+      <int, void Function(List<SearchEntry> entries, bool done)>{};
+
+  FileOps(this.controller);
+
+  bool get isLocal => controller.isLocal;
+  bool get isWindows => controller.options.value.isWindows;
+
+  /// The peers without it only have the rename, the create and the delete.
+  bool get isSupported =>
+      isLocal ||
+      controller.rootState.target?.ffiModel.pi
+              .platformAdditions[kPlatformAdditionsSupportFileOps] ==
+          true;
+
+  void _send(Map<String, dynamic> op) => bind.sessionFileOp(
+      sessionId: controller.sessionId,
+      op: jsonEncode(op),
+      isRemote: !isLocal);
+
+  Future<Map<String, dynamic>> _request(Map<String, dynamic> op, {int? id}) {
+    id ??= JobController.jobID.next();
+    final completer = Completer<Map<String, dynamic>>();
+    _pending[id] = completer;
+    _send({...op, 'id': id});
+    return completer.future;
+  }
+
+  void onResponse(Map<String, dynamic> res) {
+    final id = res['id'];
+    if (res['t'] == 'found') {
+      final entries = (res['entries'] as List)
+          .map((e) => SearchEntry.fromJson(e))
+          .toList();
+      final done = res['done'] == true;
+      final onFound = done ? _searches.remove(id) : _searches[id];
+      onFound?.call(entries, done);
+    } else {
+      _pending.remove(id)?.complete(res);
+    }
+  }
+
+  /// The error of the first file failed, null if done.
+  Future<String?> moveOrCopy(List<String> paths, String to,
+      {required bool copy}) async {
+    final res = await _request(
+        {'t': copy ? 'copy' : 'move', 'paths': paths, 'to': to});
+    return res['error'];
+  }
+
+  Future<FileProperties> properties(String path, {int? id}) async {
+    final res = await _request({'t': 'properties', 'path': path}, id: id);
+    if (res['error'] != null) throw res['error'];
+    return FileProperties.fromJson(res['properties']);
+  }
+
+  /// Stops counting the files of the properties [id], which fail with 'Cancelled'.
+  void cancelProperties(int id) {
+    final completer = _pending.remove(id);
+    if (completer != null) {
+      _send({'t': 'cancel_search', 'id': id});
+      completer.complete({'error': 'Cancelled'});
+    }
+  }
+
+  /// Returns the id of the search, [onFound] gets the batches of the results.
+  int search(String dir, String pattern, bool includeHidden,
+      void Function(List<SearchEntry> entries, bool done) onFound) {
+    final id = JobController.jobID.next();
+    _searches[id] = onFound;
+    _send({
+      't': 'search',
+      'id': id,
+      'dir': dir,
+      'pattern': pattern,
+      'include_hidden': includeHidden
+    });
+    return id;
+  }
+
+  void cancelSearch(int id) {
+    if (_searches.remove(id) != null) {
+      _send({'t': 'cancel_search', 'id': id});
+    }
+  }
+
+  Future<void> moveOrCopyAction(List<Entry> items, {required bool copy}) async {
+    final textEditingController =
+        TextEditingController(text: controller.directory.value.path);
+    final to = await controller.dialogManager?.show<String>(
+        (setState, Function(String? v) close, context) {
+      cancel() => close(null);
+      submit() => close(textEditingController.text.trim());
+      return CustomAlertDialog(
+        content: Column(
+          children: [
+            DialogTextField(
+              title: translate(copy ? 'Copy to' : 'Move to'),
+              controller: textEditingController,
+            ),
+          ],
+        ),
+        actions: [
+          dialogButton(
+            "Cancel",
+            icon: Icon(Icons.close_rounded),
+            onPressed: cancel,
+            isOutline: true,
+          ),
+          dialogButton(
+            "OK",
+            icon: Icon(Icons.done_rounded),
+            onPressed: submit,
+          ),
+        ],
+        onSubmit: submit,
+        onCancel: cancel,
+      );
+    });
+    if (to == null || to.isEmpty) return;
+    final error =
+        await moveOrCopy(items.map((e) => e.path).toList(), to, copy: copy);
+    if (error != null) showToast(translate(error));
+    controller.refresh();
+  }
+
+  Future<void> propertiesAction(Entry item) async {
+    final id = JobController.jobID.next();
+    final FileProperties p;
+    try {
+      controller.dialogManager?.showLoading(translate("Waiting"),
+          onCancel: () => cancelProperties(id));
+      p = await properties(item.path, id: id);
+    } catch (e) {
+      if (e != 'Cancelled') showToast(translate(e.toString()));
+      return;
+    } finally {
+      controller.dialogManager?.dismissAll();
+    }
+    final rows = <(String, String)>[
+      ('Path', p.path),
+      (
+        'Type',
+        translate(p.isDir ? 'Folder' : 'File') +
+            (p.isSymlink ? ' (${translate('Symbolic link')})' : '')
+      ),
+      ('Size', readableFileSize(p.size.toDouble())),
+      if (p.isDir)
+        (
+          'Contains',
+          '${p.files} ${translate('files')}, ${p.dirs} ${translate('folders')}'
+        ),
+      if (p.owner.isNotEmpty) ('Owner', p.owner),
+      if (p.group.isNotEmpty) ('Group', p.group),
+      if (p.mode != null)
+        ('Permissions', p.mode!.toRadixString(8).padLeft(4, '0')),
+      if (p.readonly) ('Read-only', translate('Yes')),
+      if (p.created != null) ('Created', p.created.toString()),
+      if (p.modified != null) ('Modified', p.modified.toString()),
+      if (p.accessed != null) ('Accessed', p.accessed.toString()),
+    ];
+    controller.dialogManager?.show((setState, close, context) {
+      return CustomAlertDialog(
+        title: Text(translate('Properties')),
+        contentBoxConstraints: BoxConstraints(minWidth: 400, maxWidth: 500),
+        content: Column(
+          mainAxisSize: MainAxisSize.min,
+          children: rows
+              .map((r) => Row(
+                    crossAxisAlignment: CrossAxisAlignment.start,
+                    children: [
+                      SizedBox(width: 120, child: Text(translate(r.$1))),
+                      Expanded(child: SelectableText(r.$2)),
+                    ],
+                  ).paddingSymmetric(vertical: 2))
+              .toList(),
+        ),
+        actions: [
+          dialogButton(
+            "OK",
+            icon: Icon(Icons.done_rounded),
+            onPressed: close,
+          ),
+        ],
+        onSubmit: close,
+        onCancel: close,
+      );
+    });
+  }
+
+  /// Searches the names under the current folder, a result opens its folder.
+  void searchAction() {
+    final dir = controller.directory.value.path;
+    final includeHidden = controller.options.value.showHidden;
+    final pattern = TextEditingController();
+    final results = <SearchEntry>[].obs;
+    final searching = false.obs;
+    int? searchId;
+    stop() {
+      if (searchId != null) cancelSearch(searchId!);
+      searchId = null;
+      searching.value = false;
+    }
+
+    controller.dialogManager?.show((setState, close, context) {
+      submit() {
+        stop();
+        results.clear();
+        if (pattern.text.trim().isEmpty) return;
+        searching.value = true;
+        searchId = search(dir, pattern.text, includeHidden, (entries, done) {
+          results.addAll(entries);
+          if (done) {
+            searchId = null;
+            searching.value = false;
+          }
+        });
+      }
+
+      cancel() {
+        stop();
+        close();
+      }
+
+      return CustomAlertDialog(
+        title: Text('${translate('Search')} $dir'),
+        contentBoxConstraints: BoxConstraints(minWidth: 500, maxWidth: 500),
+        content: Column(
+          mainAxisSize: MainAxisSize.min,
+          children: [
+            DialogTextField(
+              title: translate('search_files_tip'),
+              controller: pattern,
+            ),
+            Obx(() => searching.value
+                ? const LinearProgressIndicator()
+                : Text('${results.length} ${translate('files')}')),
+            SizedBox(
+              height: 300,
+              child: Obx(() => ListView.builder(
+                    itemCount: results.length,
+                    itemBuilder: (context, i) {
+                      final e = results[i];
+                      return ListTile(
+                        dense: true,
+                        leading: Icon(e.isDir
+                            ? Icons.folder_rounded
+                            : Icons.insert_drive_file_outlined),
+                        title: Text(e.path),
+                        subtitle: e.isDir
+                            ? null
+                            : Text(readableFileSize(e.size.toDouble())),
+                        onTap: () {
+                          cancel();
+                          controller.openDirectory(
+                              PathUtil.dirname(e.path, isWindows));
+                        },
+                      );
+                    },
+                  )),
+            ),
+          ],
+        ),
+        actions: [
+          dialogButton(
+            "Close",
+            icon: Icon(Icons.close_rounded),
+            onPressed: cancel,
+            isOutline: true,
+          ),
+          dialogButton(
+            "Search",
+            icon: Icon(Icons.search_rounded),
+            onPressed: submit,
+          ),
+        ],
+        onSubmit: submit,
+        onCancel: cancel,
+      );
+    });
+  }
+}
@@ -0,0 +1,191 @@
+import 'dart:async';
+import 'dart:convert';
+
+import 'package:flutter/foundation.dart';
+
+import 'file_model.dart';
+import 'platform_model.dart';
+
+enum FolderSyncMode { upload, download, twoWay }
+
+enum FolderSyncPolicy { newest, skip, rename }
+
+extension _SnakeCase on Enum {
+  String get snakeCase =>
+      name.replaceAllMapped(RegExp('[A-Z]'), (m) => '_${m[0]!.toLowerCase()}');
+}
+
+/// An action of the plan, the names are relative to the synced folders and
+/// separated by `/`.
+class FolderSyncAction {
+  /// upload, download, delete_local, delete_remote, rename_local,
+  /// rename_remote or skip.
+  final String action;
+  final String name;
+  final String? to;
+
+  FolderSyncAction.fromJson(Map<String, dynamic> json)
+      : action = json['action'],
+        name = json['name'],
+        to = json['to'];
+
+  bool get isSkip => action == 'skip';
+}
+
+/// Syncs the local folder with the remote one opened when created.
+///
+/// The plan is made on the Rust side from the recursive listings of both
+/// folders, and applied with the rename, remove and transfer jobs of the file
+/// manager, shown in its job list.
+class FolderSync {
+  final FileModel model;
+  final String localPath;
+  final String remotePath;
+  final bool _isLocalWindows;
+  final bool _isRemoteWindows;
+  final bool _showHidden;
+
+  FolderSync(this.model)
+      : localPath = model.localController.directory.value.path,
+        remotePath = model.remoteController.directory.value.path,
+        _isLocalWindows = model.localController.options.value.isWindows,
+        _isRemoteWindows = model.remoteController.options.value.isWindows,
+        _showHidden = model.remoteController.options.value.showHidden;
+
+  JobController get _jobs => model.jobController;
+
+  /// The listing of a remote tree may take long.
+  static const _readTimeout = Duration(seconds: 60);
+
+  Future<String> _readRemote() async {
+    final id = JobController.jobID.next();
+    try {
+      final fd = await model.fileFetcher.fetchDirectoryRecursiveToRemove(
+          id, remotePath, false, _showHidden,
+          timeout: _readTimeout);
+      return jsonEncode(fd.toJson());
+    } finally {
+      // Drops the remove job created for the listing.
+      _jobs.cancelJob(id);
+    }
+  }
+
+  /// Throws the error of the listing or the comparison.
+  Future<List<FolderSyncAction>> plan(
+      FolderSyncMode mode, FolderSyncPolicy policy, bool mirrorDeletes) async {
+    final remoteFd = await _readRemote();
+    final res = jsonDecode(await bind.sessionFolderSyncPlan(
+        sessionId: model.sessionId,
+        local: localPath,
+        remote: remotePath,
+        remoteFd: remoteFd,
+        options: jsonEncode({
+          'mode': mode.snakeCase,
+          'policy': policy.snakeCase,
+          'mirror_deletes': mirrorDeletes,
+          'include_hidden': _showHidden,
+        })));
+    if (res['error'] != null) throw res['error'];
+    return (res['actions'] as List)
+        .map((e) => FolderSyncAction.fromJson(e))
+        .toList();
+  }
+
+  String _path(bool isLocal, String name) {
+    final isWindows = isLocal ? _isLocalWindows : _isRemoteWindows;
+    return PathUtil.join(isLocal ? localPath : remotePath,
+        PathUtil.convert(name, false, isWindows), isWindows);
+  }
+
+  Entry _entry(bool isLocal, String name) => Entry()
+    ..entryType = 4
+    ..name = name
+    ..path = _path(isLocal, name);
+
+  // The error of the rename or the remove just sent, null if done.
+  Future<String?> _waitResult() async {
+    try {
+      final res = await _jobs.jobResultListener.start();
+      return res['err'];
+    } catch (e) {
+      return e.toString();
+    }
+  }
+
+  /// Applies the plan and records the state of the folders once the jobs are
+  /// done.
+  Future<void> apply(List<FolderSyncAction> actions) async {
+    final errors = <String>[];
+    // The renames and the deletes one at a time, before the transfers of the
+    // renamed files.
+    for (final a in actions) {
+      final isLocal = a.action.endsWith('_local');
+      if (a.action.startsWith('rename_')) {
+        bind.sessionRenameFile(
+            sessionId: model.sessionId,
+            actId: JobController.jobID.next(),
+            path: _path(isLocal, a.name),
+            newName: PathUtil.split(a.to!, false).last,
+            isRemote: !isLocal);
+        final err = await _waitResult();
+        if (err != null) errors.add('${a.name}: $err');
+      } else if (a.action.startsWith('delete_')) {
+        final entry = _entry(isLocal, a.name);
+        final id = _jobs.addDeleteFileJob(entry, !isLocal);
+        bind.sessionRemoveFile(
+            sessionId: model.sessionId,
+            actId: id,
+            path: entry.path,
+            isRemote: !isLocal,
+            fileNum: 0);
+        final err = await _waitResult();
+        if (err != null) errors.add('${a.name}: $err');
+      }
+    }
+    final jobs = <int>[];
+    for (final a in actions) {
+      if (a.action != 'upload' && a.action != 'download') continue;
+      final isRemoteToLocal = a.action == 'download';
+      final from = _entry(!isRemoteToLocal, a.name);
+      final id = _jobs.addTransferJob(from, isRemoteToLocal);
+      jobs.add(id);
+      bind.sessionSendFiles(
+          sessionId: model.sessionId,
+          actId: id,
+          path: from.path,
+          to: _path(isRemoteToLocal, a.name),
+          fileNum: 0,
+          includeHidden: _showHidden,
+          isRemote: isRemoteToLocal,
+          isDir: false);
+    }
+    while (jobs.isNotEmpty) {
+      await Future.delayed(const Duration(seconds: 1));
+      jobs.removeWhere((id) {
+        final i = _jobs.getJob(id);
+        if (i == -1) return true;
+        final job = _jobs.jobTable[i];
+        if (job.state == JobState.error) {
+          errors.add('${job.jobName}: ${job.err}');
+          return true;
+        }
+        return job.state == JobState.done;
+      });
+    }
+    try {
+      await bind.sessionFolderSyncFinish(
+          sessionId: model.sessionId,
+          local: localPath,
+          remote: remotePath,
+          remoteFd: await _readRemote(),
+          includeHidden: _showHidden,
+          errors: errors);
+    } catch (e) {
+      debugPrint('Failed to finish the folder sync: $e');
+    }
+    model.refreshAll();
+  }
+
+  String getLog() => bind.sessionGetFolderSyncLog(
+      sessionId: model.sessionId, local: localPath, remote: remotePath);
+}
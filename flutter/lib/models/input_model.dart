@@ -24,6 +24,14 @@ const _kMouseEventDown = 'mousedown';
 const _kMouseEventUp = 'mouseup';
 const _kMouseEventMove = 'mousemove';
 
+const _kMouseButtons = {
+  kPrimaryMouseButton: 'left',
+  kSecondaryMouseButton: 'right',
+  kMiddleMouseButton: 'wheel',
+  kBackMouseButton: 'back',
+  kForwardMouseButton: 'forward'
+};
+
 class CanvasCoords {
   double x = 0;
   double y = 0;
@@ -365,6 +373,14 @@ class InputModel {
   Rect? _windowRect;
   List<RemoteWindowCoords> _remoteWindowCoords = [];
 
+  // relative mouse
+  final relativeMouseLocked = false.obs;
+  // touch forwarding, the gestures are not recognized while it is on
+  final touchForwarding = false.obs;
+  final Map<int, Offset> _touchContacts = {};
+  Offset _relativeMouseAnchor = Offset.zero;
+  var _relativeMouseUnsent = Offset.zero;
+
   late final SessionID sessionId;
 
   bool get keyboardPerm => parent.target!.ffiModel.keyboard;
@@ -498,6 +514,7 @@ class InputModel {
   }
 
   KeyEventResult handleRawKeyEvent(RawKeyEvent e) {
+    if (e is RawKeyDownEvent) _checkRelativeMouseRelease();
     if (isViewOnly) return KeyEventResult.handled;
     if (isViewCamera) return KeyEventResult.handled;
     if (!isInputSourceFlutter) {
@@ -522,6 +539,7 @@ class InputModel {
         }
       }
       toReleaseRawKeys.updateKeyDown(key, e);
+      _localEcho(key, e.character);
     }
     if (e is RawKeyUpEvent) {
       if (key == LogicalKeyboardKey.altLeft ||
@@ -553,6 +571,7 @@ class InputModel {
   }
 
   KeyEventResult handleKeyEvent(KeyEvent e) {
+    if (e is KeyDownEvent) _checkRelativeMouseRelease();
     if (isViewOnly) return KeyEventResult.handled;
     if (isViewCamera) return KeyEventResult.handled;
     if (!isInputSourceFlutter) {
@@ -575,6 +594,9 @@ class InputModel {
     } else if (e is KeyDownEvent) {
       handleKeyDownEventModifiers(e);
     }
+    if (e is KeyDownEvent || e is KeyRepeatEvent) {
+      _localEcho(e.logicalKey, e.character);
+    }
 
     bool isMobileAndMapMode = false;
     if (isMobile) {
@@ -623,6 +645,10 @@ class InputModel {
     return KeyEventResult.handled;
   }
 
+  void _localEcho(LogicalKeyboardKey key, String? character) =>
+      parent.target?.localEchoModel
+          .onKeyDown(key, character, ctrl || alt || command);
+
   /// Send Key Event
   void newKeyboardMode(String character, int usbHid, bool down) {
     const capslock = 1;
@@ -859,6 +885,7 @@ class InputModel {
     // Fix status
     if (!enter) {
       resetModifiers();
+      unlockRelativeMouse();
     }
     _flingTimer?.cancel();
     if (!isInputSourceFlutter) {
@@ -883,10 +910,18 @@ class InputModel {
   void onPointHoverImage(PointerHoverEvent e) {
     _stopFling = true;
     if (isViewOnly && !showMyCursor) return;
+    if (_isPen(e)) {
+      if (!isViewOnly) handlePen(e);
+      return;
+    }
     if (e.kind != ui.PointerDeviceKind.mouse) return;
     if (!isPhysicalMouse.value) {
       isPhysicalMouse.value = true;
     }
+    if (relativeMouseLocked.value) {
+      _handleRelativeMouse(e, _kMouseEventMove);
+      return;
+    }
     if (isPhysicalMouse.value) {
       handleMouse(_getMouseEvent(e, _kMouseEventMove), e.position);
     }
@@ -1045,11 +1080,23 @@ class InputModel {
     _windowRect = null;
     if (isViewOnly && !showMyCursor) return;
     if (isViewCamera) return;
+    if (_isPen(e)) {
+      if (!isViewOnly) handlePen(e, contact: true);
+      return;
+    }
+    if (_isForwardedTouch(e)) {
+      _handleTouch(e);
+      return;
+    }
     if (e.kind != ui.PointerDeviceKind.mouse) {
       if (isPhysicalMouse.value) {
         isPhysicalMouse.value = false;
       }
     }
+    if (relativeMouseLocked.value) {
+      _handleRelativeMouse(e, _kMouseEventDown);
+      return;
+    }
     if (isPhysicalMouse.value) {
       handleMouse(_getMouseEvent(e, _kMouseEventDown), e.position);
     }
@@ -1059,16 +1106,56 @@ class InputModel {
     if (isDesktop) _queryOtherWindowCoords = false;
     if (isViewOnly && !showMyCursor) return;
     if (isViewCamera) return;
+    if (_isPen(e)) {
+      if (!isViewOnly) handlePen(e);
+      return;
+    }
+    if (_isForwardedTouch(e)) {
+      _handleTouch(e, lifted: true);
+      return;
+    }
     if (e.kind != ui.PointerDeviceKind.mouse) return;
+    if (relativeMouseLocked.value) {
+      _handleRelativeMouse(e, _kMouseEventUp);
+      return;
+    }
     if (isPhysicalMouse.value) {
       handleMouse(_getMouseEvent(e, _kMouseEventUp), e.position);
+      // The click goes to the peer first, to focus the window under it.
+      if (_isRelativeMouseMode) lockRelativeMouse(e.position);
+      _updateLocalEchoCaret(e.position);
     }
   }
 
+  // The caret of the local echo is likely where the user clicked.
+  void _updateLocalEchoCaret(Offset position) {
+    final localEcho = parent.target?.localEchoModel;
+    final rect = parent.target?.ffiModel.rect;
+    if (localEcho == null || !localEcho.enabled || rect == null) return;
+    final pos = handlePointerDevicePos(kPointerEventKindMouse, position.dx,
+        position.dy, false, kMouseEventTypeDown,
+        moveCanvas: false);
+    if (pos == null) return;
+    localEcho.onClick(Offset(
+        pos.x.toDouble() - rect.left, pos.y.toDouble() - rect.top));
+  }
+
   void onPointMoveImage(PointerMoveEvent e) {
     if (isViewOnly && !showMyCursor) return;
     if (isViewCamera) return;
+    if (_isPen(e)) {
+      if (!isViewOnly) handlePen(e, contact: true);
+      return;
+    }
+    if (_isForwardedTouch(e)) {
+      _handleTouch(e);
+      return;
+    }
     if (e.kind != ui.PointerDeviceKind.mouse) return;
+    if (relativeMouseLocked.value) {
+      _handleRelativeMouse(e, _kMouseEventMove);
+      return;
+    }
     if (_queryOtherWindowCoords) {
       Future.delayed(Duration.zero, () async {
         _windowRect = await fillRemoteCoordsAndGetCurFrame(_remoteWindowCoords);
@@ -1104,6 +1191,7 @@ class InputModel {
     if (isViewOnly) return;
     if (isViewCamera) return;
     if (e is PointerScrollEvent) {
+      parent.target?.localEchoModel.reset();
       var dx = e.scrollDelta.dx.toInt();
       var dy = e.scrollDelta.dy.toInt();
       if (dx > 0) {
@@ -1127,6 +1215,12 @@ class InputModel {
         'type': _kMouseEventMove,
       }, lastMousePos);
 
+  /// Moves the remote cursor to the global position [pos].
+  void moveMouseTo(Offset pos) => handleMouse({
+        'buttons': 0,
+        'type': _kMouseEventMove,
+      }, pos);
+
   void tryMoveEdgeOnExit(Offset pos) => handleMouse(
         {
           'buttons': 0,
@@ -1285,17 +1379,162 @@ class InputModel {
       evt['y'] = '${pos.y.toInt()}';
     }
 
-    Map<int, String> mapButtons = {
-      kPrimaryMouseButton: 'left',
-      kSecondaryMouseButton: 'right',
-      kMiddleMouseButton: 'wheel',
-      kBackMouseButton: 'back',
-      kForwardMouseButton: 'forward'
-    };
-    evt['buttons'] = mapButtons[evt['buttons']] ?? '';
+    evt['buttons'] = _kMouseButtons[evt['buttons']] ?? '';
     return evt;
   }
 
+  bool get _isRelativeMouseMode =>
+      isDesktop &&
+      !isViewOnly &&
+      parent.target?.ffiModel.pi.isSupportRelativeMouse == true &&
+      bind.sessionGetToggleOptionSync(
+          sessionId: sessionId, arg: kOptionRelativeMouseMode);
+
+  /// Hides and locks the local cursor at [position], its motion is sent as raw deltas
+  /// until released by Ctrl+Alt or leaving the session.
+  void lockRelativeMouse(Offset position) {
+    if (relativeMouseLocked.value) return;
+    if (!bind.mainLockCursor(lock: true)) return;
+    _relativeMouseAnchor = position;
+    _relativeMouseUnsent = Offset.zero;
+    relativeMouseLocked.value = true;
+  }
+
+  void unlockRelativeMouse() {
+    if (!relativeMouseLocked.value) return;
+    relativeMouseLocked.value = false;
+    bind.mainLockCursor(lock: false);
+  }
+
+  void _checkRelativeMouseRelease() {
+    if (HardwareKeyboard.instance.isControlPressed &&
+        HardwareKeyboard.instance.isAltPressed) {
+      unlockRelativeMouse();
+    }
+  }
+
+  void _handleRelativeMouse(PointerEvent e, String type) {
+    final evt = _getMouseEvent(e, type);
+    if (evt['type'] != _kMouseEventMove) {
+      final buttons = _kMouseButtons[evt['buttons']];
+      if (buttons == null) return;
+      bind.sessionSendMouse(
+          sessionId: sessionId,
+          msg: json.encode(modify({
+            'type': evt['type'] == _kMouseEventDown
+                ? kMouseEventTypeDown
+                : kMouseEventTypeUp,
+            'buttons': buttons
+          })));
+      return;
+    }
+    final delta = e.position - _relativeMouseAnchor;
+    // The move of the cursor back to the anchor.
+    if (delta == Offset.zero) return;
+    bind.mainRecenterCursor();
+    _relativeMouseUnsent += delta * devicePixelRatio;
+    final x = _relativeMouseUnsent.dx.truncate();
+    final y = _relativeMouseUnsent.dy.truncate();
+    _relativeMouseUnsent -= Offset(x.toDouble(), y.toDouble());
+    if (x == 0 && y == 0) return;
+    bind.sessionSendMouse(
+        sessionId: sessionId,
+        msg: json.encode(modify(
+            {'type': kMouseEventTypeMoveRelative, 'x': '$x', 'y': '$y'})));
+  }
+
+  bool _isPen(PointerEvent e) =>
+      (e.kind == ui.PointerDeviceKind.stylus ||
+          e.kind == ui.PointerDeviceKind.invertedStylus) &&
+      parent.target?.ffiModel.pi.isSupportPen == true;
+
+  /// Sends the pen with its pressure and tilt instead of the mouse, if the peer supports it.
+  void handlePen(PointerEvent e, {bool contact = false}) {
+    final x = e.position.dx;
+    final y = max(0.0, e.position.dy);
+    if (_checkPeerControlProtected(x, y)) return;
+    final pos = handlePointerDevicePos(
+        kPointerEventKindMouse, x, y, true, kMouseEventTypeDefault,
+        moveCanvas: false);
+    if (pos == null) return;
+    var pressure = 0.0;
+    if (contact && e.pressureMax > e.pressureMin) {
+      pressure = (e.pressure - e.pressureMin) / (e.pressureMax - e.pressureMin);
+    }
+    // The tilt from the perpendicular and the orientation clockwise from up,
+    // to the angles on the x and y axes.
+    final t = tan(min(e.tilt, pi / 2 - 0.01));
+    final tiltX = atan(t * sin(e.orientation)) * 180 / pi;
+    final tiltY = atan(-t * cos(e.orientation)) * 180 / pi;
+    bind.sessionSendPen(
+        sessionId: sessionId,
+        msg: json.encode({
+          'x': pos.x.toInt(),
+          'y': pos.y.toInt(),
+          'pressure': (pressure.clamp(0.0, 1.0) * kPenMaxPressure).toInt(),
+          'tilt_x': tiltX.round(),
+          'tilt_y': tiltY.round(),
+          'contact': contact,
+          'in_range': true,
+          'eraser': e.kind == ui.PointerDeviceKind.invertedStylus,
+          'barrel': e.buttons & kSecondaryStylusButton != 0,
+        }));
+  }
+
+  void onPointCancelImage(PointerCancelEvent e) {
+    if (_isForwardedTouch(e)) _handleTouch(e, lifted: true);
+  }
+
+  /// Turns the touch forwarding on if the peer supports it and the option is set.
+  void updateTouchForwarding() {
+    final on = !isViewOnly &&
+        !isViewCamera &&
+        parent.target?.ffiModel.pi.isSupportTouch == true &&
+        bind.sessionGetToggleOptionSync(
+            sessionId: sessionId, arg: kOptionForwardTouch);
+    if (!on && _touchContacts.isNotEmpty) {
+      _touchContacts.clear();
+      _sendTouchFrame();
+    }
+    touchForwarding.value = on;
+  }
+
+  bool _isForwardedTouch(PointerEvent e) =>
+      e.kind == ui.PointerDeviceKind.touch &&
+      touchForwarding.value &&
+      !isViewOnly;
+
+  /// Sends all the fingers on the remote view, the peer injects them as touch contacts.
+  void _handleTouch(PointerEvent e, {bool lifted = false}) {
+    if (lifted) {
+      if (_touchContacts.remove(e.pointer) == null) return;
+    } else {
+      final x = e.position.dx;
+      final y = max(0.0, e.position.dy);
+      if (_checkPeerControlProtected(x, y)) return;
+      final pos = handlePointerDevicePos(
+          kPointerEventKindMouse, x, y, true, kMouseEventTypeDefault,
+          moveCanvas: false);
+      if (pos == null) return;
+      _touchContacts[e.pointer] = Offset(pos.x, pos.y);
+    }
+    _sendTouchFrame();
+  }
+
+  void _sendTouchFrame() {
+    bind.sessionSendTouch(
+        sessionId: sessionId,
+        msg: json.encode({
+          'contacts': _touchContacts.entries
+              .map((c) => {
+                    'id': c.key,
+                    'x': c.value.dx.toInt(),
+                    'y': c.value.dy.toInt(),
+                  })
+              .toList(),
+        }));
+  }
+
   Map<String, dynamic>? handleMouse(
     Map<String, dynamic> evt,
     Offset offset, {
@@ -0,0 +1,156 @@
+import 'dart:async';
+
+import 'package:flutter/material.dart';
+import 'package:flutter/services.dart';
+import 'package:flutter_hbb/consts.dart';
+
+import 'model.dart';
+import 'platform_model.dart';
+
+/// The predictions are shown only if the round trip is at least this long, in ms.
+const int _kMinDelay = 100;
+
+/// The time the frame with the echo may take after the round trip, in ms.
+const int _kFrameMargin = 100;
+
+class _Prediction {
+  final String glyph;
+  final int deadline;
+
+  _Prediction(this.glyph, this.deadline);
+}
+
+/// Predicts the echo of the typed keys, like mosh does for terminals.
+///
+/// The caret is guessed from the last click and moves with the typed glyphs.
+/// The glyphs are drawn over the video until the frame with the real echo
+/// should have arrived, the measured round trip plus a margin.
+/// Keys whose effect can not be predicted, e.g. Enter or the arrows, stop the
+/// predicting until the next click.
+class LocalEchoModel with ChangeNotifier {
+  WeakReference<FFI> parent;
+
+  LocalEchoModel(this.parent);
+
+  /// The style of the glyphs, the size is in the pixels of the remote display.
+  static const style = TextStyle(fontFamily: 'monospace', fontSize: 14);
+
+  bool _enabled = false;
+  // The position of the next glyph on the current display, null if unknown.
+  Offset? _caret;
+  final List<_Prediction> _pending = [];
+  Timer? _timer;
+
+  bool get enabled => _enabled;
+  Offset? get caret => _caret;
+  String get text => _pending.map((e) => e.glyph).join();
+
+  int get _delay =>
+      int.tryParse(parent.target?.qualityMonitorModel.data.delay ?? '') ?? 0;
+
+  void updateEnabled() {
+    final ffi = parent.target;
+    if (ffi == null) return;
+    _enabled = !ffi.ffiModel.viewOnly &&
+        bind.sessionGetToggleOptionSync(
+            sessionId: ffi.sessionId, arg: kOptionLocalEcho);
+    if (!_enabled) reset();
+  }
+
+  /// The user clicked at [pos] on the current display, the caret is likely there.
+  void onClick(Offset pos) {
+    if (!_enabled) return;
+    _clear();
+    _caret = pos;
+    notifyListeners();
+  }
+
+  void onKeyDown(LogicalKeyboardKey key, String? character, bool modifiers) {
+    final caret = _caret;
+    if (!_enabled || caret == null) return;
+    if (_isModifier(key)) return;
+    if (key == LogicalKeyboardKey.backspace && !modifiers) {
+      if (_pending.isNotEmpty) {
+        _pending.removeLast();
+      } else {
+        _caret = caret.translate(-_width('m'), 0);
+      }
+      notifyListeners();
+      return;
+    }
+    if (modifiers || !_isPrintable(character)) {
+      reset();
+      return;
+    }
+    final delay = _delay;
+    if (delay < _kMinDelay) {
+      // Fast enough, only follow the caret.
+      _caret = caret.translate(_width(character!), 0);
+      return;
+    }
+    final now = DateTime.now().millisecondsSinceEpoch;
+    _pending.add(_Prediction(character!, now + delay + _kFrameMargin));
+    _schedule();
+    notifyListeners();
+  }
+
+  /// The view changed, e.g. scrolled, the caret is unknown.
+  void reset() {
+    final changed = _caret != null || _pending.isNotEmpty;
+    _clear();
+    _caret = null;
+    if (changed) notifyListeners();
+  }
+
+  void close() {
+    _clear();
+    _caret = null;
+  }
+
+  void _clear() {
+    _timer?.cancel();
+    _timer = null;
+    _pending.clear();
+  }
+
+  void _schedule() {
+    if (_timer != null || _pending.isEmpty) return;
+    final wait = _pending.first.deadline - DateTime.now().millisecondsSinceEpoch;
+    _timer = Timer(Duration(milliseconds: wait < 0 ? 0 : wait), _expire);
+  }
+
+  // The real echo is on the screen now, the caret moves past it.
+  void _expire() {
+    _timer = null;
+    final now = DateTime.now().millisecondsSinceEpoch;
+    while (_pending.isNotEmpty && _pending.first.deadline <= now) {
+      final glyph = _pending.removeAt(0).glyph;
+      if (_caret != null) _caret = _caret!.translate(_width(glyph), 0);
+    }
+    _schedule();
+    notifyListeners();
+  }
+
+  static double _width(String text) {
+    final painter = TextPainter(
+        text: TextSpan(text: text, style: style),
+        textDirection: TextDirection.ltr)
+      ..layout();
+    return painter.width;
+  }
+
+  static bool _isPrintable(String? character) =>
+      character != null &&
+      character.isNotEmpty &&
+      character.runes.every((c) => c >= 0x20 && c != 0x7f);
+
+  static bool _isModifier(LogicalKeyboardKey key) =>
+      LogicalKeyboardKey.expandSynonyms({
+        LogicalKeyboardKey.shift,
+        LogicalKeyboardKey.control,
+        LogicalKeyboardKey.alt,
+        LogicalKeyboardKey.meta,
+      }).contains(key) ||
+      key == LogicalKeyboardKey.capsLock ||
+      key == LogicalKeyboardKey.numLock;
+}
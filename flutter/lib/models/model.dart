@@ -16,6 +16,7 @@ import 'package:flutter_hbb/models/chat_model.dart';
 import 'package:flutter_hbb/models/cm_file_model.dart';
 import 'package:flutter_hbb/models/file_model.dart';
 import 'package:flutter_hbb/models/group_model.dart';
+import 'package:flutter_hbb/models/local_echo_model.dart';
 import 'package:flutter_hbb/models/peer_model.dart';
 import 'package:flutter_hbb/models/peer_tab_model.dart';
 import 'package:flutter_hbb/models/printer_model.dart';
@@ -126,6 +127,8 @@ class FfiModel with ChangeNotifier {
   Timer? waitForImageTimer;
   RxBool waitForFirstImage = true.obs;
   bool isRefreshing = false;
+  // The power state of the peer, `PowerState` in src/client.rs.
+  final peerPowerState = Rx<Map<String, dynamic>>({});
 
   Timer? timerScreenshot;
 
@@ -219,8 +222,22 @@ class FfiModel with ChangeNotifier {
 
   bool get keyboard => _permissions['keyboard'] != false;
 
+  updatePeerPowerState(String state) {
+    try {
+      final Map<String, dynamic> m = json.decode(state);
+      final wasThrottled = peerPowerState.value['throttled'] == true;
+      peerPowerState.value = m;
+      if (!wasThrottled && m['throttled'] == true) {
+        showToast(translate('remote-power-saving-tip'));
+      }
+    } catch (e) {
+      debugPrint('Invalid power state: $e');
+    }
+  }
+
   clear() {
     _pi = PeerInfo();
+    peerPowerState.value = {};
     _secure = null;
     _direct = null;
     _inputBlocked = false;
@@ -356,6 +373,14 @@ class FfiModel with ChangeNotifier {
         }
       } else if (name == 'job_error') {
         parent.target?.fileModel.jobController.jobError(evt);
+      } else if (name == 'integrity_failed') {
+        parent.target?.fileModel.onIntegrityFailed(evt);
+      } else if (name == 'file_op') {
+        parent.target?.fileModel.onFileOp(evt);
+      } else if (name == 'file_drop_target') {
+        parent.target?.fileModel.fileDrop.onTarget(evt);
+      } else if (name == 'clipboard_paste') {
+        parent.target?.fileModel.clipboardPaste.onStarted(evt);
       } else if (name == 'override_file_confirm') {
         parent.target?.fileModel.postOverrideFileConfirm(evt);
       } else if (name == 'load_last_job') {
@@ -401,6 +426,8 @@ class FfiModel with ChangeNotifier {
         parent.target?.chatModel.onVoiceCallIncoming();
       } else if (name == 'update_voice_call_state') {
         parent.target?.serverModel.updateVoiceCallState(evt);
+      } else if (name == 'update_input_blocked_state') {
+        parent.target?.serverModel.updateInputBlockedState(evt);
       } else if (name == 'fingerprint') {
         FingerprintState.find(peerId).value = evt['fingerprint'] ?? '';
       } else if (name == 'plugin_manager') {
@@ -449,6 +476,12 @@ class FfiModel with ChangeNotifier {
         _handlePrinterRequest(evt, sessionId, peerId);
       } else if (name == 'screenshot') {
         _handleScreenshot(evt, sessionId, peerId);
+      } else if (name == 'peer_power_state') {
+        updatePeerPowerState(evt['state'] ?? '');
+      } else if (name == 'audio_devices') {
+        final devices = List<String>.from(evt['devices'] ?? []);
+        audioDevicesDialog(sessionId, devices, evt['current'] ?? '',
+            parent.target!.dialogManager);
       } else {
         debugPrint('Event is not handled in the fixed branch: $name');
       }
@@ -1178,6 +1211,8 @@ class FfiModel with ChangeNotifier {
     }
 
     _pi.isSet.value = true;
+    parent.target?.inputModel.updateTouchForwarding();
+    parent.target?.localEchoModel.updateEnabled();
     stateGlobal.resetLastResolutionGroupValues(peerId);
 
     if (isDesktop || isWebDesktop) {
@@ -1822,6 +1857,7 @@ class CanvasModel with ChangeNotifier {
   final ScrollController _vertical = ScrollController();
 
   final _imageOverflow = false.obs;
+  Timer? _matchWindowSizeTimer;
 
   WeakReference<FFI> parent;
 
@@ -1884,6 +1920,33 @@ class CanvasModel with ChangeNotifier {
 
   updateSize() => _size = getSize();
 
+  // Resize the virtual display to the view, after the window stops resizing.
+  _tryMatchWindowSize() {
+    final ffiModel = parent.target?.ffiModel;
+    if (!isDesktop || ffiModel == null) return;
+    if (!ffiModel.keyboard || !ffiModel.isVirtualDisplayResolution) return;
+    if (!bind.sessionGetToggleOptionSync(
+        sessionId: sessionId, arg: kOptionVirtualDisplayMatchWindow)) {
+      return;
+    }
+    _matchWindowSizeTimer?.cancel();
+    _matchWindowSizeTimer = Timer(const Duration(milliseconds: 500), () {
+      final display = ffiModel.pi.tryGetDisplayIfNotAllDisplay();
+      if (display == null) return;
+      final ratio = ui.window.devicePixelRatio;
+      // Even sizes for the encoders.
+      final w = (size.width * ratio).round() & ~1;
+      final h = (size.height * ratio).round() & ~1;
+      if (w < 320 || h < 320) return;
+      if (w == display.width && h == display.height) return;
+      bind.sessionChangeResolution(
+          sessionId: sessionId,
+          display: ffiModel.pi.currentDisplay,
+          width: w,
+          height: h);
+    });
+  }
+
   updateViewStyle({refreshMousePos = true, notify = true}) async {
     final style = await bind.sessionGetViewStyle(sessionId: sessionId);
     if (style == null) {
@@ -1891,6 +1954,7 @@ class CanvasModel with ChangeNotifier {
     }
 
     updateSize();
+    _tryMatchWindowSize();
     final displayWidth = getDisplayWidth();
     final displayHeight = getDisplayHeight();
     final viewStyle = ViewStyle(
@@ -2359,6 +2423,16 @@ class CursorModel with ChangeNotifier {
   String peerId = '';
   WeakReference<FFI> parent;
 
+  // The positions of the peer arrive with the network jitter, the remote cursor
+  // is painted moving to the new position in the interval of the last two
+  // positions, instead of jumping.
+  Offset _smoothFrom = Offset.zero;
+  Offset _smoothPos = Offset.zero;
+  DateTime _smoothStart = DateTime.now();
+  Duration _smoothDuration = Duration.zero;
+  DateTime _lastPeerPosTime = DateTime.now();
+  Timer? _smoothTimer;
+
   // Only for mobile, touch mode
   // To block touch event above the KeyHelpTools
   //
@@ -2424,6 +2498,11 @@ class CursorModel with ChangeNotifier {
   double get x => _x - _displayOriginX;
   double get y => _y - _displayOriginY;
 
+  Offset get _paintPos => _smoothTimer != null ? _smoothPos : offset;
+  // The position to paint the remote cursor.
+  double get paintX => _paintPos.dx - _displayOriginX;
+  double get paintY => _paintPos.dy - _displayOriginY;
+
   double get devicePixelRatio => parent.target!.canvasModel.devicePixelRatio;
 
   Offset get offset => Offset(_x, _y);
@@ -2837,8 +2916,10 @@ class CursorModel with ChangeNotifier {
       gotMouseControl = false;
       _lastPeerMouse = DateTime.now();
     }
+    final from = _paintPos;
     _x = double.parse(evt['x']);
     _y = double.parse(evt['y']);
+    _smoothMove(from);
     try {
       RemoteCursorMovedState.find(id).value = true;
     } catch (e) {
@@ -2869,9 +2950,48 @@ class CursorModel with ChangeNotifier {
     notifyListeners();
   }
 
+  _smoothMove(Offset from) {
+    final now = DateTime.now();
+    final interval = now.difference(_lastPeerPosTime);
+    _lastPeerPosTime = now;
+    // Jump if the cursor was idle or is moved far away, e.g. switching displays.
+    if (interval > kCursorSmoothMaxInterval ||
+        (offset - from).distance > kCursorSmoothMaxDistance) {
+      _smoothTimer?.cancel();
+      _smoothTimer = null;
+      return;
+    }
+    _smoothFrom = from;
+    _smoothPos = from;
+    _smoothStart = now;
+    _smoothDuration = interval;
+    _smoothTimer ??= Timer.periodic(
+        const Duration(milliseconds: 8), (_) => _onSmoothTick());
+  }
+
+  _onSmoothTick() {
+    final t = DateTime.now().difference(_smoothStart).inMicroseconds /
+        max(1, _smoothDuration.inMicroseconds);
+    if (t >= 1) {
+      _smoothTimer?.cancel();
+      _smoothTimer = null;
+    } else {
+      _smoothPos = Offset.lerp(_smoothFrom, offset, t)!;
+    }
+    try {
+      notifyListeners();
+    } catch (e) {
+      // The listener may be already disposed.
+      _smoothTimer?.cancel();
+      _smoothTimer = null;
+    }
+  }
+
   clear() {
     _x = -10000;
     _x = -10000;
+    _smoothTimer?.cancel();
+    _smoothTimer = null;
     _image = null;
     _firstUpdateMouseTime = null;
     gotMouseControl = true;
@@ -2911,6 +3031,8 @@ class QualityMonitorData {
   String? targetBitrate;
   String? codecFormat;
   String? chroma;
+  String? bandwidth;
+  String? bweState;
 }
 
 class QualityMonitorModel with ChangeNotifier {
@@ -2973,6 +3095,14 @@ class QualityMonitorModel with ChangeNotifier {
       if (evt.containsKey('chroma') && (evt['chroma'] as String).isNotEmpty) {
         _data.chroma = evt['chroma'];
       }
+      if (evt.containsKey('bandwidth') &&
+          (evt['bandwidth'] as String).isNotEmpty) {
+        _data.bandwidth = evt['bandwidth'];
+      }
+      if (evt.containsKey('bwe_state') &&
+          (evt['bwe_state'] as String).isNotEmpty) {
+        _data.bweState = evt['bwe_state'];
+      }
       notifyListeners();
     } catch (e) {
       //
@@ -3055,6 +3185,7 @@ class FFI {
   late final QualityMonitorModel qualityMonitorModel; // session
   late final RecordingModel recordingModel; // session
   late final InputModel inputModel; // session
+  late final LocalEchoModel localEchoModel; // session
   late final ElevationModel elevationModel; // session
   late final CmFileModel cmFileModel; // cm
   late final TextureModel textureModel; //session
@@ -3084,6 +3215,7 @@ class FFI {
     qualityMonitorModel = QualityMonitorModel(WeakReference(this));
     recordingModel = RecordingModel(WeakReference(this));
     inputModel = InputModel(WeakReference(this));
+    localEchoModel = LocalEchoModel(WeakReference(this));
     elevationModel = ElevationModel(WeakReference(this));
     cmFileModel = CmFileModel(WeakReference(this));
     textureModel = TextureModel(WeakReference(this));
@@ -3124,6 +3256,7 @@ class FFI {
     int? tabWindowId,
     int? display,
     List<int>? displays,
+    bool isAudioOnly = false,
   }) {
     closed = false;
     auditNote = '';
@@ -3171,6 +3304,9 @@ class FFI {
         isSharedPassword: isSharedPassword ?? false,
         connToken: connToken,
       );
+      if (isAudioOnly) {
+        bind.sessionSetAudioOnly(sessionId: sessionId);
+      }
     } else if (display != null) {
       if (displays == null) {
         debugPrint(
@@ -3365,6 +3501,7 @@ class FFI {
     ffiModel.clear();
     canvasModel.clear();
     inputModel.resetModifiers();
+    localEchoModel.close();
     if (closeSession) {
       await bind.sessionClose(sessionId: sessionId);
     }
@@ -3485,6 +3622,8 @@ class PeerInfo with ChangeNotifier {
 
   bool get isWayland => platformAdditions[kPlatformAdditionsIsWayland] == true;
   bool get isHeadless => platformAdditions[kPlatformAdditionsHeadless] == true;
+  String? get windowsSessionsCurrentSid =>
+      platformAdditions[kPlatformAdditionsWindowsCurrentSid] as String?;
   bool get isInstalled =>
       platform != kPeerPlatformWindows ||
       platformAdditions[kPlatformAdditionsIsInstalled] == true;
@@ -3503,6 +3642,27 @@ class PeerInfo with ChangeNotifier {
       platformAdditions[kPlatformAdditionsIddImpl] == 'rustdesk_idd';
   bool get isAmyuniIdd =>
       platformAdditions[kPlatformAdditionsIddImpl] == 'amyuni_idd';
+  bool get isSupportVirtualDisplayModes =>
+      platformAdditions[kPlatformAdditionsVirtualDisplayModes] == true;
+  bool get isSupportAudioDevice =>
+      platformAdditions[kPlatformAdditionsSupportAudioDevice] == true;
+  bool get isSupportMicPassthrough =>
+      platformAdditions[kPlatformAdditionsSupportMicPassthrough] == true;
+  bool get isSupportGamepad =>
+      platformAdditions[kPlatformAdditionsSupportGamepad] == true;
+  bool get isSupportPen =>
+      platformAdditions[kPlatformAdditionsSupportPen] == true;
+  bool get isSupportTouch =>
+      platformAdditions[kPlatformAdditionsSupportTouch] == true;
+  bool get isSupportBlockInput =>
+      platform == kPeerPlatformWindows ||
+      platformAdditions[kPlatformAdditionsSupportBlockInput] == true;
+  List<String> get specialKeys =>
+      List<String>.from(platformAdditions[kPlatformAdditionsSpecialKeys] ?? []);
+  bool get isSupportRelativeMouse =>
+      platformAdditions[kPlatformAdditionsSupportRelativeMouse] == true;
+  bool get isSupportTunnel =>
+      platformAdditions[kPlatformAdditionsSupportTunnel] == true;
 
   Display? tryGetDisplay({int? display}) {
     if (displays.isEmpty) {
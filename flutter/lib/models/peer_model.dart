@@ -22,6 +22,9 @@ class Peer {
   String device_group_name;
   String note;
   bool? sameServer;
+  // Reported by the peer if allowed, or by the API server.
+  String osVersion;
+  String version;
 
   String getId() {
     if (alias != '') {
@@ -45,7 +48,9 @@ class Peer {
         loginName = json['loginName'] ?? '',
         device_group_name = json['device_group_name'] ?? '',
         note = json['note'] is String ? json['note'] : '',
-        sameServer = json['same_server'];
+        sameServer = json['same_server'],
+        osVersion = json['os_version'] ?? '',
+        version = json['version'] ?? '';
 
   Map<String, dynamic> toJson() {
     return <String, dynamic>{
@@ -64,6 +69,8 @@ class Peer {
       'device_group_name': device_group_name,
       'note': note,
       'same_server': sameServer,
+      'os_version': osVersion,
+      'version': version,
     };
   }
 
@@ -109,6 +116,8 @@ class Peer {
     required this.device_group_name,
     required this.note,
     this.sameServer,
+    this.osVersion = '',
+    this.version = '',
   });
 
   Peer.loading()
@@ -142,7 +151,9 @@ class Peer {
         rdpUsername == other.rdpUsername &&
         device_group_name == other.device_group_name &&
         loginName == other.loginName &&
-        note == other.note;
+        note == other.note &&
+        osVersion == other.osVersion &&
+        version == other.version;
   }
 
   Peer.copy(Peer other)
@@ -161,7 +172,9 @@ class Peer {
             loginName: other.loginName,
             device_group_name: other.device_group_name,
             note: other.note,
-            sameServer: other.sameServer);
+            sameServer: other.sameServer,
+            osVersion: other.osVersion,
+            version: other.version);
 }
 
 enum UpdateEvent { online, load }
@@ -785,6 +785,25 @@ class ServerModel with ChangeNotifier {
     }
   }
 
+  void updateInputBlockedState(Map<String, dynamic> evt) {
+    try {
+      final client = Client.fromJson(jsonDecode(evt["client"]));
+      final index = _clients.indexWhere((element) => element.id == client.id);
+      if (index != -1) {
+        _clients[index].inputBlocked = client.inputBlocked;
+        if (client.inputBlocked) {
+          // Keep the indicator in sight, the local user can not bring it up.
+          Future.delayed(Duration.zero, () {
+            windowOnTop(null);
+          });
+        }
+        notifyListeners();
+      }
+    } catch (e) {
+      debugPrint("updateInputBlockedState failed: $e");
+    }
+  }
+
   void androidUpdatekeepScreenOn() async {
     if (!isAndroid) return;
     var floatingWindowDisabled =
@@ -835,6 +854,8 @@ class Client {
   bool fromSwitch = false;
   bool inVoiceCall = false;
   bool incomingVoiceCall = false;
+  bool inputBlocked = false;
+  String sas = "";
 
   RxInt unreadChatMessageCount = 0.obs;
 
@@ -862,6 +883,8 @@ class Client {
     fromSwitch = json['from_switch'];
     inVoiceCall = json['in_voice_call'];
     incomingVoiceCall = json['incoming_voice_call'];
+    inputBlocked = json['input_blocked'] ?? false;
+    sas = json['sas'] ?? '';
   }
 
   Map<String, dynamic> toJson() {
@@ -885,6 +908,8 @@ class Client {
     data['from_switch'] = fromSwitch;
     data['in_voice_call'] = inVoiceCall;
     data['incoming_voice_call'] = incomingVoiceCall;
+    data['input_blocked'] = inputBlocked;
+    data['sas'] = sas;
     return data;
   }
 
@@ -0,0 +1,104 @@
+import 'dart:convert';
+
+import 'package:flutter/material.dart';
+import 'package:flutter_hbb/common.dart';
+import 'package:flutter_hbb/common/widgets/dialog.dart';
+
+import '../consts.dart';
+import 'file_model.dart';
+import 'platform_model.dart';
+
+/// Sends the files of the peer to another peer connected by this device,
+/// without the files going through this device, see
+/// `src/transfer_broker.rs`. The progress is reported in the jobs of the
+/// session.
+class TransferBroker {
+  final FileController controller;
+
+  TransferBroker(this.controller);
+
+  bool get isSupported =>
+      !controller.isLocal &&
+      controller.rootState.target?.ffiModel.pi
+              .platformAdditions[kPlatformAdditionsSupportTransferBroker] ==
+          true;
+
+  List<String> get peers => List<String>.from(
+      jsonDecode(bind.sessionGetBrokerPeers(sessionId: controller.sessionId)));
+
+  void send(List<Entry> items, String peerId, String to) {
+    final jobs = controller.jobController;
+    for (final item in items) {
+      final id = jobs.addTransferJob(item, true);
+      jobs.jobTable[jobs.getJob(id)]
+        ..remote = item.path
+        ..to = '$peerId: $to';
+      bind.sessionBrokerTransfer(
+          sessionId: controller.sessionId,
+          id: id,
+          peerId: peerId,
+          paths: [item.path],
+          to: to);
+    }
+    jobs.jobTable.refresh();
+  }
+
+  Future<void> sendAction(List<Entry> items) async {
+    final peers = this.peers;
+    if (peers.isEmpty) {
+      showToast(translate('transfer_broker_no_peer_tip'));
+      return;
+    }
+    var peerId = peers.first;
+    final to = TextEditingController();
+    final res = await controller.dialogManager?.show<bool>(
+        (setState, Function(bool? v) close, context) {
+      cancel() => close(false);
+      submit() {
+        if (to.text.trim().isNotEmpty) close(true);
+      }
+
+      return CustomAlertDialog(
+        title: Text(translate('Send to another device')),
+        content: Column(
+          mainAxisSize: MainAxisSize.min,
+          crossAxisAlignment: CrossAxisAlignment.start,
+          children: [
+            Text(translate('transfer_broker_tip')),
+            DropdownButton<String>(
+              value: peerId,
+              isExpanded: true,
+              items: peers
+                  .map((id) => DropdownMenuItem(value: id, child: Text(id)))
+                  .toList(),
+              onChanged: (v) {
+                if (v != null) setState(() => peerId = v);
+              },
+            ),
+            DialogTextField(
+              title: translate('Destination folder'),
+              controller: to,
+            ),
+          ],
+        ),
+        actions: [
+          dialogButton(
+            "Cancel",
+            icon: Icon(Icons.close_rounded),
+            onPressed: cancel,
+            isOutline: true,
+          ),
+          dialogButton(
+            "OK",
+            icon: Icon(Icons.done_rounded),
+            onPressed: submit,
+          ),
+        ],
+        onSubmit: submit,
+        onCancel: cancel,
+      );
+    });
+    if (res != true) return;
+    send(items, peerId, to.text.trim());
+  }
+}
@@ -230,6 +230,7 @@ class RustDeskMultiWindowManager {
     bool? isRDP,
     bool? isSharedPassword,
     String? connToken,
+    bool? isAudioOnly,
   }) async {
     var params = {
       "type": type.index,
@@ -249,6 +250,9 @@ class RustDeskMultiWindowManager {
     if (connToken != null) {
       params['connToken'] = connToken;
     }
+    if (isAudioOnly != null) {
+      params['isAudioOnly'] = isAudioOnly;
+    }
     final msg = jsonEncode(params);
 
     // separate window for file transfer is not supported
@@ -273,6 +277,7 @@ class RustDeskMultiWindowManager {
     bool? isSharedPassword,
     String? switchUuid,
     bool? forceRelay,
+    bool? isAudioOnly,
   }) async {
     return await newSession(
       WindowType.RemoteDesktop,
@@ -283,6 +288,7 @@ class RustDeskMultiWindowManager {
       forceRelay: forceRelay,
       switchUuid: switchUuid,
       isSharedPassword: isSharedPassword,
+      isAudioOnly: isAudioOnly,
     );
   }
 
@@ -673,6 +673,56 @@ class RustdeskImpl {
     throw UnimplementedError("sessionResumeJob");
   }
 
+  Future<void> sessionPauseJob(
+      {required UuidValue sessionId,
+      required int actId,
+      required bool isRemote,
+      dynamic hint}) {
+    throw UnimplementedError("sessionPauseJob");
+  }
+
+  Future<void> sessionFileOp(
+      {required UuidValue sessionId,
+      required String op,
+      required bool isRemote,
+      dynamic hint}) {
+    throw UnimplementedError("sessionFileOp");
+  }
+
+  Future<void> sessionQueryFileDrop(
+      {required UuidValue sessionId, required int id, dynamic hint}) {
+    throw UnimplementedError("sessionQueryFileDrop");
+  }
+
+  String sessionGetBrokerPeers({required UuidValue sessionId, dynamic hint}) {
+    return '[]';
+  }
+
+  Future<void> sessionBrokerTransfer(
+      {required UuidValue sessionId,
+      required int id,
+      required String peerId,
+      required List<String> paths,
+      required String to,
+      dynamic hint}) {
+    throw UnimplementedError("sessionBrokerTransfer");
+  }
+
+  String sessionGetClipboardHistory(
+      {required UuidValue sessionId, required bool remote, dynamic hint}) {
+    return '[]';
+  }
+
+  Future<void> sessionPushClip(
+      {required UuidValue sessionId, required int id, dynamic hint}) {
+    throw UnimplementedError("sessionPushClip");
+  }
+
+  Future<void> sessionPullClip(
+      {required UuidValue sessionId, required int id, dynamic hint}) {
+    throw UnimplementedError("sessionPullClip");
+  }
+
   Future<void> sessionElevateDirect(
       {required UuidValue sessionId, dynamic hint}) {
     return Future(() => js.context.callMethod('setByName', ['elevate_direct']));
@@ -1116,6 +1166,30 @@ class RustdeskImpl {
     throw UnimplementedError("sessionRemovePortForward");
   }
 
+  Future<void> sessionAddTunnel(
+      {required UuidValue sessionId,
+      required String kind,
+      required int localPort,
+      required String remoteHost,
+      required int remotePort,
+      dynamic hint}) {
+    throw UnimplementedError("sessionAddTunnel");
+  }
+
+  Future<void> sessionRemoveTunnel(
+      {required UuidValue sessionId,
+      required String kind,
+      required int localPort,
+      required String remoteHost,
+      required int remotePort,
+      dynamic hint}) {
+    throw UnimplementedError("sessionRemoveTunnel");
+  }
+
+  String sessionGetTunnels({required UuidValue sessionId, dynamic hint}) {
+    return '[]';
+  }
+
   Future<void> sessionNewRdp({required UuidValue sessionId, dynamic hint}) {
     throw UnimplementedError("sessionNewRdp");
   }
@@ -1326,6 +1400,24 @@ class RustdeskImpl {
         ]));
   }
 
+  Future<void> sessionPlugInVirtualDisplayMode(
+      {required UuidValue sessionId,
+      required int index,
+      required int width,
+      required int height,
+      required int refreshRate,
+      dynamic hint}) {
+    return Future(() => js.context.callMethod('setByName', [
+          'plug_in_virtual_display_mode',
+          jsonEncode({
+            'index': index,
+            'width': width,
+            'height': height,
+            'refresh': refreshRate
+          })
+        ]));
+  }
+
   Future<void> mainSetHomeDir({required String home, dynamic hint}) {
     throw UnimplementedError("mainSetHomeDir");
   }
@@ -1367,6 +1459,23 @@ class RustdeskImpl {
     throw UnimplementedError("mainWol");
   }
 
+  String mainGetRelayLatencies({dynamic hint}) {
+    return '{}';
+  }
+
+  Future<void> mainProbeRelayServers({dynamic hint}) {
+    throw UnimplementedError("mainProbeRelayServers");
+  }
+
+  String mainGetWolRelayPeers({required String id, dynamic hint}) {
+    return '[]';
+  }
+
+  Future<String> mainWolVia(
+      {required String id, required String relayId, dynamic hint}) {
+    throw UnimplementedError("mainWolVia");
+  }
+
   Future<void> mainCreateShortcut({required String id, dynamic hint}) {
     throw UnimplementedError("mainCreateShortcut");
   }
@@ -1781,6 +1890,18 @@ class RustdeskImpl {
     throw UnimplementedError("mainVerifyBot");
   }
 
+  Future<String> mainRegisterSecurityKey({dynamic hint}) {
+    throw UnimplementedError("mainRegisterSecurityKey");
+  }
+
+  String mainCheckAccessControlList({required String rules, dynamic hint}) {
+    return '';
+  }
+
+  Future<String> mainVerifyRecording({required String path, dynamic hint}) {
+    throw UnimplementedError("mainVerifyRecording");
+  }
+
   String mainGetUnlockPin({dynamic hint}) {
     throw UnimplementedError("mainGetUnlockPin");
   }
@@ -1789,6 +1910,19 @@ class RustdeskImpl {
     throw UnimplementedError("mainSetUnlockPin");
   }
 
+  String sessionGetSas({required UuidValue sessionId, dynamic hint}) {
+    return '';
+  }
+
+  bool sessionIsPeerVerified({required UuidValue sessionId, dynamic hint}) {
+    return false;
+  }
+
+  Future<void> sessionMarkPeerVerified(
+      {required UuidValue sessionId, dynamic hint}) {
+    return Future.value();
+  }
+
   bool sessionGetEnableTrustedDevices(
       {required UuidValue sessionId, dynamic hint}) {
     return js.context.callMethod('getByName', ['enable_trusted_devices']) ==
@@ -1807,6 +1941,53 @@ class RustdeskImpl {
     throw UnimplementedError("mainClearTrustedDevices");
   }
 
+  Future<String> mainGetTransferHistory({dynamic hint}) {
+    throw UnimplementedError("mainGetTransferHistory");
+  }
+
+  Future<String> mainExportTransferHistory(
+      {required String path, dynamic hint}) {
+    throw UnimplementedError("mainExportTransferHistory");
+  }
+
+  Future<String> mainGetLockouts({dynamic hint}) {
+    throw UnimplementedError("mainGetLockouts");
+  }
+
+  Future<void> mainClearLockouts({required String json, dynamic hint}) {
+    throw UnimplementedError("mainClearLockouts");
+  }
+
+  Future<String> mainGetSettingsProtection({dynamic hint}) {
+    return Future.value('{}');
+  }
+
+  Future<bool> mainUnlockSettings({required String code, dynamic hint}) {
+    return Future.value(false);
+  }
+
+  Future<void> mainSetSettingsAdminPassword(
+      {required String password, dynamic hint}) {
+    throw UnimplementedError("mainSetSettingsAdminPassword");
+  }
+
+  Future<String> mainGetServerKeyPins({dynamic hint}) {
+    throw UnimplementedError("mainGetServerKeyPins");
+  }
+
+  Future<String> mainGetOneTimePasswords({dynamic hint}) {
+    throw UnimplementedError("mainGetOneTimePasswords");
+  }
+
+  Future<String> mainAddOneTimePassword({required String json, dynamic hint}) {
+    throw UnimplementedError("mainAddOneTimePassword");
+  }
+
+  Future<void> mainRevokeOneTimePasswords(
+      {required String json, dynamic hint}) {
+    throw UnimplementedError("mainRevokeOneTimePasswords");
+  }
+
   Future<String> getVoiceCallInputDevice({required bool isCm, dynamic hint}) {
     throw UnimplementedError("getVoiceCallInputDevice");
   }
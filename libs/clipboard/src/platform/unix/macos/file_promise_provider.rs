@@ -0,0 +1,96 @@
+use super::pasteboard_context::{handle_paste_result, PasteObserverInfo, TEMP_FILE_PREFIX};
+use block2::Block;
+use objc2::{
+    declare_class, msg_send_id, mutability,
+    rc::Id,
+    runtime::{NSObject, NSObjectProtocol, ProtocolObject},
+    ClassType, DeclaredClass,
+};
+use objc2_app_kit::{NSFilePromiseProvider, NSFilePromiseProviderDelegate};
+use objc2_foundation::{NSError, NSOperationQueue, NSString, NSURL};
+
+// The promised files are the whole file list, the UTI of any data.
+const PROMISED_FILE_TYPE: &str = "public.data";
+
+pub(super) struct Ivars {
+    task_info: PasteObserverInfo,
+    queue: Id<NSOperationQueue>,
+}
+
+declare_class!(
+    pub(super) struct FilePromiseDelegate;
+
+    unsafe impl ClassType for FilePromiseDelegate {
+        type Super = NSObject;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "RustDeskFilePromiseDelegate";
+    }
+
+    impl DeclaredClass for FilePromiseDelegate {
+        type Ivars = Ivars;
+    }
+
+    unsafe impl NSObjectProtocol for FilePromiseDelegate {}
+
+    unsafe impl NSFilePromiseProviderDelegate for FilePromiseDelegate {
+        #[method_id(filePromiseProvider:fileNameForType:)]
+        #[allow(non_snake_case)]
+        unsafe fn filePromiseProvider_fileNameForType(
+            &self,
+            _provider: &NSFilePromiseProvider,
+            _file_type: &NSString,
+        ) -> Id<NSString> {
+            NSString::from_str(&format!("{}{}", TEMP_FILE_PREFIX, uuid::Uuid::new_v4()))
+        }
+
+        // Called on `queue` when the files are pasted, e.g. in Finder, `url` is the placeholder
+        // in the target directory. The files are written to the directory by the paste task.
+        #[method(filePromiseProvider:writePromiseToURL:completionHandler:)]
+        #[allow(non_snake_case)]
+        unsafe fn filePromiseProvider_writePromiseToURL_completionHandler(
+            &self,
+            _provider: &NSFilePromiseProvider,
+            url: &NSURL,
+            completion_handler: &Block<dyn Fn(*mut NSError)>,
+        ) {
+            if let Some(path) = url.path() {
+                let mut task_info = self.ivars().task_info.clone();
+                task_info.target_path = path.to_string();
+                handle_paste_result(&task_info);
+            }
+            completion_handler.call((std::ptr::null_mut(),));
+        }
+
+        #[method_id(operationQueueForFilePromiseProvider:)]
+        #[allow(non_snake_case)]
+        unsafe fn operationQueueForFilePromiseProvider(
+            &self,
+            _provider: &NSFilePromiseProvider,
+        ) -> Id<NSOperationQueue> {
+            self.ivars().queue.clone()
+        }
+    }
+
+    unsafe impl FilePromiseDelegate {}
+);
+
+/// The provider keeps a weak reference to the delegate, which must be kept alive
+/// as long as the provider is on the pasteboard.
+pub(super) fn create_file_promise_provider(
+    task_info: PasteObserverInfo,
+) -> (Id<NSFilePromiseProvider>, Id<FilePromiseDelegate>) {
+    let delegate = FilePromiseDelegate::alloc();
+    let delegate = delegate.set_ivars(Ivars {
+        task_info,
+        queue: unsafe { NSOperationQueue::new() },
+    });
+    let delegate: Id<FilePromiseDelegate> = unsafe { msg_send_id![super(delegate), init] };
+    let provider = unsafe {
+        NSFilePromiseProvider::initWithFileType_delegate(
+            NSFilePromiseProvider::alloc(),
+            &NSString::from_str(PROMISED_FILE_TYPE),
+            ProtocolObject::from_ref(&*delegate),
+        )
+    };
+    (provider, delegate)
+}
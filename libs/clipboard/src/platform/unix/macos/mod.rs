@@ -1,5 +1,4 @@
-mod item_data_provider;
-mod paste_observer;
+mod file_promise_provider;
 mod paste_task;
 pub mod pasteboard_context;
 
@@ -1,6 +1,5 @@
 use super::{
-    item_data_provider::create_pasteboard_file_url_provider,
-    paste_observer::PasteObserver,
+    file_promise_provider::{create_file_promise_provider, FilePromiseDelegate},
     paste_task::{FileContentsResponse, PasteTask},
 };
 use crate::{
@@ -11,17 +10,14 @@ use crate::{
 };
 use hbb_common::{allow_err, bail, log, ResultType};
 use objc2::{msg_send_id, rc::autoreleasepool, rc::Id, runtime::ProtocolObject, ClassType};
-use objc2_app_kit::{NSPasteboard, NSPasteboardTypeFileURL};
-use objc2_foundation::{NSArray, NSString};
+use objc2_app_kit::{NSPasteboard, NSPasteboardWriting};
+use objc2_foundation::{NSArray, NSInteger};
 use std::{
-    io,
     path::Path,
     sync::{
-        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        mpsc::{channel, Sender},
         Arc, Mutex,
     },
-    thread,
-    time::Duration,
 };
 
 lazy_static::lazy_static! {
@@ -38,23 +34,11 @@ pub(super) struct PasteObserverInfo {
     pub target_path: String,
 }
 
-impl PasteObserverInfo {
-    fn exit_msg() -> Self {
-        Self::default()
-    }
-}
-
-struct ContextInfo {
-    tx: Sender<io::Result<PasteObserverInfo>>,
-    handle: thread::JoinHandle<()>,
-}
-
 pub struct PasteboardContext {
     pasteboard: Id<NSPasteboard>,
-    observer: Arc<Mutex<PasteObserver>>,
-    tx_handle: Option<ContextInfo>,
-    tx_remove_file: Option<Sender<String>>,
-    remove_file_handle: Option<thread::JoinHandle<()>>,
+    // The delegate of the file promise on the pasteboard, and the change count of the pasteboard
+    // after it is written, to empty the pasteboard only if the promise is still on it.
+    promise: Option<(Id<FilePromiseDelegate>, NSInteger)>,
     tx_paste_task: Sender<FileContentsResponse>,
     paste_task: Arc<Mutex<PasteTask>>,
 }
@@ -62,17 +46,6 @@ pub struct PasteboardContext {
 unsafe impl Send for PasteboardContext {}
 unsafe impl Sync for PasteboardContext {}
 
-impl Drop for PasteboardContext {
-    fn drop(&mut self) {
-        self.observer.lock().unwrap().stop();
-        if let Some(tx_handle) = self.tx_handle.take() {
-            if tx_handle.tx.send(Ok(PasteObserverInfo::exit_msg())).is_ok() {
-                tx_handle.handle.join().ok();
-            }
-        }
-    }
-}
-
 impl CliprdrServiceContext for PasteboardContext {
     fn set_is_stopped(&mut self) -> Result<(), CliprdrError> {
         Ok(())
@@ -96,125 +69,27 @@ impl CliprdrServiceContext for PasteboardContext {
 }
 
 impl PasteboardContext {
-    fn init(&mut self) {
-        let (tx_remove_file, rx_remove_file) = channel();
-        let handle_remove_file = Self::init_thread_remove_file(rx_remove_file);
-        self.tx_remove_file = Some(tx_remove_file.clone());
-        self.remove_file_handle = Some(handle_remove_file);
-
-        let (tx, rx) = channel();
-        let observer: Arc<Mutex<PasteObserver>> = self.observer.clone();
-        let handle = Self::init_thread_observer(tx_remove_file, rx, observer);
-        self.tx_handle = Some(ContextInfo { tx, handle });
-    }
-
-    fn init_thread_observer(
-        tx_remove_file: Sender<String>,
-        rx: Receiver<io::Result<PasteObserverInfo>>,
-        observer: Arc<Mutex<PasteObserver>>,
-    ) -> thread::JoinHandle<()> {
-        let exit_msg = PasteObserverInfo::exit_msg();
-        thread::spawn(move || loop {
-            match rx.recv() {
-                Ok(Ok(task_info)) => {
-                    if task_info == exit_msg {
-                        log::debug!("pasteboard item data provider: exit");
-                        break;
-                    }
-                    tx_remove_file.send(task_info.source_path.clone()).ok();
-                    observer.lock().unwrap().start(task_info);
-                }
-                Ok(Err(e)) => {
-                    log::error!("pasteboard item data provider, inner error: {e}");
-                }
-                Err(e) => {
-                    log::error!("pasteboard item data provider, error: {e}");
-                    break;
-                }
-            }
-        })
-    }
-
-    fn init_thread_remove_file(rx: Receiver<String>) -> thread::JoinHandle<()> {
-        thread::spawn(move || {
-            let mut cur_file: Option<String> = None;
-            loop {
-                match rx.recv_timeout(Duration::from_secs(30)) {
-                    Ok(path) => {
-                        if let Some(file) = cur_file.take() {
-                            if !file.is_empty() {
-                                std::fs::remove_file(&file).ok();
-                            }
-                        }
-                        if !path.is_empty() {
-                            cur_file = Some(path);
-                        }
-                    }
-                    Err(e) => {
-                        if let Some(file) = cur_file.take() {
-                            if !file.is_empty() {
-                                std::fs::remove_file(&file).ok();
-                            }
-                        }
-                        if e == RecvTimeoutError::Disconnected {
-                            break;
-                        }
-                    }
-                }
-            }
-        })
-    }
-
-    // Just removing the file can also make paste option in the context menu disappear.
     fn empty_clipboard_(&mut self, _conn_id: i32) -> bool {
-        self.tx_remove_file
-            .as_ref()
-            .map(|tx| tx.send("".to_string()).ok());
-        true
-    }
-
-    fn temp_files_count() -> usize {
-        let mut count = 0;
-        if let Ok(entries) = std::fs::read_dir("/tmp") {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if path.is_file() {
-                        if let Some(file_name) = path.file_name() {
-                            if let Some(file_name_str) = file_name.to_str() {
-                                if file_name_str.starts_with(TEMP_FILE_PREFIX) {
-                                    count += 1;
-                                }
-                            }
-                        }
-                    }
+        if let Some((_, change_count)) = self.promise.take() {
+            unsafe {
+                if self.pasteboard.changeCount() == change_count {
+                    self.pasteboard.clearContents();
                 }
             }
         }
-        count
+        true
     }
 
     fn server_clip_file_(&mut self, conn_id: i32, msg: ClipboardFile) -> Result<(), CliprdrError> {
         match msg {
             ClipboardFile::FormatList { format_list } => {
-                let temp_files = Self::temp_files_count();
-                if temp_files >= 3 {
-                    // The temp files should be 0 or 1 in normal case.
-                    // We should not continue to paste files if there are more than 3 temp files.
-                    return Err(CliprdrError::CommonError {
-                        description: format!(
-                            "too many temp files, current: {}, limit: {}",
-                            temp_files, 3
-                        ),
-                    });
-                }
-
                 let task_lock = self.paste_task.lock().unwrap();
                 if !task_lock.is_finished() {
                     return Err(CliprdrError::CommonError {
                         description: "previous file paste task is not finished".to_string(),
                     });
                 }
+                drop(task_lock);
                 self.handle_format_list(conn_id, format_list)?;
             }
             ClipboardFile::FormatDataResponse {
@@ -237,71 +112,56 @@ impl PasteboardContext {
     }
 
     fn handle_format_list(
-        &self,
+        &mut self,
         conn_id: i32,
         format_list: Vec<(i32, String)>,
     ) -> Result<(), CliprdrError> {
-        if let Some(tx_handle) = self.tx_handle.as_ref() {
-            if !format_list
-                .iter()
-                .find(|(_, name)| name == FILECONTENTS_FORMAT_NAME)
-                .map(|(id, _)| *id)
-                .is_some()
-            {
-                return Err(CliprdrError::CommonError {
-                    description: "no file contents format found".to_string(),
-                });
-            };
-            let Some(file_descriptor_id) = format_list
-                .iter()
-                .find(|(_, name)| name == FILEDESCRIPTORW_FORMAT_NAME)
-                .map(|(id, _)| *id)
-            else {
-                return Err(CliprdrError::CommonError {
-                    description: "no file descriptor format found".to_string(),
-                });
-            };
-
-            autoreleasepool(|_| self.set_clipboard_item(tx_handle, conn_id, file_descriptor_id))?;
-        } else {
+        if !format_list
+            .iter()
+            .find(|(_, name)| name == FILECONTENTS_FORMAT_NAME)
+            .map(|(id, _)| *id)
+            .is_some()
+        {
             return Err(CliprdrError::CommonError {
-                description: "pasteboard context is not inited".to_string(),
+                description: "no file contents format found".to_string(),
             });
-        }
-        Ok(())
+        };
+        let Some(file_descriptor_id) = format_list
+            .iter()
+            .find(|(_, name)| name == FILEDESCRIPTORW_FORMAT_NAME)
+            .map(|(id, _)| *id)
+        else {
+            return Err(CliprdrError::CommonError {
+                description: "no file descriptor format found".to_string(),
+            });
+        };
+
+        autoreleasepool(|_| self.set_clipboard_item(conn_id, file_descriptor_id))
     }
 
+    // The files are promised with `NSFilePromiseProvider`, the paste task starts when the
+    // promise is fulfilled to the target directory.
     fn set_clipboard_item(
-        &self,
-        tx_handle: &ContextInfo,
+        &mut self,
         conn_id: i32,
         file_descriptor_id: i32,
     ) -> Result<(), CliprdrError> {
-        let tx = tx_handle.tx.clone();
-        let provider = create_pasteboard_file_url_provider(
-            PasteObserverInfo {
-                file_descriptor_id,
-                conn_id,
-                source_path: "".to_string(),
-                target_path: "".to_string(),
-            },
-            tx,
-        );
+        let (provider, delegate) = create_file_promise_provider(PasteObserverInfo {
+            file_descriptor_id,
+            conn_id,
+            source_path: "".to_string(),
+            target_path: "".to_string(),
+        });
         unsafe {
-            let types = NSArray::from_vec(vec![NSString::from_str(
-                &NSPasteboardTypeFileURL.to_string(),
-            )]);
-            let item = objc2_app_kit::NSPasteboardItem::new();
-            item.setDataProvider_forTypes(&ProtocolObject::from_id(provider), &types);
+            let objects: Id<NSArray<ProtocolObject<dyn NSPasteboardWriting>>> =
+                NSArray::from_vec(vec![ProtocolObject::from_id(provider)]);
             self.pasteboard.clearContents();
-            if !self
-                .pasteboard
-                .writeObjects(&Id::cast(NSArray::from_vec(vec![item])))
-            {
+            if !self.pasteboard.writeObjects(&objects) {
                 return Err(CliprdrError::CommonError {
                     description: "failed to write objects".to_string(),
                 });
             }
+            self.promise = Some((delegate, self.pasteboard.changeCount()));
         }
         Ok(())
     }
@@ -381,7 +241,7 @@ impl PasteboardContext {
     }
 }
 
-fn handle_paste_result(task_info: &PasteObserverInfo) {
+pub(super) fn handle_paste_result(task_info: &PasteObserverInfo) {
     log::info!(
         "file {} is pasted to {}",
         &task_info.source_path,
@@ -415,46 +275,11 @@ pub fn create_pasteboard_context() -> ResultType<Box<PasteboardContext>> {
     let Some(pasteboard) = pasteboard else {
         bail!("failed to get general pasteboard");
     };
-    let mut observer = PasteObserver::new();
-    observer.init(handle_paste_result)?;
     let (tx, rx) = channel();
-    let mut context = Box::new(PasteboardContext {
+    Ok(Box::new(PasteboardContext {
         pasteboard,
-        observer: Arc::new(Mutex::new(observer)),
-        tx_handle: None,
-        tx_remove_file: None,
-        remove_file_handle: None,
+        promise: None,
         tx_paste_task: tx,
         paste_task: Arc::new(Mutex::new(PasteTask::new(rx))),
-    });
-    context.init();
-    Ok(context)
-}
-
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn test_temp_files_count() {
-        let mut c = super::PasteboardContext::temp_files_count();
-
-        let mut created_files = vec![];
-        for _ in 0..10 {
-            let path = format!(
-                "/tmp/{}{}",
-                super::TEMP_FILE_PREFIX,
-                uuid::Uuid::new_v4().to_string()
-            );
-            if std::fs::File::create(&path).is_ok() {
-                created_files.push(path);
-                c += 1;
-            }
-        }
-
-        assert_eq!(c, super::PasteboardContext::temp_files_count());
-
-        // Clean up the created files.
-        for file in created_files {
-            std::fs::remove_file(&file).ok();
-        }
-    }
+    }))
 }
@@ -39,6 +39,18 @@ fn mouse_event(flags: u32, data: u32, dx: i32, dy: i32) -> DWORD {
     unsafe { SendInput(1, &mut input as LPINPUT, size_of::<INPUT>() as c_int) }
 }
 
+/// Maps a pixel of the virtual screen to the absolute coordinate of `SendInput`, 0..=65535.
+///
+/// The system maps back with `n * size / 65536` rounded down, so rounding down here lands one
+/// pixel off on most positions of the large or unevenly scaled virtual screens.
+fn normalize_absolute(pos: i32, origin: i32, size: i32) -> i32 {
+    if size <= 0 {
+        return 0;
+    }
+    let pos = (pos - origin).clamp(0, size - 1) as i64;
+    ((pos * 65536 + size as i64 - 1) / size as i64).min(65535) as i32
+}
+
 fn keybd_event(mut flags: u32, vk: u16, scan: u16) -> DWORD {
     let mut scan = scan;
     unsafe {
@@ -126,13 +138,19 @@ impl MouseControllable for Enigo {
     }
 
     fn mouse_move_to(&mut self, x: i32, y: i32) {
+        let (left, top, width, height) = unsafe {
+            (
+                GetSystemMetrics(SM_XVIRTUALSCREEN),
+                GetSystemMetrics(SM_YVIRTUALSCREEN),
+                GetSystemMetrics(SM_CXVIRTUALSCREEN),
+                GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            )
+        };
         mouse_event(
             MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK,
             0,
-            (x - unsafe { GetSystemMetrics(SM_XVIRTUALSCREEN) }) * 65535
-                / unsafe { GetSystemMetrics(SM_CXVIRTUALSCREEN) },
-            (y - unsafe { GetSystemMetrics(SM_YVIRTUALSCREEN) }) * 65535
-                / unsafe { GetSystemMetrics(SM_CYVIRTUALSCREEN) },
+            normalize_absolute(x, left, width),
+            normalize_absolute(y, top, height),
         );
     }
 
@@ -9,9 +9,10 @@ include!(concat!(env!("OUT_DIR"), "/aom_ffi.rs"));
 use crate::codec::{base_bitrate, codec_thread_num};
 use crate::{codec::EncoderApi, EncodeFrame, STRIDE_ALIGN};
 use crate::{common::GoogleImage, generate_call_macro, generate_call_ptr_macro, Error, Result};
-use crate::{EncodeInput, EncodeYuvFormat, Pixfmt};
+use crate::{EncodeInput, EncodeYuvFormat, ImageFormat, ImageRgb, Pixfmt};
 use hbb_common::{
     anyhow::{anyhow, Context},
+    bail,
     bytes::Bytes,
     log,
     message_proto::{Chroma, EncodedVideoFrame, EncodedVideoFrames, VideoFrame},
@@ -47,6 +48,10 @@ pub struct AomEncoderConfig {
     pub height: u32,
     pub quality: f32,
     pub keyframe_interval: Option<usize>,
+    // 1 for no SVC, 2 or 3 temporal layers otherwise.
+    pub temporal_layers: u32,
+    // 10-bit PQ BT.2020 from I010 input, I420 only.
+    pub hdr: bool,
 }
 
 pub struct AomEncoder {
@@ -55,6 +60,9 @@ pub struct AomEncoder {
     height: usize,
     i444: bool,
     yuvfmt: EncodeYuvFormat,
+    temporal_layers: u32,
+    frame_index: usize,
+    hdr: bool,
 }
 
 // https://webrtc.googlesource.com/src/+/refs/heads/main/modules/video_coding/codecs/av1/libaom_av1_encoder.cc
@@ -104,6 +112,10 @@ mod webrtc {
         c.g_timebase.num = 1;
         c.g_timebase.den = kTimeBaseDen as _;
         c.g_input_bit_depth = kBitDepth;
+        if cfg.hdr && !i444 {
+            c.g_bit_depth = aom_bit_depth::AOM_BITS_10;
+            c.g_input_bit_depth = 10;
+        }
         if let Some(keyframe_interval) = cfg.keyframe_interval {
             c.kf_min_dist = 0;
             c.kf_max_dist = keyframe_interval as _;
@@ -185,7 +197,7 @@ mod webrtc {
         call_ctl!(ctx, AV1E_SET_ENABLE_INTERINTRA_COMP, 0);
         call_ctl!(ctx, AV1E_SET_ENABLE_INTERINTRA_WEDGE, 0);
         call_ctl!(ctx, AV1E_SET_ENABLE_INTRA_EDGE_FILTER, 0);
-        call_ctl!(ctx, AV1E_SET_ENABLE_INTRABC, 0);
+        call_ctl!(ctx, AV1E_SET_ENABLE_INTRABC, 1);
         call_ctl!(ctx, AV1E_SET_ENABLE_MASKED_COMP, 0);
         call_ctl!(ctx, AV1E_SET_ENABLE_PAETH_INTRA, 0);
         call_ctl!(ctx, AV1E_SET_ENABLE_QM, 0);
@@ -197,6 +209,109 @@ mod webrtc {
 
         Ok(())
     }
+
+    // Signals PQ BT.2020 in the sequence header, which receivers check to tone-map.
+    pub fn set_hdr_controls(ctx: *mut aom_codec_ctx_t) -> ResultType<()> {
+        use aome_enc_control_id::*;
+        call_aom!(aom_codec_control(
+            ctx,
+            AV1E_SET_COLOR_PRIMARIES as i32,
+            aom_color_primaries::AOM_CICP_CP_BT_2020
+        ));
+        call_aom!(aom_codec_control(
+            ctx,
+            AV1E_SET_TRANSFER_CHARACTERISTICS as i32,
+            aom_transfer_characteristics::AOM_CICP_TC_SMPTE_2084
+        ));
+        call_aom!(aom_codec_control(
+            ctx,
+            AV1E_SET_MATRIX_COEFFICIENTS as i32,
+            aom_matrix_coefficients::AOM_CICP_MC_BT_2020_NCL
+        ));
+        call_aom!(aom_codec_control(
+            ctx,
+            AV1E_SET_COLOR_RANGE as i32,
+            aom_color_range::AOM_CR_STUDIO_RANGE
+        ));
+        Ok(())
+    }
+
+    // Cumulative share of the bitrate up to each temporal layer, in percent.
+    fn layer_bitrate_pct(temporal_layers: u32) -> &'static [u32] {
+        match temporal_layers {
+            2 => &[60, 100],
+            3 => &[40, 60, 100],
+            _ => &[100],
+        }
+    }
+
+    pub fn set_svc_params(
+        ctx: *mut aom_codec_ctx_t,
+        cfg: &aom_codec_enc_cfg,
+        temporal_layers: u32,
+    ) -> ResultType<()> {
+        let mut params: aom_svc_params_t = unsafe { std::mem::zeroed() };
+        params.number_spatial_layers = 1;
+        params.number_temporal_layers = temporal_layers as _;
+        params.scaling_factor_num[0] = 1;
+        params.scaling_factor_den[0] = 1;
+        for (i, pct) in layer_bitrate_pct(temporal_layers).iter().enumerate() {
+            params.max_quantizers[i] = cfg.rc_max_quantizer as _;
+            params.min_quantizers[i] = cfg.rc_min_quantizer as _;
+            params.layer_target_bitrate[i] = (cfg.rc_target_bitrate * pct / 100) as _;
+            params.framerate_factor[i] = 1 << (temporal_layers as usize - 1 - i);
+        }
+        call_aom!(aom_codec_control(
+            ctx,
+            aome_enc_control_id::AV1E_SET_SVC_PARAMS as i32,
+            &mut params as *mut aom_svc_params_t
+        ));
+        Ok(())
+    }
+
+    // (temporal layer id, reference slot, refreshed slot) of the frame at `index`.
+    // L1T2: 0 1 0 1 ..., the base layer refreshes slot 0, the other frames are not referenced.
+    // L1T3: 0 2 1 2 ..., layer 1 refreshes slot 1, which the next layer 2 frame references.
+    // A layer only references lower layers, so the receiver can drop the highest layers.
+    pub(super) fn svc_pattern(temporal_layers: u32, index: usize) -> (u32, usize, Option<usize>) {
+        match (temporal_layers, index % 4) {
+            (2, 0 | 2) | (3, 0) => (0, 0, Some(0)),
+            (2, _) => (1, 0, None),
+            (3, 2) => (1, 0, Some(1)),
+            (3, 1) => (2, 0, None),
+            (3, _) => (2, 1, None),
+            _ => (0, 0, Some(0)),
+        }
+    }
+
+    pub fn set_svc_frame(
+        ctx: *mut aom_codec_ctx_t,
+        temporal_layers: u32,
+        index: usize,
+    ) -> ResultType<()> {
+        let (temporal_id, reference, refresh) = svc_pattern(temporal_layers, index);
+        let mut layer_id: aom_svc_layer_id_t = unsafe { std::mem::zeroed() };
+        layer_id.spatial_layer_id = 0;
+        layer_id.temporal_layer_id = temporal_id as _;
+        call_aom!(aom_codec_control(
+            ctx,
+            aome_enc_control_id::AV1E_SET_SVC_LAYER_ID as i32,
+            &mut layer_id as *mut aom_svc_layer_id_t
+        ));
+        let mut ref_config: aom_svc_ref_frame_config_t = unsafe { std::mem::zeroed() };
+        // Only LAST, which is reference[0], is used.
+        ref_config.reference[0] = 1;
+        ref_config.ref_idx[0] = reference as _;
+        if let Some(refresh) = refresh {
+            ref_config.refresh[refresh] = 1;
+        }
+        call_aom!(aom_codec_control(
+            ctx,
+            aome_enc_control_id::AV1E_SET_SVC_REF_FRAME_CONFIG as i32,
+            &mut ref_config as *mut aom_svc_ref_frame_config_t
+        ));
+        Ok(())
+    }
 }
 
 impl EncoderApi for AomEncoder {
@@ -208,10 +323,15 @@ impl EncoderApi for AomEncoder {
             crate::codec::EncoderCfg::AOM(config) => {
                 let i = call_aom_ptr!(aom_codec_av1_cx());
                 let c = webrtc::enc_cfg(i, config, i444)?;
+                let hdr = config.hdr && !i444;
 
                 let mut ctx = Default::default();
                 // Flag options: AOM_CODEC_USE_PSNR and AOM_CODEC_USE_HIGHBITDEPTH
-                let flags: aom_codec_flags_t = 0;
+                let flags: aom_codec_flags_t = if hdr {
+                    AOM_CODEC_USE_HIGHBITDEPTH as _
+                } else {
+                    0
+                };
                 call_aom!(aom_codec_enc_init_ver(
                     &mut ctx,
                     i,
@@ -220,12 +340,22 @@ impl EncoderApi for AomEncoder {
                     AOM_ENCODER_ABI_VERSION as _
                 ));
                 webrtc::set_controls(&mut ctx, &c)?;
+                if hdr {
+                    webrtc::set_hdr_controls(&mut ctx)?;
+                }
+                let temporal_layers = config.temporal_layers.clamp(1, 3);
+                if temporal_layers > 1 {
+                    webrtc::set_svc_params(&mut ctx, &c, temporal_layers)?;
+                }
                 Ok(Self {
                     ctx,
                     width: config.width as _,
                     height: config.height as _,
                     i444,
-                    yuvfmt: Self::get_yuvfmt(config.width, config.height, i444),
+                    yuvfmt: Self::get_yuvfmt(config.width, config.height, i444, hdr),
+                    temporal_layers,
+                    frame_index: 0,
+                    hdr,
                 })
             }
             _ => Err(anyhow!("encoder type mismatch")),
@@ -263,6 +393,9 @@ impl EncoderApi for AomEncoder {
         c.rc_max_quantizer = q_max;
         c.rc_target_bitrate = Self::bitrate(self.width as _, self.height as _, ratio);
         call_aom!(aom_codec_enc_config_set(&mut self.ctx, &c));
+        if self.temporal_layers > 1 {
+            webrtc::set_svc_params(&mut self.ctx, &c, self.temporal_layers)?;
+        }
         Ok(())
     }
 
@@ -288,15 +421,10 @@ impl EncoderApi for AomEncoder {
 
 impl AomEncoder {
     pub fn encode(&mut self, ms: i64, data: &[u8], stride_align: usize) -> Result<EncodeFrames> {
-        let bpp = if self.i444 { 24 } else { 12 };
-        if data.len() < self.width * self.height * bpp / 8 {
+        if data.len() < self.width * self.height * self.yuvfmt.pixfmt.bpp() / 8 {
             return Err(Error::FailedCall("len not enough".to_string()));
         }
-        let fmt = if self.i444 {
-            aom_img_fmt::AOM_IMG_FMT_I444
-        } else {
-            aom_img_fmt::AOM_IMG_FMT_I420
-        };
+        let fmt = Self::img_fmt(self.i444, self.hdr);
 
         let mut image = Default::default();
         call_aom_ptr!(aom_img_wrap(
@@ -309,6 +437,11 @@ impl AomEncoder {
         ));
         let pts = webrtc::kTimeBaseDen / 1000 * ms;
         let duration = webrtc::kTimeBaseDen / 1000;
+        if self.temporal_layers > 1 {
+            webrtc::set_svc_frame(&mut self.ctx, self.temporal_layers, self.frame_index)
+                .map_err(|e| Error::FailedCall(e.to_string()))?;
+            self.frame_index = self.frame_index.wrapping_add(1);
+        }
         call_aom!(aom_codec_encode(
             &mut self.ctx,
             &image,
@@ -369,13 +502,19 @@ impl AomEncoder {
         (q_min, q_max)
     }
 
-    fn get_yuvfmt(width: u32, height: u32, i444: bool) -> EncodeYuvFormat {
-        let mut img = Default::default();
-        let fmt = if i444 {
+    fn img_fmt(i444: bool, hdr: bool) -> aom_img_fmt_t {
+        if i444 {
             aom_img_fmt::AOM_IMG_FMT_I444
+        } else if hdr {
+            aom_img_fmt::AOM_IMG_FMT_I42016
         } else {
             aom_img_fmt::AOM_IMG_FMT_I420
-        };
+        }
+    }
+
+    fn get_yuvfmt(width: u32, height: u32, i444: bool, hdr: bool) -> EncodeYuvFormat {
+        let mut img = Default::default();
+        let fmt = Self::img_fmt(i444, hdr);
         unsafe {
             aom_img_wrap(
                 &mut img,
@@ -386,7 +525,13 @@ impl AomEncoder {
                 0x1 as _,
             );
         }
-        let pixfmt = if i444 { Pixfmt::I444 } else { Pixfmt::I420 };
+        let pixfmt = if i444 {
+            Pixfmt::I444
+        } else if hdr {
+            Pixfmt::I010
+        } else {
+            Pixfmt::I420
+        };
         EncodeYuvFormat {
             pixfmt,
             w: img.w as _,
@@ -518,6 +663,76 @@ impl<'a> Iterator for DecodeFrames<'a> {
     }
 }
 
+/// The temporal layer id of an AV1 temporal unit, from the first OBU with an extension header.
+/// Streams without temporal layers have no extension headers, so 0.
+pub fn temporal_id(data: &[u8]) -> u8 {
+    let mut d = data;
+    while let Some(&header) = d.first() {
+        let has_extension = header & 0x04 != 0;
+        let has_size = header & 0x02 != 0;
+        if has_extension {
+            return d.get(1).map(|x| x >> 5).unwrap_or_default();
+        }
+        if !has_size {
+            break;
+        }
+        // leb128 obu_size
+        let mut size = 0usize;
+        let mut pos = 1;
+        loop {
+            let Some(&b) = d.get(pos) else {
+                return 0;
+            };
+            size |= ((b & 0x7f) as usize) << (7 * (pos - 1));
+            pos += 1;
+            if b & 0x80 == 0 || pos > 8 {
+                break;
+            }
+        }
+        let Some(rest) = pos.checked_add(size).and_then(|end| d.get(end..)) else {
+            break;
+        };
+        d = rest;
+    }
+    0
+}
+
+/// Drops the highest temporal layers of an SVC stream while the receiver is congested,
+/// instead of letting the queue overflow and asking for a keyframe. The limit only changes
+/// on base layer frames, from where the higher layers never reference dropped frames.
+pub struct TemporalLayerFilter {
+    highest: u8,
+    limit: u8,
+}
+
+impl Default for TemporalLayerFilter {
+    fn default() -> Self {
+        Self {
+            highest: 0,
+            limit: u8::MAX,
+        }
+    }
+}
+
+impl TemporalLayerFilter {
+    /// Whether to decode the frame of `temporal_id`.
+    pub fn keep(&mut self, temporal_id: u8, congested: bool) -> bool {
+        self.highest = self.highest.max(temporal_id);
+        if temporal_id == 0 {
+            if congested {
+                let limit = self.limit.min(self.highest).saturating_sub(1);
+                if limit != self.limit {
+                    log::debug!("Drop temporal layers above {}", limit);
+                }
+                self.limit = limit;
+            } else if self.limit < self.highest {
+                self.limit += 1;
+            }
+        }
+        temporal_id <= self.limit
+    }
+}
+
 pub struct Image(*mut aom_image_t);
 impl Image {
     #[inline]
@@ -539,6 +754,40 @@ impl Image {
     pub fn inner(&self) -> &aom_image_t {
         unsafe { &*self.0 }
     }
+
+    /// 10-bit PQ frame of an HDR stream, which `to` can't convert.
+    pub fn is_hdr(&self) -> bool {
+        let img = self.inner();
+        img.fmt == aom_img_fmt::AOM_IMG_FMT_I42016
+            && img.tc == aom_transfer_characteristics::AOM_CICP_TC_SMPTE_2084
+    }
+
+    // rgb [in/out] fmt and stride must be set in ImageRgb
+    pub fn to_sdr(&self, rgb: &mut ImageRgb) -> ResultType<()> {
+        let bgra = match rgb.fmt() {
+            ImageFormat::ARGB => true,
+            ImageFormat::ABGR => false,
+            ImageFormat::Raw => bail!("unsupported HDR output format"),
+        };
+        let img = self.inner();
+        rgb.w = self.width();
+        rgb.h = self.height();
+        let bytes_per_row = Self::get_bytes_per_row(rgb.w, rgb.fmt, rgb.align());
+        rgb.raw.resize(rgb.h * bytes_per_row, 0);
+        let chroma_h = (rgb.h + 1) / 2;
+        let plane = |i: usize, h: usize| crate::hdr::Plane16 {
+            data: unsafe { slice::from_raw_parts(img.planes[i], img.stride[i] as usize * h) },
+            stride: img.stride[i] as _,
+        };
+        crate::hdr::i010_pq_to_rgb(
+            [plane(0, rgb.h), plane(1, chroma_h), plane(2, chroma_h)],
+            rgb.w,
+            rgb.h,
+            bgra,
+            &mut rgb.raw,
+            bytes_per_row,
+        )
+    }
 }
 
 impl GoogleImage for Image {
@@ -579,3 +828,52 @@ impl Drop for Image {
 }
 
 unsafe impl Send for aom_codec_ctx_t {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_svc_pattern() {
+        let ids: Vec<_> = (0..4).map(|i| webrtc::svc_pattern(3, i).0).collect();
+        assert_eq!(ids, vec![0, 2, 1, 2]);
+        let ids: Vec<_> = (0..4).map(|i| webrtc::svc_pattern(2, i).0).collect();
+        assert_eq!(ids, vec![0, 1, 0, 1]);
+        // A frame only references slots refreshed by lower or equal layers.
+        for layers in [2, 3] {
+            for i in 0..4 {
+                let (id, reference, _) = webrtc::svc_pattern(layers, i);
+                let refreshed_by = (0..4)
+                    .filter_map(|j| {
+                        let (id, _, refresh) = webrtc::svc_pattern(layers, j);
+                        (refresh == Some(reference)).then_some(id)
+                    })
+                    .max()
+                    .unwrap();
+                assert!(refreshed_by <= id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_temporal_layers() {
+        // temporal delimiter without extension, then a frame OBU of temporal id 2
+        assert_eq!(temporal_id(&[0x12, 0x00, 0x36, 0x40, 0x01, 0xff]), 2);
+        assert_eq!(temporal_id(&[0x12, 0x00, 0x32, 0x01, 0xff]), 0);
+        assert_eq!(temporal_id(&[0x12, 0x05]), 0);
+
+        let mut filter = TemporalLayerFilter::default();
+        let pattern = [0, 2, 1, 2];
+        assert!(pattern.iter().all(|&id| filter.keep(id, false)));
+        assert!(filter.keep(0, true));
+        assert!(filter.keep(1, true));
+        assert!(!filter.keep(2, true));
+        assert!(filter.keep(0, true));
+        assert!(!filter.keep(1, false));
+        assert!(filter.keep(0, false));
+        assert!(filter.keep(1, false));
+        assert!(!filter.keep(2, false));
+        assert!(filter.keep(0, false));
+        assert!(filter.keep(2, false));
+    }
+}
@@ -80,6 +80,10 @@ pub trait EncoderApi {
     fn is_hardware(&self) -> bool;
 
     fn disable(&self);
+
+    // The cursor position in the frame, around which the encoder spends more bits if it supports
+    // region of interest.
+    fn set_cursor_pos(&mut self, _pos: Option<(usize, usize)>) {}
 }
 
 pub struct Encoder {
@@ -108,6 +112,8 @@ pub struct Decoder {
     h264_ram: Option<HwRamDecoder>,
     #[cfg(feature = "hwcodec")]
     h265_ram: Option<HwRamDecoder>,
+    #[cfg(feature = "hwcodec")]
+    av1_ram: Option<HwRamDecoder>,
     #[cfg(feature = "vram")]
     h264_vram: Option<VRamDecoder>,
     #[cfg(feature = "vram")]
@@ -502,7 +508,7 @@ impl Decoder {
         log::info!("try create new decoder, format: {format:?}, _luid: {_luid:?}");
         let (mut vp8, mut vp9, mut av1) = (None, None, None);
         #[cfg(feature = "hwcodec")]
-        let (mut h264_ram, mut h265_ram) = (None, None);
+        let (mut h264_ram, mut h265_ram, mut av1_ram) = (None, None, None);
         #[cfg(feature = "vram")]
         let (mut h264_vram, mut h265_vram) = (None, None);
         #[cfg(feature = "mediacodec")]
@@ -529,11 +535,21 @@ impl Decoder {
                 valid = vp9.is_some();
             }
             CodecFormat::AV1 => {
-                match AomDecoder::new() {
-                    Ok(v) => av1 = Some(v),
-                    Err(e) => log::error!("create AV1 decoder failed: {}", e),
+                #[cfg(feature = "hwcodec")]
+                if HwRamDecoder::try_get(format).is_some() {
+                    match HwRamDecoder::new(format) {
+                        Ok(v) => av1_ram = Some(v),
+                        Err(e) => log::error!("create AV1 ram decoder failed: {}", e),
+                    }
+                    valid = av1_ram.is_some();
+                }
+                if !valid {
+                    match AomDecoder::new() {
+                        Ok(v) => av1 = Some(v),
+                        Err(e) => log::error!("create AV1 decoder failed: {}", e),
+                    }
+                    valid = av1.is_some();
                 }
-                valid = av1.is_some();
             }
             CodecFormat::H264 => {
                 #[cfg(feature = "vram")]
@@ -604,6 +620,8 @@ impl Decoder {
             h264_ram,
             #[cfg(feature = "hwcodec")]
             h265_ram,
+            #[cfg(feature = "hwcodec")]
+            av1_ram,
             #[cfg(feature = "vram")]
             h264_vram,
             #[cfg(feature = "vram")]
@@ -652,6 +670,21 @@ impl Decoder {
                 }
             }
             video_frame::Union::Av1s(av1s) => {
+                #[cfg(feature = "hwcodec")]
+                if let Some(decoder) = &mut self.av1_ram {
+                    match Decoder::handle_hwram_video_frame(decoder, av1s, rgb, &mut self.i420) {
+                        Ok(v) => {
+                            *chroma = Some(Chroma::I420);
+                            return Ok(v);
+                        }
+                        Err(e) => {
+                            // e.g. I444, which hardware decoders rarely support
+                            log::error!("AV1 ram decoder failed, fallback to software: {}", e);
+                            self.av1_ram = None;
+                            self.av1 = AomDecoder::new().ok();
+                        }
+                    }
+                }
                 if let Some(av1) = &mut self.av1 {
                     Decoder::handle_av1s_video_frame(av1, av1s, rgb, chroma)
                 } else {
@@ -755,6 +788,10 @@ impl Decoder {
         }
         if last_frame.is_null() {
             Ok(false)
+        } else if last_frame.is_hdr() {
+            *chroma = Some(Chroma::I420);
+            last_frame.to_sdr(rgb)?;
+            Ok(true)
         } else {
             *chroma = Some(last_frame.chroma());
             last_frame.to(rgb);
@@ -1107,6 +1144,8 @@ pub fn test_av1() {
                     height,
                     quality,
                     keyframe_interval,
+                    temporal_layers: 1,
+                    hdr: false,
                 }),
                 i444,
             ) else {
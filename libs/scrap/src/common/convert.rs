@@ -13,6 +13,46 @@ use hbb_common::{bail, log, ResultType};
 
 generate_call_macro!(call_yuv, false);
 
+// Frames from this size are converted by bands of rows on several threads,
+// a 4K frame takes too long for one core at high fps.
+const PARALLEL_MIN_PIXELS: usize = 2560 * 1440;
+const PARALLEL_MAX_THREADS: usize = 4;
+
+fn convert_thread_num(width: usize, height: usize) -> usize {
+    if width * height < PARALLEL_MIN_PIXELS {
+        return 1;
+    }
+    // Leave the cores to the encoders and the other displays.
+    (num_cpus::get() / 4).clamp(1, PARALLEL_MAX_THREADS)
+}
+
+// Calls `f(first_row, rows)` for each band, the first rows of the bands are even for the 4:2:0 planes.
+fn for_each_band<F>(width: usize, height: usize, f: F) -> ResultType<()>
+where
+    F: Fn(usize, usize) -> ResultType<()> + Sync,
+{
+    let threads = convert_thread_num(width, height);
+    let band = ((height + threads - 1) / threads + 1) & !1;
+    if threads <= 1 || band >= height {
+        return f(0, height);
+    }
+    std::thread::scope(|s| {
+        let f = &f;
+        let handles: Vec<_> = (band..height)
+            .step_by(band)
+            .map(|y| s.spawn(move || f(y, band.min(height - y))))
+            .collect();
+        let mut res = f(0, band);
+        for h in handles {
+            match h.join() {
+                Ok(r) => res = res.and(r),
+                Err(_) => bail!("convert thread panicked"),
+            }
+        }
+        res
+    })
+}
+
 #[cfg(not(target_os = "ios"))]
 pub fn convert_to_yuv(
     captured: &PixelBuffer,
@@ -20,9 +60,9 @@ pub fn convert_to_yuv(
     dst: &mut Vec<u8>,
     mid_data: &mut Vec<u8>,
 ) -> ResultType<()> {
-    let src = captured.data();
-    let src_stride = captured.stride();
-    let src_pixfmt = captured.pixfmt();
+    let mut src = captured.data();
+    let mut src_stride = captured.stride();
+    let mut src_pixfmt = captured.pixfmt();
     let src_width = captured.width();
     let src_height = captured.height();
     if src_width > dst_fmt.w || src_height > dst_fmt.h {
@@ -32,6 +72,23 @@ pub fn convert_to_yuv(
             dst_fmt.h
         );
     }
+    let mut tone_mapped = Vec::new();
+    if src_pixfmt == crate::Pixfmt::RGBA16F {
+        if dst_fmt.pixfmt == crate::Pixfmt::I010 {
+            return crate::hdr::scrgb_to_i010(
+                src,
+                src_stride[0],
+                src_width,
+                src_height,
+                &dst_fmt,
+                dst,
+            );
+        }
+        crate::hdr::scrgb_to_bgra(src, src_stride[0], src_width, src_height, &mut tone_mapped)?;
+        src = &tone_mapped;
+        src_stride = vec![src_width * 4];
+        src_pixfmt = crate::Pixfmt::BGRA;
+    }
     if src_pixfmt == crate::Pixfmt::BGRA
         || src_pixfmt == crate::Pixfmt::RGBA
         || src_pixfmt == crate::Pixfmt::RGB565LE
@@ -53,6 +110,13 @@ pub fn convert_to_yuv(
             );
         }
     }
+    if src_pixfmt == crate::Pixfmt::NV12 {
+        // The UV plane follows the Y plane.
+        let len = src_stride[0] * src_height + src_stride[1] * ((src_height + 1) / 2);
+        if src.len() < len {
+            bail!("wrong src len, {} < {}", src.len(), len);
+        }
+    }
     let align = |x: usize| (x + 63) / 64 * 64;
     let unsupported = format!(
         "unsupported pixfmt conversion: {src_pixfmt:?} -> {:?}",
@@ -66,27 +130,33 @@ pub fn convert_to_yuv(
             let dst_stride_y = dst_fmt.stride[0];
             let dst_stride_uv = dst_fmt.stride[1];
             dst.resize(dst_fmt.h * dst_stride_y * 2, 0); // waste some memory to ensure memory safety
-            let dst_y = dst.as_mut_ptr();
-            let dst_u = dst[dst_fmt.u..].as_mut_ptr();
-            let dst_v = dst[dst_fmt.v..].as_mut_ptr();
             let f = match src_pixfmt {
                 crate::Pixfmt::BGRA => ARGBToI420,
                 crate::Pixfmt::RGBA => ABGRToI420,
                 crate::Pixfmt::RGB565LE => RGB565ToI420,
                 _ => bail!(unsupported),
             };
-            call_yuv!(f(
-                src.as_ptr(),
-                src_stride[0] as _,
-                dst_y,
-                dst_stride_y as _,
-                dst_u,
-                dst_stride_uv as _,
-                dst_v,
-                dst_stride_uv as _,
-                src_width as _,
-                src_height as _,
-            ));
+            let (input, input_stride) = (src.as_ptr() as usize, src_stride[0]);
+            let (dst_y, dst_u, dst_v) = (
+                dst.as_mut_ptr() as usize,
+                dst[dst_fmt.u..].as_mut_ptr() as usize,
+                dst[dst_fmt.v..].as_mut_ptr() as usize,
+            );
+            for_each_band(src_width, src_height, |y, rows| {
+                call_yuv!(f(
+                    (input + y * input_stride) as _,
+                    input_stride as _,
+                    (dst_y + y * dst_stride_y) as _,
+                    dst_stride_y as _,
+                    (dst_u + y / 2 * dst_stride_uv) as _,
+                    dst_stride_uv as _,
+                    (dst_v + y / 2 * dst_stride_uv) as _,
+                    dst_stride_uv as _,
+                    src_width as _,
+                    rows as _,
+                ));
+                Ok(())
+            })?;
         }
         (crate::Pixfmt::BGRA, crate::Pixfmt::NV12)
         | (crate::Pixfmt::RGBA, crate::Pixfmt::NV12)
@@ -97,8 +167,6 @@ pub fn convert_to_yuv(
                 align(dst_fmt.h) * (align(dst_stride_y) + align(dst_stride_uv / 2)),
                 0,
             );
-            let dst_y = dst.as_mut_ptr();
-            let dst_uv = dst[dst_fmt.u..].as_mut_ptr();
             let (input, input_stride) = match src_pixfmt {
                 crate::Pixfmt::BGRA => (src.as_ptr(), src_stride[0]),
                 crate::Pixfmt::RGBA => (src.as_ptr(), src_stride[0]),
@@ -123,17 +191,64 @@ pub fn convert_to_yuv(
                 crate::Pixfmt::RGB565LE => ARGBToNV12,
                 _ => bail!(unsupported),
             };
-            call_yuv!(f(
-                input,
-                input_stride as _,
+            let input = input as usize;
+            let (dst_y, dst_uv) = (
+                dst.as_mut_ptr() as usize,
+                dst[dst_fmt.u..].as_mut_ptr() as usize,
+            );
+            for_each_band(src_width, src_height, |y, rows| {
+                call_yuv!(f(
+                    (input + y * input_stride) as _,
+                    input_stride as _,
+                    (dst_y + y * dst_stride_y) as _,
+                    dst_stride_y as _,
+                    (dst_uv + y / 2 * dst_stride_uv) as _,
+                    dst_stride_uv as _,
+                    src_width as _,
+                    rows as _,
+                ));
+                Ok(())
+            })?;
+        }
+        (crate::Pixfmt::NV12, crate::Pixfmt::I420) => {
+            let dst_stride_y = dst_fmt.stride[0];
+            let dst_stride_uv = dst_fmt.stride[1];
+            dst.resize(dst_fmt.h * dst_stride_y * 2, 0); // waste some memory to ensure memory safety
+            let dst_y = dst.as_mut_ptr();
+            let dst_u = dst[dst_fmt.u..].as_mut_ptr();
+            let dst_v = dst[dst_fmt.v..].as_mut_ptr();
+            call_yuv!(NV12ToI420(
+                src.as_ptr(),
+                src_stride[0] as _,
+                src[src_stride[0] * src_height..].as_ptr(),
+                src_stride[1] as _,
                 dst_y,
                 dst_stride_y as _,
-                dst_uv,
+                dst_u,
+                dst_stride_uv as _,
+                dst_v,
                 dst_stride_uv as _,
                 src_width as _,
                 src_height as _,
             ));
         }
+        (crate::Pixfmt::NV12, crate::Pixfmt::NV12) => {
+            let dst_stride_y = dst_fmt.stride[0];
+            let dst_stride_uv = dst_fmt.stride[1];
+            dst.resize(
+                align(dst_fmt.h) * (align(dst_stride_y) + align(dst_stride_uv / 2)),
+                0,
+            );
+            let src_uv = &src[src_stride[0] * src_height..];
+            for y in 0..src_height {
+                dst[y * dst_stride_y..][..src_width]
+                    .copy_from_slice(&src[y * src_stride[0]..][..src_width]);
+            }
+            for y in 0..(src_height + 1) / 2 {
+                dst[dst_fmt.u + y * dst_stride_uv..][..src_width]
+                    .copy_from_slice(&src_uv[y * src_stride[1]..][..src_width]);
+            }
+        }
         (crate::Pixfmt::BGRA, crate::Pixfmt::I444)
         | (crate::Pixfmt::RGBA, crate::Pixfmt::I444)
         | (crate::Pixfmt::RGB565LE, crate::Pixfmt::I444) => {
@@ -145,9 +260,6 @@ pub fn convert_to_yuv(
                     * (align(dst_stride_y) + align(dst_stride_u) + align(dst_stride_v)),
                 0,
             );
-            let dst_y = dst.as_mut_ptr();
-            let dst_u = dst[dst_fmt.u..].as_mut_ptr();
-            let dst_v = dst[dst_fmt.v..].as_mut_ptr();
             let (input, input_stride) = match src_pixfmt {
                 crate::Pixfmt::BGRA => (src.as_ptr(), src_stride[0]),
                 crate::Pixfmt::RGBA => {
@@ -178,18 +290,27 @@ pub fn convert_to_yuv(
                 _ => bail!(unsupported),
             };
 
-            call_yuv!(ARGBToI444(
-                input,
-                input_stride as _,
-                dst_y,
-                dst_stride_y as _,
-                dst_u,
-                dst_stride_u as _,
-                dst_v,
-                dst_stride_v as _,
-                src_width as _,
-                src_height as _,
-            ));
+            let input = input as usize;
+            let (dst_y, dst_u, dst_v) = (
+                dst.as_mut_ptr() as usize,
+                dst[dst_fmt.u..].as_mut_ptr() as usize,
+                dst[dst_fmt.v..].as_mut_ptr() as usize,
+            );
+            for_each_band(src_width, src_height, |y, rows| {
+                call_yuv!(ARGBToI444(
+                    (input + y * input_stride) as _,
+                    input_stride as _,
+                    (dst_y + y * dst_stride_y) as _,
+                    dst_stride_y as _,
+                    (dst_u + y * dst_stride_u) as _,
+                    dst_stride_u as _,
+                    (dst_v + y * dst_stride_v) as _,
+                    dst_stride_v as _,
+                    src_width as _,
+                    rows as _,
+                ));
+                Ok(())
+            })?;
         }
         _ => {
             bail!(unsupported);
@@ -228,6 +349,39 @@ pub fn convert(captured: &PixelBuffer, pixfmt: crate::Pixfmt, dst: &mut Vec<u8>)
                 src_height as _,
             ));
         }
+        (crate::Pixfmt::NV12, crate::Pixfmt::BGRA) | (crate::Pixfmt::NV12, crate::Pixfmt::RGBA) => {
+            dst.resize(src_width * src_height * 4, 0);
+            let f = match pixfmt {
+                crate::Pixfmt::BGRA => NV12ToARGB,
+                _ => NV12ToABGR,
+            };
+            call_yuv!(f(
+                src.as_ptr(),
+                src_stride[0] as _,
+                src[src_stride[0] * src_height..].as_ptr(),
+                src_stride[1] as _,
+                dst.as_mut_ptr(),
+                (src_width * 4) as _,
+                src_width as _,
+                src_height as _,
+            ));
+        }
+        (crate::Pixfmt::RGBA16F, crate::Pixfmt::BGRA) => {
+            crate::hdr::scrgb_to_bgra(src, src_stride[0], src_width, src_height, dst)?;
+        }
+        (crate::Pixfmt::RGBA16F, crate::Pixfmt::RGBA) => {
+            let mut bgra = Vec::new();
+            crate::hdr::scrgb_to_bgra(src, src_stride[0], src_width, src_height, &mut bgra)?;
+            dst.resize(bgra.len(), 0);
+            call_yuv!(ABGRToARGB(
+                bgra.as_ptr(),
+                (src_width * 4) as _,
+                dst.as_mut_ptr(),
+                (src_width * 4) as _,
+                src_width as _,
+                src_height as _,
+            ));
+        }
         _ => {
             bail!(unsupported);
         }
@@ -57,6 +57,10 @@ impl TraitCapturer for Capturer {
         self.inner.set_gdi()
     }
 
+    fn is_hdr(&self) -> bool {
+        self.inner.is_hdr()
+    }
+
     #[cfg(feature = "vram")]
     fn device(&self) -> AdapterDevice {
         self.inner.device()
@@ -66,6 +70,10 @@ impl TraitCapturer for Capturer {
     fn set_output_texture(&mut self, texture: bool) {
         self.inner.set_output_texture(texture);
     }
+
+    fn set_output_nv12(&mut self, nv12: bool) -> bool {
+        self.inner.set_output_nv12(nv12)
+    }
 }
 
 pub struct PixelBuffer<'a> {
@@ -90,6 +98,22 @@ impl<'a> PixelBuffer<'a> {
         }
     }
 
+    pub fn with_stride(
+        data: &'a [u8],
+        pixfmt: Pixfmt,
+        width: usize,
+        height: usize,
+        stride: Vec<usize>,
+    ) -> Self {
+        PixelBuffer {
+            data,
+            pixfmt,
+            width,
+            height,
+            stride,
+        }
+    }
+
     #[allow(non_snake_case)]
     pub fn with_BGRA(data: &'a [u8], width: usize, height: usize) -> Self {
         Self::new(data, Pixfmt::BGRA, width, height)
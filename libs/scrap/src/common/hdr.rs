@@ -0,0 +1,378 @@
+//! HDR conversions.
+//!
+//! Windows captures HDR displays as FP16 scRGB, linear light with BT.709 primaries where 1.0 is
+//! 80 nits. Frames are either encoded as 10-bit PQ BT.2020 YUV (I010) for receivers that
+//! negotiated HDR, or tone-mapped to 8-bit sRGB on the sender, so SDR receivers don't see
+//! washed-out colors. Receivers of HDR streams tone-map the decoded frames to sRGB for display,
+//! as the UI renders 8-bit.
+//!
+//! The tone mapping is for desktops, mostly SDR content placed at the SDR white level: colors up
+//! to SDR white are kept as is and brighter highlights are scaled into white keeping their hue.
+
+use crate::EncodeYuvFormat;
+use hbb_common::{bail, ResultType};
+
+/// Nits of 1.0 in scRGB.
+pub const SCRGB_NITS: f32 = 80.0;
+/// Reference white of BT.2408, where SDR white is placed in HDR.
+pub const SDR_WHITE_NITS: f32 = 203.0;
+const PQ_MAX_NITS: f32 = 10000.0;
+const SRGB_LUT_SIZE: usize = 4096;
+const PQ_LUT_SIZE: usize = 8192;
+
+const BT709_TO_BT2020: [[f32; 3]; 3] = [
+    [0.6274, 0.3293, 0.0433],
+    [0.0691, 0.9195, 0.0114],
+    [0.0164, 0.0880, 0.8956],
+];
+const BT2020_TO_BT709: [[f32; 3]; 3] = [
+    [1.6605, -0.5876, -0.0728],
+    [-0.1246, 1.1329, -0.0083],
+    [-0.0182, -0.1006, 1.1187],
+];
+// BT.2020 non-constant luminance
+const KR: f32 = 0.2627;
+const KB: f32 = 0.0593;
+const KG: f32 = 1.0 - KR - KB;
+
+lazy_static::lazy_static! {
+    static ref F16_LUT: Vec<f32> = (0..=u16::MAX).map(f16_to_f32).collect();
+    // linear [0, 1] -> sRGB 8-bit
+    static ref SRGB_LUT: Vec<u8> = (0..SRGB_LUT_SIZE)
+        .map(|i| (srgb_oetf(i as f32 / (SRGB_LUT_SIZE - 1) as f32) * 255.0).round() as u8)
+        .collect();
+    // sqrt(nits / 10000) -> PQ [0, 1]
+    static ref PQ_LUT: Vec<f32> = (0..PQ_LUT_SIZE)
+        .map(|i| {
+            let x = i as f32 / (PQ_LUT_SIZE - 1) as f32;
+            pq_inverse_eotf(x * x * PQ_MAX_NITS)
+        })
+        .collect();
+    // 10-bit PQ code -> nits
+    static ref PQ_EOTF_LUT: Vec<f32> = (0..1024).map(|i| pq_eotf(i as f32 / 1023.0)).collect();
+}
+
+fn f16_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = ((h >> 10) & 0x1f) as i32;
+    let mant = (h & 0x3ff) as f32;
+    match exp {
+        0 => sign * mant * 2f32.powi(-24),
+        0x1f => {
+            if mant == 0.0 {
+                sign * f32::INFINITY
+            } else {
+                f32::NAN
+            }
+        }
+        _ => sign * (1.0 + mant / 1024.0) * 2f32.powi(exp - 15),
+    }
+}
+
+/// SMPTE ST 2084, PQ [0, 1] -> nits.
+pub fn pq_eotf(v: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+    let p = v.clamp(0.0, 1.0).powf(1.0 / M2);
+    let l = ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1);
+    l * PQ_MAX_NITS
+}
+
+/// SMPTE ST 2084, nits -> PQ [0, 1].
+pub fn pq_inverse_eotf(nits: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+    let l = (nits / PQ_MAX_NITS).clamp(0.0, 1.0).powf(M1);
+    ((C1 + C2 * l) / (1.0 + C3 * l)).powf(M2)
+}
+
+fn srgb_oetf(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[inline]
+fn mul(m: &[[f32; 3]; 3], c: [f32; 3]) -> [f32; 3] {
+    [
+        m[0][0] * c[0] + m[0][1] * c[1] + m[0][2] * c[2],
+        m[1][0] * c[0] + m[1][1] * c[1] + m[1][2] * c[2],
+        m[2][0] * c[0] + m[2][1] * c[1] + m[2][2] * c[2],
+    ]
+}
+
+/// Maps linear light relative to SDR white into [0, 1], scaling the highlights by their max
+/// channel instead of clipping each channel, which would shift their hue. Out of gamut colors
+/// with negative components are clipped.
+pub fn tone_map(c: [f32; 3]) -> [f32; 3] {
+    let c = [c[0].max(0.0), c[1].max(0.0), c[2].max(0.0)];
+    let m = c[0].max(c[1]).max(c[2]);
+    if m <= 1.0 {
+        return c;
+    }
+    [c[0] / m, c[1] / m, c[2] / m]
+}
+
+#[inline]
+fn srgb8(v: f32) -> u8 {
+    SRGB_LUT[(v.clamp(0.0, 1.0) * (SRGB_LUT_SIZE - 1) as f32) as usize]
+}
+
+#[inline]
+fn pq(nits: f32) -> f32 {
+    let x = (nits.max(0.0) / PQ_MAX_NITS).sqrt().min(1.0);
+    PQ_LUT[(x * (PQ_LUT_SIZE - 1) as f32) as usize]
+}
+
+#[inline]
+fn read_scrgb(src: &[u8], offset: usize) -> [f32; 3] {
+    let v = |i: usize| F16_LUT[u16::from_le_bytes([src[offset + i], src[offset + i + 1]]) as usize];
+    [v(0), v(2), v(4)]
+}
+
+fn check_src(src: &[u8], stride: usize, width: usize, height: usize) -> ResultType<()> {
+    if stride < width * 8 || src.len() < stride * height {
+        bail!("wrong scRGB buffer, len: {}, stride: {}", src.len(), stride);
+    }
+    Ok(())
+}
+
+/// FP16 scRGB (R16G16B16A16_FLOAT) -> tone-mapped BGRA.
+pub fn scrgb_to_bgra(
+    src: &[u8],
+    src_stride: usize,
+    width: usize,
+    height: usize,
+    dst: &mut Vec<u8>,
+) -> ResultType<()> {
+    check_src(src, src_stride, width, height)?;
+    dst.resize(width * height * 4, 0);
+    let scale = SCRGB_NITS / SDR_WHITE_NITS;
+    for y in 0..height {
+        for x in 0..width {
+            let c = read_scrgb(src, y * src_stride + x * 8);
+            let c = tone_map([c[0] * scale, c[1] * scale, c[2] * scale]);
+            let o = (y * width + x) * 4;
+            dst[o] = srgb8(c[2]);
+            dst[o + 1] = srgb8(c[1]);
+            dst[o + 2] = srgb8(c[0]);
+            dst[o + 3] = 255;
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+fn write_u16(dst: &mut [u8], offset: usize, v: f32, max: f32) {
+    let v = (v.round().clamp(0.0, max) as u16).to_le_bytes();
+    dst[offset] = v[0];
+    dst[offset + 1] = v[1];
+}
+
+/// FP16 scRGB -> 10-bit PQ BT.2020 limited range I420 with 16-bit samples, as `dst_fmt`.
+pub fn scrgb_to_i010(
+    src: &[u8],
+    src_stride: usize,
+    width: usize,
+    height: usize,
+    dst_fmt: &EncodeYuvFormat,
+    dst: &mut Vec<u8>,
+) -> ResultType<()> {
+    check_src(src, src_stride, width, height)?;
+    if dst_fmt.stride.len() < 3 || dst_fmt.stride[0] < width * 2 {
+        bail!("wrong I010 format: {:?}", dst_fmt);
+    }
+    let (stride_y, stride_u, stride_v) = (dst_fmt.stride[0], dst_fmt.stride[1], dst_fmt.stride[2]);
+    let chroma_h = (dst_fmt.h + 1) / 2;
+    dst.resize(
+        (dst_fmt.u + stride_u * chroma_h).max(dst_fmt.v + stride_v * chroma_h),
+        0,
+    );
+    let pq_rgb = |x: usize, y: usize| {
+        let c = read_scrgb(src, y * src_stride + x * 8);
+        let c = mul(
+            &BT709_TO_BT2020,
+            [c[0] * SCRGB_NITS, c[1] * SCRGB_NITS, c[2] * SCRGB_NITS],
+        );
+        [pq(c[0]), pq(c[1]), pq(c[2])]
+    };
+    for y in (0..height).step_by(2) {
+        for x in (0..width).step_by(2) {
+            let mut sum = [0.0f32; 3];
+            let mut n = 0.0;
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let (px, py) = (x + dx, y + dy);
+                if px >= width || py >= height {
+                    continue;
+                }
+                let c = pq_rgb(px, py);
+                let luma = KR * c[0] + KG * c[1] + KB * c[2];
+                write_u16(dst, py * stride_y + px * 2, 64.0 + 876.0 * luma, 1023.0);
+                sum = [sum[0] + c[0], sum[1] + c[1], sum[2] + c[2]];
+                n += 1.0;
+            }
+            let c = [sum[0] / n, sum[1] / n, sum[2] / n];
+            let luma = KR * c[0] + KG * c[1] + KB * c[2];
+            let cb = (c[2] - luma) / (2.0 * (1.0 - KB));
+            let cr = (c[0] - luma) / (2.0 * (1.0 - KR));
+            let (cx, cy) = (x / 2, y / 2);
+            write_u16(
+                dst,
+                dst_fmt.u + cy * stride_u + cx * 2,
+                512.0 + 896.0 * cb,
+                1023.0,
+            );
+            write_u16(
+                dst,
+                dst_fmt.v + cy * stride_v + cx * 2,
+                512.0 + 896.0 * cr,
+                1023.0,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// One plane of 16-bit samples.
+pub struct Plane16<'a> {
+    pub data: &'a [u8],
+    pub stride: usize,
+}
+
+impl Plane16<'_> {
+    #[inline]
+    fn get(&self, x: usize, y: usize) -> f32 {
+        let o = y * self.stride + x * 2;
+        u16::from_le_bytes([self.data[o], self.data[o + 1]]) as f32
+    }
+}
+
+/// 10-bit PQ BT.2020 limited range I420 -> tone-mapped 8-bit sRGB, `bgra` for ARGB in libyuv
+/// naming, RGBA otherwise.
+pub fn i010_pq_to_rgb(
+    planes: [Plane16; 3],
+    width: usize,
+    height: usize,
+    bgra: bool,
+    dst: &mut [u8],
+    dst_stride: usize,
+) -> ResultType<()> {
+    if planes[0].data.len() < planes[0].stride * height
+        || planes[1].data.len() < planes[1].stride * ((height + 1) / 2)
+        || planes[2].data.len() < planes[2].stride * ((height + 1) / 2)
+        || dst.len() < dst_stride * height
+        || dst_stride < width * 4
+    {
+        bail!("wrong I010 buffer");
+    }
+    let code = |v: f32| PQ_EOTF_LUT[(v.clamp(0.0, 1.0) * 1023.0).round() as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let luma = (planes[0].get(x, y) - 64.0) / 876.0;
+            let cb = (planes[1].get(x / 2, y / 2) - 512.0) / 896.0;
+            let cr = (planes[2].get(x / 2, y / 2) - 512.0) / 896.0;
+            let r = luma + 2.0 * (1.0 - KR) * cr;
+            let b = luma + 2.0 * (1.0 - KB) * cb;
+            let g = (luma - KR * r - KB * b) / KG;
+            let c = mul(&BT2020_TO_BT709, [code(r), code(g), code(b)]);
+            let c = tone_map([
+                c[0] / SDR_WHITE_NITS,
+                c[1] / SDR_WHITE_NITS,
+                c[2] / SDR_WHITE_NITS,
+            ]);
+            let o = y * dst_stride + x * 4;
+            let (first, last) = if bgra { (c[2], c[0]) } else { (c[0], c[2]) };
+            dst[o] = srgb8(first);
+            dst[o + 1] = srgb8(c[1]);
+            dst[o + 2] = srgb8(last);
+            dst[o + 3] = 255;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pq() {
+        for nits in [0.0, 0.1, 80.0, 203.0, 1000.0, 10000.0] {
+            assert!((pq_eotf(pq_inverse_eotf(nits)) - nits).abs() <= nits * 0.001 + 0.001);
+        }
+        // 100 nits is about 0.508 in PQ
+        assert!((pq_inverse_eotf(100.0) - 0.508).abs() < 0.001);
+        assert!((pq(1000.0) - pq_inverse_eotf(1000.0)).abs() < 0.002);
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+    }
+
+    #[test]
+    fn test_tone_map() {
+        assert_eq!(tone_map([0.5, 0.2, -0.1]), [0.5, 0.2, 0.0]);
+        assert_eq!(tone_map([1.0, 1.0, 1.0]), [1.0, 1.0, 1.0]);
+        let c = tone_map([5.0, 2.5, 0.0]);
+        assert!((c[0] - 1.0).abs() < 0.001);
+        assert!((c[1] / c[0] - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // SDR white in scRGB, 203 / 80
+        let white = 0x4113u16.to_le_bytes();
+        let one = 0x3c00u16.to_le_bytes();
+        let mut src = vec![];
+        for _ in 0..4 {
+            src.extend(white);
+            src.extend(white);
+            src.extend(white);
+            src.extend(one);
+        }
+        let mut bgra = vec![];
+        scrgb_to_bgra(&src, 16, 2, 2, &mut bgra).unwrap();
+        assert!(bgra[..3].iter().all(|&v| v >= 250));
+
+        let fmt = EncodeYuvFormat {
+            pixfmt: crate::Pixfmt::I010,
+            w: 2,
+            h: 2,
+            stride: vec![4, 2, 2],
+            u: 8,
+            v: 10,
+        };
+        let mut yuv = vec![];
+        scrgb_to_i010(&src, 16, 2, 2, &fmt, &mut yuv).unwrap();
+        let mut rgb = vec![0u8; 16];
+        i010_pq_to_rgb(
+            [
+                Plane16 {
+                    data: &yuv[..8],
+                    stride: 4,
+                },
+                Plane16 {
+                    data: &yuv[8..10],
+                    stride: 2,
+                },
+                Plane16 {
+                    data: &yuv[10..12],
+                    stride: 2,
+                },
+            ],
+            2,
+            2,
+            true,
+            &mut rgb,
+            8,
+        )
+        .unwrap();
+        assert!(rgb[..3].iter().all(|&v| v >= 245), "{:?}", rgb);
+    }
+}
@@ -130,6 +130,7 @@ impl EncoderApi for HwRamEncoder {
             match self.format {
                 DataFormat::H264 => vf.set_h264s(frames),
                 DataFormat::H265 => vf.set_h265s(frames),
+                DataFormat::AV1 => vf.set_av1s(frames),
                 _ => bail!("unsupported format: {:?}", self.format),
             }
             Ok(vf)
@@ -224,6 +225,12 @@ impl HwRamEncoder {
                     info = Some(v);
                 }
             }
+            CodecFormat::AV1 => {
+                info = HwCodecConfig::get()
+                    .ram_encode
+                    .into_iter()
+                    .find(|c| matches!(c.format, DataFormat::AV1));
+            }
             _ => {}
         }
         info
@@ -339,6 +346,13 @@ impl HwRamDecoder {
                         info = Some(v);
                     }
                 }
+                // No software fallback here, libaom decodes AV1 in software.
+                CodecFormat::AV1 => {
+                    info = HwCodecConfig::get()
+                        .ram_decode
+                        .into_iter()
+                        .find(|c| matches!(c.format, DataFormat::AV1));
+                }
                 _ => {}
             }
         }
@@ -701,8 +715,9 @@ pub fn check_available_hwcodec() -> String {
     let vram_string = vram.2;
     #[cfg(not(feature = "vram"))]
     let vram_string = "".to_owned();
-    let c = HwCodecConfig {
-        ram_encode: Encoder::available_encoders(ctx, Some(vram_string)),
+    #[allow(unused_mut)]
+    let mut c = HwCodecConfig {
+        ram_encode: Encoder::available_encoders(ctx.clone(), Some(vram_string)),
         ram_decode: Decoder::available_decoders(),
         #[cfg(feature = "vram")]
         vram_encode: vram.0,
@@ -710,6 +725,12 @@ pub fn check_available_hwcodec() -> String {
         vram_decode: vram.1,
         signature: hwcodec::common::get_gpu_signature(),
     };
+    #[cfg(target_os = "linux")]
+    {
+        let (encode, decode) = vaapi::probe_av1(ctx);
+        c.ram_encode.extend(encode);
+        c.ram_decode.extend(decode);
+    }
     log::debug!("{c:?}");
     serde_json::to_string(&c).unwrap_or_default()
 }
@@ -761,3 +782,100 @@ pub fn start_check_process() {
         std::thread::spawn(f);
     });
 }
+
+// AV1 is not probed by hwcodec, VAAPI supports it on recent GPUs, e.g. Intel Arc and
+// Meteor Lake, AMD RDNA3 for encoding and more for decoding.
+#[cfg(target_os = "linux")]
+mod vaapi {
+    use super::*;
+    use crate::{aom::AomEncoder, codec::EncoderApi, STRIDE_ALIGN};
+    use hwcodec::ffmpeg::AVHWDeviceType;
+
+    const AV1_ENCODER: &str = "av1_vaapi";
+    const AV1_DECODER: &str = "av1";
+
+    fn has_render_node() -> bool {
+        std::fs::read_dir("/dev/dri")
+            .map(|d| {
+                d.flatten()
+                    .any(|e| e.file_name().to_string_lossy().starts_with("renderD"))
+            })
+            .unwrap_or(false)
+    }
+
+    fn info(name: &str) -> CodecInfo {
+        CodecInfo {
+            name: name.to_owned(),
+            mc_name: None,
+            format: DataFormat::AV1,
+            hwdevice: AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+            priority: 0,
+        }
+    }
+
+    fn probe_encoder(mut ctx: EncodeContext) -> bool {
+        ctx.name = AV1_ENCODER.to_owned();
+        let Ok((_, _, len)) = ffmpeg_linesize_offset_length(
+            ctx.pixfmt,
+            ctx.width as _,
+            ctx.height as _,
+            HW_STRIDE_ALIGN,
+        ) else {
+            return false;
+        };
+        let Ok(mut encoder) = Encoder::new(ctx) else {
+            return false;
+        };
+        let yuv = vec![0u8; len as usize];
+        matches!(encoder.encode(&yuv, 0), Ok(frames) if !frames.is_empty())
+    }
+
+    // A keyframe of libaom, so decoders that can open the device but not decode AV1 are not used.
+    fn sample_frame() -> Option<Vec<u8>> {
+        let mut encoder = AomEncoder::new(
+            EncoderCfg::AOM(crate::aom::AomEncoderConfig {
+                width: 320,
+                height: 240,
+                quality: 1.0,
+                keyframe_interval: None,
+                temporal_layers: 1,
+                hdr: false,
+            }),
+            false,
+        )
+        .ok()?;
+        let fmt = encoder.yuvfmt();
+        let yuv = vec![0u8; fmt.v + fmt.stride[2] * (fmt.h + 1) / 2];
+        let frames = encoder.encode(0, &yuv, STRIDE_ALIGN).ok()?;
+        let data = frames.map(|f| f.data.to_vec()).collect::<Vec<_>>().concat();
+        (!data.is_empty()).then_some(data)
+    }
+
+    fn probe_decoder() -> bool {
+        let Some(sample) = sample_frame() else {
+            return false;
+        };
+        let Ok(mut decoder) = Decoder::new(DecodeContext {
+            name: AV1_DECODER.to_owned(),
+            device_type: AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+            thread_count: 4,
+        }) else {
+            return false;
+        };
+        matches!(decoder.decode(&sample), Ok(frames) if !frames.is_empty())
+    }
+
+    pub(super) fn probe_av1(ctx: EncodeContext) -> (Option<CodecInfo>, Option<CodecInfo>) {
+        if !has_render_node() {
+            return (None, None);
+        }
+        let encode = probe_encoder(ctx).then(|| info(AV1_ENCODER));
+        let decode = probe_decoder().then(|| info(AV1_DECODER));
+        log::info!(
+            "vaapi av1, encode: {}, decode: {}",
+            encode.is_some(),
+            decode.is_some()
+        );
+        (encode, decode)
+    }
+}
@@ -37,10 +37,12 @@ cfg_if! {
 
 pub mod codec;
 pub mod convert;
+pub mod hdr;
 #[cfg(feature = "hwcodec")]
 pub mod hwcodec;
 #[cfg(feature = "mediacodec")]
 pub mod mediacodec;
+pub mod roi;
 pub mod vpxcodec;
 #[cfg(feature = "vram")]
 pub mod vram;
@@ -136,11 +138,23 @@ pub trait TraitCapturer {
     #[cfg(windows)]
     fn set_gdi(&mut self) -> bool;
 
+    // Captures HDR frames as Pixfmt::RGBA16F.
+    #[cfg(windows)]
+    fn is_hdr(&self) -> bool {
+        false
+    }
+
     #[cfg(feature = "vram")]
     fn device(&self) -> AdapterDevice;
 
     #[cfg(feature = "vram")]
     fn set_output_texture(&mut self, texture: bool);
+
+    // Outputs Pixfmt::NV12 frames converted on the GPU, returns false if it is not supported.
+    #[cfg(windows)]
+    fn set_output_nv12(&mut self, _nv12: bool) -> bool {
+        false
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -232,6 +246,10 @@ pub enum Pixfmt {
     I420,
     NV12,
     I444,
+    // FP16 scRGB of HDR displays
+    RGBA16F,
+    // 10-bit I420 with 16-bit little-endian samples
+    I010,
 }
 
 impl Pixfmt {
@@ -240,7 +258,8 @@ impl Pixfmt {
             Pixfmt::BGRA | Pixfmt::RGBA => 32,
             Pixfmt::RGB565LE => 16,
             Pixfmt::I420 | Pixfmt::NV12 => 12,
-            Pixfmt::I444 => 24,
+            Pixfmt::I444 | Pixfmt::I010 => 24,
+            Pixfmt::RGBA16F => 64,
         }
     }
 
@@ -13,10 +13,13 @@ use std::{
     io,
     ops::{Deref, DerefMut},
     path::PathBuf,
-    sync::mpsc::Sender,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
     time::Instant,
 };
-use webm::mux::{self, Segment, Track, VideoTrack, Writer};
+use webm::mux::{self, AudioTrack, Segment, Track, VideoTrack, Writer};
 
 const MIN_SECS: u64 = 1;
 
@@ -28,6 +31,54 @@ pub struct RecorderContext {
     pub display_idx: usize,
     pub camera: bool,
     pub tx: Option<Sender<RecordState>>,
+    // The audio muxed into the recording, only supported by webm.
+    pub audio: Option<Arc<Mutex<RecordAudio>>>,
+}
+
+/// The opus packets of the remote audio, shared by the recorders of all displays of a session.
+#[derive(Debug, Default)]
+pub struct RecordAudio {
+    // sample rate, channels
+    format: Option<(u32, u32)>,
+    senders: Vec<Sender<(Instant, Vec<u8>)>>,
+}
+
+impl RecordAudio {
+    pub fn set_format(&mut self, sample_rate: u32, channels: u32) {
+        if self.format != Some((sample_rate, channels)) {
+            self.format = Some((sample_rate, channels));
+            // The opus header of the current recordings does not match any more.
+            self.senders.clear();
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        if self.senders.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        self.senders
+            .retain(|tx| tx.send((now, data.to_vec())).is_ok());
+    }
+
+    fn subscribe(&mut self) -> Option<((u32, u32), Receiver<(Instant, Vec<u8>)>)> {
+        let format = self.format?;
+        let (tx, rx) = channel();
+        self.senders.push(tx);
+        Some((format, rx))
+    }
+}
+
+// https://datatracker.ietf.org/doc/html/rfc7845#section-5.1
+fn opus_head(sample_rate: u32, channels: u32) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1); // version
+    head.push(channels as _);
+    head.extend_from_slice(&0u16.to_le_bytes()); // pre-skip
+    head.extend_from_slice(&sample_rate.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(0); // channel mapping family
+    head
 }
 
 #[derive(Debug, Clone)]
@@ -272,6 +323,9 @@ impl Recorder {
 
 struct WebmRecorder {
     vt: VideoTrack,
+    audio: Option<(AudioTrack, Receiver<(Instant, Vec<u8>)>)>,
+    // pts and time of the first video frame, to put the audio on the timeline of the video.
+    first: Option<(i64, Instant)>,
     webm: Option<Segment<Writer<File>>>,
     ctx: RecorderContext,
     ctx2: RecorderContext2,
@@ -315,8 +369,28 @@ impl RecorderApi for WebmRecorder {
                 bail!("Failed to set codec private");
             }
         }
+        let mut audio = None;
+        if let Some(((sample_rate, channels), rx)) = ctx
+            .audio
+            .as_ref()
+            .and_then(|a| a.lock().unwrap().subscribe())
+        {
+            let at = webm.add_audio_track(
+                sample_rate as _,
+                channels as _,
+                None,
+                mux::AudioCodecId::Opus,
+            );
+            if webm.set_codec_private(at.track_number(), &opus_head(sample_rate, channels)) {
+                audio = Some((at, rx));
+            } else {
+                log::error!("Failed to set opus codec private, record without audio");
+            }
+        }
         Ok(WebmRecorder {
             vt,
+            audio,
+            first: None,
             webm: Some(webm),
             ctx,
             ctx2,
@@ -331,6 +405,10 @@ impl RecorderApi for WebmRecorder {
             self.key = true;
         }
         if self.key {
+            if self.first.is_none() {
+                self.first = Some((frame.pts, Instant::now()));
+            }
+            self.write_audio();
             let ok = self
                 .vt
                 .add_frame(&frame.data, frame.pts as u64 * 1_000_000, frame.key);
@@ -344,6 +422,22 @@ impl RecorderApi for WebmRecorder {
     }
 }
 
+impl WebmRecorder {
+    fn write_audio(&mut self) {
+        let (Some((at, rx)), Some((pts, start))) = (self.audio.as_mut(), self.first) else {
+            return;
+        };
+        while let Ok((time, data)) = rx.try_recv() {
+            // Drop the audio before the first video frame.
+            let Some(elapsed) = time.checked_duration_since(start) else {
+                continue;
+            };
+            let timestamp = pts as u64 * 1_000_000 + elapsed.as_nanos() as u64;
+            at.add_frame(&data, timestamp, true);
+        }
+    }
+}
+
 impl Drop for WebmRecorder {
     fn drop(&mut self) {
         let _ = std::mem::replace(&mut self.webm, None).map_or(false, |webm| webm.finalize(None));
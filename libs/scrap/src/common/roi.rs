@@ -0,0 +1,142 @@
+//! Region of interest maps for the encoders.
+//!
+//! Blocks around the cursor and blocks changed in the last frames are marked, so the encoder
+//! spends more bits on them, e.g. the text being typed stays sharp at low bitrates while the
+//! static background is coarser. Changes are found by comparing the luma plane with the last one.
+
+/// Segment of the blocks with more bits.
+pub const ROI_SEGMENT: u8 = 1;
+// Frames a changed block keeps more bits.
+const RECENT_FRAMES: u8 = 15;
+// Radius around the cursor in pixels.
+const CURSOR_RADIUS: usize = 96;
+// More than this share of blocks changed, e.g. scrolling or video, no region is more interesting.
+const MAX_ROI_PERCENT: usize = 40;
+
+pub struct RoiTracker {
+    width: usize,
+    height: usize,
+    block: usize,
+    cols: usize,
+    rows: usize,
+    last_y: Vec<u8>,
+    age: Vec<u8>,
+    map: Vec<u8>,
+}
+
+impl RoiTracker {
+    /// `block` is the size of a map entry in pixels, e.g. 8 for VP9 and 16 for VP8.
+    pub fn new(width: usize, height: usize, block: usize) -> Self {
+        let cols = (width + block - 1) / block;
+        let rows = (height + block - 1) / block;
+        Self {
+            width,
+            height,
+            block,
+            cols,
+            rows,
+            last_y: Vec::new(),
+            age: vec![0; cols * rows],
+            map: vec![0; cols * rows],
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Updates with the luma plane of the next frame, returns the segment of each block in
+    /// row-major order, or None if no region is worth more bits.
+    pub fn update(
+        &mut self,
+        y: &[u8],
+        stride: usize,
+        cursor: Option<(usize, usize)>,
+    ) -> Option<&[u8]> {
+        if stride < self.width || y.len() < stride * self.height {
+            return None;
+        }
+        let first = self.last_y.is_empty();
+        if first {
+            self.last_y = vec![0; self.width * self.height];
+        }
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let x = col * self.block;
+                let w = self.block.min(self.width - x);
+                let mut changed = false;
+                for py in row * self.block..((row + 1) * self.block).min(self.height) {
+                    let src = &y[py * stride + x..py * stride + x + w];
+                    let last = &mut self.last_y[py * self.width + x..py * self.width + x + w];
+                    if src != last {
+                        last.copy_from_slice(src);
+                        changed = true;
+                    }
+                }
+                let age = &mut self.age[row * self.cols + col];
+                if changed && !first {
+                    *age = RECENT_FRAMES;
+                } else {
+                    *age = age.saturating_sub(1);
+                }
+            }
+        }
+
+        let recent = self.age.iter().filter(|a| **a > 0).count();
+        if recent * 100 > self.age.len() * MAX_ROI_PERCENT {
+            return None;
+        }
+        for (m, a) in self.map.iter_mut().zip(self.age.iter()) {
+            *m = if *a > 0 { ROI_SEGMENT } else { 0 };
+        }
+        if let Some((cx, cy)) = cursor {
+            if cx < self.width && cy < self.height {
+                let r = CURSOR_RADIUS / self.block;
+                let (col, row) = (cx / self.block, cy / self.block);
+                for row in row.saturating_sub(r)..(row + r + 1).min(self.rows) {
+                    for col in col.saturating_sub(r)..(col + r + 1).min(self.cols) {
+                        self.map[row * self.cols + col] = ROI_SEGMENT;
+                    }
+                }
+            }
+        }
+        if self.map.iter().all(|m| *m == 0) {
+            None
+        } else {
+            Some(&self.map)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roi() {
+        let (w, h) = (64, 64);
+        let mut tracker = RoiTracker::new(w, h, 16);
+        let mut y = vec![0u8; w * h];
+        assert_eq!(tracker.update(&y, w, None), None);
+        // changes of a block
+        y[20 * w + 40] = 1;
+        let map = tracker.update(&y, w, None).unwrap().to_vec();
+        assert_eq!(map.iter().filter(|m| **m == ROI_SEGMENT).count(), 1);
+        assert_eq!(map[4 + 2], ROI_SEGMENT);
+        // kept for recent frames
+        for _ in 1..RECENT_FRAMES {
+            assert!(tracker.update(&y, w, None).is_some());
+        }
+        assert_eq!(tracker.update(&y, w, None), None);
+        // cursor
+        let map = tracker.update(&y, w, Some((0, 0))).unwrap();
+        assert_eq!(map[0], ROI_SEGMENT);
+        // too many changes
+        let y = vec![1u8; w * h];
+        assert_eq!(tracker.update(&y, w, None), None);
+    }
+}
@@ -9,6 +9,7 @@ use hbb_common::message_proto::{Chroma, EncodedVideoFrame, EncodedVideoFrames, V
 use hbb_common::ResultType;
 
 use crate::codec::{base_bitrate, codec_thread_num, EncoderApi};
+use crate::roi::{RoiTracker, ROI_SEGMENT};
 use crate::{EncodeInput, EncodeYuvFormat, GoogleImage, Pixfmt, STRIDE_ALIGN};
 
 use super::vpx::{vp8e_enc_control_id::*, vpx_codec_err_t::*, *};
@@ -20,6 +21,9 @@ use std::{ptr, slice};
 generate_call_macro!(call_vpx, false);
 generate_call_ptr_macro!(call_vpx_ptr);
 
+// Quantizer delta of the region of interest, in 0 - 63.
+const ROI_DELTA_Q: c_int = -12;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum VpxVideoCodecId {
     VP8,
@@ -39,6 +43,9 @@ pub struct VpxEncoder {
     id: VpxVideoCodecId,
     i444: bool,
     yuvfmt: EncodeYuvFormat,
+    roi: RoiTracker,
+    roi_enabled: bool,
+    cursor_pos: Option<(usize, usize)>,
 }
 
 pub struct VpxDecoder {
@@ -165,6 +172,18 @@ impl EncoderApi for VpxEncoder {
                     id: config.codec,
                     i444,
                     yuvfmt: Self::get_yuvfmt(config.width, config.height, i444),
+                    // VP8 maps macroblocks, VP9 maps 8x8 blocks.
+                    roi: RoiTracker::new(
+                        config.width as _,
+                        config.height as _,
+                        if config.codec == VpxVideoCodecId::VP8 {
+                            16
+                        } else {
+                            8
+                        },
+                    ),
+                    roi_enabled: false,
+                    cursor_pos: None,
                 })
             }
             _ => Err(anyhow!("encoder type mismatch")),
@@ -172,9 +191,13 @@ impl EncoderApi for VpxEncoder {
     }
 
     fn encode_to_message(&mut self, input: EncodeInput, ms: i64) -> ResultType<VideoFrame> {
+        let yuv = input.yuv()?;
+        if let Err(e) = self.set_roi(yuv) {
+            log::error!("Failed to set roi map: {e:?}");
+        }
         let mut frames = Vec::new();
         for ref frame in self
-            .encode(ms, input.yuv()?, STRIDE_ALIGN)
+            .encode(ms, yuv, STRIDE_ALIGN)
             .with_context(|| "Failed to encode")?
         {
             frames.push(VpxEncoder::create_frame(frame));
@@ -228,9 +251,39 @@ impl EncoderApi for VpxEncoder {
     }
 
     fn disable(&self) {}
+
+    fn set_cursor_pos(&mut self, pos: Option<(usize, usize)>) {
+        self.cursor_pos = pos;
+    }
 }
 
 impl VpxEncoder {
+    // The map is copied by libvpx. An all zero map disables it.
+    fn set_roi(&mut self, yuv: &[u8]) -> ResultType<()> {
+        let (cols, rows) = (self.roi.cols(), self.roi.rows());
+        let mut map = match self.roi.update(yuv, self.yuvfmt.stride[0], self.cursor_pos) {
+            Some(map) => map.to_vec(),
+            None if self.roi_enabled => vec![0; cols * rows],
+            None => return Ok(()),
+        };
+        self.roi_enabled = map.iter().any(|m| *m != 0);
+        let mut roi: vpx_roi_map_t = unsafe { std::mem::zeroed() };
+        roi.roi_map = map.as_mut_ptr();
+        roi.rows = rows as _;
+        roi.cols = cols as _;
+        if self.roi_enabled {
+            roi.delta_q[ROI_SEGMENT as usize] = ROI_DELTA_Q;
+        }
+        // -1 for no reference frame restriction
+        roi.ref_frame = [-1; 8];
+        call_vpx!(vpx_codec_control_(
+            &mut self.ctx,
+            VP8E_SET_ROI_MAP as _,
+            &mut roi as *mut vpx_roi_map_t
+        ));
+        Ok(())
+    }
+
     pub fn encode(&mut self, pts: i64, data: &[u8], stride_align: usize) -> Result<EncodeFrames> {
         let bpp = if self.i444 { 24 } else { 12 };
         if data.len() < self.width * self.height * bpp / 8 {
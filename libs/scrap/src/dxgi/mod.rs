@@ -7,6 +7,9 @@ use winapi::{
     shared::{
         dxgi::*,
         dxgi1_2::*,
+        dxgi1_5::IDXGIOutput5,
+        dxgi1_6::{IDXGIOutput6, DXGI_OUTPUT_DESC1},
+        dxgiformat::{DXGI_FORMAT, DXGI_FORMAT_NV12, DXGI_FORMAT_R16G16B16A16_FLOAT},
         dxgitype::*,
         minwindef::{DWORD, FALSE, TRUE, UINT},
         ntdef::LONG,
@@ -18,11 +21,12 @@ use winapi::{
         d3d11::*, d3dcommon::D3D_DRIVER_TYPE_UNKNOWN, unknwnbase::IUnknown, wingdi::*,
         winnt::HRESULT, winuser::*,
     },
+    Interface,
 };
 
 use crate::RotationMode::*;
 
-use crate::{AdapterDevice, Frame, PixelBuffer};
+use crate::{AdapterDevice, Frame, PixelBuffer, Pixfmt};
 use std::ffi::c_void;
 
 pub struct ComPtr<T>(*mut T);
@@ -58,6 +62,8 @@ pub struct Capturer {
     output_texture: bool,
     adapter_desc1: DXGI_ADAPTER_DESC1,
     rotate: Rotate,
+    hdr: bool,
+    nv12: Option<Nv12>,
 }
 
 impl Capturer {
@@ -70,6 +76,7 @@ impl Capturer {
         #[allow(invalid_value)]
         let mut adapter_desc1 = unsafe { mem::MaybeUninit::uninit().assume_init() };
         let mut gdi_capturer = None;
+        let mut hdr = false;
 
         let mut res = if display.gdi {
             wrap_hresult(1)
@@ -105,7 +112,15 @@ impl Capturer {
             }
         } else {
             res = wrap_hresult(unsafe {
-                let hres = (*display.inner.0).DuplicateOutput(device.0 as *mut _, &mut duplication);
+                // The rotation of FP16 frames is not supported, the OS converts them to SDR.
+                if display.is_hdr() && display.rotation() == DXGI_MODE_ROTATION_IDENTITY {
+                    hdr = display.duplicate_hdr(device.0, &mut duplication);
+                }
+                let hres = if hdr {
+                    S_OK
+                } else {
+                    (*display.inner.0).DuplicateOutput(device.0 as *mut _, &mut duplication)
+                };
                 if hres != S_OK {
                     gdi_capturer = display.create_gdi();
                     println!("Fallback to GDI");
@@ -174,6 +189,8 @@ impl Capturer {
             output_texture: false,
             adapter_desc1,
             rotate,
+            hdr,
+            nv12: None,
         })
     }
 
@@ -314,6 +331,11 @@ impl Capturer {
         self.gdi_capturer.is_some()
     }
 
+    // Frames are FP16 scRGB.
+    pub fn is_hdr(&self) -> bool {
+        self.hdr && !self.is_gdi()
+    }
+
     pub fn set_gdi(&mut self) -> bool {
         self.gdi_capturer = self.display.create_gdi();
         self.is_gdi()
@@ -329,6 +351,161 @@ impl Capturer {
         self.output_texture = texture;
     }
 
+    // Converts the frames to NV12 on the GPU before the readback, returns false if it is not available.
+    pub fn set_output_nv12(&mut self, nv12: bool) -> bool {
+        if !nv12 {
+            if let Some(nv12) = self.nv12.take() {
+                unsafe { nv12.unmap(self.context.0) };
+            }
+            return false;
+        }
+        if self.nv12.is_some() {
+            return true;
+        }
+        let rotated = !matches!(
+            self.display.rotation(),
+            DXGI_MODE_ROTATION_IDENTITY | DXGI_MODE_ROTATION_UNSPECIFIED
+        );
+        // The desktop image in system memory is already read back.
+        if self.is_gdi()
+            || self.is_hdr()
+            || self.fastlane
+            || rotated
+            || self.width % 2 != 0
+            || self.height % 2 != 0
+        {
+            return false;
+        }
+        match unsafe { self.create_nv12() } {
+            Ok(nv12) => self.nv12 = Some(nv12),
+            Err(e) => println!("Failed to create the NV12 video processor: {:?}", e),
+        }
+        self.nv12.is_some()
+    }
+
+    unsafe fn create_nv12(&self) -> io::Result<Nv12> {
+        if self.device.is_null() || self.context.is_null() {
+            return Err(std::io::ErrorKind::AddrNotAvailable.into());
+        }
+        let (width, height) = (self.width as UINT, self.height as UINT);
+        let mut video_context: *mut ID3D11VideoContext = ptr::null_mut();
+        wrap_hresult((*self.context.0).QueryInterface(
+            &IID_ID3D11VideoContext,
+            &mut video_context as *mut *mut _ as *mut *mut _,
+        ))?;
+        let video_context = ComPtr(video_context);
+        let mut video_device: *mut ID3D11VideoDevice = ptr::null_mut();
+        wrap_hresult((*self.device.0).QueryInterface(
+            &IID_ID3D11VideoDevice,
+            &mut video_device as *mut *mut _ as *mut *mut _,
+        ))?;
+        let video_device = ComPtr(video_device);
+        let content_desc = D3D11_VIDEO_PROCESSOR_CONTENT_DESC {
+            InputFrameFormat: D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+            InputFrameRate: DXGI_RATIONAL {
+                Numerator: 30,
+                Denominator: 1,
+            },
+            InputWidth: width,
+            InputHeight: height,
+            OutputFrameRate: DXGI_RATIONAL {
+                Numerator: 30,
+                Denominator: 1,
+            },
+            OutputWidth: width,
+            OutputHeight: height,
+            Usage: D3D11_VIDEO_USAGE_PLAYBACK_NORMAL,
+        };
+        let mut video_processor_enum = ptr::null_mut();
+        wrap_hresult(
+            (*video_device.0)
+                .CreateVideoProcessorEnumerator(&content_desc, &mut video_processor_enum),
+        )?;
+        let video_processor_enum = ComPtr(video_processor_enum);
+        let mut flags: UINT = 0;
+        wrap_hresult(
+            (*video_processor_enum.0).CheckVideoProcessorFormat(DXGI_FORMAT_NV12, &mut flags),
+        )?;
+        if flags & D3D11_VIDEO_PROCESSOR_FORMAT_SUPPORT_OUTPUT == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "NV12 output is not supported".to_string(),
+            ));
+        }
+        let mut video_processor = ptr::null_mut();
+        wrap_hresult((*video_device.0).CreateVideoProcessor(
+            video_processor_enum.0,
+            0,
+            &mut video_processor,
+        ))?;
+        let video_processor = ComPtr(video_processor);
+        (*video_context.0).VideoProcessorSetStreamAutoProcessingMode(video_processor.0, 0, FALSE);
+        (*video_context.0).VideoProcessorSetStreamFrameFormat(
+            video_processor.0,
+            0,
+            D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+        );
+        // Full range RGB to BT.601 limited range YUV, the same as ARGBToNV12 of libyuv.
+        let input_color_space: D3D11_VIDEO_PROCESSOR_COLOR_SPACE = mem::zeroed();
+        let mut output_color_space: D3D11_VIDEO_PROCESSOR_COLOR_SPACE = mem::zeroed();
+        output_color_space.set_Nominal_Range(D3D11_VIDEO_PROCESSOR_NOMINAL_RANGE_16_235);
+        (*video_context.0).VideoProcessorSetStreamColorSpace(
+            video_processor.0,
+            0,
+            &input_color_space,
+        );
+        (*video_context.0)
+            .VideoProcessorSetOutputColorSpace(video_processor.0, &output_color_space);
+
+        let mut desc = D3D11_TEXTURE2D_DESC {
+            Width: width,
+            Height: height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: DXGI_FORMAT_NV12,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: D3D11_BIND_RENDER_TARGET,
+            CPUAccessFlags: 0,
+            MiscFlags: 0,
+        };
+        let mut texture = ptr::null_mut();
+        wrap_hresult((*self.device.0).CreateTexture2D(&desc, ptr::null(), &mut texture))?;
+        let texture = ComPtr(texture);
+        let mut output_view_desc: D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC = mem::zeroed();
+        output_view_desc.ViewDimension = D3D11_VPOV_DIMENSION_TEXTURE2D;
+        output_view_desc.u.Texture2D_mut().MipSlice = 0;
+        let mut output_view = ptr::null_mut();
+        wrap_hresult((*video_device.0).CreateVideoProcessorOutputView(
+            texture.0 as *mut _,
+            video_processor_enum.0,
+            &output_view_desc,
+            &mut output_view,
+        ))?;
+        let output_view = ComPtr(output_view);
+
+        desc.Usage = D3D11_USAGE_STAGING;
+        desc.BindFlags = 0;
+        desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ;
+        let mut staging = ptr::null_mut();
+        wrap_hresult((*self.device.0).CreateTexture2D(&desc, ptr::null(), &mut staging))?;
+        let staging = ComPtr(staging);
+
+        Ok(Nv12 {
+            video_context,
+            video_device,
+            video_processor_enum,
+            video_processor,
+            texture,
+            output_view,
+            staging,
+            height: self.height,
+        })
+    }
+
     unsafe fn load_frame(&mut self, timeout: UINT) -> io::Result<(*const u8, i32)> {
         let mut frame = ptr::null_mut();
         #[allow(invalid_value)]
@@ -393,11 +570,28 @@ impl Capturer {
     pub fn frame<'a>(&'a mut self, timeout: UINT) -> io::Result<Frame<'a>> {
         if self.output_texture {
             Ok(Frame::Texture(self.get_texture(timeout)?))
+        } else if self.nv12.is_some() && !self.is_gdi() {
+            let width = self.width;
+            let height = self.height;
+            let (data, pitch) = self.get_nv12(timeout)?;
+            Ok(Frame::PixelBuffer(PixelBuffer::with_stride(
+                data,
+                Pixfmt::NV12,
+                width,
+                height,
+                vec![pitch, pitch],
+            )))
         } else {
             let width = self.width;
             let height = self.height;
-            Ok(Frame::PixelBuffer(PixelBuffer::with_BGRA(
+            let pixfmt = if self.is_hdr() {
+                Pixfmt::RGBA16F
+            } else {
+                Pixfmt::BGRA
+            };
+            Ok(Frame::PixelBuffer(PixelBuffer::new(
                 self.get_pixelbuffer(timeout)?,
+                pixfmt,
                 width,
                 height,
             )))
@@ -467,6 +661,37 @@ impl Capturer {
         }
     }
 
+    fn get_nv12<'a>(&'a mut self, timeout: UINT) -> io::Result<(&'a [u8], usize)> {
+        unsafe {
+            self.unmap();
+            let mut frame = ptr::null_mut();
+            #[allow(invalid_value)]
+            let mut info = mem::MaybeUninit::uninit().assume_init();
+
+            wrap_hresult((*self.duplication.0).AcquireNextFrame(timeout, &mut info, &mut frame))?;
+            let frame = ComPtr(frame);
+
+            if *info.LastPresentTime.QuadPart() == 0 {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+
+            let mut texture: *mut ID3D11Texture2D = ptr::null_mut();
+            (*frame.0).QueryInterface(
+                &IID_ID3D11Texture2D,
+                &mut texture as *mut *mut _ as *mut *mut _,
+            );
+            let texture = ComPtr(texture);
+            if texture.is_null() {
+                return Err(std::io::ErrorKind::InvalidData.into());
+            }
+            let context = self.context.0;
+            let Some(nv12) = self.nv12.as_ref() else {
+                return Err(std::io::ErrorKind::NotFound.into());
+            };
+            nv12.convert(context, texture.0)
+        }
+    }
+
     fn get_texture(&mut self, timeout: UINT) -> io::Result<(*mut c_void, usize)> {
         unsafe {
             if self.duplication.0.is_null() {
@@ -584,6 +809,9 @@ impl Capturer {
                     (*self.surface.0).Unmap();
                 }
             }
+            if let Some(nv12) = &self.nv12 {
+                nv12.unmap(self.context.0);
+            }
         }
     }
 
@@ -821,6 +1049,60 @@ impl Display {
         self.desc.Monitor
     }
 
+    // Windows 10 1803+, HDR is on in the display settings.
+    pub fn is_hdr(&self) -> bool {
+        if self.gdi || self.inner.is_null() {
+            return false;
+        }
+        unsafe {
+            let mut output6: *mut IDXGIOutput6 = ptr::null_mut();
+            (*self.inner.0).QueryInterface(
+                &IDXGIOutput6::uuidof(),
+                &mut output6 as *mut *mut _ as *mut *mut _,
+            );
+            if output6.is_null() {
+                return false;
+            }
+            let output6 = ComPtr(output6);
+            let mut desc: DXGI_OUTPUT_DESC1 = mem::zeroed();
+            if (*output6.0).GetDesc1(&mut desc) != S_OK {
+                return false;
+            }
+            desc.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020
+        }
+    }
+
+    // Duplicates the output as FP16 scRGB, which keeps the HDR content.
+    unsafe fn duplicate_hdr(
+        &self,
+        device: *mut ID3D11Device,
+        duplication: &mut *mut IDXGIOutputDuplication,
+    ) -> bool {
+        let mut output5: *mut IDXGIOutput5 = ptr::null_mut();
+        (*self.inner.0).QueryInterface(
+            &IDXGIOutput5::uuidof(),
+            &mut output5 as *mut *mut _ as *mut *mut _,
+        );
+        if output5.is_null() {
+            return false;
+        }
+        let output5 = ComPtr(output5);
+        let formats: [DXGI_FORMAT; 1] = [DXGI_FORMAT_R16G16B16A16_FLOAT];
+        let hres = (*output5.0).DuplicateOutput1(
+            device as *mut _,
+            0,
+            formats.len() as _,
+            formats.as_ptr(),
+            duplication,
+        );
+        if hres != S_OK {
+            println!("DuplicateOutput1 failed: {:#X}", hres);
+            *duplication = ptr::null_mut();
+            return false;
+        }
+        true
+    }
+
     pub fn name(&self) -> &[u16] {
         let s = &self.desc.DeviceName;
         let i = s.iter().position(|&x| x == 0).unwrap_or(s.len());
@@ -882,3 +1164,63 @@ struct Rotate {
     video_processor: ComPtr<ID3D11VideoProcessor>,
     texture: (ComPtr<ID3D11Texture2D>, bool),
 }
+
+// BGRA to NV12 by the video processor, the readback is 3/8 of the BGRA one.
+struct Nv12 {
+    video_context: ComPtr<ID3D11VideoContext>,
+    video_device: ComPtr<ID3D11VideoDevice>,
+    video_processor_enum: ComPtr<ID3D11VideoProcessorEnumerator>,
+    video_processor: ComPtr<ID3D11VideoProcessor>,
+    texture: ComPtr<ID3D11Texture2D>,
+    output_view: ComPtr<ID3D11VideoProcessorOutputView>,
+    staging: ComPtr<ID3D11Texture2D>,
+    height: usize,
+}
+
+impl Nv12 {
+    // Returns the mapped data, the UV plane follows the Y plane, and the row pitch of both planes.
+    unsafe fn convert<'a>(
+        &'a self,
+        context: *mut ID3D11DeviceContext,
+        texture: *mut ID3D11Texture2D,
+    ) -> io::Result<(&'a [u8], usize)> {
+        let input_view_desc = D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC {
+            FourCC: 0,
+            ViewDimension: D3D11_VPIV_DIMENSION_TEXTURE2D,
+            Texture2D: D3D11_TEX2D_VPIV {
+                ArraySlice: 0,
+                MipSlice: 0,
+            },
+        };
+        let mut input_view = ptr::null_mut();
+        wrap_hresult((*self.video_device.0).CreateVideoProcessorInputView(
+            texture as *mut _,
+            self.video_processor_enum.0,
+            &input_view_desc,
+            &mut input_view,
+        ))?;
+        let input_view = ComPtr(input_view);
+        let mut stream_data: D3D11_VIDEO_PROCESSOR_STREAM = mem::zeroed();
+        stream_data.Enable = TRUE;
+        stream_data.pInputSurface = input_view.0;
+        wrap_hresult((*self.video_context.0).VideoProcessorBlt(
+            self.video_processor.0,
+            self.output_view.0,
+            0,
+            1,
+            &stream_data,
+        ))?;
+        (*context).CopyResource(self.staging.0 as *mut _, self.texture.0 as *mut _);
+        let mut mapped: D3D11_MAPPED_SUBRESOURCE = mem::zeroed();
+        wrap_hresult((*context).Map(self.staging.0 as *mut _, 0, D3D11_MAP_READ, 0, &mut mapped))?;
+        let pitch = mapped.RowPitch as usize;
+        Ok((
+            slice::from_raw_parts(mapped.pData as *const u8, pitch * self.height * 3 / 2),
+            pitch,
+        ))
+    }
+
+    unsafe fn unmap(&self, context: *mut ID3D11DeviceContext) {
+        (*context).Unmap(self.staging.0 as *mut _, 0);
+    }
+}
@@ -1,7 +1,7 @@
 use std::ptr;
 
 use block::{Block, ConcreteBlock};
-use hbb_common::libc::c_void;
+use hbb_common::{config, libc::c_void, log};
 use std::sync::{Arc, Mutex};
 
 use super::config::Config;
@@ -9,15 +9,31 @@ use super::display::Display;
 use super::ffi::*;
 use super::frame::Frame;
 
+// "N" to keep the deprecated CGDisplayStream on macOS 13+.
+const OPTION_ENABLE_SCREEN_CAPTURE_KIT: &str = "enable-screen-capture-kit";
+
+type FrameHandler = Box<dyn Fn(Frame)>;
+
+enum Stream {
+    DisplayStream {
+        stream: CGDisplayStreamRef,
+        queue: DispatchQueue,
+        stopped: Arc<Mutex<bool>>,
+    },
+    // The handler is owned by the capturer, and freed after the stream is stopped.
+    ScreenCaptureKit {
+        sck: *mut c_void,
+        handler: *mut FrameHandler,
+    },
+}
+
 pub struct Capturer {
-    stream: CGDisplayStreamRef,
-    queue: DispatchQueue,
+    stream: Stream,
 
     width: usize,
     height: usize,
     format: PixelFormat,
     display: Display,
-    stopped: Arc<Mutex<bool>>,
 }
 
 impl Capturer {
@@ -29,6 +45,27 @@ impl Capturer {
         config: Config,
         handler: F,
     ) -> Result<Capturer, CGError> {
+        let handler: FrameHandler = Box::new(handler);
+        let handler = if format == PixelFormat::Argb8888 && use_screen_capture_kit() {
+            match Self::start_screen_capture_kit(display, width, height, &config, handler) {
+                Ok(stream) => {
+                    return Ok(Capturer {
+                        stream,
+                        width,
+                        height,
+                        format,
+                        display,
+                    })
+                }
+                Err(handler) => {
+                    log::warn!("Failed to start ScreenCaptureKit, use CGDisplayStream");
+                    handler
+                }
+            }
+        } else {
+            handler
+        };
+
         let stopped = Arc::new(Mutex::new(false));
         let cloned_stopped = stopped.clone();
         let handler: FrameAvailableHandler = ConcreteBlock::new(move |status, _, surface, _| {
@@ -68,18 +105,48 @@ impl Capturer {
 
         match unsafe { CGDisplayStreamStart(stream) } {
             CGError::Success => Ok(Capturer {
-                stream,
-                queue,
+                stream: Stream::DisplayStream {
+                    stream,
+                    queue,
+                    stopped,
+                },
                 width,
                 height,
                 format,
                 display,
-                stopped,
             }),
             x => Err(x),
         }
     }
 
+    // ScreenCaptureKit delivers the frames as BGRA, with our own windows excluded.
+    // The handler is given back on failure.
+    fn start_screen_capture_kit(
+        display: Display,
+        width: usize,
+        height: usize,
+        config: &Config,
+        handler: FrameHandler,
+    ) -> Result<Stream, FrameHandler> {
+        let handler = Box::into_raw(Box::new(handler));
+        let sck = unsafe {
+            ScrapSckStart(
+                display.id(),
+                width,
+                height,
+                config.cursor,
+                config.throttle,
+                handler as *mut c_void,
+                on_sck_frame,
+            )
+        };
+        if sck.is_null() {
+            return Err(*unsafe { Box::from_raw(handler) });
+        }
+        log::info!("ScreenCaptureKit started for display {}", display.id());
+        Ok(Stream::ScreenCaptureKit { sck, handler })
+    }
+
     pub fn width(&self) -> usize {
         self.width
     }
@@ -94,18 +161,40 @@ impl Capturer {
     }
 }
 
+fn use_screen_capture_kit() -> bool {
+    config::Config::get_option(OPTION_ENABLE_SCREEN_CAPTURE_KIT) != "N"
+        && unsafe { ScrapSckIsSupported() }
+}
+
+extern "C" fn on_sck_frame(ctx: *mut c_void, surface: IOSurfaceRef) {
+    let handler = unsafe { &*(ctx as *const FrameHandler) };
+    handler(unsafe { Frame::new(surface) });
+}
+
 impl Drop for Capturer {
     fn drop(&mut self) {
         unsafe {
-            let _ = CGDisplayStreamStop(self.stream);
-            loop {
-                if *self.stopped.lock().unwrap() {
-                    break;
+            match self.stream {
+                Stream::DisplayStream {
+                    stream,
+                    queue,
+                    ref stopped,
+                } => {
+                    let _ = CGDisplayStreamStop(stream);
+                    loop {
+                        if *stopped.lock().unwrap() {
+                            break;
+                        }
+                        std::thread::sleep(std::time::Duration::from_millis(30));
+                    }
+                    CFRelease(stream);
+                    dispatch_release(queue);
+                }
+                Stream::ScreenCaptureKit { sck, handler } => {
+                    ScrapSckStop(sck);
+                    drop(Box::from_raw(handler));
                 }
-                std::thread::sleep(std::time::Duration::from_millis(30));
             }
-            CFRelease(self.stream);
-            dispatch_release(self.queue);
         }
     }
 }
@@ -4,7 +4,7 @@ use hbb_common::libc::c_void;
 
 use super::ffi::*;
 
-//TODO: Color space, YCbCr matrix.
+//TODO: YCbCr matrix.
 pub struct Config {
     /// Whether the cursor is visible.
     pub cursor: bool,
@@ -33,30 +33,41 @@ impl Config {
                 &self.queue_length as *const _ as *const c_void,
             );
 
-            let keys: [CFStringRef; 4] = [
+            // EDR content of HDR displays is tone-mapped to sRGB by the OS,
+            // instead of being clipped in the display color space.
+            let color_space = CGColorSpaceCreateWithName(kCGColorSpaceSRGB);
+
+            let keys: [CFStringRef; 5] = [
                 kCGDisplayStreamShowCursor,
                 kCGDisplayStreamPreserveAspectRatio,
                 kCGDisplayStreamMinimumFrameTime,
                 kCGDisplayStreamQueueDepth,
+                kCGDisplayStreamColorSpace,
             ];
-            let values: [*mut c_void; 4] = [
+            let values: [*mut c_void; 5] = [
                 cfbool(self.cursor),
                 cfbool(self.letterbox),
                 throttle,
                 queue_length,
+                color_space,
             ];
+            // The color space is optional.
+            let len = if color_space.is_null() { 4 } else { 5 };
 
             let res = CFDictionaryCreate(
                 ptr::null_mut(),
                 keys.as_ptr(),
                 values.as_ptr(),
-                4,
+                len,
                 &kCFTypeDictionaryKeyCallBacks,
                 &kCFTypeDictionaryValueCallBacks,
             );
 
             CFRelease(throttle);
             CFRelease(queue_length);
+            if !color_space.is_null() {
+                CFRelease(color_space);
+            }
 
             res
         }
@@ -8,11 +8,13 @@ pub type CFDictionaryRef = *mut c_void;
 pub type CFBooleanRef = *mut c_void;
 pub type CFNumberRef = *mut c_void;
 pub type CFStringRef = *mut c_void;
+pub type CGColorSpaceRef = *mut c_void;
 pub type CGDisplayStreamUpdateRef = *mut c_void;
 pub type IOSurfaceRef = *mut c_void;
 pub type DispatchQueue = *mut c_void;
 pub type DispatchQueueAttr = *mut c_void;
 pub type CFAllocatorRef = *mut c_void;
+pub type SckFrameCallback = extern "C" fn(ctx: *mut c_void, surface: IOSurfaceRef);
 
 #[repr(C)]
 pub struct CFDictionaryKeyCallBacks {
@@ -162,6 +164,10 @@ extern "C" {
     pub static kCGDisplayStreamPreserveAspectRatio: CFStringRef;
     pub static kCGDisplayStreamMinimumFrameTime: CFStringRef;
     pub static kCGDisplayStreamQueueDepth: CFStringRef;
+    pub static kCGDisplayStreamColorSpace: CFStringRef;
+    pub static kCGColorSpaceSRGB: CFStringRef;
+
+    pub fn CGColorSpaceCreateWithName(name: CFStringRef) -> CGColorSpaceRef;
 
     pub fn CGDisplayStreamCreateWithDispatchQueue(
         display: u32,
@@ -195,6 +201,20 @@ extern "C" {
     pub fn CGDisplayBounds(display: u32) -> CGRect;
     pub fn BackingScaleFactor(display: u32) -> f32;
 
+    // ScreenCaptureKit, see `src/platform/macos_sck.mm`
+
+    pub fn ScrapSckIsSupported() -> bool;
+    pub fn ScrapSckStart(
+        display: u32,
+        width: usize,
+        height: usize,
+        show_cursor: bool,
+        min_frame_time: f64,
+        ctx: *mut c_void,
+        callback: SckFrameCallback,
+    ) -> *mut c_void;
+    pub fn ScrapSckStop(sck: *mut c_void);
+
     // IOSurface
 
     pub fn IOSurfaceGetAllocSize(buffer: IOSurfaceRef) -> usize;
@@ -2,4 +2,5 @@ pub mod capturable;
 pub mod pipewire;
 mod screencast_portal;
 mod request_portal;
+pub mod clipboard_portal;
 pub mod remote_desktop_portal;
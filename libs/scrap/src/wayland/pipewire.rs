@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::io::{Read, Write};
 use std::os::unix::io::AsRawFd;
 use std::process::Command;
 use std::sync::{atomic::AtomicBool, Arc, Mutex};
@@ -21,6 +22,11 @@ use hbb_common::config;
 
 use super::capturable::PixelProvider;
 use super::capturable::{Capturable, Recorder};
+use super::clipboard_portal::{
+    OrgFreedesktopPortalClipboard as clipboard_portal,
+    OrgFreedesktopPortalClipboardSelectionOwnerChanged as SelectionOwnerChanged,
+    OrgFreedesktopPortalClipboardSelectionTransfer as SelectionTransfer,
+};
 use super::remote_desktop_portal::OrgFreedesktopPortalRemoteDesktop as remote_desktop_portal;
 use super::request_portal::OrgFreedesktopPortalRequestResponse;
 use super::screencast_portal::OrgFreedesktopPortalScreenCast as screencast_portal;
@@ -29,6 +35,7 @@ use lazy_static::lazy_static;
 
 lazy_static! {
     pub static ref RDP_SESSION_INFO: Mutex<Option<RdpSessionInfo>> = Mutex::new(None);
+    static ref PORTAL_CLIPBOARD: Mutex<PortalClipboard> = Default::default();
 }
 
 #[inline]
@@ -45,8 +52,9 @@ pub fn try_close_session() {
     let mut rdp_info = RDP_SESSION_INFO.lock().unwrap();
     let mut close = false;
     if let Some(rdp_info) = &*rdp_info {
-        // If is server running and restore token is supported, there's no need to keep the session.
-        if is_server_running() && rdp_info.is_support_restore_token {
+        // If restore token is supported, there's no need to keep the session,
+        // the next one is restored without the user interaction.
+        if rdp_info.is_support_restore_token {
             close = true;
         }
     }
@@ -211,6 +219,56 @@ pub struct PipeWireRecorder {
     width: usize,
     height: usize,
     saved_raw_data: Vec<u8>, // for faster compare and copy
+    last_crop: Option<(u32, u32, u32, u32)>,
+    last_offset: u64,
+}
+
+// The first PipeWire version whose pipewiresrc does not need `always-copy`.
+const PIPEWIRESRC_NO_COPY_VERSION: [u32; 3] = [0, 3, 44];
+
+fn pipewiresrc_needs_copy(src: &gst::Element) -> bool {
+    let Some(version) = src
+        .get_factory()
+        .and_then(|f| f.get_plugin())
+        .map(|p| p.get_version().to_string())
+    else {
+        return true;
+    };
+    let version: Vec<u32> = version.split('.').filter_map(|v| v.parse().ok()).collect();
+    debug!("pipewiresrc version {:?}", version);
+    version.as_slice() < PIPEWIRESRC_NO_COPY_VERSION.as_slice()
+}
+
+// The rows in `y..y + h` covered by the damage, None if the whole buffer has to be compared.
+// pipewiresrc attaches the damage of the compositor (SPA_META_VideoDamage) as region of interest meta,
+// no meta means the compositor or pipewiresrc does not provide it.
+fn damaged_rows(
+    damage: &[(u32, u32, u32, u32)],
+    y: usize,
+    h: usize,
+) -> Option<Vec<(usize, usize)>> {
+    if damage.is_empty() {
+        return None;
+    }
+    let mut rows: Vec<(usize, usize)> = damage
+        .iter()
+        .filter(|r| r.2 > 0 && r.3 > 0)
+        .map(|r| {
+            let start = (r.1 as usize).clamp(y, y + h);
+            let end = (r.1 as usize + r.3 as usize).clamp(y, y + h);
+            (start, end)
+        })
+        .filter(|r| r.0 < r.1)
+        .collect();
+    rows.sort();
+    let mut merged: Vec<(usize, usize)> = vec![];
+    for r in rows {
+        match merged.last_mut() {
+            Some(last) if r.0 <= last.1 => last.1 = last.1.max(r.1),
+            _ => merged.push(r),
+        }
+    }
+    Some(merged)
 }
 
 impl PipeWireRecorder {
@@ -224,7 +282,8 @@ impl PipeWireRecorder {
 
         // For some reason pipewire blocks on destruction of AppSink if this is not set to true,
         // see: https://gitlab.freedesktop.org/pipewire/pipewire/-/issues/982
-        src.set_property("always-copy", &true)?;
+        // Newer versions map the buffers of the stream (memfd or dmabuf) without copying.
+        src.set_property("always-copy", &pipewiresrc_needs_copy(&src))?;
 
         let sink = gst::ElementFactory::make("appsink", None)?;
         sink.set_property("drop", &true)?;
@@ -258,6 +317,8 @@ impl PipeWireRecorder {
             buffer_cropped: vec![],
             is_cropped: false,
             saved_raw_data: Vec::new(),
+            last_crop: None,
+            last_offset: gst::BUFFER_OFFSET_NONE,
         })
     }
 }
@@ -292,11 +353,52 @@ impl Recorder for PipeWireRecorder {
             if Some((0, 0, w as u32, h as u32)) == crop {
                 crop = None;
             }
+            let damage: Vec<_> = buf
+                .iter_meta::<gstreamer_video::VideoRegionOfInterestMeta>()
+                .map(|m| m.get_rect())
+                .collect();
+            let (crop_y, crop_h) = crop.map_or((0, h), |c| (c.1 as usize, c.3 as usize));
+            // pipewiresrc sets the offset to the sequence number of the PipeWire buffer.
+            let offset = buf.get_offset();
+            let last_offset = std::mem::replace(&mut self.last_offset, offset);
             let buf = buf
                 .into_mapped_buffer_readable()
                 .map_err(|_| GStreamerError("Failed to map buffer.".into()))?;
-            if let Err(..) = crate::would_block_if_equal(&mut self.saved_raw_data, buf.as_slice()) {
-                return Ok(PixelProvider::NONE);
+            // The damage is relative to the last buffer, only compare and save the damaged rows
+            // if no buffer is dropped by the appsink.
+            let damaged_rows = if offset != gst::BUFFER_OFFSET_NONE
+                && offset == last_offset.wrapping_add(1)
+                && crop == self.last_crop
+                && buf.get_size() == w * h * 4
+                && self.saved_raw_data.len() == buf.get_size()
+            {
+                damaged_rows(&damage, crop_y, crop_h)
+            } else {
+                None
+            };
+            self.last_crop = crop;
+            match &damaged_rows {
+                Some(rows) => {
+                    let data = buf.as_slice();
+                    let mut changed = false;
+                    for (start, end) in rows {
+                        let range = 4 * w * start..4 * w * end;
+                        if self.saved_raw_data[range.clone()] != data[range.clone()] {
+                            self.saved_raw_data[range.clone()].copy_from_slice(&data[range]);
+                            changed = true;
+                        }
+                    }
+                    if !changed {
+                        return Ok(PixelProvider::NONE);
+                    }
+                }
+                None => {
+                    if let Err(..) =
+                        crate::would_block_if_equal(&mut self.saved_raw_data, buf.as_slice())
+                    {
+                        return Ok(PixelProvider::NONE);
+                    }
+                }
             }
             let buf_size = buf.get_size();
             // BGRx is 4 bytes per pixel
@@ -319,13 +421,28 @@ impl Recorder for PipeWireRecorder {
                     let y_off = y_off as usize;
                     let w_crop = w_crop as usize;
                     let h_crop = h_crop as usize;
-                    self.buffer_cropped.clear();
                     let data = buf.as_slice();
                     // BGRx is 4 bytes per pixel
-                    self.buffer_cropped.reserve(w_crop * h_crop * 4);
-                    for y in y_off..(y_off + h_crop) {
+                    let copy_row = |cropped: &mut [u8], y: usize| {
                         let i = 4 * (w * y + x_off);
-                        self.buffer_cropped.extend(&data[i..i + 4 * w_crop]);
+                        let j = 4 * w_crop * (y - y_off);
+                        cropped[j..j + 4 * w_crop].copy_from_slice(&data[i..i + 4 * w_crop]);
+                    };
+                    match &damaged_rows {
+                        // Only copy the damaged rows into the last cropped frame.
+                        Some(rows) if self.buffer_cropped.len() == w_crop * h_crop * 4 => {
+                            for (start, end) in rows {
+                                for y in *start..*end {
+                                    copy_row(&mut self.buffer_cropped, y);
+                                }
+                            }
+                        }
+                        _ => {
+                            self.buffer_cropped.resize(w_crop * h_crop * 4, 0);
+                            for y in y_off..(y_off + h_crop) {
+                                copy_row(&mut self.buffer_cropped, y);
+                            }
+                        }
                     }
                     self.width = w_crop;
                     self.height = h_crop;
@@ -502,6 +619,13 @@ fn streams_from_response(response: OrgFreedesktopPortalRequestResponse) -> Vec<P
 static mut INIT: bool = false;
 const RESTORE_TOKEN: &str = "restore_token";
 const RESTORE_TOKEN_CONF_KEY: &str = "wayland-restore-token";
+// Persist the permission until it is revoked, so that the screen selection dialog
+// is not shown again after the restart of the host.
+const PERSIST_MODE_PERSISTENT: u32 = 2;
+const SCREENCAST_RESTORE_TOKEN_VERSION: u32 = 4;
+const REMOTE_DESKTOP_RESTORE_TOKEN_VERSION: u32 = 2;
+// https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.ScreenCast.html#org-freedesktop-portal-screencast-availablecursormodes
+const CURSOR_MODE_HIDDEN: u32 = 1;
 
 pub fn get_available_cursor_modes() -> Result<u32, dbus::Error> {
     let conn = SyncConnection::new_session()?;
@@ -509,6 +633,31 @@ pub fn get_available_cursor_modes() -> Result<u32, dbus::Error> {
     portal.available_cursor_modes()
 }
 
+// The cursor is sent by the cursor services, keep it out of the video explicitly,
+// the default mode may be changed by the portal implementations.
+fn insert_hidden_cursor_mode(portal: &Proxy<&SyncConnection>, args: &mut PropMap) {
+    match portal.available_cursor_modes() {
+        Ok(modes) if modes & CURSOR_MODE_HIDDEN != 0 => {
+            args.insert("cursor_mode".into(), Variant(Box::new(CURSOR_MODE_HIDDEN)));
+        }
+        Ok(modes) => warn!("Hidden cursor mode is not available, modes: {}", modes),
+        Err(e) => warn!("Failed to get available cursor modes: {}", e),
+    }
+}
+
+// The token is given to `SelectSources` of the ScreenCast portal, or to `SelectDevices` of the
+// RemoteDesktop portal, each token is used once and a new one is returned by `Start`.
+fn insert_restore_token(args: &mut PropMap) {
+    let restore_token = config::LocalConfig::get_option(RESTORE_TOKEN_CONF_KEY);
+    if !restore_token.is_empty() {
+        args.insert(RESTORE_TOKEN.to_string(), Variant(Box::new(restore_token)));
+    }
+    args.insert(
+        "persist_mode".to_string(),
+        Variant(Box::new(PERSIST_MODE_PERSISTENT)),
+    );
+}
+
 // mostly inspired by https://gitlab.gnome.org/-/snippets/39
 pub fn request_remote_desktop() -> Result<
     (
@@ -546,12 +695,11 @@ pub fn request_remote_desktop() -> Result<
         Variant(Box::new("u1".to_string())),
     );
 
-    let mut is_support_restore_token = false;
-    if let Ok(version) = screencast_portal::version(&portal) {
-        if version >= 4 {
-            is_support_restore_token = true;
-        }
-    }
+    let is_support_restore_token = if is_server_running() {
+        matches!(screencast_portal::version(&portal), Ok(v) if v >= SCREENCAST_RESTORE_TOKEN_VERSION)
+    } else {
+        matches!(remote_desktop_portal::version(&portal), Ok(v) if v >= REMOTE_DESKTOP_RESTORE_TOKEN_VERSION)
+    };
 
     // The following code may be improved.
     // https://flatpak.github.io/xdg-desktop-portal/#:~:text=To%20avoid%20a%20race%20condition
@@ -647,15 +795,10 @@ fn on_create_session_response(
         let portal = get_portal(c);
         let mut args: PropMap = HashMap::new();
         // See `is_server_running()` to understand the following code.
+        if is_support_restore_token {
+            insert_restore_token(&mut args);
+        }
         if is_server_running() {
-            if is_support_restore_token {
-                let restore_token = config::LocalConfig::get_option(RESTORE_TOKEN_CONF_KEY);
-                if !restore_token.is_empty() {
-                    args.insert(RESTORE_TOKEN.to_string(), Variant(Box::new(restore_token)));
-                }
-                // persist_mode may be configured by the user.
-                args.insert("persist_mode".to_string(), Variant(Box::new(2u32)));
-            }
             args.insert(
                 "handle_token".to_string(),
                 Variant(Box::new("u3".to_string())),
@@ -665,6 +808,7 @@ fn on_create_session_response(
                 args.insert("multiple".into(), Variant(Box::new(true)));
             }
             args.insert("types".into(), Variant(Box::new(1u32))); //| 2u32)));
+            insert_hidden_cursor_mode(&portal, &mut args);
 
             let path = portal.select_sources(ses.clone(), args)?;
             handle_response(
@@ -680,9 +824,7 @@ fn on_create_session_response(
                 failure.clone(),
             )?;
         } else {
-            // TODO: support persist_mode for remote_desktop_portal
             // https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.RemoteDesktop.html
-
             args.insert(
                 "handle_token".to_string(),
                 Variant(Box::new("u2".to_string())),
@@ -721,6 +863,11 @@ fn on_select_devices_response(
 ) -> Result<(), Box<dyn Error>> {
     move |_: OrgFreedesktopPortalRequestResponse, c, _| {
         let portal = get_portal(c);
+        // The clipboard can only be requested between `SelectDevices` and `Start`,
+        // it is enabled if the user allows it in the dialog of `Start`.
+        if let Err(e) = portal.request_clipboard(session.clone(), HashMap::new()) {
+            warn!("Failed to request the clipboard of the session: {}", e);
+        }
         let mut args: PropMap = HashMap::new();
         args.insert(
             "handle_token".to_string(),
@@ -731,6 +878,7 @@ fn on_select_devices_response(
             args.insert("multiple".into(), Variant(Box::new(true)));
         }
         args.insert("types".into(), Variant(Box::new(1u32))); //| 2u32)));
+        insert_hidden_cursor_mode(&portal, &mut args);
 
         let session = session.clone();
         let path = portal.select_sources(session.clone(), args)?;
@@ -803,20 +951,27 @@ fn on_start_response(
 ) -> Result<(), Box<dyn Error>> {
     move |r: OrgFreedesktopPortalRequestResponse, c, _| {
         let portal = get_portal(c);
-        // See `is_server_running()` to understand the following code.
-        if is_server_running() {
-            if is_support_restore_token {
-                if let Some(restore_token) = r.results.get(RESTORE_TOKEN) {
-                    if let Some(restore_token) = restore_token.as_str() {
-                        config::LocalConfig::set_option(
-                            RESTORE_TOKEN_CONF_KEY.to_owned(),
-                            restore_token.to_owned(),
-                        );
-                    }
+        if is_support_restore_token {
+            if let Some(restore_token) = r.results.get(RESTORE_TOKEN) {
+                if let Some(restore_token) = restore_token.as_str() {
+                    config::LocalConfig::set_option(
+                        RESTORE_TOKEN_CONF_KEY.to_owned(),
+                        restore_token.to_owned(),
+                    );
                 }
             }
         }
 
+        let clipboard_enabled = r
+            .results
+            .get("clipboard_enabled")
+            .and_then(|v| v.as_u64())
+            .map_or(false, |v| v != 0);
+        if clipboard_enabled {
+            if let Err(e) = watch_clipboard(c, session.clone()) {
+                warn!("Failed to watch the clipboard of the session: {}", e);
+            }
+        }
         streams
             .clone()
             .lock()
@@ -849,6 +1004,9 @@ pub fn get_capturables() -> Result<Vec<PipeWireCapturable>, Box<dyn Error>> {
             is_support_restore_token,
             resolution: Arc::new(Mutex::new(None)),
         };
+        if is_clipboard_of_session(&rdp_info.session) {
+            start_processing_clipboard(rdp_info.conn.clone());
+        }
         *rdp_connection = Some(rdp_info);
     }
 
@@ -882,7 +1040,7 @@ pub fn get_capturables() -> Result<Vec<PipeWireCapturable>, Box<dyn Error>> {
 // Otherwise, we have to use remote_desktop_portal's input method.
 //
 // `screencast_portal` supports restore_token and persist_mode if the version is greater than or equal to 4.
-// `remote_desktop_portal` does not support restore_token and persist_mode.
+// `remote_desktop_portal` supports restore_token and persist_mode if the version is greater than or equal to 2.
 fn is_server_running() -> bool {
     let app_name = config::APP_NAME.read().unwrap().clone().to_lowercase();
     let output = match Command::new(CMD_SH.as_str())
@@ -900,3 +1058,173 @@ fn is_server_running() -> bool {
     let is_running = output_str.contains(&format!("{} --server", app_name));
     is_running
 }
+
+// The file clipboard of the RemoteDesktop session, through the Clipboard portal, for the
+// compositors without the data control protocol of Wayland, e.g. GNOME.
+// Only available if the RemoteDesktop portal is used, see `is_server_running()`.
+#[derive(Default)]
+struct PortalClipboard {
+    session: Option<dbus::Path<'static>>,
+    // The data offered by `set_clipboard()`, by the mime type.
+    offered: HashMap<String, Vec<u8>>,
+    is_owner: bool,
+    mime_types: Vec<String>,
+    changed: bool,
+}
+
+fn watch_clipboard(conn: &SyncConnection, session: dbus::Path<'static>) -> Result<(), dbus::Error> {
+    let mut m = MatchRule::new_signal("org.freedesktop.portal.Clipboard", "SelectionTransfer");
+    m.sender = Some("org.freedesktop.portal.Desktop".into());
+    let ses = session.clone();
+    conn.add_match(m, move |t: SelectionTransfer, c, _| {
+        if t.session_handle == ses {
+            on_selection_transfer(c, &ses, t);
+        }
+        true
+    })?;
+    let mut m = MatchRule::new_signal("org.freedesktop.portal.Clipboard", "SelectionOwnerChanged");
+    m.sender = Some("org.freedesktop.portal.Desktop".into());
+    let ses = session.clone();
+    conn.add_match(m, move |o: SelectionOwnerChanged, _: &SyncConnection, _| {
+        if o.session_handle == ses {
+            let mut clipboard = PORTAL_CLIPBOARD.lock().unwrap();
+            clipboard.is_owner = o
+                .options
+                .get("session_is_owner")
+                .and_then(|v| v.as_u64())
+                .map_or(false, |v| v != 0);
+            clipboard.mime_types = o
+                .options
+                .get("mime_types")
+                .and_then(|v| v.as_iter())
+                .and_then(|mut v| v.next())
+                .and_then(|v| v.as_iter())
+                .map(|v| v.filter_map(|x| x.as_str().map(|x| x.to_owned())).collect())
+                .unwrap_or_default();
+            clipboard.changed = !clipboard.is_owner;
+        }
+        true
+    })?;
+    *PORTAL_CLIPBOARD.lock().unwrap() = PortalClipboard {
+        session: Some(session),
+        ..Default::default()
+    };
+    Ok(())
+}
+
+fn on_selection_transfer(
+    conn: &SyncConnection,
+    session: &dbus::Path<'static>,
+    t: SelectionTransfer,
+) {
+    let portal = get_portal(conn);
+    let data = PORTAL_CLIPBOARD
+        .lock()
+        .unwrap()
+        .offered
+        .get(&t.mime_type)
+        .cloned();
+    let success = match data {
+        Some(data) => match write_selection(&portal, session, t.serial, &data) {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("Failed to write the clipboard of the session: {}", e);
+                false
+            }
+        },
+        None => false,
+    };
+    if let Err(e) = portal.selection_write_done(session.clone(), t.serial, success) {
+        warn!(
+            "Failed to finish writing the clipboard of the session: {}",
+            e
+        );
+    }
+}
+
+fn write_selection(
+    portal: &Proxy<&SyncConnection>,
+    session: &dbus::Path<'static>,
+    serial: u32,
+    data: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    let fd = portal.selection_write(session.clone(), serial)?;
+    let mut file = std::fs::File::from(std::os::fd::OwnedFd::from(fd.into_fd()));
+    file.write_all(data)?;
+    Ok(())
+}
+
+fn is_clipboard_of_session(session: &dbus::Path<'static>) -> bool {
+    PORTAL_CLIPBOARD.lock().unwrap().session.as_ref() == Some(session)
+}
+
+// Nothing else processes the connection once the session is started, the signals of the
+// clipboard are dispatched here as long as the session is kept.
+fn start_processing_clipboard(conn: Arc<SyncConnection>) {
+    std::thread::spawn(move || loop {
+        let is_held = RDP_SESSION_INFO
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(false, |info| Arc::ptr_eq(&info.conn, &conn));
+        if !is_held {
+            break;
+        }
+        if let Err(e) = conn.process(Duration::from_millis(100)) {
+            warn!("Failed to process the clipboard of the session: {}", e);
+            break;
+        }
+    });
+}
+
+fn clipboard_session() -> Option<(Arc<SyncConnection>, dbus::Path<'static>)> {
+    let rdp_info = RDP_SESSION_INFO.lock().unwrap();
+    let rdp_info = rdp_info.as_ref()?;
+    if !is_clipboard_of_session(&rdp_info.session) {
+        return None;
+    }
+    Some((rdp_info.conn.clone(), rdp_info.session.clone()))
+}
+
+/// Whether the clipboard of the RemoteDesktop session is allowed by the user.
+pub fn is_clipboard_enabled() -> bool {
+    clipboard_session().is_some()
+}
+
+/// Offers the data, by the mime type, on the clipboard of the session.
+pub fn set_clipboard(data: Vec<(String, Vec<u8>)>) -> Result<(), Box<dyn Error>> {
+    let Some((conn, session)) = clipboard_session() else {
+        return Err(Box::new(DBusError("The clipboard is not enabled.".into())));
+    };
+    let mime_types = data.iter().map(|(t, _)| t.clone()).collect::<Vec<_>>();
+    PORTAL_CLIPBOARD.lock().unwrap().offered = data.into_iter().collect();
+    let mut args: PropMap = HashMap::new();
+    args.insert("mime_types".to_string(), Variant(Box::new(mime_types)));
+    get_portal(&conn).set_selection(session, args)?;
+    Ok(())
+}
+
+/// Reads the clipboard of the session in the mime type.
+///
+/// `None` if the session owns the clipboard, the type is not offered,
+/// or the clipboard is not changed since the last read and `force` is false.
+pub fn read_clipboard(mime_type: &str, force: bool) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let Some((conn, session)) = clipboard_session() else {
+        return Ok(None);
+    };
+    {
+        let mut clipboard = PORTAL_CLIPBOARD.lock().unwrap();
+        if clipboard.is_owner
+            || !(clipboard.changed || force)
+            || !clipboard.mime_types.iter().any(|t| t == mime_type)
+        {
+            return Ok(None);
+        }
+        clipboard.changed = false;
+    }
+    let fd = get_portal(&conn).selection_read(session, mime_type)?;
+    let mut file = std::fs::File::from(std::os::fd::OwnedFd::from(fd.into_fd()));
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(Some(data))
+}
@@ -0,0 +1,282 @@
+//! FIDO2 security key as a second factor.
+//!
+//! The controlled side keeps a list of registered credentials, "credential_id:public_key"
+//! in base64, and sends a random challenge to the connecting client once the password
+//! is accepted. The client gets an assertion for it from a local security key, and the
+//! connection is authorized only if the ECDSA P-256 signature over
+//! `authenticator_data || sha256(challenge)` verifies against a registered key.
+//!
+//! The challenge and the assertion are carried by `PluginRequest` with a reserved id,
+//! no protocol change is needed.
+
+use hbb_common::{
+    bail,
+    bytes::Bytes,
+    config::{self, Config},
+    message_proto::{message, misc, Message, Misc, PluginRequest},
+    rand::{self, RngCore},
+    tokio, ResultType, Stream,
+};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const PLUGIN_ID: &str = "__security_key";
+pub const OPTION_REQUIRE_SECURITY_KEY: &str = "allow-require-security-key";
+pub const OPTION_SECURITY_KEYS: &str = "security-keys";
+const RP_ID: &str = "rustdesk";
+const CHALLENGE_LEN: usize = 32;
+// User present
+const FLAG_UP: u8 = 0x01;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Challenge {
+    pub challenge: String,
+    pub rp_id: String,
+    pub credential_ids: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Assertion {
+    pub credential_id: String,
+    pub auth_data: String,
+    pub signature: String,
+}
+
+struct Credential {
+    id: Vec<u8>,
+    public_key: Vec<u8>,
+}
+
+fn parse_credentials(s: &str) -> Vec<Credential> {
+    s.split(|c| c == ',' || c == '\n')
+        .filter_map(|x| {
+            let mut it = x.trim().split(':');
+            let id = crate::decode64(it.next()?).ok()?;
+            let public_key = crate::decode64(it.next()?).ok()?;
+            if id.is_empty() || public_key.is_empty() {
+                return None;
+            }
+            Some(Credential { id, public_key })
+        })
+        .collect()
+}
+
+fn credentials() -> Vec<Credential> {
+    parse_credentials(&Config::get_option(OPTION_SECURITY_KEYS))
+}
+
+/// Required only if enabled and at least one key is registered, so that enabling it
+/// without any key does not lock everybody out.
+pub fn is_required() -> bool {
+    config::option2bool(
+        OPTION_REQUIRE_SECURITY_KEY,
+        &Config::get_option(OPTION_REQUIRE_SECURITY_KEY),
+    ) && !credentials().is_empty()
+}
+
+fn make_msg(content: Vec<u8>) -> Message {
+    let mut misc = Misc::new();
+    misc.set_plugin_request(PluginRequest {
+        id: PLUGIN_ID.to_owned(),
+        content: Bytes::from(content),
+        ..Default::default()
+    });
+    let mut msg = Message::new();
+    msg.set_misc(misc);
+    msg
+}
+
+/// A new random challenge and the message carrying it to the client.
+pub fn new_challenge() -> (Vec<u8>, Message) {
+    let mut challenge = vec![0u8; CHALLENGE_LEN];
+    rand::thread_rng().fill_bytes(&mut challenge);
+    let c = Challenge {
+        challenge: crate::encode64(&challenge),
+        rp_id: RP_ID.to_owned(),
+        credential_ids: credentials()
+            .iter()
+            .map(|c| crate::encode64(&c.id))
+            .collect(),
+    };
+    let content = serde_json::to_vec(&c).unwrap_or_default();
+    (challenge, make_msg(content))
+}
+
+fn verify_assertion(
+    challenge: &[u8],
+    assertion: &Assertion,
+    credentials: &[Credential],
+) -> ResultType<()> {
+    let credential_id = crate::decode64(&assertion.credential_id)?;
+    let Some(credential) = credentials.iter().find(|c| c.id == credential_id) else {
+        bail!("unknown credential");
+    };
+    let auth_data = crate::decode64(&assertion.auth_data)?;
+    // rpIdHash (32) | flags (1) | signCount (4)
+    if auth_data.len() < 37 {
+        bail!("invalid authenticator data");
+    }
+    if auth_data[..32] != Sha256::digest(RP_ID.as_bytes())[..] {
+        bail!("rp id mismatch");
+    }
+    if auth_data[32] & FLAG_UP == 0 {
+        bail!("user not present");
+    }
+    let key = VerifyingKey::from_sec1_bytes(&credential.public_key)?;
+    let signature = Signature::from_der(&crate::decode64(&assertion.signature)?)?;
+    let mut signed = auth_data;
+    signed.extend_from_slice(&Sha256::digest(challenge));
+    key.verify(&signed, &signature)?;
+    Ok(())
+}
+
+/// Check the assertion sent by the client against the challenge and the registered keys.
+pub fn verify(challenge: &[u8], content: &[u8]) -> bool {
+    let res = serde_json::from_slice::<Assertion>(content)
+        .map_err(hbb_common::anyhow::Error::from)
+        .and_then(|a| verify_assertion(challenge, &a, &credentials()));
+    if let Err(err) = &res {
+        hbb_common::log::warn!("security key verification failed: {}", err);
+    }
+    res.is_ok()
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod hid {
+    use super::*;
+    use ctap_hid_fido2::{
+        fidokey::{GetAssertionArgsBuilder, MakeCredentialArgsBuilder},
+        verifier, Cfg, FidoKeyHidFactory,
+    };
+
+    pub fn sign(content: &[u8]) -> ResultType<Message> {
+        let c: Challenge = serde_json::from_slice(content)?;
+        if c.rp_id != RP_ID {
+            bail!("unexpected rp id {}", c.rp_id);
+        }
+        let challenge = crate::decode64(&c.challenge)?;
+        let device = FidoKeyHidFactory::create(&Cfg::init())?;
+        for id in c.credential_ids.iter() {
+            let credential_id = crate::decode64(id)?;
+            let args = GetAssertionArgsBuilder::new(RP_ID, &challenge)
+                .credential_id(&credential_id)
+                .without_pin_and_uv()
+                .build();
+            // The key does not hold this credential, try the next one.
+            let Ok(assertions) = device.get_assertion_with_args(&args) else {
+                continue;
+            };
+            if let Some(a) = assertions.first() {
+                let assertion = Assertion {
+                    credential_id: id.clone(),
+                    auth_data: crate::encode64(&a.auth_data),
+                    signature: crate::encode64(&a.signature),
+                };
+                return Ok(make_msg(serde_json::to_vec(&assertion)?));
+            }
+        }
+        bail!("No registered security key found")
+    }
+
+    pub fn register() -> ResultType<String> {
+        let device = FidoKeyHidFactory::create(&Cfg::init())?;
+        let challenge = verifier::create_challenge();
+        let args = MakeCredentialArgsBuilder::new(RP_ID, &challenge)
+            .without_pin_and_uv()
+            .build();
+        let attestation = device.make_credential_with_args(&args)?;
+        let res = verifier::verify_attestation(RP_ID, &challenge, &attestation);
+        if !res.is_success {
+            bail!("Failed to verify the attestation");
+        }
+        Ok(format!(
+            "{}:{}",
+            crate::encode64(&res.credential_id),
+            crate::encode64(&res.credential_public_key.der)
+        ))
+    }
+}
+
+/// Get an assertion for the challenge from a local security key, blocks until the key
+/// is touched.
+pub fn sign(content: &[u8]) -> ResultType<Message> {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    return hid::sign(content);
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    {
+        let _ = content;
+        bail!("Security keys are not supported on this platform");
+    }
+}
+
+/// Answers the challenge if `msg` carries one, for the login loops without a session UI, e.g.
+/// the port forwarding and the CLI. Returns whether it was a challenge, fails if the key
+/// cannot sign, so they do not wait for the timeout.
+pub async fn answer(msg: &Message, stream: &mut Stream) -> ResultType<bool> {
+    let Some(message::Union::Misc(Misc {
+        union: Some(misc::Union::PluginRequest(p)),
+        ..
+    })) = &msg.union
+    else {
+        return Ok(false);
+    };
+    if p.id != PLUGIN_ID {
+        return Ok(false);
+    }
+    let content = p.content.to_vec();
+    let msg = match tokio::task::spawn_blocking(move || sign(&content)).await? {
+        Ok(msg) => msg,
+        Err(err) => bail!("Failed to sign with the security key: {}", err),
+    };
+    stream.send(&msg).await?;
+    Ok(true)
+}
+
+/// Register a new credential on a local security key, returns the line to be added to
+/// the security keys of the controlled side.
+pub fn register() -> ResultType<String> {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    return hid::register();
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    bail!("Security keys are not supported on this platform");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::{signature::Signer, SigningKey};
+
+    #[test]
+    fn test_verify_assertion() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32].into()).unwrap();
+        let public_key = signing_key
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec();
+        let line = format!(
+            "{}:{}",
+            crate::encode64(b"cred"),
+            crate::encode64(&public_key)
+        );
+        let credentials = parse_credentials(&format!("{}\n bad line", line));
+        assert_eq!(credentials.len(), 1);
+
+        let challenge = [1u8; CHALLENGE_LEN];
+        let mut auth_data = Sha256::digest(RP_ID.as_bytes()).to_vec();
+        auth_data.extend_from_slice(&[FLAG_UP, 0, 0, 0, 1]);
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(challenge));
+        let signature: Signature = signing_key.sign(&signed);
+        let mut assertion = Assertion {
+            credential_id: crate::encode64(b"cred"),
+            auth_data: crate::encode64(&auth_data),
+            signature: crate::encode64(signature.to_der().as_bytes()),
+        };
+        assert!(verify_assertion(&challenge, &assertion, &credentials).is_ok());
+        assert!(verify_assertion(&[2u8; CHALLENGE_LEN], &assertion, &credentials).is_err());
+        assertion.credential_id = crate::encode64(b"other");
+        assert!(verify_assertion(&challenge, &assertion, &credentials).is_err());
+    }
+}
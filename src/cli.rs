@@ -1,17 +1,54 @@
 use crate::client::*;
 use async_trait::async_trait;
 use hbb_common::{
+    bail,
     config::PeerConfig,
-    config::READ_TIMEOUT,
+    config::{LocalConfig, READ_TIMEOUT},
+    fs,
     futures::{SinkExt, StreamExt},
     log,
     message_proto::*,
     protobuf::Message as _,
     rendezvous_proto::ConnType,
-    tokio::{self, sync::mpsc},
-    Stream,
+    timeout,
+    tokio::{
+        self,
+        sync::mpsc,
+        time::{self, Duration, Instant},
+    },
+    ResultType, Stream,
 };
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+/// The password of the peer, so the scripts are not prompted.
+pub const PASSWORD_ENV: &str = "RUSTDESK_PASSWORD";
+/// The access token of the account, instead of the one of the logged in account.
+pub const TOKEN_ENV: &str = "RUSTDESK_TOKEN";
+/// The public key of the id server, instead of the configured one.
+pub const KEY_ENV: &str = "RUSTDESK_KEY";
+
+// The exit codes of `rustdesk cli`.
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+const USAGE: &str = "Usage:
+    rustdesk cli connect <id>
+    rustdesk cli send <id> <local-path>... <remote-dir>
+    rustdesk cli forward <id> <local-port>:<remote-host>:<remote-port>
+
+Environment:
+    RUSTDESK_PASSWORD    the password of the peer, prompted if not set and not remembered
+    RUSTDESK_TOKEN       the access token, the one of the logged in account if not set
+    RUSTDESK_KEY         the key of the id server, the configured one if not set
+
+Exit codes: 0 on success, 1 on errors, 2 on wrong arguments.";
 
 #[derive(Clone)]
 pub struct Session {
@@ -19,27 +56,32 @@ pub struct Session {
     lc: Arc<RwLock<LoginConfigHandler>>,
     sender: mpsc::UnboundedSender<Data>,
     password: String,
+    // No prompt if the password is wrong, the scripts may not have a terminal.
+    password_from_env: bool,
 }
 
 impl Session {
-    pub fn new(id: &str, sender: mpsc::UnboundedSender<Data>) -> Self {
-        let mut password = "".to_owned();
-        if PeerConfig::load(id).password.is_empty() {
-            password = rpassword::prompt_password("Enter password: ").unwrap();
+    pub fn new(id: &str, sender: mpsc::UnboundedSender<Data>, conn_type: ConnType) -> Self {
+        let mut password = std::env::var(PASSWORD_ENV).unwrap_or_default();
+        let password_from_env = !password.is_empty();
+        if !password_from_env && PeerConfig::load(id).password.is_empty() {
+            password = rpassword::prompt_password("Enter password: ").unwrap_or_default();
         }
         let session = Self {
             id: id.to_owned(),
             sender,
             password,
+            password_from_env,
             lc: Default::default(),
         };
         session.lc.write().unwrap().initialize(
             id.to_owned(),
-            ConnType::PORT_FORWARD,
+            conn_type,
             None,
             false,
             None,
             None,
+            None,
         );
         session
     }
@@ -47,7 +89,7 @@ impl Session {
 
 #[async_trait]
 impl Interface for Session {
-    fn get_login_config_handler(&self) -> Arc<RwLock<LoginConfigHandler>> {
+    fn get_lch(&self) -> Arc<RwLock<LoginConfigHandler>> {
         return self.lc.clone();
     }
 
@@ -55,14 +97,24 @@ impl Interface for Session {
         match msgtype {
             "input-password" => {
                 self.sender
-                    .send(Data::Login((self.password.clone(), true)))
+                    .send(Data::Login((
+                        "".to_owned(),
+                        "".to_owned(),
+                        self.password.clone(),
+                        true,
+                    )))
                     .ok();
             }
+            "re-input-password" if self.password_from_env => {
+                log::error!("{}: {}", title, text);
+                self.sender.send(Data::Close).ok();
+            }
             "re-input-password" => {
                 log::error!("{}: {}", title, text);
                 match rpassword::prompt_password("Enter password: ") {
                     Ok(password) => {
-                        let login_data = Data::Login((password, true));
+                        let login_data =
+                            Data::Login(("".to_owned(), "".to_owned(), password, true));
                         self.sender.send(login_data).ok();
                     }
                     Err(e) => {
@@ -87,6 +139,10 @@ impl Interface for Session {
         self.lc.write().unwrap().handle_peer_info(&pi);
     }
 
+    fn set_multiple_windows_session(&self, sessions: Vec<WindowsSession>) {
+        log::info!("windows sessions: {:?}", sessions);
+    }
+
     async fn handle_hash(&self, pass: &str, hash: Hash, peer: &mut Stream) {
         log::info!(
             "password={}",
@@ -126,12 +182,12 @@ impl Interface for Session {
 #[tokio::main(flavor = "current_thread")]
 pub async fn connect_test(id: &str, key: String, token: String) {
     let (sender, mut receiver) = mpsc::unbounded_channel::<Data>();
-    let handler = Session::new(&id, sender);
+    let handler = Session::new(&id, sender, ConnType::PORT_FORWARD);
     match crate::client::Client::start(id, &key, &token, ConnType::PORT_FORWARD, handler).await {
         Err(err) => {
             log::error!("Failed to connect {}: {}", &id, err);
         }
-        Ok((mut stream, direct)) => {
+        Ok(((mut stream, direct, ..), _)) => {
             log::info!("direct: {}", direct);
             // rpassword::prompt_password("Input anything to exit").ok();
             loop {
@@ -172,7 +228,7 @@ pub async fn start_one_port_forward(
     crate::common::test_rendezvous_server();
     crate::common::test_nat_type();
     let (sender, mut receiver) = mpsc::unbounded_channel::<Data>();
-    let handler = Session::new(&id, sender);
+    let handler = Session::new(&id, sender, ConnType::PORT_FORWARD);
     if let Err(err) = crate::port_forward::listen(
         handler.id.clone(),
         handler.password.clone(),
@@ -191,3 +247,373 @@ pub async fn start_one_port_forward(
     }
     log::info!("port forward (:{}) exit", port);
 }
+
+/// Saves a PNG screenshot of the display of the peer.
+#[tokio::main(flavor = "current_thread")]
+pub async fn take_screenshot(id: String, display: i32, path: String, key: String, token: String) {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Data>();
+    let handler = Session::new(&id, sender, ConnType::DEFAULT_CONN);
+    match take_screenshot_(handler, &mut receiver, display, &key, &token).await {
+        Ok(data) => match std::fs::write(&path, data) {
+            Ok(_) => log::info!("Screenshot of display {} is saved to {}", display, path),
+            Err(err) => log::error!("Failed to save screenshot to {}: {}", path, err),
+        },
+        Err(err) => {
+            log::error!("Failed to take screenshot of {}: {}", id, err);
+        }
+    }
+}
+
+async fn take_screenshot_(
+    handler: Session,
+    receiver: &mut mpsc::UnboundedReceiver<Data>,
+    display: i32,
+    key: &str,
+    token: &str,
+) -> ResultType<bytes::Bytes> {
+    let id = handler.id.clone();
+    let ((mut stream, direct, ..), _) =
+        Client::start(&id, key, token, ConnType::DEFAULT_CONN, handler.clone()).await?;
+    log::info!("direct: {}", direct);
+    loop {
+        tokio::select! {
+            res = timeout(READ_TIMEOUT, stream.next()) => match res {
+                Err(_) => {
+                    bail!("Timeout");
+                }
+                Ok(Some(Ok(bytes))) => {
+                    let msg_in = Message::parse_from_bytes(&bytes)?;
+                    match msg_in.union {
+                        Some(message::Union::Hash(hash)) => {
+                            handler.handle_hash(&handler.password, hash, &mut stream).await;
+                        }
+                        Some(message::Union::LoginResponse(lr)) => match lr.union {
+                            Some(login_response::Union::Error(err)) => {
+                                if !handler.handle_login_error(&err) {
+                                    bail!("{}", err);
+                                }
+                            }
+                            Some(login_response::Union::PeerInfo(pi)) => {
+                                if display < 0 || display as usize >= pi.displays.len() {
+                                    bail!("Display {} not found, displays: {}", display, pi.displays.len());
+                                }
+                                handler.handle_peer_info(pi);
+                                let mut msg_out = Message::new();
+                                msg_out.set_screenshot_request(ScreenshotRequest {
+                                    display,
+                                    sid: id.clone(),
+                                    ..Default::default()
+                                });
+                                stream.send(&msg_out).await?;
+                            }
+                            _ => {}
+                        },
+                        Some(message::Union::ScreenshotResponse(res)) => {
+                            if !res.msg.is_empty() {
+                                bail!("{}", res.msg);
+                            }
+                            return Ok(res.data);
+                        }
+                        Some(message::Union::TestDelay(t)) => {
+                            handler.handle_test_delay(t, &mut stream).await;
+                        }
+                        _ => {
+                            crate::auth_security_key::answer(&msg_in, &mut stream).await?;
+                        }
+                    }
+                }
+                Ok(Some(Err(err))) => {
+                    bail!("Connection closed: {}", err);
+                }
+                _ => {
+                    bail!("Reset by the peer");
+                }
+            },
+            d = receiver.recv() => {
+                if let Some(Data::Login((os_username, os_password, password, remember))) = d {
+                    handler
+                        .handle_login_from_ui(os_username, os_password, password, remember, &mut stream)
+                        .await;
+                }
+            }
+        }
+    }
+}
+
+/// Runs `rustdesk cli <subcommand>`, returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    let res = match args.as_slice() {
+        ["connect", id] => connect(id.to_string()),
+        ["send", id, paths @ .., to] if !paths.is_empty() => send_files(
+            id.to_string(),
+            paths.iter().map(|p| p.to_string()).collect(),
+            to.to_string(),
+        ),
+        ["forward", id, spec] => match parse_forward(spec) {
+            Some((port, remote_host, remote_port)) => {
+                forward(id.to_string(), port, remote_host, remote_port)
+            }
+            None => {
+                eprintln!("Wrong forward options: {}", spec);
+                return EXIT_USAGE;
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    match res {
+        Ok(_) => EXIT_OK,
+        Err(err) => {
+            eprintln!("{}", err);
+            EXIT_ERROR
+        }
+    }
+}
+
+// "<local-port>:<remote-host>:<remote-port>"
+fn parse_forward(spec: &str) -> Option<(i32, String, i32)> {
+    let mut it = spec.splitn(2, ':');
+    let port = it.next()?.parse().ok()?;
+    let (remote_host, remote_port) = it.next()?.rsplit_once(':')?;
+    let remote_host = remote_host.trim_start_matches('[').trim_end_matches(']');
+    if remote_host.is_empty() {
+        return None;
+    }
+    Some((port, remote_host.to_owned(), remote_port.parse().ok()?))
+}
+
+async fn key_and_token() -> (String, String) {
+    let key = match std::env::var(KEY_ENV) {
+        Ok(key) if !key.is_empty() => key,
+        _ => crate::get_key(true).await,
+    };
+    let token = match std::env::var(TOKEN_ENV) {
+        Ok(token) if !token.is_empty() => token,
+        _ => LocalConfig::get_option("access_token"),
+    };
+    (key, token)
+}
+
+// Connects and logs in, returns the stream once the peer info is received.
+async fn login(
+    handler: &Session,
+    receiver: &mut mpsc::UnboundedReceiver<Data>,
+    conn_type: ConnType,
+) -> ResultType<(Stream, PeerInfo)> {
+    let (key, token) = key_and_token().await;
+    let ((mut stream, direct, ..), _) =
+        Client::start(&handler.id, &key, &token, conn_type, handler.clone()).await?;
+    log::info!("direct: {}", direct);
+    loop {
+        tokio::select! {
+            res = timeout(READ_TIMEOUT, stream.next()) => {
+                let bytes = match res {
+                    Err(_) => bail!("Timeout"),
+                    Ok(Some(Ok(bytes))) => bytes,
+                    Ok(Some(Err(err))) => bail!("Connection closed: {}", err),
+                    Ok(None) => bail!("Reset by the peer"),
+                };
+                let msg_in = Message::parse_from_bytes(&bytes)?;
+                match msg_in.union {
+                    Some(message::Union::Hash(hash)) => {
+                        handler.handle_hash(&handler.password, hash, &mut stream).await;
+                    }
+                    Some(message::Union::LoginResponse(lr)) => match lr.union {
+                        Some(login_response::Union::Error(err)) => {
+                            if !handler.handle_login_error(&err) {
+                                bail!("{}", err);
+                            }
+                        }
+                        Some(login_response::Union::PeerInfo(pi)) => {
+                            handler.handle_peer_info(pi.clone());
+                            return Ok((stream, pi));
+                        }
+                        _ => {}
+                    },
+                    Some(message::Union::TestDelay(t)) => {
+                        handler.handle_test_delay(t, &mut stream).await;
+                    }
+                    _ => {
+                        crate::auth_security_key::answer(&msg_in, &mut stream).await?;
+                    }
+                }
+            }
+            d = receiver.recv() => match d {
+                Some(Data::Login((os_username, os_password, password, remember))) => {
+                    handler
+                        .handle_login_from_ui(os_username, os_password, password, remember, &mut stream)
+                        .await;
+                }
+                Some(Data::Close) => bail!("Wrong password"),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn connect(id: String) -> ResultType<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Data>();
+    let handler = Session::new(&id, sender, ConnType::DEFAULT_CONN);
+    let (_, pi) = login(&handler, &mut receiver, ConnType::DEFAULT_CONN).await?;
+    println!(
+        "connected {} {} {} {}",
+        id, pi.platform, pi.hostname, pi.version
+    );
+    Ok(())
+}
+
+/// Uploads the files or folders to the folder of the peer, the progress is printed every second,
+/// "progress <finished bytes> <total bytes> <bytes per second>", then "done".
+#[tokio::main(flavor = "current_thread")]
+async fn send_files(id: String, paths: Vec<String>, to: String) -> ResultType<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Data>();
+    let handler = Session::new(&id, sender, ConnType::FILE_TRANSFER);
+    let (mut stream, pi) = login(&handler, &mut receiver, ConnType::FILE_TRANSFER).await?;
+    let is_windows = pi.platform == "Windows";
+    let mut jobs: Vec<fs::TransferJob> = Vec::new();
+    // The jobs not done by the peer yet.
+    let mut pending = HashSet::new();
+    let mut total_size = 0;
+    for (i, path) in paths.iter().enumerate() {
+        let job_id = i as i32 + 1;
+        let to = crate::transfer_broker::target_path(path, &to, is_windows);
+        let job = fs::TransferJob::new_read(
+            job_id,
+            fs::JobType::Generic,
+            to.clone(),
+            fs::DataSource::FilePath(PathBuf::from(path)),
+            0,
+            false,
+            false,
+            true,
+        )?;
+        #[cfg(not(windows))]
+        let files = job.files().clone();
+        #[cfg(windows)]
+        let mut files = job.files().clone();
+        #[cfg(windows)]
+        if !is_windows {
+            fs::transform_windows_path(&mut files);
+        }
+        total_size += job.total_size();
+        stream
+            .send(&fs::new_receive(job_id, to, 0, files, job.total_size()))
+            .await?;
+        jobs.push(job);
+        pending.insert(job_id);
+    }
+    let mut timer = crate::rustdesk_interval(time::interval(MILLI1));
+    let mut last_progress = (Instant::now(), 0);
+    while !pending.is_empty() {
+        tokio::select! {
+            res = timeout(READ_TIMEOUT, stream.next()) => {
+                let bytes = match res {
+                    Err(_) => bail!("Timeout"),
+                    Ok(Some(Ok(bytes))) => bytes,
+                    Ok(Some(Err(err))) => bail!("Connection closed: {}", err),
+                    Ok(None) => bail!("Reset by the peer"),
+                };
+                let msg_in = Message::parse_from_bytes(&bytes)?;
+                match msg_in.union {
+                    Some(message::Union::FileResponse(fr)) => match fr.union {
+                        // The file differs from the one of the peer, overwritten.
+                        Some(file_response::Union::Digest(digest)) if digest.is_upload => {
+                            if let Some(job) = fs::get_job(digest.id, &mut jobs) {
+                                let req = FileTransferSendConfirmRequest {
+                                    id: digest.id,
+                                    file_num: digest.file_num,
+                                    union: Some(
+                                        file_transfer_send_confirm_request::Union::OffsetBlk(0),
+                                    ),
+                                    ..Default::default()
+                                };
+                                job.confirm(&req).await;
+                                stream.send(&fs::new_send_confirm(req)).await?;
+                            }
+                        }
+                        Some(file_response::Union::Done(d)) => {
+                            pending.remove(&d.id);
+                        }
+                        Some(file_response::Union::Error(e)) => {
+                            bail!("{}", e.error);
+                        }
+                        _ => {}
+                    },
+                    Some(message::Union::FileAction(action)) => {
+                        if let Some(file_action::Union::SendConfirm(c)) = action.union {
+                            if let Some(job) = fs::get_job(c.id, &mut jobs) {
+                                job.confirm(&c).await;
+                            }
+                        }
+                    }
+                    Some(message::Union::TestDelay(t)) => {
+                        handler.handle_test_delay(t, &mut stream).await;
+                    }
+                    _ => {}
+                }
+            }
+            _ = timer.tick(), if !jobs.is_empty() => {
+                fs::handle_read_jobs(&mut jobs, &mut stream).await?;
+                let elapsed = last_progress.0.elapsed();
+                if elapsed >= PROGRESS_INTERVAL {
+                    // The jobs read are removed, their files are sent.
+                    let remaining: u64 = jobs
+                        .iter()
+                        .map(|j| j.total_size().saturating_sub(j.finished_size()))
+                        .sum();
+                    let finished_size = total_size - remaining.min(total_size);
+                    let speed = (finished_size - last_progress.1) as f64 / elapsed.as_secs_f64();
+                    println!("progress {} {} {:.0}", finished_size, total_size, speed);
+                    last_progress = (Instant::now(), finished_size);
+                }
+            }
+        }
+    }
+    println!("done {}", total_size);
+    Ok(())
+}
+
+/// Forwards the local port to the port of the host in the network of the peer, until killed.
+#[tokio::main(flavor = "current_thread")]
+async fn forward(id: String, port: i32, remote_host: String, remote_port: i32) -> ResultType<()> {
+    let (sender, receiver) = mpsc::unbounded_channel::<Data>();
+    let handler = Session::new(&id, sender, ConnType::PORT_FORWARD);
+    let (key, token) = key_and_token().await;
+    println!("listening {} {}:{}", port, remote_host, remote_port);
+    crate::port_forward::listen(
+        handler.id.clone(),
+        handler.password.clone(),
+        port,
+        handler.clone(),
+        receiver,
+        &key,
+        &token,
+        handler.lc.clone(),
+        remote_host,
+        remote_port,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_forward() {
+        assert_eq!(
+            parse_forward("8080:localhost:80"),
+            Some((8080, "localhost".to_owned(), 80))
+        );
+        assert_eq!(
+            parse_forward("3389:[fe80::1]:3389"),
+            Some((3389, "fe80::1".to_owned(), 3389))
+        );
+        assert_eq!(parse_forward("8080:80"), None);
+        assert_eq!(parse_forward("a:localhost:80"), None);
+    }
+}
@@ -15,12 +15,13 @@ use magnum_opus::{Channels::*, Decoder as AudioDecoder};
 use ringbuf::{ring_buffer::RbBase, Rb};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     ffi::c_void,
     net::SocketAddr,
     ops::Deref,
     str::FromStr,
     sync::{
+        atomic::{AtomicUsize, Ordering},
         mpsc::{self, RecvTimeoutError},
         Arc, Mutex, RwLock,
     },
@@ -30,7 +31,7 @@ use uuid::Uuid;
 use crate::{
     check_port,
     common::input::{MOUSE_BUTTON_LEFT, MOUSE_BUTTON_RIGHT, MOUSE_TYPE_DOWN, MOUSE_TYPE_UP},
-    create_symmetric_key_msg, decode_id_pk, get_rs_pk, is_keyboard_mode_supported,
+    create_symmetric_key_msg, decode_id_pk, get_rs_pk, ice, is_keyboard_mode_supported,
     kcp_stream::KcpStream,
     secure_tcp,
     ui_interface::{get_builtin_option, use_texture_render},
@@ -75,7 +76,7 @@ use hbb_common::{
 pub use helper::*;
 use scrap::{
     codec::Decoder,
-    record::{Recorder, RecorderContext},
+    record::{RecordAudio, Recorder, RecorderContext},
     CodecFormat, ImageFormat, ImageRgb, ImageTexture,
 };
 
@@ -89,6 +90,7 @@ use crate::ui_session_interface::SessionPermissionConfig;
 
 pub use super::lang::*;
 
+pub mod bonding;
 pub mod file_trait;
 pub mod helper;
 pub mod io_loop;
@@ -115,6 +117,8 @@ pub const LOGIN_MSG_PASSWORD_EMPTY: &str = "Empty Password";
 pub const LOGIN_MSG_PASSWORD_WRONG: &str = "Wrong Password";
 pub const LOGIN_MSG_2FA_WRONG: &str = "Wrong 2FA Code";
 pub const REQUIRE_2FA: &'static str = "2FA Required";
+pub const LOGIN_MSG_SECURITY_KEY_WRONG: &str = "Security key verification failed";
+pub const REQUIRE_SECURITY_KEY: &str = "Security Key Required";
 pub const LOGIN_MSG_NO_PASSWORD_ACCESS: &str = "No Password Access";
 pub const LOGIN_MSG_OFFLINE: &str = "Offline";
 pub const LOGIN_SCREEN_WAYLAND: &str = "Wayland login screen is not supported";
@@ -126,8 +130,123 @@ pub const SCRAP_OTHER_VERSION_OR_X11_REQUIRED: &str =
 pub const SCRAP_X11_REQUIRED: &str = "x11 expected";
 pub const SCRAP_X11_REF_URL: &str = "https://rustdesk.com/docs/en/manual/linux/#x11-required";
 
+/// Peer option, never fall back to a relay for this peer.
+pub const PEER_OPTION_DIRECT_ONLY: &str = "direct-only";
+/// Peer option, the relay server to use for this peer when we request a relay,
+/// instead of the one given by the rendezvous server.
+pub const PEER_OPTION_RELAY_SERVER: &str = "peer-relay-server";
+/// Peer option, the direct access port used when connecting to this peer by IP.
+pub const PEER_OPTION_DIRECT_PORT: &str = "direct-access-port";
+/// Local option, mux the remote audio into the recordings of the outgoing sessions.
+pub const OPTION_RECORD_AUDIO: &str = "allow-record-audio";
+
+/// Plugin request id to ask the controlled side for AV1 temporal layers.
+pub const AV1_SVC_PLUGIN_ID: &str = "__av1_svc";
+
+/// Plugin request id to tell the controlled side this side tone-maps 10-bit HDR AV1.
+pub const HDR_PLUGIN_ID: &str = "__hdr";
+
+/// Plugin request id to ask the controlled side for the stats of its bandwidth estimation,
+/// which it then sends with the same id every few seconds, [`BweStats`] in json.
+pub const BWE_STATS_PLUGIN_ID: &str = "__bwe_stats";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BweStats {
+    /// The estimated bottleneck bandwidth in kbps.
+    #[serde(default)]
+    pub bandwidth: Option<u32>,
+    /// "Startup", "Drain" or "ProbeBW", "Off" if the estimation is disabled.
+    #[serde(default)]
+    pub state: String,
+}
+
+/// Plugin request id to plug in a virtual display with a mode, or change the mode of it.
+/// The content is [`VirtualDisplayMode`] in json.
+pub const VIRTUAL_DISPLAY_MODE_PLUGIN_ID: &str = "__virtual_display_mode";
+
+/// Plugin request id to list the audio devices of the controlled side with an empty content,
+/// or to capture one of them with [`AudioDevices::current`] in json.
+/// The response is [`AudioDevices`].
+pub const AUDIO_DEVICE_PLUGIN_ID: &str = "__audio_device";
+/// Peer option, the audio device of the peer to capture, empty for the default one.
+pub const PEER_OPTION_AUDIO_DEVICE: &str = "audio-device";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AudioDevices {
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// The selected device, empty for the default one.
+    #[serde(default)]
+    pub current: String,
+}
+
+/// Plugin request id to play the microphone of this side on the controlled side,
+/// without a voice call.
+/// The content is "Y" to start and "N" to stop.
+pub const MIC_PASSTHROUGH_PLUGIN_ID: &str = "__mic_passthrough";
+
+/// Plugin request id to report the percent of the audio frames concealed recently,
+/// the controlled side tunes the audio encoder with it.
+/// The content is the percent in decimal.
+pub const AUDIO_LOSS_PLUGIN_ID: &str = "__audio_loss";
+
+/// Plugin request id sent before the login request, to start a session with the audio only,
+/// no video and no input.
+/// The content is empty.
+pub const AUDIO_ONLY_PLUGIN_ID: &str = "__audio_only";
+
+/// Plugin request id with the timestamp of the next audio frame, in the clock of the
+/// timestamps of the video frames, the following frames are counted from it.
+/// The content is the milliseconds in decimal.
+pub const AUDIO_PTS_PLUGIN_ID: &str = "__audio_pts";
+
+/// Plugin request id to tell the controlling side the power state of the controlled side,
+/// sent when it changes.
+/// The content is [`PowerState`] in json.
+pub const POWER_STATE_PLUGIN_ID: &str = "__power_state";
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PowerState {
+    #[serde(default)]
+    pub on_battery: bool,
+    #[serde(default)]
+    pub battery_percent: Option<u32>,
+    #[serde(default)]
+    pub thermal_throttled: bool,
+    /// The capture rate is reduced for the state above.
+    #[serde(default)]
+    pub throttled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtualDisplayMode {
+    pub index: u32,
+    pub width: u32,
+    pub height: u32,
+    pub refresh: u32,
+}
+
+impl VirtualDisplayMode {
+    pub fn is_valid(&self) -> bool {
+        (320..=8192).contains(&self.width)
+            && (320..=8192).contains(&self.height)
+            && (24..=240).contains(&self.refresh)
+    }
+}
+
 #[cfg(not(target_os = "linux"))]
 pub const AUDIO_BUFFER_MS: usize = 3000;
+// Longer gaps are the silence gate of the peer rather than late frames, they are not concealed.
+const AUDIO_CONCEAL_MAX_GAP: Duration = Duration::from_millis(500);
+// Audio and video out of sync less than it is not noticeable.
+const AV_SYNC_TOLERANCE_MS: i64 = 45;
+// The audio waits at most this long for the late video, e.g. the video under load.
+const AV_SYNC_MAX_AUDIO_DELAY: Duration = Duration::from_millis(1000);
+// The video waits at most this long for the late audio.
+const AV_SYNC_MAX_VIDEO_DELAY: Duration = Duration::from_millis(200);
+// A clock is not followed if not updated for longer, e.g. a static screen or silence.
+const AV_SYNC_STALE: Duration = Duration::from_millis(500);
+const AV_SYNC_POLL: Duration = Duration::from_millis(10);
 
 #[cfg(feature = "flutter")]
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -250,12 +369,20 @@ impl Client {
         if config::is_incoming_only() {
             bail!("Incoming only mode");
         }
-        // to-do: remember the port for each peer, so that we can retry easier
         if hbb_common::is_ip_str(peer) {
+            let port = interface
+                .get_lch()
+                .read()
+                .unwrap()
+                .get_option(PEER_OPTION_DIRECT_PORT)
+                .parse::<u16>()
+                .ok()
+                .filter(|p| *p > 0)
+                .map(|p| p as i32)
+                .unwrap_or(RELAY_PORT + 1);
             return Ok((
                 (
-                    connect_tcp_local(check_port(peer, RELAY_PORT + 1), None, CONNECT_TIMEOUT)
-                        .await?,
+                    connect_tcp_local(check_port(peer, port), None, CONNECT_TIMEOUT).await?,
                     true,
                     None,
                     None,
@@ -386,7 +513,8 @@ impl Client {
         bool,
     )> {
         let mut start = Instant::now();
-        let mut socket = connect_tcp(&*rendezvous_server, CONNECT_TIMEOUT).await;
+        let mut socket =
+            crate::tls_pin::connect_tcp(rendezvous_server.clone(), CONNECT_TIMEOUT).await;
         debug_assert!(!servers.contains(&rendezvous_server));
         let rtt = start.elapsed();
         log::debug!("TCP connection establishment time used: {:?}", rtt);
@@ -394,7 +522,7 @@ impl Client {
             log::info!("try the other servers: {:?}", servers);
             for server in servers {
                 let server = check_port(server, RENDEZVOUS_PORT);
-                socket = connect_tcp(&*server, CONNECT_TIMEOUT).await;
+                socket = crate::tls_pin::connect_tcp(server.clone(), CONNECT_TIMEOUT).await;
                 if socket.is_ok() {
                     rendezvous_server = server;
                     break;
@@ -404,6 +532,13 @@ impl Client {
         } else if !contained {
             crate::refresh_rendezvous_server();
         }
+        if socket.is_err() {
+            // No IPv4 route, e.g. IPv6-only network with NAT64.
+            if let (_, Some(addr_v6)) = crate::resolve_dual_stack(&rendezvous_server).await {
+                log::info!("try the rendezvous server over IPv6: {}", addr_v6);
+                socket = connect_tcp(addr_v6, CONNECT_TIMEOUT).await;
+            }
+        }
         log::info!("rendezvous server: {}", rendezvous_server);
         let mut socket = socket?;
         let my_addr = socket.local_addr();
@@ -452,6 +587,7 @@ impl Client {
         } else {
             (None, None)
         };
+        let mut turn = None;
         let udp_nat_port = udp.1.map(|x| *x.lock().unwrap()).unwrap_or(0);
         let punch_type = if udp_nat_port > 0 { "UDP" } else { "TCP" };
         msg_out.set_punch_hole_request(PunchHoleRequest {
@@ -504,7 +640,10 @@ impl Client {
                             peer_nat_type = ph.nat_type();
                             is_local = ph.is_local();
                             signed_id_pk = ph.pk.into();
-                            relay_server = ph.relay_server;
+                            relay_server = crate::relay_latency::select(&peer_relay_server(
+                                &interface,
+                                ph.relay_server,
+                            ));
                             peer_addr = AddrMangle::decode(&ph.socket_addr);
                             feedback = ph.feedback;
                             let s = udp.0.take();
@@ -515,12 +654,16 @@ impl Client {
                                 }
                             }
                             let s = ipv6.0.take();
-                            if !ph.socket_addr_v6.is_empty() && s.is_some() {
+                            if !ph.socket_addr_v6.is_empty() {
                                 let addr = AddrMangle::decode(&ph.socket_addr_v6);
                                 if addr.port() > 0 {
-                                    if let Some(s) = s {
-                                        allow_err!(s.connect(addr).await);
-                                        ipv6.0 = Some(s);
+                                    if addr.is_ipv6() {
+                                        if let Some(s) = s {
+                                            allow_err!(s.connect(addr).await);
+                                            ipv6.0 = Some(s);
+                                        }
+                                    } else {
+                                        turn = turn_candidate(addr).await;
                                     }
                                 }
                             }
@@ -535,43 +678,75 @@ impl Client {
                             rr.relay_server
                         );
                         start = Instant::now();
-                        let mut connect_futures = Vec::new();
+                        let mut checks = Vec::new();
+                        let direct_only = interface.is_direct_only();
+                        let addr = AddrMangle::decode(&rr.socket_addr_v6);
+                        if addr.port() > 0 && addr.is_ipv4() && !direct_only {
+                            if let Some(s) = turn_candidate(addr).await {
+                                checks.push((
+                                    ice::Candidate::new(ice::CandidateType::Relay, addr, "UDP"),
+                                    udp_turn_connect(s, CONNECT_TIMEOUT, use_fec(&interface))
+                                        .boxed(),
+                                ));
+                            }
+                        }
                         if let Some(s) = ipv6.0 {
-                            let addr = AddrMangle::decode(&rr.socket_addr_v6);
-                            if addr.port() > 0 {
+                            if addr.port() > 0 && addr.is_ipv6() {
                                 if s.connect(addr).await.is_ok() {
-                                    connect_futures
-                                        .push(udp_nat_connect(s, "IPv6", CONNECT_TIMEOUT).boxed());
+                                    checks.push((
+                                        ice::Candidate::new(
+                                            ice::CandidateType::ServerReflexive,
+                                            addr,
+                                            "UDP",
+                                        ),
+                                        udp_nat_connect(
+                                            s,
+                                            "IPv6",
+                                            CONNECT_TIMEOUT,
+                                            use_fec(&interface),
+                                        )
+                                        .boxed(),
+                                    ));
                                 }
                             }
                         }
                         signed_id_pk = rr.pk().into();
-                        let fut = Self::create_relay(
-                            &peer,
-                            rr.uuid,
-                            rr.relay_server,
-                            &key,
-                            conn_type,
-                            my_addr.is_ipv4(),
-                        );
-                        connect_futures.push(
-                            async move {
-                                let conn = fut.await?;
-                                Ok((conn, None, if use_ws() { "WebSocket" } else { "Relay" }))
-                            }
-                            .boxed(),
-                        );
-                        // Run all connection attempts concurrently, return the first successful one
-                        let (conn, kcp, typ) = match select_ok(connect_futures).await {
-                            Ok(conn) => (Ok(conn.0 .0), conn.0 .1, conn.0 .2),
+                        if !direct_only {
+                            let fut = Self::create_relay(
+                                &peer,
+                                rr.uuid,
+                                rr.relay_server,
+                                &key,
+                                conn_type,
+                                my_addr.is_ipv4(),
+                            );
+                            // The address of the relay server is only known once connected.
+                            checks.push((
+                                ice::Candidate::new(
+                                    ice::CandidateType::Relay,
+                                    SocketAddr::from(([0, 0, 0, 0], 0)),
+                                    "TCP",
+                                ),
+                                async move {
+                                    let conn = fut.await?;
+                                    Ok((conn, None, if use_ws() { "WebSocket" } else { "Relay" }))
+                                }
+                                .boxed(),
+                            ));
+                        } else if checks.is_empty() {
+                            bail!("Peer requested relay, but relay is disabled for this peer");
+                        }
+                        let (conn, kcp, typ) = match ice::select(checks).await {
+                            Ok(conn) => (Ok(conn.0), conn.1, conn.2),
 
                             Err(e) => (Err(e), None, ""),
                         };
                         let mut conn = conn?;
                         feedback = rr.feedback;
                         log::info!("{:?} used to establish {typ} connection", start.elapsed());
-                        let pk =
+                        let (pk, sas) =
                             Self::secure_connection(&peer, signed_id_pk, &key, &mut conn).await?;
+                        interface.get_lch().write().unwrap().sas = sas;
                         return Ok((
                             (conn, typ == "IPv6", pk, kcp, typ),
                             (feedback, rendezvous_server),
@@ -618,6 +793,7 @@ impl Client {
                 interface,
                 udp.0,
                 ipv6.0,
+                turn,
                 punch_type,
             )
             .await?,
@@ -644,6 +820,7 @@ impl Client {
         interface: impl Interface,
         udp_socket_nat: Option<Arc<UdpSocket>>,
         udp_socket_v6: Option<Arc<UdpSocket>>,
+        udp_socket_turn: Option<Arc<UdpSocket>>,
         punch_type: &str,
     ) -> ResultType<(
         Stream,
@@ -686,30 +863,56 @@ impl Client {
         }
         log::info!("peer address: {}, timeout: {}", peer, connect_timeout);
         let start = std::time::Instant::now();
-
-        let mut connect_futures = Vec::new();
+        let fec = use_fec(&interface);
+        let mut checks = Vec::new();
         let fut = connect_tcp_local(peer, Some(local_addr), connect_timeout);
-        connect_futures.push(
+        checks.push((
+            ice::Candidate::new(
+                if is_local {
+                    ice::CandidateType::Host
+                } else {
+                    ice::CandidateType::ServerReflexive
+                },
+                peer,
+                "TCP",
+            ),
             async move {
                 let conn = fut.await?;
                 Ok((conn, None, "TCP"))
             }
             .boxed(),
-        );
+        ));
         if let Some(udp_socket_nat) = udp_socket_nat {
-            connect_futures.push(udp_nat_connect(udp_socket_nat, "UDP", connect_timeout).boxed());
+            checks.push((
+                ice::Candidate::new(ice::CandidateType::ServerReflexive, peer, "UDP"),
+                udp_nat_connect(udp_socket_nat, "UDP", connect_timeout, fec).boxed(),
+            ));
         }
         if let Some(udp_socket_v6) = udp_socket_v6 {
-            connect_futures.push(udp_nat_connect(udp_socket_v6, "IPv6", connect_timeout).boxed());
+            let addr = udp_socket_v6.peer_addr().unwrap_or(peer);
+            checks.push((
+                ice::Candidate::new(ice::CandidateType::ServerReflexive, addr, "UDP"),
+                udp_nat_connect(udp_socket_v6, "IPv6", connect_timeout, fec).boxed(),
+            ));
         }
-        // Run all connection attempts concurrently, return the first successful one
-        let (mut conn, kcp, mut typ) = match select_ok(connect_futures).await {
-            Ok(conn) => (Ok(conn.0 .0), conn.0 .1, conn.0 .2),
+        if let Some(udp_socket_turn) = udp_socket_turn {
+            let addr = udp_socket_turn.peer_addr().unwrap_or(peer);
+            checks.push((
+                ice::Candidate::new(ice::CandidateType::Relay, addr, "UDP"),
+                udp_turn_connect(udp_socket_turn, connect_timeout, fec).boxed(),
+            ));
+        }
+        let (mut conn, kcp, mut typ) = match ice::select(checks).await {
+            Ok(conn) => (Ok(conn.0), conn.1, conn.2),
             Err(e) => (Err(e), None, ""),
         };
 
-        let mut direct = !conn.is_err();
+        let mut direct = !conn.is_err() && typ != "TURN";
         if interface.is_force_relay() || conn.is_err() {
+            if interface.is_direct_only() {
+                interface.update_direct(Some(false));
+                bail!("Failed to make direct connection to remote desktop, relay is disabled for this peer");
+            }
             if !relay_server.is_empty() {
                 conn = Self::request_relay(
                     peer_id,
@@ -738,9 +941,31 @@ impl Client {
             start.elapsed(),
             punch_type
         );
-        let res = Self::secure_connection(peer_id, signed_id_pk, key, &mut conn).await;
+        let bonding_mode = bonding::BondingMode::local();
+        let bond_id = if direct
+            && kcp.is_none()
+            && !relay_server.is_empty()
+            && !interface.is_direct_only()
+            && bonding_mode != bonding::BondingMode::Off
+            && interface
+                .get_lch()
+                .read()
+                .unwrap()
+                .get_option(bonding::PEER_OPTION_BONDING_SUPPORTED)
+                == "Y"
+        {
+            let bond_id = bonding::new_bond_id();
+            bonding::hello(&mut conn, &bond_id, 0, bonding_mode).await?;
+            Some(bond_id)
+        } else {
+            None
+        };
+        let res = Self::secure_connection(peer_id, signed_id_pk.clone(), key, &mut conn).await;
         let pk: Option<Vec<u8>> = match res {
-            Ok(pk) => pk,
+            Ok((pk, sas)) => {
+                interface.get_lch().write().unwrap().sas = sas;
+                pk
+            }
             Err(e) => {
                 // this direct is mainly used by on_establish_connection_error, so we update it here before bail
                 interface.update_direct(Some(direct));
@@ -748,16 +973,115 @@ impl Client {
             }
         };
         log::debug!("{} punch secure_connection ok", punch_type);
+        if let Some(bond_id) = bond_id {
+            let (bonded, sas) = Self::bond_with_relay(
+                conn,
+                bond_id,
+                bonding_mode,
+                peer_id,
+                signed_id_pk,
+                relay_server,
+                rendezvous_server,
+                key,
+                token,
+                conn_type,
+            )
+            .await?;
+            conn = bonded;
+            if sas.is_some() {
+                interface.get_lch().write().unwrap().sas = sas;
+            }
+            typ = "Bonded";
+        }
         Ok((conn, direct, pk, kcp, typ))
     }
 
+    /// Turn the direct connection, whose hello is sent before its handshake, into a bond and add
+    /// a relay path in the background.
+    async fn bond_with_relay(
+        mut conn: Stream,
+        bond_id: bonding::BondId,
+        mode: bonding::BondingMode,
+        peer_id: &str,
+        signed_id_pk: Vec<u8>,
+        relay_server: &str,
+        rendezvous_server: &str,
+        key: &str,
+        token: &str,
+        conn_type: ConnType,
+    ) -> ResultType<(Stream, Option<String>)> {
+        if let Some(pending) = bonding::offer(&mut conn, &bond_id).await? {
+            log::info!("Peer refused bonding, keep the direct connection only");
+            let (conn, _) = bonding::spawn(
+                conn,
+                "direct",
+                bonding::BondingMode::Off,
+                Some(pending),
+                None,
+            );
+            return Ok((conn, None));
+        }
+        let (mut bonded, adder) = bonding::spawn(conn, "direct", mode, None, None);
+        let (_, sas) =
+            Self::secure_connection(peer_id, signed_id_pk.clone(), key, &mut bonded).await?;
+        let peer_id = peer_id.to_owned();
+        let relay_server = relay_server.to_owned();
+        let rendezvous_server = rendezvous_server.to_owned();
+        let key = key.to_owned();
+        let token = token.to_owned();
+        tokio::spawn(async move {
+            let res: ResultType<()> = async {
+                let mut relay = Self::request_relay(
+                    &peer_id,
+                    relay_server,
+                    &rendezvous_server,
+                    !signed_id_pk.is_empty(),
+                    &key,
+                    &token,
+                    conn_type,
+                )
+                .await?;
+                bonding::hello(&mut relay, &bond_id, 1, mode).await?;
+                Self::secure_connection(&peer_id, signed_id_pk, &key, &mut relay).await?;
+                if bonding::offer(&mut relay, &bond_id).await?.is_some() {
+                    bail!("relay path refused by peer");
+                }
+                adder.send((relay, "relay")).ok();
+                Ok(())
+            }
+            .await;
+            if let Err(err) = res {
+                log::warn!("Failed to add relay path to bond: {}", err);
+            }
+        });
+        Ok((bonded, sas))
+    }
+
+    /// Open another connection to the peer, negotiated like the main one, as the file stream.
+    pub async fn connect_file_stream(
+        peer: &str,
+        key: &str,
+        token: &str,
+        conn_type: ConnType,
+        interface: impl Interface,
+        file_token: &crate::file_stream::Token,
+    ) -> ResultType<Stream> {
+        // The short authentication string shown is the one of the main connection.
+        let sas = interface.get_lch().read().unwrap().sas.clone();
+        let res = Self::_start(peer, key, token, conn_type, interface.clone()).await;
+        interface.get_lch().write().unwrap().sas = sas;
+        let ((mut stream, ..), ..) = res?;
+        crate::file_stream::offer(&mut stream, file_token).await?;
+        Ok(stream)
+    }
+
     /// Establish secure connection with the server.
     async fn secure_connection(
         peer_id: &str,
         signed_id_pk: Vec<u8>,
         key: &str,
         conn: &mut Stream,
-    ) -> ResultType<Option<Vec<u8>>> {
+    ) -> ResultType<(Option<Vec<u8>>, Option<String>)> {
         let rs_pk = get_rs_pk(if key.is_empty() {
             config::RS_PUB_KEY
         } else {
@@ -783,9 +1107,10 @@ impl Client {
             None => {
                 // send an empty message out in case server is setting up secure and waiting for first message
                 conn.send(&Message::new()).await?;
-                return Ok(option_pk);
+                return Ok((option_pk, None));
             }
         };
+        let mut sas = None;
         match timeout(READ_TIMEOUT, conn.next()).await? {
             Some(res) => {
                 let bytes = res?;
@@ -795,6 +1120,11 @@ impl Client {
                             if id == peer_id {
                                 let (asymmetric_value, symmetric_value, key) =
                                     create_symmetric_key_msg(their_pk_b);
+                                sas = Some(crate::sas::compute(
+                                    &their_pk_b,
+                                    &asymmetric_value,
+                                    &symmetric_value,
+                                ));
                                 let mut msg_out = Message::new();
                                 msg_out.set_public_key(PublicKey {
                                     asymmetric_value,
@@ -827,7 +1157,7 @@ impl Client {
                 bail!("Reset by the peer");
             }
         }
-        Ok(option_pk)
+        Ok((option_pk, sas))
     }
 
     /// Request a relay connection to the server.
@@ -846,9 +1176,10 @@ impl Client {
 
         for i in 1..=3 {
             // use different socket due to current hbbs implementation requiring different nat address for each attempt
-            let mut socket = connect_tcp(rendezvous_server, CONNECT_TIMEOUT)
-                .await
-                .with_context(|| "Failed to connect to rendezvous server")?;
+            let mut socket =
+                crate::tls_pin::connect_tcp(rendezvous_server.to_owned(), CONNECT_TIMEOUT)
+                    .await
+                    .with_context(|| "Failed to connect to rendezvous server")?;
 
             if !key.is_empty() && !token.is_empty() {
                 // mainly for the security of token
@@ -903,7 +1234,7 @@ impl Client {
         conn_type: ConnType,
         ipv4: bool,
     ) -> ResultType<Stream> {
-        let mut conn = connect_tcp(
+        let mut conn = crate::tls_pin::connect_tcp(
             ipv4_to_ipv6(check_port(relay_server, RELAY_PORT), ipv4),
             CONNECT_TIMEOUT,
         )
@@ -1171,10 +1502,98 @@ impl ClientClipboardHandler {
     }
 }
 
+/// Audio frames decoded and concealed by an [`AudioHandler`].
+#[derive(Default)]
+pub struct AudioStats {
+    frames: AtomicUsize,
+    concealed: AtomicUsize,
+}
+
+impl AudioStats {
+    /// Percent of the frames concealed since the last call, None if no frame.
+    pub fn take_loss_percent(&self) -> Option<u32> {
+        let frames = self.frames.swap(0, Ordering::Relaxed);
+        let concealed = self.concealed.swap(0, Ordering::Relaxed);
+        if frames == 0 {
+            return None;
+        }
+        Some((concealed * 100 / (frames + concealed)) as u32)
+    }
+}
+
+/// The timestamps of the audio and the video being played, in the clock of the peer.
+///
+/// The audio waits for the video which is late, and the video for the audio, with limits.
+#[derive(Default)]
+pub struct AvSync {
+    audio: Mutex<Option<(i64, Instant)>>,
+    video: Mutex<Option<(i64, Instant)>>,
+}
+
+impl AvSync {
+    fn now(clock: &Mutex<Option<(i64, Instant)>>) -> Option<i64> {
+        let (pts, instant) = (*clock.lock().unwrap())?;
+        let elapsed = instant.elapsed();
+        if elapsed > AV_SYNC_STALE {
+            return None;
+        }
+        Some(pts + elapsed.as_millis() as i64)
+    }
+
+    #[inline]
+    fn audio_now(&self) -> Option<i64> {
+        Self::now(&self.audio)
+    }
+
+    #[inline]
+    fn video_now(&self) -> Option<i64> {
+        Self::now(&self.video)
+    }
+
+    #[inline]
+    fn set_audio(&self, pts: i64) {
+        *self.audio.lock().unwrap() = Some((pts, Instant::now()));
+    }
+
+    #[inline]
+    fn set_video(&self, pts: i64) {
+        *self.video.lock().unwrap() = Some((pts, Instant::now()));
+    }
+
+    /// How long to wait before showing the video frame of `pts`, for the audio to catch up.
+    fn video_delay(&self, pts: i64) -> Option<Duration> {
+        let ahead = pts - self.audio_now()? - AV_SYNC_TOLERANCE_MS;
+        if ahead <= 0 {
+            return None;
+        }
+        Some(Duration::from_millis(ahead as _).min(AV_SYNC_MAX_VIDEO_DELAY))
+    }
+}
+
+fn video_frame_pts(vf: &VideoFrame) -> Option<i64> {
+    let frames = match vf.union.as_ref()? {
+        video_frame::Union::Vp8s(f) => f,
+        video_frame::Union::Vp9s(f) => f,
+        video_frame::Union::Av1s(f) => f,
+        video_frame::Union::H264s(f) => f,
+        video_frame::Union::H265s(f) => f,
+        _ => return None,
+    };
+    frames.frames.last().map(|f| f.pts)
+}
+
 /// Audio handler for the [`Client`].
 #[derive(Default)]
 pub struct AudioHandler {
     audio_decoder: Option<(AudioDecoder, Vec<f32>)>,
+    stats: Arc<AudioStats>,
+    av_sync: Arc<AvSync>,
+    // The timestamp of the next decoded frame.
+    pts: Option<i64>,
+    // The decoded frames waiting for the video, with their timestamps.
+    held: VecDeque<(i64, Instant, Vec<f32>)>,
+    last_frame: Option<Instant>,
+    frame_duration: Duration,
     #[cfg(target_os = "linux")]
     simple: Option<psimple::Simple>,
     #[cfg(not(target_os = "linux"))]
@@ -1398,6 +1817,8 @@ impl AudioHandler {
             Ok(d) => {
                 let buffer = vec![0.; f.sample_rate as usize * f.channels as usize];
                 self.audio_decoder = Some((d, buffer));
+                self.pts = None;
+                self.held.clear();
                 self.channels = f.channels as _;
                 allow_err!(self.start_audio(f));
             }
@@ -1419,42 +1840,120 @@ impl AudioHandler {
             log::debug!("PulseAudio simple binding does not exists");
             return;
         }
-        self.audio_decoder.as_mut().map(|(d, buffer)| {
-            if let Ok(n) = d.decode_float(&frame.data, buffer, false) {
-                let channels = self.channels;
-                let n = n * (channels as usize);
-                #[cfg(not(target_os = "linux"))]
-                {
-                    let sample_rate0 = self.sample_rate.0;
-                    let sample_rate = self.sample_rate.1;
-                    let mut buffer = buffer[0..n].to_owned();
-                    if sample_rate != sample_rate0 {
-                        buffer = crate::audio_resample(
-                            &buffer[0..n],
-                            sample_rate0,
-                            sample_rate,
-                            channels,
-                        );
-                    }
-                    if self.channels != self.device_channel {
-                        buffer = crate::audio_rechannel(
-                            buffer,
-                            sample_rate,
-                            sample_rate,
-                            self.channels,
-                            self.device_channel,
-                        );
-                    }
-                    self.audio_buffer.append_pcm(&buffer);
-                }
-                #[cfg(target_os = "linux")]
-                {
-                    let data_u8 =
-                        unsafe { std::slice::from_raw_parts::<u8>(buffer.as_ptr() as _, n * 4) };
-                    self.simple.as_mut().map(|x| x.write(data_u8));
+        let Some((mut d, mut buffer)) = self.audio_decoder.take() else {
+            return;
+        };
+        let late = self.last_frame.map_or(false, |t| {
+            let gap = t.elapsed();
+            gap > self.frame_duration * 2 && gap < AUDIO_CONCEAL_MAX_GAP
+        });
+        self.last_frame = Some(Instant::now());
+        // Packet loss concealment fills the gap instead of a click of silence.
+        if late {
+            if let Ok(n) = d.decode_float(&[], &mut buffer, false) {
+                self.stats.concealed.fetch_add(1, Ordering::Relaxed);
+                self.queue(&buffer[0..n * self.channels as usize], n);
+            }
+        }
+        if let Ok(n) = d.decode_float(&frame.data, &mut buffer, false) {
+            self.stats.frames.fetch_add(1, Ordering::Relaxed);
+            if self.sample_rate.0 > 0 {
+                self.frame_duration = Duration::from_secs_f64(n as f64 / self.sample_rate.0 as f64);
+            }
+            self.queue(&buffer[0..n * self.channels as usize], n);
+        }
+        self.audio_decoder = Some((d, buffer));
+    }
+
+    /// The timestamp of the next frame.
+    pub fn handle_pts(&mut self, pts: i64) {
+        self.pts = Some(pts);
+    }
+
+    // Plays the decoded frame of `samples` per channel, or holds it for the video.
+    fn queue(&mut self, buffer: &[f32], samples: usize) {
+        let Some(pts) = self.pts else {
+            self.play(buffer);
+            return;
+        };
+        if self.sample_rate.0 > 0 {
+            self.pts = Some(pts + (samples as u64 * 1000 / self.sample_rate.0 as u64) as i64);
+        }
+        self.held
+            .push_back((pts, Instant::now(), buffer.to_owned()));
+        self.release();
+    }
+
+    /// Plays the held frames which the video has caught up with or which have waited long enough.
+    pub fn release(&mut self) {
+        let video = self.av_sync.video_now();
+        while let Some((pts, instant, _)) = self.held.front() {
+            let (pts, instant) = (*pts, *instant);
+            let wait = match video {
+                Some(video) => {
+                    pts - self.buffered_ms() > video + AV_SYNC_TOLERANCE_MS
+                        && instant.elapsed() < AV_SYNC_MAX_AUDIO_DELAY
                 }
+                None => false,
+            };
+            if wait {
+                break;
             }
-        });
+            if let Some((_, _, buffer)) = self.held.pop_front() {
+                self.play(&buffer);
+            }
+            self.av_sync.set_audio(pts - self.buffered_ms());
+        }
+    }
+
+    // The milliseconds of the audio played but not heard yet.
+    fn buffered_ms(&self) -> i64 {
+        #[cfg(not(target_os = "linux"))]
+        {
+            let samples = self.audio_buffer.0.lock().unwrap().occupied_len();
+            let rate = self.sample_rate.1 as usize * self.device_channel.max(1) as usize;
+            if rate == 0 {
+                return 0;
+            }
+            (samples * 1000 / rate) as _
+        }
+        #[cfg(target_os = "linux")]
+        {
+            self.simple
+                .as_ref()
+                .and_then(|s| s.get_latency().ok())
+                .map(|l| (l.0 / 1000) as _)
+                .unwrap_or(0)
+        }
+    }
+
+    fn play(&mut self, buffer: &[f32]) {
+        #[cfg(not(target_os = "linux"))]
+        {
+            let channels = self.channels;
+            let sample_rate0 = self.sample_rate.0;
+            let sample_rate = self.sample_rate.1;
+            let mut buffer = buffer.to_owned();
+            if sample_rate != sample_rate0 {
+                buffer = crate::audio_resample(&buffer, sample_rate0, sample_rate, channels);
+            }
+            if self.channels != self.device_channel {
+                buffer = crate::audio_rechannel(
+                    buffer,
+                    sample_rate,
+                    sample_rate,
+                    self.channels,
+                    self.device_channel,
+                );
+            }
+            self.audio_buffer.append_pcm(&buffer);
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let data_u8 =
+                unsafe { std::slice::from_raw_parts::<u8>(buffer.as_ptr() as _, buffer.len() * 4) };
+            self.simple.as_mut().map(|x| x.write(data_u8));
+        }
     }
 
     /// Build audio output stream for current device.
@@ -1648,7 +2147,14 @@ impl VideoHandler {
     }
 
     /// Start or stop screen record.
-    pub fn record_screen(&mut self, start: bool, id: String, display_idx: usize, camera: bool) {
+    pub fn record_screen(
+        &mut self,
+        start: bool,
+        id: String,
+        display_idx: usize,
+        camera: bool,
+        audio: Option<Arc<Mutex<RecordAudio>>>,
+    ) {
         self.record = false;
         if start {
             self.recorder = Recorder::new(RecorderContext {
@@ -1658,6 +2164,7 @@ impl VideoHandler {
                 display_idx,
                 camera,
                 tx: None,
+                audio,
             })
             .map_or(Default::default(), |r| Arc::new(Mutex::new(Some(r))));
         } else {
@@ -1755,6 +2262,15 @@ pub struct LoginConfigHandler {
     pub enable_trusted_devices: bool,
     pub record_state: bool,
     pub record_permission: bool,
+    pub support_wol_relay: bool,
+    // Short authentication string of the secure channel, `None` if not secured.
+    pub sas: Option<String>,
+    // Fingerprint of the long-term key of the peer.
+    pub key_fingerprint: String,
+    // Only the audio of the peer is received, set after `initialize()`.
+    pub audio_only: bool,
+    // The keyboard layouts of the two sides are known and differ.
+    pub keyboard_layout_mismatch: bool,
 }
 
 impl Deref for LoginConfigHandler {
@@ -1864,6 +2380,11 @@ impl LoginConfigHandler {
         self.shared_password = shared_password;
         self.record_state = false;
         self.record_permission = true;
+        self.support_wol_relay = false;
+        self.sas = None;
+        self.key_fingerprint = Default::default();
+        self.audio_only = false;
+        self.keyboard_layout_mismatch = false;
 
         // `std::env::remove_var("IS_TERMINAL_ADMIN");` is called in `session_add_sync()` - `flutter_ffi.rs`.
         let is_terminal_admin = conn_type == ConnType::TERMINAL
@@ -2537,6 +3058,16 @@ impl LoginConfigHandler {
                 .options
                 .insert("force-always-relay".to_owned(), "Y".to_owned());
         }
+        match crate::patch_level::from_platform_additions(&pi.platform_additions) {
+            Some(v) => {
+                config
+                    .options
+                    .insert(crate::patch_level::PEER_OPTION.to_owned(), v);
+            }
+            None => {
+                config.options.remove(crate::patch_level::PEER_OPTION);
+            }
+        }
         #[cfg(feature = "flutter")]
         {
             // sync connected password to personal ab automatically if it is not shared password
@@ -2753,6 +3284,7 @@ pub enum MediaData {
     VideoFrame(Box<VideoFrame>),
     AudioFrame(Box<AudioFrame>),
     AudioFormat(AudioFormat),
+    AudioPts(i64),
     Reset,
     RecordScreen(bool),
 }
@@ -2772,6 +3304,7 @@ pub fn start_video_thread<F, T>(
     fps: Arc<RwLock<Option<usize>>>,
     chroma: Arc<RwLock<Option<Chroma>>>,
     discard_queue: Arc<RwLock<bool>>,
+    av_sync: Arc<AvSync>,
     video_callback: F,
 ) where
     F: 'static + FnMut(usize, &mut scrap::ImageRgb, *mut c_void, bool) + Send,
@@ -2814,6 +3347,10 @@ pub fn start_video_thread<F, T>(
                             }
                         };
                         let display = vf.display as usize;
+                        let pts = video_frame_pts(&vf);
+                        if let Some(delay) = pts.and_then(|pts| av_sync.video_delay(pts)) {
+                            std::thread::sleep(delay);
+                        }
                         let start = std::time::Instant::now();
                         let format = CodecFormat::from(&vf);
                         if video_handler.is_none() {
@@ -2822,7 +3359,13 @@ pub fn start_video_thread<F, T>(
                             let record_permission = session.lc.read().unwrap().record_permission;
                             let id = session.lc.read().unwrap().id.clone();
                             if record_state && record_permission {
-                                handler.record_screen(true, id, display, is_view_camera);
+                                handler.record_screen(
+                                    true,
+                                    id,
+                                    display,
+                                    is_view_camera,
+                                    session.get_record_audio(),
+                                );
                             }
                             video_handler = Some(handler);
                         }
@@ -2838,6 +3381,9 @@ pub fn start_video_thread<F, T>(
                                         handler.texture.texture,
                                         pixelbuffer,
                                     );
+                                    if let Some(pts) = pts {
+                                        av_sync.set_video(pts);
+                                    }
 
                                     // chroma
                                     if tmp_chroma.is_some() && last_chroma != tmp_chroma {
@@ -2903,7 +3449,13 @@ pub fn start_video_thread<F, T>(
                     MediaData::RecordScreen(start) => {
                         let id = session.lc.read().unwrap().id.clone();
                         if let Some(handler) = video_handler.as_mut() {
-                            handler.record_screen(start, id, display, is_view_camera);
+                            handler.record_screen(
+                                start,
+                                id,
+                                display,
+                                is_view_camera,
+                                session.get_record_audio(),
+                            );
                         }
                     }
                     _ => {}
@@ -2919,12 +3471,23 @@ pub fn start_video_thread<F, T>(
 /// Start an audio thread
 /// Return a audio [`MediaSender`]
 pub fn start_audio_thread() -> MediaSender {
+    start_audio_thread_with_stats(Default::default(), Default::default())
+}
+
+/// Start an audio thread which counts the decoded and concealed frames in `stats`,
+/// and syncs the playback with the video by `av_sync`
+/// Return a audio [`MediaSender`]
+pub fn start_audio_thread_with_stats(stats: Arc<AudioStats>, av_sync: Arc<AvSync>) -> MediaSender {
     let (audio_sender, audio_receiver) = mpsc::channel::<MediaData>();
     std::thread::spawn(move || {
-        let mut audio_handler = AudioHandler::default();
+        let mut audio_handler = AudioHandler {
+            stats,
+            av_sync,
+            ..Default::default()
+        };
         loop {
-            if let Ok(data) = audio_receiver.recv() {
-                match data {
+            match audio_receiver.recv_timeout(AV_SYNC_POLL) {
+                Ok(data) => match data {
                     MediaData::AudioFrame(af) => {
                         audio_handler.handle_frame(*af);
                     }
@@ -2932,10 +3495,17 @@ pub fn start_audio_thread() -> MediaSender {
                         log::debug!("recved audio format, sample rate={}", f.sample_rate);
                         audio_handler.handle_format(f);
                     }
+                    MediaData::AudioPts(pts) => {
+                        audio_handler.handle_pts(pts);
+                    }
                     _ => {}
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    audio_handler.release();
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    break;
                 }
-            } else {
-                break;
             }
         }
         log::info!("Audio decoder loop exits");
@@ -3322,6 +3892,10 @@ pub fn handle_login_error(
         }
         interface.msgbox("input-2fa", err, "", "");
         true
+    } else if err == REQUIRE_SECURITY_KEY {
+        // The assertion is sent as soon as the key is touched.
+        interface.msgbox("connecting", err, "touch_security_key_tip", "");
+        true
     } else if LOGIN_ERROR_MAP.contains_key(err) {
         if let Some(msgbox_info) = LOGIN_ERROR_MAP.get(err) {
             interface.msgbox(
@@ -3503,6 +4077,16 @@ async fn send_login(
     password: Vec<u8>,
     peer: &mut Stream,
 ) {
+    if lc.read().unwrap().audio_only {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: AUDIO_ONLY_PLUGIN_ID.to_owned(),
+            ..Default::default()
+        });
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        allow_err!(peer.send(&msg_out).await);
+    }
     let msg_out = lc
         .read()
         .unwrap()
@@ -3608,6 +4192,17 @@ pub trait Interface: Send + Clone + 'static + Sized {
         self.get_lch().read().unwrap().force_relay
     }
 
+    fn is_direct_only(&self) -> bool {
+        config::option2bool(
+            PEER_OPTION_DIRECT_ONLY,
+            &self
+                .get_lch()
+                .read()
+                .unwrap()
+                .get_option(PEER_OPTION_DIRECT_ONLY),
+        )
+    }
+
     fn swap_modifier_mouse(&self, _msg: &mut hbb_common::protos::message::MouseEvent) {}
 
     fn update_direct(&self, direct: Option<bool>) {
@@ -3673,14 +4268,23 @@ pub enum Data {
     SetConfirmOverrideFile((i32, i32, bool, bool, bool)),
     AddJob((i32, JobType, String, String, i32, bool, bool)),
     ResumeJob((i32, bool)),
+    PauseJob((i32, bool)),
     RecordScreen(bool),
     ElevateDirect,
     ElevateWithLogon(String, String),
     NewVoiceCall,
     CloseVoiceCall,
+    MicPassthrough(bool),
+    ForwardGamepad(bool),
     ResetDecoder(Option<usize>),
     RenameFile((i32, String, String, bool)),
     TakeScreenshot((i32, String)),
+    AddTunnel(crate::tunnel::TunnelConfig),
+    RemoveTunnel(crate::tunnel::TunnelConfig),
+    IntegrityHashes((i32, Vec<(i32, String)>)),
+    FileOp((String, bool)),
+    QueryFileDrop(i32),
+    TransferBroker(crate::transfer_broker::BrokerMessage),
 }
 
 /// Keycode for key events.
@@ -4149,6 +4753,7 @@ async fn udp_nat_connect(
     socket: Arc<UdpSocket>,
     typ: &'static str,
     ms_timeout: u64,
+    fec: bool,
 ) -> ResultType<(Stream, Option<KcpStream>, &'static str)> {
     crate::punch_udp(socket.clone(), false)
         .await
@@ -4156,7 +4761,7 @@ async fn udp_nat_connect(
             log::debug!("{err}");
             anyhow!(err)
         })?;
-    let res = KcpStream::connect(socket, Duration::from_millis(ms_timeout))
+    let res = KcpStream::connect(socket, Duration::from_millis(ms_timeout), fec)
         .await
         .map_err(|err| {
             log::debug!("Failed to connect KCP stream: {}", err);
@@ -4164,3 +4769,48 @@ async fn udp_nat_connect(
         })?;
     Ok((res.1, Some(res.0), typ))
 }
+
+fn peer_relay_server(interface: &impl Interface, provided_by_rendezvous_server: String) -> String {
+    let relay_server = interface
+        .get_lch()
+        .read()
+        .unwrap()
+        .get_option(PEER_OPTION_RELAY_SERVER);
+    if relay_server.is_empty() {
+        provided_by_rendezvous_server
+    } else {
+        relay_server
+    }
+}
+
+// The relay candidate of the peer, a TURN relayed address sent in the IPv6 candidate slot.
+// The TURN permission of the peer is for the IP seen by the rendezvous server, which is
+// also the IP of a new socket.
+async fn turn_candidate(addr: SocketAddr) -> Option<Arc<UdpSocket>> {
+    let socket = UdpSocket::bind(Config::get_any_listen_addr(true))
+        .await
+        .ok()?;
+    socket.connect(addr).await.ok()?;
+    Some(Arc::new(socket))
+}
+
+// The check of a relay candidate starts a bit later, so that a working direct path wins.
+async fn udp_turn_connect(
+    socket: Arc<UdpSocket>,
+    ms_timeout: u64,
+    fec: bool,
+) -> ResultType<(Stream, Option<KcpStream>, &'static str)> {
+    hbb_common::sleep(ice::CandidateType::Relay.check_delay().as_secs_f32()).await;
+    udp_nat_connect(socket, "TURN", ms_timeout, fec).await
+}
+
+// Old peers would feed FEC datagrams into KCP as is, so only use it once the peer has advertised support.
+fn use_fec(interface: &impl Interface) -> bool {
+    crate::kcp_stream::is_fec_enabled()
+        && interface
+            .get_lch()
+            .read()
+            .unwrap()
+            .get_option(crate::kcp_stream::PEER_OPTION_FEC_SUPPORTED)
+            == "Y"
+}
@@ -0,0 +1,740 @@
+//! Multipath connection bonding.
+//!
+//! A bond presents several established paths to the same peer (e.g. a direct
+//! connection over Wi-Fi and a relay connection over LTE) as a single [`Stream`],
+//! so `io_loop` and `Connection` do not need to know about it.
+//!
+//! Every path is a regular, individually secured connection. Before its
+//! handshake the client sends a hello frame carrying the bond id, so the controlled
+//! side knows whether to expect a bond without waiting for it. If `allow-bonding` is
+//! enabled it replies with an ack once the path is secured, older peers simply go on
+//! with the usual `Hash` message and we fall back to the single path.
+//!
+//! Frame on a path: `[0u8][type: u8][seq: u64 BE][payload]`.
+//! The leading zero is an invalid protobuf tag, so peers unaware of bonding
+//! drop these frames instead of misinterpreting them.
+//!
+//! The bonded stream is secured once more on top, because paths may come and go
+//! during the session while the encryption nonce sequence must stay continuous.
+
+use hbb_common::{
+    bail,
+    bytes::{BufMut, Bytes, BytesMut},
+    bytes_codec::BytesCodec,
+    config::{self, Config, LocalConfig, READ_TIMEOUT},
+    futures::{SinkExt, StreamExt},
+    log,
+    tcp::{DynTcpStream, FramedStream},
+    timeout,
+    tokio::{
+        self,
+        io::DuplexStream,
+        sync::mpsc,
+        time::{interval, Duration, Instant},
+    },
+    tokio_util::codec::Framed,
+    ResultType, Stream,
+};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Mutex,
+};
+
+/// Local option of the controlling side: "", "duplicate", "stripe" or "failover".
+pub const OPTION_BONDING_MODE: &str = "bonding-mode";
+/// Option of the controlled side, accept bonded paths.
+pub const OPTION_ALLOW_BONDING: &str = "allow-bonding";
+/// Peer option, remembered once the peer has advertised bonding support.
+pub const PEER_OPTION_BONDING_SUPPORTED: &str = "bonding-supported";
+
+const FRAME_DATA: u8 = 1;
+const FRAME_PING: u8 = 2;
+const FRAME_PONG: u8 = 3;
+const FRAME_ACK: u8 = 4;
+const FRAME_HELLO: u8 = 5;
+const FRAME_HELLO_ACK: u8 = 6;
+// The hello of a file stream, see `crate::file_stream`.
+pub(crate) const FRAME_FILE_HELLO: u8 = 7;
+pub(crate) const FRAME_FILE_HELLO_ACK: u8 = 8;
+const HEADER_LEN: usize = 10;
+const BOND_ID_LEN: usize = 16;
+
+const DUPLEX_BUF_SIZE: usize = 1024 * 1024;
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+// A path that has not delivered anything (not even a pong) for this long is degraded.
+const DEGRADED_TIMEOUT: Duration = Duration::from_millis(2500);
+// Switch primary only if the candidate is clearly better, to avoid flapping.
+const SWITCH_RTT_PERCENT: u32 = 60;
+const ACK_EVERY: usize = 64;
+// No more data is taken from the bonded stream until the peer acks, the frames are
+// kept until then to be resent on another path.
+const MAX_UNACKED: usize = 8192;
+const MAX_PENDING: usize = 8192;
+// The client of a file stream sends the hello right after its `PublicKey`.
+const HELLO_TIMEOUT: u64 = 300;
+
+pub type BondId = [u8; BOND_ID_LEN];
+
+lazy_static::lazy_static! {
+    static ref BONDS: Mutex<HashMap<BondId, mpsc::UnboundedSender<(Stream, &'static str)>>> = Default::default();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BondingMode {
+    Off,
+    /// Send every frame on all paths, the first copy wins.
+    Duplicate,
+    /// Spread frames over healthy paths.
+    Stripe,
+    /// Send on the best path only, switch when it degrades.
+    Failover,
+}
+
+impl BondingMode {
+    pub fn from_option(v: &str) -> Self {
+        match v {
+            "duplicate" => Self::Duplicate,
+            "stripe" => Self::Stripe,
+            "failover" => Self::Failover,
+            _ => Self::Off,
+        }
+    }
+
+    #[inline]
+    pub fn local() -> Self {
+        Self::from_option(&LocalConfig::get_option(OPTION_BONDING_MODE))
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Off => 0,
+            Self::Duplicate => 1,
+            Self::Stripe => 2,
+            Self::Failover => 3,
+        }
+    }
+
+    fn from_u8(v: u8) -> Self {
+        match v {
+            1 => Self::Duplicate,
+            2 => Self::Stripe,
+            3 => Self::Failover,
+            _ => Self::Off,
+        }
+    }
+}
+
+#[inline]
+pub fn is_allowed() -> bool {
+    config::option2bool(
+        OPTION_ALLOW_BONDING,
+        &Config::get_option(OPTION_ALLOW_BONDING),
+    )
+}
+
+pub fn new_bond_id() -> BondId {
+    *uuid::Uuid::new_v4().as_bytes()
+}
+
+pub(crate) fn encode_frame(typ: u8, seq: u64, payload: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(HEADER_LEN + payload.len());
+    buf.put_u8(0);
+    buf.put_u8(typ);
+    buf.put_u64(seq);
+    buf.put_slice(payload);
+    buf.freeze()
+}
+
+pub(crate) fn decode_frame(mut bytes: Bytes) -> Option<(u8, u64, Bytes)> {
+    if bytes.len() < HEADER_LEN || bytes[0] != 0 {
+        return None;
+    }
+    let typ = bytes[1];
+    let mut seq = [0u8; 8];
+    seq.copy_from_slice(&bytes[2..HEADER_LEN]);
+    let payload = bytes.split_off(HEADER_LEN);
+    Some((typ, u64::from_be_bytes(seq), payload))
+}
+
+fn encode_hello(typ: u8, bond_id: &BondId, index: u8, mode: BondingMode) -> Bytes {
+    let mut payload = bond_id.to_vec();
+    payload.push(index);
+    payload.push(mode.to_u8());
+    encode_frame(typ, 0, &payload)
+}
+
+fn decode_hello(typ: u8, bytes: Bytes) -> Option<(BondId, u8, BondingMode)> {
+    let (t, _, payload) = decode_frame(bytes)?;
+    if t != typ || payload.len() != BOND_ID_LEN + 2 {
+        return None;
+    }
+    let mut bond_id = [0u8; BOND_ID_LEN];
+    bond_id.copy_from_slice(&payload[..BOND_ID_LEN]);
+    Some((
+        bond_id,
+        payload[BOND_ID_LEN],
+        BondingMode::from_u8(payload[BOND_ID_LEN + 1]),
+    ))
+}
+
+/// Send the bonding hello on a new path, before its handshake.
+pub async fn hello(
+    stream: &mut Stream,
+    bond_id: &BondId,
+    index: u8,
+    mode: BondingMode,
+) -> ResultType<()> {
+    stream
+        .send_bytes(encode_hello(FRAME_HELLO, bond_id, index, mode))
+        .await
+}
+
+/// Whether the first message on an incoming path is a bonding hello.
+pub(crate) fn is_hello(bytes: &[u8]) -> bool {
+    bytes.len() > HEADER_LEN && bytes[0] == 0 && bytes[1] == FRAME_HELLO
+}
+
+/// Wait for the ack of the [`hello`] once the path is secured.
+///
+/// Returns `None` if the peer accepted, otherwise the first regular message
+/// sent by a peer which does not support bonding, which must not be lost.
+pub async fn offer(stream: &mut Stream, bond_id: &BondId) -> ResultType<Option<Bytes>> {
+    loop {
+        match timeout(READ_TIMEOUT, stream.next()).await? {
+            Some(res) => {
+                let bytes = res?.freeze();
+                if bytes.is_empty() {
+                    continue;
+                }
+                match decode_hello(FRAME_HELLO_ACK, bytes.clone()) {
+                    Some((id, _, _)) if &id == bond_id => return Ok(None),
+                    _ => return Ok(Some(bytes)),
+                }
+            }
+            None => bail!("Reset by the peer"),
+        }
+    }
+}
+
+pub enum Accepted {
+    /// Not a bonded path, go on as usual.
+    Plain(Stream),
+    /// First path of a new bond, the returned stream is the bonded one.
+    Bonded(Stream),
+    /// Additional path, handed over to its existing bond.
+    Attached,
+    /// A file stream with its token, see `crate::file_stream`.
+    FileStream(Bytes, Stream),
+}
+
+/// Wait shortly for a file stream hello on a secured incoming path, while one is pending.
+pub async fn accept(mut stream: Stream) -> ResultType<Accepted> {
+    let bytes = loop {
+        match timeout(HELLO_TIMEOUT, stream.next()).await {
+            Ok(Some(res)) => {
+                let bytes = res?;
+                // Sent by non-secure clients in case we are waiting for the public key.
+                if bytes.is_empty() {
+                    continue;
+                }
+                break bytes.freeze();
+            }
+            Ok(None) => bail!("Reset by the peer"),
+            Err(_) => return Ok(Accepted::Plain(stream)),
+        }
+    };
+    if let Some((FRAME_FILE_HELLO, _, token)) = decode_frame(bytes.clone()) {
+        return Ok(Accepted::FileStream(token, stream));
+    }
+    // Not for us, e.g. the login of a client which is not a file stream.
+    let (stream, _) = spawn(stream, "plain", BondingMode::Off, Some(bytes), None);
+    Ok(Accepted::Plain(stream))
+}
+
+/// Accept a path whose bonding [`hello`] was received before its handshake.
+pub async fn accept_hello(mut stream: Stream, hello: Bytes) -> ResultType<Accepted> {
+    let Some((bond_id, index, mode)) = decode_hello(FRAME_HELLO, hello).filter(|_| is_allowed())
+    else {
+        // No ack, the client falls back to the single path on our `Hash`.
+        log::info!("Bonding hello refused");
+        return Ok(Accepted::Plain(stream));
+    };
+    stream
+        .send_bytes(encode_hello(FRAME_HELLO_ACK, &bond_id, index, mode))
+        .await?;
+    if index == 0 {
+        log::info!("New bond, mode: {:?}", mode);
+        let (stream, adder) = spawn(stream, "primary", mode, None, Some(bond_id));
+        BONDS.lock().unwrap().insert(bond_id, adder);
+        return Ok(Accepted::Bonded(stream));
+    }
+    let adder = BONDS.lock().unwrap().get(&bond_id).cloned();
+    match adder {
+        Some(adder) => {
+            log::info!("Path #{} attached to bond", index);
+            adder.send((stream, "secondary")).ok();
+            Ok(Accepted::Attached)
+        }
+        None => bail!("Path #{} refers to an unknown bond", index),
+    }
+}
+
+/// Start a bond on top of its first path.
+///
+/// Returns the bonded stream and a sender to attach more paths later.
+/// With [`BondingMode::Off`] the path is passed through unframed, which is used
+/// to replay `pending` when the peer turned out not to support bonding.
+pub fn spawn(
+    stream: Stream,
+    name: &'static str,
+    mode: BondingMode,
+    pending: Option<Bytes>,
+    bond_id: Option<BondId>,
+) -> (Stream, mpsc::UnboundedSender<(Stream, &'static str)>) {
+    let local_addr = stream.local_addr();
+    let (local, remote) = tokio::io::duplex(DUPLEX_BUF_SIZE);
+    let (add_tx, add_rx) = mpsc::unbounded_channel();
+    let (in_tx, in_rx) = mpsc::unbounded_channel();
+    let mut bond = Bond {
+        mode,
+        bond_id,
+        paths: Vec::new(),
+        primary: 0,
+        rr: 0,
+        next_seq: 0,
+        unacked: VecDeque::new(),
+        reassembler: Default::default(),
+        since_ack: 0,
+        start: Instant::now(),
+    };
+    bond.add_path(stream, name, &in_tx);
+    tokio::spawn(async move {
+        let inner = Framed::new(remote, BytesCodec::new());
+        if let Err(err) = bond.run(inner, in_tx, in_rx, add_rx, pending).await {
+            log::error!("Bond closed: {}", err);
+        }
+        if let Some(id) = bond.bond_id.as_ref() {
+            BONDS.lock().unwrap().remove(id);
+        }
+    });
+    let stream = Stream::Tcp(FramedStream(
+        Framed::new(DynTcpStream(Box::new(local)), BytesCodec::new()),
+        local_addr,
+        None,
+        0,
+    ));
+    (stream, add_tx)
+}
+
+#[derive(Debug, Default)]
+struct Reassembler {
+    next: u64,
+    pending: BTreeMap<u64, Bytes>,
+}
+
+impl Reassembler {
+    // Returns the frames which are ready to be delivered in order.
+    fn push(&mut self, seq: u64, data: Bytes) -> Vec<Bytes> {
+        if seq < self.next || self.pending.contains_key(&seq) {
+            return Vec::new();
+        }
+        self.pending.insert(seq, data);
+        let mut out = Vec::new();
+        while let Some(data) = self.pending.remove(&self.next) {
+            out.push(data);
+            self.next += 1;
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PathHealth {
+    srtt: Option<u32>,
+    last_recv: Instant,
+}
+
+impl PathHealth {
+    fn new() -> Self {
+        Self {
+            srtt: None,
+            last_recv: Instant::now(),
+        }
+    }
+
+    fn on_rtt(&mut self, rtt: u32) {
+        self.srtt = Some(match self.srtt {
+            Some(srtt) => (srtt * 7 + rtt) / 8,
+            None => rtt,
+        });
+    }
+
+    fn is_degraded(&self) -> bool {
+        self.last_recv.elapsed() > DEGRADED_TIMEOUT
+    }
+}
+
+// The best path, sticking to `current` unless it degraded or another one is clearly faster.
+fn pick_primary(paths: &[Option<&PathHealth>], current: usize) -> usize {
+    let best = paths
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| h.map(|h| (i, h)))
+        .filter(|(_, h)| !h.is_degraded())
+        .min_by_key(|(_, h)| h.srtt.unwrap_or(u32::MAX));
+    let Some((best, best_health)) = best else {
+        return current;
+    };
+    match paths.get(current).copied().flatten() {
+        Some(h) if !h.is_degraded() => match (h.srtt, best_health.srtt) {
+            (Some(cur), Some(b)) if b < cur * SWITCH_RTT_PERCENT / 100 => best,
+            _ => current,
+        },
+        _ => best,
+    }
+}
+
+struct Path {
+    name: &'static str,
+    tx: mpsc::UnboundedSender<Bytes>,
+    health: PathHealth,
+}
+
+struct Bond {
+    mode: BondingMode,
+    bond_id: Option<BondId>,
+    paths: Vec<Option<Path>>,
+    primary: usize,
+    rr: usize,
+    next_seq: u64,
+    unacked: VecDeque<(u64, Bytes)>,
+    reassembler: Reassembler,
+    since_ack: usize,
+    start: Instant,
+}
+
+impl Bond {
+    fn add_path(
+        &mut self,
+        mut stream: Stream,
+        name: &'static str,
+        in_tx: &mpsc::UnboundedSender<(usize, Option<BytesMut>)>,
+    ) {
+        let index = self.paths.len();
+        let (tx, mut rx) = mpsc::unbounded_channel::<Bytes>();
+        let in_tx = in_tx.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    res = stream.next() => match res {
+                        Some(Ok(bytes)) => {
+                            if in_tx.send((index, Some(bytes))).is_err() {
+                                break;
+                            }
+                        }
+                        Some(Err(err)) => {
+                            log::info!("Bond path {} read error: {}", name, err);
+                            break;
+                        }
+                        None => break,
+                    },
+                    out = rx.recv() => match out {
+                        Some(bytes) => {
+                            if let Err(err) = stream.send_bytes(bytes).await {
+                                log::info!("Bond path {} write error: {}", name, err);
+                                break;
+                            }
+                        }
+                        None => break,
+                    },
+                }
+            }
+            in_tx.send((index, None)).ok();
+        });
+        log::info!("Bond path #{} {} added", index, name);
+        self.paths.push(Some(Path {
+            name,
+            tx,
+            health: PathHealth::new(),
+        }));
+        // Frames sent before the path existed may be missing on the other side.
+        if index > 0 {
+            self.resend_unacked(index);
+        }
+    }
+
+    async fn run(
+        &mut self,
+        mut inner: Framed<DuplexStream, BytesCodec>,
+        in_tx: mpsc::UnboundedSender<(usize, Option<BytesMut>)>,
+        mut in_rx: mpsc::UnboundedReceiver<(usize, Option<BytesMut>)>,
+        mut add_rx: mpsc::UnboundedReceiver<(Stream, &'static str)>,
+        pending: Option<Bytes>,
+    ) -> ResultType<()> {
+        if let Some(pending) = pending {
+            inner.send(pending).await?;
+        }
+        let mut timer = crate::rustdesk_interval(interval(PING_INTERVAL));
+        loop {
+            tokio::select! {
+                res = inner.next(), if self.unacked.len() < MAX_UNACKED => match res {
+                    Some(Ok(bytes)) => self.send_data(bytes.freeze()),
+                    Some(Err(err)) => bail!(err),
+                    None => break,
+                },
+                Some((index, res)) = in_rx.recv() => match res {
+                    Some(bytes) => {
+                        for data in self.on_frame(index, bytes.freeze())? {
+                            inner.send(data).await?;
+                        }
+                    }
+                    None => {
+                        if !self.on_path_closed(index) {
+                            break;
+                        }
+                    }
+                },
+                Some((stream, name)) = add_rx.recv() => {
+                    if self.mode != BondingMode::Off {
+                        self.add_path(stream, name, &in_tx);
+                    }
+                }
+                _ = timer.tick() => self.on_tick(),
+            }
+        }
+        Ok(())
+    }
+
+    fn send_on(&mut self, index: usize, frame: Bytes) {
+        if let Some(Some(path)) = self.paths.get(index) {
+            path.tx.send(frame).ok();
+        }
+    }
+
+    fn alive(&self) -> Vec<usize> {
+        self.paths
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.as_ref().map(|_| i))
+            .collect()
+    }
+
+    fn healthy(&self) -> Vec<usize> {
+        let healthy: Vec<usize> = self
+            .paths
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.as_ref().map(|p| (i, p)))
+            .filter(|(_, p)| !p.health.is_degraded())
+            .map(|(i, _)| i)
+            .collect();
+        if healthy.is_empty() {
+            self.alive()
+        } else {
+            healthy
+        }
+    }
+
+    fn send_data(&mut self, data: Bytes) {
+        if self.mode == BondingMode::Off {
+            self.send_on(0, data);
+            return;
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let frame = encode_frame(FRAME_DATA, seq, &data);
+        self.unacked.push_back((seq, frame.clone()));
+        match self.mode {
+            BondingMode::Duplicate => {
+                for index in self.alive() {
+                    self.send_on(index, frame.clone());
+                }
+            }
+            BondingMode::Stripe => {
+                let healthy = self.healthy();
+                if !healthy.is_empty() {
+                    self.rr = (self.rr + 1) % healthy.len();
+                    self.send_on(healthy[self.rr], frame);
+                }
+            }
+            BondingMode::Failover | BondingMode::Off => self.send_on(self.primary, frame),
+        }
+    }
+
+    fn on_frame(&mut self, index: usize, bytes: Bytes) -> ResultType<Vec<Bytes>> {
+        if self.mode == BondingMode::Off {
+            return Ok(vec![bytes]);
+        }
+        let Some((typ, seq, payload)) = decode_frame(bytes) else {
+            return Ok(Vec::new());
+        };
+        let now = self.start.elapsed().as_millis() as u64;
+        let mut rtt = None;
+        match typ {
+            FRAME_DATA => {
+                self.since_ack += 1;
+                if self.since_ack >= ACK_EVERY {
+                    self.since_ack = 0;
+                    let ack = encode_frame(FRAME_ACK, self.reassembler.next, &[]);
+                    self.send_on(index, ack);
+                }
+                let out = self.reassembler.push(seq, payload);
+                if self.reassembler.pending.len() > MAX_PENDING {
+                    bail!("Too many out of order frames");
+                }
+                self.touch(index, None);
+                return Ok(out);
+            }
+            FRAME_PING => {
+                self.on_ack(seq);
+                let pong = encode_frame(FRAME_PONG, self.reassembler.next, &payload);
+                self.send_on(index, pong);
+            }
+            FRAME_PONG => {
+                self.on_ack(seq);
+                if payload.len() == 8 {
+                    let mut ts = [0u8; 8];
+                    ts.copy_from_slice(&payload);
+                    rtt = Some(now.saturating_sub(u64::from_be_bytes(ts)) as u32);
+                }
+            }
+            FRAME_ACK => self.on_ack(seq),
+            _ => {}
+        }
+        self.touch(index, rtt);
+        Ok(Vec::new())
+    }
+
+    fn touch(&mut self, index: usize, rtt: Option<u32>) {
+        if let Some(Some(path)) = self.paths.get_mut(index) {
+            path.health.last_recv = Instant::now();
+            if let Some(rtt) = rtt {
+                path.health.on_rtt(rtt);
+            }
+        }
+    }
+
+    fn on_ack(&mut self, next: u64) {
+        while let Some((seq, _)) = self.unacked.front() {
+            if *seq >= next {
+                break;
+            }
+            self.unacked.pop_front();
+        }
+    }
+
+    fn resend_unacked(&mut self, index: usize) {
+        let frames: Vec<Bytes> = self.unacked.iter().map(|(_, f)| f.clone()).collect();
+        for frame in frames {
+            self.send_on(index, frame);
+        }
+    }
+
+    // Returns false if no path is left.
+    fn on_path_closed(&mut self, index: usize) -> bool {
+        if let Some(path) = self.paths.get_mut(index).and_then(|p| p.take()) {
+            log::info!("Bond path #{} {} closed", index, path.name);
+        }
+        if self.mode == BondingMode::Off {
+            return false;
+        }
+        let alive = self.alive();
+        if alive.is_empty() {
+            return false;
+        }
+        if index == self.primary {
+            self.primary = self.pick_primary();
+        }
+        // Whatever went down with the path must be delivered by another one.
+        self.resend_unacked(self.primary);
+        true
+    }
+
+    fn pick_primary(&self) -> usize {
+        let healths: Vec<_> = self
+            .paths
+            .iter()
+            .map(|p| p.as_ref().map(|p| &p.health))
+            .collect();
+        pick_primary(&healths, self.primary)
+    }
+
+    fn on_tick(&mut self) {
+        if self.mode == BondingMode::Off {
+            return;
+        }
+        let now = (self.start.elapsed().as_millis() as u64).to_be_bytes();
+        for index in self.alive() {
+            let ping = encode_frame(FRAME_PING, self.reassembler.next, &now);
+            self.send_on(index, ping);
+        }
+        let primary = self.pick_primary();
+        if primary != self.primary {
+            log::info!(
+                "Bond switches primary path from #{} to #{}",
+                self.primary,
+                primary
+            );
+            self.primary = primary;
+            if self.mode == BondingMode::Failover {
+                self.resend_unacked(primary);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let frame = encode_frame(FRAME_DATA, 42, b"hello");
+        let (typ, seq, payload) = decode_frame(frame).unwrap();
+        assert_eq!(typ, FRAME_DATA);
+        assert_eq!(seq, 42);
+        assert_eq!(&payload[..], b"hello");
+        assert!(decode_frame(Bytes::from_static(b"\x01\x01abcdefgh")).is_none());
+
+        let id = new_bond_id();
+        let hello = encode_hello(FRAME_HELLO, &id, 1, BondingMode::Stripe);
+        assert_eq!(
+            decode_hello(FRAME_HELLO, hello.clone()),
+            Some((id, 1, BondingMode::Stripe))
+        );
+        assert!(is_hello(&hello));
+        assert!(decode_hello(FRAME_HELLO_ACK, hello).is_none());
+        assert!(!is_hello(&encode_frame(FRAME_FILE_HELLO, 0, &id)));
+    }
+
+    #[test]
+    fn test_reassembler() {
+        let mut r = Reassembler::default();
+        assert_eq!(r.push(1, Bytes::from_static(b"b")).len(), 0);
+        let out = r.push(0, Bytes::from_static(b"a"));
+        assert_eq!(
+            out,
+            vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+        );
+        // duplicates from another path are dropped
+        assert!(r.push(0, Bytes::from_static(b"a")).is_empty());
+        assert!(r.push(1, Bytes::from_static(b"b")).is_empty());
+        assert_eq!(r.push(2, Bytes::from_static(b"c")).len(), 1);
+        assert_eq!(r.next, 3);
+    }
+
+    #[test]
+    fn test_pick_primary() {
+        let mut a = PathHealth::new();
+        a.on_rtt(100);
+        let mut b = PathHealth::new();
+        b.on_rtt(80);
+        // not clearly better, stay
+        assert_eq!(pick_primary(&[Some(&a), Some(&b)], 0), 0);
+        let mut c = PathHealth::new();
+        c.on_rtt(30);
+        assert_eq!(pick_primary(&[Some(&a), Some(&c)], 0), 1);
+        // current path gone
+        assert_eq!(pick_primary(&[None, Some(&b)], 0), 1);
+    }
+}
@@ -165,6 +165,15 @@ pub trait FileManager: Interface {
         self.send(Data::ResumeJob((id, is_remote)));
     }
 
+    fn pause_job(&self, id: i32, is_remote: bool) {
+        self.send(Data::PauseJob((id, is_remote)));
+    }
+
+    /// A request of `crate::file_ops` in json.
+    fn file_op(&self, op: String, is_remote: bool) {
+        self.send(Data::FileOp((op, is_remote)));
+    }
+
     fn set_confirm_override_file(
         &self,
         id: i32,
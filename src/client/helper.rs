@@ -13,6 +13,8 @@ pub struct QualityStatus {
     pub target_bitrate: Option<i32>,
     pub codec_format: Option<CodecFormat>,
     pub chroma: Option<String>,
+    pub bandwidth: Option<i32>,
+    pub bwe_state: Option<String>,
 }
 
 #[inline]
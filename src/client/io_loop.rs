@@ -1,5 +1,5 @@
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-use crate::clipboard::{update_clipboard, ClipboardSide};
+use crate::clipboard::{update_clipboard_of_peer, ClipboardSide};
 #[cfg(not(any(target_os = "ios")))]
 use crate::{audio_service, clipboard::CLIPBOARD_INTERVAL, ConnInner, CLIENT_SERVER};
 use crate::{
@@ -8,6 +8,16 @@ use crate::{
         QualityStatus, MILLI1, SEC30,
     },
     common::get_default_sound_input,
+    delta_sync::DeltaMessage,
+    file_compression,
+    file_drop::{self, FileDropMessage},
+    file_ops::{self, FileOpMessage},
+    file_stream::{self, FileStreamMessage},
+    transfer_broker::{self, BrokerMessage},
+    transfer_history,
+    transfer_integrity::{self, IntegrityMessage},
+    transfer_metadata::{self, MetadataMessage},
+    transfer_throttle,
     ui_session_interface::{InvokeUiSession, Session},
 };
 #[cfg(feature = "unix-file-copy-paste")]
@@ -43,7 +53,7 @@ use hbb_common::{
 use hbb_common::{tokio::sync::Mutex as TokioMutex, ResultType};
 use scrap::CodecFormat;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ffi::c_void,
     num::NonZeroI64,
     path::PathBuf,
@@ -56,6 +66,8 @@ use std::{
 pub struct Remote<T: InvokeUiSession> {
     handler: Session<T>,
     audio_sender: MediaSender,
+    audio_stats: Arc<client::AudioStats>,
+    av_sync: Arc<client::AvSync>,
     receiver: mpsc::UnboundedReceiver<Data>,
     sender: mpsc::UnboundedSender<Data>,
     // Stop sending local audio to remote client.
@@ -78,6 +90,19 @@ pub struct Remote<T: InvokeUiSession> {
     chroma: Arc<RwLock<Option<Chroma>>>,
     last_record_state: bool,
     sent_close_reason: bool,
+    tunnel: Option<crate::tunnel::Tunnel>,
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    gamepad: Option<crate::gamepad::Capture>,
+    throttle: transfer_throttle::Throttle,
+    integrity: transfer_integrity::Verifier,
+    metadata: transfer_metadata::Jobs,
+    file_stream: file_stream::FileStream,
+    file_compressor: file_compression::Compressor,
+    history: transfer_history::Jobs,
+    // The jobs sent by the peer to another one, see `crate::transfer_broker`.
+    brokered: HashSet<i32>,
+    #[cfg(any(target_os = "windows", feature = "unix-file-copy-paste"))]
+    paste: crate::clipboard_paste::Paste,
 }
 
 #[derive(Default)]
@@ -87,6 +112,18 @@ struct ParsedPeerInfo {
     idd_impl: String,
     support_view_camera: bool,
     support_terminal: bool,
+    support_tunnel: bool,
+    support_av1_svc: bool,
+    support_hdr: bool,
+    support_bwe_stats: bool,
+    support_audio_device: bool,
+    support_audio_qos: bool,
+    support_gamepad: bool,
+    support_delta_sync: bool,
+    support_transfer_integrity: bool,
+    support_transfer_metadata: bool,
+    support_file_stream: bool,
+    support_file_compression: bool,
 }
 
 impl ParsedPeerInfo {
@@ -103,9 +140,16 @@ impl<T: InvokeUiSession> Remote<T> {
         receiver: mpsc::UnboundedReceiver<Data>,
         sender: mpsc::UnboundedSender<Data>,
     ) -> Self {
+        let audio_stats: Arc<client::AudioStats> = Default::default();
+        let av_sync: Arc<client::AvSync> = Default::default();
         Self {
             handler,
-            audio_sender: crate::client::start_audio_thread(),
+            audio_sender: crate::client::start_audio_thread_with_stats(
+                audio_stats.clone(),
+                av_sync.clone(),
+            ),
+            audio_stats,
+            av_sync,
             receiver,
             sender,
             read_jobs: Vec::new(),
@@ -127,6 +171,18 @@ impl<T: InvokeUiSession> Remote<T> {
             chroma: Default::default(),
             last_record_state: false,
             sent_close_reason: false,
+            tunnel: None,
+            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+            gamepad: None,
+            throttle: Default::default(),
+            integrity: Default::default(),
+            metadata: Default::default(),
+            file_stream: Default::default(),
+            file_compressor: Default::default(),
+            history: Default::default(),
+            brokered: Default::default(),
+            #[cfg(any(target_os = "windows", feature = "unix-file-copy-paste"))]
+            paste: Default::default(),
         }
     }
 
@@ -184,8 +240,9 @@ impl<T: InvokeUiSession> Remote<T> {
                     .set_connection_type(peer.is_secured(), direct, stream_type); // flutter -> connection_ready
                 self.handler.update_direct(Some(direct));
                 if conn_type == ConnType::DEFAULT_CONN || conn_type == ConnType::VIEW_CAMERA {
-                    self.handler
-                        .set_fingerprint(crate::common::pk_to_fingerprint(pk.unwrap_or_default()));
+                    let fingerprint = crate::common::pk_to_fingerprint(pk.unwrap_or_default());
+                    self.handler.lc.write().unwrap().key_fingerprint = fingerprint.clone();
+                    self.handler.set_fingerprint(fingerprint);
                 }
 
                 // just build for now
@@ -268,16 +325,45 @@ impl<T: InvokeUiSession> Remote<T> {
                                 self.handler.msgbox("error", "Connection Error", "Timeout", "");
                                 break;
                             }
-                            if !self.read_jobs.is_empty() {
-                                if let Err(err) = fs::handle_read_jobs(&mut self.read_jobs, &mut peer).await {
+                            // The paused jobs and the ones waiting in the queue are not read.
+                            if self.read_jobs.iter().any(|j| !j.is_last_job) {
+                                if !self.throttle.ready(|| Self::throttle_options(&self.handler)) {
+                                    continue;
+                                }
+                                let before = transfer_throttle::finished_size(&self.read_jobs);
+                                if let Err(err) = self.file_compressor.handle_read_jobs(&mut self.read_jobs, self.file_stream.writer(&mut peer)).await {
                                     self.handler.msgbox("error", "Connection Error", &err.to_string(), "");
                                     break;
                                 }
+                                let after = transfer_throttle::finished_size(&self.read_jobs);
+                                self.throttle.pay(after.saturating_sub(before));
                                 self.update_jobs_status();
                             } else {
+                                self.file_stream.on_idle();
                                 self.timer = crate::rustdesk_interval(time::interval_at(Instant::now() + SEC30, SEC30));
                             }
                         }
+                        event = self.file_stream.recv() => {
+                            match event {
+                                file_stream::Event::Attached => {
+                                    if !self.read_jobs.iter().any(|j| !j.is_last_job) {
+                                        self.file_stream.on_idle();
+                                    }
+                                }
+                                file_stream::Event::Message(bytes) => {
+                                    last_recv_time = Instant::now();
+                                    self.data_count.fetch_add(bytes.len(), Ordering::Relaxed);
+                                    if !self.handle_msg_from_peer(&bytes, &mut peer).await {
+                                        break
+                                    }
+                                }
+                                // The blocks sent on it are lost.
+                                file_stream::Event::Closed(err) => {
+                                    self.handler.msgbox("error", "Connection Error", &format!("File stream: {}", err), "");
+                                    break;
+                                }
+                            }
+                        }
                         _ = status_timer.tick() => {
                             let elapsed = fps_instant.elapsed().as_millis();
                             if elapsed < 1000 {
@@ -296,6 +382,7 @@ impl<T: InvokeUiSession> Remote<T> {
                                 *v.frame_count.write().unwrap() = 0;
                             });
                             self.fps_control(direct, fps.clone());
+                            self.report_audio_loss(&mut peer).await;
                             let chroma = self.chroma.read().unwrap().clone();
                             let chroma = match chroma {
                                 Some(Chroma::I444) => "4:4:4",
@@ -356,7 +443,7 @@ impl<T: InvokeUiSession> Remote<T> {
 
     #[cfg(any(target_os = "windows", feature = "unix-file-copy-paste"))]
     async fn handle_local_clipboard_msg(
-        &self,
+        &mut self,
         peer: &mut Stream,
         msg: Option<clipboard::ClipboardFile>,
     ) {
@@ -395,6 +482,21 @@ impl<T: InvokeUiSession> Remote<T> {
                             log::error!("failed to restart clipboard context: {}", e);
                             // to-do: Show msgbox with "Don't show again" option
                         };
+                        match self.paste.on_local(&clip) {
+                            crate::clipboard_paste::Local::Send(event) => {
+                                if let Some(event) = event {
+                                    self.on_clipboard_paste(event);
+                                }
+                            }
+                            crate::clipboard_paste::Local::Fail(res) => {
+                                if let Some(message::Union::Cliprdr(res)) =
+                                    crate::clipboard_file::clip_2_msg(res).union
+                                {
+                                    self.handle_cliprdr_msg(res, peer).await;
+                                }
+                                return;
+                            }
+                        }
                         log::debug!("Send system clipboard message to remote");
                         let msg = crate::clipboard_file::clip_2_msg(clip);
                         allow_err!(peer.send(&msg).await);
@@ -407,7 +509,28 @@ impl<T: InvokeUiSession> Remote<T> {
         }
     }
 
+    #[cfg(any(target_os = "windows", feature = "unix-file-copy-paste"))]
+    fn on_clipboard_paste(&self, event: crate::clipboard_paste::Event) {
+        use crate::clipboard_paste::Event;
+        match event {
+            Event::Started { id, files, total } => {
+                let content = serde_json::json!({"id": id, "files": files, "total": total});
+                self.handler.clipboard_paste(&content.to_string());
+            }
+            Event::Progress {
+                id,
+                file_num,
+                speed,
+                finished,
+            } => self
+                .handler
+                .job_progress(id, file_num, speed, finished as f64),
+            Event::Done { id, file_num } => self.handler.job_done(id, file_num),
+        }
+    }
+
     fn handle_job_status(&mut self, id: i32, file_num: i32, err: Option<String>) {
+        self.history.finish(id, err.clone());
         if let Some(job) = self.remove_jobs.get_mut(&id) {
             if job.no_confirm {
                 let file_num = (file_num + 1) as usize;
@@ -441,6 +564,13 @@ impl<T: InvokeUiSession> Remote<T> {
         }
     }
 
+    // The voice call and the microphone passthrough share the recorder, restart it after the call.
+    fn resume_mic_passthrough(&mut self) {
+        if *self.handler.mic_passthrough.read().unwrap() && self.stop_voice_call_sender.is_none() {
+            self.stop_voice_call_sender = self.start_voice_call();
+        }
+    }
+
     // Start a voice call recorder, records audio and send to remote
     fn start_voice_call(&mut self) -> Option<std::sync::mpsc::Sender<()>> {
         if self.handler.is_file_transfer()
@@ -569,6 +699,8 @@ impl<T: InvokeUiSession> Remote<T> {
             }
             Data::SendFiles((id, r#type, path, to, file_num, include_hidden, is_remote)) => {
                 log::info!("send files, is remote {}", is_remote);
+                self.request_file_stream(peer).await;
+                self.enable_file_compression(peer).await;
                 let od = can_enable_overwrite_detection(self.handler.lc.read().unwrap().version);
                 if is_remote {
                     log::debug!("New job {}, write to {} from remote {}", id, to, path);
@@ -589,6 +721,9 @@ impl<T: InvokeUiSession> Remote<T> {
                         Vec::new(),
                         od,
                     ));
+                    if let Some(job) = self.write_jobs.last() {
+                        self.history.start(&self.handler.get_id(), job);
+                    }
                     allow_err!(
                         peer.send(&fs::new_send(id, r#type, path, file_num, include_hidden))
                             .await
@@ -632,6 +767,7 @@ impl<T: InvokeUiSession> Remote<T> {
                                 fs::transform_windows_path(&mut files);
                             }
                             let total_size = job.total_size();
+                            self.add_upload(&job);
                             self.read_jobs.push(job);
                             self.timer = crate::rustdesk_interval(time::interval(MILLI1));
                             allow_err!(
@@ -663,6 +799,7 @@ impl<T: InvokeUiSession> Remote<T> {
                         od,
                     );
                     job.is_last_job = true;
+                    self.history.start(&self.handler.get_id(), &job);
                     self.write_jobs.push(job);
                 } else {
                     match fs::TransferJob::new_read(
@@ -694,6 +831,7 @@ impl<T: InvokeUiSession> Remote<T> {
                                 true,
                             );
                             job.is_last_job = true;
+                            self.add_upload(&job);
                             self.read_jobs.push(job);
                             self.timer = crate::rustdesk_interval(time::interval(MILLI1));
                         }
@@ -742,6 +880,7 @@ impl<T: InvokeUiSession> Remote<T> {
                                     ))
                                     .await
                                 );
+                                self.timer = crate::rustdesk_interval(time::interval(MILLI1));
                             }
                             fs::DataSource::MemoryCursor(_) => {
                                 // unreachable!()
@@ -751,6 +890,28 @@ impl<T: InvokeUiSession> Remote<T> {
                     }
                 }
             }
+            Data::PauseJob((id, is_remote)) => {
+                // The peer drops its side of the job, the job waits here as the last jobs
+                // loaded, until it is resumed from where it stopped.
+                let job = if is_remote {
+                    get_job(id, &mut self.write_jobs)
+                } else {
+                    get_job(id, &mut self.read_jobs)
+                };
+                if let Some(job) = job {
+                    job.is_last_job = true;
+                    let mut msg_out = Message::new();
+                    let mut file_action = FileAction::new();
+                    file_action.set_cancel(FileTransferCancel {
+                        id,
+                        ..Default::default()
+                    });
+                    msg_out.set_file_action(file_action);
+                    allow_err!(peer.send(&msg_out).await);
+                    crate::delta_sync::cancel_write(id);
+                    crate::delta_sync::cancel_read(id);
+                }
+            }
             Data::SetNoConfirm(id) => {
                 if let Some(job) = self.remove_jobs.get_mut(&id) {
                     job.no_confirm = true;
@@ -774,7 +935,20 @@ impl<T: InvokeUiSession> Remote<T> {
                         if remember {
                             job.set_overwrite_strategy(Some(need_override));
                         }
-                        job.confirm(&FileTransferSendConfirmRequest {
+                        let size = job
+                            .files()
+                            .get(file_num as usize)
+                            .map(|f| f.size)
+                            .unwrap_or_default();
+                        if need_override
+                            && self.peer_info.support_delta_sync
+                            && crate::delta_sync::is_worth(size)
+                        {
+                            let msg = DeltaMessage::Request { id, file_num }.to_message();
+                            allow_err!(peer.send(&msg).await);
+                            return true;
+                        }
+                        let req = FileTransferSendConfirmRequest {
                             id,
                             file_num,
                             union: if need_override {
@@ -783,14 +957,23 @@ impl<T: InvokeUiSession> Remote<T> {
                                 Some(file_transfer_send_confirm_request::Union::Skip(true))
                             },
                             ..Default::default()
-                        })
-                        .await;
+                        };
+                        self.integrity.on_confirm(&req);
+                        job.confirm(&req).await;
                     }
                 } else {
                     if let Some(job) = fs::get_job(id, &mut self.write_jobs) {
                         if remember {
                             job.set_overwrite_strategy(Some(need_override));
                         }
+                        if need_override && self.peer_info.support_delta_sync {
+                            let sender = self.sender.clone();
+                            if crate::delta_sync::start_write(job, file_num, move |msg| {
+                                sender.send(Data::Message(msg)).ok();
+                            }) {
+                                return true;
+                            }
+                        }
                         let mut msg = Message::new();
                         let mut file_action = FileAction::new();
                         let req = FileTransferSendConfirmRequest {
@@ -803,6 +986,7 @@ impl<T: InvokeUiSession> Remote<T> {
                             },
                             ..Default::default()
                         };
+                        self.integrity.on_confirm(&req);
                         job.confirm(&req).await;
                         file_action.set_send_confirm(req);
                         msg.set_file_action(file_action);
@@ -853,11 +1037,24 @@ impl<T: InvokeUiSession> Remote<T> {
                 });
                 msg_out.set_file_action(file_action);
                 allow_err!(peer.send(&msg_out).await);
+                crate::delta_sync::cancel_write(id);
+                crate::delta_sync::cancel_read(id);
+                self.integrity.cancel(id);
+                self.metadata.cancel(id);
+                self.history.finish(id, Some("Cancelled".to_owned()));
+                if self.brokered.remove(&id) {
+                    allow_err!(peer.send(&BrokerMessage::Cancel { id }.to_message()).await);
+                }
                 if let Some(job) = fs::remove_job(id, &mut self.write_jobs) {
+                    crate::transfer_checkpoint::on_cancelled(&job);
                     job.remove_download_file();
                 }
                 let _ = fs::remove_job(id, &mut self.read_jobs);
                 self.remove_jobs.remove(&id);
+                #[cfg(any(target_os = "windows", feature = "unix-file-copy-paste"))]
+                if let Some(file_num) = self.paste.cancel(id) {
+                    self.handler.job_error(id, "Cancelled".to_owned(), file_num);
+                }
             }
             Data::RemoveDir((id, path)) => {
                 let mut msg_out = Message::new();
@@ -979,6 +1176,26 @@ impl<T: InvokeUiSession> Remote<T> {
                 self.handler
                     .on_voice_call_closed("Closed manually by the peer");
                 allow_err!(peer.send(&msg).await);
+                self.resume_mic_passthrough();
+            }
+            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+            Data::ForwardGamepad(on) => {
+                self.forward_gamepad(on);
+            }
+            Data::MicPassthrough(on) => {
+                let mut misc = Misc::new();
+                misc.set_plugin_request(PluginRequest {
+                    id: client::MIC_PASSTHROUGH_PLUGIN_ID.to_owned(),
+                    content: if on { "Y" } else { "N" }.as_bytes().to_vec().into(),
+                    ..Default::default()
+                });
+                let mut msg = Message::new();
+                msg.set_misc(misc);
+                allow_err!(peer.send(&msg).await);
+                self.stop_voice_call();
+                if on {
+                    self.stop_voice_call_sender = self.start_voice_call();
+                }
             }
             Data::ResetDecoder(display) => match display {
                 Some(display) => {
@@ -1001,6 +1218,50 @@ impl<T: InvokeUiSession> Remote<T> {
                 });
                 allow_err!(peer.send(&msg).await);
             }
+            Data::AddTunnel(t) => {
+                if let Some(tunnel) = self.tunnel.as_ref() {
+                    if let Err(err) = tunnel.add(&t).await {
+                        self.handler.msgbox("error", "Tunnel", &err.to_string(), "");
+                    }
+                }
+            }
+            Data::RemoveTunnel(t) => {
+                if let Some(tunnel) = self.tunnel.as_ref() {
+                    tunnel.remove(&t);
+                }
+            }
+            Data::IntegrityHashes((id, hashes)) => {
+                if let Some(corrupted) = self.integrity.on_local(id, hashes) {
+                    self.on_integrity_checked(id, corrupted);
+                }
+            }
+            Data::QueryFileDrop(id) => {
+                allow_err!(peer.send(&FileDropMessage::Query { id }.to_message()).await);
+            }
+            Data::TransferBroker(msg) => match msg {
+                // Failed before the job is sent to the peer.
+                BrokerMessage::Done { id, error } => self.handle_job_status(id, -1, error),
+                _ => {
+                    if let BrokerMessage::Send { id, .. } = &msg {
+                        self.brokered.insert(*id);
+                    }
+                    allow_err!(peer.send(&msg.to_message()).await);
+                }
+            },
+            Data::FileOp((op, is_remote)) => {
+                let Some(msg) = FileOpMessage::parse(op.as_bytes()) else {
+                    return true;
+                };
+                if is_remote {
+                    allow_err!(peer.send(&msg.to_message()).await);
+                } else {
+                    let handler = self.handler.clone();
+                    file_ops::handle(msg, move |m| {
+                        let content = serde_json::to_string(&m).unwrap_or_default();
+                        handler.file_op_response(true, &content);
+                    });
+                }
+            }
             _ => {}
         }
         true
@@ -1030,6 +1291,14 @@ impl<T: InvokeUiSession> Remote<T> {
         handler.job_progress(job.id(), file_num, speed, job.finished_size() as f64);
     }
 
+    fn throttle_options(handler: &Session<T>) -> transfer_throttle::Options {
+        transfer_throttle::Options::new(
+            &handler.get_option(transfer_throttle::OPTION_RATE_LIMIT.to_owned()),
+            &LocalConfig::get_option(transfer_throttle::OPTION_RATE_LIMIT),
+            &LocalConfig::get_option(transfer_throttle::OPTION_PAUSE_FOR_VIDEO),
+        )
+    }
+
     fn update_jobs_status(&mut self) {
         let elapsed = self.last_update_jobs_status.0.elapsed().as_millis() as i32;
         if elapsed >= 1000 {
@@ -1053,6 +1322,202 @@ impl<T: InvokeUiSession> Remote<T> {
         }
     }
 
+    // Reads the files of the uploads and writes the ones of the downloads.
+    async fn handle_delta_sync(&mut self, content: &[u8], peer: &mut Stream) {
+        let Some(msg) = DeltaMessage::parse(content) else {
+            return;
+        };
+        match msg {
+            DeltaMessage::Signatures {
+                id,
+                file_num,
+                block_size,
+                weak,
+                strong,
+            } => {
+                let Some(path) = fs::get_job(id, &mut self.read_jobs)
+                    .and_then(|job| crate::delta_sync::file_path(job, file_num))
+                else {
+                    return;
+                };
+                let sender = self.sender.clone();
+                crate::delta_sync::start_read(
+                    id,
+                    file_num,
+                    path,
+                    block_size,
+                    weak,
+                    strong,
+                    move |msg| {
+                        sender.send(Data::Message(msg)).ok();
+                    },
+                );
+            }
+            DeltaMessage::Ack { id, file_num } => crate::delta_sync::on_ack(id, file_num),
+            DeltaMessage::Ops { id, file_num, ops } => {
+                let msg = crate::delta_sync::on_ops(id, file_num, &ops);
+                allow_err!(peer.send(&msg).await);
+            }
+            DeltaMessage::End { id, file_num, hash } => {
+                let req = crate::delta_sync::on_end(id, file_num, &hash);
+                if let Some(job) = fs::get_job(id, &mut self.write_jobs) {
+                    job.confirm(&req).await;
+                }
+                allow_err!(peer.send(&new_send_confirm(req)).await);
+            }
+            DeltaMessage::Request { .. } => {}
+        }
+    }
+
+    // Keeps the files of the upload, checked once the peer is done.
+    fn add_upload(&mut self, job: &fs::TransferJob) {
+        if job.r#type != fs::JobType::Generic {
+            return;
+        }
+        self.history.start(&self.handler.get_id(), job);
+        if self.peer_info.support_transfer_integrity {
+            let files = transfer_integrity::entries(job, self.handler.get_path_sep(true));
+            self.integrity.add_upload(job.id(), files);
+        }
+        if self.peer_info.support_transfer_metadata {
+            if let Some(m) = transfer_metadata::Job::new(job) {
+                self.metadata.add_upload(job.id(), m);
+            }
+        }
+    }
+
+    // Sends the metadata of the files of the upload done, or asks for the ones of the download.
+    async fn send_transfer_metadata(
+        &mut self,
+        id: i32,
+        download: Option<transfer_metadata::Job>,
+        peer: &mut Stream,
+    ) {
+        if !self.peer_info.support_transfer_metadata {
+            return;
+        }
+        let xattrs = LocalConfig::get_option(transfer_metadata::OPTION_TRANSFER_XATTRS) == "Y";
+        let skipped = self.integrity.skipped(id);
+        match self.metadata.on_done(id, download, &skipped, xattrs) {
+            Some(transfer_metadata::Done::Upload(job)) => {
+                let sender = self.sender.clone();
+                std::thread::spawn(move || {
+                    let msg = transfer_metadata::upload_records(id, &job, xattrs);
+                    sender.send(Data::Message(msg)).ok();
+                });
+            }
+            Some(transfer_metadata::Done::Request(msg)) => {
+                allow_err!(peer.send(&msg).await);
+            }
+            None => {}
+        }
+    }
+
+    // Asks the peer for the file stream once, the blocks of the jobs are sent on it.
+    async fn request_file_stream(&mut self, peer: &mut Stream) {
+        let enabled = config::option2bool(
+            file_stream::OPTION_ENABLE_FILE_STREAM,
+            &LocalConfig::get_option(file_stream::OPTION_ENABLE_FILE_STREAM),
+        );
+        if !enabled || !self.peer_info.support_file_stream {
+            return;
+        }
+        if let Some(msg) = self.file_stream.request() {
+            allow_err!(peer.send(&msg).await);
+        }
+    }
+
+    // Lets both sides compress the blocks they send, once.
+    async fn enable_file_compression(&mut self, peer: &mut Stream) {
+        let enabled = config::option2bool(
+            file_compression::OPTION_ENABLE_FILE_COMPRESSION,
+            &LocalConfig::get_option(file_compression::OPTION_ENABLE_FILE_COMPRESSION),
+        );
+        if !enabled || !self.peer_info.support_file_compression || self.file_compressor.is_enabled()
+        {
+            return;
+        }
+        self.file_compressor.enable();
+        allow_err!(peer.send(&file_compression::enable_message()).await);
+    }
+
+    // Opens the file stream with the token issued by the peer, in the background.
+    fn handle_file_stream(&mut self, content: &[u8]) {
+        let Some(FileStreamMessage::Token { token }) = FileStreamMessage::parse(content) else {
+            return;
+        };
+        let Ok(file_token) = file_stream::Token::try_from(token.as_slice()) else {
+            log::info!("File stream refused by the peer");
+            return;
+        };
+        let handler = self.handler.clone();
+        let conn_type = handler.lc.read().unwrap().conn_type;
+        let tx = self.file_stream.sender();
+        tokio::spawn(async move {
+            let token = LocalConfig::get_option("access_token");
+            let key = crate::get_key(false).await;
+            let res = Client::connect_file_stream(
+                &handler.get_id(),
+                &key,
+                &token,
+                conn_type,
+                handler.clone(),
+                &file_token,
+            )
+            .await;
+            match res {
+                Ok(stream) => {
+                    tx.send(stream).ok();
+                }
+                Err(err) => log::warn!("Failed to open the file stream: {}", err),
+            }
+        });
+    }
+
+    fn handle_transfer_metadata(&mut self, content: &[u8]) {
+        if let Some(MetadataMessage::Records { id, records, .. }) = MetadataMessage::parse(content)
+        {
+            self.metadata.on_records(id, &records);
+        }
+    }
+
+    // Asks the peer for the hashes of the files of the job done, the local ones are hashed in a
+    // thread. `download` is the files of the download, none for an upload.
+    async fn start_integrity_check(
+        &mut self,
+        id: i32,
+        download: Option<Vec<transfer_integrity::Entry>>,
+        peer: &mut Stream,
+    ) {
+        if !self.peer_info.support_transfer_integrity {
+            return;
+        }
+        let Some((files, msg)) = self.integrity.start(id, download) else {
+            return;
+        };
+        allow_err!(peer.send(&msg).await);
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            let hashes = transfer_integrity::hash_files(&files);
+            sender.send(Data::IntegrityHashes((id, hashes))).ok();
+        });
+    }
+
+    fn handle_transfer_integrity(&mut self, content: &[u8]) {
+        if let Some(IntegrityMessage::Hashes { id, hashes }) = IntegrityMessage::parse(content) {
+            if let Some(corrupted) = self.integrity.on_remote(id, hashes) {
+                self.on_integrity_checked(id, corrupted);
+            }
+        }
+    }
+
+    fn on_integrity_checked(&self, id: i32, corrupted: Vec<transfer_integrity::Corrupted>) {
+        if !corrupted.is_empty() {
+            let files = serde_json::to_string(&corrupted).unwrap_or_default();
+            self.handler.integrity_failed(id, &files);
+        }
+    }
+
     pub async fn sync_jobs_status_to_local(&mut self) -> bool {
         log::info!("sync transfer job status");
         let mut config: PeerConfig = self.handler.load_config();
@@ -1128,6 +1593,20 @@ impl<T: InvokeUiSession> Remote<T> {
         }
     }
 
+    // Drops the frames of the highest AV1 temporal layers while frames are queued for decoding.
+    fn keep_temporal_layer(thread: &mut VideoThread, vf: &VideoFrame) -> bool {
+        let Some(video_frame::Union::Av1s(f)) = &vf.union else {
+            return true;
+        };
+        let Some(frame) = f.frames.first() else {
+            return true;
+        };
+        let congested = thread.video_queue.read().unwrap().len() > 1;
+        thread
+            .layer_filter
+            .keep(scrap::aom::temporal_id(&frame.data), congested)
+    }
+
     // Currently, this function only considers decoding speed and queue length, not network delay.
     // The controlled end can consider auto fps as the maximum decoding fps.
     #[inline]
@@ -1298,6 +1777,9 @@ impl<T: InvokeUiSession> Remote<T> {
                     let Some(thread) = self.video_threads.get_mut(&display) else {
                         return true;
                     };
+                    if self.peer_info.support_av1_svc && !Self::keep_temporal_layer(thread, &vf) {
+                        return true;
+                    }
                     if Self::contains_key_frame(&vf) {
                         thread
                             .video_sender
@@ -1345,6 +1827,10 @@ impl<T: InvokeUiSession> Remote<T> {
                             }
                         }
                         self.handler.handle_peer_info(pi);
+                        if self.handler.is_file_transfer() {
+                            self.request_file_stream(peer).await;
+                            self.enable_file_compression(peer).await;
+                        }
                         #[cfg(all(target_os = "windows", not(feature = "flutter")))]
                         self.check_clipboard_file_context();
                         if self.handler.is_default() {
@@ -1370,7 +1856,9 @@ impl<T: InvokeUiSession> Remote<T> {
                             }
 
                             #[cfg(not(any(target_os = "android", target_os = "ios")))]
-                            if self.handler.lc.read().unwrap().sync_init_clipboard.v {
+                            if self.handler.lc.read().unwrap().sync_init_clipboard.v
+                                && !self.handler.is_manual_clipboard_sync()
+                            {
                                 if let Some(msg_out) = crate::clipboard::get_current_clipboard_msg(
                                     &peer_version,
                                     &peer_platform,
@@ -1402,6 +1890,23 @@ impl<T: InvokeUiSession> Remote<T> {
                                 crate::plugin::EVENT_ON_CONN_CLIENT.to_owned(),
                                 self.handler.get_id(),
                             );
+
+                            if self.handler.is_peer_key_changed() {
+                                self.handler.msgbox(
+                                    "custom-nocancel-error",
+                                    "Peer key changed",
+                                    "peer_key_changed_tip",
+                                    "",
+                                );
+                            }
+
+                            self.start_tunnels().await;
+                            self.request_video_features();
+                            self.select_audio_device();
+                            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                            self.forward_gamepad(self.handler.get_toggle_option(
+                                crate::gamepad::PEER_OPTION_FORWARD_GAMEPAD.to_owned(),
+                            ));
                         }
 
                         if self.handler.is_file_transfer() {
@@ -1424,7 +1929,11 @@ impl<T: InvokeUiSession> Remote<T> {
                 Some(message::Union::Clipboard(cb)) => {
                     if !self.handler.lc.read().unwrap().disable_clipboard.v {
                         #[cfg(not(any(target_os = "android", target_os = "ios")))]
-                        update_clipboard(vec![cb], ClipboardSide::Client);
+                        if self.handler.is_manual_clipboard_sync() {
+                            self.handler.remote_clips.lock().unwrap().push(vec![cb]);
+                        } else {
+                            update_clipboard_of_peer(vec![cb], ClipboardSide::Client);
+                        }
                         #[cfg(target_os = "ios")]
                         {
                             let content = if cb.compress {
@@ -1443,7 +1952,15 @@ impl<T: InvokeUiSession> Remote<T> {
                 Some(message::Union::MultiClipboards(_mcb)) => {
                     if !self.handler.lc.read().unwrap().disable_clipboard.v {
                         #[cfg(not(any(target_os = "android", target_os = "ios")))]
-                        update_clipboard(_mcb.clipboards, ClipboardSide::Client);
+                        if self.handler.is_manual_clipboard_sync() {
+                            self.handler
+                                .remote_clips
+                                .lock()
+                                .unwrap()
+                                .push(_mcb.clipboards);
+                        } else {
+                            update_clipboard_of_peer(_mcb.clipboards, ClipboardSide::Client);
+                        }
                         #[cfg(target_os = "android")]
                         crate::clipboard::handle_msg_multi_clipboards(_mcb);
                     }
@@ -1494,7 +2011,19 @@ impl<T: InvokeUiSession> Remote<T> {
                                                     offset = digest.transferred_size as _;
                                                 }
                                             }
-                                            if let Some(overwrite) = overwrite_strategy {
+                                            if overwrite_strategy == Some(true)
+                                                && offset == 0
+                                                && self.peer_info.support_delta_sync
+                                                && crate::delta_sync::is_worth(digest.file_size)
+                                            {
+                                                let msg = DeltaMessage::Request {
+                                                    id: digest.id,
+                                                    file_num: digest.file_num,
+                                                }
+                                                .to_message();
+                                                // Confirmed by the peer once the delta is applied.
+                                                allow_err!(peer.send(&msg).await);
+                                            } else if let Some(overwrite) = overwrite_strategy {
                                                 let req = FileTransferSendConfirmRequest {
                                                     id: digest.id,
                                                     file_num: digest.file_num,
@@ -1507,6 +2036,7 @@ impl<T: InvokeUiSession> Remote<T> {
                                                     }),
                                                     ..Default::default()
                                                 };
+                                                self.integrity.on_confirm(&req);
                                                 job.confirm(&req).await;
                                                 let msg = new_send_confirm(req);
                                                 allow_err!(peer.send(&msg).await);
@@ -1547,11 +2077,18 @@ impl<T: InvokeUiSession> Remote<T> {
                                                             union: Some(file_transfer_send_confirm_request::Union::Skip(true)),
                                                             ..Default::default()
                                                         };
+                                                        self.integrity.on_confirm(&req);
                                                         job.confirm(&req).await;
                                                         let msg = new_send_confirm(req);
                                                         allow_err!(peer.send(&msg).await);
                                                     }
-                                                    DigestCheckResult::NeedConfirm(digest) => {
+                                                    DigestCheckResult::NeedConfirm(mut digest) => {
+                                                        if job.is_resume {
+                                                            crate::transfer_checkpoint::verify(
+                                                                &write_path,
+                                                                &mut digest,
+                                                            );
+                                                        }
                                                         let mut overwrite_strategy =
                                                             job.default_overwrite_strategy();
                                                         let mut offset = 0;
@@ -1562,7 +2099,23 @@ impl<T: InvokeUiSession> Remote<T> {
                                                             overwrite_strategy = Some(true);
                                                             offset = digest.transferred_size as _;
                                                         }
-                                                        if let Some(overwrite) = overwrite_strategy
+                                                        let sender = self.sender.clone();
+                                                        if overwrite_strategy == Some(true)
+                                                            && offset == 0
+                                                            && self.peer_info.support_delta_sync
+                                                            && crate::delta_sync::start_write(
+                                                                job,
+                                                                digest.file_num,
+                                                                move |msg| {
+                                                                    sender
+                                                                        .send(Data::Message(msg))
+                                                                        .ok();
+                                                                },
+                                                            )
+                                                        {
+                                                            // Confirmed once the delta is applied.
+                                                        } else if let Some(overwrite) =
+                                                            overwrite_strategy
                                                         {
                                                             let req =
                                                                 FileTransferSendConfirmRequest {
@@ -1575,6 +2128,7 @@ impl<T: InvokeUiSession> Remote<T> {
                                                                     }),
                                                                     ..Default::default()
                                                                 };
+                                                            self.integrity.on_confirm(&req);
                                                             job.confirm(&req).await;
                                                             let msg = new_send_confirm(req);
                                                             allow_err!(peer.send(&msg).await);
@@ -1595,6 +2149,7 @@ impl<T: InvokeUiSession> Remote<T> {
                                                         union: Some(file_transfer_send_confirm_request::Union::OffsetBlk(0)),
                                                         ..Default::default()
                                                     };
+                                                        self.integrity.on_confirm(&req);
                                                         job.confirm(&req).await;
                                                         let msg = new_send_confirm(req);
                                                         allow_err!(peer.send(&msg).await);
@@ -1611,9 +2166,11 @@ impl<T: InvokeUiSession> Remote<T> {
                         }
                         Some(file_response::Union::Block(block)) => {
                             if let Some(job) = fs::get_job(block.id, &mut self.write_jobs) {
+                                let file_num = block.file_num;
                                 if let Err(_err) = job.write(block).await {
                                     // to-do: add "skip" for writing job
                                 }
+                                crate::transfer_checkpoint::on_written(job, file_num);
                                 if job.r#type == fs::JobType::Generic {
                                     self.update_jobs_status();
                                 }
@@ -1623,10 +2180,18 @@ impl<T: InvokeUiSession> Remote<T> {
                             let mut err: Option<String> = None;
                             let mut job_type = fs::JobType::Generic;
                             let mut printer_data = None;
+                            let mut download = None;
+                            let mut download_metadata = None;
                             if let Some(job) = fs::remove_job(d.id, &mut self.write_jobs) {
+                                self.history.update(&job);
                                 job.modify_time();
                                 err = job.job_error();
                                 job_type = job.r#type;
+                                download_metadata = transfer_metadata::Job::new(&job);
+                                download = Some(transfer_integrity::entries(
+                                    &job,
+                                    self.handler.get_path_sep(true),
+                                ));
                                 printer_data = match job.get_buf_data().await {
                                     Ok(d) => d,
                                     Err(e) => {
@@ -1637,6 +2202,11 @@ impl<T: InvokeUiSession> Remote<T> {
                             }
                             match job_type {
                                 fs::JobType::Generic => {
+                                    if err.is_none() {
+                                        self.send_transfer_metadata(d.id, download_metadata, peer)
+                                            .await;
+                                        self.start_integrity_check(d.id, download, peer).await;
+                                    }
                                     self.handle_job_status(d.id, d.file_num, err);
                                 }
                                 fs::JobType::Printer => {
@@ -1676,6 +2246,8 @@ impl<T: InvokeUiSession> Remote<T> {
                             let job_type = fs::remove_job(e.id, &mut self.write_jobs)
                                 .map(|j| j.r#type)
                                 .unwrap_or(fs::JobType::Generic);
+                            self.integrity.cancel(e.id);
+                            self.metadata.cancel(e.id);
                             match job_type {
                                 fs::JobType::Generic => {
                                     self.handle_job_status(e.id, e.file_num, Some(e.error));
@@ -1690,6 +2262,11 @@ impl<T: InvokeUiSession> Remote<T> {
                 }
                 Some(message::Union::Misc(misc)) => match misc.union {
                     Some(misc::Union::AudioFormat(f)) => {
+                        self.handler
+                            .record_audio
+                            .lock()
+                            .unwrap()
+                            .set_format(f.sample_rate, f.channels);
                         self.audio_sender.send(MediaData::AudioFormat(f)).ok();
                     }
                     Some(misc::Union::ChatMessage(c)) => {
@@ -1865,6 +2442,113 @@ impl<T: InvokeUiSession> Remote<T> {
                         #[cfg(feature = "flutter")]
                         self.handler.switch_back(&self.handler.get_id());
                     }
+                    Some(misc::Union::PluginRequest(p))
+                        if crate::tunnel::is_tunnel_request(&p.id) =>
+                    {
+                        if let Some(tunnel) = self.tunnel.as_ref() {
+                            if let Some(err) = tunnel.handle(&p.content).await {
+                                self.handler.msgbox("error", "Tunnel", &err, "");
+                            }
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == client::AUDIO_DEVICE_PLUGIN_ID =>
+                    {
+                        match serde_json::from_slice::<client::AudioDevices>(&p.content) {
+                            Ok(devices) => self
+                                .handler
+                                .update_audio_devices(devices.devices, devices.current),
+                            Err(e) => log::error!("Invalid audio devices: {}", e),
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == client::POWER_STATE_PLUGIN_ID =>
+                    {
+                        self.handler
+                            .update_peer_power_state(&String::from_utf8_lossy(&p.content));
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == crate::delta_sync::PLUGIN_ID => {
+                        self.handle_delta_sync(&p.content, peer).await;
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == transfer_integrity::PLUGIN_ID =>
+                    {
+                        self.handle_transfer_integrity(&p.content);
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == transfer_metadata::PLUGIN_ID => {
+                        self.handle_transfer_metadata(&p.content);
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == file_stream::PLUGIN_ID => {
+                        self.handle_file_stream(&p.content);
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == file_drop::PLUGIN_ID => {
+                        self.handler
+                            .file_drop_target(&String::from_utf8_lossy(&p.content));
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == file_ops::PLUGIN_ID => {
+                        self.handler
+                            .file_op_response(false, &String::from_utf8_lossy(&p.content));
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == transfer_broker::PLUGIN_ID => {
+                        match BrokerMessage::parse(&p.content) {
+                            Some(msg @ BrokerMessage::Issued { .. }) => {
+                                transfer_broker::on_issued(msg)
+                            }
+                            Some(BrokerMessage::Progress {
+                                id,
+                                finished_size,
+                                speed,
+                                ..
+                            }) => {
+                                self.handler.job_progress(id, 0, speed, finished_size as _);
+                            }
+                            Some(BrokerMessage::Done { id, error }) => {
+                                if self.brokered.remove(&id) {
+                                    self.handle_job_status(id, -1, error);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == client::BWE_STATS_PLUGIN_ID => {
+                        if let Ok(stats) = serde_json::from_slice::<client::BweStats>(&p.content) {
+                            self.handler.update_quality_status(QualityStatus {
+                                bandwidth: stats.bandwidth.map(|x| x as _),
+                                bwe_state: Some(stats.state),
+                                ..Default::default()
+                            });
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == client::AUDIO_PTS_PLUGIN_ID => {
+                        if !self.handler.lc.read().unwrap().disable_audio.v {
+                            if let Ok(pts) = String::from_utf8_lossy(&p.content).parse::<i64>() {
+                                self.audio_sender.send(MediaData::AudioPts(pts)).ok();
+                            }
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::auth_security_key::PLUGIN_ID =>
+                    {
+                        // Blocks until the key is touched.
+                        let sender = self.sender.clone();
+                        let handler = self.handler.clone();
+                        std::thread::spawn(move || {
+                            match crate::auth_security_key::sign(&p.content) {
+                                Ok(msg) => {
+                                    sender.send(Data::Message(msg)).ok();
+                                }
+                                Err(err) => {
+                                    log::error!("Failed to sign with the security key: {}", err);
+                                    handler.msgbox(
+                                        "error",
+                                        crate::client::REQUIRE_SECURITY_KEY,
+                                        &err.to_string(),
+                                        "",
+                                    );
+                                }
+                            }
+                        });
+                    }
                     #[cfg(all(feature = "flutter", feature = "plugin_framework"))]
                     #[cfg(not(any(target_os = "android", target_os = "ios")))]
                     Some(misc::Union::PluginRequest(p)) => {
@@ -1895,10 +2579,14 @@ impl<T: InvokeUiSession> Remote<T> {
                     _ => {}
                 },
                 Some(message::Union::TestDelay(t)) => {
+                    if !t.from_client && self.handler.is_default() {
+                        self.throttle.on_video_delay(t.last_delay);
+                    }
                     self.handler.handle_test_delay(t, peer).await;
                 }
                 Some(message::Union::AudioFrame(frame)) => {
                     if !self.handler.lc.read().unwrap().disable_audio.v {
+                        self.handler.record_audio.lock().unwrap().push(&frame.data);
                         self.audio_sender
                             .send(MediaData::AudioFrame(Box::new(frame)))
                             .ok();
@@ -1942,6 +2630,7 @@ impl<T: InvokeUiSession> Remote<T> {
                     },
                     Some(file_action::Union::SendConfirm(c)) => {
                         if let Some(job) = fs::get_job(c.id, &mut self.read_jobs) {
+                            self.integrity.on_confirm(&c);
                             job.confirm(&c).await;
                         }
                     }
@@ -1967,6 +2656,7 @@ impl<T: InvokeUiSession> Remote<T> {
                             allow_err!(sender.send(()));
                             self.handler.on_voice_call_closed("");
                         }
+                        self.resume_mic_passthrough();
                     }
                 }
                 Some(message::Union::VoiceCallResponse(response)) => {
@@ -2014,6 +2704,9 @@ impl<T: InvokeUiSession> Remote<T> {
 
     fn set_peer_info(&mut self, pi: &PeerInfo) {
         self.peer_info.platform = pi.platform.clone();
+        self.peer_info.support_av1_svc = false;
+        self.peer_info.support_hdr = false;
+        self.peer_info.support_bwe_stats = false;
 
         // Check features field for terminal support
         if let Some(features) = pi.features.as_ref() {
@@ -2039,9 +2732,224 @@ impl<T: InvokeUiSession> Remote<T> {
                 .map(|v| v.as_bool())
                 .flatten()
                 .unwrap_or(false);
+            self.peer_info.support_delta_sync = platform_additions
+                .get(crate::delta_sync::PLATFORM_ADDITION_KEY)
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.peer_info.support_transfer_integrity = platform_additions
+                .get(transfer_integrity::PLATFORM_ADDITION_KEY)
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.peer_info.support_transfer_metadata = platform_additions
+                .get(transfer_metadata::PLATFORM_ADDITION_KEY)
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.peer_info.support_file_stream = platform_additions
+                .get(file_stream::PLATFORM_ADDITION_KEY)
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.peer_info.support_file_compression = platform_additions
+                .get(file_compression::PLATFORM_ADDITION_KEY)
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            let support_bonding = platform_additions
+                .get("support_bonding")
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.handler.lc.write().unwrap().set_option(
+                client::bonding::PEER_OPTION_BONDING_SUPPORTED.to_owned(),
+                if support_bonding { "Y" } else { "" }.to_owned(),
+            );
+            let support_fec = platform_additions
+                .get("support_fec")
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.handler.lc.write().unwrap().set_option(
+                crate::kcp_stream::PEER_OPTION_FEC_SUPPORTED.to_owned(),
+                if support_fec { "Y" } else { "" }.to_owned(),
+            );
+            let macs: Vec<&str> = platform_additions
+                .get("mac_addresses")
+                .and_then(|v| v.as_array())
+                .map(|v| v.iter().filter_map(|x| x.as_str()).collect())
+                .unwrap_or_default();
+            if !macs.is_empty() {
+                self.handler
+                    .lc
+                    .write()
+                    .unwrap()
+                    .set_option(crate::lan::PEER_OPTION_WOL_MAC.to_owned(), macs.join(","));
+            }
+            let support_wol_relay = platform_additions
+                .get("support_wol_relay")
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.handler.lc.write().unwrap().support_wol_relay = support_wol_relay;
+            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+            if let Some(peer_layout) = platform_additions
+                .get(crate::keyboard_layout::PLATFORM_ADDITION_KEY)
+                .and_then(|v| v.as_str())
+            {
+                let is_wayland = platform_additions
+                    .get("is_wayland")
+                    .map(|v| v.as_bool())
+                    .flatten()
+                    .unwrap_or(false);
+                let local_layout = crate::keyboard_layout::get().unwrap_or_default();
+                // Only the map mode is supported by the wayland peers.
+                let mismatch =
+                    !is_wayland && crate::keyboard_layout::is_mismatch(&local_layout, peer_layout);
+                if mismatch {
+                    log::info!(
+                        "Keyboard layout mismatch, local: {}, peer: {}",
+                        local_layout,
+                        peer_layout
+                    );
+                }
+                self.handler.lc.write().unwrap().keyboard_layout_mismatch = mismatch;
+            }
+            self.peer_info.support_av1_svc = platform_additions
+                .get("support_av1_svc")
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.peer_info.support_hdr = platform_additions
+                .get("support_hdr")
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.peer_info.support_bwe_stats = platform_additions
+                .get("support_bwe_stats")
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.peer_info.support_tunnel = platform_additions
+                .get("support_tunnel")
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.peer_info.support_audio_device = platform_additions
+                .get("support_audio_device")
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.peer_info.support_audio_qos = platform_additions
+                .get("support_audio_qos")
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
+            self.peer_info.support_gamepad = platform_additions
+                .get("support_gamepad")
+                .map(|v| v.as_bool())
+                .flatten()
+                .unwrap_or(false);
         }
     }
 
+    // Ask the peer to encode AV1 with temporal layers, which this side can drop under congestion,
+    // and in 10-bit HDR, which this side tone-maps.
+    fn request_video_features(&self) {
+        let mut ids = vec![];
+        if self.peer_info.support_av1_svc {
+            ids.push(client::AV1_SVC_PLUGIN_ID);
+        }
+        if self.peer_info.support_hdr {
+            ids.push(client::HDR_PLUGIN_ID);
+        }
+        if self.peer_info.support_bwe_stats {
+            ids.push(client::BWE_STATS_PLUGIN_ID);
+        }
+        for id in ids {
+            let mut misc = Misc::new();
+            misc.set_plugin_request(PluginRequest {
+                id: id.to_owned(),
+                ..Default::default()
+            });
+            let mut msg = Message::new();
+            msg.set_misc(misc);
+            self.sender.send(Data::Message(msg)).ok();
+        }
+    }
+
+    // The peer tunes the audio encoder with the frames concealed here.
+    async fn report_audio_loss(&mut self, peer: &mut Stream) {
+        let Some(percent) = self.audio_stats.take_loss_percent() else {
+            return;
+        };
+        if !self.peer_info.support_audio_qos {
+            return;
+        }
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: client::AUDIO_LOSS_PLUGIN_ID.to_owned(),
+            content: percent.to_string().into_bytes().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        allow_err!(peer.send(&msg).await);
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    fn forward_gamepad(&mut self, on: bool) {
+        if !on || !self.peer_info.support_gamepad {
+            self.gamepad = None;
+            return;
+        }
+        if self.gamepad.is_none() {
+            let sender = self.sender.clone();
+            self.gamepad = Some(crate::gamepad::Capture::start(move |msg| {
+                sender.send(Data::Message(msg)).ok();
+            }));
+        }
+    }
+
+    // Capture the audio device selected for the peer last time.
+    fn select_audio_device(&self) {
+        if !self.peer_info.support_audio_device {
+            return;
+        }
+        let device = self
+            .handler
+            .get_option(client::PEER_OPTION_AUDIO_DEVICE.to_owned());
+        if !device.is_empty() {
+            self.handler.select_audio_device(device);
+        }
+    }
+
+    // Start the tunnels persisted for the peer, the peer has to allow them.
+    async fn start_tunnels(&mut self) {
+        if !self.peer_info.support_tunnel {
+            return;
+        }
+        let sender = self.sender.clone();
+        let tunnel = crate::tunnel::Tunnel::new(
+            true,
+            Arc::new(move |msg| {
+                sender.send(Data::Message(msg)).ok();
+            }),
+        );
+        let tunnels = crate::tunnel::parse_tunnels(
+            &self
+                .handler
+                .get_option(crate::tunnel::PEER_OPTION_TUNNELS.to_owned()),
+        );
+        for t in tunnels.iter() {
+            if let Err(err) = tunnel.add(t).await {
+                log::error!("failed to start tunnel {}: {}", t.key(), err);
+            }
+        }
+        self.tunnel = Some(tunnel);
+    }
+
     async fn handle_back_notification(&mut self, notification: BackNotification) -> bool {
         match notification.union {
             Some(back_notification::Union::BlockInputState(state)) => {
@@ -2243,6 +3151,9 @@ impl<T: InvokeUiSession> Remote<T> {
             log::warn!("failed to decode cliprdr msg from server peer");
             return;
         };
+        if let Some(event) = self.paste.on_peer(&clip) {
+            self.on_clipboard_paste(event);
+        }
 
         let is_stopping_allowed = clip.is_beginning_message();
         let file_transfer_enabled = self.handler.is_file_clipboard_required();
@@ -2316,6 +3227,7 @@ impl<T: InvokeUiSession> Remote<T> {
             frame_count: frame_count.clone(),
             fps_control: Default::default(),
             discard_queue: discard_queue.clone(),
+            layer_filter: Default::default(),
         };
         let handler = self.handler.ui_handler.clone();
         crate::client::start_video_thread(
@@ -2326,6 +3238,7 @@ impl<T: InvokeUiSession> Remote<T> {
             decode_fps,
             self.chroma.clone(),
             discard_queue,
+            self.av_sync.clone(),
             move |display: usize,
                   data: &mut scrap::ImageRgb,
                   _texture: *mut c_void,
@@ -2420,6 +3333,7 @@ struct VideoThread {
     frame_count: Arc<RwLock<usize>>,
     discard_queue: Arc<RwLock<bool>>,
     fps_control: FpsControl,
+    layer_filter: scrap::aom::TemporalLayerFilter,
 }
 
 impl Drop for VideoThread {
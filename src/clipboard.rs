@@ -63,6 +63,7 @@ pub fn check_clipboard(
             if !content.is_empty() {
                 let mut msg = Message::new();
                 let clipboards = proto::create_multi_clipboards(content);
+                let clipboards = crate::clipboard_policy::outgoing(clipboards, side)?;
                 msg.set_multi_clipboards(clipboards.clone());
                 *LAST_MULTI_CLIPBOARDS.lock().unwrap() = clipboards;
                 return Some(msg);
@@ -102,6 +103,10 @@ pub fn check_clipboard_files(
     if ctx.is_none() {
         *ctx = ClipboardContext::new().ok();
     }
+    #[cfg(target_os = "linux")]
+    if side == ClipboardSide::Host && scrap::wayland::pipewire::is_clipboard_enabled() {
+        return get_portal_clipboard_files(force);
+    }
     let ctx2 = ctx.as_mut()?;
     match ctx2.get_files(side, force) {
         Ok(Some(urls)) => {
@@ -117,11 +122,83 @@ pub fn check_clipboard_files(
     None
 }
 
+// `x-special/gnome-copied-files` is set besides `text/uri-list`,
+// as Nautilus and its forks paste the files only from it.
+#[cfg(all(target_os = "linux", feature = "unix-file-copy-paste"))]
+const MIME_URI_LIST: &'static str = "text/uri-list";
+#[cfg(all(target_os = "linux", feature = "unix-file-copy-paste"))]
+const MIME_GNOME_COPIED_FILES: &'static str = "x-special/gnome-copied-files";
+
+#[cfg(all(target_os = "linux", feature = "unix-file-copy-paste"))]
+fn to_file_urls(files: &[String]) -> Vec<String> {
+    files
+        .iter()
+        .filter_map(|f| url::Url::from_file_path(f).ok())
+        .map(|u| u.to_string())
+        .collect()
+}
+
+#[cfg(all(target_os = "linux", feature = "unix-file-copy-paste"))]
+fn gnome_copied_files(urls: &[String]) -> Vec<u8> {
+    format!("copy\n{}", urls.join("\n")).into_bytes()
+}
+
+#[cfg(all(target_os = "linux", feature = "unix-file-copy-paste"))]
+fn from_uri_list(data: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| url::Url::parse(l).ok()?.to_file_path().ok())
+        .map(|p| p.to_string_lossy().to_string())
+        .collect()
+}
+
+// The clipboard of the RemoteDesktop portal session on Wayland, if the user allows it,
+// as the clipboard of the compositors without the data control protocol, e.g. GNOME,
+// can not be read or set by the service without the focus.
+#[cfg(all(target_os = "linux", feature = "unix-file-copy-paste"))]
+fn get_portal_clipboard_files(force: bool) -> Option<Vec<String>> {
+    match scrap::wayland::pipewire::read_clipboard(MIME_URI_LIST, force) {
+        Ok(data) => Some(from_uri_list(&data?)).filter(|files| !files.is_empty()),
+        Err(e) => {
+            log::error!("Failed to get the file urls of the portal clipboard. {}", e);
+            None
+        }
+    }
+}
+
 #[cfg(all(target_os = "linux", feature = "unix-file-copy-paste"))]
 pub fn update_clipboard_files(files: Vec<String>, side: ClipboardSide) {
     if !files.is_empty() {
         std::thread::spawn(move || {
-            do_update_clipboard_(vec![ClipboardData::FileUrl(files)], side);
+            let urls = to_file_urls(&files);
+            if side == ClipboardSide::Host && scrap::wayland::pipewire::is_clipboard_enabled() {
+                let data = vec![
+                    (
+                        MIME_URI_LIST.to_owned(),
+                        format!("{}\r\n", urls.join("\r\n")).into_bytes(),
+                    ),
+                    (
+                        MIME_GNOME_COPIED_FILES.to_owned(),
+                        gnome_copied_files(&urls),
+                    ),
+                ];
+                match scrap::wayland::pipewire::set_clipboard(data) {
+                    Ok(_) => return,
+                    Err(e) => log::error!("Failed to set the portal clipboard: {}", e),
+                }
+            }
+            do_update_clipboard_(
+                vec![
+                    ClipboardData::FileUrl(files),
+                    ClipboardData::Special((
+                        MIME_GNOME_COPIED_FILES.to_owned(),
+                        gnome_copied_files(&urls),
+                    )),
+                ],
+                side,
+            );
         });
     }
 }
@@ -238,6 +315,15 @@ pub fn update_clipboard(multi_clipboards: Vec<Clipboard>, side: ClipboardSide) {
     });
 }
 
+/// Sets the clipboard received from the peer, within the limits of [`crate::clipboard_policy`].
+#[cfg(not(target_os = "android"))]
+pub fn update_clipboard_of_peer(multi_clipboards: Vec<Clipboard>, side: ClipboardSide) {
+    let multi_clipboards = crate::clipboard_policy::incoming(multi_clipboards, side);
+    if !multi_clipboards.is_empty() {
+        update_clipboard(multi_clipboards, side);
+    }
+}
+
 #[cfg(not(target_os = "android"))]
 pub struct ClipboardContext {
     inner: arboard::Clipboard,
@@ -486,9 +572,7 @@ pub fn get_current_clipboard_msg(
         let mut ctx = ClipboardContext::new().ok()?;
         *multi_clipboards = proto::create_multi_clipboards(ctx.get(side, true).ok()?);
     }
-    if multi_clipboards.clipboards.is_empty() {
-        return None;
-    }
+    let multi_clipboards = crate::clipboard_policy::outgoing(multi_clipboards.clone(), side)?;
 
     if is_support_multi_clipboard(peer_version, peer_platform) {
         let mut msg = Message::new();
@@ -543,21 +627,44 @@ impl std::fmt::Display for ClipboardSide {
 
 pub use proto::get_msg_if_not_support_multi_clip;
 mod proto {
+    use crate::compression::compress as compress_func;
     #[cfg(not(target_os = "android"))]
     use arboard::ClipboardData;
     use hbb_common::{
-        compress::{compress as compress_func, decompress},
+        compress::decompress,
         message_proto::{Clipboard, ClipboardFormat, Message, MultiClipboards},
     };
 
+    // Bytes before the compression, the larger contents are neither sent nor set. The other
+    // formats of the same copy are still synced.
+    #[cfg(not(target_os = "android"))]
+    const MAX_TEXT_SIZE: usize = 16 * 1024 * 1024;
+    #[cfg(not(target_os = "android"))]
+    const MAX_IMAGE_SIZE: usize = 128 * 1024 * 1024;
+
+    #[cfg(not(target_os = "android"))]
+    fn within_limit(format: ClipboardFormat, size: usize) -> bool {
+        let max = match format {
+            ClipboardFormat::ImageRgba | ClipboardFormat::ImagePng | ClipboardFormat::ImageSvg => {
+                MAX_IMAGE_SIZE
+            }
+            _ => MAX_TEXT_SIZE,
+        };
+        if size > max {
+            hbb_common::log::warn!(
+                "Clipboard {:?} of {} bytes skipped, the limit is {}",
+                format,
+                size,
+                max
+            );
+        }
+        size <= max
+    }
+
     fn plain_to_proto(s: String, format: ClipboardFormat) -> Clipboard {
         let compressed = compress_func(s.as_bytes());
-        let compress = compressed.len() < s.as_bytes().len();
-        let content = if compress {
-            compressed
-        } else {
-            s.bytes().collect::<Vec<u8>>()
-        };
+        let compress = compressed.is_some();
+        let content = compressed.unwrap_or_else(|| s.into_bytes());
         Clipboard {
             compress,
             content: content.into(),
@@ -571,12 +678,8 @@ mod proto {
         match &a {
             arboard::ImageData::Rgba(rgba) => {
                 let compressed = compress_func(&a.bytes());
-                let compress = compressed.len() < a.bytes().len();
-                let content = if compress {
-                    compressed
-                } else {
-                    a.bytes().to_vec()
-                };
+                let compress = compressed.is_some();
+                let content = compressed.unwrap_or_else(|| a.bytes().to_vec());
                 Clipboard {
                     compress,
                     content: content.into(),
@@ -594,12 +697,8 @@ mod proto {
             },
             arboard::ImageData::Svg(_) => {
                 let compressed = compress_func(&a.bytes());
-                let compress = compressed.len() < a.bytes().len();
-                let content = if compress {
-                    compressed
-                } else {
-                    a.bytes().to_vec()
-                };
+                let compress = compressed.is_some();
+                let content = compressed.unwrap_or_else(|| a.bytes().to_vec());
                 Clipboard {
                     compress,
                     content: content.into(),
@@ -612,12 +711,8 @@ mod proto {
 
     fn special_to_proto(d: Vec<u8>, s: String) -> Clipboard {
         let compressed = compress_func(&d);
-        let compress = compressed.len() < d.len();
-        let content = if compress {
-            compressed
-        } else {
-            s.bytes().collect::<Vec<u8>>()
-        };
+        let compress = compressed.is_some();
+        let content = compressed.unwrap_or_else(|| s.bytes().collect::<Vec<u8>>());
         Clipboard {
             compress,
             content: content.into(),
@@ -629,6 +724,17 @@ mod proto {
 
     #[cfg(not(target_os = "android"))]
     fn clipboard_data_to_proto(data: ClipboardData) -> Option<Clipboard> {
+        let (format, size) = match &data {
+            ClipboardData::Text(s) => (ClipboardFormat::Text, s.len()),
+            ClipboardData::Rtf(s) => (ClipboardFormat::Rtf, s.len()),
+            ClipboardData::Html(s) => (ClipboardFormat::Html, s.len()),
+            ClipboardData::Image(a) => (ClipboardFormat::ImageRgba, a.bytes().len()),
+            ClipboardData::Special((_, d)) => (ClipboardFormat::Special, d.len()),
+            _ => return None,
+        };
+        if !within_limit(format, size) {
+            return None;
+        }
         let d = match data {
             ClipboardData::Text(s) => plain_to_proto(s, ClipboardFormat::Text),
             ClipboardData::Rtf(s) => plain_to_proto(s, ClipboardFormat::Rtf),
@@ -658,6 +764,9 @@ mod proto {
         } else {
             clipboard.content.into()
         };
+        if !within_limit(clipboard.format.enum_value().ok()?, data.len()) {
+            return None;
+        }
         match clipboard.format.enum_value() {
             Ok(ClipboardFormat::Text) => String::from_utf8(data).ok().map(ClipboardData::Text),
             Ok(ClipboardFormat::Rtf) => String::from_utf8(data).ok().map(ClipboardData::Rtf),
@@ -0,0 +1,149 @@
+//! The manual clipboard sync of a session.
+//!
+//! With [`OPTION_MANUAL_CLIPBOARD_SYNC`] toggled on, the clipboard of this device is no longer
+//! sent to the peer on each copy, nor the clipboard of the peer set on this device. The recent
+//! copies of both sides are kept instead, the ones of this device in [`LOCAL`], shared by the
+//! sessions, the ones of the peer in the session, and the user pushes or pulls the one chosen
+//! from the toolbar. The peer keeps sending its clipboard as usual.
+
+use hbb_common::{
+    compress::decompress,
+    get_time,
+    message_proto::{message, Clipboard, ClipboardFormat, Message},
+};
+use serde_json::json;
+use std::{collections::VecDeque, sync::Mutex};
+
+/// Session toggle option.
+pub const OPTION_MANUAL_CLIPBOARD_SYNC: &str = "manual-clipboard-sync";
+
+const MAX_CLIPS: usize = 10;
+// Chars of the text shown in the history.
+const PREVIEW_LEN: usize = 100;
+
+lazy_static::lazy_static! {
+    pub static ref LOCAL: Mutex<Clips> = Default::default();
+}
+
+struct Clip {
+    id: i32,
+    /// Milliseconds since the epoch.
+    time: i64,
+    clipboards: Vec<Clipboard>,
+}
+
+/// The recent copies of one side, the newest first.
+#[derive(Default)]
+pub struct Clips {
+    clips: VecDeque<Clip>,
+    next_id: i32,
+}
+
+impl Clips {
+    /// Keeps the clipboard of the message, unless it is the newest already.
+    pub fn push_msg(&mut self, msg: &Message) {
+        match &msg.union {
+            Some(message::Union::MultiClipboards(mcb)) => self.push(mcb.clipboards.clone()),
+            Some(message::Union::Clipboard(cb)) => self.push(vec![cb.clone()]),
+            _ => {}
+        }
+    }
+
+    pub fn push(&mut self, clipboards: Vec<Clipboard>) {
+        if clipboards.is_empty()
+            || self
+                .clips
+                .front()
+                .map_or(false, |c| c.clipboards == clipboards)
+        {
+            return;
+        }
+        self.next_id += 1;
+        self.clips.push_front(Clip {
+            id: self.next_id,
+            time: get_time(),
+            clipboards,
+        });
+        self.clips.truncate(MAX_CLIPS);
+    }
+
+    pub fn get(&self, id: i32) -> Option<Vec<Clipboard>> {
+        self.clips
+            .iter()
+            .find(|c| c.id == id)
+            .map(|c| c.clipboards.clone())
+    }
+
+    /// `[{"id", "time", "preview"}]`, for the ui.
+    pub fn to_json(&self) -> String {
+        let clips = self
+            .clips
+            .iter()
+            .map(|c| json!({"id": c.id, "time": c.time, "preview": preview(&c.clipboards)}))
+            .collect::<Vec<_>>();
+        serde_json::to_string(&clips).unwrap_or_default()
+    }
+}
+
+/// The text of the copy, or its formats if it has none.
+fn preview(clipboards: &[Clipboard]) -> String {
+    let text = clipboards
+        .iter()
+        .find(|c| c.format.enum_value() == Ok(ClipboardFormat::Text));
+    if let Some(c) = text {
+        let content = if c.compress {
+            decompress(&c.content)
+        } else {
+            c.content.to_vec()
+        };
+        let text = String::from_utf8_lossy(&content);
+        let mut preview = text.chars().take(PREVIEW_LEN).collect::<String>();
+        if text.chars().count() > PREVIEW_LEN {
+            preview.push_str("...");
+        }
+        return preview;
+    }
+    clipboards
+        .iter()
+        .filter(|c| c.format.enum_value() != Ok(ClipboardFormat::Special))
+        .map(|c| match c.format.enum_value() {
+            Ok(ClipboardFormat::ImageRgba | ClipboardFormat::ImagePng) if c.width > 0 => {
+                format!("[Image {}x{}]", c.width, c.height)
+            }
+            Ok(format) => format!("[{:?}]", format),
+            Err(_) => "[?]".to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Clipboard {
+        Clipboard {
+            content: s.as_bytes().to_vec().into(),
+            format: ClipboardFormat::Text.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_clips() {
+        let mut clips = Clips::default();
+        clips.push(vec![text("a")]);
+        clips.push(vec![text("a")]);
+        assert_eq!(clips.clips.len(), 1);
+        for i in 0..MAX_CLIPS {
+            clips.push(vec![text(&i.to_string())]);
+        }
+        assert_eq!(clips.clips.len(), MAX_CLIPS);
+        assert!(clips.get(1).is_none());
+        assert_eq!(clips.get(clips.next_id), Some(vec![text("9")]));
+        assert_eq!(
+            preview(&[text(&"x".repeat(PREVIEW_LEN + 1))]).len(),
+            PREVIEW_LEN + 3
+        );
+    }
+}
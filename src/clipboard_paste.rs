@@ -0,0 +1,299 @@
+//! The progress of the files pasted from the clipboard of the peer.
+//!
+//! The clipboard of this device pulls the files chunk by chunk with `FileContentsRequest`, the
+//! next chunk being requested once the previous one is received, so the memory used does not
+//! grow with the size of the files. The session reads the sizes of the files in the
+//! `FileGroupDescriptorW` format sent by the peer, then counts the chunks received, and reports
+//! the paste to the ui as a transfer job, shown and cancelled like the jobs of the file manager.
+//! Once cancelled, the requests of the clipboard are failed locally instead of being sent to the
+//! peer, until the files are pasted again.
+
+use clipboard::ClipboardFile;
+use hbb_common::{
+    fs::get_next_job_id,
+    tokio::time::{Duration, Instant},
+};
+use std::collections::HashMap;
+
+const FORMAT_FILE_LIST: &str = "FileGroupDescriptorW";
+// FILEDESCRIPTORW, with the size at 64, high part first.
+const FILE_DESCRIPTOR_LEN: usize = 592;
+const FILE_SIZE_OFFSET: usize = 64;
+const FLAG_RANGE: i32 = 0x2;
+const RESPONSE_FAIL: i32 = 0x2;
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Started {
+        id: i32,
+        files: usize,
+        total: u64,
+    },
+    Progress {
+        id: i32,
+        file_num: i32,
+        speed: f64,
+        finished: u64,
+    },
+    Done {
+        id: i32,
+        file_num: i32,
+    },
+}
+
+/// What to do with a message of the clipboard of this device.
+pub enum Local {
+    Send(Option<Event>),
+    /// The paste is cancelled, the answer to give back to the clipboard.
+    Fail(ClipboardFile),
+}
+
+struct Job {
+    id: i32,
+    sizes: Vec<u64>,
+    total: u64,
+    finished: u64,
+    file_num: i32,
+    started: Option<Instant>,
+    last_report: Instant,
+    cancelled: bool,
+    // The ranges requested, by stream.
+    pending: HashMap<i32, i32>,
+}
+
+#[derive(Default)]
+pub struct Paste {
+    list_format: Option<i32>,
+    list_requested: bool,
+    job: Option<Job>,
+}
+
+fn parse_sizes(data: &[u8]) -> Vec<u64> {
+    let Some(count) = data.get(..4) else {
+        return vec![];
+    };
+    let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+    data[4..]
+        .chunks_exact(FILE_DESCRIPTOR_LEN)
+        .take(count)
+        .map(|d| {
+            let s = &d[FILE_SIZE_OFFSET..FILE_SIZE_OFFSET + 8];
+            let high = u32::from_le_bytes([s[0], s[1], s[2], s[3]]) as u64;
+            let low = u32::from_le_bytes([s[4], s[5], s[6], s[7]]) as u64;
+            high << 32 | low
+        })
+        .collect()
+}
+
+impl Paste {
+    /// A message of the peer, before it is handed to the clipboard.
+    pub fn on_peer(&mut self, clip: &ClipboardFile) -> Option<Event> {
+        match clip {
+            ClipboardFile::FormatList { format_list } => {
+                self.list_format = format_list
+                    .iter()
+                    .find(|(_, name)| name == FORMAT_FILE_LIST)
+                    .map(|(id, _)| *id);
+                self.job = None;
+            }
+            ClipboardFile::FormatDataResponse {
+                msg_flags,
+                format_data,
+            } if self.list_requested => {
+                self.list_requested = false;
+                if *msg_flags & RESPONSE_FAIL != 0 {
+                    return None;
+                }
+                let sizes = parse_sizes(format_data);
+                self.job = Some(Job {
+                    id: 0,
+                    total: sizes.iter().sum(),
+                    sizes,
+                    finished: 0,
+                    file_num: 0,
+                    started: None,
+                    last_report: Instant::now(),
+                    cancelled: false,
+                    pending: HashMap::new(),
+                });
+            }
+            ClipboardFile::FileContentsResponse {
+                stream_id,
+                requested_data,
+                ..
+            } => {
+                let job = self.job.as_mut()?;
+                let file_num = job.pending.remove(stream_id)?;
+                job.finished += requested_data.len() as u64;
+                job.file_num = file_num;
+                if job.finished >= job.total {
+                    let id = job.id;
+                    self.job = None;
+                    return Some(Event::Done { id, file_num });
+                }
+                if job.last_report.elapsed() >= REPORT_INTERVAL {
+                    job.last_report = Instant::now();
+                    let elapsed = job.started?.elapsed().as_secs_f64();
+                    return Some(Event::Progress {
+                        id: job.id,
+                        file_num,
+                        speed: job.finished as f64 / elapsed.max(0.001),
+                        finished: job.finished,
+                    });
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// A message of the clipboard of this device, before it is sent to the peer.
+    pub fn on_local(&mut self, clip: &ClipboardFile) -> Local {
+        match clip {
+            ClipboardFile::FormatDataRequest {
+                requested_format_id,
+            } => {
+                // The files are pasted again.
+                self.list_requested = self.list_format == Some(*requested_format_id);
+                if self.list_requested {
+                    self.job = None;
+                }
+            }
+            ClipboardFile::FileContentsRequest {
+                stream_id,
+                list_index,
+                dw_flags,
+                ..
+            } => {
+                let Some(job) = self.job.as_mut() else {
+                    return Local::Send(None);
+                };
+                if job.cancelled {
+                    return Local::Fail(ClipboardFile::FileContentsResponse {
+                        msg_flags: RESPONSE_FAIL,
+                        stream_id: *stream_id,
+                        requested_data: vec![],
+                    });
+                }
+                if *dw_flags & FLAG_RANGE == 0 || job.total == 0 {
+                    return Local::Send(None);
+                }
+                job.pending.insert(*stream_id, *list_index);
+                if job.started.is_none() {
+                    job.id = get_next_job_id();
+                    job.started = Some(Instant::now());
+                    return Local::Send(Some(Event::Started {
+                        id: job.id,
+                        files: job.sizes.len(),
+                        total: job.total,
+                    }));
+                }
+            }
+            _ => {}
+        }
+        Local::Send(None)
+    }
+
+    /// Cancels the paste of the job, returns the file being pasted.
+    pub fn cancel(&mut self, id: i32) -> Option<i32> {
+        let job = self.job.as_mut()?;
+        if job.started.is_none() || job.id != id || job.cancelled {
+            return None;
+        }
+        job.cancelled = true;
+        job.pending.clear();
+        Some(job.file_num)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_list(sizes: &[u64]) -> Vec<u8> {
+        let mut data = (sizes.len() as u32).to_le_bytes().to_vec();
+        for size in sizes {
+            let mut d = vec![0u8; FILE_DESCRIPTOR_LEN];
+            d[FILE_SIZE_OFFSET..FILE_SIZE_OFFSET + 4]
+                .copy_from_slice(&((size >> 32) as u32).to_le_bytes());
+            d[FILE_SIZE_OFFSET + 4..FILE_SIZE_OFFSET + 8]
+                .copy_from_slice(&(*size as u32).to_le_bytes());
+            data.extend(d);
+        }
+        data
+    }
+
+    fn range(stream_id: i32, list_index: i32) -> ClipboardFile {
+        ClipboardFile::FileContentsRequest {
+            stream_id,
+            list_index,
+            dw_flags: FLAG_RANGE,
+            n_position_low: 0,
+            n_position_high: 0,
+            cb_requested: 4,
+            have_clip_data_id: false,
+            clip_data_id: 0,
+        }
+    }
+
+    fn response(stream_id: i32, len: usize) -> ClipboardFile {
+        ClipboardFile::FileContentsResponse {
+            msg_flags: 0x1,
+            stream_id,
+            requested_data: vec![0; len],
+        }
+    }
+
+    #[test]
+    fn test_parse_sizes() {
+        assert_eq!(
+            parse_sizes(&file_list(&[0, 5, 1 << 33])),
+            vec![0, 5, 1 << 33]
+        );
+        assert!(parse_sizes(&[1]).is_empty());
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut paste = Paste::default();
+        paste.on_peer(&ClipboardFile::FormatList {
+            format_list: vec![(49000, FORMAT_FILE_LIST.to_owned())],
+        });
+        paste.on_local(&ClipboardFile::FormatDataRequest {
+            requested_format_id: 49000,
+        });
+        paste.on_peer(&ClipboardFile::FormatDataResponse {
+            msg_flags: 0x1,
+            format_data: file_list(&[4, 4]),
+        });
+        let Local::Send(Some(Event::Started { id, files, total })) = paste.on_local(&range(1, 0))
+        else {
+            panic!("not started");
+        };
+        assert_eq!((files, total), (2, 8));
+        assert_eq!(paste.on_peer(&response(1, 4)), None);
+        paste.on_local(&range(2, 1));
+        assert_eq!(
+            paste.on_peer(&response(2, 4)),
+            Some(Event::Done { id, file_num: 1 })
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut paste = Paste::default();
+        paste.list_requested = true;
+        paste.on_peer(&ClipboardFile::FormatDataResponse {
+            msg_flags: 0x1,
+            format_data: file_list(&[8]),
+        });
+        let Local::Send(Some(Event::Started { id, .. })) = paste.on_local(&range(1, 0)) else {
+            panic!("not started");
+        };
+        assert_eq!(paste.cancel(id + 1), None);
+        assert_eq!(paste.cancel(id), Some(0));
+        assert!(matches!(paste.on_local(&range(2, 0)), Local::Fail(_)));
+        assert_eq!(paste.on_peer(&response(1, 4)), None);
+    }
+}
@@ -0,0 +1,171 @@
+//! The limits of the clipboard synced with the peers, set by the user of each side.
+//!
+//! The controlled side reads them from its options, the controlling side from its local
+//! options. They are applied where the clipboard is read to be sent and where the clipboard of
+//! the peer is about to be set, so that the peer can not bypass them:
+//!
+//! - [`OPTION_CLIPBOARD_MAX_SIZE`], the formats larger than it are neither sent nor set.
+//! - [`OPTION_CLIPBOARD_DIRECTION`], to only send the clipboard of this device to the peers,
+//!   or only receive theirs.
+//! - [`OPTION_CLIPBOARD_FILTERS`], a copy whose text matches one of them is not sent at all.
+//!
+//! The files copied are not concerned.
+
+use crate::clipboard::ClipboardSide;
+use hbb_common::{
+    compress::decompress,
+    config::{Config, LocalConfig},
+    log,
+    message_proto::{Clipboard, ClipboardFormat, MultiClipboards},
+    regex::Regex,
+};
+use std::sync::Mutex;
+
+/// KB, empty or 0 for no limit.
+pub const OPTION_CLIPBOARD_MAX_SIZE: &str = "clipboard-max-size";
+/// [`DIRECTION_SEND`], [`DIRECTION_RECEIVE`], or empty for both.
+pub const OPTION_CLIPBOARD_DIRECTION: &str = "clipboard-direction";
+/// Regular expressions, one per line.
+pub const OPTION_CLIPBOARD_FILTERS: &str = "clipboard-filters";
+
+/// Only from this device to the peers.
+pub const DIRECTION_SEND: &str = "send";
+/// Only from the peers to this device.
+pub const DIRECTION_RECEIVE: &str = "receive";
+
+lazy_static::lazy_static! {
+    // The filters compiled, with the option they are compiled from.
+    static ref FILTERS: Mutex<(String, Vec<Regex>)> = Default::default();
+}
+
+fn get_option(side: ClipboardSide, key: &str) -> String {
+    match side {
+        ClipboardSide::Host => Config::get_option(key),
+        ClipboardSide::Client => LocalConfig::get_option(key),
+    }
+}
+
+fn max_size(side: ClipboardSide) -> Option<usize> {
+    match get_option(side, OPTION_CLIPBOARD_MAX_SIZE)
+        .trim()
+        .parse::<usize>()
+    {
+        Ok(kb) if kb > 0 => Some(kb * 1024),
+        _ => None,
+    }
+}
+
+fn parse_filters(option: &str) -> Vec<Regex> {
+    option
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .filter_map(|l| {
+            Regex::new(l)
+                .map_err(|e| log::error!("Invalid clipboard filter {}: {}", l, e))
+                .ok()
+        })
+        .collect()
+}
+
+fn is_text(c: &Clipboard) -> bool {
+    matches!(
+        c.format.enum_value(),
+        Ok(ClipboardFormat::Text | ClipboardFormat::Html | ClipboardFormat::Rtf)
+    )
+}
+
+fn content(c: &Clipboard) -> Vec<u8> {
+    if c.compress {
+        decompress(&c.content)
+    } else {
+        c.content.to_vec()
+    }
+}
+
+fn within_size(c: &Clipboard, max: Option<usize>) -> bool {
+    let Some(max) = max else {
+        return true;
+    };
+    let size = content(c).len();
+    if size > max {
+        log::info!(
+            "Clipboard {:?} of {} bytes skipped, the limit set is {}",
+            c.format,
+            size,
+            max
+        );
+    }
+    size <= max
+}
+
+fn is_filtered(clipboards: &[Clipboard], filters: &[Regex]) -> bool {
+    if filters.is_empty() {
+        return false;
+    }
+    clipboards.iter().filter(|c| is_text(c)).any(|c| {
+        let text = String::from_utf8_lossy(&content(c)).into_owned();
+        filters.iter().any(|f| f.is_match(&text))
+    })
+}
+
+/// The clipboard of this device to send to the peers, `None` if nothing is allowed.
+pub fn outgoing(mut clipboards: MultiClipboards, side: ClipboardSide) -> Option<MultiClipboards> {
+    if get_option(side, OPTION_CLIPBOARD_DIRECTION) == DIRECTION_RECEIVE {
+        return None;
+    }
+    let max = max_size(side);
+    clipboards.clipboards.retain(|c| within_size(c, max));
+    let option = get_option(side, OPTION_CLIPBOARD_FILTERS);
+    let mut filters = FILTERS.lock().unwrap();
+    if filters.0 != option {
+        *filters = (option.clone(), parse_filters(&option));
+    }
+    if is_filtered(&clipboards.clipboards, &filters.1) {
+        log::info!("Clipboard not sent, it matches a filter");
+        return None;
+    }
+    if clipboards.clipboards.is_empty() {
+        return None;
+    }
+    Some(clipboards)
+}
+
+/// The clipboard of the peer to set on this device.
+pub fn incoming(mut clipboards: Vec<Clipboard>, side: ClipboardSide) -> Vec<Clipboard> {
+    if get_option(side, OPTION_CLIPBOARD_DIRECTION) == DIRECTION_SEND {
+        return vec![];
+    }
+    let max = max_size(side);
+    clipboards.retain(|c| within_size(c, max));
+    clipboards
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Clipboard {
+        Clipboard {
+            content: s.as_bytes().to_vec().into(),
+            format: ClipboardFormat::Text.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_filters() {
+        let filters = parse_filters("\n(?i)password\\s*[:=]\n[invalid\n  \n");
+        assert_eq!(filters.len(), 1);
+        assert!(is_filtered(&[text("Password: 123")], &filters));
+        assert!(!is_filtered(&[text("hello")], &filters));
+        assert!(!is_filtered(&[text("password: 123")], &[]));
+    }
+
+    #[test]
+    fn test_size() {
+        assert!(within_size(&text("1234"), None));
+        assert!(within_size(&text("1234"), Some(4)));
+        assert!(!within_size(&text("12345"), Some(4)));
+    }
+}
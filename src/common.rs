@@ -69,6 +69,8 @@ pub mod input {
     pub const MOUSE_TYPE_UP: i32 = 2;
     pub const MOUSE_TYPE_WHEEL: i32 = 3;
     pub const MOUSE_TYPE_TRACKPAD: i32 = 4;
+    /// x and y are the deltas of the raw motion, not a position.
+    pub const MOUSE_TYPE_MOVE_RELATIVE: i32 = 5;
 
     pub const MOUSE_BUTTON_LEFT: i32 = 0x01;
     pub const MOUSE_BUTTON_RIGHT: i32 = 0x02;
@@ -82,6 +84,7 @@ lazy_static::lazy_static! {
     pub static ref DEVICE_ID: Arc<Mutex<String>> = Default::default();
     pub static ref DEVICE_NAME: Arc<Mutex<String>> = Default::default();
     static ref PUBLIC_IPV6_ADDR: Arc<Mutex<(Option<SocketAddr>, Option<Instant>)>> = Default::default();
+    static ref NAT64_PREFIX: Arc<Mutex<(Option<std::net::Ipv6Addr>, Option<Instant>)>> = Default::default();
 }
 
 lazy_static::lazy_static! {
@@ -902,6 +905,11 @@ pub fn check_software_update() {
     if is_custom_client() {
         return;
     }
+    // The service downloads and installs the updates of the update URL itself.
+    #[cfg(target_os = "windows")]
+    if crate::updater::manifest::has_update_url() {
+        return;
+    }
     let opt = LocalConfig::get_option(keys::OPTION_ENABLE_CHECK_UPDATE);
     if config::option2bool(keys::OPTION_ENABLE_CHECK_UPDATE, &opt) {
         std::thread::spawn(move || allow_err!(do_check_software_update()));
@@ -2040,6 +2048,81 @@ pub async fn get_ipv6_socket() -> Option<(Arc<UdpSocket>, bytes::Bytes)> {
     None
 }
 
+// RFC 7050, `ipv4only.arpa` only has these two A records, a DNS64 resolver synthesizes
+// AAAA records from them with the NAT64 prefix of the network.
+const IPV4ONLY_ARPA: &str = "ipv4only.arpa:0";
+const IPV4ONLY_ADDRS: [[u8; 4]; 2] = [[192, 0, 0, 170], [192, 0, 0, 171]];
+
+// Only the /96 prefix is supported, which is the well-known 64:ff9b::/96 and what all
+// carriers we have seen use.
+fn nat64_prefix_of(addr: std::net::Ipv6Addr) -> Option<std::net::Ipv6Addr> {
+    let octets = addr.octets();
+    if !IPV4ONLY_ADDRS.iter().any(|x| octets[12..] == x[..]) {
+        return None;
+    }
+    let mut prefix = [0u8; 16];
+    prefix[..12].copy_from_slice(&octets[..12]);
+    Some(prefix.into())
+}
+
+fn nat64_synthesize(prefix: std::net::Ipv6Addr, addr: std::net::Ipv4Addr) -> std::net::Ipv6Addr {
+    let mut octets = prefix.octets();
+    octets[12..].copy_from_slice(&addr.octets());
+    octets.into()
+}
+
+async fn get_nat64_prefix() -> Option<std::net::Ipv6Addr> {
+    if let (prefix, Some(tm)) = *NAT64_PREFIX.lock().unwrap() {
+        if tm.elapsed().as_secs() < 60 {
+            return prefix;
+        }
+    }
+    let prefix = match timeout(3_000, tokio::net::lookup_host(IPV4ONLY_ARPA)).await {
+        Ok(Ok(addrs)) => addrs
+            .filter_map(|x| match x.ip() {
+                std::net::IpAddr::V6(ip) => nat64_prefix_of(ip),
+                _ => None,
+            })
+            .next(),
+        _ => None,
+    };
+    if let Some(prefix) = prefix {
+        log::debug!("Found NAT64 prefix: {}/96", prefix);
+    }
+    *NAT64_PREFIX.lock().unwrap() = (prefix, Some(Instant::now()));
+    prefix
+}
+
+/// Resolve `host` (with port) into one IPv4 and one IPv6 address.
+///
+/// The IPv6 address is only returned if we have IPv6 connectivity. On an IPv6-only
+/// network with NAT64, an IPv4-only host gets an IPv6 address synthesized with the
+/// NAT64 prefix, so that it is still reachable when there is no IPv4 route at all.
+pub async fn resolve_dual_stack(host: &str) -> (Option<SocketAddr>, Option<SocketAddr>) {
+    let addrs: Vec<SocketAddr> = match timeout(3_000, tokio::net::lookup_host(host)).await {
+        Ok(Ok(addrs)) => addrs.collect(),
+        _ => return (None, None),
+    };
+    let v4 = addrs.iter().find(|x| x.is_ipv4()).cloned();
+    test_ipv6().await;
+    if PUBLIC_IPV6_ADDR.lock().unwrap().0.is_none() {
+        return (v4, None);
+    }
+    let mut v6 = addrs
+        .iter()
+        .find(|x| x.is_ipv6() && !x.ip().is_loopback())
+        .cloned();
+    if v6.is_none() {
+        if let (Some(SocketAddr::V4(addr)), Some(prefix)) = (v4, get_nat64_prefix().await) {
+            v6 = Some(SocketAddr::from((
+                nat64_synthesize(prefix, *addr.ip()),
+                addr.port(),
+            )));
+        }
+    }
+    (v4, v6)
+}
+
 // The color is the same to `str2color()` in flutter.
 pub fn str2color(s: &str, alpha: u8) -> u32 {
     let bytes = s.as_bytes();
@@ -2196,4 +2279,16 @@ mod tests {
             Duration::from_nanos(0)
         );
     }
+
+    #[test]
+    fn test_nat64_synthesize() {
+        let addr: std::net::Ipv6Addr = "64:ff9b::c000:aa".parse().unwrap();
+        let prefix = nat64_prefix_of(addr).unwrap();
+        assert_eq!(prefix, "64:ff9b::".parse::<std::net::Ipv6Addr>().unwrap());
+        assert_eq!(
+            nat64_synthesize(prefix, [1, 2, 3, 4].into()),
+            "64:ff9b::102:304".parse::<std::net::Ipv6Addr>().unwrap()
+        );
+        assert!(nat64_prefix_of("2001:db8::1".parse().unwrap()).is_none());
+    }
 }
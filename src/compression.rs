@@ -0,0 +1,81 @@
+//! zstd compression of the non-video payloads, clipboard and terminal data, with the
+//! level tuned by the CPU headroom.
+//!
+//! The payloads carry a `compress(ed)` flag, so the receiver decompresses whatever
+//! level is used and older peers are not affected. File blocks are compressed by
+//! `crate::file_compression`.
+
+use hbb_common::sysinfo::System;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Not worth the frame header below this.
+const MIN_SIZE: usize = 64;
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static::lazy_static! {
+    static ref CPU: Mutex<(System, Option<Instant>, f32)> = Mutex::new((System::new(), None, 0.));
+}
+
+// Percent of the CPU in use, sampled at most once per `SAMPLE_INTERVAL`.
+fn cpu_usage() -> f32 {
+    let mut lock = CPU.lock().unwrap();
+    let (system, last, usage) = &mut *lock;
+    if last.map(|t| t.elapsed() >= SAMPLE_INTERVAL).unwrap_or(true) {
+        // The first refresh only sets the baseline.
+        system.refresh_cpu();
+        if last.is_some() {
+            *usage = system.global_cpu_info().cpu_usage();
+        }
+        *last = Some(Instant::now());
+    }
+    *usage
+}
+
+fn level_for_usage(usage: f32) -> i32 {
+    if usage < 30. {
+        9
+    } else if usage < 60. {
+        6
+    } else if usage < 85. {
+        3
+    } else {
+        1
+    }
+}
+
+#[inline]
+pub fn level() -> i32 {
+    level_for_usage(cpu_usage())
+}
+
+/// The compressed data, `None` if it is too small or does not shrink.
+pub fn compress(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < MIN_SIZE {
+        return None;
+    }
+    zstd::bulk::compress(data, level())
+        .ok()
+        .filter(|c| c.len() < data.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress() {
+        assert_eq!(level_for_usage(10.), 9);
+        assert_eq!(level_for_usage(95.), 1);
+        assert!(compress(b"short").is_none());
+        let data = "rustdesk ".repeat(1000);
+        let compressed = compress(data.as_bytes()).unwrap();
+        assert!(compressed.len() < data.len());
+        assert_eq!(
+            hbb_common::compress::decompress(&compressed),
+            data.as_bytes()
+        );
+    }
+}
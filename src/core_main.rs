@@ -30,6 +30,7 @@ macro_rules! my_println{
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 pub fn core_main() -> Option<Vec<String>> {
     crate::load_custom_client();
+    crate::policy::load();
     #[cfg(windows)]
     if !crate::platform::windows::bootstrap() {
         // return None to terminate the process
@@ -149,7 +150,8 @@ pub fn core_main() -> Option<Vec<String>> {
             log_name = name;
         }
     }
-    hbb_common::init_log(false, &log_name);
+    crate::logging::init(&log_name);
+    crate::crash_report::init(&log_name);
 
     // linux uni (url) go here.
     #[cfg(all(target_os = "linux", feature = "flutter"))]
@@ -330,6 +332,7 @@ pub fn core_main() -> Option<Vec<String>> {
             return None;
         } else if args[0] == "--service" {
             log::info!("start --service");
+            crate::deploy_config::apply_preseed();
             crate::start_os_service();
             return None;
         } else if args[0] == "--server" {
@@ -345,6 +348,10 @@ pub fn core_main() -> Option<Vec<String>> {
                 hbb_common::allow_err!(crate::run_me(vec!["--tray"]));
             }
             #[cfg(windows)]
+            if args.len() == 3 && args[1] == crate::platform::privilege_broker::ARG_TOKEN {
+                crate::platform::privilege_broker::set_worker_token(args[2].clone());
+            }
+            #[cfg(windows)]
             crate::privacy_mode::restore_reg_connectivity(true, false);
             #[cfg(any(target_os = "linux", target_os = "windows"))]
             {
@@ -453,6 +460,70 @@ pub fn core_main() -> Option<Vec<String>> {
                 println!("Installation and administrative privileges required!");
             }
             return None;
+        } else if args[0] == "--deploy-config" || args[0] == "--set-option" {
+            if !is_root() {
+                println!("Administrative privileges required!");
+                std::process::exit(1);
+            }
+            let res = if args[0] == "--deploy-config" {
+                match args.get(1).map(std::fs::read_to_string) {
+                    Some(Ok(text)) => deploy(&text),
+                    Some(Err(err)) => Err(err.into()),
+                    None => Err(hbb_common::anyhow::anyhow!("No config file")),
+                }
+            } else {
+                crate::deploy_config::DeployConfig::from_option_args(&args[1..])
+                    .and_then(|cfg| crate::deploy_config::apply(&cfg, true))
+                    .map(print_deploy_changed)
+            };
+            if let Err(err) = res {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+            return None;
+        } else if args[0] == "--sign-policy" {
+            // <policy.json> <secret key of the policies>, prints the content of policy.txt
+            let res = match (args.get(1), args.get(2)) {
+                (Some(json), Some(sk)) => std::fs::read_to_string(json)
+                    .and_then(|json| std::fs::read_to_string(sk).map(|sk| (json, sk)))
+                    .map_err(|err| err.into())
+                    .and_then(|(json, sk)| crate::policy::sign_policy(&json, &sk)),
+                _ => Err(hbb_common::anyhow::anyhow!(
+                    "Usage: --sign-policy <policy.json> <secret key file>"
+                )),
+            };
+            match res {
+                Ok(signed) => println!("{}", signed),
+                Err(err) => {
+                    println!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            return None;
+        } else if args[0] == "--log-levels" {
+            // Without a value, prints the levels of the server.
+            match crate::ipc::log_levels(args.get(1).cloned()) {
+                Ok(levels) => println!("{}", levels),
+                Err(err) => {
+                    println!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            return None;
+        } else if args[0] == "--server-key-pins" {
+            // Without a value, prints the pins and the keys the custom servers present now,
+            // to add the new one before rotating the server key.
+            if args.len() == 2 {
+                if crate::platform::is_installed() && is_root() {
+                    crate::ipc::set_option(crate::tls_pin::OPTION_SERVER_KEY_PINS, &args[1]);
+                    println!("Done!");
+                } else {
+                    println!("Installation and administrative privileges required!");
+                }
+            } else {
+                println!("{}", crate::ui_interface::get_server_key_pins());
+            }
+            return None;
         } else if args[0] == "--assign" {
             if config::Config::no_register_device() {
                 println!("Cannot assign an unregistrable device!");
@@ -574,6 +645,34 @@ pub fn core_main() -> Option<Vec<String>> {
                 crate::flutter::connection_manager::start_cm_no_ui();
             }
             return None;
+        } else if args[0] == "--input-script" {
+            // Drives the remote sessions of the running client:
+            // `record <id>`, `stop <id> <path>`, `replay <id> <path>` or `abort <id>`.
+            use crate::ipc::DataInputScript;
+            let arg = |i: usize| args.get(i).cloned().unwrap_or_default();
+            let req = match arg(1).as_str() {
+                "record" if args.len() == 3 => Some(DataInputScript::Record { id: arg(2) }),
+                "stop" if args.len() == 4 => Some(DataInputScript::Stop {
+                    id: arg(2),
+                    path: arg(3),
+                }),
+                "replay" if args.len() == 4 => Some(DataInputScript::Replay {
+                    id: arg(2),
+                    path: arg(3),
+                }),
+                "abort" if args.len() == 3 => Some(DataInputScript::Abort { id: arg(2) }),
+                _ => None,
+            };
+            match req {
+                Some(req) => match crate::input_script::send_ipc(req) {
+                    Ok(()) => println!("Done!"),
+                    Err(err) => println!("{}", err),
+                },
+                None => println!(
+                    "Usage: --input-script record <id> | stop <id> <path> | replay <id> <path> | abort <id>"
+                ),
+            }
+            return None;
         } else if args[0] == "--whiteboard" {
             #[cfg(not(any(target_os = "android", target_os = "ios")))]
             {
@@ -655,6 +754,31 @@ fn import_config(path: &str) {
     }
 }
 
+// Applies by the service, or leaves it to the service if it is not running, e.g. in the installer.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn deploy(text: &str) -> hbb_common::ResultType<()> {
+    let cfg = crate::deploy_config::DeployConfig::parse(text)?;
+    if crate::deploy_config::is_service_running() {
+        print_deploy_changed(crate::deploy_config::apply(&cfg, true)?);
+    } else {
+        let path = crate::deploy_config::save_preseed(text)?;
+        println!(
+            "Saved to {}, applied when the service starts",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn print_deploy_changed(changed: Vec<String>) {
+    if changed.is_empty() {
+        println!("Unchanged");
+    } else {
+        println!("Changed: {}", changed.join(", "));
+    }
+}
+
 /// invoke a new connection
 ///
 /// [Note]
@@ -668,8 +792,8 @@ fn core_main_invoke_new_connection(mut args: std::env::Args) -> Option<Vec<Strin
     let mut param_array = vec![];
     while let Some(arg) = args.next() {
         match arg.as_str() {
-            "--connect" | "--play" | "--file-transfer" | "--view-camera" | "--port-forward" | "--terminal"
-            | "--rdp" => {
+            "--connect" | "--play" | "--file-transfer" | "--view-camera" | "--port-forward"
+            | "--terminal" | "--rdp" => {
                 authority = Some((&arg.to_string()[2..]).to_owned());
                 id = args.next();
             }
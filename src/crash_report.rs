@@ -0,0 +1,211 @@
+//! Reports of the crashes of the desktop processes, for the crashes in the field.
+//!
+//! A panic, or a fatal signal caught by the breakdown handler of `hbb_common`, is written with
+//! its backtrace to `<log dir>/crashes/<time>-<process>.txt`. On Windows, an unhandled
+//! exception is written as a minidump `<time>-<process>.dmp` instead, for WinDbg.
+//! The newest `MAX_REPORTS` are kept.
+//!
+//! If `allow-upload-crash-reports` is `Y`, the server uploads the reports not uploaded yet to
+//! `crash-report-url` when it starts, one JSON per report:
+//! `{"id", "uuid", "version", "os", "name", "content": base64}`.
+
+use hbb_common::{
+    config::{self, Config},
+    log, ResultType,
+};
+use serde_json::json;
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+pub const OPTION_ALLOW_UPLOAD_CRASH_REPORTS: &str = "allow-upload-crash-reports";
+pub const OPTION_CRASH_REPORT_URL: &str = "crash-report-url";
+const DIR_NAME: &str = "crashes";
+const UPLOADED_SUFFIX: &str = ".uploaded";
+const MAX_REPORTS: usize = 10;
+const MAX_UPLOAD_SIZE: u64 = 16 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    static ref PROCESS_NAME: Mutex<String> = Default::default();
+}
+
+pub fn dir() -> PathBuf {
+    Config::log_path().join(DIR_NAME)
+}
+
+fn new_report_path(ext: &str) -> PathBuf {
+    let mut name = PROCESS_NAME.lock().map(|n| n.clone()).unwrap_or_default();
+    if name.is_empty() {
+        name = "main".to_owned();
+    }
+    let time = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    dir().join(format!("{}-{}.{}", time, name, ext))
+}
+
+// The names start with the time, so the oldest sort first.
+fn rotate(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<_> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+    files.sort();
+    let n = files.len().saturating_sub(MAX_REPORTS);
+    for f in files.iter().take(n) {
+        fs::remove_file(f).ok();
+    }
+}
+
+fn write_report(reason: &str) {
+    let dir = dir();
+    fs::create_dir_all(&dir).ok();
+    let path = new_report_path("txt");
+    let backtrace = std::backtrace::Backtrace::force_capture();
+    let res = fs::File::create(&path).and_then(|mut f| {
+        write!(
+            f,
+            "process: {}\nversion: {}\nos: {} {}\nreason: {}\n\nbacktrace:\n{}\n",
+            PROCESS_NAME.lock().map(|n| n.clone()).unwrap_or_default(),
+            crate::VERSION,
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            reason,
+            backtrace
+        )
+    });
+    match res {
+        Ok(_) => log::error!("Crash report written to {:?}", path),
+        Err(err) => log::error!("Failed to write the crash report {:?}: {}", path, err),
+    }
+    rotate(&dir);
+}
+
+/// Installs the panic hook, and the exception filter on Windows.
+pub fn init(name: &str) {
+    if let Ok(mut n) = PROCESS_NAME.lock() {
+        *n = name.to_owned();
+    }
+    let prev = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        write_report(&format!(
+            "panic in thread '{}': {}",
+            thread.name().unwrap_or("<unnamed>"),
+            info
+        ));
+        prev(info);
+    }));
+    #[cfg(windows)]
+    exception::set_exception_filter();
+}
+
+/// Called by the breakdown handler of a fatal signal.
+pub fn on_breakdown() {
+    write_report("fatal signal");
+}
+
+#[cfg(windows)]
+mod exception {
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::{
+        Foundation::{EXCEPTION_CONTINUE_SEARCH, HANDLE},
+        System::{
+            Diagnostics::Debug::{
+                MiniDumpWithThreadInfo, MiniDumpWriteDump, SetUnhandledExceptionFilter,
+                EXCEPTION_POINTERS, MINIDUMP_EXCEPTION_INFORMATION,
+            },
+            Threading::{GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId},
+        },
+    };
+
+    unsafe extern "system" fn exception_filter(info: *const EXCEPTION_POINTERS) -> i32 {
+        let dir = super::dir();
+        std::fs::create_dir_all(&dir).ok();
+        let path = super::new_report_path("dmp");
+        if let Ok(file) = std::fs::File::create(&path) {
+            let exception = MINIDUMP_EXCEPTION_INFORMATION {
+                ThreadId: GetCurrentThreadId(),
+                ExceptionPointers: info as _,
+                ClientPointers: false.into(),
+            };
+            MiniDumpWriteDump(
+                GetCurrentProcess(),
+                GetCurrentProcessId(),
+                HANDLE(file.as_raw_handle() as _),
+                MiniDumpWithThreadInfo,
+                Some(&exception as *const _),
+                None,
+                None,
+            )
+            .ok();
+        }
+        super::rotate(&dir);
+        EXCEPTION_CONTINUE_SEARCH.0
+    }
+
+    pub(super) fn set_exception_filter() {
+        unsafe {
+            SetUnhandledExceptionFilter(Some(exception_filter));
+        }
+    }
+}
+
+fn upload_url() -> Option<String> {
+    let allowed = config::option2bool(
+        OPTION_ALLOW_UPLOAD_CRASH_REPORTS,
+        &Config::get_option(OPTION_ALLOW_UPLOAD_CRASH_REPORTS),
+    );
+    let url = Config::get_option(OPTION_CRASH_REPORT_URL);
+    (allowed && !url.is_empty()).then_some(url)
+}
+
+async fn upload_one(url: &str, path: &Path) -> ResultType<()> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let body = json!({
+        "id": Config::get_id(),
+        "uuid": crate::encode64(hbb_common::get_uuid()),
+        "version": crate::VERSION,
+        "os": format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+        "name": name,
+        "content": crate::encode64(fs::read(path)?),
+    });
+    crate::post_request(url.to_owned(), body.to_string(), "").await?;
+    let mut uploaded = path.as_os_str().to_owned();
+    uploaded.push(UPLOADED_SUFFIX);
+    fs::rename(path, uploaded)?;
+    Ok(())
+}
+
+/// Uploads the reports not uploaded yet if allowed, called by the server when it starts.
+pub async fn upload() {
+    let Some(url) = upload_url() else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(dir()) else {
+        return;
+    };
+    for path in entries.flatten().map(|e| e.path()) {
+        let name = path.to_string_lossy().to_string();
+        if name.ends_with(UPLOADED_SUFFIX)
+            || fs::metadata(&path).map_or(true, |m| !m.is_file() || m.len() > MAX_UPLOAD_SIZE)
+        {
+            continue;
+        }
+        match upload_one(&url, &path).await {
+            Ok(_) => log::info!("Crash report {:?} uploaded", path),
+            Err(err) => {
+                log::error!("Failed to upload the crash report {:?}: {}", path, err);
+                break;
+            }
+        }
+    }
+}
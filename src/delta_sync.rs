@@ -0,0 +1,667 @@
+//! Delta sync of the files overwritten by the file transfer, like rsync.
+//!
+//! When the destination exists and differs, the writing side sends the signatures of its
+//! blocks, a rolling checksum and a strong hash each, instead of confirming to receive the
+//! whole file. The reading side looks for the blocks in its file with the rolling checksum
+//! and sends the delta, the runs of blocks found and the data between them. The writing side
+//! builds the new file from the old one and the delta, checks the hash of the whole file,
+//! replaces the old one and confirms to skip the file. On any failure, it confirms to
+//! receive the whole file as before.
+//!
+//! The messages are plugin requests with [`PLUGIN_ID`]. For an upload, the reading side
+//! decides to overwrite, so it asks for the signatures with [`DeltaMessage::Request`].
+//! Only the peers with [`PLATFORM_ADDITION_KEY`] are asked, the others get the whole file.
+
+use hbb_common::{
+    base64::{engine::general_purpose::STANDARD, Engine as _},
+    fs::{self, get_string},
+    log,
+    message_proto::{
+        file_transfer_send_confirm_request, FileTransferSendConfirmRequest, Message, Misc,
+        PluginRequest,
+    },
+};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    sync::{mpsc as std_mpsc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+pub const PLUGIN_ID: &str = "__delta_sync";
+pub const PLATFORM_ADDITION_KEY: &str = "support_delta_sync";
+/// The smaller files are sent whole.
+pub const MIN_FILE_SIZE: u64 = 4 << 20;
+
+const MIN_BLOCK_SIZE: u64 = 4 << 10;
+const MAX_BLOCK_SIZE: u64 = 128 << 10;
+// The data of the delta sent in a message, at most.
+const MAX_DATA: usize = 512 << 10;
+const READ_SIZE: usize = 1 << 20;
+// The messages of the delta sent but not applied yet, at most.
+const WINDOW: usize = 8;
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", rename_all = "snake_case")]
+pub enum DeltaMessage {
+    /// The reading side asks for the signatures of the destination.
+    Request { id: i32, file_num: i32 },
+    /// The block size is 0 if the destination can not be read.
+    Signatures {
+        id: i32,
+        file_num: i32,
+        block_size: u64,
+        weak: Vec<u32>,
+        strong: Vec<u64>,
+    },
+    Ops {
+        id: i32,
+        file_num: i32,
+        ops: Vec<Op>,
+    },
+    /// The ops are applied.
+    Ack { id: i32, file_num: i32 },
+    /// The hash of the whole source, empty if the delta failed.
+    End {
+        id: i32,
+        file_num: i32,
+        hash: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Op {
+    /// The blocks of the destination from `block`.
+    Copy {
+        block: u32,
+        count: u32,
+    },
+    Data(#[serde(with = "base64_bytes")] Vec<u8>),
+}
+
+mod base64_bytes {
+    use super::*;
+
+    pub fn serialize<S: serde::Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&STANDARD.encode(v))
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let s: String = serde::Deserialize::deserialize(d)?;
+        STANDARD.decode(s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl DeltaMessage {
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        serde_json::from_slice(content)
+            .map_err(|e| log::error!("Invalid delta sync message: {}", e))
+            .ok()
+    }
+
+    pub fn to_message(&self) -> Message {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(self).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        msg
+    }
+}
+
+/// The weak checksum of rsync, which can be rolled over the data byte by byte.
+struct Rolling {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl Rolling {
+    fn new(data: &[u8]) -> Self {
+        let len = data.len() as u32;
+        let (mut a, mut b) = (0u32, 0u32);
+        for (i, x) in data.iter().enumerate() {
+            a = a.wrapping_add(*x as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(*x as u32));
+        }
+        Self { a, b, len }
+    }
+
+    fn roll(&mut self, out: u8, r#in: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(r#in as u32);
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a);
+    }
+
+    fn digest(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+fn strong_hash(data: &[u8]) -> u64 {
+    let hash = Sha256::digest(data);
+    u64::from_le_bytes(hash[..8].try_into().unwrap_or_default())
+}
+
+fn block_size(file_size: u64) -> u64 {
+    // About the square root of the size, as rsync.
+    ((file_size as f64).sqrt() as u64 / 1024 * 1024).clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE)
+}
+
+/// The signatures of the full blocks of the data.
+fn signatures(mut data: impl Read, file_size: u64) -> io::Result<(u64, Vec<u32>, Vec<u64>)> {
+    let block_size = block_size(file_size);
+    let mut buf = vec![0u8; block_size as usize];
+    let (mut weak, mut strong) = (vec![], vec![]);
+    loop {
+        let mut n = 0;
+        while n < buf.len() {
+            match data.read(&mut buf[n..])? {
+                0 => break,
+                m => n += m,
+            }
+        }
+        if n < buf.len() {
+            break;
+        }
+        weak.push(Rolling::new(&buf).digest());
+        strong.push(strong_hash(&buf));
+    }
+    Ok((block_size, weak, strong))
+}
+
+/// Calls `emit` with the delta of the source against the signatures, returns the hash of
+/// the whole source.
+fn delta(
+    mut source: impl Read,
+    block_size: u64,
+    weak: &[u32],
+    strong: &[u64],
+    mut emit: impl FnMut(Op) -> io::Result<()>,
+) -> io::Result<String> {
+    let bs = block_size as usize;
+    let mut table: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, w) in weak.iter().enumerate() {
+        table.entry(*w).or_default().push(i);
+    }
+    let mut hasher = Sha256::new();
+    // The data not emitted yet, from `lit`, and the window, from `pos`.
+    let mut data: Vec<u8> = vec![];
+    let (mut pos, mut lit) = (0, 0);
+    let mut eof = false;
+    let mut rolling: Option<Rolling> = None;
+    // The run of blocks found, not emitted yet.
+    let mut run: Option<(u32, u32)> = None;
+    let mut chunk = vec![0u8; READ_SIZE];
+    loop {
+        // One more byte than the window to roll.
+        if !eof && data.len() <= pos + bs {
+            data.drain(..lit);
+            pos -= lit;
+            lit = 0;
+            let n = source.read(&mut chunk)?;
+            if n == 0 {
+                eof = true;
+            } else {
+                hasher.update(&chunk[..n]);
+                data.extend_from_slice(&chunk[..n]);
+            }
+            continue;
+        }
+        if data.len() < pos + bs {
+            break;
+        }
+        let window = &data[pos..pos + bs];
+        let r = rolling.get_or_insert_with(|| Rolling::new(window));
+        let found = table.get(&r.digest()).and_then(|blocks| {
+            let hash = strong_hash(window);
+            blocks.iter().find(|i| strong[**i] == hash).copied()
+        });
+        if let Some(block) = found {
+            let block = block as u32;
+            if lit < pos {
+                if let Some((block, count)) = run.take() {
+                    emit(Op::Copy { block, count })?;
+                }
+                emit(Op::Data(data[lit..pos].to_vec()))?;
+            }
+            run = match run {
+                Some((start, count)) if start + count == block => Some((start, count + 1)),
+                Some((start, count)) => {
+                    emit(Op::Copy {
+                        block: start,
+                        count,
+                    })?;
+                    Some((block, 1))
+                }
+                None => Some((block, 1)),
+            };
+            pos += bs;
+            lit = pos;
+            rolling = None;
+            continue;
+        }
+        if pos + bs == data.len() {
+            break;
+        }
+        r.roll(data[pos], data[pos + bs]);
+        pos += 1;
+        if pos - lit >= MAX_DATA {
+            if let Some((block, count)) = run.take() {
+                emit(Op::Copy { block, count })?;
+            }
+            emit(Op::Data(data[lit..pos].to_vec()))?;
+            lit = pos;
+        }
+    }
+    if let Some((block, count)) = run.take() {
+        emit(Op::Copy { block, count })?;
+    }
+    if lit < data.len() {
+        emit(Op::Data(data[lit..].to_vec()))?;
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn delta_path(path: &str) -> String {
+    format!("{}.delta", path)
+}
+
+/// Builds the new file from the old one and the delta, beside the old one.
+struct Builder {
+    path: String,
+    last_modified: u64,
+    block_size: u64,
+    old: File,
+    new: File,
+    hasher: Sha256,
+}
+
+impl Builder {
+    fn new(path: &str, last_modified: u64, block_size: u64) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_owned(),
+            last_modified,
+            block_size,
+            old: File::open(path)?,
+            new: File::create(delta_path(path))?,
+            hasher: Sha256::new(),
+        })
+    }
+
+    fn apply(&mut self, op: &Op) -> io::Result<()> {
+        match op {
+            Op::Copy { block, count } => {
+                let mut data = vec![0u8; self.block_size as usize];
+                self.old
+                    .seek(SeekFrom::Start(*block as u64 * self.block_size))?;
+                for _ in 0..*count {
+                    self.old.read_exact(&mut data)?;
+                    self.hasher.update(&data);
+                    self.new.write_all(&data)?;
+                }
+            }
+            Op::Data(data) => {
+                self.hasher.update(data);
+                self.new.write_all(data)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self, hash: &str) -> io::Result<()> {
+        let Self {
+            path,
+            last_modified,
+            old,
+            mut new,
+            hasher,
+            ..
+        } = self;
+        drop(old);
+        if hex(&hasher.finalize()) != hash {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "hash mismatch"));
+        }
+        new.flush()?;
+        new.set_modified(UNIX_EPOCH + Duration::from_secs(last_modified))
+            .ok();
+        drop(new);
+        std::fs::rename(delta_path(&path), &path)
+    }
+}
+
+// A file being written with the delta, the builder is none if the destination can not be
+// read or the delta failed.
+struct Writing {
+    path: String,
+    builder: Option<Builder>,
+}
+
+lazy_static::lazy_static! {
+    static ref WRITINGS: Mutex<HashMap<(i32, i32), Writing>> = Default::default();
+    // The acks of the files being read, by the job id and the file number.
+    static ref READINGS: Mutex<HashMap<(i32, i32), std_mpsc::Sender<()>>> = Default::default();
+}
+
+/// The path of the file of the job.
+pub fn file_path(job: &fs::TransferJob, file_num: i32) -> Option<String> {
+    let file = job.files().get(file_num as usize)?;
+    let fs::DataSource::FilePath(p) = &job.data_source else {
+        return None;
+    };
+    Some(get_string(&fs::TransferJob::join(p, &file.name)))
+}
+
+/// Whether the file is large enough to be worth the delta.
+pub fn is_worth(size: u64) -> bool {
+    size >= MIN_FILE_SIZE
+}
+
+/// The confirm of the file, to skip it or to receive it whole.
+pub fn confirm(id: i32, file_num: i32, skip: bool) -> FileTransferSendConfirmRequest {
+    FileTransferSendConfirmRequest {
+        id,
+        file_num,
+        union: Some(if skip {
+            file_transfer_send_confirm_request::Union::Skip(true)
+        } else {
+            file_transfer_send_confirm_request::Union::OffsetBlk(0)
+        }),
+        ..Default::default()
+    }
+}
+
+/// The writing side, sends the signatures of the destination with `send` in a thread.
+/// Returns false if the destination is not worth the delta, the file is received whole.
+pub fn start_write(
+    job: &fs::TransferJob,
+    file_num: i32,
+    send: impl Fn(Message) + Send + 'static,
+) -> bool {
+    let Some(path) = file_path(job, file_num) else {
+        return false;
+    };
+    let size = std::fs::metadata(&path)
+        .ok()
+        .filter(|m| m.is_file())
+        .map(|m| m.len());
+    if !is_worth(size.unwrap_or_default()) {
+        return false;
+    }
+    let id = job.id();
+    let last_modified = job
+        .files()
+        .get(file_num as usize)
+        .map(|f| f.modified_time)
+        .unwrap_or_default();
+    std::thread::spawn(move || {
+        let res = File::open(&path).and_then(|f| {
+            let len = f.metadata()?.len();
+            signatures(BufReader::new(f), len)
+        });
+        let (block_size, weak, strong) = match res {
+            Ok(signatures) => signatures,
+            Err(err) => {
+                log::error!("Failed to read the signatures of {}: {}", path, err);
+                (0, vec![], vec![])
+            }
+        };
+        let builder = if block_size > 0 {
+            Builder::new(&path, last_modified, block_size)
+                .map_err(|e| log::error!("Failed to start the delta of {}: {}", path, e))
+                .ok()
+        } else {
+            None
+        };
+        WRITINGS
+            .lock()
+            .unwrap()
+            .insert((id, file_num), Writing { path, builder });
+        send(
+            DeltaMessage::Signatures {
+                id,
+                file_num,
+                block_size,
+                weak,
+                strong,
+            }
+            .to_message(),
+        );
+    });
+    true
+}
+
+/// The writing side, applies the ops, returns the ack to send.
+pub fn on_ops(id: i32, file_num: i32, ops: &[Op]) -> Message {
+    if let Some(writing) = WRITINGS.lock().unwrap().get_mut(&(id, file_num)) {
+        if let Some(builder) = writing.builder.as_mut() {
+            if let Err(err) = ops.iter().try_for_each(|op| builder.apply(op)) {
+                log::error!("Failed to apply the delta of {}: {}", writing.path, err);
+                writing.builder = None;
+            }
+        }
+    }
+    DeltaMessage::Ack { id, file_num }.to_message()
+}
+
+/// The writing side, returns the confirm of the file, to skip it if the delta is applied,
+/// or to receive it whole.
+pub fn on_end(id: i32, file_num: i32, hash: &str) -> FileTransferSendConfirmRequest {
+    let writing = WRITINGS.lock().unwrap().remove(&(id, file_num));
+    let mut done = false;
+    if let Some(Writing { path, builder }) = writing {
+        if let Some(builder) = builder.filter(|_| !hash.is_empty()) {
+            match builder.finish(hash) {
+                Ok(()) => {
+                    log::info!("Delta of {} applied", path);
+                    done = true;
+                }
+                Err(err) => log::error!("Failed to finish the delta of {}: {}", path, err),
+            }
+        }
+        if !done {
+            std::fs::remove_file(delta_path(&path)).ok();
+        }
+    }
+    confirm(id, file_num, done)
+}
+
+/// The writing side, the job is cancelled.
+pub fn cancel_write(id: i32) {
+    WRITINGS.lock().unwrap().retain(|(i, _), w| {
+        if *i == id {
+            std::fs::remove_file(delta_path(&w.path)).ok();
+        }
+        *i != id
+    });
+}
+
+/// The reading side, sends the delta of the source with `send` in a thread.
+pub fn start_read(
+    id: i32,
+    file_num: i32,
+    path: String,
+    block_size: u64,
+    weak: Vec<u32>,
+    strong: Vec<u64>,
+    send: impl Fn(Message) + Send + 'static,
+) {
+    let (tx, rx) = std_mpsc::channel();
+    READINGS.lock().unwrap().insert((id, file_num), tx);
+    std::thread::spawn(move || {
+        let mut in_flight = 0;
+        let mut ops = vec![];
+        let mut size = 0;
+        let mut flush = |ops: &mut Vec<Op>, size: &mut usize| -> io::Result<()> {
+            if ops.is_empty() {
+                return Ok(());
+            }
+            while in_flight >= WINDOW {
+                rx.recv_timeout(ACK_TIMEOUT)
+                    .map_err(|_| io::Error::new(io::ErrorKind::Interrupted, "no ack"))?;
+                in_flight -= 1;
+            }
+            send(
+                DeltaMessage::Ops {
+                    id,
+                    file_num,
+                    ops: std::mem::take(ops),
+                }
+                .to_message(),
+            );
+            in_flight += 1;
+            *size = 0;
+            Ok(())
+        };
+        let res = if block_size == 0 || weak.len() != strong.len() {
+            Err(io::Error::new(io::ErrorKind::Other, "no signatures"))
+        } else {
+            File::open(&path).and_then(|f| {
+                let hash = delta(f, block_size, &weak, &strong, |op| {
+                    size += match &op {
+                        Op::Data(data) => data.len(),
+                        Op::Copy { .. } => 0,
+                    };
+                    ops.push(op);
+                    if size >= MAX_DATA || ops.len() >= 1024 {
+                        flush(&mut ops, &mut size)?;
+                    }
+                    Ok(())
+                })?;
+                flush(&mut ops, &mut size)?;
+                Ok(hash)
+            })
+        };
+        READINGS.lock().unwrap().remove(&(id, file_num));
+        let hash = res.unwrap_or_else(|err| {
+            log::error!("Failed to send the delta of {}: {}", path, err);
+            "".to_owned()
+        });
+        send(DeltaMessage::End { id, file_num, hash }.to_message());
+    });
+}
+
+/// The reading side, the job is cancelled.
+pub fn cancel_read(id: i32) {
+    // The thread stops once its sender is dropped.
+    READINGS.lock().unwrap().retain(|(i, _), _| *i != id);
+}
+
+/// The reading side, the writing side applied some ops.
+pub fn on_ack(id: i32, file_num: i32) {
+    if let Some(tx) = READINGS.lock().unwrap().get(&(id, file_num)) {
+        tx.send(()).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rebuild(old: &[u8], new: &[u8]) -> (Vec<u8>, Vec<Op>) {
+        let (block_size, weak, strong) = signatures(old, old.len() as u64).unwrap();
+        let mut ops = vec![];
+        let hash = delta(new, block_size, &weak, &strong, |op| {
+            ops.push(op);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(hash, hex(&Sha256::digest(new)));
+        let mut out = vec![];
+        for op in &ops {
+            match op {
+                Op::Copy { block, count } => {
+                    let start = (*block as u64 * block_size) as usize;
+                    out.extend_from_slice(
+                        &old[start..start + (*count as u64 * block_size) as usize],
+                    );
+                }
+                Op::Data(data) => out.extend_from_slice(data),
+            }
+        }
+        (out, ops)
+    }
+
+    fn data(len: usize, seed: u32) -> Vec<u8> {
+        let mut x = seed;
+        (0..len)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                (x >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn data_size(ops: &[Op]) -> usize {
+        ops.iter()
+            .map(|op| match op {
+                Op::Data(data) => data.len(),
+                Op::Copy { .. } => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_rolling() {
+        let data = data(1000, 1);
+        let mut r = Rolling::new(&data[..100]);
+        for i in 0..900 {
+            r.roll(data[i], data[i + 100]);
+            assert_eq!(r.digest(), Rolling::new(&data[i + 1..i + 101]).digest());
+        }
+    }
+
+    #[test]
+    fn test_delta() {
+        let old = data(300_000, 1);
+        let bs = block_size(old.len() as u64) as usize;
+
+        let (out, ops) = rebuild(&old, &old);
+        assert_eq!(out, old);
+        assert_eq!(data_size(&ops), old.len() % bs);
+
+        // Changed in the middle, inserted at the start, appended at the end.
+        let mut new = old.clone();
+        new[150_000] ^= 0xff;
+        new.splice(0..0, data(10, 2));
+        new.extend(data(5000, 3));
+        let (out, ops) = rebuild(&old, &new);
+        assert_eq!(out, new);
+        assert!(data_size(&ops) < 10 + 2 * bs + 5000 + bs);
+
+        // Nothing in common.
+        let new = data(100_000, 4);
+        let (out, ops) = rebuild(&old, &new);
+        assert_eq!(out, new);
+        assert_eq!(data_size(&ops), new.len());
+
+        // Empty.
+        let (out, _) = rebuild(&old, &[]);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_message() {
+        let msg = DeltaMessage::Ops {
+            id: 1,
+            file_num: 2,
+            ops: vec![Op::Copy { block: 3, count: 4 }, Op::Data(vec![0, 1, 255])],
+        };
+        let content = serde_json::to_vec(&msg).unwrap();
+        assert_eq!(DeltaMessage::parse(&content), Some(msg));
+    }
+}
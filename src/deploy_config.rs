@@ -0,0 +1,349 @@
+//! Unattended configuration for the mass deployment, by the automation tools.
+//!
+//! `--deploy-config <file>` applies a TOML file, `--set-option key=value...` the options only.
+//! Both are idempotent, only the values which differ are written, and print the changed names,
+//! so they can be run on every run of Ansible or Intune. The service is asked to write them, if
+//! it is not running, the file is saved as the pre-seeded one, which the service applies when it
+//! starts, also if the file was put there by the MSI or the deb package.
+//!
+//! ```toml
+//! id-server = "hbbs.example.com"
+//! relay-server = "hbbr.example.com"
+//! api-server = "https://api.example.com"
+//! key = "..."
+//! # Or the salt and base64(sha256(password + salt)), not to put the password into the file:
+//! # salt = "..."
+//! # permanent-password-hash = "..."
+//! password = "..."
+//!
+//! [options]
+//! verification-method = "use-permanent-password"
+//! approve-mode = "password"
+//! ```
+
+use hbb_common::{
+    bail,
+    config::{Config, LocalConfig},
+    log, toml, ResultType,
+};
+use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// The salted hash of the permanent password, checked by the connection besides the password.
+pub const OPTION_PERMANENT_PASSWORD_HASH: &str = "permanent-password-hash";
+// The hash of the pre-seeded file applied last, so the changes made later in the settings are
+// not reverted on every start.
+const LOCAL_OPTION_PRESEED_HASH: &str = "deploy-config-preseed-hash";
+const PRESEED_FILE_NAME: &str = "deploy.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DeployConfig {
+    pub id_server: Option<String>,
+    pub relay_server: Option<String>,
+    pub api_server: Option<String>,
+    pub key: Option<String>,
+    pub password: Option<String>,
+    pub salt: Option<String>,
+    pub permanent_password_hash: Option<String>,
+    pub options: HashMap<String, String>,
+}
+
+impl DeployConfig {
+    pub fn parse(text: &str) -> ResultType<Self> {
+        let cfg: Self = toml::from_str(text)?;
+        if cfg.password.is_some() && cfg.permanent_password_hash.is_some() {
+            bail!("Only one of password and permanent-password-hash can be set");
+        }
+        if let Some(hash) = cfg.permanent_password_hash.as_ref() {
+            if cfg.salt.as_deref().unwrap_or_default().is_empty() {
+                bail!("The salt of permanent-password-hash is not set");
+            }
+            if !hash.is_empty() && crate::decode64(hash).map(|h| h.len()).unwrap_or_default() != 32
+            {
+                bail!("permanent-password-hash is not a base64 encoded sha256 hash");
+            }
+        }
+        if cfg.options.keys().any(|k| k.is_empty()) {
+            bail!("Empty option name");
+        }
+        Ok(cfg)
+    }
+
+    /// `key=value` of `--set-option`, an empty value removes the option.
+    pub fn from_option_args(args: &[String]) -> ResultType<Self> {
+        let mut cfg = Self::default();
+        for arg in args {
+            let Some((k, v)) = arg.split_once('=') else {
+                bail!("Wrong option {}, format: key=value", arg);
+            };
+            if k.is_empty() {
+                bail!("Empty option name");
+            }
+            cfg.options.insert(k.to_owned(), v.to_owned());
+        }
+        Ok(cfg)
+    }
+
+    fn options(&self) -> HashMap<String, String> {
+        let mut options = self.options.clone();
+        for (k, v) in [
+            ("custom-rendezvous-server", &self.id_server),
+            ("relay-server", &self.relay_server),
+            ("api-server", &self.api_server),
+            ("key", &self.key),
+            (
+                OPTION_PERMANENT_PASSWORD_HASH,
+                &self.permanent_password_hash,
+            ),
+        ] {
+            if let Some(v) = v {
+                options.insert(k.to_owned(), v.clone());
+            }
+        }
+        // The hash set before is replaced by the password.
+        if self.password.is_some() {
+            options.insert(OPTION_PERMANENT_PASSWORD_HASH.to_owned(), "".to_owned());
+        }
+        options
+    }
+}
+
+/// The salted hash of `OPTION_PERMANENT_PASSWORD_HASH`, it is only valid with the salt it was
+/// made with.
+pub fn get_permanent_password_hash() -> Option<Vec<u8>> {
+    let hash = Config::get_option(OPTION_PERMANENT_PASSWORD_HASH);
+    if hash.is_empty() {
+        return None;
+    }
+    crate::decode64(hash).ok().filter(|h| h.len() == 32)
+}
+
+/// Applies the config, by the service if `by_ipc`, or directly if the service is not running
+/// yet. Returns the names of the changed values.
+pub fn apply(cfg: &DeployConfig, by_ipc: bool) -> ResultType<Vec<String>> {
+    let mut changed = vec![];
+    // Before the hash, which is checked with the current salt.
+    if let Some(salt) = cfg.salt.as_ref().filter(|s| !s.is_empty()) {
+        if *salt != Config::get_salt() {
+            if by_ipc {
+                crate::ipc::set_config("salt", salt.clone())?;
+            }
+            Config::set_salt(salt);
+            changed.push("salt".to_owned());
+        }
+    }
+
+    let mut options = if by_ipc {
+        crate::ipc::get_options()
+    } else {
+        Config::get_options()
+    };
+    let mut options_changed = false;
+    let mut new_options: Vec<_> = cfg.options().into_iter().collect();
+    new_options.sort();
+    for (k, v) in new_options {
+        let old = options.get(&k).map(|x| x.as_str()).unwrap_or_default();
+        if old == v {
+            continue;
+        }
+        if v.is_empty() {
+            options.remove(&k);
+        } else {
+            options.insert(k.clone(), v);
+        }
+        changed.push(k);
+        options_changed = true;
+    }
+    if options_changed {
+        if by_ipc {
+            crate::ipc::set_options(options)?;
+        } else {
+            Config::set_options(options);
+        }
+    }
+
+    // The password is replaced by the hash, or the other way around.
+    let password = match (cfg.password.as_ref(), cfg.permanent_password_hash.as_ref()) {
+        (Some(password), _) => Some(password.clone()),
+        (None, Some(_)) => Some("".to_owned()),
+        _ => None,
+    };
+    if let Some(password) = password {
+        let old = if by_ipc {
+            crate::ipc::get_permanent_password()
+        } else {
+            Config::get_permanent_password()
+        };
+        if old != password {
+            if by_ipc {
+                crate::ipc::set_permanent_password(password)?;
+            } else {
+                Config::set_permanent_password(&password);
+            }
+            changed.push("password".to_owned());
+        }
+    }
+    Ok(changed)
+}
+
+/// The directory of the files put by the installers or the automation, writable by the admin.
+pub fn system_config_dir() -> PathBuf {
+    #[cfg(windows)]
+    {
+        let dir = std::env::var("ProgramData").unwrap_or("C:\\ProgramData".to_owned());
+        return PathBuf::from(dir).join(crate::get_app_name());
+    }
+    #[cfg(target_os = "macos")]
+    {
+        return PathBuf::from("/Library/Application Support").join(crate::get_app_name());
+    }
+    #[allow(unreachable_code)]
+    PathBuf::from("/etc").join(crate::get_app_name().to_lowercase())
+}
+
+// Whether the file or directory is owned by the admin and not writable by the others, so it was
+// not planted by a standard user.
+fn is_admin_only(path: &Path) -> bool {
+    #[cfg(windows)]
+    {
+        return crate::platform::windows::is_admin_owned(path);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        return std::fs::symlink_metadata(path)
+            .map_or(false, |m| m.uid() == 0 && m.mode() & 0o022 == 0);
+    }
+    #[allow(unreachable_code)]
+    false
+}
+
+/// Creates the system config directory writable only by the admin. A directory made before by a
+/// standard user, e.g. in `ProgramData` which they can write, is removed with its files first.
+pub fn create_system_config_dir() -> ResultType<PathBuf> {
+    let dir = system_config_dir();
+    if dir.exists() && !is_admin_only(&dir) {
+        log::warn!("Removing {:?} which is not owned by the admin", dir);
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+    #[cfg(windows)]
+    crate::platform::windows::set_admin_only_permission(&dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(dir)
+}
+
+/// Reads a file of the system config directory, only if both are owned by the admin.
+pub fn read_system_file(path: &Path) -> Option<String> {
+    if !path.exists() {
+        return None;
+    }
+    if !path.parent().map_or(false, is_admin_only) || !is_admin_only(path) {
+        log::error!("Ignored {:?} which is not owned by the admin", path);
+        return None;
+    }
+    std::fs::read_to_string(path).ok()
+}
+
+/// The file applied by the service on start.
+pub fn preseed_path() -> PathBuf {
+    system_config_dir().join(PRESEED_FILE_NAME)
+}
+
+fn hash_text(text: &str) -> String {
+    crate::encode64(Sha256::digest(text.as_bytes()))
+}
+
+/// Saves the file to be applied by the service when it starts.
+pub fn save_preseed(text: &str) -> ResultType<PathBuf> {
+    let path = create_system_config_dir()?.join(PRESEED_FILE_NAME);
+    std::fs::write(&path, text)?;
+    Ok(path)
+}
+
+/// Applies the pre-seeded file once per content, called by the service before the server starts.
+pub fn apply_preseed() {
+    let path = preseed_path();
+    let Some(text) = read_system_file(&path) else {
+        return;
+    };
+    let hash = hash_text(&text);
+    if LocalConfig::get_option(LOCAL_OPTION_PRESEED_HASH) == hash {
+        return;
+    }
+    match DeployConfig::parse(&text).and_then(|cfg| apply(&cfg, false)) {
+        Ok(changed) => {
+            log::info!("Applied {:?}, changed: {:?}", path, changed);
+            LocalConfig::set_option(LOCAL_OPTION_PRESEED_HASH.to_owned(), hash);
+        }
+        Err(err) => log::error!("Failed to apply {:?}: {}", path, err),
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+pub async fn is_service_running() -> bool {
+    crate::ipc::connect(1000, "").await.is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let cfg = DeployConfig::parse(
+            r#"
+id-server = "hbbs.example.com"
+key = "abc"
+password = "123456"
+
+[options]
+approve-mode = "password"
+"#,
+        )
+        .unwrap();
+        let options = cfg.options();
+        assert_eq!(
+            options.get("custom-rendezvous-server").map(|x| x.as_str()),
+            Some("hbbs.example.com")
+        );
+        assert_eq!(
+            options.get("approve-mode").map(|x| x.as_str()),
+            Some("password")
+        );
+        assert_eq!(
+            options
+                .get(OPTION_PERMANENT_PASSWORD_HASH)
+                .map(|x| x.as_str()),
+            Some("")
+        );
+        assert!(options.get("relay-server").is_none());
+
+        // Without the salt.
+        assert!(DeployConfig::parse(r#"permanent-password-hash = "abc""#).is_err());
+        assert!(DeployConfig::parse(r#"unknown = "abc""#).is_err());
+    }
+
+    #[test]
+    fn test_from_option_args() {
+        let cfg = DeployConfig::from_option_args(&[
+            "a=1".to_owned(),
+            "b=".to_owned(),
+            "c=x=y".to_owned(),
+        ])
+        .unwrap();
+        assert_eq!(cfg.options.get("a").map(|x| x.as_str()), Some("1"));
+        assert_eq!(cfg.options.get("b").map(|x| x.as_str()), Some(""));
+        assert_eq!(cfg.options.get("c").map(|x| x.as_str()), Some("x=y"));
+        assert!(DeployConfig::from_option_args(&["a".to_owned()]).is_err());
+    }
+}
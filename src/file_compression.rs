@@ -0,0 +1,208 @@
+//! zstd compression of the blocks of the file transfer, skipped for the content which is
+//! already compressed.
+//!
+//! Whether a file is worth it is decided once per file, by its extension, e.g. `zip` or `mp4`,
+//! and by the entropy of a sample of its first block. The blocks of the other files are
+//! compressed at the level of [`crate::compression::level`], and kept raw if they do not shrink.
+//! The receiver decompresses the blocks with the `compressed` flag, as for the blocks which the
+//! transfer jobs of `hbb_common::fs` compress themselves, those are sent as they are.
+//!
+//! The controlling side enables it with [`enable_message`] if the peer has
+//! [`PLATFORM_ADDITION_KEY`] and [`OPTION_ENABLE_FILE_COMPRESSION`] is not "N", then both sides
+//! compress the blocks they send.
+
+use hbb_common::{
+    fs::{self, TransferJob},
+    message_proto::{FileTransferBlock, Message, Misc, PluginRequest},
+    ResultType, Stream,
+};
+use std::collections::HashMap;
+
+pub const PLUGIN_ID: &str = "__file_compression";
+pub const PLATFORM_ADDITION_KEY: &str = "support_file_compression";
+/// Local option of the controlling side, "N" to send the blocks as they are.
+pub const OPTION_ENABLE_FILE_COMPRESSION: &str = "enable-file-compression";
+
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "aac", "apk", "avi", "br", "bz2", "cab", "deb", "docx", "dmg", "flac", "gif", "gz",
+    "heic", "iso", "jar", "jpeg", "jpg", "lz", "lz4", "lzma", "m4a", "mkv", "mov", "mp3", "mp4",
+    "msi", "odt", "ogg", "opus", "png", "pptx", "rar", "rpm", "tgz", "txz", "webm", "webp", "whl",
+    "xlsx", "xz", "zip", "zst",
+];
+const SAMPLE_SIZE: usize = 64 << 10;
+// In bits per byte, the compressed formats and the encrypted data are close to 8.
+const MAX_ENTROPY: f64 = 7.5;
+
+pub fn enable_message() -> Message {
+    let mut misc = Misc::new();
+    misc.set_plugin_request(PluginRequest {
+        id: PLUGIN_ID.to_owned(),
+        ..Default::default()
+    });
+    let mut msg = Message::new();
+    msg.set_misc(misc);
+    msg
+}
+
+fn has_compressed_extension(name: &str) -> bool {
+    std::path::Path::new(name)
+        .extension()
+        .and_then(|x| x.to_str())
+        .map_or(false, |x| {
+            COMPRESSED_EXTENSIONS.contains(&x.to_lowercase().as_str())
+        })
+}
+
+// Shannon entropy of the bytes, in bits per byte.
+fn entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.;
+    }
+    let mut counts = [0usize; 256];
+    for b in data {
+        counts[*b as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| {
+            let p = *c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn is_worth(name: &str, first_block: &[u8]) -> bool {
+    !has_compressed_extension(name)
+        && entropy(&first_block[..first_block.len().min(SAMPLE_SIZE)]) < MAX_ENTROPY
+}
+
+/// The decisions of the files being read, by the job id and the file number.
+#[derive(Default)]
+pub struct Compressor {
+    enabled: bool,
+    files: HashMap<(i32, i32), bool>,
+}
+
+impl Compressor {
+    pub fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn compress(&mut self, name: &str, block: &mut FileTransferBlock) {
+        if !self.enabled || block.compressed {
+            return;
+        }
+        let worth = *self
+            .files
+            .entry((block.id, block.file_num))
+            .or_insert_with(|| is_worth(name, &block.data));
+        if !worth {
+            return;
+        }
+        if let Some(data) = crate::compression::compress(&block.data) {
+            block.data = data.into();
+            block.compressed = true;
+        }
+    }
+
+    /// As `fs::handle_read_jobs`, with the blocks compressed if enabled.
+    ///
+    /// A copy of the loop of `hbb_common`, to be kept in sync with it until `hbb_common::fs`
+    /// takes a hook for the blocks read, e.g. `handle_read_jobs_with(jobs, stream, |job,
+    /// block| ...)`, and this only becomes that hook.
+    pub async fn handle_read_jobs(
+        &mut self,
+        jobs: &mut Vec<TransferJob>,
+        stream: &mut Stream,
+    ) -> ResultType<String> {
+        if !self.enabled {
+            return fs::handle_read_jobs(jobs, stream).await;
+        }
+        let mut job_log = Default::default();
+        let mut finished = Vec::new();
+        for job in jobs.iter_mut() {
+            if job.is_last_job {
+                continue;
+            }
+            match job.read(stream).await {
+                Err(err) => {
+                    stream
+                        .send(&fs::new_error(job.id(), err, job.file_num()))
+                        .await?;
+                }
+                Ok(Some(mut block)) => {
+                    let name = job
+                        .files()
+                        .get(block.file_num as usize)
+                        .map(|f| f.name.clone())
+                        .unwrap_or_default();
+                    self.compress(&name, &mut block);
+                    stream.send(&fs::new_block(block)).await?;
+                }
+                Ok(None) => {
+                    if job.job_completed() {
+                        job_log = fs::serialize_transfer_job(job, true, false, "");
+                        finished.push(job.id());
+                        match job.job_error() {
+                            Some(err) => {
+                                job_log = fs::serialize_transfer_job(job, false, false, &err);
+                                stream
+                                    .send(&fs::new_error(job.id(), err, job.file_num()))
+                                    .await?
+                            }
+                            None => stream.send(&fs::new_done(job.id(), job.file_num())).await?,
+                        }
+                    }
+                }
+            }
+        }
+        for id in finished {
+            self.files.retain(|(job, _), _| *job != id);
+            fs::remove_job(id, jobs);
+        }
+        Ok(job_log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_worth() {
+        let text = "2024-01-01 INFO connection established\n".repeat(100);
+        assert!(is_worth("server.log", text.as_bytes()));
+        assert!(!is_worth("backup.ZIP", text.as_bytes()));
+        let random: Vec<u8> = (0..SAMPLE_SIZE)
+            .map(|i| (i as u32).wrapping_mul(2654435761).rotate_right(13) as u8)
+            .collect();
+        assert!(entropy(&random) > MAX_ENTROPY);
+        assert!(!is_worth("dump.bin", &random));
+        assert_eq!(entropy(&[7u8; 100]), 0.);
+    }
+
+    #[test]
+    fn test_compress() {
+        let mut compressor = Compressor::default();
+        let data = "rustdesk ".repeat(1000);
+        let mut block = FileTransferBlock {
+            data: data.clone().into_bytes().into(),
+            ..Default::default()
+        };
+        compressor.compress("a.txt", &mut block);
+        assert!(!block.compressed);
+        compressor.enable();
+        compressor.compress("a.txt", &mut block);
+        assert!(block.compressed);
+        assert_eq!(
+            hbb_common::compress::decompress(&block.data),
+            data.as_bytes()
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! The files dropped onto the remote desktop, uploaded to the folder under the remote cursor.
+//!
+//! The controlling side moves the remote cursor to the drop position and asks for the folder
+//! with [`FileDropMessage::Query`]. The connection manager of the peer replies with the folder
+//! of the Explorer window at the cursor on Windows, else with the desktop folder of the user,
+//! then the files are uploaded by the transfer jobs of the session. The connection accepts
+//! the uploads of a remote desktop session once the drop is queried with the file transfer
+//! permission. Only the peers with [`PLATFORM_ADDITION_KEY`] are asked.
+
+use hbb_common::{
+    log,
+    message_proto::{Message, Misc, PluginRequest},
+};
+use serde_derive::{Deserialize, Serialize};
+
+pub const PLUGIN_ID: &str = "__file_drop";
+pub const PLATFORM_ADDITION_KEY: &str = "support_file_drop";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", rename_all = "snake_case")]
+pub enum FileDropMessage {
+    Query {
+        id: i32,
+    },
+    /// The folder to upload the files into, empty with the error.
+    Target {
+        id: i32,
+        dir: String,
+        error: Option<String>,
+    },
+}
+
+impl FileDropMessage {
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        serde_json::from_slice(content)
+            .map_err(|e| log::error!("Invalid file drop message: {}", e))
+            .ok()
+    }
+
+    pub fn to_message(&self) -> Message {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(self).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        msg
+    }
+
+    pub fn error(id: i32, error: &str) -> Self {
+        Self::Target {
+            id,
+            dir: String::new(),
+            error: Some(error.to_owned()),
+        }
+    }
+}
+
+// The folder of the Explorer window at the cursor, from its location.
+#[cfg(windows)]
+fn explorer_folder_at_cursor() -> Option<String> {
+    use windows::Win32::{
+        Foundation::POINT,
+        System::Com::{CoInitializeEx, CoUninitialize, COINIT_APARTMENTTHREADED},
+        UI::WindowsAndMessaging::{GetAncestor, GetCursorPos, WindowFromPoint, GA_ROOT},
+    };
+    unsafe {
+        let mut pt = POINT::default();
+        GetCursorPos(&mut pt).ok()?;
+        let hwnd = GetAncestor(WindowFromPoint(pt), GA_ROOT);
+        let initialized = CoInitializeEx(None, COINIT_APARTMENTTHREADED).is_ok();
+        let dir = explorer_folder(hwnd.0 as isize);
+        if initialized {
+            CoUninitialize();
+        }
+        dir
+    }
+}
+
+#[cfg(windows)]
+unsafe fn explorer_folder(hwnd: isize) -> Option<String> {
+    use windows::{
+        core::Interface,
+        Win32::{
+            System::{
+                Com::{CoCreateInstance, CLSCTX_ALL},
+                Variant::VARIANT,
+            },
+            UI::Shell::{IShellWindows, IWebBrowserApp, ShellWindows},
+        },
+    };
+    let windows: IShellWindows = CoCreateInstance(&ShellWindows, None, CLSCTX_ALL).ok()?;
+    for i in 0..windows.Count().ok()? {
+        let Ok(browser) = windows
+            .Item(&VARIANT::from(i))
+            .and_then(|d| d.cast::<IWebBrowserApp>())
+        else {
+            continue;
+        };
+        if browser.HWND().map_or(true, |h| h.0 != hwnd) {
+            continue;
+        }
+        let url = browser.LocationURL().ok()?.to_string();
+        let path = url::Url::parse(&url).ok()?.to_file_path().ok()?;
+        return Some(path.to_string_lossy().to_string());
+    }
+    None
+}
+
+/// The folder to upload the files dropped into, in the connection manager of the user.
+pub fn target_dir() -> Option<String> {
+    #[cfg(windows)]
+    if let Some(dir) = explorer_folder_at_cursor() {
+        return Some(dir);
+    }
+    let user = hbb_common::directories_next::UserDirs::new()?;
+    let dir = user
+        .desktop_dir()
+        .filter(|d| d.exists())
+        .unwrap_or(user.home_dir());
+    Some(dir.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let msg = FileDropMessage::error(1, "one-way-file-transfer-tip");
+        let content = serde_json::to_vec(&msg).unwrap();
+        assert_eq!(FileDropMessage::parse(&content), Some(msg));
+        assert_eq!(
+            FileDropMessage::parse(br#"{"t":"query","id":2}"#),
+            Some(FileDropMessage::Query { id: 2 })
+        );
+    }
+}
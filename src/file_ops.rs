@@ -0,0 +1,598 @@
+//! The operations of the file manager within a side, without a transfer: the move and the copy
+//! of files to another folder of the same side, the properties of a file, and the recursive
+//! search of the names under a folder.
+//!
+//! The requests of the remote side are sent to the peer, whose connection manager runs them in
+//! threads and replies with [`FileOpMessage::Done`], [`FileOpMessage::PropertiesResult`] or the
+//! batches of [`FileOpMessage::Found`]. The local ones run the same way in the session.
+//! Only the peers with [`PLATFORM_ADDITION_KEY`] are asked.
+
+use hbb_common::{
+    log,
+    message_proto::{Message, Misc, PluginRequest},
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const PLUGIN_ID: &str = "__file_ops";
+pub const PLATFORM_ADDITION_KEY: &str = "support_file_ops";
+
+const MAX_SEARCH_RESULTS: usize = 10_000;
+const SEARCH_BATCH: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", rename_all = "snake_case")]
+pub enum FileOpMessage {
+    /// Moves the files into the folder `to`.
+    Move {
+        id: i32,
+        paths: Vec<String>,
+        to: String,
+    },
+    /// Copies the files into the folder `to`, with a free name if they are there already.
+    Copy {
+        id: i32,
+        paths: Vec<String>,
+        to: String,
+    },
+    Properties {
+        id: i32,
+        path: String,
+    },
+    /// Searches the names under `dir` matching `pattern`, ignoring the case. A pattern with `*`
+    /// or `?` matches the whole names, another one any part of them.
+    Search {
+        id: i32,
+        dir: String,
+        pattern: String,
+        include_hidden: bool,
+    },
+    /// Cancels the search, or the totals of the properties, with the same id.
+    CancelSearch {
+        id: i32,
+    },
+    /// The result of a move or a copy, with the error of the first file failed.
+    Done {
+        id: i32,
+        error: Option<String>,
+    },
+    PropertiesResult {
+        id: i32,
+        properties: Option<Properties>,
+        error: Option<String>,
+    },
+    /// A batch of the names found, the last one is `done`.
+    Found {
+        id: i32,
+        entries: Vec<SearchEntry>,
+        done: bool,
+    },
+}
+
+impl FileOpMessage {
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        serde_json::from_slice(content)
+            .map_err(|e| log::error!("Invalid file operation message: {}", e))
+            .ok()
+    }
+
+    pub fn to_message(&self) -> Message {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(self).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        msg
+    }
+
+    /// The id of a move or a copy, the requests refused with the one-way file transfer.
+    pub fn modifying_id(&self) -> Option<i32> {
+        match self {
+            Self::Move { id, .. } | Self::Copy { id, .. } => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Properties {
+    pub path: String,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    /// The total size of the files, recursively for the folders.
+    pub size: u64,
+    pub files: u64,
+    pub dirs: u64,
+    /// Empty if unknown.
+    pub owner: String,
+    pub group: String,
+    pub mode: Option<u32>,
+    pub readonly: bool,
+    /// In seconds since the epoch.
+    pub created: Option<u64>,
+    pub modified: Option<u64>,
+    pub accessed: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchEntry {
+    pub path: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: u64,
+}
+
+lazy_static::lazy_static! {
+    // The cancel flags of the running searches and properties.
+    static ref CANCELLABLE: Mutex<HashMap<i32, Arc<AtomicBool>>> = Default::default();
+}
+
+fn start_cancellable(id: i32) -> Arc<AtomicBool> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    if let Some(old) = CANCELLABLE.lock().unwrap().insert(id, cancelled.clone()) {
+        old.store(true, Ordering::Relaxed);
+    }
+    cancelled
+}
+
+fn finish_cancellable(id: i32, cancelled: &Arc<AtomicBool>) {
+    let mut lock = CANCELLABLE.lock().unwrap();
+    if lock.get(&id).map_or(false, |c| Arc::ptr_eq(c, cancelled)) {
+        lock.remove(&id);
+    }
+}
+
+/// Runs the request in a thread, `reply` sends its responses to the requester.
+pub fn handle(msg: FileOpMessage, reply: impl Fn(FileOpMessage) + Send + 'static) {
+    match msg {
+        FileOpMessage::Move { id, paths, to } => {
+            std::thread::spawn(move || {
+                let error = move_files(&paths, &to).err();
+                reply(FileOpMessage::Done { id, error });
+            });
+        }
+        FileOpMessage::Copy { id, paths, to } => {
+            std::thread::spawn(move || {
+                let error = copy_files(&paths, &to).err();
+                reply(FileOpMessage::Done { id, error });
+            });
+        }
+        FileOpMessage::Properties { id, path } => {
+            let cancelled = start_cancellable(id);
+            std::thread::spawn(move || {
+                let res = properties(&path, &cancelled);
+                finish_cancellable(id, &cancelled);
+                let msg = match res {
+                    Ok(p) => FileOpMessage::PropertiesResult {
+                        id,
+                        properties: Some(p),
+                        error: None,
+                    },
+                    Err(e) => FileOpMessage::PropertiesResult {
+                        id,
+                        properties: None,
+                        error: Some(e.to_string()),
+                    },
+                };
+                reply(msg);
+            });
+        }
+        FileOpMessage::Search {
+            id,
+            dir,
+            pattern,
+            include_hidden,
+        } => {
+            let cancelled = start_cancellable(id);
+            std::thread::spawn(move || {
+                search(
+                    &dir,
+                    &pattern,
+                    include_hidden,
+                    &cancelled,
+                    |entries, done| reply(FileOpMessage::Found { id, entries, done }),
+                );
+                finish_cancellable(id, &cancelled);
+            });
+        }
+        FileOpMessage::CancelSearch { id } => {
+            if let Some(cancelled) = CANCELLABLE.lock().unwrap().remove(&id) {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+        FileOpMessage::Done { .. }
+        | FileOpMessage::PropertiesResult { .. }
+        | FileOpMessage::Found { .. } => {}
+    }
+}
+
+fn target(path: &Path, to: &Path) -> Result<PathBuf, String> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("Invalid path: {}", path.display()))?;
+    // Through the links and the `..`, e.g. a link inside the folder to the folder itself.
+    let real = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_owned());
+    let source = path
+        .parent()
+        .map_or(path.to_owned(), |p| real(p).join(name));
+    if real(to).starts_with(&source) {
+        return Err(format!("Can not put {} into itself", path.display()));
+    }
+    Ok(to.join(name))
+}
+
+// The error of a rename to another file system, the files are copied and removed then.
+fn crosses_devices(e: &io::Error) -> bool {
+    // EXDEV, ERROR_NOT_SAME_DEVICE
+    #[cfg(windows)]
+    return e.raw_os_error() == Some(17);
+    #[cfg(not(windows))]
+    return e.raw_os_error() == Some(18);
+}
+
+fn move_files(paths: &[String], to: &str) -> Result<(), String> {
+    let to = Path::new(to);
+    for path in paths {
+        let path = Path::new(path);
+        let target = target(path, to)?;
+        if target == path {
+            continue;
+        }
+        if target.symlink_metadata().is_ok() {
+            return Err(format!("{} already exists", target.display()));
+        }
+        let res = match fs::rename(path, &target) {
+            Err(e) if crosses_devices(&e) => copy(path, &target).and_then(|_| remove(path)),
+            res => res,
+        };
+        res.map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+fn copy_files(paths: &[String], to: &str) -> Result<(), String> {
+    let to = Path::new(to);
+    for path in paths {
+        let path = Path::new(path);
+        let target = free_name(&target(path, to)?);
+        copy(path, &target).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// The path itself if free, else with " (n)" before the extension.
+fn free_name(path: &Path) -> PathBuf {
+    if path.symlink_metadata().is_err() {
+        return path.to_owned();
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|p| p.symlink_metadata().is_err())
+        .unwrap_or_else(|| path.to_owned())
+}
+
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    let meta = from.symlink_metadata()?;
+    #[cfg(unix)]
+    if meta.file_type().is_symlink() {
+        return std::os::unix::fs::symlink(fs::read_link(from)?, to);
+    }
+    if meta.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        fs::set_permissions(to, meta.permissions())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+fn secs(time: io::Result<SystemTime>) -> Option<u64> {
+    time.ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+// The totals of a folder, without following its links.
+fn totals(dir: &Path, p: &mut Properties, cancelled: &AtomicBool) -> io::Result<()> {
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "Cancelled"));
+            }
+            let Ok(meta) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                p.dirs += 1;
+                dirs.push(entry.path());
+            } else {
+                p.files += 1;
+                p.size += meta.len();
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn owner(meta: &fs::Metadata) -> (String, String) {
+    use std::os::unix::fs::MetadataExt;
+    let user = users::get_user_by_uid(meta.uid())
+        .map(|u| u.name().to_string_lossy().to_string())
+        .unwrap_or_else(|| meta.uid().to_string());
+    let group = users::get_group_by_gid(meta.gid())
+        .map(|g| g.name().to_string_lossy().to_string())
+        .unwrap_or_else(|| meta.gid().to_string());
+    (user, group)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn owner(meta: &fs::Metadata) -> (String, String) {
+    use std::os::unix::fs::MetadataExt;
+    (meta.uid().to_string(), meta.gid().to_string())
+}
+
+#[cfg(not(unix))]
+fn owner(_meta: &fs::Metadata) -> (String, String) {
+    Default::default()
+}
+
+fn properties(path: &str, cancelled: &AtomicBool) -> io::Result<Properties> {
+    let link = Path::new(path).symlink_metadata()?;
+    let meta = fs::metadata(path).unwrap_or_else(|_| link.clone());
+    let (owner, group) = owner(&meta);
+    #[cfg(unix)]
+    let mode = Some(std::os::unix::fs::PermissionsExt::mode(&meta.permissions()) & 0o7777);
+    #[cfg(not(unix))]
+    let mode = None;
+    let mut p = Properties {
+        path: path.to_owned(),
+        is_dir: meta.is_dir(),
+        is_symlink: link.file_type().is_symlink(),
+        owner,
+        group,
+        mode,
+        readonly: meta.permissions().readonly(),
+        created: secs(meta.created()),
+        modified: secs(meta.modified()),
+        accessed: secs(meta.accessed()),
+        ..Default::default()
+    };
+    if p.is_dir {
+        totals(Path::new(path), &mut p, cancelled)?;
+    } else {
+        p.size = meta.len();
+    }
+    Ok(p)
+}
+
+fn is_hidden(name: &str, _meta: &fs::Metadata) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        // FILE_ATTRIBUTE_HIDDEN
+        if _meta.file_attributes() & 0x2 != 0 {
+            return true;
+        }
+    }
+    name.starts_with('.')
+}
+
+// Only goes back to the last `*`, which is enough as it matches any run of chars, so it
+// is linear in the name for every `*` instead of exponential in their number.
+fn wildcard(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The position after the last `*`, and the position in the name it resumes from.
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                star = Some((p, n));
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp;
+                    n = sn + 1;
+                    star = Some((sp, n));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Whether the name matches the pattern of a search, ignoring the case.
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let name = name.to_lowercase();
+    if pattern.contains(['*', '?']) {
+        let pattern = pattern.chars().collect::<Vec<_>>();
+        wildcard(&pattern, &name.chars().collect::<Vec<_>>())
+    } else {
+        name.contains(&pattern)
+    }
+}
+
+/// Searches the tree of `dir` without following its links, `found` gets the batches of the
+/// results until the search is done, cancelled or over `MAX_SEARCH_RESULTS`.
+fn search(
+    dir: &str,
+    pattern: &str,
+    include_hidden: bool,
+    cancelled: &AtomicBool,
+    found: impl Fn(Vec<SearchEntry>, bool),
+) {
+    let pattern = pattern.trim();
+    let mut batch = Vec::new();
+    let mut count = 0;
+    let mut dirs = vec![PathBuf::from(dir)];
+    'walk: while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if cancelled.load(Ordering::Relaxed) {
+                break 'walk;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Ok(meta) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if !include_hidden && is_hidden(&name, &meta) {
+                continue;
+            }
+            if meta.is_dir() {
+                dirs.push(entry.path());
+            }
+            if !pattern.is_empty() && matches(pattern, &name) {
+                batch.push(SearchEntry {
+                    path: entry.path().to_string_lossy().to_string(),
+                    is_dir: meta.is_dir(),
+                    size: if meta.is_dir() { 0 } else { meta.len() },
+                    modified: secs(meta.modified()).unwrap_or_default(),
+                });
+                count += 1;
+                if count >= MAX_SEARCH_RESULTS {
+                    break 'walk;
+                }
+                if batch.len() >= SEARCH_BATCH {
+                    found(std::mem::take(&mut batch), false);
+                }
+            }
+        }
+    }
+    found(batch, true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustdesk_test_file_ops_{}", name));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/x.txt"), b"x").unwrap();
+        fs::write(dir.join("a/b/y.TXT"), b"yy").unwrap();
+        fs::create_dir(dir.join("to")).unwrap();
+        dir
+    }
+
+    fn path(dir: &Path, name: &str) -> String {
+        dir.join(name).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_message() {
+        let msg = FileOpMessage::Search {
+            id: 1,
+            dir: "/a".to_owned(),
+            pattern: "*.txt".to_owned(),
+            include_hidden: false,
+        };
+        let content = serde_json::to_vec(&msg).unwrap();
+        assert_eq!(FileOpMessage::parse(&content), Some(msg.clone()));
+        assert_eq!(msg.modifying_id(), None);
+        let msg = FileOpMessage::parse(br#"{"t":"copy","id":2,"paths":[],"to":"/b"}"#);
+        assert_eq!(msg.unwrap().modifying_id(), Some(2));
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*.txt", "a.TXT"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "abbc"));
+        assert!(!matches("*.txt", "a.txt.bak"));
+        assert!(matches("Report", "my report.pdf"));
+        assert!(!matches("report", "repo"));
+        assert!(matches("*a*b", "xaxxb"));
+        assert!(matches("a*", "a"));
+        assert!(!matches("*a*a*a*a*a*a*a*a*b", &"a".repeat(1000)));
+        assert!(matches("*a*a*a*a*a*a*a*a*b", &("a".repeat(1000) + "b")));
+    }
+
+    #[test]
+    fn test_copy_move() {
+        let dir = temp_dir("copy_move");
+        copy_files(&[path(&dir, "a")], &path(&dir, "to")).unwrap();
+        assert_eq!(fs::read(dir.join("to/a/b/y.TXT")).unwrap(), b"yy");
+        copy_files(&[path(&dir, "a/x.txt")], &path(&dir, "a")).unwrap();
+        assert!(dir.join("a/x (1).txt").exists());
+        assert!(copy_files(&[path(&dir, "a")], &path(&dir, "a/b")).is_err());
+        assert!(move_files(&[path(&dir, "a")], &path(&dir, "to")).is_err());
+        assert!(copy_files(&[path(&dir, "a")], &path(&dir, "to/../a/b")).is_err());
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("a"), dir.join("link")).unwrap();
+            assert!(copy_files(&[path(&dir, "a")], &path(&dir, "link/b")).is_err());
+            fs::remove_file(dir.join("link")).unwrap();
+        }
+        move_files(&[path(&dir, "a/x.txt")], &path(&dir, "to")).unwrap();
+        assert!(!dir.join("a/x.txt").exists());
+        assert!(dir.join("to/x.txt").exists());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_properties_search() {
+        let dir = temp_dir("properties_search");
+        let p = properties(&path(&dir, "a"), &AtomicBool::new(false)).unwrap();
+        assert!(p.is_dir);
+        assert_eq!((p.size, p.files, p.dirs), (3, 2, 1));
+        assert!(properties(&path(&dir, "none"), &AtomicBool::new(false)).is_err());
+        assert!(properties(&path(&dir, "a"), &AtomicBool::new(true)).is_err());
+        fs::write(dir.join("a/.z.txt"), b"z").unwrap();
+        let found = Mutex::new(Vec::new());
+        search(
+            &path(&dir, "a"),
+            "*.txt",
+            false,
+            &AtomicBool::new(false),
+            |entries, done| found.lock().unwrap().push((entries.len(), done)),
+        );
+        assert_eq!(*found.lock().unwrap(), vec![(2, true)]);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,238 @@
+//! A second connection to the peer, dedicated to the blocks of the transfer jobs.
+//!
+//! The blocks of a multi-GB copy otherwise queue up in front of the video frames and the input
+//! on the main connection. Once logged in, the controlling side asks the peer for a token with
+//! [`FileStreamMessage::Request`], then opens another connection, negotiated like the main one,
+//! and sends a hello frame carrying the token right after its handshake. The controlled side
+//! waits for that hello only while it has tokens pending, hands the connection over to the
+//! connection which issued the token and acks it. The new connection is secured with its own
+//! key and has its own TCP flow control.
+//!
+//! Both sides read the messages of the file stream like the ones of the main connection, and
+//! send the blocks of their jobs on it once the jobs started on the main connection are done,
+//! to keep the blocks of a file in order. Only the peers with [`PLATFORM_ADDITION_KEY`] are
+//! asked.
+
+use crate::client::bonding::{decode_frame, encode_frame, FRAME_FILE_HELLO, FRAME_FILE_HELLO_ACK};
+use hbb_common::{
+    bail,
+    bytes::BytesMut,
+    config::READ_TIMEOUT,
+    futures::StreamExt,
+    log,
+    message_proto::{Message, Misc, PluginRequest},
+    timeout,
+    tokio::{
+        sync::mpsc,
+        time::{Duration, Instant},
+    },
+    ResultType, Stream,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+
+pub const PLUGIN_ID: &str = "__file_stream";
+pub const PLATFORM_ADDITION_KEY: &str = "support_file_stream";
+/// Local option of the controlling side, "N" to keep the blocks on the main connection.
+pub const OPTION_ENABLE_FILE_STREAM: &str = "enable-file-stream";
+
+const TOKEN_LEN: usize = 16;
+// The peer opens the stream right after the token is received.
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub type Token = [u8; TOKEN_LEN];
+
+lazy_static::lazy_static! {
+    static ref PENDING: Mutex<HashMap<Token, (mpsc::UnboundedSender<Stream>, Instant)>> = Default::default();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", rename_all = "snake_case")]
+pub enum FileStreamMessage {
+    Request,
+    /// The token to send in the hello of the stream, empty if refused.
+    Token {
+        token: Vec<u8>,
+    },
+}
+
+impl FileStreamMessage {
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        serde_json::from_slice(content)
+            .map_err(|e| log::error!("Invalid file stream message: {}", e))
+            .ok()
+    }
+
+    pub fn to_message(&self) -> Message {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(self).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        msg
+    }
+}
+
+/// Whether the incoming connections may be file streams, to wait for their hello.
+pub fn is_pending() -> bool {
+    let mut pending = PENDING.lock().unwrap();
+    pending.retain(|_, (_, t)| t.elapsed() < TOKEN_TIMEOUT);
+    !pending.is_empty()
+}
+
+/// Hands the secured incoming connection over to the connection which issued the token.
+pub async fn attach(token: &[u8], mut stream: Stream) -> ResultType<()> {
+    let tx = Token::try_from(token)
+        .ok()
+        .and_then(|t| PENDING.lock().unwrap().remove(&t));
+    let Some((tx, _)) = tx else {
+        bail!("Unknown file stream token");
+    };
+    stream
+        .send_bytes(encode_frame(FRAME_FILE_HELLO_ACK, 0, token))
+        .await?;
+    if tx.send(stream).is_err() {
+        bail!("The connection of the file stream is closed");
+    }
+    log::info!("File stream attached");
+    Ok(())
+}
+
+/// Sends the hello of the file stream on a freshly secured connection, and waits for the ack.
+pub async fn offer(stream: &mut Stream, token: &Token) -> ResultType<()> {
+    stream
+        .send_bytes(encode_frame(FRAME_FILE_HELLO, 0, token))
+        .await?;
+    loop {
+        match timeout(READ_TIMEOUT, stream.next()).await? {
+            Some(res) => {
+                let bytes = res?.freeze();
+                if bytes.is_empty() {
+                    continue;
+                }
+                match decode_frame(bytes) {
+                    Some((FRAME_FILE_HELLO_ACK, _, t)) if t[..] == token[..] => return Ok(()),
+                    _ => bail!("File stream refused by the peer"),
+                }
+            }
+            None => bail!("Reset by the peer"),
+        }
+    }
+}
+
+pub enum Event {
+    Attached,
+    Message(BytesMut),
+    Closed(String),
+}
+
+/// The file stream of a connection, on either side.
+pub struct FileStream {
+    tx: mpsc::UnboundedSender<Stream>,
+    rx: mpsc::UnboundedReceiver<Stream>,
+    stream: Option<Stream>,
+    writable: bool,
+    requested: bool,
+}
+
+impl Default for FileStream {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            tx,
+            rx,
+            stream: None,
+            writable: false,
+            requested: false,
+        }
+    }
+}
+
+impl FileStream {
+    /// The message asking the peer for a token, only once.
+    pub fn request(&mut self) -> Option<Message> {
+        if self.requested {
+            return None;
+        }
+        self.requested = true;
+        Some(FileStreamMessage::Request.to_message())
+    }
+
+    /// Issues a token for the peer to open the stream with.
+    pub fn new_token(&self) -> Token {
+        let token = *uuid::Uuid::new_v4().as_bytes();
+        PENDING
+            .lock()
+            .unwrap()
+            .insert(token, (self.tx.clone(), Instant::now()));
+        token
+    }
+
+    /// The sender of the stream opened by the controlling side.
+    pub fn sender(&self) -> mpsc::UnboundedSender<Stream> {
+        self.tx.clone()
+    }
+
+    /// Called when no job is read, the following ones are sent on the stream if attached.
+    pub fn on_idle(&mut self) {
+        self.writable = self.stream.is_some();
+    }
+
+    /// The stream to send the blocks of the jobs on.
+    pub fn writer<'a>(&'a mut self, main: &'a mut Stream) -> &'a mut Stream {
+        match self.stream.as_mut() {
+            Some(stream) if self.writable => stream,
+            _ => main,
+        }
+    }
+
+    /// Waits for the stream, then for its messages.
+    pub async fn recv(&mut self) -> Event {
+        let Some(stream) = self.stream.as_mut() else {
+            return match self.rx.recv().await {
+                Some(stream) => {
+                    self.stream = Some(stream);
+                    Event::Attached
+                }
+                None => Event::Closed("Reset by the peer".to_owned()),
+            };
+        };
+        let err = match stream.next().await {
+            Some(Ok(bytes)) => return Event::Message(bytes),
+            Some(Err(err)) => err.to_string(),
+            None => "Reset by the peer".to_owned(),
+        };
+        self.stream = None;
+        self.writable = false;
+        Event::Closed(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let msg = FileStreamMessage::Token {
+            token: vec![1, 2, 3],
+        };
+        let content = serde_json::to_vec(&msg).unwrap();
+        assert_eq!(FileStreamMessage::parse(&content), Some(msg));
+        assert_eq!(
+            FileStreamMessage::parse(br#"{"t":"request"}"#),
+            Some(FileStreamMessage::Request)
+        );
+    }
+
+    #[test]
+    fn test_pending() {
+        let file_stream = FileStream::default();
+        let token = file_stream.new_token();
+        assert!(is_pending());
+        PENDING.lock().unwrap().remove(&token);
+    }
+}
@@ -712,6 +712,11 @@ impl InvokeUiSession for FlutterHandler {
                     &status.codec_format.map_or(NULL, |it| it.to_string()),
                 ),
                 ("chroma", &status.chroma.map_or(NULL, |it| it.to_string())),
+                (
+                    "bandwidth",
+                    &status.bandwidth.map_or(NULL, |it| it.to_string()),
+                ),
+                ("bwe_state", &status.bwe_state.map_or(NULL, |it| it)),
             ],
             &[],
         );
@@ -753,6 +758,30 @@ impl InvokeUiSession for FlutterHandler {
         );
     }
 
+    fn integrity_failed(&self, id: i32, files: &str) {
+        self.push_event(
+            "integrity_failed",
+            &[("id", &id.to_string()), ("files", files)],
+            &[],
+        );
+    }
+
+    fn file_op_response(&self, is_local: bool, content: &str) {
+        self.push_event(
+            "file_op",
+            &[("is_local", &is_local.to_string()), ("content", content)],
+            &[],
+        );
+    }
+
+    fn file_drop_target(&self, content: &str) {
+        self.push_event("file_drop_target", &[("content", content)], &[]);
+    }
+
+    fn clipboard_paste(&self, content: &str) {
+        self.push_event("clipboard_paste", &[("content", content)], &[]);
+    }
+
     // unused in flutter
     fn clear_all_jobs(&self) {}
 
@@ -1090,6 +1119,18 @@ impl InvokeUiSession for FlutterHandler {
         self.push_event("record_status", &[("start", &start.to_string())], &[]);
     }
 
+    fn update_audio_devices(&self, devices: Vec<String>, current: String) {
+        self.push_event(
+            "audio_devices",
+            &[("devices", json!(devices)), ("current", json!(current))],
+            &[],
+        );
+    }
+
+    fn update_peer_power_state(&self, state: &str) {
+        self.push_event("peer_power_state", &[("state", state)], &[]);
+    }
+
     fn printer_request(&self, id: i32, path: String) {
         self.push_event(
             "printer_request",
@@ -1349,6 +1390,10 @@ pub fn session_add(
 
     let session = Arc::new(session.clone());
     sessions::insert_session(session_id.to_owned(), conn_type, session.clone());
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    if conn_type == ConnType::DEFAULT_CONN {
+        crate::input_script::start_ipc_server();
+    }
 
     Ok(session)
 }
@@ -1444,6 +1489,14 @@ pub fn send_clipboard_msg(msg: Message, _is_file: bool) {
             continue;
         }
         if s.is_text_clipboard_required() {
+            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+            if s.is_manual_clipboard_sync() {
+                crate::clipboard_history::LOCAL
+                    .lock()
+                    .unwrap()
+                    .push_msg(&msg);
+                continue;
+            }
             // Check if the client supports multi clipboards
             if let Some(message::Union::MultiClipboards(multi_clipboards)) = &msg.union {
                 let version = s.ui_handler.peer_info.read().unwrap().version.clone();
@@ -1533,6 +1586,11 @@ pub mod connection_manager {
             self.push_event("update_voice_call_state", &[("client", &client_json)]);
         }
 
+        fn update_input_blocked_state(&self, client: &crate::ui_cm_interface::Client) {
+            let client_json = serde_json::to_string(&client).unwrap_or("".into());
+            self.push_event("update_input_blocked_state", &[("client", &client_json)]);
+        }
+
         fn file_transfer_log(&self, action: &str, log: &str) {
             self.push_event("cm_file_transfer_log", &[(action, log)]);
         }
@@ -2138,8 +2196,12 @@ pub mod sessions {
     pub fn session_switch_display(is_desktop: bool, session_id: SessionID, value: Vec<i32>) {
         for s in SESSIONS.read().unwrap().values() {
             let mut write_lock = s.ui_handler.session_handlers.write().unwrap();
+            let is_single_window = write_lock.len() == 1;
             if let Some(h) = write_lock.get_mut(&session_id) {
                 h.displays = value.iter().map(|x| *x as usize).collect::<_>();
+                if is_single_window {
+                    s.set_last_display((value.len() == 1).then(|| value[0]));
+                }
                 #[cfg(not(any(target_os = "android", target_os = "ios")))]
                 let displays_refresh = value.clone();
                 if value.len() == 1 {
@@ -33,6 +33,8 @@ pub type SessionID = uuid::Uuid;
 
 lazy_static::lazy_static! {
     static ref TEXTURE_RENDER_KEY: Arc<AtomicI32> = Arc::new(AtomicI32::new(0));
+    // Where the local cursor is locked in the relative mouse mode.
+    static ref CURSOR_ANCHOR: std::sync::Mutex<Option<(i32, i32)>> = Default::default();
 }
 
 fn initialize(app_dir: &str, custom_client_config: &str) {
@@ -48,6 +50,8 @@ fn initialize(app_dir: &str, custom_client_config: &str) {
     } else {
         crate::read_custom_client(custom_client_config);
     }
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    crate::policy::load();
     #[cfg(target_os = "android")]
     {
         // flexi_logger can't work when android_logger initialized.
@@ -73,7 +77,7 @@ fn initialize(app_dir: &str, custom_client_config: &str) {
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     {
         // core_main's init_log does not work for flutter since it is only applied to its load_library in main.c
-        hbb_common::init_log(false, "flutter_ffi");
+        crate::logging::init("flutter_ffi");
     }
 }
 
@@ -183,6 +187,9 @@ pub fn session_start_with_displays(
     id: String,
     displays: Vec<i32>,
 ) -> ResultType<()> {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.start_with_displays.store(true, Ordering::SeqCst);
+    }
     session_start_(&session_id, &id, events2ui)?;
 
     if let Some(session) = sessions::get_session_by_session_id(&session_id) {
@@ -241,6 +248,30 @@ pub fn session_send2fa(session_id: SessionID, code: String, trust_this_device: b
     }
 }
 
+pub fn session_get_sas(session_id: SessionID) -> SyncReturn<String> {
+    let v = if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.get_sas()
+    } else {
+        "".to_owned()
+    };
+    SyncReturn(v)
+}
+
+pub fn session_is_peer_verified(session_id: SessionID) -> SyncReturn<bool> {
+    let v = if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.is_peer_verified()
+    } else {
+        false
+    };
+    SyncReturn(v)
+}
+
+pub fn session_mark_peer_verified(session_id: SessionID) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.mark_peer_verified();
+    }
+}
+
 pub fn session_get_enable_trusted_devices(session_id: SessionID) -> SyncReturn<bool> {
     let v = if let Some(session) = sessions::get_session_by_session_id(&session_id) {
         session.get_enable_trusted_devices()
@@ -273,7 +304,70 @@ pub fn session_take_screenshot(session_id: SessionID, display: usize) {
     }
 }
 
-pub fn session_handle_screenshot(#[allow(unused_variables)] session_id: SessionID, action: String) -> String {
+pub fn session_query_file_drop(session_id: SessionID, id: i32) {
+    if let Some(s) = sessions::get_session_by_session_id(&session_id) {
+        s.query_file_drop(id);
+    }
+}
+
+/// The other peers connected, which the peer of the session may send files to.
+pub fn session_get_broker_peers(session_id: SessionID) -> SyncReturn<String> {
+    let id = sessions::get_session_by_session_id(&session_id)
+        .map(|s| s.get_id())
+        .unwrap_or_default();
+    let mut peers: Vec<String> = sessions::get_sessions()
+        .into_iter()
+        .filter(|s| s.is_transfer_broker_supported() && s.get_id() != id)
+        .map(|s| s.get_id())
+        .collect();
+    peers.sort();
+    peers.dedup();
+    SyncReturn(serde_json::to_string(&peers).unwrap_or_default())
+}
+
+pub fn session_broker_transfer(
+    session_id: SessionID,
+    id: i32,
+    peer_id: String,
+    paths: Vec<String>,
+    to: String,
+) {
+    if let Some(s) = sessions::get_session_by_session_id(&session_id) {
+        let receiver = sessions::get_sessions()
+            .into_iter()
+            .find(|r| r.is_transfer_broker_supported() && r.get_id() == peer_id);
+        s.broker_transfer(id, paths, to, receiver.as_ref());
+    }
+}
+
+/// The recent copies of the peer if `remote`, of this device otherwise, in the manual
+/// clipboard sync.
+pub fn session_get_clipboard_history(_session_id: SessionID, _remote: bool) -> SyncReturn<String> {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    if let Some(s) = sessions::get_session_by_session_id(&_session_id) {
+        return SyncReturn(s.get_clipboard_history(_remote));
+    }
+    SyncReturn("[]".to_owned())
+}
+
+pub fn session_push_clip(_session_id: SessionID, _id: i32) {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    if let Some(s) = sessions::get_session_by_session_id(&_session_id) {
+        s.push_clip(_id);
+    }
+}
+
+pub fn session_pull_clip(_session_id: SessionID, _id: i32) {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    if let Some(s) = sessions::get_session_by_session_id(&_session_id) {
+        s.pull_clip(_id);
+    }
+}
+
+pub fn session_handle_screenshot(
+    #[allow(unused_variables)] session_id: SessionID,
+    action: String,
+) -> String {
     crate::client::screenshot::handle_screenshot(action)
 }
 
@@ -846,6 +940,18 @@ pub fn session_resume_job(session_id: SessionID, act_id: i32, is_remote: bool) {
     }
 }
 
+pub fn session_pause_job(session_id: SessionID, act_id: i32, is_remote: bool) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.pause_job(act_id, is_remote);
+    }
+}
+
+pub fn session_file_op(session_id: SessionID, op: String, is_remote: bool) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.file_op(op, is_remote);
+    }
+}
+
 pub fn session_rename_file(
     session_id: SessionID,
     act_id: i32,
@@ -858,6 +964,58 @@ pub fn session_rename_file(
     }
 }
 
+/// Returns the actions syncing the folders, `{"actions": [...]}`, or `{"error": "..."}`.
+pub fn session_folder_sync_plan(
+    session_id: SessionID,
+    local: String,
+    remote: String,
+    remote_fd: String,
+    options: String,
+) -> String {
+    let Some(session) = sessions::get_session_by_session_id(&session_id) else {
+        return "".to_owned();
+    };
+    let res = serde_json::from_str(&options)
+        .map_err(|e| e.into())
+        .and_then(|options| {
+            crate::folder_sync::plan(&session.get_id(), &local, &remote, &remote_fd, &options)
+        });
+    match res {
+        Ok(actions) => serde_json::json!({ "actions": actions }).to_string(),
+        Err(err) => serde_json::json!({ "error": err.to_string() }).to_string(),
+    }
+}
+
+pub fn session_folder_sync_finish(
+    session_id: SessionID,
+    local: String,
+    remote: String,
+    remote_fd: String,
+    include_hidden: bool,
+    errors: Vec<String>,
+) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        let id = session.get_id();
+        if let Err(err) =
+            crate::folder_sync::finish(&id, &local, &remote, &remote_fd, include_hidden, &errors)
+        {
+            log::error!("Failed to finish the folder sync: {}", err);
+        }
+    }
+}
+
+pub fn session_get_folder_sync_log(
+    session_id: SessionID,
+    local: String,
+    remote: String,
+) -> SyncReturn<String> {
+    SyncReturn(
+        sessions::get_session_by_session_id(&session_id)
+            .map(|s| crate::folder_sync::get_log(&s.get_id(), &local, &remote))
+            .unwrap_or_default(),
+    )
+}
+
 pub fn session_elevate_direct(session_id: SessionID) {
     if let Some(session) = sessions::get_session_by_session_id(&session_id) {
         session.elevate_direct();
@@ -1179,6 +1337,32 @@ pub fn main_set_input_source(session_id: SessionID, value: String) {
     }
 }
 
+pub fn main_get_passthrough_hotkeys() -> SyncReturn<String> {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let hotkeys = crate::hotkey_passthrough::get_hotkeys();
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    let hotkeys = "".to_owned();
+    SyncReturn(hotkeys)
+}
+
+/// Saves the hotkeys if they are valid, returns the invalid lines otherwise.
+pub fn main_set_passthrough_hotkeys(hotkeys: String) -> SyncReturn<String> {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    {
+        let invalid = crate::hotkey_passthrough::invalid_lines(&hotkeys);
+        if !invalid.is_empty() {
+            return SyncReturn(invalid.join("\n"));
+        }
+        set_local_option(
+            crate::hotkey_passthrough::OPTION_HOTKEYS.to_owned(),
+            hotkeys.trim().to_owned(),
+        );
+    }
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    let _ = hotkeys;
+    SyncReturn("".to_owned())
+}
+
 pub fn main_get_my_id() -> String {
     get_id()
 }
@@ -1503,6 +1687,52 @@ pub fn session_remove_port_forward(session_id: SessionID, local_port: i32) {
     }
 }
 
+fn make_tunnel(
+    kind: String,
+    local_port: i32,
+    remote_host: String,
+    remote_port: i32,
+) -> crate::tunnel::TunnelConfig {
+    crate::tunnel::TunnelConfig {
+        kind,
+        local_port: local_port as _,
+        remote_host,
+        remote_port: remote_port as _,
+    }
+}
+
+pub fn session_add_tunnel(
+    session_id: SessionID,
+    kind: String,
+    local_port: i32,
+    remote_host: String,
+    remote_port: i32,
+) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.add_tunnel(make_tunnel(kind, local_port, remote_host, remote_port));
+    }
+}
+
+pub fn session_remove_tunnel(
+    session_id: SessionID,
+    kind: String,
+    local_port: i32,
+    remote_host: String,
+    remote_port: i32,
+) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.remove_tunnel(make_tunnel(kind, local_port, remote_host, remote_port));
+    }
+}
+
+pub fn session_get_tunnels(session_id: SessionID) -> SyncReturn<String> {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        SyncReturn(serde_json::to_string(&session.get_tunnels()).unwrap_or_default())
+    } else {
+        SyncReturn("[]".to_owned())
+    }
+}
+
 pub fn session_new_rdp(session_id: SessionID) {
     if let Some(session) = sessions::get_session_by_session_id(&session_id) {
         session.new_rdp();
@@ -1521,6 +1751,38 @@ pub fn session_close_voice_call(session_id: SessionID) {
     }
 }
 
+pub fn session_query_audio_devices(session_id: SessionID) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.query_audio_devices();
+    }
+}
+
+pub fn session_select_audio_device(session_id: SessionID, device: String) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.select_audio_device(device);
+    }
+}
+
+pub fn session_set_audio_only(session_id: SessionID) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.set_audio_only();
+    }
+}
+
+pub fn session_set_mic_passthrough(session_id: SessionID, on: bool) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.set_mic_passthrough(on);
+    }
+}
+
+pub fn session_get_mic_passthrough(session_id: SessionID) -> SyncReturn<bool> {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        SyncReturn(session.get_mic_passthrough())
+    } else {
+        SyncReturn(false)
+    }
+}
+
 pub fn session_get_conn_token(session_id: SessionID) -> SyncReturn<Option<String>> {
     if let Some(session) = sessions::get_session_by_session_id(&session_id) {
         SyncReturn(session.get_conn_token())
@@ -1569,6 +1831,13 @@ pub fn main_get_software_update_url() {
 }
 
 pub fn main_get_home_dir() -> String {
+    // Only the documents of the app are shown in the Files app, see `UIFileSharingEnabled`.
+    #[cfg(target_os = "ios")]
+    return std::path::Path::new(&fs::get_home_as_string())
+        .join("Documents")
+        .to_string_lossy()
+        .to_string();
+    #[cfg(not(target_os = "ios"))]
     fs::get_home_as_string()
 }
 
@@ -1630,7 +1899,11 @@ pub fn main_has_vram() -> SyncReturn<bool> {
 
 pub fn main_supported_hwdecodings() -> SyncReturn<String> {
     let decoding = supported_hwdecodings();
-    let msg = HashMap::from([("h264", decoding.0), ("h265", decoding.1)]);
+    let msg = HashMap::from([
+        ("h264", decoding.0),
+        ("h265", decoding.1),
+        ("av1", decoding.2),
+    ]);
 
     SyncReturn(serde_json::ser::to_string(&msg).unwrap_or("".to_owned()))
 }
@@ -1720,6 +1993,7 @@ pub fn session_send_mouse(session_id: SessionID, msg: String) {
                 "up" => MOUSE_TYPE_UP,
                 "wheel" => MOUSE_TYPE_WHEEL,
                 "trackpad" => MOUSE_TYPE_TRACKPAD,
+                "move_relative" => MOUSE_TYPE_MOVE_RELATIVE,
                 _ => 0,
             };
         }
@@ -1739,6 +2013,96 @@ pub fn session_send_mouse(session_id: SessionID, msg: String) {
     }
 }
 
+/// Locks the local cursor where it is for the relative mouse mode, false if it can not be locked.
+pub fn main_lock_cursor(lock: bool) -> SyncReturn<bool> {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    {
+        let mut anchor = CURSOR_ANCHOR.lock().unwrap();
+        *anchor = if lock {
+            crate::platform::get_cursor_pos()
+        } else {
+            None
+        };
+        SyncReturn(!lock || anchor.is_some())
+    }
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    SyncReturn(!lock)
+}
+
+/// Moves the locked cursor back, after its motion is sent as a delta.
+pub fn main_recenter_cursor() -> SyncReturn<bool> {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    if let Some((x, y)) = *CURSOR_ANCHOR.lock().unwrap() {
+        return SyncReturn(crate::platform::set_cursor_pos(x, y));
+    }
+    SyncReturn(false)
+}
+
+pub fn session_send_pen(session_id: SessionID, msg: String) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.send_pen(msg);
+    }
+}
+
+pub fn session_send_touch(session_id: SessionID, msg: String) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.send_touch(msg);
+    }
+}
+
+pub fn session_send_special_key(session_id: SessionID, name: String) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.send_special_key(name);
+    }
+}
+
+pub fn session_start_input_recording(session_id: SessionID) -> String {
+    match sessions::get_session_by_session_id(&session_id) {
+        Some(session) => session
+            .start_input_recording()
+            .err()
+            .map(|e| e.to_string())
+            .unwrap_or_default(),
+        None => "".to_owned(),
+    }
+}
+
+pub fn session_stop_input_recording(session_id: SessionID, path: String) -> String {
+    match sessions::get_session_by_session_id(&session_id) {
+        Some(session) => session
+            .stop_input_recording(&path)
+            .err()
+            .map(|e| e.to_string())
+            .unwrap_or_default(),
+        None => "".to_owned(),
+    }
+}
+
+pub fn session_is_input_recording(session_id: SessionID) -> SyncReturn<bool> {
+    SyncReturn(
+        sessions::get_session_by_session_id(&session_id)
+            .map(|s| s.is_input_recording())
+            .unwrap_or_default(),
+    )
+}
+
+pub fn session_replay_input(session_id: SessionID, path: String) -> String {
+    match sessions::get_session_by_session_id(&session_id) {
+        Some(session) => session
+            .replay_input(&path)
+            .err()
+            .map(|e| e.to_string())
+            .unwrap_or_default(),
+        None => "".to_owned(),
+    }
+}
+
+pub fn session_abort_input_replay(session_id: SessionID) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.abort_input_replay("Aborted by the user");
+    }
+}
+
 pub fn session_restart_remote_device(session_id: SessionID) {
     if let Some(session) = sessions::get_session_by_session_id(&session_id) {
         session.restart_remote_device();
@@ -1787,6 +2151,24 @@ pub fn session_toggle_virtual_display(session_id: SessionID, index: i32, on: boo
     }
 }
 
+pub fn session_plug_in_virtual_display_mode(
+    session_id: SessionID,
+    index: i32,
+    width: i32,
+    height: i32,
+    refresh_rate: i32,
+) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.plug_in_virtual_display_mode(crate::client::VirtualDisplayMode {
+            index: index as _,
+            width: width as _,
+            height: height as _,
+            refresh: refresh_rate as _,
+        });
+        flutter::session_update_virtual_display(&session, index, true);
+    }
+}
+
 pub fn session_printer_response(
     session_id: SessionID,
     id: i32,
@@ -1874,6 +2256,33 @@ pub fn main_wol(id: String) {
     crate::lan::send_wol(id)
 }
 
+pub fn main_get_relay_latencies() -> SyncReturn<String> {
+    SyncReturn(serde_json::to_string(&crate::relay_latency::get_latencies()).unwrap_or_default())
+}
+
+pub fn main_probe_relay_servers() {
+    crate::relay_latency::probe_now();
+}
+
+pub fn main_get_wol_relay_peers(id: String) -> SyncReturn<String> {
+    let peers: Vec<String> = sessions::get_sessions()
+        .into_iter()
+        .filter(|s| s.is_default() && s.is_wol_relay_supported() && s.get_id() != id)
+        .map(|s| s.get_id())
+        .collect();
+    SyncReturn(serde_json::to_string(&peers).unwrap_or_default())
+}
+
+pub fn main_wol_via(id: String, relay_id: String) -> String {
+    match sessions::get_session_by_peer_id(relay_id, ConnType::DEFAULT_CONN) {
+        Some(session) => match session.send_wol(&id) {
+            Ok(()) => "".to_owned(),
+            Err(err) => err.to_string(),
+        },
+        None => "The relay peer is not connected".to_owned(),
+    }
+}
+
 pub fn main_create_shortcut(_id: String) {
     #[cfg(windows)]
     create_shortcut(_id);
@@ -2449,6 +2858,18 @@ pub fn main_has_valid_bot_sync() -> SyncReturn<bool> {
     SyncReturn(has_valid_bot())
 }
 
+pub fn main_register_security_key() -> String {
+    register_security_key()
+}
+
+pub fn main_check_access_control_list(rules: String) -> SyncReturn<String> {
+    SyncReturn(check_access_control_list(rules))
+}
+
+pub fn main_verify_recording(path: String) -> String {
+    verify_recording(path)
+}
+
 pub fn main_get_hard_option(key: String) -> SyncReturn<String> {
     SyncReturn(get_hard_option(key))
 }
@@ -2473,6 +2894,50 @@ pub fn main_clear_trusted_devices() {
     clear_trusted_devices()
 }
 
+pub fn main_get_lockouts() -> String {
+    get_lockouts()
+}
+
+pub fn main_clear_lockouts(json: String) {
+    clear_lockouts(&json)
+}
+
+pub fn main_get_settings_protection() -> String {
+    get_settings_protection()
+}
+
+pub fn main_unlock_settings(code: String) -> bool {
+    unlock_settings(code)
+}
+
+pub fn main_set_settings_admin_password(password: String) {
+    set_settings_admin_password(password)
+}
+
+pub fn main_get_one_time_passwords() -> String {
+    get_one_time_passwords()
+}
+
+pub fn main_add_one_time_password(json: String) -> String {
+    add_one_time_password(&json)
+}
+
+pub fn main_revoke_one_time_passwords(json: String) {
+    revoke_one_time_passwords(&json)
+}
+
+pub fn main_get_transfer_history() -> String {
+    get_transfer_history()
+}
+
+pub fn main_export_transfer_history(path: String) -> String {
+    export_transfer_history(&path)
+}
+
+pub fn main_get_server_key_pins() -> String {
+    get_server_key_pins()
+}
+
 pub fn main_max_encrypt_len() -> SyncReturn<usize> {
     SyncReturn(max_encrypt_len())
 }
@@ -2692,7 +3157,11 @@ pub fn session_get_common_sync(
     SyncReturn(session_get_common(session_id, key, param))
 }
 
-pub fn session_get_common(session_id: SessionID, key: String, #[allow(unused_variables)] param: String) -> Option<String> {
+pub fn session_get_common(
+    session_id: SessionID,
+    key: String,
+    #[allow(unused_variables)] param: String,
+) -> Option<String> {
     if let Some(s) = sessions::get_session_by_session_id(&session_id) {
         let v = if key == "is_screenshot_supported" {
             s.is_screenshot_supported().to_string()
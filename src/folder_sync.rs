@@ -0,0 +1,636 @@
+//! Synchronization of a local and a remote folder by the file transfer.
+//!
+//! The trees are compared by the relative path, the size and the modification time of their
+//! files, the file transfer keeps the modification time of the files it writes. The result is
+//! a plan of the copies, deletes and renames needed, previewed before it is applied by the file
+//! manager with the usual transfer, remove and rename jobs.
+//!
+//! The state of the files after the last sync is stored for the pair of folders, so a two-way
+//! sync tells a file created on one side from one deleted on the other, and a file changed on
+//! one side from a conflict, a file changed on both sides, or on the destination of a one-way
+//! sync, since the last sync.
+
+use hbb_common::{
+    config::{self, Config},
+    fs, log,
+    message_proto::{FileEntry, FileType},
+    ResultType,
+};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+const DIR: &str = "folder_sync";
+// The modification times of FAT are in steps of 2 seconds.
+const TIME_TOLERANCE: u64 = 2;
+const MAX_LOG_LINES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Local to remote.
+    Upload,
+    /// Remote to local.
+    Download,
+    TwoWay,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Policy {
+    /// The version modified last is kept.
+    Newest,
+    /// Both versions are left as they are.
+    Skip,
+    /// The older version is kept beside the newer one, renamed.
+    Rename,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Options {
+    pub mode: Mode,
+    pub policy: Policy,
+    /// Whether a one-way sync deletes the files missing in the source.
+    #[serde(default)]
+    pub mirror_deletes: bool,
+    #[serde(default)]
+    pub include_hidden: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stat {
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub modified_time: u64,
+}
+
+impl Stat {
+    fn same(&self, other: &Stat) -> bool {
+        self.size == other.size
+            && self.modified_time.abs_diff(other.modified_time) <= TIME_TOLERANCE
+    }
+}
+
+/// The names are relative to the synchronized folders, separated by `/`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    Upload {
+        name: String,
+    },
+    Download {
+        name: String,
+    },
+    DeleteLocal {
+        name: String,
+    },
+    DeleteRemote {
+        name: String,
+    },
+    RenameLocal {
+        name: String,
+        to: String,
+    },
+    RenameRemote {
+        name: String,
+        to: String,
+    },
+    /// A conflict left as it is.
+    Skip {
+        name: String,
+    },
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct Synced {
+    #[serde(default)]
+    local: Stat,
+    #[serde(default)]
+    remote: Stat,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    #[serde(default)]
+    peer_id: String,
+    #[serde(default)]
+    local: String,
+    #[serde(default)]
+    remote: String,
+    #[serde(default)]
+    files: BTreeMap<String, Synced>,
+}
+
+type Tree = BTreeMap<String, Stat>;
+
+lazy_static::lazy_static! {
+    // The plans not applied yet, by the key of the pair of folders.
+    static ref PLANS: Mutex<HashMap<String, Vec<Action>>> = Default::default();
+}
+
+#[derive(Deserialize)]
+struct JsonDirectory {
+    entries: Vec<JsonEntry>,
+}
+
+// An entry of `crate::common::make_fd_to_json`.
+#[derive(Deserialize)]
+struct JsonEntry {
+    entry_type: i32,
+    name: String,
+    size: u64,
+    modified_time: u64,
+}
+
+fn tree(entries: impl Iterator<Item = (i32, String, Stat)>) -> Tree {
+    entries
+        .filter(|(entry_type, _, _)| *entry_type >= FileType::File as i32)
+        .map(|(_, name, stat)| (name.replace('\\', "/"), stat))
+        .collect()
+}
+
+fn local_tree(path: &str, include_hidden: bool) -> ResultType<Tree> {
+    let entries = fs::get_recursive_files(path, include_hidden)?;
+    Ok(tree(entries.into_iter().map(|e: FileEntry| {
+        let stat = Stat {
+            size: e.size,
+            modified_time: e.modified_time,
+        };
+        (e.entry_type.value(), e.name, stat)
+    })))
+}
+
+fn remote_tree(fd_json: &str) -> ResultType<Tree> {
+    let fd: JsonDirectory = serde_json::from_str(fd_json)?;
+    Ok(tree(fd.entries.into_iter().map(|e| {
+        let stat = Stat {
+            size: e.size,
+            modified_time: e.modified_time,
+        };
+        (e.entry_type, e.name, stat)
+    })))
+}
+
+fn key(peer_id: &str, local: &str, remote: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\n{}\n{}", peer_id, local, remote).as_bytes());
+    hasher.finalize()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn state_path(key: &str) -> PathBuf {
+    Config::path(DIR).join(format!("{}.toml", key))
+}
+
+fn log_path(key: &str) -> PathBuf {
+    Config::path(DIR).join(format!("{}.log", key))
+}
+
+fn load(key: &str, peer_id: &str, local: &str, remote: &str) -> State {
+    let state: State = config::load_path(state_path(key));
+    if state.peer_id == peer_id && state.local == local && state.remote == remote {
+        state
+    } else {
+        Default::default()
+    }
+}
+
+fn store(key: &str, state: &State) {
+    let path = state_path(key);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).ok();
+    }
+    if let Err(err) = config::store_path(path, state) {
+        log::error!("Failed to store the folder sync state: {}", err);
+    }
+}
+
+fn append_log(key: &str, lines: &[String]) {
+    let path = log_path(key);
+    let mut all = std::fs::read_to_string(&path)
+        .map(|s| s.lines().map(|l| l.to_owned()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+    all.extend(lines.iter().map(|l| format!("{} {}", time, l)));
+    let skip = all.len().saturating_sub(MAX_LOG_LINES);
+    let res = std::fs::create_dir_all(Config::path(DIR))
+        .and_then(|_| std::fs::File::create(&path))
+        .and_then(|mut f| f.write_all((all[skip..].join("\n") + "\n").as_bytes()));
+    if let Err(err) = res {
+        log::error!("Failed to write the folder sync log: {}", err);
+    }
+}
+
+// `dir/name (conflict 2024-01-01 120000).ext`
+fn conflict_name(name: &str, time: &str) -> String {
+    let (dir, file) = match name.rfind('/') {
+        Some(i) => name.split_at(i + 1),
+        None => ("", name),
+    };
+    let (stem, ext) = match file.rfind('.') {
+        Some(i) if i > 0 => file.split_at(i),
+        _ => (file, ""),
+    };
+    format!("{}{} (conflict {}){}", dir, stem, time, ext)
+}
+
+// Resolves the conflict on the file by the policy, the source of a one-way sync and the local
+// version of a two-way sync are taken as the newer ones when the modification times are equal.
+fn resolve(
+    name: &str,
+    local: &Stat,
+    remote: &Stat,
+    options: &Options,
+    time: &str,
+    actions: &mut Vec<Action>,
+) {
+    let mode = options.mode;
+    let upload = match local.modified_time.cmp(&remote.modified_time) {
+        std::cmp::Ordering::Greater => true,
+        std::cmp::Ordering::Less => false,
+        std::cmp::Ordering::Equal => mode != Mode::Download,
+    };
+    let name = name.to_owned();
+    match options.policy {
+        Policy::Skip => actions.push(Action::Skip { name }),
+        Policy::Newest => match (mode, upload) {
+            (Mode::Download, true) | (Mode::Upload, false) => actions.push(Action::Skip { name }),
+            (_, true) => actions.push(Action::Upload { name }),
+            (_, false) => actions.push(Action::Download { name }),
+        },
+        // The version of the destination of a one-way sync is kept on the destination, the
+        // older one of a two-way sync is copied to both sides.
+        Policy::Rename => {
+            let to = conflict_name(&name, time);
+            match (mode, upload) {
+                (Mode::Upload, _) | (Mode::TwoWay, true) => {
+                    actions.push(Action::RenameRemote {
+                        name: name.clone(),
+                        to: to.clone(),
+                    });
+                    actions.push(Action::Upload { name });
+                    if mode == Mode::TwoWay {
+                        actions.push(Action::Download { name: to });
+                    }
+                }
+                (Mode::Download, _) | (Mode::TwoWay, false) => {
+                    actions.push(Action::RenameLocal {
+                        name: name.clone(),
+                        to: to.clone(),
+                    });
+                    actions.push(Action::Download { name });
+                    if mode == Mode::TwoWay {
+                        actions.push(Action::Upload { name: to });
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn compare(
+    local: &Tree,
+    remote: &Tree,
+    base: &BTreeMap<String, Synced>,
+    options: &Options,
+    time: &str,
+) -> Vec<Action> {
+    let (mode, mirror_deletes) = (options.mode, options.mirror_deletes);
+    let mut actions = vec![];
+    let names: BTreeSet<&String> = local.keys().chain(remote.keys()).collect();
+    for name in names {
+        let synced = base.get(name);
+        let local_changed = |s: &Stat| synced.map_or(true, |b| !s.same(&b.local));
+        let remote_changed = |s: &Stat| synced.map_or(true, |b| !s.same(&b.remote));
+        let name = name.to_owned();
+        match (local.get(&name), remote.get(&name)) {
+            (Some(l), Some(r)) => {
+                if l.same(r) {
+                    continue;
+                }
+                let conflict = match mode {
+                    // Without a state, the destination newer is taken as changed.
+                    Mode::Upload => match synced {
+                        Some(_) => remote_changed(r),
+                        None => r.modified_time > l.modified_time + TIME_TOLERANCE,
+                    },
+                    Mode::Download => match synced {
+                        Some(_) => local_changed(l),
+                        None => l.modified_time > r.modified_time + TIME_TOLERANCE,
+                    },
+                    Mode::TwoWay => local_changed(l) && remote_changed(r),
+                };
+                if conflict {
+                    resolve(&name, l, r, options, time, &mut actions);
+                } else if mode == Mode::Upload || (mode == Mode::TwoWay && local_changed(l)) {
+                    actions.push(Action::Upload { name });
+                } else {
+                    actions.push(Action::Download { name });
+                }
+            }
+            (Some(l), None) => match mode {
+                Mode::Upload => actions.push(Action::Upload { name }),
+                Mode::Download if mirror_deletes => actions.push(Action::DeleteLocal { name }),
+                Mode::Download => {}
+                // Deleted on the remote side, unless changed since.
+                Mode::TwoWay if synced.is_some() && !local_changed(l) => {
+                    actions.push(Action::DeleteLocal { name })
+                }
+                Mode::TwoWay => actions.push(Action::Upload { name }),
+            },
+            (None, Some(r)) => match mode {
+                Mode::Download => actions.push(Action::Download { name }),
+                Mode::Upload if mirror_deletes => actions.push(Action::DeleteRemote { name }),
+                Mode::Upload => {}
+                Mode::TwoWay if synced.is_some() && !remote_changed(r) => {
+                    actions.push(Action::DeleteRemote { name })
+                }
+                Mode::TwoWay => actions.push(Action::Download { name }),
+            },
+            (None, None) => {}
+        }
+    }
+    actions
+}
+
+/// Compares the local folder with the listing of the remote one, `remote_fd` is the json of
+/// its recursive read, and returns the plan.
+pub fn plan(
+    peer_id: &str,
+    local: &str,
+    remote: &str,
+    remote_fd: &str,
+    options: &Options,
+) -> ResultType<Vec<Action>> {
+    let key = key(peer_id, local, remote);
+    let local_tree = local_tree(local, options.include_hidden)?;
+    let remote_tree = remote_tree(remote_fd)?;
+    let state = load(&key, peer_id, local, remote);
+    let time = chrono::Local::now().format("%Y-%m-%d %H%M%S").to_string();
+    let actions = compare(&local_tree, &remote_tree, &state.files, options, &time);
+    PLANS.lock().unwrap().insert(key, actions.clone());
+    Ok(actions)
+}
+
+/// The plan is applied, stores the state of the files identical on both sides and logs the
+/// actions with the errors of the jobs.
+pub fn finish(
+    peer_id: &str,
+    local: &str,
+    remote: &str,
+    remote_fd: &str,
+    include_hidden: bool,
+    errors: &[String],
+) -> ResultType<()> {
+    let key = key(peer_id, local, remote);
+    let local_tree = local_tree(local, include_hidden)?;
+    let remote_tree = remote_tree(remote_fd)?;
+    let files: BTreeMap<String, Synced> = local_tree
+        .iter()
+        .filter_map(|(name, l)| {
+            let r = remote_tree.get(name).filter(|r| l.same(r))?;
+            Some((
+                name.clone(),
+                Synced {
+                    local: *l,
+                    remote: *r,
+                },
+            ))
+        })
+        .collect();
+    let state = State {
+        peer_id: peer_id.to_owned(),
+        local: local.to_owned(),
+        remote: remote.to_owned(),
+        files,
+    };
+    store(&key, &state);
+    let actions = PLANS.lock().unwrap().remove(&key).unwrap_or_default();
+    let mut lines = vec![format!("sync {} <-> {}:{}", local, peer_id, remote)];
+    lines.extend(
+        actions
+            .iter()
+            .map(|a| serde_json::to_string(a).unwrap_or_default()),
+    );
+    lines.extend(errors.iter().map(|e| format!("error: {}", e)));
+    lines.push(format!(
+        "{} actions, {} errors, {} files in sync",
+        actions.len(),
+        errors.len(),
+        state.files.len()
+    ));
+    append_log(&key, &lines);
+    Ok(())
+}
+
+/// The log of the syncs of the pair of folders.
+pub fn get_log(peer_id: &str, local: &str, remote: &str) -> String {
+    std::fs::read_to_string(log_path(&key(peer_id, local, remote))).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(size: u64, modified_time: u64) -> Stat {
+        Stat {
+            size,
+            modified_time,
+        }
+    }
+
+    fn tree(files: &[(&str, Stat)]) -> Tree {
+        files.iter().map(|(n, s)| (n.to_string(), *s)).collect()
+    }
+
+    fn options(mode: Mode, policy: Policy, mirror_deletes: bool) -> Options {
+        Options {
+            mode,
+            policy,
+            mirror_deletes,
+            include_hidden: false,
+        }
+    }
+
+    fn upload(name: &str) -> Action {
+        Action::Upload {
+            name: name.to_owned(),
+        }
+    }
+
+    fn download(name: &str) -> Action {
+        Action::Download {
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_conflict_name() {
+        assert_eq!(conflict_name("a/b.txt", "t"), "a/b (conflict t).txt");
+        assert_eq!(conflict_name("a.b/c", "t"), "a.b/c (conflict t)");
+        assert_eq!(conflict_name(".bashrc", "t"), ".bashrc (conflict t)");
+    }
+
+    #[test]
+    fn test_one_way() {
+        let local = tree(&[
+            ("a", stat(1, 100)),
+            ("b", stat(2, 100)),
+            ("c", stat(3, 100)),
+        ]);
+        let remote = tree(&[("a", stat(1, 101)), ("b", stat(2, 50)), ("d", stat(4, 100))]);
+        let base = BTreeMap::new();
+        let actions = compare(
+            &local,
+            &remote,
+            &base,
+            &options(Mode::Upload, Policy::Newest, false),
+            "t",
+        );
+        assert_eq!(actions, vec![upload("b"), upload("c")]);
+        let actions = compare(
+            &local,
+            &remote,
+            &base,
+            &options(Mode::Upload, Policy::Newest, true),
+            "t",
+        );
+        assert_eq!(
+            actions,
+            vec![
+                upload("b"),
+                upload("c"),
+                Action::DeleteRemote {
+                    name: "d".to_owned()
+                }
+            ]
+        );
+        // The destination is newer.
+        let remote = tree(&[("b", stat(5, 200))]);
+        let actions = compare(
+            &local,
+            &remote,
+            &base,
+            &options(Mode::Upload, Policy::Newest, false),
+            "t",
+        );
+        assert_eq!(
+            actions,
+            vec![
+                upload("a"),
+                Action::Skip {
+                    name: "b".to_owned()
+                },
+                upload("c")
+            ]
+        );
+        let actions = compare(
+            &local,
+            &remote,
+            &base,
+            &options(Mode::Upload, Policy::Rename, false),
+            "t",
+        );
+        assert_eq!(
+            actions[1..3],
+            [
+                Action::RenameRemote {
+                    name: "b".to_owned(),
+                    to: "b (conflict t)".to_owned()
+                },
+                upload("b")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_two_way() {
+        let base: BTreeMap<String, Synced> = ["same", "deleted", "changed", "both"]
+            .iter()
+            .map(|n| {
+                let s = Synced {
+                    local: stat(1, 100),
+                    remote: stat(1, 100),
+                };
+                (n.to_string(), s)
+            })
+            .collect();
+        let local = tree(&[
+            ("same", stat(1, 100)),
+            ("deleted", stat(1, 100)),
+            ("changed", stat(1, 100)),
+            ("both", stat(2, 300)),
+            ("new", stat(1, 100)),
+        ]);
+        let remote = tree(&[
+            ("same", stat(1, 100)),
+            ("changed", stat(2, 200)),
+            ("both", stat(3, 200)),
+        ]);
+        let actions = compare(
+            &local,
+            &remote,
+            &base,
+            &options(Mode::TwoWay, Policy::Newest, false),
+            "t",
+        );
+        assert_eq!(
+            actions,
+            vec![
+                upload("both"),
+                download("changed"),
+                Action::DeleteLocal {
+                    name: "deleted".to_owned()
+                },
+                upload("new"),
+            ]
+        );
+        let actions = compare(
+            &local,
+            &remote,
+            &base,
+            &options(Mode::TwoWay, Policy::Rename, false),
+            "t",
+        );
+        assert_eq!(
+            actions[..3],
+            [
+                Action::RenameRemote {
+                    name: "both".to_owned(),
+                    to: "both (conflict t)".to_owned()
+                },
+                upload("both"),
+                download("both (conflict t)"),
+            ]
+        );
+        // Without a state nothing is deleted.
+        let actions = compare(
+            &local,
+            &remote,
+            &BTreeMap::new(),
+            &options(Mode::TwoWay, Policy::Skip, false),
+            "t",
+        );
+        assert_eq!(
+            actions,
+            vec![
+                Action::Skip {
+                    name: "both".to_owned()
+                },
+                Action::Skip {
+                    name: "changed".to_owned()
+                },
+                upload("deleted"),
+                upload("new"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,406 @@
+//! Gamepad forwarding.
+//!
+//! The controlling side reads its gamepads by gilrs, XInput on Windows and evdev on Linux,
+//! and sends their state in the XInput layout on every change, with the plug and unplug.
+//! The controlled side plugs one virtual Xbox 360 controller per forwarded gamepad, by
+//! ViGEm on Windows and uinput on Linux, and replays the state on it.
+//!
+//! The states are carried by `PluginRequest` with a reserved id, no protocol change is needed.
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+use hbb_common::{bail, ResultType};
+use hbb_common::{
+    config::{self, Config},
+    message_proto::{Message, Misc, PluginRequest},
+};
+use serde_derive::{Deserialize, Serialize};
+
+/// The content is a [`GamepadState`] in json.
+pub const PLUGIN_ID: &str = "__gamepad";
+/// Peer option of the controlling side, to forward the local gamepads.
+pub const PEER_OPTION_FORWARD_GAMEPAD: &str = "forward-gamepad";
+/// Option of the controlled side, to replay the gamepads of the peers with the keyboard
+/// permission. Off by default, a peer profile can override it.
+pub const OPTION_ALLOW_REMOTE_GAMEPAD: &str = "allow-remote-gamepad";
+/// XInput supports 4 controllers.
+pub const MAX_GAMEPADS: usize = 4;
+
+// The buttons of XINPUT_GAMEPAD.
+pub const BUTTON_DPAD_UP: u16 = 0x0001;
+pub const BUTTON_DPAD_DOWN: u16 = 0x0002;
+pub const BUTTON_DPAD_LEFT: u16 = 0x0004;
+pub const BUTTON_DPAD_RIGHT: u16 = 0x0008;
+pub const BUTTON_START: u16 = 0x0010;
+pub const BUTTON_BACK: u16 = 0x0020;
+pub const BUTTON_LEFT_THUMB: u16 = 0x0040;
+pub const BUTTON_RIGHT_THUMB: u16 = 0x0080;
+pub const BUTTON_LEFT_SHOULDER: u16 = 0x0100;
+pub const BUTTON_RIGHT_SHOULDER: u16 = 0x0200;
+pub const BUTTON_GUIDE: u16 = 0x0400;
+pub const BUTTON_A: u16 = 0x1000;
+pub const BUTTON_B: u16 = 0x2000;
+pub const BUTTON_X: u16 = 0x4000;
+pub const BUTTON_Y: u16 = 0x8000;
+
+/// The state of a gamepad, the axes up and right are positive.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GamepadState {
+    pub index: u8,
+    pub connected: bool,
+    #[serde(default)]
+    pub buttons: u16,
+    #[serde(default)]
+    pub left_trigger: u8,
+    #[serde(default)]
+    pub right_trigger: u8,
+    #[serde(default)]
+    pub thumb_lx: i16,
+    #[serde(default)]
+    pub thumb_ly: i16,
+    #[serde(default)]
+    pub thumb_rx: i16,
+    #[serde(default)]
+    pub thumb_ry: i16,
+}
+
+impl GamepadState {
+    pub fn to_message(&self) -> Message {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(self).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        msg
+    }
+}
+
+#[inline]
+pub fn is_allowed() -> bool {
+    config::option2bool(
+        OPTION_ALLOW_REMOTE_GAMEPAD,
+        &Config::get_option(OPTION_ALLOW_REMOTE_GAMEPAD),
+    )
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub use capture::Capture;
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod capture {
+    use super::*;
+    use gilrs::{Axis, Button, EventType, Gamepad, GamepadId, Gilrs};
+    use hbb_common::log;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread::JoinHandle,
+        time::Duration,
+    };
+
+    const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+    const BUTTONS: [(Button, u16); 15] = [
+        (Button::DPadUp, BUTTON_DPAD_UP),
+        (Button::DPadDown, BUTTON_DPAD_DOWN),
+        (Button::DPadLeft, BUTTON_DPAD_LEFT),
+        (Button::DPadRight, BUTTON_DPAD_RIGHT),
+        (Button::Start, BUTTON_START),
+        (Button::Select, BUTTON_BACK),
+        (Button::LeftThumb, BUTTON_LEFT_THUMB),
+        (Button::RightThumb, BUTTON_RIGHT_THUMB),
+        (Button::LeftTrigger, BUTTON_LEFT_SHOULDER),
+        (Button::RightTrigger, BUTTON_RIGHT_SHOULDER),
+        (Button::Mode, BUTTON_GUIDE),
+        (Button::South, BUTTON_A),
+        (Button::East, BUTTON_B),
+        (Button::West, BUTTON_X),
+        (Button::North, BUTTON_Y),
+    ];
+
+    /// Reads the local gamepads until dropped, the states are sent by the callback.
+    pub struct Capture {
+        running: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Capture {
+        pub fn start(callback: impl FnMut(Message) + Send + 'static) -> Self {
+            let running = Arc::new(AtomicBool::new(true));
+            let running_cloned = running.clone();
+            let thread = std::thread::spawn(move || run(running_cloned, callback));
+            Self {
+                running,
+                thread: Some(thread),
+            }
+        }
+    }
+
+    impl Drop for Capture {
+        fn drop(&mut self) {
+            self.running.store(false, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                thread.join().ok();
+            }
+        }
+    }
+
+    fn run(running: Arc<AtomicBool>, mut callback: impl FnMut(Message)) {
+        let mut gilrs = match Gilrs::new() {
+            Ok(gilrs) => gilrs,
+            Err(e) => {
+                log::error!("Failed to read the gamepads: {}", e);
+                return;
+            }
+        };
+        // The index of the forwarded gamepad is the slot.
+        let mut slots: [Option<(GamepadId, GamepadState)>; MAX_GAMEPADS] = Default::default();
+        let ids: Vec<GamepadId> = gilrs.gamepads().map(|(id, _)| id).collect();
+        for id in ids {
+            plug(&mut slots, &gilrs, id, &mut callback);
+        }
+        while running.load(Ordering::SeqCst) {
+            let Some(event) = gilrs.next_event_blocking(Some(POLL_TIMEOUT)) else {
+                continue;
+            };
+            match event.event {
+                EventType::Connected => plug(&mut slots, &gilrs, event.id, &mut callback),
+                EventType::Disconnected => {
+                    if let Some(slot) = slots
+                        .iter_mut()
+                        .find(|s| s.map_or(false, |(id, _)| id == event.id))
+                    {
+                        if let Some((_, state)) = slot.take() {
+                            log::info!("Gamepad {} is unplugged", state.index);
+                            callback(unplugged(state.index));
+                        }
+                    }
+                }
+                _ => {
+                    for slot in slots.iter_mut() {
+                        if let Some((id, last)) = slot {
+                            if *id == event.id {
+                                let state = read_state(last.index, &gilrs.gamepad(*id));
+                                if state != *last {
+                                    *last = state;
+                                    callback(state.to_message());
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for (_, state) in slots.iter().flatten() {
+            callback(unplugged(state.index));
+        }
+    }
+
+    fn plug(
+        slots: &mut [Option<(GamepadId, GamepadState)>; MAX_GAMEPADS],
+        gilrs: &Gilrs,
+        id: GamepadId,
+        callback: &mut impl FnMut(Message),
+    ) {
+        if slots.iter().flatten().any(|(x, _)| *x == id) {
+            return;
+        }
+        let Some(index) = slots.iter().position(|s| s.is_none()) else {
+            log::warn!("No more than {} gamepads are forwarded", MAX_GAMEPADS);
+            return;
+        };
+        let gamepad = gilrs.gamepad(id);
+        log::info!("Gamepad {} is plugged: {}", index, gamepad.name());
+        let state = read_state(index as _, &gamepad);
+        slots[index] = Some((id, state));
+        callback(state.to_message());
+    }
+
+    fn unplugged(index: u8) -> Message {
+        GamepadState {
+            index,
+            connected: false,
+            ..Default::default()
+        }
+        .to_message()
+    }
+
+    fn read_state(index: u8, gamepad: &Gamepad) -> GamepadState {
+        let buttons = BUTTONS
+            .iter()
+            .filter(|(b, _)| gamepad.is_pressed(*b))
+            .fold(0, |acc, (_, bit)| acc | bit);
+        let trigger = |b| {
+            gamepad
+                .button_data(b)
+                .map(|d| (d.value().clamp(0., 1.) * u8::MAX as f32) as u8)
+                .unwrap_or(0)
+        };
+        let thumb = |a| (gamepad.value(a).clamp(-1., 1.) * i16::MAX as f32) as i16;
+        GamepadState {
+            index,
+            connected: true,
+            buttons,
+            left_trigger: trigger(Button::LeftTrigger2),
+            right_trigger: trigger(Button::RightTrigger2),
+            thumb_lx: thumb(Axis::LeftStickX),
+            thumb_ly: thumb(Axis::LeftStickY),
+            thumb_rx: thumb(Axis::RightStickX),
+            thumb_ry: thumb(Axis::RightStickY),
+        }
+    }
+}
+
+/// The virtual gamepads plugged for the gamepads of a peer, unplugged when dropped.
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+#[derive(Default)]
+pub struct VirtualGamepads {
+    pads: std::collections::HashMap<u8, VirtualGamepad>,
+}
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+impl VirtualGamepads {
+    pub fn handle(&mut self, state: &GamepadState) -> ResultType<()> {
+        if state.index as usize >= MAX_GAMEPADS {
+            bail!("Invalid gamepad index {}", state.index);
+        }
+        if !state.connected {
+            if self.pads.remove(&state.index).is_some() {
+                hbb_common::log::info!("Virtual gamepad {} is unplugged", state.index);
+            }
+            return Ok(());
+        }
+        if !self.pads.contains_key(&state.index) {
+            self.pads.insert(state.index, VirtualGamepad::new()?);
+            hbb_common::log::info!("Virtual gamepad {} is plugged", state.index);
+        }
+        if let Some(pad) = self.pads.get_mut(&state.index) {
+            pad.update(state)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+struct VirtualGamepad(vigem_client::Xbox360Wired<vigem_client::Client>);
+
+#[cfg(target_os = "windows")]
+impl VirtualGamepad {
+    fn new() -> ResultType<Self> {
+        let client = match vigem_client::Client::connect() {
+            Ok(client) => client,
+            Err(e) => bail!("Failed to connect to ViGEmBus, is it installed? {}", e),
+        };
+        let mut target =
+            vigem_client::Xbox360Wired::new(client, vigem_client::TargetId::XBOX360_WIRED);
+        target.plugin()?;
+        target.wait_ready()?;
+        Ok(Self(target))
+    }
+
+    fn update(&mut self, state: &GamepadState) -> ResultType<()> {
+        self.0.update(&vigem_client::XGamepad {
+            buttons: vigem_client::XButtons { raw: state.buttons },
+            left_trigger: state.left_trigger,
+            right_trigger: state.right_trigger,
+            thumb_lx: state.thumb_lx,
+            thumb_ly: state.thumb_ly,
+            thumb_rx: state.thumb_rx,
+            thumb_ry: state.thumb_ry,
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct VirtualGamepad {
+    device: evdev::uinput::VirtualDevice,
+    last: GamepadState,
+}
+
+#[cfg(target_os = "linux")]
+impl VirtualGamepad {
+    // The button codes of the xpad driver.
+    const BUTTONS: [(u16, evdev::Key); 11] = [
+        (BUTTON_A, evdev::Key::BTN_SOUTH),
+        (BUTTON_B, evdev::Key::BTN_EAST),
+        (BUTTON_X, evdev::Key::BTN_NORTH),
+        (BUTTON_Y, evdev::Key::BTN_WEST),
+        (BUTTON_LEFT_SHOULDER, evdev::Key::BTN_TL),
+        (BUTTON_RIGHT_SHOULDER, evdev::Key::BTN_TR),
+        (BUTTON_BACK, evdev::Key::BTN_SELECT),
+        (BUTTON_START, evdev::Key::BTN_START),
+        (BUTTON_GUIDE, evdev::Key::BTN_MODE),
+        (BUTTON_LEFT_THUMB, evdev::Key::BTN_THUMBL),
+        (BUTTON_RIGHT_THUMB, evdev::Key::BTN_THUMBR),
+    ];
+
+    fn new() -> ResultType<Self> {
+        use evdev::{AbsInfo, AbsoluteAxisType, AttributeSet, BusType, InputId, UinputAbsSetup};
+        let mut keys = AttributeSet::<evdev::Key>::new();
+        for (_, key) in Self::BUTTONS.iter() {
+            keys.insert(*key);
+        }
+        let thumb = AbsInfo::new(0, i16::MIN as _, i16::MAX as _, 16, 128, 0);
+        let trigger = AbsInfo::new(0, 0, u8::MAX as _, 0, 0, 0);
+        let hat = AbsInfo::new(0, -1, 1, 0, 0, 0);
+        // The ids of the Xbox 360 controller, for the games to know the layout.
+        let device = evdev::uinput::VirtualDeviceBuilder::new()?
+            .name("RustDesk Virtual Gamepad")
+            .input_id(InputId::new(BusType::BUS_USB, 0x045e, 0x028e, 0x0110))
+            .with_keys(&keys)?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_X, thumb))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, thumb))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_RX, thumb))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_RY, thumb))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Z, trigger))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_RZ, trigger))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_HAT0X, hat))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_HAT0Y, hat))?
+            .build()?;
+        Ok(Self {
+            device,
+            last: Default::default(),
+        })
+    }
+
+    fn update(&mut self, state: &GamepadState) -> ResultType<()> {
+        use evdev::{AbsoluteAxisType, EventType, InputEvent};
+        let mut events = vec![];
+        for (bit, key) in Self::BUTTONS.iter() {
+            let pressed = state.buttons & bit != 0;
+            if pressed != (self.last.buttons & bit != 0) {
+                events.push(InputEvent::new(EventType::KEY, key.code(), pressed as _));
+            }
+        }
+        let hat = |negative: u16, positive: u16| {
+            (state.buttons & positive != 0) as i32 - (state.buttons & negative != 0) as i32
+        };
+        // The y axes of evdev are positive down.
+        let axes = [
+            (AbsoluteAxisType::ABS_X, state.thumb_lx as i32),
+            (AbsoluteAxisType::ABS_Y, -(state.thumb_ly as i32)),
+            (AbsoluteAxisType::ABS_RX, state.thumb_rx as i32),
+            (AbsoluteAxisType::ABS_RY, -(state.thumb_ry as i32)),
+            (AbsoluteAxisType::ABS_Z, state.left_trigger as i32),
+            (AbsoluteAxisType::ABS_RZ, state.right_trigger as i32),
+            (
+                AbsoluteAxisType::ABS_HAT0X,
+                hat(BUTTON_DPAD_LEFT, BUTTON_DPAD_RIGHT),
+            ),
+            (
+                AbsoluteAxisType::ABS_HAT0Y,
+                hat(BUTTON_DPAD_UP, BUTTON_DPAD_DOWN),
+            ),
+        ];
+        for (axis, value) in axes {
+            events.push(InputEvent::new(EventType::ABSOLUTE, axis.0, value));
+        }
+        self.device.emit(&events)?;
+        self.last = *state;
+        Ok(())
+    }
+}
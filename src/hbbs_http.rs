@@ -10,6 +10,7 @@ pub mod sync;
 pub mod downloader;
 pub use http_client::create_http_client;
 pub use http_client::create_http_client_async;
+pub use http_client::create_http_client_async_with_tls_info;
 
 #[derive(Debug)]
 pub enum HbbHttpResponse<T> {
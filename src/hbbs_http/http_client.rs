@@ -65,11 +65,24 @@ macro_rules! configure_http_client {
 }
 
 pub fn create_http_client() -> SyncClient {
-    let builder = SyncClient::builder();
+    let mut builder = SyncClient::builder();
+    if let Some(tls) = crate::tls_pin::client_config() {
+        builder = builder.use_preconfigured_tls(tls);
+    }
     configure_http_client!(builder, SyncClient)
 }
 
 pub fn create_http_client_async() -> AsyncClient {
-    let builder = AsyncClient::builder();
+    let mut builder = AsyncClient::builder();
+    if let Some(tls) = crate::tls_pin::client_config() {
+        builder = builder.use_preconfigured_tls(tls);
+    }
+    configure_http_client!(builder, AsyncClient)
+}
+
+/// A client which exposes the certificate of the server, to check it against the pins, so it
+/// does not check them itself.
+pub fn create_http_client_async_with_tls_info() -> AsyncClient {
+    let builder = AsyncClient::builder().tls_info(true);
     configure_http_client!(builder, AsyncClient)
 }
@@ -132,6 +132,9 @@ async fn start_hbbs_sync_async() {
                     v["version"] = json!(crate::VERSION);
                     v["id"] = json!(id);
                     v["uuid"] = json!(crate::encode64(hbb_common::get_uuid()));
+                    if let Some(patch_level) = crate::patch_level::get_if_allowed() {
+                        v[crate::patch_level::KEY] = patch_level;
+                    }
                     let ab_name = Config::get_option(keys::OPTION_PRESET_ADDRESS_BOOK_NAME);
                     if !ab_name.is_empty() {
                         v[keys::OPTION_PRESET_ADDRESS_BOOK_NAME] = json!(ab_name);
@@ -266,6 +269,12 @@ async fn start_hbbs_sync_async() {
                                 handle_config_options(strategy.config_options);
                             }
                         }
+                        #[cfg(not(target_os = "android"))]
+                        if let Some(policy) = rsp.remove("policy") {
+                            if let Some(policy) = policy.as_str() {
+                                crate::policy::update(policy);
+                            }
+                        }
                     }
                 }
             }
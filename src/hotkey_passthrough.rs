@@ -0,0 +1,387 @@
+//! Passes the shortcuts the local system takes, e.g. Alt+Tab, the Win key or Ctrl+W, to the
+//! peer while a session is focused, with the input source of flutter.
+//!
+//! The shortcuts are one per line in the local option [`OPTION_HOTKEYS`], a combination like
+//! `Alt+Tab` or a single key like `Win`, the defaults of the platform if it is empty.
+//! The low-level keyboard hook of the grab loop swallows a key completing a shortcut, with
+//! exactly the modifiers of the shortcut down, and sends it as the grab of rdev does.
+//! The other keys, the modifiers included, go to the window as usual.
+//!
+//! X11 has no such hook and the window manager grabs its shortcuts before any window, so the
+//! whole keyboard is grabbed instead while the session is focused. Wayland is not supported.
+
+#[cfg(feature = "flutter")]
+use crate::common::GrabState;
+#[cfg(feature = "flutter")]
+use hbb_common::config::LocalConfig;
+use rdev::Key;
+use std::{collections::HashSet, sync::Mutex};
+
+pub const OPTION_ALLOW: &str = "allow-hotkey-passthrough";
+pub const OPTION_HOTKEYS: &str = "passthrough-hotkeys";
+
+#[cfg(target_os = "windows")]
+pub const DEFAULT_HOTKEYS: &[&str] = &[
+    "Alt+Tab",
+    "Alt+Shift+Tab",
+    "Alt+Esc",
+    "Alt+F4",
+    "Ctrl+Esc",
+    "Win",
+    "Ctrl+W",
+];
+#[cfg(target_os = "macos")]
+pub const DEFAULT_HOTKEYS: &[&str] = &[
+    "Cmd+Tab",
+    "Cmd+Shift+Tab",
+    "Cmd+Q",
+    "Cmd+W",
+    "Cmd+H",
+    "Cmd+M",
+    "Cmd+Space",
+    "Ctrl+Up",
+    "Ctrl+Down",
+    "Ctrl+Left",
+    "Ctrl+Right",
+];
+#[cfg(target_os = "linux")]
+pub const DEFAULT_HOTKEYS: &[&str] = &["Alt+Tab", "Alt+Shift+Tab", "Alt+F4", "Super", "Ctrl+W"];
+
+const LETTERS: [Key; 26] = [
+    Key::KeyA,
+    Key::KeyB,
+    Key::KeyC,
+    Key::KeyD,
+    Key::KeyE,
+    Key::KeyF,
+    Key::KeyG,
+    Key::KeyH,
+    Key::KeyI,
+    Key::KeyJ,
+    Key::KeyK,
+    Key::KeyL,
+    Key::KeyM,
+    Key::KeyN,
+    Key::KeyO,
+    Key::KeyP,
+    Key::KeyQ,
+    Key::KeyR,
+    Key::KeyS,
+    Key::KeyT,
+    Key::KeyU,
+    Key::KeyV,
+    Key::KeyW,
+    Key::KeyX,
+    Key::KeyY,
+    Key::KeyZ,
+];
+const DIGITS: [Key; 10] = [
+    Key::Num0,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+];
+const FUNCTION_KEYS: [Key; 12] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Modifiers {
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    meta: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Hotkey {
+    key: Key,
+    modifiers: Modifiers,
+}
+
+impl Hotkey {
+    fn parse(s: &str) -> Option<Self> {
+        let combo = crate::key_remap::parse_combo(s)?;
+        Some(Self {
+            key: parse_key(&combo.key)?,
+            modifiers: Modifiers {
+                ctrl: combo.ctrl,
+                alt: combo.alt,
+                shift: combo.shift,
+                meta: combo.command,
+            },
+        })
+    }
+}
+
+// The left and right modifiers are the same in the shortcuts.
+fn normalize(key: Key) -> Key {
+    match key {
+        Key::ControlRight => Key::ControlLeft,
+        Key::ShiftRight => Key::ShiftLeft,
+        Key::MetaRight => Key::MetaLeft,
+        Key::AltGr => Key::Alt,
+        _ => key,
+    }
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    let name = name.to_lowercase();
+    let key = match name.as_str() {
+        "ctrl" | "control" => Key::ControlLeft,
+        "alt" | "option" => Key::Alt,
+        "shift" => Key::ShiftLeft,
+        "meta" | "cmd" | "command" | "win" | "super" => Key::MetaLeft,
+        "tab" => Key::Tab,
+        "esc" | "escape" => Key::Escape,
+        "space" => Key::Space,
+        "enter" | "return" => Key::Return,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "insert" | "ins" => Key::Insert,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" | "pgup" => Key::PageUp,
+        "pagedown" | "pgdn" => Key::PageDown,
+        "up" => Key::UpArrow,
+        "down" => Key::DownArrow,
+        "left" => Key::LeftArrow,
+        "right" => Key::RightArrow,
+        "printscreen" | "prtsc" => Key::PrintScreen,
+        _ => {
+            if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<usize>().ok()) {
+                return FUNCTION_KEYS.get(n.checked_sub(1)?).copied();
+            }
+            let mut chars = name.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return None;
+            };
+            return match c {
+                'a'..='z' => Some(LETTERS[c as usize - 'a' as usize]),
+                '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+                _ => None,
+            };
+        }
+    };
+    Some(key)
+}
+
+#[derive(Default)]
+struct Filter {
+    active: bool,
+    hotkeys: Vec<Hotkey>,
+    // The modifiers down, normalized.
+    pressed: HashSet<Key>,
+    // The keys down which were swallowed, their releases are swallowed too.
+    swallowed: HashSet<Key>,
+}
+
+impl Filter {
+    fn set_hotkeys(&mut self, lines: &str) {
+        self.hotkeys = if lines.trim().is_empty() {
+            DEFAULT_HOTKEYS
+                .iter()
+                .filter_map(|s| Hotkey::parse(s))
+                .collect()
+        } else {
+            lines.lines().filter_map(Hotkey::parse).collect()
+        };
+    }
+
+    fn modifiers(&self) -> Modifiers {
+        Modifiers {
+            ctrl: self.pressed.contains(&Key::ControlLeft),
+            alt: self.pressed.contains(&Key::Alt),
+            shift: self.pressed.contains(&Key::ShiftLeft),
+            meta: self.pressed.contains(&Key::MetaLeft),
+        }
+    }
+
+    // Returns whether the key is swallowed.
+    fn on_key(&mut self, key: Key, down: bool) -> bool {
+        let normalized = normalize(key);
+        let is_modifier = matches!(
+            normalized,
+            Key::ControlLeft | Key::Alt | Key::ShiftLeft | Key::MetaLeft
+        );
+        if !down {
+            if is_modifier {
+                self.pressed.remove(&normalized);
+            }
+            return self.swallowed.remove(&key);
+        }
+        if self.swallowed.contains(&key) {
+            // Repeated.
+            return true;
+        }
+        let modifiers = self.modifiers();
+        let matched = self.active
+            && self
+                .hotkeys
+                .iter()
+                .any(|h| h.key == normalized && h.modifiers == modifiers);
+        if is_modifier {
+            self.pressed.insert(normalized);
+        }
+        if matched {
+            self.swallowed.insert(key);
+        }
+        matched
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref FILTER: Mutex<Filter> = Default::default();
+}
+
+/// The hotkeys saved, or the defaults, one per line.
+#[cfg(feature = "flutter")]
+pub fn get_hotkeys() -> String {
+    let hotkeys = LocalConfig::get_option(OPTION_HOTKEYS);
+    if hotkeys.trim().is_empty() {
+        DEFAULT_HOTKEYS.join("\n")
+    } else {
+        hotkeys
+    }
+}
+
+/// The lines which are not a shortcut, to show in the settings.
+#[cfg(feature = "flutter")]
+pub fn invalid_lines(lines: &str) -> Vec<String> {
+    lines
+        .lines()
+        .filter(|l| !l.trim().is_empty() && Hotkey::parse(l).is_none())
+        .map(|l| l.to_owned())
+        .collect()
+}
+
+/// Called for every key of the hook, returns whether the key is swallowed and sent to
+/// the peer.
+pub fn on_key(key: Key, down: bool) -> bool {
+    FILTER.lock().unwrap().on_key(key, down)
+}
+
+/// The session gains or loses the focus, with the input source of flutter.
+#[cfg(feature = "flutter")]
+pub fn change_grab_status(state: &GrabState, keyboard_mode: &str) {
+    match state {
+        GrabState::Run => {
+            if LocalConfig::get_option(OPTION_ALLOW) != "Y" {
+                return;
+            }
+            let mut filter = FILTER.lock().unwrap();
+            filter.set_hotkeys(&LocalConfig::get_option(OPTION_HOTKEYS));
+            filter.active = !filter.hotkeys.is_empty();
+            #[cfg(target_os = "linux")]
+            if filter.active && crate::platform::linux::is_x11() {
+                rdev::enable_grab();
+            }
+        }
+        GrabState::Wait => {
+            let was_active = std::mem::replace(&mut FILTER.lock().unwrap().active, false);
+            if was_active {
+                crate::keyboard::release_remote_keys(keyboard_mode);
+                #[cfg(target_os = "linux")]
+                if crate::platform::linux::is_x11() {
+                    rdev::disable_grab();
+                }
+            }
+        }
+        GrabState::Ready | GrabState::Exit => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(lines: &str) -> Filter {
+        let mut filter = Filter::default();
+        filter.set_hotkeys(lines);
+        filter.active = true;
+        filter
+    }
+
+    #[test]
+    fn test_parse() {
+        let hotkey = Hotkey::parse("Alt+Shift+Tab").unwrap();
+        assert_eq!(hotkey.key, Key::Tab);
+        assert!(hotkey.modifiers.alt && hotkey.modifiers.shift);
+        assert_eq!(Hotkey::parse("win").unwrap().key, Key::MetaLeft);
+        assert_eq!(Hotkey::parse("Ctrl+F12").unwrap().key, Key::F12);
+        assert_eq!(Hotkey::parse("Cmd+1").unwrap().key, Key::Num1);
+        assert!(Hotkey::parse("Ctrl+F13").is_none());
+        assert!(Hotkey::parse("Hyper+Tab").is_none());
+        assert!(Hotkey::parse("Ctrl+").is_none());
+        for s in DEFAULT_HOTKEYS {
+            assert!(Hotkey::parse(s).is_some(), "{}", s);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "flutter")]
+    fn test_invalid_lines() {
+        assert_eq!(invalid_lines("Alt+Tab\n\nCtrl+Foo"), vec!["Ctrl+Foo"]);
+        assert!(invalid_lines(&DEFAULT_HOTKEYS.join("\n")).is_empty());
+    }
+
+    #[test]
+    fn test_combo() {
+        let mut filter = filter("Alt+Tab\nCtrl+W");
+        assert!(!filter.on_key(Key::Alt, true));
+        assert!(filter.on_key(Key::Tab, true));
+        assert!(filter.on_key(Key::Tab, true));
+        assert!(filter.on_key(Key::Tab, false));
+        assert!(!filter.on_key(Key::Alt, false));
+        // Not the exact modifiers.
+        assert!(!filter.on_key(Key::Tab, true));
+        assert!(!filter.on_key(Key::Tab, false));
+        assert!(!filter.on_key(Key::ControlRight, true));
+        assert!(!filter.on_key(Key::ShiftLeft, true));
+        assert!(!filter.on_key(Key::KeyW, true));
+        assert!(!filter.on_key(Key::KeyW, false));
+        assert!(!filter.on_key(Key::ShiftLeft, false));
+        assert!(filter.on_key(Key::KeyW, true));
+        // The release is swallowed even if the modifier is up first.
+        assert!(!filter.on_key(Key::ControlRight, false));
+        assert!(filter.on_key(Key::KeyW, false));
+    }
+
+    #[test]
+    fn test_single_key() {
+        let mut filter = filter("Win");
+        assert!(filter.on_key(Key::MetaRight, true));
+        assert!(!filter.on_key(Key::KeyR, true));
+        assert!(!filter.on_key(Key::KeyR, false));
+        assert!(filter.on_key(Key::MetaRight, false));
+        assert!(filter.pressed.is_empty());
+    }
+
+    #[test]
+    fn test_inactive() {
+        let mut filter = filter("Alt+Tab");
+        filter.active = false;
+        assert!(!filter.on_key(Key::Alt, true));
+        assert!(!filter.on_key(Key::Tab, true));
+        // The modifiers are tracked, the shortcut works once active.
+        filter.active = true;
+        assert!(filter.on_key(Key::Tab, true));
+    }
+}
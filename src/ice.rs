@@ -0,0 +1,239 @@
+//! ICE-style candidates of a connection attempt.
+//!
+//! The rendezvous server already tells both sides the host (intranet) and the
+//! server-reflexive (hole punched) addresses of each other. A relay candidate is added
+//! if a standard TURN server is configured: the controlled side allocates a relayed
+//! address on it and sends it back in the slot of its IPv6 candidate, see `turn`.
+//!
+//! The slot is shared because the rendezvous messages have no field for a relay candidate,
+//! it is only used if the controlled side has no IPv6 candidate to send, and the relayed
+//! address is told apart by being IPv4. A `socket_addr_relay` field in `PunchHoleSent`,
+//! `LocalAddr` and `RelayResponse` of `rendezvous.proto` in hbb_common, relayed by hbbs,
+//! would remove both limits, and then the candidates could be sent together.
+//!
+//! All the connectivity checks run concurrently, like before, and the candidate with the
+//! highest priority which succeeds is selected: once a check succeeds, the checks of the
+//! candidates with a higher priority get a little more time. The relay checks also start a
+//! bit later, so that a direct path wins over a relayed one if both work.
+
+use hbb_common::{
+    anyhow::anyhow,
+    futures::{stream::FuturesUnordered, Future, StreamExt},
+    log,
+    tokio::{
+        self,
+        time::{Duration, Instant},
+    },
+    ResultType,
+};
+use std::net::SocketAddr;
+
+pub mod turn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CandidateType {
+    Relay,
+    ServerReflexive,
+    Host,
+}
+
+impl CandidateType {
+    // RFC 8445 5.1.2.2, recommended type preferences.
+    fn type_preference(&self) -> u32 {
+        match self {
+            CandidateType::Host => 126,
+            CandidateType::ServerReflexive => 100,
+            CandidateType::Relay => 0,
+        }
+    }
+
+    /// Delay before the connectivity check of the candidate starts.
+    pub fn check_delay(&self) -> Duration {
+        match self {
+            CandidateType::Host | CandidateType::ServerReflexive => Duration::ZERO,
+            CandidateType::Relay => Duration::from_millis(RELAY_CHECK_DELAY_MS),
+        }
+    }
+}
+
+// Direct paths usually finish hole punching well below this.
+const RELAY_CHECK_DELAY_MS: u64 = 300;
+// How long the checks of the better candidates may still run after a check succeeded.
+const NOMINATION_WAIT: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub typ: CandidateType,
+    pub addr: SocketAddr,
+    pub transport: &'static str,
+}
+
+impl Candidate {
+    pub fn new(typ: CandidateType, addr: SocketAddr, transport: &'static str) -> Self {
+        Self {
+            typ,
+            addr,
+            transport,
+        }
+    }
+
+    /// RFC 8445 5.1.2.1, with IPv6 preferred over IPv4 and UDP over TCP.
+    pub fn priority(&self) -> u32 {
+        let mut local_preference = 0u32;
+        if self.addr.is_ipv6() {
+            local_preference += 2;
+        }
+        if self.transport != "TCP" {
+            local_preference += 1;
+        }
+        (self.typ.type_preference() << 24) + (local_preference << 8) + (256 - 1)
+    }
+}
+
+impl std::fmt::Display for Candidate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} {} {} priority {}",
+            self.typ,
+            self.transport,
+            self.addr,
+            self.priority()
+        )
+    }
+}
+
+/// Sort the candidates by priority, the highest first.
+pub fn sort_candidates(candidates: &mut Vec<Candidate>) {
+    candidates.sort_by(|a, b| b.priority().cmp(&a.priority()));
+}
+
+/// Run the connectivity checks of the candidates concurrently, and return the result of
+/// the candidate with the highest priority which succeeds, or the last error.
+pub async fn select<T, F>(checks: Vec<(Candidate, F)>) -> ResultType<T>
+where
+    F: Future<Output = ResultType<T>>,
+{
+    let mut candidates: Vec<_> = checks.iter().map(|(c, _)| c.clone()).collect();
+    sort_candidates(&mut candidates);
+    for c in candidates.iter() {
+        log::debug!("candidate: {}", c);
+    }
+    let mut pending: Vec<u32> = candidates.iter().map(|c| c.priority()).collect();
+    let mut checks: FuturesUnordered<_> = checks
+        .into_iter()
+        .map(|(c, f)| async move { (c, f.await) })
+        .collect();
+    let mut best: Option<(Candidate, T)> = None;
+    let mut last_err = None;
+    let mut deadline = None;
+    loop {
+        let next = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, checks.next()).await {
+                Ok(next) => next,
+                Err(_) => break,
+            },
+            None => checks.next().await,
+        };
+        let Some((c, res)) = next else {
+            break;
+        };
+        let priority = c.priority();
+        if let Some(i) = pending.iter().position(|p| *p == priority) {
+            pending.remove(i);
+        }
+        match res {
+            Ok(v) => {
+                log::debug!("check of candidate {} succeeded", c);
+                if best.as_ref().map_or(true, |(b, _)| priority > b.priority()) {
+                    best = Some((c, v));
+                }
+                deadline.get_or_insert(Instant::now() + NOMINATION_WAIT);
+            }
+            Err(err) => {
+                log::debug!("check of candidate {} failed: {}", c, err);
+                last_err = Some(err);
+            }
+        }
+        if let Some((b, _)) = best.as_ref() {
+            if pending.iter().all(|p| *p <= b.priority()) {
+                break;
+            }
+        }
+    }
+    match best {
+        Some((c, v)) => {
+            log::info!("selected candidate: {}", c);
+            Ok(v)
+        }
+        None => Err(last_err.unwrap_or_else(|| anyhow!("No candidate to check"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_priority() {
+        let v4: SocketAddr = "1.2.3.4:5".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:5".parse().unwrap();
+        let mut candidates = vec![
+            Candidate::new(CandidateType::Relay, v4, "UDP"),
+            Candidate::new(CandidateType::ServerReflexive, v4, "TCP"),
+            Candidate::new(CandidateType::ServerReflexive, v4, "UDP"),
+            Candidate::new(CandidateType::ServerReflexive, v6, "UDP"),
+            Candidate::new(CandidateType::Host, v4, "TCP"),
+        ];
+        sort_candidates(&mut candidates);
+        let order: Vec<_> = candidates
+            .iter()
+            .map(|c| (c.typ, c.addr.is_ipv6(), c.transport))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (CandidateType::Host, false, "TCP"),
+                (CandidateType::ServerReflexive, true, "UDP"),
+                (CandidateType::ServerReflexive, false, "UDP"),
+                (CandidateType::ServerReflexive, false, "TCP"),
+                (CandidateType::Relay, false, "UDP"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_select() {
+        let v4: SocketAddr = "1.2.3.4:5".parse().unwrap();
+        let check = |typ, ms, ok: bool| {
+            (Candidate::new(typ, v4, "UDP"), async move {
+                tokio::time::sleep(Duration::from_millis(ms)).await;
+                if ok {
+                    Ok(typ)
+                } else {
+                    Err(anyhow!("failed"))
+                }
+            })
+        };
+        // The relay succeeds first, the direct path a bit later wins.
+        let res = select(vec![
+            check(CandidateType::Relay, 10, true),
+            check(CandidateType::ServerReflexive, 100, true),
+        ])
+        .await;
+        assert_eq!(res.unwrap(), CandidateType::ServerReflexive);
+        // The direct path which takes too long does not.
+        let res = select(vec![
+            check(CandidateType::Relay, 10, true),
+            check(CandidateType::ServerReflexive, 1000, true),
+        ])
+        .await;
+        assert_eq!(res.unwrap(), CandidateType::Relay);
+        let res = select(vec![
+            check(CandidateType::Relay, 10, false),
+            check(CandidateType::Host, 20, false),
+        ])
+        .await;
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,504 @@
+//! Minimal TURN client (RFC 8656), UDP transport with long-term credentials.
+//!
+//! Only what the relay candidate needs: Allocate, Refresh and ChannelBind for one peer.
+//! The allocation is hidden behind a local UDP socket pair, the datagrams of the local
+//! socket are relayed to the peer as ChannelData, so that the hole punching and the
+//! KCP code work on it unchanged.
+
+use hbb_common::{
+    allow_err,
+    anyhow::anyhow,
+    bail,
+    bytes::{BufMut, BytesMut},
+    config::Config,
+    log, rand,
+    tokio::{
+        self,
+        net::UdpSocket,
+        time::{interval, Duration, Instant},
+    },
+    ResultType,
+};
+use hmac::{Hmac, Mac};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Weak},
+};
+
+/// Option, `host:port` of the TURN server, empty to disable the relay candidate.
+pub const OPTION_TURN_SERVER: &str = "turn-server";
+pub const OPTION_TURN_USERNAME: &str = "turn-username";
+pub const OPTION_TURN_PASSWORD: &str = "turn-password";
+
+const DEFAULT_PORT: i32 = 3478;
+const MAGIC_COOKIE: u32 = 0x2112A442;
+const HEADER_LEN: usize = 20;
+
+const METHOD_ALLOCATE: u16 = 0x0003;
+const METHOD_REFRESH: u16 = 0x0004;
+const METHOD_CHANNEL_BIND: u16 = 0x0009;
+const CLASS_MASK: u16 = 0x0110;
+const CLASS_SUCCESS: u16 = 0x0100;
+const CLASS_ERROR: u16 = 0x0110;
+
+const ATTR_USERNAME: u16 = 0x0006;
+const ATTR_MESSAGE_INTEGRITY: u16 = 0x0008;
+const ATTR_ERROR_CODE: u16 = 0x0009;
+const ATTR_CHANNEL_NUMBER: u16 = 0x000C;
+const ATTR_LIFETIME: u16 = 0x000D;
+const ATTR_XOR_PEER_ADDRESS: u16 = 0x0012;
+const ATTR_REALM: u16 = 0x0014;
+const ATTR_NONCE: u16 = 0x0015;
+const ATTR_XOR_RELAYED_ADDRESS: u16 = 0x0016;
+const ATTR_REQUESTED_TRANSPORT: u16 = 0x0019;
+
+const ERROR_UNAUTHORIZED: u16 = 401;
+const ERROR_STALE_NONCE: u16 = 438;
+
+const PROTOCOL_UDP: u8 = 17;
+const LIFETIME_SECS: u32 = 600;
+// Permissions expire after 300s, a ChannelBind refreshes both the channel and the permission.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(240);
+const CHANNEL: u16 = 0x4000;
+const RETRANSMIT_MS: [u64; 3] = [500, 1000, 2000];
+
+pub fn get_turn_server() -> Option<(String, String, String)> {
+    let server = Config::get_option(OPTION_TURN_SERVER);
+    if server.is_empty() {
+        return None;
+    }
+    Some((
+        crate::check_port(server, DEFAULT_PORT),
+        Config::get_option(OPTION_TURN_USERNAME),
+        Config::get_option(OPTION_TURN_PASSWORD),
+    ))
+}
+
+#[inline]
+pub fn is_configured() -> bool {
+    get_turn_server().is_some()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    typ: u16,
+    tid: [u8; 12],
+    attrs: Vec<(u16, Vec<u8>)>,
+}
+
+impl Message {
+    fn new(typ: u16) -> Self {
+        Self {
+            typ,
+            tid: rand::random(),
+            attrs: Vec::new(),
+        }
+    }
+
+    fn attr(&self, typ: u16) -> Option<&[u8]> {
+        self.attrs
+            .iter()
+            .find(|(t, _)| *t == typ)
+            .map(|(_, v)| &v[..])
+    }
+
+    fn error_code(&self) -> Option<u16> {
+        let v = self.attr(ATTR_ERROR_CODE)?;
+        if v.len() < 4 {
+            return None;
+        }
+        Some((v[2] & 0x7) as u16 * 100 + v[3] as u16)
+    }
+
+    fn encode(&self, key: Option<&[u8]>) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        buf.put_u16(self.typ);
+        buf.put_u16(0);
+        buf.put_u32(MAGIC_COOKIE);
+        buf.put_slice(&self.tid);
+        for (typ, value) in self.attrs.iter() {
+            buf.put_u16(*typ);
+            buf.put_u16(value.len() as _);
+            buf.put_slice(value);
+            buf.put_bytes(0, (4 - value.len() % 4) % 4);
+        }
+        if let Some(key) = key {
+            // The length covers the MESSAGE-INTEGRITY attribute itself when computing it.
+            let len = (buf.len() - HEADER_LEN + 24) as u16;
+            buf[2..4].copy_from_slice(&len.to_be_bytes());
+            let mut mac = Hmac::<sha1::Sha1>::new_from_slice(key).expect("any key length");
+            mac.update(&buf);
+            buf.put_u16(ATTR_MESSAGE_INTEGRITY);
+            buf.put_u16(20);
+            buf.put_slice(&mac.finalize().into_bytes());
+        }
+        let len = (buf.len() - HEADER_LEN) as u16;
+        buf[2..4].copy_from_slice(&len.to_be_bytes());
+        buf.to_vec()
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < HEADER_LEN
+            || buf[0] & 0xC0 != 0
+            || u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) != MAGIC_COOKIE
+        {
+            return None;
+        }
+        let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        if buf.len() < HEADER_LEN + len {
+            return None;
+        }
+        let mut tid = [0u8; 12];
+        tid.copy_from_slice(&buf[8..HEADER_LEN]);
+        let mut attrs = Vec::new();
+        let mut pos = HEADER_LEN;
+        while pos + 4 <= HEADER_LEN + len {
+            let typ = u16::from_be_bytes([buf[pos], buf[pos + 1]]);
+            let n = u16::from_be_bytes([buf[pos + 2], buf[pos + 3]]) as usize;
+            pos += 4;
+            if pos + n > buf.len() {
+                return None;
+            }
+            attrs.push((typ, buf[pos..pos + n].to_vec()));
+            pos += (n + 3) / 4 * 4;
+        }
+        Some(Self {
+            typ: u16::from_be_bytes([buf[0], buf[1]]),
+            tid,
+            attrs,
+        })
+    }
+}
+
+fn encode_xor_addr(addr: SocketAddr, tid: &[u8; 12]) -> Vec<u8> {
+    let mut buf = vec![0u8];
+    let port = addr.port() ^ (MAGIC_COOKIE >> 16) as u16;
+    let mut mask = MAGIC_COOKIE.to_be_bytes().to_vec();
+    mask.extend_from_slice(tid);
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            buf.push(1);
+            buf.extend_from_slice(&port.to_be_bytes());
+            buf.extend(ip.octets().iter().zip(mask.iter()).map(|(a, b)| a ^ b));
+        }
+        IpAddr::V6(ip) => {
+            buf.push(2);
+            buf.extend_from_slice(&port.to_be_bytes());
+            buf.extend(ip.octets().iter().zip(mask.iter()).map(|(a, b)| a ^ b));
+        }
+    }
+    buf
+}
+
+fn decode_xor_addr(v: &[u8], tid: &[u8; 12]) -> Option<SocketAddr> {
+    if v.len() < 8 {
+        return None;
+    }
+    let port = u16::from_be_bytes([v[2], v[3]]) ^ (MAGIC_COOKIE >> 16) as u16;
+    let mut mask = MAGIC_COOKIE.to_be_bytes().to_vec();
+    mask.extend_from_slice(tid);
+    let ip: IpAddr = match v[1] {
+        1 => {
+            let mut octets = [0u8; 4];
+            for (o, (a, b)) in octets.iter_mut().zip(v[4..].iter().zip(mask.iter())) {
+                *o = a ^ b;
+            }
+            Ipv4Addr::from(octets).into()
+        }
+        2 if v.len() >= 20 => {
+            let mut octets = [0u8; 16];
+            for (o, (a, b)) in octets.iter_mut().zip(v[4..].iter().zip(mask.iter())) {
+                *o = a ^ b;
+            }
+            Ipv6Addr::from(octets).into()
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+fn encode_channel_data(channel: u16, data: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(4 + data.len());
+    buf.extend_from_slice(&channel.to_be_bytes());
+    buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    buf.extend_from_slice(data);
+    buf
+}
+
+fn decode_channel_data(buf: &[u8]) -> Option<(u16, &[u8])> {
+    if buf.len() < 4 || buf[0] & 0xC0 != 0x40 {
+        return None;
+    }
+    let channel = u16::from_be_bytes([buf[0], buf[1]]);
+    let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+    if buf.len() < 4 + len {
+        return None;
+    }
+    Some((channel, &buf[4..4 + len]))
+}
+
+struct Credentials {
+    username: String,
+    password: String,
+    realm: Vec<u8>,
+    nonce: Vec<u8>,
+}
+
+impl Credentials {
+    // key = MD5(username ":" realm ":" password)
+    fn key(&self) -> Option<Vec<u8>> {
+        if self.realm.is_empty() {
+            return None;
+        }
+        use md5::Digest;
+        let mut md5 = md5::Md5::new();
+        md5.update(self.username.as_bytes());
+        md5.update(b":");
+        md5.update(&self.realm);
+        md5.update(b":");
+        md5.update(self.password.as_bytes());
+        Some(md5.finalize().to_vec())
+    }
+
+    fn sign(&self, mut msg: Message) -> Vec<u8> {
+        let key = self.key();
+        if key.is_some() {
+            msg.attrs
+                .push((ATTR_USERNAME, self.username.as_bytes().to_vec()));
+            msg.attrs.push((ATTR_REALM, self.realm.clone()));
+            msg.attrs.push((ATTR_NONCE, self.nonce.clone()));
+        }
+        msg.encode(key.as_deref())
+    }
+
+    // Take the realm and nonce of a 401 or 438 error, returns whether to retry.
+    fn update(&mut self, resp: &Message) -> bool {
+        let code = resp.error_code();
+        if code != Some(ERROR_UNAUTHORIZED) && code != Some(ERROR_STALE_NONCE) {
+            return false;
+        }
+        let Some(nonce) = resp.attr(ATTR_NONCE) else {
+            return false;
+        };
+        if code == Some(ERROR_UNAUTHORIZED) && !self.realm.is_empty() && self.nonce == nonce {
+            // wrong credentials
+            return false;
+        }
+        if let Some(realm) = resp.attr(ATTR_REALM) {
+            self.realm = realm.to_vec();
+        }
+        self.nonce = nonce.to_vec();
+        true
+    }
+}
+
+struct Allocation {
+    socket: Arc<UdpSocket>,
+    credentials: Credentials,
+    relayed: SocketAddr,
+    peer: SocketAddr,
+}
+
+impl Allocation {
+    // Send a request and wait for its response, retransmitted on timeout, and retried
+    // once authenticated after the 401 challenge or a stale nonce. XOR-PEER-ADDRESS is
+    // filled with `peer` here, because it depends on the transaction id.
+    async fn transact(
+        socket: &UdpSocket,
+        credentials: &mut Credentials,
+        msg: Message,
+        peer: Option<SocketAddr>,
+    ) -> ResultType<Message> {
+        let mut buf = vec![0u8; 1500];
+        for _ in 0..3 {
+            let mut msg = msg.clone();
+            msg.tid = rand::random();
+            if let Some(peer) = peer {
+                msg.attrs
+                    .push((ATTR_XOR_PEER_ADDRESS, encode_xor_addr(peer, &msg.tid)));
+            }
+            let data = credentials.sign(msg.clone());
+            let mut resp = None;
+            'retransmit: for ms in RETRANSMIT_MS {
+                socket.send(&data).await?;
+                let deadline = Instant::now() + Duration::from_millis(ms);
+                while let Ok(res) = tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await {
+                    if let Some(m) = Message::decode(&buf[..res?]) {
+                        if m.tid == msg.tid {
+                            resp = Some(m);
+                            break 'retransmit;
+                        }
+                    }
+                }
+            }
+            let Some(resp) = resp else {
+                bail!("TURN request timeout");
+            };
+            match resp.typ & CLASS_MASK {
+                CLASS_SUCCESS => return Ok(resp),
+                CLASS_ERROR if credentials.update(&resp) => continue,
+                _ => bail!("TURN request failed, error code {:?}", resp.error_code()),
+            }
+        }
+        bail!("TURN authentication failed")
+    }
+
+    async fn allocate(peer: SocketAddr) -> ResultType<Self> {
+        let (server, username, password) =
+            get_turn_server().ok_or_else(|| anyhow!("No TURN server configured"))?;
+        let server_addr = tokio::net::lookup_host(&server)
+            .await?
+            .find(|x| x.is_ipv4() == peer.is_ipv4())
+            .ok_or_else(|| anyhow!("Failed to resolve TURN server {}", server))?;
+        let socket = UdpSocket::bind(Config::get_any_listen_addr(server_addr.is_ipv4())).await?;
+        socket.connect(server_addr).await?;
+        let mut credentials = Credentials {
+            username,
+            password,
+            realm: Vec::new(),
+            nonce: Vec::new(),
+        };
+        let mut msg = Message::new(METHOD_ALLOCATE);
+        msg.attrs
+            .push((ATTR_REQUESTED_TRANSPORT, vec![PROTOCOL_UDP, 0, 0, 0]));
+        msg.attrs
+            .push((ATTR_LIFETIME, LIFETIME_SECS.to_be_bytes().to_vec()));
+        let resp = Self::transact(&socket, &mut credentials, msg, None).await?;
+        let relayed = resp
+            .attr(ATTR_XOR_RELAYED_ADDRESS)
+            .and_then(|v| decode_xor_addr(v, &resp.tid))
+            .ok_or_else(|| anyhow!("No relayed address in TURN allocate response"))?;
+        let mut allocation = Self {
+            socket: Arc::new(socket),
+            credentials,
+            relayed,
+            peer,
+        };
+        allocation.bind_channel().await?;
+        Ok(allocation)
+    }
+
+    // Also installs the permission for the peer, which only cares about the IP.
+    async fn bind_channel(&mut self) -> ResultType<()> {
+        let mut msg = Message::new(METHOD_CHANNEL_BIND);
+        msg.attrs.push((
+            ATTR_CHANNEL_NUMBER,
+            [CHANNEL.to_be_bytes(), [0, 0]].concat(),
+        ));
+        Self::transact(&self.socket, &mut self.credentials, msg, Some(self.peer)).await?;
+        Ok(())
+    }
+
+    async fn refresh(&mut self, lifetime: u32) -> ResultType<()> {
+        let mut msg = Message::new(METHOD_REFRESH);
+        msg.attrs
+            .push((ATTR_LIFETIME, lifetime.to_be_bytes().to_vec()));
+        Self::transact(&self.socket, &mut self.credentials, msg, None).await?;
+        Ok(())
+    }
+}
+
+/// Allocate a relayed address for `peer` on the configured TURN server.
+///
+/// Returns the relayed address to give to the peer, a local socket and the address it
+/// must be connected to. The datagrams sent on the local socket come out of the relayed
+/// address, and the datagrams of the peer to the relayed address are received on it.
+/// The allocation is released once the local socket is dropped.
+pub async fn relay_for(peer: SocketAddr) -> ResultType<(SocketAddr, Arc<UdpSocket>, SocketAddr)> {
+    let mut allocation = Allocation::allocate(peer).await?;
+    let loopback: SocketAddr = if allocation.socket.local_addr()?.is_ipv4() {
+        (Ipv4Addr::LOCALHOST, 0).into()
+    } else {
+        (Ipv6Addr::LOCALHOST, 0).into()
+    };
+    let local = Arc::new(UdpSocket::bind(loopback).await?);
+    let proxy = UdpSocket::bind(loopback).await?;
+    proxy.connect(local.local_addr()?).await?;
+    let proxy_addr = proxy.local_addr()?;
+    let relayed = allocation.relayed;
+    log::info!("TURN relayed address {} allocated for {}", relayed, peer);
+    let weak = Arc::downgrade(&local);
+    tokio::spawn(async move {
+        allow_err!(pump(&mut allocation, proxy, weak).await);
+        allow_err!(allocation.refresh(0).await);
+        log::debug!("TURN allocation {} released", allocation.relayed);
+    });
+    Ok((relayed, local, proxy_addr))
+}
+
+async fn pump(
+    allocation: &mut Allocation,
+    proxy: UdpSocket,
+    local: Weak<UdpSocket>,
+) -> ResultType<()> {
+    let mut timer = interval(Duration::from_secs(1));
+    let mut last_refresh = Instant::now();
+    let mut buf = vec![0u8; 1500];
+    let mut buf2 = vec![0u8; 1500];
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {
+                if local.strong_count() == 0 {
+                    return Ok(());
+                }
+                if last_refresh.elapsed() >= REFRESH_INTERVAL {
+                    last_refresh = Instant::now();
+                    allocation.refresh(LIFETIME_SECS).await?;
+                    allocation.bind_channel().await?;
+                }
+            }
+            res = proxy.recv(&mut buf) => {
+                let n = res?;
+                allocation.socket.send(&encode_channel_data(CHANNEL, &buf[..n])).await?;
+            }
+            res = allocation.socket.recv(&mut buf2) => {
+                let n = res?;
+                if let Some((CHANNEL, data)) = decode_channel_data(&buf2[..n]) {
+                    proxy.send(data).await.ok();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let mut msg = Message::new(METHOD_ALLOCATE);
+        msg.attrs
+            .push((ATTR_REQUESTED_TRANSPORT, vec![PROTOCOL_UDP, 0, 0, 0]));
+        msg.attrs.push((ATTR_USERNAME, b"user1".to_vec()));
+        let peer: SocketAddr = "[2001:db8::1]:21118".parse().unwrap();
+        msg.attrs
+            .push((ATTR_XOR_PEER_ADDRESS, encode_xor_addr(peer, &msg.tid)));
+        let buf = msg.encode(Some(b"key"));
+        assert_eq!(buf.len() % 4, 0);
+        let decoded = Message::decode(&buf).unwrap();
+        assert_eq!(decoded.typ, msg.typ);
+        assert_eq!(decoded.attr(ATTR_USERNAME), Some(&b"user1"[..]));
+        assert_eq!(
+            decoded.attr(ATTR_MESSAGE_INTEGRITY).map(|x| x.len()),
+            Some(20)
+        );
+        assert_eq!(
+            decode_xor_addr(decoded.attr(ATTR_XOR_PEER_ADDRESS).unwrap(), &decoded.tid),
+            Some(peer)
+        );
+        let peer: SocketAddr = "1.2.3.4:5".parse().unwrap();
+        assert_eq!(
+            decode_xor_addr(&encode_xor_addr(peer, &msg.tid), &msg.tid),
+            Some(peer)
+        );
+    }
+
+    #[test]
+    fn test_channel_data() {
+        let buf = encode_channel_data(CHANNEL, b"kcp");
+        assert_eq!(decode_channel_data(&buf), Some((CHANNEL, &b"kcp"[..])));
+        // STUN messages start with two zero bits
+        assert!(decode_channel_data(&Message::new(METHOD_REFRESH).encode(None)).is_none());
+    }
+}
@@ -0,0 +1,438 @@
+//! Recording of the input events of a session to a script, and its replay against a peer,
+//! to automate the repetitive remote maintenance tasks.
+//!
+//! The script has the mouse and key messages sent to the peer, with their times from the
+//! start of the recording, and the sizes of the displays of the peer when recorded. The replay
+//! sends the messages at the same times, it refuses to start if the displays differ, and
+//! stops if it falls behind, or the display or the focus changes.
+//!
+//! The sessions of the client are driven by the ipc [`IPC_POSTFIX`], see `--input-script`.
+
+#[cfg(all(
+    feature = "flutter",
+    not(any(target_os = "android", target_os = "ios"))
+))]
+use hbb_common::log;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use hbb_common::tokio;
+use hbb_common::{
+    bail,
+    base64::{engine::general_purpose::STANDARD, Engine as _},
+    message_proto::*,
+    protobuf::Message as _,
+    ResultType,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+pub const IPC_POSTFIX: &str = "_input_script";
+pub const SCRIPT_VERSION: u32 = 1;
+/// The replay stops if it is late by more, e.g. the system was suspended.
+const MAX_LAG: Duration = Duration::from_millis(500);
+// The abort flag is checked at least this often while waiting.
+const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputScript {
+    pub version: u32,
+    /// The sizes of the displays of the peer when recorded.
+    #[serde(default)]
+    pub displays: Vec<(i32, i32)>,
+    #[serde(default)]
+    pub events: Vec<ScriptEvent>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptEvent {
+    /// Milliseconds from the start of the recording.
+    pub t: u64,
+    /// The mouse or key `Message` in protobuf, in base64.
+    pub msg: String,
+}
+
+pub fn display_sizes(pi: &PeerInfo) -> Vec<(i32, i32)> {
+    pi.displays.iter().map(|d| (d.width, d.height)).collect()
+}
+
+impl InputScript {
+    pub fn load(path: &str) -> ResultType<Self> {
+        let script: Self = serde_json::from_slice(&std::fs::read(path)?)?;
+        if script.version != SCRIPT_VERSION {
+            bail!("Unsupported script version {}", script.version);
+        }
+        Ok(script)
+    }
+
+    pub fn save(&self, path: &str) -> ResultType<()> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The same displays are required to replay, the positions are of the displays.
+    pub fn check_displays(&self, displays: &[(i32, i32)]) -> ResultType<()> {
+        if self.displays != displays {
+            bail!(
+                "The displays {:?} are not the recorded ones {:?}",
+                displays,
+                self.displays
+            );
+        }
+        Ok(())
+    }
+
+    fn messages(&self) -> ResultType<Vec<(Duration, Message)>> {
+        let mut out = Vec::with_capacity(self.events.len());
+        let mut last = 0;
+        for e in &self.events {
+            if e.t < last {
+                bail!("The events are not in order at {}ms", e.t);
+            }
+            last = e.t;
+            let msg = Message::parse_from_bytes(&STANDARD.decode(&e.msg)?)?;
+            if !is_input(&msg) {
+                bail!("Not an input event at {}ms", e.t);
+            }
+            out.push((Duration::from_millis(e.t), msg));
+        }
+        Ok(out)
+    }
+}
+
+fn is_input(msg: &Message) -> bool {
+    matches!(
+        msg.union,
+        Some(message::Union::MouseEvent(_)) | Some(message::Union::KeyEvent(_))
+    )
+}
+
+pub struct Recorder {
+    start: Instant,
+    script: InputScript,
+}
+
+impl Recorder {
+    pub fn new(displays: Vec<(i32, i32)>) -> Self {
+        Self {
+            start: Instant::now(),
+            script: InputScript {
+                version: SCRIPT_VERSION,
+                displays,
+                events: vec![],
+            },
+        }
+    }
+
+    /// Records the message if it is an input event.
+    pub fn record(&mut self, msg: &Message) {
+        if !is_input(msg) {
+            return;
+        }
+        if let Ok(bytes) = msg.write_to_bytes() {
+            self.script.events.push(ScriptEvent {
+                t: self.start.elapsed().as_millis() as _,
+                msg: STANDARD.encode(bytes),
+            });
+        }
+    }
+
+    pub fn finish(self) -> InputScript {
+        self.script
+    }
+}
+
+/// The keys and the mouse buttons down, released if the replay stops.
+#[derive(Default)]
+struct Pressed {
+    keys: Vec<KeyEvent>,
+    mouse: Option<MouseEvent>,
+}
+
+impl Pressed {
+    fn update(&mut self, msg: &Message) {
+        match &msg.union {
+            Some(message::Union::KeyEvent(k)) if !k.press => {
+                self.keys.retain(|p| p.union != k.union || p.mode != k.mode);
+                if k.down {
+                    self.keys.push(k.clone());
+                }
+            }
+            Some(message::Union::MouseEvent(m)) => match m.mask & 0x7 {
+                crate::input::MOUSE_TYPE_DOWN => self.mouse = Some(m.clone()),
+                crate::input::MOUSE_TYPE_UP => self.mouse = None,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn release(self) -> Vec<Message> {
+        let mut out = vec![];
+        for mut k in self.keys.into_iter().rev() {
+            k.down = false;
+            let mut msg = Message::new();
+            msg.set_key_event(k);
+            out.push(msg);
+        }
+        if let Some(mut m) = self.mouse {
+            m.mask = (m.mask & !0x7) | crate::input::MOUSE_TYPE_UP;
+            let mut msg = Message::new();
+            msg.set_mouse_event(m);
+            out.push(msg);
+        }
+        out
+    }
+}
+
+/// Sends the events at their times from now. The times are from the start, not from the
+/// previous event, for the delays not to accumulate.
+pub fn replay(
+    script: &InputScript,
+    abort: &AtomicBool,
+    mut send: impl FnMut(Message),
+) -> ResultType<()> {
+    let messages = script.messages()?;
+    let start = Instant::now();
+    let mut pressed = Pressed::default();
+    let mut res = Ok(());
+    for (t, msg) in messages {
+        let due = start + t;
+        loop {
+            if abort.load(Ordering::SeqCst) {
+                break;
+            }
+            let now = Instant::now();
+            if now >= due {
+                break;
+            }
+            std::thread::sleep((due - now).min(CHECK_INTERVAL));
+        }
+        if abort.load(Ordering::SeqCst) {
+            res = Err(hbb_common::anyhow::anyhow!("Aborted"));
+            break;
+        }
+        if Instant::now() > due + MAX_LAG {
+            res = Err(hbb_common::anyhow::anyhow!(
+                "Late by more than {}ms at {}ms",
+                MAX_LAG.as_millis(),
+                t.as_millis()
+            ));
+            break;
+        }
+        pressed.update(&msg);
+        send(msg);
+    }
+    if res.is_err() {
+        for msg in pressed.release() {
+            send(msg);
+        }
+    }
+    res
+}
+
+/// Starts the ipc server of the sessions of this process, once.
+#[cfg(all(
+    feature = "flutter",
+    not(any(target_os = "android", target_os = "ios"))
+))]
+pub fn start_ipc_server() {
+    static START: std::sync::Once = std::sync::Once::new();
+    START.call_once(|| {
+        std::thread::spawn(start_ipc_server_);
+    });
+}
+
+#[cfg(all(
+    feature = "flutter",
+    not(any(target_os = "android", target_os = "ios"))
+))]
+#[tokio::main(flavor = "current_thread")]
+async fn start_ipc_server_() {
+    use hbb_common::futures::StreamExt;
+    let mut incoming = match crate::ipc::new_listener(IPC_POSTFIX).await {
+        Ok(incoming) => incoming,
+        Err(err) => {
+            log::error!("Failed to start input script ipc server: {}", err);
+            return;
+        }
+    };
+    // The input of the sessions is for the user only.
+    #[cfg(not(windows))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let path = hbb_common::config::Config::ipc_path(IPC_POSTFIX);
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o0600)).ok();
+    }
+    while let Some(result) = incoming.next().await {
+        match result {
+            Ok(stream) => {
+                tokio::spawn(handle_ipc(crate::ipc::Connection::new(stream)));
+            }
+            Err(err) => {
+                log::error!("Couldn't get input script client: {:?}", err);
+            }
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "flutter",
+    not(any(target_os = "android", target_os = "ios"))
+))]
+async fn handle_ipc(mut conn: crate::ipc::Connection) {
+    use crate::ipc::{Data, DataInputScript};
+    while let Ok(Some(data)) = conn.next().await {
+        if let Data::InputScript(req) = data {
+            let res = handle_request(req)
+                .err()
+                .map(|e| e.to_string())
+                .unwrap_or_default();
+            if conn
+                .send(&Data::InputScript(DataInputScript::Response(res)))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "flutter",
+    not(any(target_os = "android", target_os = "ios"))
+))]
+fn handle_request(req: crate::ipc::DataInputScript) -> ResultType<()> {
+    use crate::ipc::DataInputScript;
+    let session = |id: &str| {
+        crate::flutter::sessions::get_session_by_peer_id(
+            id.to_owned(),
+            hbb_common::rendezvous_proto::ConnType::DEFAULT_CONN,
+        )
+        .ok_or_else(|| hbb_common::anyhow::anyhow!("No session of {}", id))
+    };
+    match req {
+        DataInputScript::Record { id } => session(&id)?.start_input_recording(),
+        DataInputScript::Stop { id, path } => session(&id)?.stop_input_recording(&path),
+        DataInputScript::Replay { id, path } => session(&id)?.replay_input(&path),
+        DataInputScript::Abort { id } => {
+            session(&id)?.abort_input_replay("Aborted by ipc");
+            Ok(())
+        }
+        DataInputScript::Response(_) => bail!("Unexpected response"),
+    }
+}
+
+/// Sends a command to the sessions of the client, for the command line.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[tokio::main(flavor = "current_thread")]
+pub async fn send_ipc(req: crate::ipc::DataInputScript) -> ResultType<()> {
+    use crate::ipc::{Data, DataInputScript};
+    let mut conn = crate::ipc::connect(1000, IPC_POSTFIX).await?;
+    conn.send(&Data::InputScript(req)).await?;
+    match conn.next_timeout(3000).await? {
+        Some(Data::InputScript(DataInputScript::Response(err))) => {
+            if err.is_empty() {
+                Ok(())
+            } else {
+                bail!("{}", err)
+            }
+        }
+        _ => bail!("No response of the client"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mouse(mask: i32) -> Message {
+        let mut msg = Message::new();
+        msg.set_mouse_event(MouseEvent {
+            mask,
+            x: 10,
+            y: 20,
+            ..Default::default()
+        });
+        msg
+    }
+
+    fn key(down: bool) -> Message {
+        let mut evt = KeyEvent::new();
+        evt.set_chr(30);
+        evt.down = down;
+        evt.mode = KeyboardMode::Map.into();
+        let mut msg = Message::new();
+        msg.set_key_event(evt);
+        msg
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut recorder = Recorder::new(vec![(1920, 1080)]);
+        recorder.record(&key(true));
+        recorder.record(&key(false));
+        recorder.record(&Message::new());
+        let script = recorder.finish();
+        assert_eq!(script.events.len(), 2);
+        assert!(script.check_displays(&[(1920, 1080)]).is_ok());
+        assert!(script.check_displays(&[(1280, 720)]).is_err());
+
+        let mut sent = vec![];
+        replay(&script, &AtomicBool::new(false), |m| sent.push(m)).unwrap();
+        assert_eq!(sent, vec![key(true), key(false)]);
+    }
+
+    #[test]
+    fn test_abort_releases() {
+        let button_down = crate::input::MOUSE_TYPE_DOWN | (crate::input::MOUSE_BUTTON_LEFT << 3);
+        let script = InputScript {
+            version: SCRIPT_VERSION,
+            displays: vec![],
+            events: vec![
+                ScriptEvent {
+                    t: 0,
+                    msg: STANDARD.encode(key(true).write_to_bytes().unwrap()),
+                },
+                ScriptEvent {
+                    t: 0,
+                    msg: STANDARD.encode(mouse(button_down).write_to_bytes().unwrap()),
+                },
+                ScriptEvent {
+                    t: 60_000,
+                    msg: STANDARD.encode(key(false).write_to_bytes().unwrap()),
+                },
+            ],
+        };
+        let abort = AtomicBool::new(false);
+        let mut sent = vec![];
+        let res = replay(&script, &abort, |m| {
+            sent.push(m);
+            if sent.len() == 2 {
+                abort.store(true, Ordering::SeqCst);
+            }
+        });
+        assert!(res.is_err());
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[2], key(false));
+        assert_eq!(
+            sent[3],
+            mouse(crate::input::MOUSE_TYPE_UP | (crate::input::MOUSE_BUTTON_LEFT << 3))
+        );
+    }
+
+    #[test]
+    fn test_invalid_script() {
+        let script = InputScript {
+            version: SCRIPT_VERSION,
+            displays: vec![],
+            events: vec![ScriptEvent {
+                t: 0,
+                msg: STANDARD.encode(Message::new().write_to_bytes().unwrap()),
+            }],
+        };
+        assert!(replay(&script, &AtomicBool::new(false), |_| {}).is_err());
+    }
+}
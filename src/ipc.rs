@@ -107,11 +107,19 @@ pub enum FS {
         is_resume: bool,
     },
     SendConfirm(Vec<u8>),
+    /// The content of a delta sync message of an upload, see `crate::delta_sync`.
+    DeltaSync(Vec<u8>),
     Rename {
         id: i32,
         path: String,
         new_name: String,
     },
+    /// The content of a file operation request, see `crate::file_ops`.
+    FileOps(Vec<u8>),
+    /// The content of a file drop query, see `crate::file_drop`.
+    FileDrop(Vec<u8>),
+    /// The content of a transfer metadata message of a job done, see `crate::transfer_metadata`.
+    TransferMetadata(Vec<u8>),
 }
 
 #[cfg(target_os = "windows")]
@@ -169,6 +177,9 @@ pub enum DataControl {
         miny: i32,
         maxy: i32,
     },
+    BlockInput(bool),
+    // The error of `BlockInput`, empty if it succeeded.
+    BlockInputResult(String),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -185,6 +196,35 @@ pub enum DataPortableService {
     CmShowElevation(bool),
 }
 
+// Between the unprivileged server and the service, see `platform::privilege_broker`.
+#[cfg(windows)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "t", content = "c")]
+pub enum DataBroker {
+    Hello(String),
+    Capabilities(Vec<String>),
+    Request(String),
+    // Empty on success, the error otherwise.
+    Response(String),
+    // From the broker only, the worker asks with `None`.
+    SyncConfig(Option<Box<(Config, Config2)>>),
+    // The results of the network detection of the worker, never the settings.
+    SyncState { nat_type: i32, serial: i32 },
+}
+
+// The commands to the sessions of the client, see `input_script`.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "t", content = "c")]
+pub enum DataInputScript {
+    Record { id: String },
+    Stop { id: String, path: String },
+    Replay { id: String, path: String },
+    Abort { id: String },
+    // Empty on success, the error otherwise.
+    Response(String),
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "t", content = "c")]
 pub enum Data {
@@ -206,6 +246,7 @@ pub enum Data {
         recording: bool,
         block_input: bool,
         from_switch: bool,
+        sas: String,
     },
     ChatMessage {
         text: String,
@@ -259,11 +300,14 @@ pub enum Data {
     StartVoiceCall,
     VoiceCallResponse(bool),
     CloseVoiceCall(String),
+    InputBlocked(bool),
     #[cfg(all(feature = "flutter", feature = "plugin_framework"))]
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     Plugin(Plugin),
     #[cfg(windows)]
     SyncWinCpuUsage(Option<f64>),
+    #[cfg(windows)]
+    Broker(DataBroker),
     FileTransferLog((String, String)),
     #[cfg(windows)]
     ControlledSessionCount(usize),
@@ -276,6 +320,16 @@ pub enum Data {
     HwCodecConfig(Option<String>),
     RemoveTrustedDevices(Vec<Bytes>),
     ClearTrustedDevices,
+    ClearLockouts(Vec<String>),
+    AddOneTimePassword(String),
+    // The code to unlock the protected settings, answered with "Y" if accepted.
+    UnlockSettings(String),
+    // Sets the levels of `crate::logging` if some, answered with the levels in effect, or None
+    // if invalid.
+    LogLevels(Option<String>),
+    RevokeOneTimePasswords(Vec<String>),
+    // The JSON of a record of `crate::transfer_history`.
+    AddTransferRecord(String),
     #[cfg(all(target_os = "windows", feature = "flutter"))]
     PrinterData(Vec<u8>),
     InstallOption(Option<(String, String)>),
@@ -291,6 +345,8 @@ pub enum Data {
     SocksWs(Option<Box<(Option<config::Socks5Server>, String)>>),
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     Whiteboard((String, crate::whiteboard::CustomEvent)),
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    InputScript(DataInputScript),
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -546,12 +602,23 @@ async fn handle(data: Data, stream: &mut Connection) {
                     value = Some(Config::get_unlock_pin());
                 } else if name == "trusted-devices" {
                     value = Some(Config::get_trusted_devices_json());
+                } else if name == "lockouts" {
+                    value = Some(crate::server::lockout::get_json());
+                } else if name == "one-time-passwords" {
+                    value = Some(crate::server::one_time_password::get_json());
+                } else if name == "transfer-history" {
+                    value = Some(crate::transfer_history::get_json());
+                } else if name == "settings-protection" {
+                    value = Some(crate::settings_guard::get_status_json());
                 } else {
                     value = None;
                 }
                 allow_err!(stream.send(&Data::Config((name, value))).await);
             }
             Some(value) => {
+                if !crate::settings_guard::allow_config(&name) {
+                    return;
+                }
                 if name == "id" {
                     Config::set_key_confirmed(false);
                     Config::set_id(&value);
@@ -565,6 +632,11 @@ async fn handle(data: Data, stream: &mut Connection) {
                     crate::audio_service::set_voice_call_input_device(Some(value), true);
                 } else if name == "unlock-pin" {
                     Config::set_unlock_pin(&value);
+                } else if name == crate::settings_guard::OPTION_SETTINGS_ADMIN_PASSWORD {
+                    if crate::settings_guard::is_locked() {
+                        return;
+                    }
+                    crate::settings_guard::set_admin_password(&value);
                 } else {
                     return;
                 }
@@ -577,6 +649,18 @@ async fn handle(data: Data, stream: &mut Connection) {
                 allow_err!(stream.send(&Data::Options(Some(v))).await);
             }
             Some(value) => {
+                if !crate::settings_guard::allow_options(&value) {
+                    allow_err!(stream.send(&Data::Options(None)).await);
+                    return;
+                }
+                if let Some(rule) = value
+                    .get(crate::server::access_list::OPTION_ACCESS_CONTROL_LIST)
+                    .and_then(|v| crate::server::access_list::first_invalid(v))
+                {
+                    log::error!("Rejected the access control list, invalid rule: {}", rule);
+                    allow_err!(stream.send(&Data::Options(None)).await);
+                    return;
+                }
                 let _chk = CheckIfRestart::new();
                 let _nat = CheckTestNatType::new();
                 if let Some(v) = value.get("privacy-mode-impl-key") {
@@ -593,7 +677,9 @@ async fn handle(data: Data, stream: &mut Connection) {
         Data::SyncConfig(Some(configs)) => {
             let (config, config2) = *configs;
             let _chk = CheckIfRestart::new();
+            let protected = crate::settings_guard::snapshot();
             Config::set(config);
+            crate::settings_guard::restore(protected);
             Config2::set(config2);
             allow_err!(stream.send(&Data::SyncConfig(None)).await);
         }
@@ -717,6 +803,48 @@ async fn handle(data: Data, stream: &mut Connection) {
         Data::ClearTrustedDevices => {
             Config::clear_trusted_devices();
         }
+        Data::ClearLockouts(v) => {
+            if crate::settings_guard::allow_config(crate::settings_guard::LOCKOUTS) {
+                crate::server::lockout::clear(&v);
+            }
+        }
+        Data::UnlockSettings(code) => {
+            let ok = tokio::task::spawn_blocking(move || crate::settings_guard::unlock(&code))
+                .await
+                .unwrap_or_default();
+            allow_err!(
+                stream
+                    .send(&Data::UnlockSettings(if ok { "Y" } else { "" }.to_owned()))
+                    .await
+            );
+        }
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        Data::LogLevels(levels) => {
+            let res = match levels {
+                Some(levels) => match crate::logging::set_levels(&levels) {
+                    Ok(()) => Some(crate::logging::get_levels()),
+                    Err(err) => {
+                        log::error!("Failed to set the log levels: {}", err);
+                        None
+                    }
+                },
+                None => Some(crate::logging::get_levels()),
+            };
+            allow_err!(stream.send(&Data::LogLevels(res)).await);
+        }
+        Data::AddOneTimePassword(v) => {
+            if crate::settings_guard::allow_config(crate::settings_guard::ONE_TIME_PASSWORDS) {
+                crate::server::one_time_password::add(&v);
+            }
+        }
+        Data::RevokeOneTimePasswords(v) => {
+            if crate::settings_guard::allow_config(crate::settings_guard::ONE_TIME_PASSWORDS) {
+                crate::server::one_time_password::revoke(&v);
+            }
+        }
+        Data::AddTransferRecord(v) => {
+            crate::transfer_history::append_json(&v);
+        }
         Data::InstallOption(opt) => match opt {
             Some((_k, _v)) => {
                 #[cfg(target_os = "windows")]
@@ -1083,6 +1211,85 @@ pub fn clear_trusted_devices() {
     allow_err!(set_data(&Data::ClearTrustedDevices));
 }
 
+#[cfg(feature = "flutter")]
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn get_lockouts() -> String {
+    if let Ok(Some(v)) = get_config("lockouts") {
+        v
+    } else {
+        crate::server::lockout::get_json()
+    }
+}
+
+#[cfg(feature = "flutter")]
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn clear_lockouts(sources: Vec<String>) {
+    allow_err!(set_data(&Data::ClearLockouts(sources)));
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn get_settings_protection() -> String {
+    if let Ok(Some(v)) = get_config("settings-protection") {
+        v
+    } else {
+        crate::settings_guard::get_status_json()
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[tokio::main(flavor = "current_thread")]
+pub async fn unlock_settings(code: String) -> ResultType<bool> {
+    let mut c = connect(1000, "").await?;
+    c.send(&Data::UnlockSettings(code)).await?;
+    if let Some(Data::UnlockSettings(v)) = c.next_timeout(1000).await? {
+        return Ok(v == "Y");
+    }
+    Ok(false)
+}
+
+/// Sets the log levels of the server if some, returns the levels in effect.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[tokio::main(flavor = "current_thread")]
+pub async fn log_levels(levels: Option<String>) -> ResultType<String> {
+    let mut c = connect(1000, "").await?;
+    c.send(&Data::LogLevels(levels)).await?;
+    match c.next_timeout(1000).await? {
+        Some(Data::LogLevels(Some(v))) => Ok(v),
+        _ => bail!("Wrong log levels"),
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn get_one_time_passwords() -> String {
+    if let Ok(Some(v)) = get_config("one-time-passwords") {
+        v
+    } else {
+        crate::server::one_time_password::get_json()
+    }
+}
+
+#[cfg(feature = "flutter")]
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn get_transfer_history() -> String {
+    if let Ok(Some(v)) = get_config("transfer-history") {
+        v
+    } else {
+        crate::transfer_history::get_json()
+    }
+}
+
+#[cfg(feature = "flutter")]
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn add_one_time_password(json: String) {
+    allow_err!(set_data(&Data::AddOneTimePassword(json)));
+}
+
+#[cfg(feature = "flutter")]
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn revoke_one_time_passwords(ids: Vec<String>) {
+    allow_err!(set_data(&Data::RevokeOneTimePasswords(ids)));
+}
+
 pub fn get_id() -> String {
     if let Ok(Some(v)) = get_config("id") {
         // update salt also, so that next time reinstallation not causing first-time auto-login failure
@@ -14,6 +14,21 @@ use kcp_sys::{
 };
 use std::{net::SocketAddr, sync::Arc};
 
+mod fec;
+
+/// Local option, use FEC on UDP connections to peers supporting it.
+pub const OPTION_ENABLE_UDP_FEC: &str = "enable-udp-fec";
+/// Peer option, remembered once the peer has advertised FEC support.
+pub const PEER_OPTION_FEC_SUPPORTED: &str = "fec-supported";
+
+#[inline]
+pub fn is_fec_enabled() -> bool {
+    config::option2bool(
+        OPTION_ENABLE_UDP_FEC,
+        &config::LocalConfig::get_option(OPTION_ENABLE_UDP_FEC),
+    )
+}
+
 pub struct KcpStream {
     _endpoint: KcpEndpoint,
     stop_sender: Option<oneshot::Sender<()>>,
@@ -44,12 +59,23 @@ impl KcpStream {
                 .ok_or_else(|| anyhow::anyhow!("Failed to get output receiver"))?,
         );
         let (stop_sender, stop_receiver) = oneshot::channel();
+        // FEC is turned on as soon as the peer sends FEC datagrams.
+        let mut fec = false;
         if let Some(packet) = init_packet {
-            if packet.len() >= std::mem::size_of::<KcpPacketHeader>() {
+            if fec::is_fec_packet(&packet) {
+                fec = true;
+                if let Some(fec::Decoded::Data(packets)) = fec::FecDecoder::new().decode(&packet) {
+                    for packet in packets {
+                        if packet.len() >= std::mem::size_of::<KcpPacketHeader>() {
+                            input.send(BytesMut::from(&packet[..]).into()).await?;
+                        }
+                    }
+                }
+            } else if packet.len() >= std::mem::size_of::<KcpPacketHeader>() {
                 input.send(packet.into()).await?;
             }
         }
-        Self::kcp_io(udp_socket.clone(), input, output, stop_receiver).await;
+        Self::kcp_io(udp_socket.clone(), input, output, stop_receiver, fec).await;
 
         let conn_id = tokio::time::timeout(timeout, endpoint.accept()).await??;
         if let Some(stream) = stream::KcpStream::new(&endpoint, conn_id) {
@@ -68,6 +94,7 @@ impl KcpStream {
     pub async fn connect(
         udp_socket: Arc<UdpSocket>,
         timeout: std::time::Duration,
+        fec: bool,
     ) -> ResultType<(Self, Stream)> {
         let mut endpoint = KcpEndpoint::new();
         endpoint.run().await;
@@ -79,7 +106,7 @@ impl KcpStream {
                 .ok_or_else(|| anyhow::anyhow!("Failed to get output receiver"))?,
         );
         let (stop_sender, stop_receiver) = oneshot::channel();
-        Self::kcp_io(udp_socket.clone(), input, output, stop_receiver).await;
+        Self::kcp_io(udp_socket.clone(), input, output, stop_receiver, fec).await;
 
         let conn_id = endpoint.connect(timeout, 0, 0, Bytes::new()).await?;
         if let Some(stream) = stream::KcpStream::new(&endpoint, conn_id) {
@@ -100,10 +127,14 @@ impl KcpStream {
         input: mpsc::Sender<KcpPacket>,
         mut output: mpsc::Receiver<KcpPacket>,
         mut stop_receiver: oneshot::Receiver<()>,
+        fec: bool,
     ) {
         let udp = udp_socket.clone();
         tokio::spawn(async move {
             let mut buf = vec![0; 1500];
+            let mut fec_on = fec;
+            let mut encoder = fec::FecEncoder::new();
+            let mut decoder = fec::FecDecoder::new();
             loop {
                 tokio::select! {
                     _ = &mut stop_receiver => {
@@ -111,7 +142,20 @@ impl KcpStream {
                         break;
                     }
                     Some(data) = output.recv() => {
-                        if let Err(e) = udp.send(&data.inner()).await {
+                        let data = data.inner();
+                        let res = if fec_on {
+                            let mut res = Ok(0);
+                            for packet in encoder.encode(&data) {
+                                res = udp.send(&packet).await;
+                                if res.is_err() {
+                                    break;
+                                }
+                            }
+                            res
+                        } else {
+                            udp.send(&data).await
+                        };
+                        if let Err(e) = res {
                             log::debug!("KCP send error: {:?}", e);
                             break;
                         }
@@ -119,6 +163,28 @@ impl KcpStream {
                     result = udp.recv_from(&mut buf) => {
                         match result {
                             Ok((size, _)) => {
+                                if fec::is_fec_packet(&buf[..size]) {
+                                    if !fec_on {
+                                        log::info!("Peer sends FEC datagrams, enable FEC");
+                                        fec_on = true;
+                                    }
+                                    match decoder.decode(&buf[..size]) {
+                                        Some(fec::Decoded::Data(packets)) => {
+                                            for packet in packets {
+                                                if packet.len() < std::mem::size_of::<KcpPacketHeader>() {
+                                                    continue;
+                                                }
+                                                input.send(BytesMut::from(&packet[..]).into()).await.ok();
+                                            }
+                                        }
+                                        Some(fec::Decoded::Report(loss)) => encoder.set_loss(loss),
+                                        None => {}
+                                    }
+                                    if let Some(loss) = decoder.take_report() {
+                                        udp.send(&fec::encode_report(loss)).await.ok();
+                                    }
+                                    continue;
+                                }
                                 if size < std::mem::size_of::<KcpPacketHeader>() {
                                     continue;
                                 }
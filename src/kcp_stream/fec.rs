@@ -0,0 +1,305 @@
+//! XOR parity forward error correction below KCP.
+//!
+//! KCP repairs a lost datagram by retransmission, which costs at least one RTT and
+//! often makes the video decoder give up on the frame and request a keyframe.
+//! Every `k` datagrams we send one parity datagram, so a single loss per group
+//! is repaired locally without waiting for the sender.
+//!
+//! The receiver reports the measured loss once per second and the sender picks `k`
+//! accordingly, from no parity at all on a clean link to one parity every 3 datagrams.
+//!
+//! Datagram: `[MAGIC: 4][kind: u8][k: u8][index: u8][seq: u32 BE][payload]`.
+//! Data datagrams carry the KCP packet as payload, parity datagrams carry
+//! `[xor of payload lengths: u16 BE][xor of zero padded payloads]`,
+//! report datagrams carry the loss in permille as `u16 BE`.
+
+use hbb_common::bytes::{BufMut, Bytes, BytesMut};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+const MAGIC: [u8; 4] = [0xFE, 0xC0, 0x5A, 0xA5];
+const HEADER_LEN: usize = 11;
+const KIND_DATA: u8 = 0;
+const KIND_PARITY: u8 = 1;
+const KIND_REPORT: u8 = 2;
+
+const MAX_GROUPS: usize = 64;
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn is_fec_packet(buf: &[u8]) -> bool {
+    buf.len() >= HEADER_LEN && buf[..MAGIC.len()] == MAGIC
+}
+
+/// Group size for the loss reported by the peer, 0 means no parity.
+pub fn group_size_for_loss(permille: u16) -> u8 {
+    match permille {
+        0..=9 => 0,
+        10..=29 => 10,
+        30..=79 => 5,
+        _ => 3,
+    }
+}
+
+fn header(kind: u8, k: u8, index: u8, seq: u32, payload_len: usize) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(HEADER_LEN + payload_len);
+    buf.put_slice(&MAGIC);
+    buf.put_u8(kind);
+    buf.put_u8(k);
+    buf.put_u8(index);
+    buf.put_u32(seq);
+    buf
+}
+
+fn xor_into(parity: &mut Vec<u8>, payload: &[u8]) {
+    if parity.len() < payload.len() {
+        parity.resize(payload.len(), 0);
+    }
+    for (p, b) in parity.iter_mut().zip(payload) {
+        *p ^= b;
+    }
+}
+
+pub struct FecEncoder {
+    k: u8,
+    seq: u32,
+    index: u8,
+    parity: Vec<u8>,
+    parity_len: u16,
+}
+
+impl FecEncoder {
+    pub fn new() -> Self {
+        Self {
+            k: group_size_for_loss(0),
+            seq: 0,
+            index: 0,
+            parity: Vec::new(),
+            parity_len: 0,
+        }
+    }
+
+    pub fn set_loss(&mut self, permille: u16) {
+        let k = group_size_for_loss(permille);
+        if k != self.k {
+            hbb_common::log::debug!("FEC group size {} -> {}, loss {}‰", self.k, k, permille);
+            self.k = k;
+            // The current group is sent without parity, start a new one.
+            self.index = 0;
+            self.parity.clear();
+            self.parity_len = 0;
+        }
+    }
+
+    /// Wrap one KCP datagram, followed by a parity datagram if it completes a group.
+    pub fn encode(&mut self, payload: &[u8]) -> Vec<Bytes> {
+        let mut out = Vec::with_capacity(2);
+        let mut buf = header(KIND_DATA, self.k, self.index, self.seq, payload.len());
+        buf.put_slice(payload);
+        out.push(buf.freeze());
+        self.seq = self.seq.wrapping_add(1);
+        if self.k == 0 {
+            return out;
+        }
+        xor_into(&mut self.parity, payload);
+        self.parity_len ^= payload.len() as u16;
+        self.index += 1;
+        if self.index == self.k {
+            let group = self.seq.wrapping_sub(self.k as _);
+            let mut buf = header(KIND_PARITY, self.k, 0, group, 2 + self.parity.len());
+            buf.put_u16(self.parity_len);
+            buf.put_slice(&self.parity);
+            out.push(buf.freeze());
+            self.index = 0;
+            self.parity.clear();
+            self.parity_len = 0;
+        }
+        out
+    }
+}
+
+pub fn encode_report(permille: u16) -> Bytes {
+    let mut buf = header(KIND_REPORT, 0, 0, 0, 2);
+    buf.put_u16(permille);
+    buf.freeze()
+}
+
+#[derive(Default)]
+struct Group {
+    k: u8,
+    data: BTreeMap<u8, Bytes>,
+    parity: Option<Bytes>,
+    recovered: bool,
+}
+
+impl Group {
+    fn try_recover(&mut self) -> Option<Bytes> {
+        if self.recovered || self.data.len() + 1 != self.k as usize {
+            return None;
+        }
+        let parity = self.parity.as_ref()?;
+        if parity.len() < 2 {
+            return None;
+        }
+        let mut len = u16::from_be_bytes([parity[0], parity[1]]);
+        let mut payload = parity[2..].to_vec();
+        for d in self.data.values() {
+            xor_into(&mut payload, d);
+            len ^= d.len() as u16;
+        }
+        self.recovered = true;
+        if len as usize > payload.len() {
+            return None;
+        }
+        payload.truncate(len as _);
+        Some(payload.into())
+    }
+}
+
+pub enum Decoded {
+    /// KCP datagrams to feed into the endpoint, the recovered one included.
+    Data(Vec<Bytes>),
+    /// Loss measured by the peer, in permille.
+    Report(u16),
+}
+
+pub struct FecDecoder {
+    groups: BTreeMap<u32, Group>,
+    // Sequence numbers seen in the current report period.
+    window: VecDeque<u32>,
+    recovered: usize,
+    last_report: Instant,
+}
+
+impl FecDecoder {
+    pub fn new() -> Self {
+        Self {
+            groups: BTreeMap::new(),
+            window: VecDeque::new(),
+            recovered: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn decode(&mut self, buf: &[u8]) -> Option<Decoded> {
+        if !is_fec_packet(buf) {
+            return None;
+        }
+        let kind = buf[4];
+        let k = buf[5];
+        let index = buf[6];
+        let seq = u32::from_be_bytes([buf[7], buf[8], buf[9], buf[10]]);
+        let payload = Bytes::copy_from_slice(&buf[HEADER_LEN..]);
+        match kind {
+            KIND_DATA => {
+                self.window.push_back(seq);
+                let mut out = vec![payload.clone()];
+                if k > 0 {
+                    let group = self.group(seq.wrapping_sub(index as _), k);
+                    group.data.insert(index, payload);
+                    if let Some(recovered) = group.try_recover() {
+                        self.recovered += 1;
+                        out.push(recovered);
+                    }
+                }
+                Some(Decoded::Data(out))
+            }
+            KIND_PARITY => {
+                let group = self.group(seq, k);
+                group.parity = Some(payload);
+                let mut out = Vec::new();
+                if let Some(recovered) = group.try_recover() {
+                    self.recovered += 1;
+                    out.push(recovered);
+                }
+                Some(Decoded::Data(out))
+            }
+            KIND_REPORT if payload.len() >= 2 => Some(Decoded::Report(u16::from_be_bytes([
+                payload[0], payload[1],
+            ]))),
+            _ => None,
+        }
+    }
+
+    fn group(&mut self, first: u32, k: u8) -> &mut Group {
+        while !self.groups.contains_key(&first) && self.groups.len() >= MAX_GROUPS {
+            self.groups.pop_first();
+        }
+        self.groups.entry(first).or_insert_with(|| Group {
+            k,
+            ..Default::default()
+        })
+    }
+
+    /// Loss before repair in permille, once per report interval.
+    pub fn take_report(&mut self) -> Option<u16> {
+        if self.last_report.elapsed() < REPORT_INTERVAL {
+            return None;
+        }
+        self.last_report = Instant::now();
+        let received = self.window.len();
+        let (Some(min), Some(max)) = (
+            self.window.iter().min().copied(),
+            self.window.iter().max().copied(),
+        ) else {
+            return None;
+        };
+        self.window.clear();
+        let expected = (max - min) as usize + 1;
+        let loss = expected.saturating_sub(received) * 1000 / expected;
+        if self.recovered > 0 {
+            hbb_common::log::debug!("FEC recovered {} datagrams, loss {}‰", self.recovered, loss);
+            self.recovered = 0;
+        }
+        Some(loss.min(1000) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(decoded: Option<Decoded>) -> Vec<Bytes> {
+        match decoded {
+            Some(Decoded::Data(v)) => v,
+            _ => panic!("data expected"),
+        }
+    }
+
+    #[test]
+    fn test_recover_single_loss() {
+        let mut enc = FecEncoder::new();
+        enc.set_loss(50);
+        assert_eq!(enc.k, 5);
+        let payloads: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 10 + i as usize]).collect();
+        let mut packets = Vec::new();
+        for p in payloads.iter() {
+            packets.extend(enc.encode(p));
+        }
+        assert_eq!(packets.len(), 6);
+        let mut dec = FecDecoder::new();
+        let mut received = Vec::new();
+        for (i, p) in packets.iter().enumerate() {
+            // drop the third datagram
+            if i == 2 {
+                continue;
+            }
+            received.extend(data(dec.decode(p)));
+        }
+        assert_eq!(received.len(), 5);
+        assert!(received.contains(&Bytes::from(payloads[2].clone())));
+    }
+
+    #[test]
+    fn test_no_parity_on_clean_link() {
+        let mut enc = FecEncoder::new();
+        let packets: Vec<_> = (0..20).flat_map(|_| enc.encode(b"kcp")).collect();
+        assert_eq!(packets.len(), 20);
+        assert!(packets.iter().all(|p| is_fec_packet(p)));
+        assert!(!is_fec_packet(b"kcp packet"));
+        let mut dec = FecDecoder::new();
+        match dec.decode(&encode_report(42)) {
+            Some(Decoded::Report(v)) => assert_eq!(v, 42),
+            _ => panic!("report expected"),
+        }
+    }
+}
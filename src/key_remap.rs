@@ -0,0 +1,155 @@
+//! Key remaps and macros of a peer, applied to the local key events before they are
+//! translated and sent, e.g. Meta to Ctrl, or F13 to type a login snippet.
+//!
+//! The keys are the USB HID usages of the physical keys, as flutter reports them. The table
+//! is saved in json in the peer option [`PEER_OPTION_KEY_REMAP`].
+
+use serde_derive::{Deserialize, Serialize};
+
+pub const PEER_OPTION_KEY_REMAP: &str = "key-remap";
+/// The longest delay step of a macro.
+pub const MAX_MACRO_DELAY_MS: u64 = 10_000;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyRemap {
+    #[serde(default)]
+    pub remap: Vec<Remap>,
+    #[serde(default)]
+    pub macros: Vec<Macro>,
+}
+
+/// The key `from` is sent as the key `to`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Remap {
+    pub from: u32,
+    pub to: u32,
+    /// Shown in the settings only.
+    #[serde(default)]
+    pub label: String,
+}
+
+/// The key is not sent, the steps are run when it is pressed.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    pub key: u32,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub steps: Vec<MacroStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MacroStep {
+    /// A combination like `Ctrl+Alt+VK_DELETE`, see [`parse_combo`].
+    Keys(String),
+    Text(String),
+    /// Milliseconds, up to [`MAX_MACRO_DELAY_MS`].
+    Delay(u64),
+}
+
+impl KeyRemap {
+    /// None if there is nothing to apply.
+    pub fn parse(s: &str) -> Option<Self> {
+        if s.is_empty() {
+            return None;
+        }
+        let remap: Self = serde_json::from_str(s).ok()?;
+        if remap.remap.is_empty() && remap.macros.is_empty() {
+            return None;
+        }
+        Some(remap)
+    }
+
+    pub fn find_remap(&self, key: rdev::Key) -> Option<u32> {
+        self.remap
+            .iter()
+            .find(|r| rdev::usb_hid_key_from_code(r.from as _) == key)
+            .map(|r| r.to)
+    }
+
+    pub fn find_macro(&self, key: rdev::Key) -> Option<&Macro> {
+        self.macros
+            .iter()
+            .find(|m| rdev::usb_hid_key_from_code(m.key as _) == key)
+    }
+}
+
+/// A key with the modifiers pressed with it, the key is a name of `KEY_MAP`
+/// or a single character.
+#[derive(Debug, Default, PartialEq)]
+pub struct Combo {
+    pub key: String,
+    pub alt: bool,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub command: bool,
+}
+
+/// Parses `Ctrl+Shift+VK_T`, the modifiers are `Ctrl`, `Alt`, `Shift` and `Meta`
+/// in any case, and the key is the last one.
+pub fn parse_combo(s: &str) -> Option<Combo> {
+    let mut combo = Combo::default();
+    let mut parts: Vec<&str> = s.split('+').map(|p| p.trim()).collect();
+    let key = parts.pop().filter(|k| !k.is_empty())?;
+    for part in parts {
+        match part.to_lowercase().as_str() {
+            "ctrl" | "control" => combo.ctrl = true,
+            "alt" | "option" => combo.alt = true,
+            "shift" => combo.shift = true,
+            "meta" | "cmd" | "command" | "win" | "super" => combo.command = true,
+            _ => return None,
+        }
+    }
+    combo.key = key.to_owned();
+    Some(combo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(KeyRemap::parse(""), None);
+        assert_eq!(KeyRemap::parse("{}"), None);
+        assert_eq!(KeyRemap::parse("not json"), None);
+        let remap = KeyRemap::parse(
+            r#"{"remap":[{"from":458979,"to":458976}],
+                "macros":[{"key":458856,"steps":[{"text":"me"},{"delay":100},{"keys":"VK_TAB"}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(remap.remap[0].to, 458976);
+        assert_eq!(
+            remap.macros[0].steps,
+            vec![
+                MacroStep::Text("me".to_owned()),
+                MacroStep::Delay(100),
+                MacroStep::Keys("VK_TAB".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_combo() {
+        assert_eq!(
+            parse_combo("Ctrl+alt + VK_DELETE"),
+            Some(Combo {
+                key: "VK_DELETE".to_owned(),
+                alt: true,
+                ctrl: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_combo("Cmd+v"),
+            Some(Combo {
+                key: "v".to_owned(),
+                command: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(parse_combo("Ctrl+"), None);
+        assert_eq!(parse_combo("Hyper+VK_A"), None);
+    }
+}
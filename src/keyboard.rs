@@ -75,6 +75,7 @@ pub mod client {
     pub fn change_grab_status(state: GrabState, keyboard_mode: &str) {
         #[cfg(feature = "flutter")]
         if !IS_RDEV_ENABLED.load(Ordering::SeqCst) {
+            crate::hotkey_passthrough::change_grab_status(&state, keyboard_mode);
             return;
         }
         match state {
@@ -109,6 +110,9 @@ pub mod client {
         if is_long_press(&event) {
             return;
         }
+        let Some(event) = remap_key_event(event) else {
+            return;
+        };
         let peer = get_peer_platform().to_lowercase();
         for key_event in event_to_key_events(peer, &event, keyboard_mode, lock_modes) {
             send_key_event(&key_event);
@@ -125,6 +129,9 @@ pub mod client {
         if is_long_press(&event) {
             return;
         }
+        let Some(event) = session.remap_key_event(event) else {
+            return;
+        };
         let peer = session.peer_platform().to_lowercase();
         for key_event in event_to_key_events(peer, &event, keyboard_mode, lock_modes) {
             session.send_key_event(&key_event);
@@ -278,6 +285,8 @@ fn start_grab_loop() {
 
             let _scan_code = event.position_code;
             let _code = event.platform_code as KeyCode;
+            // Tracks the modifiers even if the keyboard is hooked.
+            let passthrough = crate::hotkey_passthrough::on_key(key, is_press);
             let res = if KEYBOARD_HOOKED.load(Ordering::SeqCst) {
                 client::process_event(&get_keyboard_mode(), &event, None);
                 if is_press {
@@ -285,6 +294,9 @@ fn start_grab_loop() {
                 } else {
                     Some(event)
                 }
+            } else if passthrough {
+                client::process_event(&get_keyboard_mode(), &event, None);
+                None
             } else {
                 Some(event)
             };
@@ -640,6 +652,18 @@ pub fn send_key_event(key_event: &KeyEvent) {
     }
 }
 
+fn remap_key_event(event: &Event) -> Option<Event> {
+    #[cfg(not(any(feature = "flutter", feature = "cli")))]
+    if let Some(session) = CUR_SESSION.lock().unwrap().as_ref() {
+        return session.remap_key_event(event);
+    }
+    #[cfg(feature = "flutter")]
+    if let Some(session) = flutter::get_cur_session() {
+        return session.remap_key_event(event);
+    }
+    Some(event.clone())
+}
+
 pub fn get_peer_platform() -> String {
     #[cfg(not(any(feature = "flutter", feature = "cli")))]
     if let Some(session) = CUR_SESSION.lock().unwrap().as_ref() {
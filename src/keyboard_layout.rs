@@ -0,0 +1,184 @@
+//! The active keyboard layout of this side, named as the xkb layouts, `us`, `fr`,
+//! `us-dvorak` and so on, to be compared with the layout of the peer.
+//!
+//! When the layouts of the two sides differ, the positional keys give other characters on
+//! the peer than on the keyboard, and the auto keyboard mode sends the characters instead.
+
+use hbb_common::log;
+
+/// The layout of the controlled side in the platform additions.
+pub const PLATFORM_ADDITION_KEY: &str = "keyboard_layout";
+/// The peer option to choose the keyboard mode manually.
+pub const PEER_OPTION_DISABLE_AUTO_KEYBOARD_MODE: &str = "disable-auto-keyboard-mode";
+
+/// The name of the active layout, None if unknown.
+pub fn get() -> Option<String> {
+    let layout = get_().filter(|l| !l.is_empty());
+    if layout.is_none() {
+        log::debug!("Unknown keyboard layout");
+    }
+    layout
+}
+
+/// The layouts are known on both sides and give different characters.
+pub fn is_mismatch(local: &str, peer: &str) -> bool {
+    !local.is_empty() && !peer.is_empty() && local != peer
+}
+
+fn join(layout: &str, variant: &str) -> String {
+    if variant.is_empty() || variant == "basic" {
+        layout.to_owned()
+    } else {
+        format!("{}-{}", layout, variant)
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn get_() -> Option<String> {
+    use winapi::um::winuser::{GetForegroundWindow, GetKeyboardLayout, GetWindowThreadProcessId};
+    use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+
+    let hkl = unsafe {
+        let thread = GetWindowThreadProcessId(GetForegroundWindow(), std::ptr::null_mut());
+        GetKeyboardLayout(thread) as usize
+    };
+    let lang = (hkl & 0xFFFF) as u32;
+    let device = ((hkl >> 16) & 0xFFFF) as u32;
+    let klid = if device & 0xF000 == 0xF000 {
+        // A variant, its id is in the registry.
+        let layout_id = format!("{:04x}", device & 0x0FFF);
+        let layouts = RegKey::predef(HKEY_LOCAL_MACHINE)
+            .open_subkey("SYSTEM\\CurrentControlSet\\Control\\Keyboard Layouts")
+            .ok()?;
+        layouts.enum_keys().flatten().find(|k| {
+            layouts
+                .open_subkey(k)
+                .and_then(|k| k.get_value::<String, _>("Layout Id"))
+                .map(|v| v.eq_ignore_ascii_case(&layout_id))
+                .unwrap_or(false)
+        })?
+    } else if device == 0 {
+        format!("{:08X}", lang)
+    } else {
+        format!("{:08X}", device)
+    };
+    // The other ids can not be compared with the layouts of the other platforms.
+    from_klid(&klid)
+}
+
+#[cfg(target_os = "windows")]
+fn from_klid(klid: &str) -> Option<String> {
+    let (layout, variant) = match klid.to_uppercase().as_str() {
+        "00000409" => ("us", ""),
+        "00010409" => ("us", "dvorak"),
+        "00020409" => ("us", "intl"),
+        "00000809" => ("gb", ""),
+        "0000040C" => ("fr", ""),
+        "0000080C" => ("be", ""),
+        "00000C0C" => ("ca", ""),
+        "0000100C" => ("ch", "fr"),
+        "00000407" => ("de", ""),
+        "00000807" => ("ch", ""),
+        "00000410" => ("it", ""),
+        "0000040A" => ("es", ""),
+        "0000080A" => ("latam", ""),
+        "00000816" => ("pt", ""),
+        "00000416" => ("br", ""),
+        "0000041D" => ("se", ""),
+        "00000414" => ("no", ""),
+        "00000406" => ("dk", ""),
+        "0000040B" => ("fi", ""),
+        "00000413" => ("nl", ""),
+        "00000415" | "00010415" => ("pl", ""),
+        "00000405" => ("cz", ""),
+        "0000041F" => ("tr", ""),
+        "00000419" => ("ru", ""),
+        "00000422" => ("ua", ""),
+        "00000411" => ("jp", ""),
+        "00000412" => ("kr", ""),
+        _ => return None,
+    };
+    Some(join(layout, variant))
+}
+
+#[cfg(target_os = "linux")]
+fn get_() -> Option<String> {
+    let (layout, variant) = if crate::platform::linux::is_x11() {
+        let out = run("setxkbmap", &["-query"])?;
+        (
+            value(&out, "layout:")?,
+            value(&out, "variant:").unwrap_or_default(),
+        )
+    } else {
+        let out = run("localectl", &["status"])?;
+        (
+            value(&out, "X11 Layout:")?,
+            value(&out, "X11 Variant:").unwrap_or_default(),
+        )
+    };
+    // The first one is the active one of a list, on most desktops.
+    let first = |s: &str| s.split(',').next().unwrap_or_default().trim().to_owned();
+    Some(join(&first(&layout), &first(&variant)))
+}
+
+#[cfg(target_os = "linux")]
+fn value(out: &str, key: &str) -> Option<String> {
+    out.lines()
+        .find_map(|l| l.trim().strip_prefix(key))
+        .map(|v| v.trim().to_owned())
+}
+
+#[cfg(target_os = "macos")]
+fn get_() -> Option<String> {
+    let out = run(
+        "defaults",
+        &[
+            "read",
+            "com.apple.HIToolbox",
+            "AppleCurrentKeyboardLayoutInputSourceID",
+        ],
+    )?;
+    let id = out
+        .trim()
+        .strip_prefix("com.apple.keylayout.")?
+        .to_lowercase();
+    let (layout, variant) = match id.as_str() {
+        "us" | "abc" => ("us", ""),
+        "dvorak" => ("us", "dvorak"),
+        "colemak" => ("us", "colemak"),
+        "usinternational-pc" | "abc-extended" => ("us", "intl"),
+        "british" | "british-pc" => ("gb", ""),
+        "french" | "french-pc" | "french-numerical" => ("fr", ""),
+        "belgian" => ("be", ""),
+        "canadian-csa" => ("ca", ""),
+        "swissfrench" => ("ch", "fr"),
+        "german" => ("de", ""),
+        "swissgerman" => ("ch", ""),
+        "italian" | "italian-pro" => ("it", ""),
+        "spanish" | "spanish-iso" => ("es", ""),
+        "latinamerican" => ("latam", ""),
+        "portuguese" => ("pt", ""),
+        "brazilian" | "brazilian-abnt2" => ("br", ""),
+        "swedish" | "swedish-pro" => ("se", ""),
+        "norwegian" => ("no", ""),
+        "danish" => ("dk", ""),
+        "finnish" => ("fi", ""),
+        "dutch" => ("nl", ""),
+        "polish" | "polishpro" => ("pl", ""),
+        "czech" | "czech-qwerty" => ("cz", ""),
+        "turkish" | "turkish-qwerty-pc" => ("tr", ""),
+        "russian" | "russian-pc" => ("ru", ""),
+        "ukrainian" | "ukrainian-pc" => ("ua", ""),
+        _ => return None,
+    };
+    Some(join(layout, variant))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn run(cmd: &str, args: &[&str]) -> Option<String> {
+    let out = std::process::Command::new(cmd).args(args).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&out.stdout).into_owned())
+}
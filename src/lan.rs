@@ -21,10 +21,16 @@ use std::{
     time::Instant,
 };
 
+#[cfg(not(target_os = "ios"))]
+mod mdns;
+
 type Message = RendezvousMessage;
 
 #[cfg(not(target_os = "ios"))]
 pub(super) fn start_listening() -> ResultType<()> {
+    std::thread::spawn(|| {
+        allow_err!(mdns::start_advertising());
+    });
     let addr = SocketAddr::from(([0, 0, 0, 0], get_broadcast_port()));
     let socket = std::net::UdpSocket::bind(addr)?;
     socket.set_read_timeout(Some(std::time::Duration::from_millis(1000)))?;
@@ -75,35 +81,121 @@ pub(super) fn start_listening() -> ResultType<()> {
 
 #[tokio::main(flavor = "current_thread")]
 pub async fn discover() -> ResultType<()> {
-    let sockets = send_query()?;
-    let rx = spawn_wait_responses(sockets);
+    let (tx, rx) = unbounded_channel::<_>();
+    #[cfg(not(target_os = "ios"))]
+    {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            allow_err!(mdns::browse(tx));
+        });
+    }
+    match send_query() {
+        Ok(sockets) => spawn_wait_responses(sockets, tx),
+        // mDNS may still work.
+        Err(err) => {
+            drop(tx);
+            log::error!("Failed to send discover ping: {}", err);
+        }
+    }
     handle_received_peers(rx).await?;
 
     log::info!("discover ping done");
     Ok(())
 }
 
+pub const WOL_PLUGIN_ID: &str = "__wol";
+pub const PEER_OPTION_WOL_MAC: &str = "wol-mac";
+/// Option of the controlled side, advertise its MAC addresses and send the magic packets of
+/// its peers to the LAN on their behalf.
+pub const OPTION_ALLOW_WOL_RELAY: &str = "allow-wol-relay";
+
+#[inline]
+pub fn is_wol_relay_allowed() -> bool {
+    config::option2bool(
+        OPTION_ALLOW_WOL_RELAY,
+        &Config::get_option(OPTION_ALLOW_WOL_RELAY),
+    )
+}
+
 pub fn send_wol(id: String) {
-    let interfaces = default_net::get_interfaces();
+    send_wol_to_macs(&get_wol_macs(&id));
+}
+
+/// The MAC addresses of a peer, found by LAN discovery or reported by the peer
+/// in a previous session.
+pub fn get_wol_macs(id: &str) -> Vec<String> {
+    let mut macs = Vec::new();
     for peer in &config::LanPeers::load().peers {
         if peer.id == id {
-            for (_, mac) in peer.ip_mac.iter() {
-                if let Ok(mac_addr) = mac.parse() {
-                    for interface in &interfaces {
-                        for ipv4 in &interface.ipv4 {
-                            // remove below mask check to avoid unexpected bug
-                            // if (u32::from(ipv4.addr) & u32::from(ipv4.netmask)) == (u32::from(peer_ip) & u32::from(ipv4.netmask))
-                            log::info!("Send wol to {mac_addr} of {}", ipv4.addr);
-                            allow_err!(wol::send_wol(mac_addr, None, Some(IpAddr::V4(ipv4.addr))));
-                        }
-                    }
+            macs.extend(peer.ip_mac.values().cloned());
+            break;
+        }
+    }
+    if let Some(v) = config::PeerConfig::load(id)
+        .options
+        .get(PEER_OPTION_WOL_MAC)
+    {
+        macs.extend(v.split(',').filter(|x| !x.is_empty()).map(|x| x.to_owned()));
+    }
+    macs.sort();
+    macs.dedup();
+    macs
+}
+
+pub fn send_wol_to_macs(macs: &[String]) {
+    let interfaces = default_net::get_interfaces();
+    for mac in macs.iter() {
+        if let Ok(mac_addr) = mac.parse() {
+            for interface in &interfaces {
+                for ipv4 in &interface.ipv4 {
+                    // remove below mask check to avoid unexpected bug
+                    // if (u32::from(ipv4.addr) & u32::from(ipv4.netmask)) == (u32::from(peer_ip) & u32::from(ipv4.netmask))
+                    log::info!("Send wol to {mac_addr} of {}", ipv4.addr);
+                    allow_err!(wol::send_wol(mac_addr, None, Some(IpAddr::V4(ipv4.addr))));
                 }
             }
-            break;
         }
     }
 }
 
+/// The MAC addresses of the interfaces with an IPv4 address, to be woken up later.
+#[cfg(not(target_os = "ios"))]
+pub fn get_local_macs() -> Vec<String> {
+    let mut macs: Vec<String> = default_net::get_interfaces()
+        .into_iter()
+        .filter(|x| x.ipv4.iter().any(|ip| !ip.addr.is_loopback()))
+        .filter_map(|x| x.mac_addr.map(|m| m.address()))
+        .filter(|x| x != "00:00:00:00:00:00")
+        .collect();
+    macs.sort();
+    macs.dedup();
+    macs
+}
+
+/// Ask a connected peer to emit the magic packets for the MAC addresses on its LAN.
+pub fn make_wol_request(macs: &[String]) -> hbb_common::message_proto::Message {
+    use hbb_common::message_proto::{Misc, PluginRequest};
+    let mut misc = Misc::new();
+    misc.set_plugin_request(PluginRequest {
+        id: WOL_PLUGIN_ID.to_owned(),
+        content: serde_json::to_vec(macs).unwrap_or_default().into(),
+        ..Default::default()
+    });
+    let mut msg = hbb_common::message_proto::Message::new();
+    msg.set_misc(misc);
+    msg
+}
+
+pub fn handle_wol_request(content: &[u8]) {
+    match serde_json::from_slice::<Vec<String>>(content) {
+        Ok(macs) => {
+            log::info!("Relay wol to {:?}", macs);
+            send_wol_to_macs(&macs);
+        }
+        Err(err) => log::error!("Invalid wol request: {}", err),
+    }
+}
+
 #[inline]
 fn get_broadcast_port() -> u16 {
     (RENDEZVOUS_PORT + 3) as _
@@ -287,8 +379,7 @@ fn wait_response(
     Ok(())
 }
 
-fn spawn_wait_responses(sockets: Vec<UdpSocket>) -> UnboundedReceiver<config::DiscoveryPeer> {
-    let (tx, rx) = unbounded_channel::<_>();
+fn spawn_wait_responses(sockets: Vec<UdpSocket>, tx: UnboundedSender<config::DiscoveryPeer>) {
     for socket in sockets {
         let tx_clone = tx.clone();
         std::thread::spawn(move || {
@@ -299,7 +390,6 @@ fn spawn_wait_responses(sockets: Vec<UdpSocket>) -> UnboundedReceiver<config::Di
             ));
         });
     }
-    rx
 }
 
 async fn handle_received_peers(mut rx: UnboundedReceiver<config::DiscoveryPeer>) -> ResultType<()> {
@@ -317,8 +407,19 @@ async fn handle_received_peers(mut rx: UnboundedReceiver<config::DiscoveryPeer>)
                     let in_response_set = !response_set.insert(peer.id.clone());
                     if let Some(pos) = peers.iter().position(|x| x.is_same_peer(&peer) ) {
                         let peer1 = peers.remove(pos);
+                        // The peers found by mDNS have no username and MAC.
+                        if peer.username.is_empty() {
+                            peer.username = peer1.username.clone();
+                        }
+                        for (ip, mac) in peer1.ip_mac {
+                            if in_response_set || peer.ip_mac.contains_key(&ip) {
+                                let m = peer.ip_mac.entry(ip).or_default();
+                                if m.is_empty() {
+                                    *m = mac;
+                                }
+                            }
+                        }
                         if in_response_set {
-                            peer.ip_mac.extend(peer1.ip_mac);
                             peer.online = true;
                         }
                     }
@@ -0,0 +1,124 @@
+//! mDNS / DNS-SD discovery, for the networks where the UDP broadcast is blocked
+//! but multicast DNS is allowed.
+//!
+//! Every peer with LAN discovery enabled advertises `_rustdesk._tcp.local.` with its
+//! ID, hostname and platform in the TXT record. The username and the MAC are not in it, the
+//! record is cached and forwarded further than the answer of the broadcast ping, they are
+//! only filled by the ping if the broadcast gets through.
+
+use super::get_broadcast_port;
+use hbb_common::{
+    config::{self, Config},
+    log,
+    tokio::sync::mpsc::UnboundedSender,
+    ResultType,
+};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+const SERVICE_TYPE: &str = "_rustdesk._tcp.local.";
+const BROWSE_TIMEOUT: Duration = Duration::from_millis(3_000);
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+fn is_enabled() -> bool {
+    config::option2bool(
+        "enable-lan-discovery",
+        &Config::get_option("enable-lan-discovery"),
+    )
+}
+
+fn service_info() -> ResultType<ServiceInfo> {
+    let id = Config::get_id();
+    let mut hostname = crate::whoami_hostname();
+    if hostname == "localhost" {
+        hostname = "unknown".to_owned();
+    }
+    let platform = hbb_common::whoami::platform().to_string();
+    let properties = [
+        ("id", id.as_str()),
+        ("hostname", hostname.as_str()),
+        ("platform", platform.as_str()),
+    ];
+    let info = ServiceInfo::new(
+        SERVICE_TYPE,
+        &id,
+        &format!("rustdesk-{}.local.", id),
+        "",
+        get_broadcast_port(),
+        &properties[..],
+    )?
+    .enable_addr_auto();
+    Ok(info)
+}
+
+/// Advertise the service and keep it in sync with the ID and the option, never returns.
+pub(super) fn start_advertising() -> ResultType<()> {
+    let daemon = ServiceDaemon::new()?;
+    log::info!("mdns advertising started");
+    let mut registered: Option<(String, String)> = None; // (fullname, id)
+    loop {
+        let id = Config::get_id();
+        let enabled = is_enabled();
+        let stale = match registered.as_ref() {
+            Some((_, registered_id)) => !enabled || *registered_id != id,
+            None => false,
+        };
+        if stale {
+            if let Some((fullname, _)) = registered.take() {
+                daemon.unregister(&fullname).ok();
+            }
+        }
+        if enabled && registered.is_none() {
+            match service_info().and_then(|info| {
+                let fullname = info.get_fullname().to_owned();
+                daemon.register(info)?;
+                Ok(fullname)
+            }) {
+                Ok(fullname) => registered = Some((fullname, id)),
+                Err(err) => log::error!("mdns failed to register: {}", err),
+            }
+        }
+        std::thread::sleep(CHECK_INTERVAL);
+    }
+}
+
+/// Browse the peers for a while, the peers found are sent to `tx`.
+pub(super) fn browse(tx: UnboundedSender<config::DiscoveryPeer>) -> ResultType<()> {
+    let daemon = ServiceDaemon::new()?;
+    let receiver = daemon.browse(SERVICE_TYPE)?;
+    let my_id = Config::get_id();
+    let start = Instant::now();
+    while let Some(left) = BROWSE_TIMEOUT.checked_sub(start.elapsed()) {
+        let Ok(event) = receiver.recv_timeout(left) else {
+            break;
+        };
+        if let ServiceEvent::ServiceResolved(info) = event {
+            let prop = |k: &str| info.get_property_val_str(k).unwrap_or_default().to_owned();
+            let id = prop("id");
+            if id.is_empty() || id == my_id {
+                continue;
+            }
+            let ip_mac: HashMap<String, String> = info
+                .get_addresses()
+                .iter()
+                .filter(|ip| ip.is_ipv4())
+                .map(|ip| (ip.to_string(), String::new()))
+                .collect();
+            tx.send(config::DiscoveryPeer {
+                id,
+                ip_mac,
+                hostname: prop("hostname"),
+                platform: prop("platform"),
+                online: true,
+                ..Default::default()
+            })
+            .ok();
+        }
+    }
+    daemon.stop_browse(SERVICE_TYPE).ok();
+    daemon.shutdown().ok();
+    Ok(())
+}
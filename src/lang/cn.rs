@@ -721,5 +721,208 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("Show virtual joystick", "显示虚拟摇杆"),
         ("Edit note", "编辑备注"),
         ("Alias", "别名"),
+        ("Network settings", "网络设置"),
+        ("Direct connection only", "仅直连"),
+        ("peer_relay_server_tip", "本机请求中继时使用，留空则使用 ID 服务器提供的中继服务器"),
+        ("Allow session tunnels and SOCKS5 proxy", "允许会话内隧道和 SOCKS5 代理"),
+        ("Send the magic packet from", "发送唤醒包的设备"),
+        ("This device", "本机"),
+        ("Reconnect after (seconds)", "重连延时（秒）"),
+        ("wol_retry_tip", "{} 秒后重新连接"),
+        ("Allow Wake-on-LAN relaying", "允许代发网络唤醒"),
+        ("Relay server latency", "中继服务器延迟"),
+        ("relay_latency_tip", "配置多个中继服务器（以逗号分隔）时，每个连接使用延迟最低的可用中继服务器"),
+        ("Unreachable", "不可达"),
+        ("Security key", "安全密钥"),
+        ("Require security key", "需要安全密钥"),
+        ("require-security-key-tip", "连接方需在密码验证后使用已登记的 FIDO2 安全密钥进行确认。"),
+        ("Manage security keys", "管理安全密钥"),
+        ("security-keys-tip", "每行一个已登记的密钥。插入安全密钥后点击“登记安全密钥”，或粘贴在其他设备上登记的密钥。"),
+        ("Register a security key", "登记安全密钥"),
+        ("Security Key Required", "需要安全密钥"),
+        ("touch_security_key_tip", "请触摸您的安全密钥以完成登录。"),
+        ("Security key verification failed", "安全密钥验证失败"),
+        ("Peer permission profiles", "按设备设置权限"),
+        ("peer-profiles-tip", "为指定 ID 的控制端单独限制权限。由于 ID 由控制端自称，这里只能关闭权限。未勾选也未取消（横线）的项沿用全局设置。"),
+        ("Access control list", "访问控制列表"),
+        ("access-control-list-tip", "在验证密码之前，按 IP、IP 段、ID 和时间段允许或拒绝连接。"),
+        ("access-control-list-sep", "每行一条规则：allow|deny <IP、CIDR、ID 或 *> [星期，如 mon-fri] [时间段，如 08:00-18:00]。第一条匹配的规则生效；若存在 allow 规则，未匹配任何规则的连接将被拒绝。ID 由对方自行声明且未经验证，请使用 IP 规则限制可连接的设备。"),
+        ("Invalid rule", "无效的规则"),
+        ("Access denied by the access control list of the peer", "被对方的访问控制列表拒绝"),
+        ("Login lockouts", "登录锁定"),
+        ("login-lockouts-tip", "多次输错密码的来源（IP 或 ID@IP）会被锁定，时长逐次加倍，重启服务后依然有效。"),
+        ("No lockouts", "没有锁定记录"),
+        ("Source", "来源"),
+        ("Failed attempts", "失败次数"),
+        ("Locked until", "锁定至"),
+        ("Verify connection", "验证连接"),
+        ("sas_tip", "请通过电话或当面与对方核对以下表情，一致则说明连接未被中间人窃听。"),
+        ("They match", "一致"),
+        ("Verified", "已验证"),
+        ("Peer key changed", "对方密钥已变更"),
+        ("peer_key_changed_tip", "对方的密钥与之前验证的不同，连接可能被中间人截获。请重新验证连接。"),
+        ("Enable audit log", "启用审计日志"),
+        ("Push audit log to the server", "推送审计日志到服务器"),
+        ("Record and sign all incoming sessions", "录制并签名所有传入会话"),
+        ("Verify a recording", "验证录像"),
+        ("sign-incoming-recording-tip", "启用后，所有传入会话都会被录制，同时记录对方的输入事件。文件结束后以本机密钥分块签名，签名保存在同名的 .sig 文件中，可用于证明录像未被篡改。"),
+        ("recording-verified-tip", "录像未被修改，且由本设备的密钥签名，其指纹为："),
+        ("Single-use passwords", "单次密码"),
+        ("single-use-passwords-tip", "单次密码在首次登录成功后失效，或在到期后失效，并限制该连接的权限。"),
+        ("Generate", "生成"),
+        ("Expires", "过期时间"),
+        ("No expiry", "永不过期"),
+        ("10 minutes", "10 分钟"),
+        ("1 hour", "1 小时"),
+        ("1 day", "1 天"),
+        ("Protect security settings", "保护安全设置"),
+        ("Set admin password", "设置管理员密码"),
+        ("Protected settings", "受保护的设置"),
+        ("protect-settings-tip", "启用后，修改安全设置和永久密码需要输入双重认证验证码或管理员密码，该检查在服务中执行。"),
+        ("2FA code or admin password", "双重认证验证码或管理员密码"),
+        ("Wrong code or password", "验证码或密码错误"),
+        ("Server key pins", "服务器密钥固定"),
+        ("server-key-pins-tip", "设置后，通过 TLS（WebSocket 或 HTTPS）连接自定义服务器时，若服务器公钥不在列表中则拒绝连接。更换服务器证书前，请先添加新密钥，切换完成后再移除旧密钥。"),
+        ("Get current keys", "获取当前密钥"),
+        ("max_session_duration_option_tip", "限制传入会话的最长时长"),
+        ("Session duration limit reached", "已达到会话时长上限, 连接被自动断开"),
+        ("Session timeout", "会话超时"),
+        ("idle-timeout-warning-tip", "由于长时间无操作, 会话将在 1 分钟后断开。"),
+        ("max-session-duration-warning-tip", "会话将在 1 分钟后达到时长上限并断开。"),
+        ("privilege-separation-tip", "以当前用户权限运行网络服务, 仅由系统服务处理需要特权的操作 (重启服务后生效)"),
+        ("report-patch-level-tip", "向控制端和服务器报告系统版本和 RustDesk 版本"),
+        ("Minimum client version", "最低客户端版本"),
+        ("min-client-version-tip", "拒绝低于此版本的客户端连接, 留空则不限制"),
+        ("outdated-client-tip", "您的客户端版本过低, 对方要求更新后再连接"),
+        ("Hardware", "硬件"),
+        ("virtual_display_not_supported_tip", "不支持虚拟显示器。Linux 需要 X11 和提供虚拟输出的显卡驱动，例如无头模式下的 dummy 驱动；macOS 需要 10.14 及更高的版本。"),
+        ("Custom virtual display", "自定义虚拟显示器"),
+        ("Match window size", "匹配窗口大小"),
+        ("Width", "宽"),
+        ("Height", "高"),
+        ("Refresh rate", "刷新率"),
+        ("virtual_display_mode_invalid_tip", "宽和高需在 320 到 8192 之间，刷新率需在 24 到 240 之间。"),
+        ("Record the remote audio", "录制远端音频"),
+        ("Send my microphone", "发送我的麦克风"),
+        ("Allow playing the microphone of the peer", "允许播放对方的麦克风"),
+        ("Remote audio device", "远程音频设备"),
+        ("Application", "应用程序"),
+        ("Audio only", "仅音频"),
+        ("Forward gamepads", "转发游戏手柄"),
+        ("Allow remote gamepads", "允许远程游戏手柄"),
+        ("Relative mouse mode", "相对鼠标模式"),
+        ("Auto keyboard mode", "自动键盘模式"),
+        ("Key remapping and macros", "按键映射和宏"),
+        ("Press a key", "请按一个键"),
+        ("From key", "原按键"),
+        ("To key", "映射为"),
+        ("Trigger key", "触发键"),
+        ("steps", "步骤"),
+        ("key_macro_steps_tip", "每行一个步骤：text 文本、keys Ctrl+Alt+VK_DELETE 或 delay 毫秒数"),
+        ("Forward touch", "转发触摸"),
+        ("Special keys", "特殊按键"),
+        ("Play / Pause", "播放 / 暂停"),
+        ("Previous track", "上一曲"),
+        ("Next track", "下一曲"),
+        ("Record input", "录制输入"),
+        ("Stop recording input", "停止录制输入"),
+        ("Replay input", "回放输入"),
+        ("Input replay stopped", "输入回放已停止"),
+        ("Local echo", "本地回显"),
+        ("local_echo_tip", "在高延迟连接上，在画面到达前先显示输入的字符"),
+        ("input_blocked_by_peer_tip", "您的键盘和鼠标已被远程用户屏蔽"),
+        ("Pass shortcuts to the remote", "将快捷键传给远端"),
+        ("Edit", "编辑"),
+        ("Invalid shortcuts", "无效的快捷键"),
+        ("hotkey_passthrough_tip", "会话窗口获得焦点时，列表中的快捷键不再由本机处理，而是发送给远端。仅适用于输入源 2，X11 上会独占整个键盘。"),
+        ("passthrough_hotkeys_tip", "每行一个快捷键，例如 Alt+Tab、Ctrl+W 或 Win，留空则使用默认列表。"),
+        ("Synchronize folders", "同步文件夹"),
+        ("The folders are in sync", "文件夹已同步"),
+        ("Sync log", "同步日志"),
+        ("Comparing", "正在比较"),
+        ("Two-way", "双向"),
+        ("Local to remote", "本地到远程"),
+        ("Remote to local", "远程到本地"),
+        ("On conflict", "冲突时"),
+        ("Newest wins", "保留最新"),
+        ("Keep both", "保留两者"),
+        ("Delete the files missing in the source", "删除源中不存在的文件"),
+        ("Compare", "比较"),
+        ("Pause file transfers while a remote session lags", "远程会话卡顿时暂停文件传输"),
+        ("Transfer rate limit (KB/s)", "传输速率限制 (KB/s)"),
+        ("Upload rate limit", "上传速率限制"),
+        ("upload_rate_limit_tip", "此会话上传的速率限制，留空则只使用全局限制。"),
+        ("Corrupted files", "文件已损坏"),
+        ("corrupted_files_tip", "以下文件传输后与源文件不一致，是否重新传输？"),
+        ("Move up", "上移"),
+        ("Move down", "下移"),
+        ("Pause", "暂停"),
+        ("Concurrent transfers", "同时传输的任务数"),
+        ("concurrent_transfers_tip", "超出的任务会排队等待，留空或为0表示不限制。"),
+        ("Transfer the extended attributes of the files", "传输文件的扩展属性"),
+        ("Copy to", "复制到"),
+        ("Move to", "移动到"),
+        ("Path", "路径"),
+        ("Contains", "包含"),
+        ("Created", "创建时间"),
+        ("Accessed", "访问时间"),
+        ("Folder", "文件夹"),
+        ("File", "文件"),
+        ("Symbolic link", "符号链接"),
+        ("folders", "个文件夹"),
+        ("Yes", "是"),
+        ("search_files_tip", "在所有子文件夹中查找名称，支持 * 和 ? 通配符"),
+        ("Sending to", "正在发送到"),
+        ("Sent to", "已发送到"),
+        ("Failed to get the folder", "获取文件夹失败"),
+        ("Transfer history", "传输历史"),
+        ("Transfer done", "传输完成"),
+        ("Transfer failed", "传输失败"),
+        ("Cancelled", "已取消"),
+        ("Export", "导出"),
+        ("Duration", "时长"),
+        ("Result", "结果"),
+        ("Time", "时间"),
+        ("Notify when a file transfer is done or failed", "文件传输完成或失败时通知"),
+        ("Send to another device", "发送到其他设备"),
+        ("Destination folder", "目标文件夹"),
+        ("Not connected to the peer", "未连接到该设备"),
+        ("transfer_broker_tip", "文件由对方直接发送到所选设备，不经过本机。"),
+        ("transfer_broker_no_peer_tip", "没有其他已连接且支持的设备。"),
+        ("Clipboard limits", "剪贴板限制"),
+        ("Both directions", "双向"),
+        ("Send only", "仅发送"),
+        ("Receive only", "仅接收"),
+        ("Clipboard size limit (KB)", "剪贴板大小限制 (KB)"),
+        ("Clipboard filters", "剪贴板过滤"),
+        ("clipboard_filters_tip", "每行一个正则表达式，文本匹配其中任意一个的复制内容不会发送给对方。"),
+        ("Clipboard history", "剪贴板历史"),
+        ("Manual clipboard sync", "手动同步剪贴板"),
+        ("Push", "推送"),
+        ("Pull", "拉取"),
+        ("Pushed", "已推送"),
+        ("Pulled", "已拉取"),
+        ("Clipboard", "剪贴板"),
+        ("Pasting files", "正在粘贴文件"),
+        ("Hide", "隐藏"),
+        ("Headless session", "无头会话"),
+        ("Auto login user", "自动登录用户"),
+        ("linux_headless_user_tip", "无人登录时，服务自动登录该用户的无头会话，无需密码"),
+        ("Switch Windows session", "切换 Windows 会话"),
+        ("switch_windows_session_other_connections_tip", "此设备还有其他连接，请在它们断开后再切换会话。"),
+        ("Reduce the frame rate on battery or when overheated", "使用电池或过热时降低帧率"),
+        ("remote-power-saving-tip", "远程设备正在使用电池或过热，帧率已降低。"),
+        ("remote-on-battery-tip", "远程设备正在使用电池，帧率已降低。"),
+        ("remote-overheated-tip", "远程设备过热，帧率已降低。"),
+        ("Denied by the session hook", "被会话钩子拒绝"),
+        ("unlock-locked-out-tip", "失败次数过多，请稍后再试，剩余"),
+        ("Session tunnels", "会话隧道"),
+        ("Local forward", "本地转发"),
+        ("Reverse forward", "反向转发"),
+        ("SOCKS5 proxy", "SOCKS5 代理"),
+        ("allow-tunnel-public-listen-tip", "允许会话隧道在非回环地址上监听"),
+        ("Bandwidth estimation", "带宽估计"),
+        ("recording-partially-signed-tip", "录制未正常结束，仅已签名的部分经过验证，其后的数据可能是后来追加的。"),
+        ("settings-locked-tip", "安全设置已受保护，请先解锁。"),
     ].iter().cloned().collect();
 }
@@ -258,5 +258,53 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("websocket_tip", "When using WebSocket, only relay connections are supported."),
         ("terminal-admin-login-tip", "Please input the administrator username and password of the controlled side."),
         ("elevation_username_tip", "Input username or domain\\username"),
+        ("peer_relay_server_tip", "Used when this device requests a relay, leave empty to use the one provided by the ID server"),
+        ("wol_retry_tip", "Reconnecting in {} seconds"),
+        ("relay_latency_tip", "When multiple relay servers are configured, separated by commas, each connection uses the reachable one with the lowest latency"),
+        ("require-security-key-tip", "The connecting side has to confirm with a registered FIDO2 security key after the password is accepted."),
+        ("security-keys-tip", "One registered key per line. Plug in a security key and click \"Register a security key\", or paste a key registered on another device."),
+        ("touch_security_key_tip", "Please touch your security key to complete the login."),
+        ("peer-profiles-tip", "Restrict the permissions of specific peer IDs. As the ID is claimed by the peer, a permission can only be turned off here. An item left indeterminate (dash) follows the global setting."),
+        ("access-control-list-tip", "Allow or deny connections by IP, IP range, ID and time window, before the password is checked."),
+        ("access-control-list-sep", "One rule per line: allow|deny <IP, CIDR, ID or *> [days, e.g. mon-fri] [time, e.g. 08:00-18:00]. The first matching rule applies; if there is any allow rule, connections matching no rule are rejected. The ID is claimed by the peer and not verified, use IP rules to restrict who can connect."),
+        ("login-lockouts-tip", "Sources (IP or ID@IP) with repeated wrong passwords are locked out, twice as long each time, also across service restarts."),
+        ("sas_tip", "Compare these emojis with the other side by phone or in person. If they match, nobody is intercepting the connection."),
+        ("peer_key_changed_tip", "The key of the peer differs from the one verified before. The connection may be intercepted, please verify the connection again."),
+        ("sign-incoming-recording-tip", "All incoming sessions are recorded together with the input events of the peer. Finished files are signed in chunks with the device key into a .sig file next to them, which proves they were not modified."),
+        ("recording-verified-tip", "The recording is unmodified and signed by the key of this device, with the fingerprint:"),
+        ("recording-partially-signed-tip", "The recording was not finished, only its signed part is verified, the data after it may have been appended later."),
+        ("single-use-passwords-tip", "A single-use password is invalidated by the first successful login or when it expires, and limits the permissions of that connection."),
+        ("protect-settings-tip", "Changing the security settings and the permanent password requires the 2FA code or the admin password. The check is done by the service, not only by this window."),
+        ("unlock-locked-out-tip", "Too many failed attempts, try again in"),
+        ("settings-locked-tip", "The security settings are protected, unlock them first."),
+        ("server-key-pins-tip", "If set, TLS connections (WebSocket or HTTPS) to the custom servers are refused unless the server key is in the list. Before replacing the server certificate, add the new key, and remove the old one after the switch."),
+        ("max_session_duration_option_tip", "Limit the maximum duration of incoming sessions"),
+        ("Session duration limit reached", "Automatically disconnected as the session duration limit was reached"),
+        ("idle-timeout-warning-tip", "The session will be closed in one minute due to inactivity."),
+        ("max-session-duration-warning-tip", "The session will be closed in one minute as it reaches the duration limit."),
+        ("privilege-separation-tip", "Run the network-facing server with the privileges of the logged-in user, only privileged operations are done by the system service (takes effect after restarting the service)"),
+        ("report-patch-level-tip", "Report the OS version and RustDesk version to controlling peers and the server"),
+        ("min-client-version-tip", "Refuse connections from clients older than this version, no limit if empty"),
+        ("outdated-client-tip", "Your client is outdated, the remote side requires a newer version"),
+        ("virtual_display_not_supported_tip", "Virtual display is not supported. On Linux, X11 and a graphics driver providing virtual outputs are required, e.g. the dummy driver of the headless mode. On macOS, version 10.14 or newer is required."),
+        ("virtual_display_mode_invalid_tip", "The width and height must be between 320 and 8192, and the refresh rate between 24 and 240."),
+        ("key_macro_steps_tip", "One step per line: text <text>, keys <combination like Ctrl+Alt+VK_DELETE> or delay <milliseconds>"),
+        ("local_echo_tip", "On slow connections, show the typed characters where you clicked before the screen updates"),
+        ("upload_rate_limit_tip", "The rate limit of the uploads of this session, empty for the global limit only."),
+        ("corrupted_files_tip", "These files differ from their source after the transfer. Transfer them again?"),
+        ("concurrent_transfers_tip", "The other jobs wait in the queue. Empty or 0 for no limit."),
+        ("remote-power-saving-tip", "The remote device is on battery or overheated, its frame rate is reduced."),
+        ("remote-on-battery-tip", "The remote device is on battery, its frame rate is reduced."),
+        ("remote-overheated-tip", "The remote device is overheated, its frame rate is reduced."),
+        ("switch_windows_session_other_connections_tip", "There are other connections to this device, please switch the session after they are closed."),
+        ("linux_headless_user_tip", "The service logs this user into the headless session when nobody is logged in, without the password."),
+        ("clipboard_filters_tip", "One regular expression per line, a copy whose text matches any of them is not sent to the peer."),
+        ("transfer_broker_tip", "The files are sent by the peer to the selected device directly, without going through this device."),
+        ("transfer_broker_no_peer_tip", "No other connected device supports it."),
+        ("search_files_tip", "Names to find in all the subfolders, with * and ? as wildcards"),
+        ("hotkey_passthrough_tip", "While a session window is focused, the listed shortcuts are sent to the remote side instead of being handled locally. Only for Input source 2, on X11 the whole keyboard is grabbed."),
+        ("passthrough_hotkeys_tip", "One shortcut per line, e.g. Alt+Tab, Ctrl+W or Win, empty for the default list."),
+        ("input_blocked_by_peer_tip", "Your keyboard and mouse are blocked by the remote user"),
+        ("allow-tunnel-public-listen-tip", "Allow the reverse session tunnels to listen on the addresses which are not loopback"),
     ].iter().cloned().collect();
 }
@@ -721,5 +721,208 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("Show virtual joystick", "نمایش جوی‌استیک مجازی"),
         ("Edit note", ""),
         ("Alias", ""),
+        ("Network settings", ""),
+        ("Direct connection only", ""),
+        ("peer_relay_server_tip", ""),
+        ("Allow session tunnels and SOCKS5 proxy", ""),
+        ("Send the magic packet from", ""),
+        ("This device", ""),
+        ("Reconnect after (seconds)", ""),
+        ("wol_retry_tip", ""),
+        ("Allow Wake-on-LAN relaying", ""),
+        ("Relay server latency", ""),
+        ("relay_latency_tip", ""),
+        ("Unreachable", ""),
+        ("Security key", ""),
+        ("Require security key", ""),
+        ("require-security-key-tip", ""),
+        ("Manage security keys", ""),
+        ("security-keys-tip", ""),
+        ("Register a security key", ""),
+        ("Security Key Required", ""),
+        ("touch_security_key_tip", ""),
+        ("Security key verification failed", ""),
+        ("Peer permission profiles", ""),
+        ("peer-profiles-tip", ""),
+        ("Access control list", ""),
+        ("access-control-list-tip", ""),
+        ("access-control-list-sep", ""),
+        ("Invalid rule", ""),
+        ("Access denied by the access control list of the peer", ""),
+        ("Login lockouts", ""),
+        ("login-lockouts-tip", ""),
+        ("No lockouts", ""),
+        ("Source", ""),
+        ("Failed attempts", ""),
+        ("Locked until", ""),
+        ("Verify connection", ""),
+        ("sas_tip", ""),
+        ("They match", ""),
+        ("Verified", ""),
+        ("Peer key changed", ""),
+        ("peer_key_changed_tip", ""),
+        ("Enable audit log", ""),
+        ("Push audit log to the server", ""),
+        ("Record and sign all incoming sessions", ""),
+        ("Verify a recording", ""),
+        ("sign-incoming-recording-tip", ""),
+        ("recording-verified-tip", ""),
+        ("Single-use passwords", ""),
+        ("single-use-passwords-tip", ""),
+        ("Generate", ""),
+        ("Expires", ""),
+        ("No expiry", ""),
+        ("10 minutes", ""),
+        ("1 hour", ""),
+        ("1 day", ""),
+        ("Protect security settings", ""),
+        ("Set admin password", ""),
+        ("Protected settings", ""),
+        ("protect-settings-tip", ""),
+        ("2FA code or admin password", ""),
+        ("Wrong code or password", ""),
+        ("Server key pins", ""),
+        ("server-key-pins-tip", ""),
+        ("Get current keys", ""),
+        ("max_session_duration_option_tip", ""),
+        ("Session duration limit reached", ""),
+        ("Session timeout", ""),
+        ("idle-timeout-warning-tip", ""),
+        ("max-session-duration-warning-tip", ""),
+        ("privilege-separation-tip", ""),
+        ("report-patch-level-tip", ""),
+        ("Minimum client version", ""),
+        ("min-client-version-tip", ""),
+        ("outdated-client-tip", ""),
+        ("Hardware", ""),
+        ("virtual_display_not_supported_tip", ""),
+        ("Custom virtual display", ""),
+        ("Match window size", ""),
+        ("Width", ""),
+        ("Height", ""),
+        ("Refresh rate", ""),
+        ("virtual_display_mode_invalid_tip", ""),
+        ("Record the remote audio", ""),
+        ("Send my microphone", ""),
+        ("Allow playing the microphone of the peer", ""),
+        ("Remote audio device", ""),
+        ("Application", ""),
+        ("Audio only", ""),
+        ("Forward gamepads", ""),
+        ("Allow remote gamepads", ""),
+        ("Relative mouse mode", ""),
+        ("Auto keyboard mode", ""),
+        ("Key remapping and macros", ""),
+        ("Press a key", ""),
+        ("From key", ""),
+        ("To key", ""),
+        ("Trigger key", ""),
+        ("steps", ""),
+        ("key_macro_steps_tip", ""),
+        ("Forward touch", ""),
+        ("Special keys", ""),
+        ("Play / Pause", ""),
+        ("Previous track", ""),
+        ("Next track", ""),
+        ("Record input", ""),
+        ("Stop recording input", ""),
+        ("Replay input", ""),
+        ("Input replay stopped", ""),
+        ("Local echo", ""),
+        ("local_echo_tip", ""),
+        ("input_blocked_by_peer_tip", ""),
+        ("Pass shortcuts to the remote", ""),
+        ("Edit", ""),
+        ("Invalid shortcuts", ""),
+        ("hotkey_passthrough_tip", ""),
+        ("passthrough_hotkeys_tip", ""),
+        ("Synchronize folders", ""),
+        ("The folders are in sync", ""),
+        ("Sync log", ""),
+        ("Comparing", ""),
+        ("Two-way", ""),
+        ("Local to remote", ""),
+        ("Remote to local", ""),
+        ("On conflict", ""),
+        ("Newest wins", ""),
+        ("Keep both", ""),
+        ("Delete the files missing in the source", ""),
+        ("Compare", ""),
+        ("Pause file transfers while a remote session lags", ""),
+        ("Transfer rate limit (KB/s)", ""),
+        ("Upload rate limit", ""),
+        ("upload_rate_limit_tip", ""),
+        ("Corrupted files", ""),
+        ("corrupted_files_tip", ""),
+        ("Move up", ""),
+        ("Move down", ""),
+        ("Pause", ""),
+        ("Concurrent transfers", ""),
+        ("concurrent_transfers_tip", ""),
+        ("Transfer the extended attributes of the files", ""),
+        ("Copy to", ""),
+        ("Move to", ""),
+        ("Path", ""),
+        ("Contains", ""),
+        ("Created", ""),
+        ("Accessed", ""),
+        ("Folder", ""),
+        ("File", ""),
+        ("Symbolic link", ""),
+        ("folders", ""),
+        ("Yes", ""),
+        ("search_files_tip", ""),
+        ("Sending to", ""),
+        ("Sent to", ""),
+        ("Failed to get the folder", ""),
+        ("Transfer history", ""),
+        ("Transfer done", ""),
+        ("Transfer failed", ""),
+        ("Cancelled", ""),
+        ("Export", ""),
+        ("Duration", ""),
+        ("Result", ""),
+        ("Time", ""),
+        ("Notify when a file transfer is done or failed", ""),
+        ("Send to another device", ""),
+        ("Destination folder", ""),
+        ("Not connected to the peer", ""),
+        ("transfer_broker_tip", ""),
+        ("transfer_broker_no_peer_tip", ""),
+        ("Clipboard limits", ""),
+        ("Both directions", ""),
+        ("Send only", ""),
+        ("Receive only", ""),
+        ("Clipboard size limit (KB)", ""),
+        ("Clipboard filters", ""),
+        ("clipboard_filters_tip", ""),
+        ("Clipboard history", ""),
+        ("Manual clipboard sync", ""),
+        ("Push", ""),
+        ("Pull", ""),
+        ("Pushed", ""),
+        ("Pulled", ""),
+        ("Clipboard", ""),
+        ("Pasting files", ""),
+        ("Hide", ""),
+        ("Headless session", ""),
+        ("Auto login user", ""),
+        ("linux_headless_user_tip", ""),
+        ("Switch Windows session", ""),
+        ("switch_windows_session_other_connections_tip", ""),
+        ("Reduce the frame rate on battery or when overheated", ""),
+        ("remote-power-saving-tip", ""),
+        ("remote-on-battery-tip", ""),
+        ("remote-overheated-tip", ""),
+        ("Denied by the session hook", ""),
+        ("unlock-locked-out-tip", ""),
+        ("Session tunnels", ""),
+        ("Local forward", ""),
+        ("Reverse forward", ""),
+        ("SOCKS5 proxy", ""),
+        ("allow-tunnel-public-listen-tip", ""),
+        ("Bandwidth estimation", ""),
+        ("recording-partially-signed-tip", ""),
+        ("settings-locked-tip", ""),
     ].iter().cloned().collect();
 }
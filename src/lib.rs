@@ -37,15 +37,20 @@ pub mod flutter;
 pub mod flutter_ffi;
 use common::*;
 mod auth_2fa;
+mod auth_security_key;
 #[cfg(feature = "cli")]
 pub mod cli;
 #[cfg(not(target_os = "ios"))]
 mod clipboard;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod clipboard_policy;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod clipboard_history;
 #[cfg(not(any(target_os = "android", target_os = "ios", feature = "cli")))]
 pub mod core_main;
 mod custom_server;
 mod lang;
-#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[cfg(not(target_os = "android"))]
 mod port_forward;
 
 #[cfg(all(feature = "flutter", feature = "plugin_framework"))]
@@ -69,10 +74,51 @@ mod hbbs_http;
 
 #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
 pub mod clipboard_file;
+#[cfg(any(target_os = "windows", feature = "unix-file-copy-paste"))]
+mod clipboard_paste;
 
 pub mod privacy_mode;
 
 #[cfg(windows)]
 pub mod virtual_display_manager;
 
+mod ice;
 mod kcp_stream;
+mod tunnel;
+mod relay_latency;
+mod compression;
+mod sas;
+mod settings_guard;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod deploy_config;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod policy;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod logging;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod crash_report;
+mod tls_pin;
+mod patch_level;
+mod gamepad;
+mod pen;
+mod touch;
+mod special_keys;
+mod input_script;
+mod key_remap;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod hotkey_passthrough;
+mod transfer_checkpoint;
+mod delta_sync;
+mod transfer_throttle;
+mod transfer_integrity;
+mod transfer_metadata;
+mod file_ops;
+mod file_drop;
+mod file_stream;
+mod file_compression;
+mod transfer_history;
+mod transfer_broker;
+#[cfg(any(target_os = "android", target_os = "ios", feature = "flutter"))]
+mod folder_sync;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod keyboard_layout;
@@ -0,0 +1,323 @@
+//! The logger of the desktop processes, instead of the one of `hbb_common::init_log`.
+//!
+//! A line is a JSON object, or plain text if `log-format` is `text`, with the time, the level,
+//! the module, the message, and the connection id and the peer id if logged by a connection.
+//!
+//! The levels are set by `log-levels` in the syntax of `RUST_LOG`, the most specific module
+//! wins, e.g. `info,librustdesk::server::connection=debug,webrtc=off`. They can be changed at
+//! runtime without restarting the service by `--log-levels <levels>`, by IPC.
+//!
+//! The files are `<log dir>/<process name>/rustdesk.jsonl`, rotated by the size.
+
+use hbb_common::{
+    bail,
+    config::Config,
+    log::{self, LevelFilter, Log, Metadata, Record},
+    tokio, ResultType,
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
+pub const OPTION_LOG_LEVELS: &str = "log-levels";
+pub const OPTION_LOG_FORMAT: &str = "log-format";
+#[cfg(debug_assertions)]
+const DEFAULT_LEVELS: &str = "debug";
+#[cfg(not(debug_assertions))]
+const DEFAULT_LEVELS: &str = "info";
+const FILE_STEM: &str = "rustdesk";
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const MAX_FILES: usize = 5;
+
+/// The fields of the connection which logs, in the task of the connection.
+#[derive(Debug, Default)]
+pub struct Context {
+    pub conn_id: i32,
+    pub peer_id: String,
+}
+
+tokio::task_local! {
+    static CONTEXT: Arc<Mutex<Context>>;
+}
+
+/// Runs the future of a connection, whose logs carry its fields.
+pub async fn scope<F: std::future::Future>(conn_id: i32, f: F) -> F::Output {
+    let ctx = Arc::new(Mutex::new(Context {
+        conn_id,
+        ..Default::default()
+    }));
+    CONTEXT.scope(ctx, f).await
+}
+
+/// Sets the peer id of the connection running in the current task, once it is known.
+pub fn set_peer_id(peer_id: &str) {
+    CONTEXT
+        .try_with(|ctx| ctx.lock().unwrap().peer_id = peer_id.to_owned())
+        .ok();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Levels {
+    default: LevelFilter,
+    // Sorted by the length of the module, the longest first.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Levels {
+    fn parse(spec: &str) -> ResultType<Self> {
+        let mut levels = Self {
+            default: LevelFilter::Info,
+            modules: vec![],
+        };
+        for item in spec.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let (module, level) = match item.split_once('=') {
+                Some((m, l)) => (Some(m.trim()), l.trim()),
+                None => (None, item),
+            };
+            let Ok(level) = level.parse::<LevelFilter>() else {
+                bail!("Wrong log level: {}", item);
+            };
+            match module {
+                Some("") => bail!("Empty module: {}", item),
+                Some(m) => levels.modules.push((m.to_owned(), level)),
+                None => levels.default = level,
+            }
+        }
+        levels.modules.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        Ok(levels)
+    }
+
+    fn get(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(m, _)| {
+                target == m
+                    || target
+                        .strip_prefix(m.as_str())
+                        .map_or(false, |t| t.starts_with("::"))
+            })
+            .map_or(self.default, |(_, l)| *l)
+    }
+
+    fn max(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, l)| *l)
+            .fold(self.default, std::cmp::max)
+    }
+}
+
+struct Logger {
+    levels: RwLock<Levels>,
+    json: bool,
+    dir: PathBuf,
+    // The file and its size.
+    file: Mutex<Option<(File, u64)>>,
+}
+
+fn file_path(dir: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        dir.join(format!("{}.jsonl", FILE_STEM))
+    } else {
+        dir.join(format!("{}.{}.jsonl", FILE_STEM, index))
+    }
+}
+
+// rustdesk.jsonl -> rustdesk.1.jsonl -> ... -> rustdesk.{MAX_FILES - 1}.jsonl
+fn rotate(dir: &Path) {
+    fs::remove_file(file_path(dir, MAX_FILES - 1)).ok();
+    for i in (0..MAX_FILES - 1).rev() {
+        fs::rename(file_path(dir, i), file_path(dir, i + 1)).ok();
+    }
+}
+
+impl Logger {
+    fn format(&self, record: &Record) -> String {
+        let time = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z");
+        let (conn_id, peer_id) = CONTEXT
+            .try_with(|ctx| {
+                let ctx = ctx.lock().unwrap();
+                (Some(ctx.conn_id), ctx.peer_id.clone())
+            })
+            .unwrap_or_default();
+        if self.json {
+            let mut v = serde_json::json!({
+                "time": time.to_string(),
+                "level": record.level().as_str(),
+                "module": record.target(),
+                "msg": record.args().to_string(),
+            });
+            if let Some(line) = record.line() {
+                v["line"] = line.into();
+            }
+            if let Some(conn_id) = conn_id {
+                v["conn_id"] = conn_id.into();
+            }
+            if !peer_id.is_empty() {
+                v["peer_id"] = peer_id.into();
+            }
+            v.to_string()
+        } else {
+            let mut fields = String::new();
+            if let Some(conn_id) = conn_id {
+                fields = format!(" [conn {}", conn_id);
+                if !peer_id.is_empty() {
+                    fields += &format!(" peer {}", peer_id);
+                }
+                fields += "]";
+            }
+            format!(
+                "[{}] {} [{}:{}]{} {}",
+                time,
+                record.level(),
+                record.target(),
+                record.line().unwrap_or_default(),
+                fields,
+                record.args()
+            )
+        }
+    }
+
+    fn write(&self, line: &str) {
+        let mut file = self.file.lock().unwrap();
+        if file
+            .as_ref()
+            .map_or(false, |(_, size)| *size >= MAX_FILE_SIZE)
+        {
+            *file = None;
+            rotate(&self.dir);
+        }
+        if file.is_none() {
+            fs::create_dir_all(&self.dir).ok();
+            *file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file_path(&self.dir, 0))
+                .ok()
+                .map(|f| {
+                    let size = f.metadata().map_or(0, |m| m.len());
+                    (f, size)
+                });
+        }
+        let ok = file.as_mut().map_or(true, |(f, size)| {
+            *size += line.len() as u64 + 1;
+            writeln!(f, "{}", line).is_ok()
+        });
+        if !ok {
+            *file = None;
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.levels.read().unwrap().get(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = self.format(record);
+        #[cfg(debug_assertions)]
+        eprintln!("{}", line);
+        self.write(&line);
+    }
+
+    fn flush(&self) {
+        if let Some((f, _)) = self.file.lock().unwrap().as_mut() {
+            f.flush().ok();
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref LOGGER: Mutex<Option<&'static Logger>> = Default::default();
+}
+
+pub fn get_levels() -> String {
+    let spec = Config::get_option(OPTION_LOG_LEVELS);
+    if spec.is_empty() {
+        DEFAULT_LEVELS.to_owned()
+    } else {
+        spec
+    }
+}
+
+/// Installs the logger, the name is the sub directory of the process, as `hbb_common::init_log`.
+pub fn init(name: &str) {
+    let mut lock = LOGGER.lock().unwrap();
+    if lock.is_some() {
+        return;
+    }
+    let levels = Levels::parse(&get_levels()).unwrap_or_else(|err| {
+        eprintln!("{}, {} is used", err, DEFAULT_LEVELS);
+        Levels::parse(DEFAULT_LEVELS).unwrap_or(Levels {
+            default: LevelFilter::Info,
+            modules: vec![],
+        })
+    });
+    let mut dir = Config::log_path();
+    if !name.is_empty() {
+        dir.push(name);
+    }
+    let max = levels.max();
+    let logger: &'static Logger = Box::leak(Box::new(Logger {
+        levels: RwLock::new(levels),
+        json: Config::get_option(OPTION_LOG_FORMAT) != "text",
+        dir,
+        file: Default::default(),
+    }));
+    if log::set_logger(logger).is_err() {
+        return;
+    }
+    log::set_max_level(max);
+    *lock = Some(logger);
+}
+
+/// Changes the levels of this process and saves them, empty for the default.
+pub fn set_levels(spec: &str) -> ResultType<()> {
+    let levels = Levels::parse(if spec.is_empty() {
+        DEFAULT_LEVELS
+    } else {
+        spec
+    })?;
+    if let Some(logger) = LOGGER.lock().unwrap().as_ref() {
+        log::set_max_level(levels.max());
+        *logger.levels.write().unwrap() = levels;
+    }
+    Config::set_option(OPTION_LOG_LEVELS.to_owned(), spec.to_owned());
+    log::info!("Log levels set to {:?}", spec);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        let levels =
+            Levels::parse("warn, librustdesk::server=debug,librustdesk::server::video_service=off")
+                .unwrap();
+        assert_eq!(levels.get("librustdesk::client"), LevelFilter::Warn);
+        assert_eq!(levels.get("librustdesk::server"), LevelFilter::Debug);
+        assert_eq!(
+            levels.get("librustdesk::server::connection"),
+            LevelFilter::Debug
+        );
+        assert_eq!(
+            levels.get("librustdesk::server::video_service"),
+            LevelFilter::Off
+        );
+        // Not a sub module.
+        assert_eq!(levels.get("librustdesk::server2"), LevelFilter::Warn);
+        assert_eq!(levels.max(), LevelFilter::Debug);
+        assert!(Levels::parse("librustdesk=verbose").is_err());
+        assert!(Levels::parse("=debug").is_err());
+        assert_eq!(Levels::parse("").unwrap().default, LevelFilter::Info);
+    }
+}
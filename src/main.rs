@@ -43,9 +43,18 @@ fn main() {
     }
     use clap::App;
     use hbb_common::log;
+    let cli_args: Vec<String> = std::env::args().skip(1).collect();
+    if cli_args.first().map(|a| a.as_str()) == Some("cli") {
+        use hbb_common::env_logger::*;
+        init_from_env(Env::default().filter_or(DEFAULT_FILTER_ENV, "warn"));
+        let code = cli::run(&cli_args[1..]);
+        common::global_clean();
+        std::process::exit(code);
+    }
     let args = format!(
         "-p, --port-forward=[PORT-FORWARD-OPTIONS] 'Format: remote-id:local-port:remote-port[:remote-host]'
         -c, --connect=[REMOTE_ID] 'test only'
+        -S, --screenshot=[SCREENSHOT-OPTIONS] 'Format: remote-id:display:png-path'
         -k, --key=[KEY] ''
        -s, --server=[] 'Start server'",
     );
@@ -93,6 +102,22 @@ fn main() {
             key,
             token,
         );
+    } else if let Some(p) = matches.value_of("screenshot") {
+        // The path may contain ':' on Windows.
+        let options: Vec<String> = p.splitn(3, ":").map(|x| x.to_owned()).collect();
+        if options.len() < 3 {
+            log::error!("Wrong screenshot options");
+            return;
+        }
+        let Ok(display) = options[1].parse::<i32>() else {
+            log::error!("Wrong display");
+            return;
+        };
+        common::test_rendezvous_server();
+        common::test_nat_type();
+        let key = matches.value_of("key").unwrap_or("").to_owned();
+        let token = LocalConfig::get_option("access_token");
+        cli::take_screenshot(options[0].clone(), display, options[2].clone(), key, token);
     } else if let Some(p) = matches.value_of("connect") {
         common::test_rendezvous_server();
         common::test_nat_type();
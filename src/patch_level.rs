@@ -0,0 +1,79 @@
+//! OS and RustDesk version of the controlled side, for admins to find outdated agents.
+//!
+//! If allowed, the controlled side reports it to the controlling side in the peer info, where
+//! it is kept in the peer config, and to the API server with the heartbeat. Independently,
+//! the controlled side can refuse clients older than a minimum version.
+
+use hbb_common::{config::Config, get_version_number, log, sysinfo::System};
+use serde_json::{json, Value};
+
+pub const OPTION_REPORT_PATCH_LEVEL: &str = "allow-report-patch-level";
+pub const OPTION_MIN_CLIENT_VERSION: &str = "min-client-version";
+// The key in `platform_additions` and in the heartbeat.
+pub const KEY: &str = "patch_level";
+// The peer option of the controlling side.
+pub const PEER_OPTION: &str = "patch-level";
+pub const LOGIN_MSG_OUTDATED_CLIENT: &str = "outdated-client-tip";
+
+pub fn is_report_allowed() -> bool {
+    Config::get_option(OPTION_REPORT_PATCH_LEVEL) == "Y"
+}
+
+pub fn get() -> Value {
+    let system = System::new();
+    json!({
+        "os": system.name().unwrap_or_default(),
+        "os_version": system.os_version().unwrap_or_default(),
+        "os_build": system.kernel_version().unwrap_or_default(),
+        "version": crate::VERSION,
+    })
+}
+
+/// `None` if the report is not allowed.
+pub fn get_if_allowed() -> Option<Value> {
+    if is_report_allowed() {
+        Some(get())
+    } else {
+        None
+    }
+}
+
+fn is_version_allowed(version: &str, min: &str) -> bool {
+    let min = min.trim();
+    min.is_empty() || get_version_number(version) >= get_version_number(min)
+}
+
+pub fn is_client_version_allowed(version: &str) -> bool {
+    let min = Config::get_option(OPTION_MIN_CLIENT_VERSION);
+    let allowed = is_version_allowed(version, &min);
+    if !allowed {
+        log::warn!("Refused the client of version {}, minimum {}", version, min);
+    }
+    allowed
+}
+
+/// The patch level in the `platform_additions` of the peer info, as a JSON string.
+pub fn from_platform_additions(platform_additions: &str) -> Option<String> {
+    let v: Value = serde_json::from_str(platform_additions).ok()?;
+    let v = v.get(KEY)?;
+    v.is_object().then(|| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_allowed() {
+        assert!(is_version_allowed("1.3.0", ""));
+        assert!(is_version_allowed("1.3.0", "1.2.7"));
+        assert!(is_version_allowed("1.3.0", "1.3.0"));
+        assert!(!is_version_allowed("1.2.7", "1.3.0"));
+        assert!(!is_version_allowed("", "1.3.0"));
+        assert_eq!(
+            from_platform_additions(r#"{"patch_level":{"version":"1.3.0"}}"#),
+            Some(r#"{"version":"1.3.0"}"#.to_owned())
+        );
+        assert_eq!(from_platform_additions(r#"{"headless":true}"#), None);
+    }
+}
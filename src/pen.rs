@@ -0,0 +1,265 @@
+//! Pen and stylus input.
+//!
+//! The controlling side sends the pen events of the remote view, with the pressure, the
+//! tilt, the eraser and the hover, in the coordinates of the mouse events. The controlled
+//! side injects them on a synthetic pen, Windows Ink on Windows and a uinput tablet on
+//! Linux, for the drawing applications to get the pressure and the tilt.
+//!
+//! The events are carried by `PluginRequest` with a reserved id, no protocol change is needed.
+
+use hbb_common::message_proto::{Message, Misc, PluginRequest};
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+use hbb_common::ResultType;
+use serde_derive::{Deserialize, Serialize};
+
+/// The content is a [`PenEvent`] in json.
+pub const PLUGIN_ID: &str = "__pen";
+pub const MAX_PRESSURE: u32 = 1024;
+pub const MAX_TILT: i32 = 90;
+
+/// The state of the pen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PenEvent {
+    pub x: i32,
+    pub y: i32,
+    /// 0 to [`MAX_PRESSURE`].
+    #[serde(default)]
+    pub pressure: u32,
+    /// Degrees, positive to the right.
+    #[serde(default)]
+    pub tilt_x: i32,
+    /// Degrees, positive toward the user.
+    #[serde(default)]
+    pub tilt_y: i32,
+    /// The pen touches the surface.
+    #[serde(default)]
+    pub contact: bool,
+    /// The pen is near the surface, false when it leaves.
+    #[serde(default)]
+    pub in_range: bool,
+    #[serde(default)]
+    pub eraser: bool,
+    /// The barrel button.
+    #[serde(default)]
+    pub barrel: bool,
+}
+
+impl PenEvent {
+    pub fn to_message(&self) -> Message {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(self).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        msg
+    }
+
+    fn clamped(mut self) -> Self {
+        self.pressure = self.pressure.min(MAX_PRESSURE);
+        self.tilt_x = self.tilt_x.clamp(-MAX_TILT, MAX_TILT);
+        self.tilt_y = self.tilt_y.clamp(-MAX_TILT, MAX_TILT);
+        self.contact &= self.in_range;
+        self
+    }
+}
+
+/// The synthetic pen of a connection, removed when dropped.
+#[cfg(target_os = "windows")]
+pub struct VirtualPen {
+    device: windows::Win32::UI::Controls::HSYNTHETICPOINTERDEVICE,
+    last: PenEvent,
+}
+
+#[cfg(target_os = "windows")]
+impl VirtualPen {
+    pub fn new() -> ResultType<Self> {
+        use windows::Win32::UI::{Controls::*, WindowsAndMessaging::PT_PEN};
+        // Windows 10 1809 and later.
+        let device = unsafe { CreateSyntheticPointerDevice(PT_PEN, 1, POINTER_FEEDBACK_DEFAULT)? };
+        Ok(Self {
+            device,
+            last: Default::default(),
+        })
+    }
+
+    pub fn handle(&mut self, evt: &PenEvent) -> ResultType<()> {
+        use windows::Win32::{
+            Foundation::POINT,
+            UI::{Controls::*, Input::Pointer::*, WindowsAndMessaging::*},
+        };
+        let evt = evt.clamped();
+        let mut flags = POINTER_FLAG_NONE;
+        if evt.in_range {
+            flags |= POINTER_FLAG_INRANGE;
+        }
+        if evt.contact {
+            flags |= POINTER_FLAG_INCONTACT;
+            if evt.barrel {
+                flags |= POINTER_FLAG_SECONDBUTTON;
+            } else {
+                flags |= POINTER_FLAG_FIRSTBUTTON;
+            }
+        }
+        flags |= match (self.last.contact, evt.contact) {
+            (false, true) => POINTER_FLAG_DOWN,
+            (true, false) => POINTER_FLAG_UP,
+            _ => POINTER_FLAG_UPDATE,
+        };
+        let mut pen_flags = PEN_FLAG_NONE;
+        if evt.barrel {
+            pen_flags |= PEN_FLAG_BARREL;
+        }
+        if evt.eraser {
+            pen_flags |= PEN_FLAG_ERASER | PEN_FLAG_INVERTED;
+        }
+        let info = POINTER_TYPE_INFO {
+            r#type: PT_PEN,
+            Anonymous: POINTER_TYPE_INFO_0 {
+                penInfo: POINTER_PEN_INFO {
+                    pointerInfo: POINTER_INFO {
+                        pointerType: PT_PEN,
+                        pointerFlags: flags,
+                        ptPixelLocation: POINT { x: evt.x, y: evt.y },
+                        ..Default::default()
+                    },
+                    penFlags: pen_flags,
+                    penMask: PEN_MASK_PRESSURE | PEN_MASK_TILT_X | PEN_MASK_TILT_Y,
+                    pressure: evt.pressure,
+                    tiltX: evt.tilt_x,
+                    tiltY: evt.tilt_y,
+                    ..Default::default()
+                },
+            },
+        };
+        crate::platform::windows::try_change_desktop();
+        unsafe { InjectSyntheticPointerInput(self.device, &[info])? };
+        self.last = evt;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for VirtualPen {
+    fn drop(&mut self) {
+        unsafe { windows::Win32::UI::Controls::DestroySyntheticPointerDevice(self.device) };
+    }
+}
+
+/// The uinput tablet of a connection, removed when dropped.
+#[cfg(target_os = "linux")]
+pub struct VirtualPen {
+    device: evdev::uinput::VirtualDevice,
+    // The origin of the displays, the axes of the tablet start from 0.
+    origin: (i32, i32),
+    last: PenEvent,
+}
+
+#[cfg(target_os = "linux")]
+impl VirtualPen {
+    // Pixels per millimeter, libinput requires a resolution for the tablets.
+    const RESOLUTION: i32 = 4;
+
+    pub fn new() -> ResultType<Self> {
+        use evdev::{AbsInfo, AbsoluteAxisType, AttributeSet, Key, PropType, UinputAbsSetup};
+        let (min_x, min_y, max_x, max_y) = display_bounds()?;
+        let mut keys = AttributeSet::<Key>::new();
+        keys.insert(Key::BTN_TOOL_PEN);
+        keys.insert(Key::BTN_TOOL_RUBBER);
+        keys.insert(Key::BTN_TOUCH);
+        keys.insert(Key::BTN_STYLUS);
+        let mut props = AttributeSet::<PropType>::new();
+        props.insert(PropType::DIRECT);
+        let tilt = AbsInfo::new(0, -MAX_TILT, MAX_TILT, 0, 0, 1);
+        let device = evdev::uinput::VirtualDeviceBuilder::new()?
+            .name("RustDesk Virtual Pen")
+            .with_keys(&keys)?
+            .with_properties(&props)?
+            .with_absolute_axis(&UinputAbsSetup::new(
+                AbsoluteAxisType::ABS_X,
+                AbsInfo::new(0, 0, max_x - min_x - 1, 0, 0, Self::RESOLUTION),
+            ))?
+            .with_absolute_axis(&UinputAbsSetup::new(
+                AbsoluteAxisType::ABS_Y,
+                AbsInfo::new(0, 0, max_y - min_y - 1, 0, 0, Self::RESOLUTION),
+            ))?
+            .with_absolute_axis(&UinputAbsSetup::new(
+                AbsoluteAxisType::ABS_PRESSURE,
+                AbsInfo::new(0, 0, MAX_PRESSURE as _, 0, 0, 0),
+            ))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_TILT_X, tilt))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_TILT_Y, tilt))?
+            .build()?;
+        Ok(Self {
+            device,
+            origin: (min_x, min_y),
+            last: Default::default(),
+        })
+    }
+
+    pub fn handle(&mut self, evt: &PenEvent) -> ResultType<()> {
+        use evdev::{AbsoluteAxisType, EventType, InputEvent, Key};
+        let evt = evt.clamped();
+        let key = |key: Key, on: bool| InputEvent::new(EventType::KEY, key.code(), on as _);
+        let abs = |axis: AbsoluteAxisType, value: i32| {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        };
+        let mut events = vec![];
+        // The tool changes out of range only.
+        if self.last.in_range && (!evt.in_range || self.last.eraser != evt.eraser) {
+            events.push(key(Key::BTN_TOUCH, false));
+            events.push(key(tool(self.last.eraser), false));
+            self.device.emit(&events)?;
+            events.clear();
+            self.last = Default::default();
+        }
+        if !evt.in_range {
+            return Ok(());
+        }
+        events.push(abs(AbsoluteAxisType::ABS_X, evt.x - self.origin.0));
+        events.push(abs(AbsoluteAxisType::ABS_Y, evt.y - self.origin.1));
+        events.push(abs(AbsoluteAxisType::ABS_PRESSURE, evt.pressure as _));
+        events.push(abs(AbsoluteAxisType::ABS_TILT_X, evt.tilt_x));
+        events.push(abs(AbsoluteAxisType::ABS_TILT_Y, evt.tilt_y));
+        if !self.last.in_range {
+            events.push(key(tool(evt.eraser), true));
+        }
+        if self.last.contact != evt.contact {
+            events.push(key(Key::BTN_TOUCH, evt.contact));
+        }
+        if self.last.barrel != evt.barrel {
+            events.push(key(Key::BTN_STYLUS, evt.barrel));
+        }
+        self.device.emit(&events)?;
+        self.last = evt;
+        Ok(())
+    }
+}
+
+/// The left, top, right and bottom of all the displays, the axes of the uinput devices.
+#[cfg(target_os = "linux")]
+pub(crate) fn display_bounds() -> ResultType<(i32, i32, i32, i32)> {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+    for d in crate::server::display_service::try_get_displays()? {
+        let (x, y) = (d.origin().0 as i32, d.origin().1 as i32);
+        min_x = min_x.min(x);
+        min_y = min_y.min(y);
+        max_x = max_x.max(x + d.width() as i32);
+        max_y = max_y.max(y + d.height() as i32);
+    }
+    if min_x >= max_x || min_y >= max_y {
+        hbb_common::bail!("No display");
+    }
+    Ok((min_x, min_y, max_x, max_y))
+}
+
+#[cfg(target_os = "linux")]
+fn tool(eraser: bool) -> evdev::Key {
+    if eraser {
+        evdev::Key::BTN_TOOL_RUBBER
+    } else {
+        evdev::Key::BTN_TOOL_PEN
+    }
+}
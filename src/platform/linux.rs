@@ -35,9 +35,12 @@ static mut UNMODIFIED: bool = true;
 
 const INVALID_TERM_VALUES: [&str; 3] = ["", "unknown", "dumb"];
 const SHELL_PROCESSES: [&str; 4] = ["bash", "zsh", "fish", "sh"];
+// The name prefixes of the devices injecting the input of the peer.
+const VIRTUAL_INPUT_DEVICES: [&str; 2] = ["RustDesk", "mouce-library-fake-mouse"];
 
 lazy_static::lazy_static! {
     pub static ref IS_X11: bool = hbb_common::platform::linux::is_x11_or_headless();
+    static ref GRABBED_INPUT_DEVICES: std::sync::Mutex<std::collections::HashMap<PathBuf, evdev::Device>> = Default::default();
     static ref DATABASE_XTERM_256COLOR: Option<Database> = {
         match Database::from_name("xterm-256color") {
             Ok(database) => Some(database),
@@ -62,6 +65,7 @@ extern "C" {
         screen_num: *mut c_int,
     ) -> c_int;
     fn xdo_new(display: *const c_char) -> Xdo;
+    fn xdo_move_mouse(xdo: Xdo, x: c_int, y: c_int, screen: c_int) -> c_int;
     fn xdo_get_active_window(xdo: Xdo, window: *mut *mut c_void) -> c_int;
     fn xdo_get_window_location(
         xdo: Xdo,
@@ -138,6 +142,19 @@ pub fn get_cursor_pos() -> Option<(i32, i32)> {
     res
 }
 
+pub fn set_cursor_pos(x: i32, y: i32) -> bool {
+    let mut res = false;
+    XDO.with(|xdo| {
+        if let Ok(xdo) = xdo.try_borrow_mut() {
+            if xdo.is_null() {
+                return;
+            }
+            res = unsafe { xdo_move_mouse(*xdo, x, y, 0) } == 0;
+        }
+    });
+    res
+}
+
 pub fn reset_input_cache() {}
 
 pub fn get_focused_display(displays: Vec<DisplayInfo>) -> Option<usize> {
@@ -845,8 +862,58 @@ pub fn toggle_blank_screen(_v: bool) {
     // https://unix.stackexchange.com/questions/17170/disable-keyboard-mouse-input-on-unix-under-x
 }
 
-pub fn block_input(_v: bool) -> (bool, String) {
-    (true, "".to_owned())
+pub fn block_input(v: bool) -> (bool, String) {
+    let res = if is_root() {
+        grab_input_devices(v)
+    } else {
+        crate::server::uinput::client::block_input(v)
+    };
+    match res {
+        Ok(()) => (true, "".to_owned()),
+        Err(e) => (false, e.to_string()),
+    }
+}
+
+/// Grabs the keyboards, mice and touchpads, so only the virtual devices of RustDesk reach
+/// the desktop, on X11 and Wayland alike. Root is required.
+///
+/// The devices plugged in after the last call are grabbed too.
+pub fn grab_input_devices(v: bool) -> ResultType<()> {
+    let mut grabbed = GRABBED_INPUT_DEVICES.lock().unwrap();
+    if !v {
+        for (_, mut dev) in grabbed.drain() {
+            allow_err!(dev.ungrab());
+        }
+        return Ok(());
+    }
+    for (path, mut dev) in evdev::enumerate() {
+        if grabbed.contains_key(&path) {
+            continue;
+        }
+        let name = dev.name().unwrap_or_default();
+        if VIRTUAL_INPUT_DEVICES.iter().any(|n| name.starts_with(n)) {
+            continue;
+        }
+        let is_input = dev.supported_keys().map_or(false, |keys| {
+            keys.contains(evdev::Key::KEY_A)
+                || keys.contains(evdev::Key::BTN_LEFT)
+                || keys.contains(evdev::Key::BTN_TOUCH)
+        });
+        if !is_input {
+            continue;
+        }
+        match dev.grab() {
+            Ok(()) => {
+                log::info!("Grabbed the input device {}", name);
+                grabbed.insert(path, dev);
+            }
+            Err(e) => log::warn!("Failed to grab the input device {}: {}", name, e),
+        }
+    }
+    if grabbed.is_empty() {
+        bail!("No input device can be blocked");
+    }
+    Ok(())
 }
 
 pub fn is_installed() -> bool {
@@ -989,7 +1056,7 @@ fn get_width_height_from_captures<'t>(caps: &Captures<'t>) -> Option<(i32, i32)>
 #[inline]
 fn get_xrandr_conn_pat(name: &str) -> String {
     format!(
-        r"{}\s+connected.+?(?P<width>\d+)x(?P<height>\d+)\+(?P<x>\d+)\+(?P<y>\d+).*?\n",
+        r"{}\s+connected.+?(?P<width>\d+)x(?P<height>\d+)\+(?P<x>\d+)\+(?P<y>\d+)(\s+(?P<rotation>left|right)\b)?.*?\n",
         name
     )
 }
@@ -1025,6 +1092,8 @@ pub fn resolutions(name: &str) -> Vec<Resolution> {
                 rdp0 connected primary 1920x1080+0+0 0mm x 0mm
                     */
                 if let Some(caps) = re.captures(&xrandr_output) {
+                    // Rotated left or right, "HDMI-1 connected 1080x1920+0+0 left (normal left ...".
+                    let sideways = caps.name("rotation").is_some();
                     if let Some(resolutions) = caps.name("resolutions") {
                         let resolution_pat =
                             r"\s*(?P<width>\d+)x(?P<height>\d+)\s+(?P<rates>(\d+\.\d+\D*)+)\s*\n";
@@ -1033,9 +1102,13 @@ pub fn resolutions(name: &str) -> Vec<Resolution> {
                             return vec![];
                         };
                         for resolution_caps in resolution_re.captures_iter(resolutions.as_str()) {
-                            if let Some((width, height)) =
+                            if let Some((mut width, mut height)) =
                                 get_width_height_from_captures(&resolution_caps)
                             {
+                                // The modes are in the unrotated orientation.
+                                if sideways {
+                                    std::mem::swap(&mut width, &mut height);
+                                }
                                 let resolution = Resolution {
                                     width,
                                     height,
@@ -1072,15 +1145,7 @@ pub fn current_resolution(name: &str) -> ResultType<Resolution> {
 }
 
 pub fn change_resolution_directly(name: &str, width: usize, height: usize) -> ResultType<()> {
-    Command::new("xrandr")
-        .args(vec![
-            "--output",
-            name,
-            "--mode",
-            &format!("{}x{}", width, height),
-        ])
-        .spawn()?;
-    Ok(())
+    super::linux_virtual_display::set_mode(name, width as _, height as _)
 }
 
 #[inline]
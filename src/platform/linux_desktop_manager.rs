@@ -4,7 +4,7 @@ use crate::client::{
     LOGIN_MSG_DESKTOP_SESSION_NOT_READY, LOGIN_MSG_DESKTOP_XORG_NOT_FOUND,
     LOGIN_MSG_DESKTOP_XSESSION_FAILED,
 };
-use hbb_common::{allow_err, bail, log, rand::prelude::*, tokio::time};
+use hbb_common::{allow_err, bail, config::Config, log, rand::prelude::*, tokio::time};
 use pam;
 use std::{
     collections::HashMap,
@@ -20,6 +20,17 @@ use std::{
 };
 use users::{get_user_by_name, os::unix::UserExt, User};
 
+/// The user logged into the headless session by the service, without the credentials
+/// from the peer, when nobody is logged in on seat0.
+pub const OPTION_LINUX_HEADLESS_USER: &str = "linux-headless-user";
+/// `WIDTHxHEIGHT` of the headless session.
+pub const OPTION_LINUX_HEADLESS_RESOLUTION: &str = "linux-headless-resolution";
+
+const DEFAULT_RESOLUTION: (u32, u32) = (1920, 1080);
+const XVFB: &str = "Xvfb";
+// Between the auto logins failed.
+const AUTO_LOGIN_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
 lazy_static::lazy_static! {
     static ref DESKTOP_RUNNING: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
     static ref DESKTOP_MANAGER: Arc<Mutex<Option<DesktopManager>>> = Arc::new(Mutex::new(None));
@@ -50,9 +61,18 @@ pub fn start_xdesktop() {
         *DESKTOP_MANAGER.lock().unwrap() = Some(DesktopManager::new());
 
         let interval = time::Duration::from_millis(super::SERVICE_INTERVAL);
+        let mut last_auto_login: Option<Instant> = None;
         DESKTOP_RUNNING.store(true, Ordering::SeqCst);
         while DESKTOP_RUNNING.load(Ordering::SeqCst) {
             check_desktop_manager();
+            if last_auto_login.map_or(true, |t| t.elapsed() >= AUTO_LOGIN_RETRY_INTERVAL) {
+                if let Some(res) = try_auto_login() {
+                    if let Err(e) = res {
+                        log::error!("Failed to auto login the headless session, {}", e);
+                    }
+                    last_auto_login = Some(Instant::now());
+                }
+            }
             std::thread::sleep(interval);
         }
         log::info!("xdesktop child thread exit");
@@ -64,16 +84,13 @@ pub fn stop_xdesktop() {
     *DESKTOP_MANAGER.lock().unwrap() = None;
 }
 
+fn which(cmd: &str) -> bool {
+    run_cmds(&format!("which {}", cmd)).map_or(false, |output| !output.trim().is_empty())
+}
+
 fn detect_headless() -> Option<&'static str> {
-    match run_cmds(&format!("which {}", DesktopManager::get_xorg())) {
-        Ok(output) => {
-            if output.trim().is_empty() {
-                return Some(LOGIN_MSG_DESKTOP_XORG_NOT_FOUND);
-            }
-        }
-        _ => {
-            return Some(LOGIN_MSG_DESKTOP_XORG_NOT_FOUND);
-        }
+    if !which(DesktopManager::get_xorg()) && !which(XVFB) {
+        return Some(LOGIN_MSG_DESKTOP_XORG_NOT_FOUND);
     }
 
     match run_cmds("ls /usr/share/xsessions/") {
@@ -139,6 +156,42 @@ pub fn try_start_desktop(_username: &str, _passsword: &str) -> String {
     }
 }
 
+fn get_headless_resolution() -> (u32, u32) {
+    let option = Config::get_option(OPTION_LINUX_HEADLESS_RESOLUTION);
+    let Some((w, h)) = option.trim().split_once('x') else {
+        return DEFAULT_RESOLUTION;
+    };
+    match (w.trim().parse::<u32>(), h.trim().parse::<u32>()) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+        _ => {
+            log::warn!("Invalid headless resolution {}", option);
+            DEFAULT_RESOLUTION
+        }
+    }
+}
+
+// `None` if there's nothing to do.
+fn try_auto_login() -> Option<ResultType<()>> {
+    if !is_headless_allowed() {
+        return None;
+    }
+    let username = Config::get_option(OPTION_LINUX_HEADLESS_USER);
+    if username.is_empty() || detect_headless().is_some() {
+        return None;
+    }
+    let mut desktop_manager = DESKTOP_MANAGER.lock().unwrap();
+    let desktop_manager = desktop_manager.as_mut()?;
+    if desktop_manager.is_running()
+        || desktop_manager
+            .get_supported_display_seat0_username()
+            .is_some()
+    {
+        return None;
+    }
+    log::info!("Auto login the headless session of {}", &username);
+    Some(desktop_manager.auto_login_x_session(&username))
+}
+
 fn try_start_x_session(username: &str, password: &str) -> ResultType<(String, bool)> {
     let mut desktop_manager = DESKTOP_MANAGER.lock().unwrap();
     if let Some(desktop_manager) = &mut (*desktop_manager) {
@@ -256,7 +309,7 @@ impl DesktopManager {
                             return Ok(());
                         }
 
-                        match self.start_x_session(&userinfo, username, password) {
+                        match self.start_x_session(&userinfo, username, Some(password)) {
                             Ok(_) => {
                                 log::info!("Succeeded to start x11");
                                 self.child_username = username.to_string();
@@ -278,6 +331,17 @@ impl DesktopManager {
         }
     }
 
+    // The service logs the user in without the password, as the auto login of the display managers.
+    fn auto_login_x_session(&mut self, username: &str) -> ResultType<()> {
+        let Some(userinfo) = get_user_by_name(username) else {
+            bail!("failed to get userinfo of {}", username);
+        };
+        self.start_x_session(&userinfo, username, None)?;
+        log::info!("Succeeded to auto login x11");
+        self.child_username = username.to_string();
+        Ok(())
+    }
+
     // The logic mainly from https://github.com/neutrinolabs/xrdp/blob/34fe9b60ebaea59e8814bbc3ca5383cabaa1b869/sesman/session.c#L334.
     fn get_avail_display() -> ResultType<u32> {
         let display_range = 0..51;
@@ -300,7 +364,7 @@ impl DesktopManager {
         &mut self,
         userinfo: &User,
         username: &str,
-        password: &str,
+        password: Option<&str>,
     ) -> ResultType<()> {
         self.stop_children();
 
@@ -327,7 +391,7 @@ impl DesktopManager {
         let is_child_running = self.is_child_running.clone();
 
         let (tx_res, rx_res) = sync_channel(1);
-        let password = password.to_string();
+        let password = password.map(|p| p.to_string());
         let username = username.to_string();
         // start x11
         std::thread::spawn(move || {
@@ -376,14 +440,17 @@ impl DesktopManager {
         gid: u32,
         display_num: u32,
         username: String,
-        password: String,
+        password: Option<String>,
         envs: HashMap<&str, String>,
     ) -> ResultType<()> {
         let mut client = pam::Client::with_password(&pam_get_service_name())?;
         client
             .conversation_mut()
-            .set_credentials(&username, &password);
-        client.authenticate()?;
+            .set_credentials(&username, password.as_deref().unwrap_or_default());
+        // No authentication for the auto login.
+        if password.is_some() {
+            client.authenticate()?;
+        }
 
         client.set_item(pam::PamItemType::TTY, &Self::display_from_num(display_num))?;
         client.open_session()?;
@@ -507,6 +574,15 @@ impl DesktopManager {
 
         std::env::set_var("DISPLAY", &display);
         std::env::set_var("XAUTHORITY", &xauth);
+        // Xvfb is started with the resolution.
+        if !Self::use_xvfb() {
+            let (width, height) = get_headless_resolution();
+            if (width, height) != DEFAULT_RESOLUTION {
+                if let Err(e) = super::linux_virtual_display::set_headless_mode(width, height) {
+                    log::error!("Failed to set the headless resolution, {}", e);
+                }
+            }
+        }
         // start window manager (startwm.sh)
         let child_wm = match Self::start_x_window_manager(uid, gid, &envs) {
             Ok(c) => c,
@@ -659,6 +735,52 @@ impl DesktopManager {
         "Xorg"
     }
 
+    // Xorg with the dummy driver is preferred, which supports changing the resolution and
+    // the virtual displays, Xvfb is used if Xorg is not installed, e.g. on the cloud VMs.
+    #[inline]
+    fn use_xvfb() -> bool {
+        !which(Self::get_xorg()) && which(XVFB)
+    }
+
+    fn start_xvfb(
+        xauth: &str,
+        display: &str,
+        uid: u32,
+        gid: u32,
+        envs: &HashMap<&str, String>,
+    ) -> ResultType<Child> {
+        let (width, height) = get_headless_resolution();
+        let screen = format!("{}x{}x24", width, height);
+        log::info!("Use Xvfb, screen: {}", &screen);
+        match Command::new(XVFB)
+            .envs(envs)
+            .uid(uid)
+            .gid(gid)
+            .args(vec![
+                "-screen",
+                "0",
+                screen.as_ref(),
+                "-nolisten",
+                "tcp",
+                "+extension",
+                "GLX",
+                "+extension",
+                "RANDR",
+                "+extension",
+                "RENDER",
+                "-auth",
+                xauth,
+                display,
+            ])
+            .spawn()
+        {
+            Ok(c) => Ok(c),
+            Err(e) => {
+                bail!("Failed to start Xvfb with display {}, {}", display, e);
+            }
+        }
+    }
+
     fn start_x_server(
         xauth: &str,
         display: &str,
@@ -666,6 +788,9 @@ impl DesktopManager {
         gid: u32,
         envs: &HashMap<&str, String>,
     ) -> ResultType<Child> {
+        if Self::use_xvfb() {
+            return Self::start_xvfb(xauth, display, uid, gid, envs);
+        }
         let xorg = Self::get_xorg();
         log::info!("Use xorg: {}", &xorg);
         let app_name = crate::get_app_name().to_lowercase();
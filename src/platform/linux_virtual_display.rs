@@ -0,0 +1,344 @@
+// Virtual displays on X11, for machines without a monitor attached or the headless sessions.
+//
+// The outputs without a monitor, `VIRTUAL*` of the intel/modesetting drivers or `DUMMY*` of the dummy driver,
+// are turned on with a mode generated by CVT reduced blanking, so any resolution can be set.
+use super::linux::{is_x11, run_cmds};
+use hbb_common::{bail, log, regex::Regex, ResultType};
+use std::{collections::HashMap, process::Command, sync::Mutex};
+
+const DEFAULT_WIDTH: u32 = 1920;
+const DEFAULT_HEIGHT: u32 = 1080;
+const DEFAULT_REFRESH_RATE: u32 = 60;
+const VIRTUAL_OUTPUT_PREFIXES: [&str; 2] = ["VIRTUAL", "DUMMY"];
+// The same as `IDD_PLUG_OUT_ALL_INDEX` on Windows.
+const PLUG_OUT_ALL_INDEX: i32 = -1;
+
+lazy_static::lazy_static! {
+    // Peer index -> output name.
+    static ref VIRTUAL_DISPLAYS: Mutex<HashMap<u32, String>> = Default::default();
+}
+
+#[derive(Debug, Clone)]
+struct Output {
+    name: String,
+    // x, y, width, height
+    geometry: Option<(i32, i32, u32, u32)>,
+    // Rotated left or right, the modes are in the unrotated orientation, but the geometry is not.
+    sideways: bool,
+    modes: Vec<String>,
+}
+
+fn xrandr(args: &[&str]) -> ResultType<()> {
+    let output = Command::new("xrandr").args(args).output()?;
+    if !output.status.success() {
+        bail!(
+            "xrandr {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn query_outputs() -> ResultType<Vec<Output>> {
+    parse_outputs(&run_cmds("xrandr --query")?)
+}
+
+// "HDMI-1 connected 1080x1920+1920+0 left (normal left inverted right x axis y axis) 527mm x 296mm"
+fn parse_outputs(xrandr_output: &str) -> ResultType<Vec<Output>> {
+    let output_re = Regex::new(r"^(?P<name>\S+)\s+(connected|disconnected)")?;
+    let geometry_re = Regex::new(
+        r"(?P<w>\d+)x(?P<h>\d+)\+(?P<x>-?\d+)\+(?P<y>-?\d+)(\s+(?P<rotation>left|right)\b)?",
+    )?;
+    let mode_re = Regex::new(r"^\s+(?P<mode>\S+)\s+\d+\.\d+")?;
+    let mut outputs: Vec<Output> = vec![];
+    for line in xrandr_output.lines() {
+        if let Some(caps) = output_re.captures(line) {
+            let geometry_caps = geometry_re.captures(line);
+            let sideways = geometry_caps
+                .as_ref()
+                .map_or(false, |c| c.name("rotation").is_some());
+            let geometry = geometry_caps.and_then(|c| {
+                Some((
+                    c["x"].parse().ok()?,
+                    c["y"].parse().ok()?,
+                    c["w"].parse().ok()?,
+                    c["h"].parse().ok()?,
+                ))
+            });
+            outputs.push(Output {
+                name: caps["name"].to_owned(),
+                geometry,
+                sideways,
+                modes: vec![],
+            });
+        } else if let (Some(caps), Some(output)) = (mode_re.captures(line), outputs.last_mut()) {
+            output.modes.push(caps["mode"].to_owned());
+        }
+    }
+    Ok(outputs)
+}
+
+fn is_virtual_output(name: &str) -> bool {
+    let name = name.to_uppercase();
+    VIRTUAL_OUTPUT_PREFIXES.iter().any(|p| name.starts_with(p))
+}
+
+// Modeline of CVT reduced blanking, "clock hdisp hsyncstart hsyncend htotal vdisp vsyncstart vsyncend vtotal".
+fn cvt_rb_modeline(width: u32, height: u32, refresh: f64) -> String {
+    const H_BLANK: u32 = 160;
+    const H_FRONT_PORCH: u32 = 48;
+    const H_SYNC: u32 = 32;
+    const V_FRONT_PORCH: u32 = 3;
+    const MIN_V_BACK_PORCH: u32 = 6;
+    const MIN_V_BLANK_US: f64 = 460.0;
+    const CLOCK_STEP_MHZ: f64 = 0.25;
+
+    let width = width / 8 * 8;
+    // By the aspect ratio, 4:3, 16:9, 16:10, 5:4 or 15:9, others.
+    let v_sync = if width * 3 == height * 4 {
+        4
+    } else if width * 9 == height * 16 {
+        5
+    } else if width * 10 == height * 16 {
+        6
+    } else if width * 4 == height * 5 || width * 9 == height * 15 {
+        7
+    } else {
+        10
+    };
+    let h_period_us = (1_000_000.0 / refresh - MIN_V_BLANK_US) / height as f64;
+    let v_blank =
+        ((MIN_V_BLANK_US / h_period_us) as u32 + 1).max(V_FRONT_PORCH + v_sync + MIN_V_BACK_PORCH);
+    let v_total = height + v_blank;
+    let h_total = width + H_BLANK;
+    let clock = ((refresh * (v_total * h_total) as f64 / 1_000_000.0) / CLOCK_STEP_MHZ).floor()
+        * CLOCK_STEP_MHZ;
+    format!(
+        "{:.2} {} {} {} {} {} {} {} {}",
+        clock,
+        width,
+        width + H_FRONT_PORCH,
+        width + H_FRONT_PORCH + H_SYNC,
+        h_total,
+        height,
+        height + V_FRONT_PORCH,
+        height + V_FRONT_PORCH + v_sync,
+        v_total
+    )
+}
+
+// Adds the mode if the output does not have it.
+fn ensure_mode(output: &Output, width: u32, height: u32, refresh: u32) -> ResultType<String> {
+    // Keep "WxH" for the default refresh rate, `resolutions()` only parses this form.
+    let mode = if refresh == DEFAULT_REFRESH_RATE {
+        format!("{}x{}", width, height)
+    } else {
+        format!("{}x{}_{}", width, height, refresh)
+    };
+    if output.modes.contains(&mode) {
+        return Ok(mode);
+    }
+    let modeline = cvt_rb_modeline(width, height, refresh as _);
+    let mut args = vec!["--newmode", mode.as_str()];
+    args.extend(modeline.split(' '));
+    args.extend(["+hsync", "-vsync"]);
+    // The mode may already exist on the screen, but not on this output.
+    if let Err(e) = xrandr(&args) {
+        log::debug!("{}", e);
+    }
+    xrandr(&["--addmode", &output.name, &mode])?;
+    Ok(mode)
+}
+
+/// Sets the resolution of the output, adds the mode first if the output does not have it,
+/// e.g. the dummy monitor of the headless session only has several modes in xorg.conf.
+pub fn set_mode(name: &str, width: u32, height: u32) -> ResultType<()> {
+    set_mode_refresh(name, width, height, DEFAULT_REFRESH_RATE)
+}
+
+fn set_mode_refresh(name: &str, width: u32, height: u32, refresh: u32) -> ResultType<()> {
+    let outputs = query_outputs()?;
+    let Some(output) = outputs.iter().find(|o| o.name == name) else {
+        bail!("Output {} not found", name);
+    };
+    // The resolution is in the rotated orientation, the same as the captured display.
+    let (width, height) = if output.sideways {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let mode = ensure_mode(output, width, height, refresh)?;
+    xrandr(&["--output", name, "--mode", &mode])
+}
+
+/// Sets the mode of the active output of the headless session, started with the dummy driver.
+pub fn set_headless_mode(width: u32, height: u32) -> ResultType<()> {
+    let outputs = query_outputs()?;
+    let Some(output) = outputs
+        .iter()
+        .find(|o| o.geometry.is_some())
+        .or(outputs.first())
+    else {
+        bail!("No output found");
+    };
+    let mode = ensure_mode(output, width, height, DEFAULT_REFRESH_RATE)?;
+    xrandr(&["--output", &output.name, "--mode", &mode])
+}
+
+pub fn is_supported() -> bool {
+    if !is_x11() {
+        return false;
+    }
+    match query_outputs() {
+        Ok(outputs) => outputs.iter().any(|o| is_virtual_output(&o.name)),
+        Err(e) => {
+            log::error!("Failed to query xrandr outputs: {}", e);
+            false
+        }
+    }
+}
+
+pub fn get_virtual_displays() -> Vec<u32> {
+    VIRTUAL_DISPLAYS.lock().unwrap().keys().cloned().collect()
+}
+
+pub fn get_platform_additions() -> serde_json::Map<String, serde_json::Value> {
+    let mut map = serde_json::Map::new();
+    if !is_supported() {
+        return map;
+    }
+    // The same as the RustDesk IDD on Windows, the peer toggles the virtual displays by index.
+    map.insert("idd_impl".into(), serde_json::json!("rustdesk_idd"));
+    map.insert("virtual_display_modes".into(), serde_json::json!(true));
+    let virtual_displays = get_virtual_displays();
+    if !virtual_displays.is_empty() {
+        map.insert(
+            "rustdesk_virtual_displays".into(),
+            serde_json::json!(virtual_displays),
+        );
+    }
+    map
+}
+
+pub fn plug_in_monitor(idx: u32) -> ResultType<()> {
+    if VIRTUAL_DISPLAYS.lock().unwrap().contains_key(&idx) {
+        return Ok(());
+    }
+    plug_in_monitor_mode(idx, DEFAULT_WIDTH, DEFAULT_HEIGHT, DEFAULT_REFRESH_RATE)
+}
+
+// Plugs in the virtual display with the mode, or changes the mode if it is already plugged in.
+pub fn plug_in_monitor_mode(idx: u32, width: u32, height: u32, refresh: u32) -> ResultType<()> {
+    let mut displays = VIRTUAL_DISPLAYS.lock().unwrap();
+    if let Some(name) = displays.get(&idx) {
+        return set_mode_refresh(name, width, height, refresh);
+    }
+    let outputs = query_outputs()?;
+    let Some(output) = outputs.iter().find(|o| {
+        is_virtual_output(&o.name)
+            && o.geometry.is_none()
+            && !displays.values().any(|name| name == &o.name)
+    }) else {
+        bail!("No free virtual output");
+    };
+    // Place it at the right of the existing displays.
+    let right = outputs
+        .iter()
+        .filter_map(|o| o.geometry.map(|(x, _, w, _)| x + w as i32))
+        .max()
+        .unwrap_or(0);
+    let mode = ensure_mode(output, width, height, refresh)?;
+    xrandr(&[
+        "--output",
+        &output.name,
+        "--mode",
+        &mode,
+        "--pos",
+        &format!("{}x0", right),
+    ])?;
+    log::info!("Virtual display {} is plugged in as {}", idx, output.name);
+    displays.insert(idx, output.name.clone());
+    Ok(())
+}
+
+pub fn plug_out_monitor(index: i32) -> ResultType<()> {
+    let mut displays = VIRTUAL_DISPLAYS.lock().unwrap();
+    let indices: Vec<u32> = if index == PLUG_OUT_ALL_INDEX {
+        displays.keys().cloned().collect()
+    } else {
+        vec![index as _]
+    };
+    for idx in indices {
+        if let Some(name) = displays.remove(&idx) {
+            xrandr(&["--output", &name, "--off"])?;
+            log::info!("Virtual display {} ({}) is plugged out", idx, name);
+        }
+    }
+    Ok(())
+}
+
+pub fn is_virtual_display(name: &str) -> bool {
+    VIRTUAL_DISPLAYS.lock().unwrap().values().any(|n| n == name)
+}
+
+pub fn change_resolution_if_is_virtual_display(name: &str, w: u32, h: u32) -> Option<bool> {
+    if !is_virtual_display(name) {
+        return None;
+    }
+    match set_mode(name, w, h) {
+        Ok(_) => Some(true),
+        Err(e) => {
+            log::error!(
+                "Failed to change the resolution of virtual display {}: {}",
+                name,
+                e
+            );
+            Some(false)
+        }
+    }
+}
+
+pub fn reset_all() -> ResultType<()> {
+    plug_out_monitor(PLUG_OUT_ALL_INDEX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cvt_rb_modeline() {
+        // The same as `cvt -r 1920 1080 60`.
+        assert_eq!(
+            cvt_rb_modeline(1920, 1080, 60.0),
+            "138.50 1920 1968 2000 2080 1080 1083 1088 1111"
+        );
+        assert_eq!(
+            cvt_rb_modeline(2560, 1440, 60.0),
+            "241.50 2560 2608 2640 2720 1440 1443 1448 1481"
+        );
+    }
+
+    #[test]
+    fn test_parse_outputs() {
+        let outputs = parse_outputs(
+            "Screen 0: minimum 320 x 200, current 3000 x 1920, maximum 16384 x 16384
+eDP-1 connected primary 1920x1080+1080+0 (normal left inverted right x axis y axis) 344mm x 193mm
+   1920x1080     60.01*+  59.97
+HDMI-1 connected 1080x1920+0+0 left (normal left inverted right x axis y axis) 527mm x 296mm
+   1920x1080     60.00*+  50.00
+   1280x720      60.00
+VIRTUAL1 disconnected (normal left inverted right x axis y axis)
+",
+        )
+        .unwrap();
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs[0].geometry, Some((1080, 0, 1920, 1080)));
+        assert!(!outputs[0].sideways);
+        assert_eq!(outputs[1].geometry, Some((0, 0, 1080, 1920)));
+        assert!(outputs[1].sideways);
+        assert_eq!(outputs[1].modes, vec!["1920x1080", "1280x720"]);
+        assert_eq!(outputs[2].geometry, None);
+    }
+}
@@ -292,3 +292,111 @@ extern "C" bool MacSetMode(CGDirectDisplayID display, uint32_t width, uint32_t h
     CFRelease(allModes);
     return ret;
 }
+
+// CGVirtualDisplay is a private API of CoreGraphics, available since macOS 10.14.
+@interface CGVirtualDisplayDescriptor : NSObject
+@property(retain, nonatomic) dispatch_queue_t queue;
+@property(retain, nonatomic) NSString *name;
+@property(nonatomic) unsigned int maxPixelsHigh;
+@property(nonatomic) unsigned int maxPixelsWide;
+@property(nonatomic) CGSize sizeInMillimeters;
+@property(nonatomic) unsigned int productID;
+@property(nonatomic) unsigned int vendorID;
+@property(nonatomic) unsigned int serialNum;
+@end
+
+@interface CGVirtualDisplayMode : NSObject
+- (instancetype)initWithWidth:(unsigned int)width height:(unsigned int)height refreshRate:(double)refreshRate;
+@end
+
+@interface CGVirtualDisplaySettings : NSObject
+@property(nonatomic) unsigned int hiDPI;
+@property(retain, nonatomic) NSArray *modes;
+@end
+
+@interface CGVirtualDisplay : NSObject
+@property(readonly, nonatomic) unsigned int displayID;
+- (instancetype)initWithDescriptor:(CGVirtualDisplayDescriptor *)descriptor;
+- (BOOL)applySettings:(CGVirtualDisplaySettings *)settings;
+@end
+
+// Index -> CGVirtualDisplay, the display is removed when the object is released.
+static NSMutableDictionary *gVirtualDisplays = nil;
+
+static bool applyVirtualDisplayMode(CGVirtualDisplay *display, uint32_t width, uint32_t height, uint32_t refresh) {
+    CGVirtualDisplaySettings *settings = [[CGVirtualDisplaySettings alloc] init];
+    CGVirtualDisplayMode *mode = [[CGVirtualDisplayMode alloc] initWithWidth:width height:height refreshRate:refresh];
+    settings.hiDPI = 0;
+    settings.modes = @[mode];
+    bool ret = [display applySettings:settings];
+    [mode release];
+    [settings release];
+    return ret;
+}
+
+extern "C" bool MacIsVirtualDisplaySupported() {
+    return NSClassFromString(@"CGVirtualDisplay") != nil;
+}
+
+// Returns the display ID of the new virtual display, 0 if failed.
+extern "C" uint32_t MacPlugInVirtualDisplay(uint32_t index, uint32_t width, uint32_t height, uint32_t refresh) {
+    if (!MacIsVirtualDisplaySupported()) {
+        return 0;
+    }
+    @autoreleasepool {
+        if (gVirtualDisplays == nil) {
+            gVirtualDisplays = [[NSMutableDictionary alloc] init];
+        }
+        NSNumber *key = @(index);
+        CGVirtualDisplay *display = gVirtualDisplays[key];
+        if (display != nil) {
+            return display.displayID;
+        }
+
+        CGVirtualDisplayDescriptor *descriptor = [[CGVirtualDisplayDescriptor alloc] init];
+        descriptor.queue = dispatch_get_main_queue();
+        descriptor.name = [NSString stringWithFormat:@"RustDesk Virtual Display %u", index];
+        descriptor.maxPixelsWide = 7680;
+        descriptor.maxPixelsHigh = 4320;
+        // 96 DPI, so the display is not treated as a retina display.
+        descriptor.sizeInMillimeters = CGSizeMake(25.4 * width / 96, 25.4 * height / 96);
+        descriptor.vendorID = 0x5244;
+        descriptor.productID = 0x5644;
+        descriptor.serialNum = index;
+        display = [[CGVirtualDisplay alloc] initWithDescriptor:descriptor];
+        [descriptor release];
+        if (display == nil) {
+            return 0;
+        }
+        if (!applyVirtualDisplayMode(display, width, height, refresh)) {
+            [display release];
+            return 0;
+        }
+        gVirtualDisplays[key] = display;
+        [display release];
+        return display.displayID;
+    }
+}
+
+extern "C" bool MacPlugOutVirtualDisplay(uint32_t index) {
+    @autoreleasepool {
+        NSNumber *key = @(index);
+        if (gVirtualDisplays == nil || gVirtualDisplays[key] == nil) {
+            return false;
+        }
+        [gVirtualDisplays removeObjectForKey:key];
+        return true;
+    }
+}
+
+extern "C" bool MacSetVirtualDisplayMode(uint32_t displayID, uint32_t width, uint32_t height, uint32_t refresh) {
+    @autoreleasepool {
+        for (NSNumber *key in gVirtualDisplays) {
+            CGVirtualDisplay *display = gVirtualDisplays[key];
+            if (display.displayID == displayID) {
+                return applyVirtualDisplayMode(display, width, height, refresh);
+            }
+        }
+        return false;
+    }
+}
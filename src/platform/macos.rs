@@ -44,6 +44,7 @@ extern "C" {
     fn CGSCurrentCursorSeed() -> i32;
     fn CGEventCreate(r: *const c_void) -> *const c_void;
     fn CGEventGetLocation(e: *const c_void) -> CGPoint;
+    fn CGWarpMouseCursorPosition(point: CGPoint) -> i32;
     static kAXTrustedCheckOptionPrompt: CFStringRef;
     fn AXIsProcessTrustedWithOptions(options: CFDictionaryRef) -> BOOL;
     fn InputMonitoringAuthStatus(_: BOOL) -> BOOL;
@@ -385,6 +386,15 @@ pub fn get_cursor_pos() -> Option<(i32, i32)> {
     */
 }
 
+pub fn set_cursor_pos(x: i32, y: i32) -> bool {
+    unsafe {
+        CGWarpMouseCursorPosition(CGPoint {
+            x: x as _,
+            y: y as _,
+        }) == 0
+    }
+}
+
 pub fn get_focused_display(displays: Vec<DisplayInfo>) -> Option<usize> {
     autoreleasepool(|| unsafe_get_focused_display(displays))
 }
@@ -694,8 +704,106 @@ pub fn toggle_blank_screen(_v: bool) {
     // https://unix.stackexchange.com/questions/17115/disable-keyboard-mouse-temporarily
 }
 
-pub fn block_input(_v: bool) -> (bool, String) {
-    (true, "".to_owned())
+lazy_static::lazy_static! {
+    // Set to stop the event tap blocking the input.
+    static ref BLOCK_INPUT_STOP: std::sync::Mutex<Option<std::sync::Arc<std::sync::atomic::AtomicBool>>> = Default::default();
+}
+
+/// Drops the local keyboard and mouse events with an event tap. The events injected for the
+/// peer are marked with `ENIGO_INPUT_EXTRA_VALUE` and pass.
+pub fn block_input(v: bool) -> (bool, String) {
+    use std::sync::{atomic::AtomicBool, atomic::Ordering, Arc};
+    let mut stop = BLOCK_INPUT_STOP.lock().unwrap();
+    if !v {
+        if let Some(stop) = stop.take() {
+            stop.store(true, Ordering::SeqCst);
+        }
+        return (true, "".to_owned());
+    }
+    if stop.is_some() {
+        return (true, "".to_owned());
+    }
+    let flag = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = std::sync::mpsc::channel();
+    let flag_cloned = flag.clone();
+    std::thread::spawn(move || run_block_input_tap(flag_cloned, tx));
+    match rx.recv() {
+        Ok(Ok(())) => {
+            *stop = Some(flag);
+            (true, "".to_owned())
+        }
+        Ok(Err(e)) => (false, e),
+        Err(_) => (false, "Failed to start the event tap".to_owned()),
+    }
+}
+
+fn run_block_input_tap(
+    stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    tx: std::sync::mpsc::Sender<Result<(), String>>,
+) {
+    use core_foundation::runloop::{kCFRunLoopCommonModes, kCFRunLoopDefaultMode, CFRunLoop};
+    use core_graphics::event::{
+        CGEventTap, CGEventTapLocation, CGEventTapOptions, CGEventTapPlacement, CGEventType,
+        EventField,
+    };
+    use CGEventType::*;
+    let events = vec![
+        KeyDown,
+        KeyUp,
+        FlagsChanged,
+        LeftMouseDown,
+        LeftMouseUp,
+        RightMouseDown,
+        RightMouseUp,
+        OtherMouseDown,
+        OtherMouseUp,
+        MouseMoved,
+        LeftMouseDragged,
+        RightMouseDragged,
+        OtherMouseDragged,
+        ScrollWheel,
+    ];
+    let tap = CGEventTap::new(
+        CGEventTapLocation::HID,
+        CGEventTapPlacement::HeadInsertEventTap,
+        CGEventTapOptions::Default,
+        events,
+        |_proxy, _type, event| {
+            if event.get_integer_value_field(EventField::EVENT_SOURCE_USER_DATA)
+                != enigo::ENIGO_INPUT_EXTRA_VALUE
+            {
+                // A null event is dropped.
+                event.set_type(Null);
+            }
+            None
+        },
+    );
+    let Ok(tap) = tap else {
+        tx.send(Err(
+            "Failed to create the event tap, the accessibility permission is required".to_owned(),
+        ))
+        .ok();
+        return;
+    };
+    let Ok(source) = tap.mach_port.create_runloop_source(0) else {
+        tx.send(Err("Failed to create the run loop source".to_owned()))
+            .ok();
+        return;
+    };
+    unsafe {
+        CFRunLoop::get_current().add_source(&source, kCFRunLoopCommonModes);
+    }
+    tap.enable();
+    tx.send(Ok(())).ok();
+    while !stop.load(std::sync::atomic::Ordering::SeqCst) {
+        CFRunLoop::run_in_mode(
+            unsafe { kCFRunLoopDefaultMode },
+            std::time::Duration::from_millis(300),
+            false,
+        );
+        // The system disables the tap if it is too slow, e.g. the machine is busy.
+        tap.enable();
+    }
 }
 
 pub fn is_installed() -> bool {
@@ -0,0 +1,148 @@
+#import <AppKit/AppKit.h>
+#import <ApplicationServices/ApplicationServices.h>
+#include <unistd.h>
+
+// Privacy mode of `src/privacy_mode/macos.rs`.
+// The displays are covered by black windows above everything, and dimmed with a zero gamma.
+// Both are applied after the pixels are captured: the windows of this app are excluded by
+// ScreenCaptureKit (`src/platform/macos_sck.mm`), and by `NSWindowSharingNone` for CGDisplayStream.
+// The local input is dropped by an event tap, the events posted by this process, from the
+// controlling side, pass.
+
+static NSMutableArray<NSWindow *> *gPrivacyWindows = nil;
+static CFMachPortRef gPrivacyEventTap = NULL;
+static CFRunLoopSourceRef gPrivacyEventTapSource = NULL;
+
+static void runOnMain(dispatch_block_t block) {
+    if ([NSThread isMainThread]) {
+        block();
+    } else {
+        dispatch_sync(dispatch_get_main_queue(), block);
+    }
+}
+
+static CGEventRef privacyEventTapCallback(CGEventTapProxy proxy, CGEventType type,
+                                          CGEventRef event, void *userInfo) {
+    if (type == kCGEventTapDisabledByTimeout || type == kCGEventTapDisabledByUserInput) {
+        if (gPrivacyEventTap) {
+            CGEventTapEnable(gPrivacyEventTap, true);
+        }
+        return event;
+    }
+    if (CGEventGetIntegerValueField(event, kCGEventSourceUnixProcessID) == getpid()) {
+        return event;
+    }
+    return NULL;
+}
+
+static bool startEventTap() {
+    CGEventMask mask = CGEventMaskBit(kCGEventKeyDown) | CGEventMaskBit(kCGEventKeyUp) |
+                       CGEventMaskBit(kCGEventFlagsChanged) | CGEventMaskBit(kCGEventMouseMoved) |
+                       CGEventMaskBit(kCGEventLeftMouseDown) | CGEventMaskBit(kCGEventLeftMouseUp) |
+                       CGEventMaskBit(kCGEventLeftMouseDragged) |
+                       CGEventMaskBit(kCGEventRightMouseDown) |
+                       CGEventMaskBit(kCGEventRightMouseUp) |
+                       CGEventMaskBit(kCGEventRightMouseDragged) |
+                       CGEventMaskBit(kCGEventOtherMouseDown) |
+                       CGEventMaskBit(kCGEventOtherMouseUp) |
+                       CGEventMaskBit(kCGEventOtherMouseDragged) |
+                       CGEventMaskBit(kCGEventScrollWheel);
+    gPrivacyEventTap = CGEventTapCreate(kCGSessionEventTap, kCGHeadInsertEventTap,
+                                        kCGEventTapOptionDefault, mask, privacyEventTapCallback,
+                                        NULL);
+    if (!gPrivacyEventTap) {
+        return false;
+    }
+    gPrivacyEventTapSource = CFMachPortCreateRunLoopSource(kCFAllocatorDefault, gPrivacyEventTap, 0);
+    // The main run loop is run by the tray of the server process.
+    CFRunLoopAddSource(CFRunLoopGetMain(), gPrivacyEventTapSource, kCFRunLoopCommonModes);
+    CGEventTapEnable(gPrivacyEventTap, true);
+    return true;
+}
+
+static void stopEventTap() {
+    if (gPrivacyEventTapSource) {
+        CFRunLoopRemoveSource(CFRunLoopGetMain(), gPrivacyEventTapSource, kCFRunLoopCommonModes);
+        CFRelease(gPrivacyEventTapSource);
+        gPrivacyEventTapSource = NULL;
+    }
+    if (gPrivacyEventTap) {
+        CGEventTapEnable(gPrivacyEventTap, false);
+        CFMachPortInvalidate(gPrivacyEventTap);
+        CFRelease(gPrivacyEventTap);
+        gPrivacyEventTap = NULL;
+    }
+}
+
+static void showWindows() {
+    gPrivacyWindows = [[NSMutableArray alloc] init];
+    for (NSScreen *screen in [NSScreen screens]) {
+        NSWindow *window = [[NSWindow alloc] initWithContentRect:screen.frame
+                                                       styleMask:NSWindowStyleMaskBorderless
+                                                         backing:NSBackingStoreBuffered
+                                                           defer:NO
+                                                          screen:screen];
+        window.releasedWhenClosed = NO;
+        window.backgroundColor = [NSColor blackColor];
+        window.level = CGShieldingWindowLevel();
+        window.sharingType = NSWindowSharingNone;
+        // The events from the controlling side go to the windows below.
+        window.ignoresMouseEvents = YES;
+        window.collectionBehavior = NSWindowCollectionBehaviorCanJoinAllSpaces |
+                                    NSWindowCollectionBehaviorStationary |
+                                    NSWindowCollectionBehaviorFullScreenAuxiliary;
+        [window setFrame:screen.frame display:YES];
+        [window orderFrontRegardless];
+        [gPrivacyWindows addObject:window];
+        [window release];
+    }
+}
+
+static void hideWindows() {
+    for (NSWindow *window in gPrivacyWindows) {
+        [window orderOut:nil];
+        [window close];
+    }
+    [gPrivacyWindows release];
+    gPrivacyWindows = nil;
+}
+
+static void dimDisplays() {
+    CGDirectDisplayID displays[16];
+    uint32_t count = 0;
+    if (CGGetOnlineDisplayList(16, displays, &count) != kCGErrorSuccess) {
+        return;
+    }
+    for (uint32_t i = 0; i < count; i++) {
+        if (CGDisplayIsBuiltin(displays[i]) || CGDisplayIsActive(displays[i])) {
+            CGSetDisplayTransferByFormula(displays[i], 0, 0, 1, 0, 0, 1, 0, 0, 1);
+        }
+    }
+}
+
+extern "C" bool MacPrivacyModeOn() {
+    __block bool ok = false;
+    runOnMain(^{
+      if (gPrivacyEventTap) {
+          ok = true;
+          return;
+      }
+      if (!startEventTap()) {
+          NSLog(@"Failed to create the event tap of privacy mode");
+          return;
+      }
+      showWindows();
+      dimDisplays();
+      ok = true;
+    });
+    return ok;
+}
+
+extern "C" void MacPrivacyModeOff() {
+    runOnMain(^{
+      stopEventTap();
+      hideWindows();
+      // The gamma is also restored by the system once the process exits.
+      CGDisplayRestoreColorSyncSettings();
+    });
+}
@@ -0,0 +1,204 @@
+#import <CoreMedia/CoreMedia.h>
+#import <CoreVideo/CoreVideo.h>
+#import <Foundation/Foundation.h>
+#import <ScreenCaptureKit/ScreenCaptureKit.h>
+
+// ScreenCaptureKit capture of a display, used by `libs/scrap/src/quartz/capturer.rs`
+// on macOS 13+ instead of the deprecated CGDisplayStream.
+// ScreenCaptureKit is weak linked, see `build.rs`.
+
+typedef void (*ScrapSckFrameCallback)(void *ctx, IOSurfaceRef surface);
+
+static const int64_t kSckTimeoutSeconds = 5;
+
+API_AVAILABLE(macos(13.0))
+@interface ScrapSckOutput : NSObject <SCStreamOutput, SCStreamDelegate>
+@property(nonatomic, assign) void *ctx;
+@property(nonatomic, assign) ScrapSckFrameCallback callback;
+@end
+
+@implementation ScrapSckOutput
+- (void)stream:(SCStream *)stream
+    didOutputSampleBuffer:(CMSampleBufferRef)sampleBuffer
+                   ofType:(SCStreamOutputType)type {
+    if (type != SCStreamOutputTypeScreen || !CMSampleBufferIsValid(sampleBuffer)) {
+        return;
+    }
+    // The idle frames carry no image, only the status.
+    CFArrayRef attachments = CMSampleBufferGetSampleAttachmentsArray(sampleBuffer, false);
+    if (attachments && CFArrayGetCount(attachments) > 0) {
+        NSDictionary *attachment = (NSDictionary *)CFArrayGetValueAtIndex(attachments, 0);
+        NSNumber *status = attachment[SCStreamFrameInfoStatus];
+        if (status && [status integerValue] != SCFrameStatusComplete) {
+            return;
+        }
+    }
+    CVPixelBufferRef pixelBuffer = CMSampleBufferGetImageBuffer(sampleBuffer);
+    if (!pixelBuffer) {
+        return;
+    }
+    IOSurfaceRef surface = CVPixelBufferGetIOSurface(pixelBuffer);
+    if (surface) {
+        self.callback(self.ctx, surface);
+    }
+}
+
+- (void)stream:(SCStream *)stream didStopWithError:(NSError *)error {
+    NSLog(@"ScreenCaptureKit stream stopped: %@", error);
+}
+@end
+
+struct ScrapSck {
+    id stream;
+    id output;
+    dispatch_queue_t queue;
+};
+
+static bool waitSemaphore(dispatch_semaphore_t sem) {
+    return dispatch_semaphore_wait(
+               sem, dispatch_time(DISPATCH_TIME_NOW, kSckTimeoutSeconds * NSEC_PER_SEC)) == 0;
+}
+
+extern "C" bool ScrapSckIsSupported() {
+    if (@available(macOS 13.0, *)) {
+        return true;
+    }
+    return false;
+}
+
+// Returns NULL on failure, the caller falls back to CGDisplayStream.
+// `min_frame_time` 0 captures at the refresh rate of the display.
+extern "C" void *ScrapSckStart(uint32_t display_id, size_t width, size_t height,
+                               bool show_cursor, double min_frame_time, void *ctx,
+                               ScrapSckFrameCallback callback) {
+    if (@available(macOS 13.0, *)) {
+        @autoreleasepool {
+            __block SCShareableContent *content = nil;
+            dispatch_semaphore_t sem = dispatch_semaphore_create(0);
+            [SCShareableContent
+                getShareableContentExcludingDesktopWindows:NO
+                                       onScreenWindowsOnly:YES
+                                         completionHandler:^(SCShareableContent *c,
+                                                             NSError *error) {
+                                           if (error) {
+                                               NSLog(@"Failed to get shareable content: %@",
+                                                     error);
+                                           }
+                                           content = [c retain];
+                                           dispatch_semaphore_signal(sem);
+                                         }];
+            if (!waitSemaphore(sem)) {
+                // The handler may still be called, the semaphore is leaked on purpose.
+                NSLog(@"Timeout to get shareable content");
+                return NULL;
+            }
+            dispatch_release(sem);
+            if (!content) {
+                return NULL;
+            }
+
+            SCDisplay *display = nil;
+            for (SCDisplay *d in content.displays) {
+                if (d.displayID == display_id) {
+                    display = d;
+                    break;
+                }
+            }
+            if (!display) {
+                [content release];
+                return NULL;
+            }
+
+            // Our own windows, e.g. the connection manager and the whiteboard, are not captured.
+            NSMutableArray<SCRunningApplication *> *excluded = [NSMutableArray array];
+            NSString *bundleId = [[NSBundle mainBundle] bundleIdentifier];
+            pid_t pid = getpid();
+            for (SCRunningApplication *app in content.applications) {
+                if (app.processID == pid ||
+                    (bundleId && [app.bundleIdentifier isEqualToString:bundleId])) {
+                    [excluded addObject:app];
+                }
+            }
+            SCContentFilter *filter = [[SCContentFilter alloc] initWithDisplay:display
+                                                          excludingApplications:excluded
+                                                               exceptingWindows:@[]];
+            [content release];
+
+            SCStreamConfiguration *config = [[SCStreamConfiguration alloc] init];
+            config.width = width;
+            config.height = height;
+            config.pixelFormat = kCVPixelFormatType_32BGRA;
+            config.showsCursor = show_cursor;
+            config.queueDepth = 3;
+            // EDR content of HDR displays is tone-mapped to sRGB, as with CGDisplayStream.
+            config.colorSpaceName = kCGColorSpaceSRGB;
+            config.minimumFrameInterval =
+                min_frame_time > 0 ? CMTimeMakeWithSeconds(min_frame_time, 1000000) : kCMTimeZero;
+
+            ScrapSckOutput *output = [[ScrapSckOutput alloc] init];
+            output.ctx = ctx;
+            output.callback = callback;
+            SCStream *stream = [[SCStream alloc] initWithFilter:filter
+                                                  configuration:config
+                                                       delegate:output];
+            [filter release];
+            [config release];
+
+            dispatch_queue_t queue = dispatch_queue_create("rustdesk.scrap.sck", DISPATCH_QUEUE_SERIAL);
+            NSError *error = nil;
+            __block bool started = false;
+            if ([stream addStreamOutput:output
+                                   type:SCStreamOutputTypeScreen
+                     sampleHandlerQueue:queue
+                                  error:&error]) {
+                sem = dispatch_semaphore_create(0);
+                [stream startCaptureWithCompletionHandler:^(NSError *error) {
+                  if (error) {
+                      NSLog(@"Failed to start ScreenCaptureKit stream: %@", error);
+                  }
+                  started = error == nil;
+                  dispatch_semaphore_signal(sem);
+                }];
+                if (waitSemaphore(sem)) {
+                    dispatch_release(sem);
+                } else {
+                    NSLog(@"Timeout to start ScreenCaptureKit stream");
+                }
+            } else {
+                NSLog(@"Failed to add ScreenCaptureKit stream output: %@", error);
+            }
+            if (!started) {
+                [stream release];
+                [output release];
+                dispatch_release(queue);
+                return NULL;
+            }
+            return new ScrapSck{stream, output, queue};
+        }
+    }
+    return NULL;
+}
+
+// No frame is delivered once it returns.
+extern "C" void ScrapSckStop(void *handle) {
+    ScrapSck *sck = (ScrapSck *)handle;
+    if (!sck) {
+        return;
+    }
+    if (@available(macOS 13.0, *)) {
+        SCStream *stream = sck->stream;
+        dispatch_semaphore_t sem = dispatch_semaphore_create(0);
+        [stream stopCaptureWithCompletionHandler:^(NSError *error) {
+          dispatch_semaphore_signal(sem);
+        }];
+        if (waitSemaphore(sem)) {
+            dispatch_release(sem);
+        }
+        // Wait for the frames being handled.
+        dispatch_sync(sck->queue, ^{});
+        [sck->stream release];
+        [sck->output release];
+        dispatch_release(sck->queue);
+    }
+    delete sck;
+}
@@ -0,0 +1,122 @@
+// Virtual displays on macOS by CGVirtualDisplay, for machines without a monitor attached.
+//
+// The display exists as long as this process holds it.
+use hbb_common::{bail, log, ResultType};
+use std::{collections::HashMap, sync::Mutex};
+
+const DEFAULT_WIDTH: u32 = 1920;
+const DEFAULT_HEIGHT: u32 = 1080;
+const DEFAULT_REFRESH_RATE: u32 = 60;
+// The same as `IDD_PLUG_OUT_ALL_INDEX` on Windows.
+const PLUG_OUT_ALL_INDEX: i32 = -1;
+
+extern "C" {
+    fn MacIsVirtualDisplaySupported() -> bool;
+    fn MacPlugInVirtualDisplay(index: u32, width: u32, height: u32, refresh: u32) -> u32;
+    fn MacPlugOutVirtualDisplay(index: u32) -> bool;
+    fn MacSetVirtualDisplayMode(display: u32, width: u32, height: u32, refresh: u32) -> bool;
+}
+
+lazy_static::lazy_static! {
+    // Peer index -> display ID.
+    static ref VIRTUAL_DISPLAYS: Mutex<HashMap<u32, u32>> = Default::default();
+}
+
+pub fn is_supported() -> bool {
+    unsafe { MacIsVirtualDisplaySupported() }
+}
+
+pub fn get_virtual_displays() -> Vec<u32> {
+    VIRTUAL_DISPLAYS.lock().unwrap().keys().cloned().collect()
+}
+
+pub fn get_platform_additions() -> serde_json::Map<String, serde_json::Value> {
+    let mut map = serde_json::Map::new();
+    if !is_supported() {
+        return map;
+    }
+    // The same as the RustDesk IDD on Windows, the peer toggles the virtual displays by index.
+    map.insert("idd_impl".into(), serde_json::json!("rustdesk_idd"));
+    map.insert("virtual_display_modes".into(), serde_json::json!(true));
+    let virtual_displays = get_virtual_displays();
+    if !virtual_displays.is_empty() {
+        map.insert(
+            "rustdesk_virtual_displays".into(),
+            serde_json::json!(virtual_displays),
+        );
+    }
+    map
+}
+
+pub fn plug_in_monitor(idx: u32) -> ResultType<()> {
+    if VIRTUAL_DISPLAYS.lock().unwrap().contains_key(&idx) {
+        return Ok(());
+    }
+    plug_in_monitor_mode(idx, DEFAULT_WIDTH, DEFAULT_HEIGHT, DEFAULT_REFRESH_RATE)
+}
+
+// Plugs in the virtual display with the mode, or changes the mode if it is already plugged in.
+pub fn plug_in_monitor_mode(idx: u32, width: u32, height: u32, refresh: u32) -> ResultType<()> {
+    let mut displays = VIRTUAL_DISPLAYS.lock().unwrap();
+    if let Some(display) = displays.get(&idx) {
+        if !unsafe { MacSetVirtualDisplayMode(*display, width, height, refresh) } {
+            bail!("Failed to change the mode of virtual display {}", idx);
+        }
+        return Ok(());
+    }
+    let display = unsafe { MacPlugInVirtualDisplay(idx, width, height, refresh) };
+    if display == 0 {
+        bail!("Failed to create virtual display {}", idx);
+    }
+    log::info!("Virtual display {} is plugged in as {}", idx, display);
+    displays.insert(idx, display);
+    Ok(())
+}
+
+pub fn plug_out_monitor(index: i32) -> ResultType<()> {
+    let mut displays = VIRTUAL_DISPLAYS.lock().unwrap();
+    let indices: Vec<u32> = if index == PLUG_OUT_ALL_INDEX {
+        displays.keys().cloned().collect()
+    } else {
+        vec![index as _]
+    };
+    for idx in indices {
+        if let Some(display) = displays.remove(&idx) {
+            if unsafe { MacPlugOutVirtualDisplay(idx) } {
+                log::info!("Virtual display {} ({}) is plugged out", idx, display);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn is_virtual_display(name: &str) -> bool {
+    let Ok(display) = name.parse::<u32>() else {
+        return false;
+    };
+    VIRTUAL_DISPLAYS
+        .lock()
+        .unwrap()
+        .values()
+        .any(|d| *d == display)
+}
+
+// The modes of a virtual display are set by ourselves, `MacSetMode()` only finds the existing ones.
+pub fn change_resolution_if_is_virtual_display(name: &str, w: u32, h: u32) -> Option<bool> {
+    if !is_virtual_display(name) {
+        return None;
+    }
+    let display = name.parse::<u32>().ok()?;
+    let ok = unsafe { MacSetVirtualDisplayMode(display, w, h, DEFAULT_REFRESH_RATE) };
+    if !ok {
+        log::error!(
+            "Failed to change the resolution of virtual display {}",
+            name
+        );
+    }
+    Some(ok)
+}
+
+pub fn reset_all() -> ResultType<()> {
+    plug_out_monitor(PLUG_OUT_ALL_INDEX)
+}
@@ -11,6 +11,12 @@ pub mod windows;
 #[cfg(windows)]
 pub mod win_device;
 
+#[cfg(windows)]
+pub mod win_audio;
+
+#[cfg(windows)]
+pub mod privilege_broker;
+
 #[cfg(target_os = "macos")]
 pub mod macos;
 
@@ -26,6 +32,16 @@ pub mod linux_desktop_manager;
 #[cfg(target_os = "linux")]
 pub mod gtk_sudo;
 
+#[cfg(target_os = "linux")]
+pub mod linux_virtual_display;
+#[cfg(target_os = "linux")]
+pub use linux_virtual_display as virtual_display;
+
+#[cfg(target_os = "macos")]
+pub mod macos_virtual_display;
+#[cfg(target_os = "macos")]
+pub use macos_virtual_display as virtual_display;
+
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 use hbb_common::{
     message_proto::CursorData,
@@ -56,6 +72,8 @@ pub fn is_xfce() -> bool {
 }
 
 pub fn breakdown_callback() {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    crate::crash_report::on_breakdown();
     #[cfg(target_os = "linux")]
     crate::input_service::clear_remapped_keycode();
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
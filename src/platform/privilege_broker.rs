@@ -0,0 +1,265 @@
+//! Privilege separation of the Windows service.
+//!
+//! By default, the service launches `--server` as SYSTEM in the active session, so all the
+//! network-facing code, i.e. rendezvous, relay, codecs and the peer protocol, runs with the
+//! highest privileges. If enabled, the service instead launches the server with the token of
+//! the logged-in user and stays a small privileged broker, which does only what the server
+//! cannot do itself: capturing the secure desktop and UAC prompts through the portable service,
+//! and installing the virtual display driver.
+//!
+//! The worker gets a random token on its command line, which is not readable by other users.
+//! It opens the service IPC with the token and receives the capabilities it may request, the
+//! broker rejects any other request. The config is synced from the service over the same
+//! connection, one way, so the worker can not change the settings of the service, it only
+//! reports the results of its network detection. At the login screen there is no user token,
+//! so the server runs privileged there as before.
+
+use super::{get_current_session_id, is_share_rdp, launch_privileged_process};
+use crate::ipc::{self, Data, DataBroker};
+use hbb_common::{
+    allow_err,
+    anyhow::anyhow,
+    bail,
+    config::{self, Config, Config2},
+    log,
+    rand::{self, Rng},
+    sleep, tokio, ResultType,
+};
+use std::sync::Mutex;
+use winapi::um::{handleapi::CloseHandle, synchapi::WaitForSingleObject};
+
+pub const OPTION_PRIVILEGE_SEPARATION: &str = "allow-privilege-separation";
+pub const ARG_TOKEN: &str = "--broker-token";
+pub const CAP_SECURE_DESKTOP_CAPTURE: &str = "secure-desktop-capture";
+pub const CAP_INSTALL_DRIVER: &str = "install-driver";
+const CAPABILITIES: &[&str] = &[CAP_SECURE_DESKTOP_CAPTURE, CAP_INSTALL_DRIVER];
+const CONFIG_SYNC_INTERVAL_SECS: f32 = 1.;
+const INSTALL_DRIVER_TIMEOUT_MS: u32 = 60_000;
+
+lazy_static::lazy_static! {
+    // Broker, the token of the last launched worker.
+    static ref WORKER_TOKEN: Mutex<String> = Default::default();
+    // Worker, the token from the command line.
+    static ref TOKEN: Mutex<Option<String>> = Default::default();
+}
+
+/// Broker, whether to launch the server unprivileged.
+pub fn is_enabled() -> bool {
+    config::option2bool(
+        OPTION_PRIVILEGE_SEPARATION,
+        &Config::get_option(OPTION_PRIVILEGE_SEPARATION),
+    )
+}
+
+/// Broker, a new token for the worker about to be launched, the previous one is revoked.
+pub fn new_worker_token() -> String {
+    let token = format!("{:032x}", rand::thread_rng().gen::<u128>());
+    *WORKER_TOKEN.lock().unwrap() = token.clone();
+    token
+}
+
+pub fn check_worker_token(token: &str) -> bool {
+    let lock = WORKER_TOKEN.lock().unwrap();
+    !lock.is_empty() && *lock == token
+}
+
+/// Broker, serve the connection of a worker after a successful hello.
+pub async fn serve_worker(mut stream: ipc::Connection) {
+    let caps = CAPABILITIES.iter().map(|x| x.to_string()).collect();
+    allow_err!(
+        stream
+            .send(&Data::Broker(DataBroker::Capabilities(caps)))
+            .await
+    );
+    loop {
+        match stream.next().await {
+            Ok(Some(Data::Broker(DataBroker::Request(cap)))) => {
+                log::info!("Broker request: {}", cap);
+                // Not to block the service loop while waiting for the driver installation.
+                let cap2 = cap.clone();
+                let res = tokio::task::spawn_blocking(move || handle_request(&cap2))
+                    .await
+                    .map_err(|e| anyhow!(e))
+                    .and_then(|x| x);
+                let res = match res {
+                    Ok(()) => "".to_owned(),
+                    Err(err) => {
+                        log::error!("Broker request {} failed: {}", cap, err);
+                        err.to_string()
+                    }
+                };
+                allow_err!(stream.send(&Data::Broker(DataBroker::Response(res))).await);
+            }
+            Ok(Some(Data::Broker(DataBroker::SyncState { nat_type, serial }))) => {
+                if nat_type != Config::get_nat_type() {
+                    Config::set_nat_type(nat_type);
+                }
+                if serial != Config::get_serial() {
+                    Config::set_serial(serial);
+                }
+            }
+            Ok(Some(Data::Broker(DataBroker::SyncConfig(None)))) => {
+                allow_err!(
+                    stream
+                        .send(&Data::Broker(DataBroker::SyncConfig(Some(
+                            (Config::get(), Config2::get()).into()
+                        ))))
+                        .await
+                );
+            }
+            Ok(Some(data)) => {
+                log::warn!("Unexpected data from the worker: {:?}", data);
+            }
+            _ => break,
+        }
+    }
+}
+
+fn handle_request(cap: &str) -> ResultType<()> {
+    let exe = std::env::current_exe()?.to_string_lossy().to_string();
+    let session_id = get_current_session_id(is_share_rdp());
+    match cap {
+        CAP_SECURE_DESKTOP_CAPTURE => launch(
+            session_id,
+            &format!("\"{}\" --portable-service --run-as-system", exe),
+            0,
+        ),
+        CAP_INSTALL_DRIVER => launch(
+            session_id,
+            &format!("\"{}\" --install-idd", exe),
+            INSTALL_DRIVER_TIMEOUT_MS,
+        ),
+        _ => bail!("Capability not granted: {}", cap),
+    }
+}
+
+fn launch(session_id: u32, cmd: &str, wait_ms: u32) -> ResultType<()> {
+    let h = launch_privileged_process(session_id, cmd)?;
+    if h.is_null() {
+        bail!("Failed to launch {}", cmd);
+    }
+    unsafe {
+        if wait_ms > 0 {
+            WaitForSingleObject(h, wait_ms);
+        }
+        CloseHandle(h);
+    }
+    Ok(())
+}
+
+/// Worker, called with the token from the command line.
+pub fn set_worker_token(token: String) {
+    *TOKEN.lock().unwrap() = Some(token);
+}
+
+/// Whether this is the unprivileged server launched by the broker.
+pub fn is_worker() -> bool {
+    TOKEN.lock().unwrap().is_some()
+}
+
+async fn connect_broker() -> ResultType<(
+    ipc::ConnectionTmpl<parity_tokio_ipc::ConnectionClient>,
+    Vec<String>,
+)> {
+    let Some(token) = TOKEN.lock().unwrap().clone() else {
+        bail!("Not launched by the broker");
+    };
+    let mut conn = ipc::connect(1000, crate::POSTFIX_SERVICE).await?;
+    conn.send(&Data::Broker(DataBroker::Hello(token))).await?;
+    match conn.next_timeout(3000).await? {
+        Some(Data::Broker(DataBroker::Capabilities(caps))) => Ok((conn, caps)),
+        _ => bail!("Broker handshake failed"),
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn request_(cap: &'static str) -> ResultType<()> {
+    let (mut conn, caps) = connect_broker().await?;
+    if !caps.iter().any(|x| x == cap) {
+        bail!("Capability not granted: {}", cap);
+    }
+    conn.send(&Data::Broker(DataBroker::Request(cap.to_owned())))
+        .await?;
+    match conn
+        .next_timeout(INSTALL_DRIVER_TIMEOUT_MS as u64 + 3000)
+        .await?
+    {
+        Some(Data::Broker(DataBroker::Response(err))) if err.is_empty() => Ok(()),
+        Some(Data::Broker(DataBroker::Response(err))) => bail!(err),
+        _ => bail!("No response from the broker"),
+    }
+}
+
+/// Worker, ask the broker for a privileged operation.
+pub fn request(cap: &'static str) -> ResultType<()> {
+    // In a new thread, as it may be called inside a runtime.
+    std::thread::spawn(move || request_(cap))
+        .join()
+        .map_err(|_| anyhow!("Broker request {} panicked", cap))?
+}
+
+/// Worker, sync the config from the broker and keep the portable service running for the
+/// secure desktop.
+pub async fn run_worker() {
+    let mut from_broker = None;
+    let mut state0 = (Config::get_nat_type(), Config::get_serial());
+    loop {
+        let (mut conn, caps) = match connect_broker().await {
+            Ok(x) => x,
+            Err(err) => {
+                log::error!("Failed to connect to the broker: {}", err);
+                sleep(3.).await;
+                continue;
+            }
+        };
+        if caps.iter().any(|x| x == CAP_SECURE_DESKTOP_CAPTURE) {
+            std::thread::spawn(|| {
+                use crate::portable_service::client;
+                allow_err!(client::start_portable_service(client::StartPara::Broker));
+            });
+        }
+        loop {
+            if conn
+                .send(&Data::Broker(DataBroker::SyncConfig(None)))
+                .await
+                .is_err()
+            {
+                break;
+            }
+            match conn.next_timeout(1000).await {
+                Ok(Some(Data::Broker(DataBroker::SyncConfig(Some(configs))))) => {
+                    // Only the changes of the broker, not to revert the state of the worker.
+                    if from_broker.as_ref() != Some(&*configs) {
+                        let (config, config2) = (*configs).clone();
+                        let _chk = ipc::CheckIfRestart::new();
+                        if !config.is_empty() {
+                            Config::set(config);
+                            Config2::set(config2);
+                            state0 = (Config::get_nat_type(), Config::get_serial());
+                            log::info!("config synced from the broker");
+                        }
+                        from_broker = Some(*configs);
+                    }
+                }
+                Err(err) => {
+                    log::error!("sync config from the broker failed: {}", err);
+                    break;
+                }
+                _ => {}
+            }
+            let state = (Config::get_nat_type(), Config::get_serial());
+            if state != state0 {
+                let (nat_type, serial) = state;
+                if let Err(err) = conn
+                    .send(&Data::Broker(DataBroker::SyncState { nat_type, serial }))
+                    .await
+                {
+                    log::error!("sync state to the broker failed: {}", err);
+                    break;
+                }
+                state0 = state;
+            }
+            sleep(CONFIG_SYNC_INTERVAL_SECS).await;
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! Capture of the audio of one application, by the process loopback of WASAPI.
+//!
+//! It is available since Windows 10 build 20348, the process and its children are captured,
+//! whatever device they play on.
+
+use hbb_common::{
+    anyhow::anyhow,
+    bail, log,
+    sysinfo::{Pid, System},
+    ResultType,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+use windows::{
+    core::{implement, Interface, Ref, HRESULT},
+    Win32::{
+        Foundation::CloseHandle,
+        Media::Audio::*,
+        System::{
+            Com::{
+                CoCreateInstance, CoInitializeEx, CoUninitialize, BLOB, CLSCTX_ALL,
+                COINIT_MULTITHREADED,
+            },
+            Threading::{CreateEventW, WaitForSingleObject},
+            Variant::VT_BLOB,
+        },
+    },
+};
+
+pub const SAMPLE_RATE: u32 = 48000;
+pub const CHANNELS: u16 = 2;
+/// Prefix of the audio input capturing one application, followed by its executable name.
+pub const AUDIO_INPUT_PREFIX: &str = "process:";
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const ACTIVATE_TIMEOUT: Duration = Duration::from_secs(5);
+const BUFFER_DURATION_100NS: i64 = 200_000; // 20ms
+const WAIT_MS: u32 = 100;
+
+// PROPVARIANT holding a blob, the layout of the VT_BLOB case of the Windows type.
+#[repr(C)]
+struct BlobVariant {
+    vt: u16,
+    reserved: [u16; 3],
+    blob: BLOB,
+}
+
+#[implement(IActivateAudioInterfaceCompletionHandler)]
+struct ActivateHandler(mpsc::Sender<()>);
+
+impl IActivateAudioInterfaceCompletionHandler_Impl for ActivateHandler_Impl {
+    fn ActivateCompleted(
+        &self,
+        _operation: Ref<'_, IActivateAudioInterfaceAsyncOperation>,
+    ) -> windows::core::Result<()> {
+        self.0.send(()).ok();
+        Ok(())
+    }
+}
+
+/// The executable names of the applications playing audio on the default output device,
+/// as audio inputs.
+pub fn get_audio_apps() -> Vec<String> {
+    let pids = match unsafe { get_session_pids() } {
+        Ok(pids) => pids,
+        Err(e) => {
+            log::debug!("Failed to get audio sessions: {}", e);
+            return vec![];
+        }
+    };
+    let mut sys = System::new();
+    sys.refresh_processes();
+    let mut out = vec![];
+    for pid in pids {
+        if let Some(p) = sys.process(Pid::from_u32(pid)) {
+            let input = format!("{}{}", AUDIO_INPUT_PREFIX, p.name());
+            if !out.contains(&input) {
+                out.push(input);
+            }
+        }
+    }
+    out
+}
+
+unsafe fn get_session_pids() -> ResultType<Vec<u32>> {
+    let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+    let enumerator: IMMDeviceEnumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+    let device = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
+    let manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
+    let sessions = manager.GetSessionEnumerator()?;
+    let mut pids = vec![];
+    for i in 0..sessions.GetCount()? {
+        let control: IAudioSessionControl2 = sessions.GetSession(i)?.cast()?;
+        // The system sounds session is not an application.
+        if control.IsSystemSoundsSession() == HRESULT(0) {
+            continue;
+        }
+        let pid = control.GetProcessId()?;
+        if pid != 0 && !pids.contains(&pid) {
+            pids.push(pid);
+        }
+    }
+    Ok(pids)
+}
+
+/// The process of the executable, the one playing audio if several.
+pub fn find_process(name: &str) -> Option<u32> {
+    let playing = unsafe { get_session_pids() }.unwrap_or_default();
+    let mut sys = System::new();
+    sys.refresh_processes();
+    let pids: Vec<u32> = sys
+        .processes()
+        .iter()
+        .filter(|(_, p)| p.name().eq_ignore_ascii_case(name))
+        .map(|(pid, _)| pid.as_u32())
+        .collect();
+    pids.iter()
+        .find(|pid| playing.contains(pid))
+        .or(pids.first())
+        .copied()
+}
+
+/// Captures the audio of a process tree until dropped, as interleaved f32 samples
+/// of [`SAMPLE_RATE`] and [`CHANNELS`].
+pub struct ProcessLoopback {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ProcessLoopback {
+    pub fn start(pid: u32, callback: impl FnMut(&[f32]) + Send + 'static) -> ResultType<Self> {
+        let running = Arc::new(AtomicBool::new(true));
+        let (tx, rx) = mpsc::channel();
+        let running_cloned = running.clone();
+        let thread = std::thread::spawn(move || unsafe {
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            match activate(pid) {
+                Ok(client) => {
+                    tx.send(Ok(())).ok();
+                    if let Err(e) = capture(&client, &running_cloned, callback) {
+                        log::error!("Process loopback capture failed: {}", e);
+                    }
+                }
+                Err(e) => {
+                    tx.send(Err(e)).ok();
+                }
+            }
+            CoUninitialize();
+        });
+        match rx.recv() {
+            Ok(Ok(())) => {
+                log::info!("Capturing the audio of process {}", pid);
+                Ok(Self {
+                    running,
+                    thread: Some(thread),
+                })
+            }
+            Ok(Err(e)) => Err(e),
+            Err(e) => bail!("Process loopback thread exits: {}", e),
+        }
+    }
+}
+
+impl Drop for ProcessLoopback {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl cpal::traits::StreamTrait for ProcessLoopback {
+    fn play(&self) -> Result<(), cpal::PlayStreamError> {
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), cpal::PauseStreamError> {
+        Ok(())
+    }
+}
+
+unsafe fn activate(pid: u32) -> ResultType<IAudioClient> {
+    let mut params = AUDIOCLIENT_ACTIVATION_PARAMS {
+        ActivationType: AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK,
+        Anonymous: AUDIOCLIENT_ACTIVATION_PARAMS_0 {
+            ProcessLoopbackParams: AUDIOCLIENT_PROCESS_LOOPBACK_PARAMS {
+                TargetProcessId: pid,
+                ProcessLoopbackMode: PROCESS_LOOPBACK_MODE_INCLUDE_TARGET_PROCESS_TREE,
+            },
+        },
+    };
+    let variant = BlobVariant {
+        vt: VT_BLOB.0,
+        reserved: [0; 3],
+        blob: BLOB {
+            cbSize: std::mem::size_of::<AUDIOCLIENT_ACTIVATION_PARAMS>() as _,
+            pBlobData: &mut params as *mut _ as *mut u8,
+        },
+    };
+    let (tx, rx) = mpsc::channel();
+    let handler: IActivateAudioInterfaceCompletionHandler = ActivateHandler(tx).into();
+    let operation = ActivateAudioInterfaceAsync(
+        VIRTUAL_AUDIO_DEVICE_PROCESS_LOOPBACK,
+        &IAudioClient::IID,
+        Some((&variant as *const BlobVariant).cast()),
+        &handler,
+    )?;
+    rx.recv_timeout(ACTIVATE_TIMEOUT)
+        .map_err(|_| anyhow!("Timeout to activate the process loopback"))?;
+    let mut hr = HRESULT(0);
+    let mut interface = None;
+    operation.GetActivateResult(&mut hr, &mut interface)?;
+    hr.ok()?;
+    let client: IAudioClient = interface
+        .ok_or_else(|| anyhow!("No audio client of the process loopback"))?
+        .cast()?;
+    let format = WAVEFORMATEX {
+        wFormatTag: WAVE_FORMAT_IEEE_FLOAT,
+        nChannels: CHANNELS,
+        nSamplesPerSec: SAMPLE_RATE,
+        nAvgBytesPerSec: SAMPLE_RATE * CHANNELS as u32 * 4,
+        nBlockAlign: CHANNELS * 4,
+        wBitsPerSample: 32,
+        cbSize: 0,
+    };
+    client.Initialize(
+        AUDCLNT_SHAREMODE_SHARED,
+        AUDCLNT_STREAMFLAGS_LOOPBACK
+            | AUDCLNT_STREAMFLAGS_EVENTCALLBACK
+            | AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM,
+        BUFFER_DURATION_100NS,
+        0,
+        &format,
+        None,
+    )?;
+    Ok(client)
+}
+
+unsafe fn capture(
+    client: &IAudioClient,
+    running: &AtomicBool,
+    mut callback: impl FnMut(&[f32]),
+) -> ResultType<()> {
+    let event = CreateEventW(None, false, false, None)?;
+    let res = (|| -> ResultType<()> {
+        client.SetEventHandle(event)?;
+        let capture: IAudioCaptureClient = client.GetService()?;
+        client.Start()?;
+        let mut silence = vec![];
+        while running.load(Ordering::SeqCst) {
+            WaitForSingleObject(event, WAIT_MS);
+            while capture.GetNextPacketSize()? > 0 {
+                let mut data = std::ptr::null_mut();
+                let mut frames = 0;
+                let mut flags = 0;
+                capture.GetBuffer(&mut data, &mut frames, &mut flags, None, None)?;
+                let len = frames as usize * CHANNELS as usize;
+                if flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0 || data.is_null() {
+                    silence.resize(len, 0.);
+                    callback(&silence);
+                } else {
+                    callback(std::slice::from_raw_parts(data as *const f32, len));
+                }
+                capture.ReleaseBuffer(frames)?;
+            }
+        }
+        client.Stop()?;
+        Ok(())
+    })();
+    CloseHandle(event).ok();
+    res
+}
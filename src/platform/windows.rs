@@ -39,6 +39,8 @@ use winapi::{
     ctypes::c_void,
     shared::{minwindef::*, ntdef::NULL, windef::*, winerror::*},
     um::{
+        accctrl::SE_FILE_OBJECT,
+        aclapi::GetNamedSecurityInfoW,
         errhandlingapi::GetLastError,
         handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
         libloaderapi::{
@@ -51,6 +53,7 @@ use winapi::{
         },
         securitybaseapi::{
             AllocateAndInitializeSid, DuplicateToken, EqualSid, FreeSid, GetTokenInformation,
+            IsWellKnownSid,
         },
         shellapi::ShellExecuteW,
         sysinfoapi::{GetNativeSystemInfo, SYSTEM_INFO},
@@ -58,10 +61,11 @@ use winapi::{
         wingdi::*,
         winnt::{
             SecurityImpersonation, TokenElevation, TokenGroups, TokenImpersonation, TokenType,
-            DOMAIN_ALIAS_RID_ADMINS, ES_AWAYMODE_REQUIRED, ES_CONTINUOUS, ES_DISPLAY_REQUIRED,
-            ES_SYSTEM_REQUIRED, HANDLE, PROCESS_ALL_ACCESS, PROCESS_QUERY_LIMITED_INFORMATION,
-            PSID, SECURITY_BUILTIN_DOMAIN_RID, SECURITY_NT_AUTHORITY, SID_IDENTIFIER_AUTHORITY,
-            TOKEN_ELEVATION, TOKEN_GROUPS, TOKEN_QUERY, TOKEN_TYPE,
+            WinBuiltinAdministratorsSid, WinLocalSystemSid, DOMAIN_ALIAS_RID_ADMINS,
+            ES_AWAYMODE_REQUIRED, ES_CONTINUOUS, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED, HANDLE,
+            OWNER_SECURITY_INFORMATION, PROCESS_ALL_ACCESS, PROCESS_QUERY_LIMITED_INFORMATION,
+            PSECURITY_DESCRIPTOR, PSID, SECURITY_BUILTIN_DOMAIN_RID, SECURITY_NT_AUTHORITY,
+            SID_IDENTIFIER_AUTHORITY, TOKEN_ELEVATION, TOKEN_GROUPS, TOKEN_QUERY, TOKEN_TYPE,
         },
         winreg::HKEY_CURRENT_USER,
         winspool::{
@@ -106,10 +110,11 @@ pub fn get_focused_display(displays: Vec<DisplayInfo>) -> Option<usize> {
         displays.iter().position(|display| {
             let center_x = rect.left + (rect.right - rect.left) / 2;
             let center_y = rect.top + (rect.bottom - rect.top) / 2;
+            // The right and bottom edges are the left and top edges of the adjacent displays.
             center_x >= display.x
-                && center_x <= display.x + display.width
+                && center_x < display.x + display.width
                 && center_y >= display.y
-                && center_y <= display.y + display.height
+                && center_y < display.y + display.height
         })
     }
 }
@@ -125,6 +130,10 @@ pub fn get_cursor_pos() -> Option<(i32, i32)> {
     }
 }
 
+pub fn set_cursor_pos(x: i32, y: i32) -> bool {
+    unsafe { SetCursorPos(x, y) != FALSE }
+}
+
 pub fn reset_input_cache() {}
 
 pub fn get_cursor() -> ResultType<Option<u64>> {
@@ -604,6 +613,13 @@ async fn run_service(_arguments: Vec<OsString>) -> ResultType<()> {
                             ipc::Data::SAS => {
                                 send_sas();
                             }
+                            ipc::Data::Broker(ipc::DataBroker::Hello(token)) => {
+                                if super::privilege_broker::check_worker_token(&token) {
+                                    tokio::spawn(super::privilege_broker::serve_worker(stream));
+                                } else {
+                                    log::warn!("Rejected a broker connection with a wrong token");
+                                }
+                            }
                             ipc::Data::UserSid(usid) => {
                                 if let Some(usid) = usid {
                                     if session_id != usid {
@@ -689,10 +705,27 @@ async fn launch_server(session_id: DWORD, close_first: bool) -> ResultType<HANDL
         "\"{}\" --server",
         std::env::current_exe()?.to_str().unwrap_or("")
     );
+    if super::privilege_broker::is_enabled() {
+        // No user at the login screen, the server runs privileged there.
+        let token = super::privilege_broker::new_worker_token();
+        let h = launch_process(
+            session_id,
+            &format!("{} {} {}", cmd, super::privilege_broker::ARG_TOKEN, token),
+            true,
+        )?;
+        if !h.is_null() {
+            return Ok(h);
+        }
+        log::info!("Failed to launch the unprivileged server, fall back to the privileged one");
+    }
     launch_privileged_process(session_id, &cmd)
 }
 
 pub fn launch_privileged_process(session_id: DWORD, cmd: &str) -> ResultType<HANDLE> {
+    launch_process(session_id, cmd, false)
+}
+
+fn launch_process(session_id: DWORD, cmd: &str, as_user: bool) -> ResultType<HANDLE> {
     use std::os::windows::ffi::OsStrExt;
     let wstr: Vec<u16> = std::ffi::OsStr::new(&cmd)
         .encode_wide()
@@ -700,10 +733,16 @@ pub fn launch_privileged_process(session_id: DWORD, cmd: &str) -> ResultType<HAN
         .collect();
     let wstr = wstr.as_ptr();
     let mut token_pid = 0;
-    let h = unsafe { LaunchProcessWin(wstr, session_id, FALSE, FALSE, &mut token_pid) };
+    let as_user = if as_user { TRUE } else { FALSE };
+    let h = unsafe { LaunchProcessWin(wstr, session_id, as_user, FALSE, &mut token_pid) };
     if h.is_null() {
         log::error!(
-            "Failed to launch privileged process: {}",
+            "Failed to launch {} process: {}",
+            if as_user == TRUE {
+                "user"
+            } else {
+                "privileged"
+            },
             io::Error::last_os_error()
         );
         if token_pid == 0 {
@@ -897,6 +936,39 @@ pub fn try_change_desktop() -> bool {
     return false;
 }
 
+fn input_desktop_name() -> Option<String> {
+    unsafe {
+        let desktop = OpenInputDesktop(0, FALSE, DESKTOP_READOBJECTS);
+        if desktop.is_null() {
+            return None;
+        }
+        let mut name = [0u16; 256];
+        let mut len = 0;
+        let res = GetUserObjectInformationW(
+            desktop as _,
+            UOI_NAME as _,
+            name.as_mut_ptr() as _,
+            (name.len() * 2) as _,
+            &mut len,
+        );
+        CloseDesktop(desktop);
+        if res == FALSE {
+            return None;
+        }
+        let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+        Some(String::from_utf16_lossy(&name[..len]))
+    }
+}
+
+/// Whether the input desktop is the secure desktop, which shows the UAC prompt,
+/// the Ctrl+Alt+Del screen and the lock screen.
+///
+/// Only the system account can open the secure desktop, so it is assumed when the
+/// input desktop can not be opened.
+pub fn is_secure_desktop() -> bool {
+    input_desktop_name().map_or(true, |name| name.eq_ignore_ascii_case("Winlogon"))
+}
+
 fn share_rdp() -> BOOL {
     if get_reg("share_rdp") != "false" {
         TRUE
@@ -2402,6 +2474,62 @@ pub fn set_path_permission(dir: &Path, permission: &str) -> ResultType<()> {
     Ok(())
 }
 
+/// Makes the directory owned by the administrators, writable only by them and the system,
+/// readable by the users, without the inherited permissions of `ProgramData`.
+pub fn set_admin_only_permission(dir: &Path) -> ResultType<()> {
+    for args in [
+        &["/setowner", "*S-1-5-32-544", "/T"][..],
+        &[
+            "/inheritance:r",
+            "/grant:r",
+            "*S-1-5-18:(OI)(CI)F",
+            "*S-1-5-32-544:(OI)(CI)F",
+            "*S-1-5-32-545:(OI)(CI)RX",
+            "/T",
+        ][..],
+    ] {
+        let output = std::process::Command::new("icacls")
+            .arg(dir.as_os_str())
+            .args(args)
+            .creation_flags(CREATE_NO_WINDOW)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "icacls {:?} failed: {}",
+                args,
+                String::from_utf8_lossy(&output.stdout).trim()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Whether the file is owned by the system or the administrators.
+pub fn is_admin_owned(path: &Path) -> bool {
+    let wpath = wide_string(&path.to_string_lossy());
+    unsafe {
+        let mut owner: PSID = NULL;
+        let mut sd: PSECURITY_DESCRIPTOR = NULL;
+        if GetNamedSecurityInfoW(
+            wpath.as_ptr(),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION,
+            &mut owner,
+            NULL as _,
+            NULL as _,
+            NULL as _,
+            &mut sd,
+        ) != ERROR_SUCCESS
+        {
+            return false;
+        }
+        let res = IsWellKnownSid(owner, WinLocalSystemSid) == TRUE
+            || IsWellKnownSid(owner, WinBuiltinAdministratorsSid) == TRUE;
+        LocalFree(sd as _);
+        res
+    }
+}
+
 #[inline]
 fn str_to_device_name(name: &str) -> [u16; 32] {
     let mut device_name: Vec<u16> = wide_string(name);
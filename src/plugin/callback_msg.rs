@@ -139,6 +139,22 @@ pub(super) extern "C" fn cb_msg(
     cb_msg_field!(target);
     cb_msg_field!(id);
 
+    let required = match &target as _ {
+        MSG_TO_PEER_TARGET => Some(capability::CAP_PEER_MESSAGE),
+        MSG_TO_UI_TARGET => Some(capability::CAP_UI),
+        MSG_TO_CONFIG_TARGET => Some(capability::CAP_CONFIG),
+        MSG_TO_EXT_SUPPORT_TARGET => Some(capability::CAP_EXT_SUPPORT),
+        _ => None,
+    };
+    if let Some(required) = required {
+        if !capability::is_granted(&id, required) {
+            return PluginReturn::new(
+                errno::ERR_CALLBACK_CAPABILITY,
+                &format!("Plugin '{}' is not granted '{}'", id, required),
+            );
+        }
+    }
+
     match &target as _ {
         MSG_TO_PEER_TARGET => {
             cb_msg_field!(peer);
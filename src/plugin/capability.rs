@@ -0,0 +1,118 @@
+//! The versions of the plugin API and the capabilities granted to the plugins.
+//!
+//! A plugin declares `api_version` and the `capabilities` it needs in its description.
+//! API version 1 is the original native API, whose plugins are granted everything.
+//! From version 2, a plugin gets only the known capabilities it requests, narrowed by the
+//! `capabilities` plugin option of the manager, and the granted ones are passed to it in the
+//! init info. The host checks them on every callback, for the WASM plugins the capabilities
+//! are the only way out of the sandbox.
+
+use super::desc::Desc;
+use hbb_common::{bail, lazy_static, ResultType};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+pub const API_VERSION: u32 = 2;
+const MIN_API_VERSION: u32 = 1;
+
+/// Read and write the clipboard, WASM only.
+pub const CAP_CLIPBOARD: &str = "clipboard";
+/// Show the panels of the plugin in the UI and send events to them.
+pub const CAP_UI: &str = "ui";
+/// Exchange the custom protocol messages with the plugin of the peer.
+pub const CAP_PEER_MESSAGE: &str = "peer_message";
+/// Write the shared and the peer config of the plugin.
+pub const CAP_CONFIG: &str = "config";
+/// The extended features of RustDesk, e.g. the privacy mode.
+pub const CAP_EXT_SUPPORT: &str = "ext_support";
+const CAPABILITIES: &[&str] = &[
+    CAP_CLIPBOARD,
+    CAP_UI,
+    CAP_PEER_MESSAGE,
+    CAP_CONFIG,
+    CAP_EXT_SUPPORT,
+];
+
+lazy_static::lazy_static! {
+    static ref GRANTED: RwLock<HashMap<String, HashSet<String>>> = Default::default();
+}
+
+/// The capabilities granted to the plugin, `grants` is the `capabilities` option of the manager.
+pub(super) fn negotiate(desc: &Desc, grants: Option<&[String]>) -> ResultType<HashSet<String>> {
+    let version = desc.api_version();
+    if version < MIN_API_VERSION || version > API_VERSION {
+        bail!(
+            "Unsupported plugin API version {}, supported {} to {}",
+            version,
+            MIN_API_VERSION,
+            API_VERSION
+        );
+    }
+    if version == 1 {
+        return Ok(CAPABILITIES.iter().map(|c| c.to_string()).collect());
+    }
+    Ok(desc
+        .capabilities()
+        .iter()
+        .filter(|c| CAPABILITIES.contains(&c.as_str()))
+        .filter(|c| grants.map_or(true, |g| g.contains(c)))
+        .cloned()
+        .collect())
+}
+
+pub(super) fn set_granted(id: &str, granted: HashSet<String>) {
+    GRANTED.write().unwrap().insert(id.to_owned(), granted);
+}
+
+pub(super) fn remove_granted(id: &str) {
+    GRANTED.write().unwrap().remove(id);
+}
+
+pub fn is_granted(id: &str, capability: &str) -> bool {
+    GRANTED
+        .read()
+        .unwrap()
+        .get(id)
+        .map_or(false, |g| g.contains(capability))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desc(api_version: u32, capabilities: &[&str]) -> Desc {
+        serde_json::from_value(serde_json::json!({
+            "meta": {
+                "id": "test", "name": "", "version": "", "description": "", "author": "",
+                "home": "", "license": "", "source": "",
+                "publish_info": {"published": "", "last_released": ""}
+            },
+            "need_reboot": false,
+            "location": {"ui": {}},
+            "config": {"shared": [], "peer": []},
+            "listen_events": [],
+            "api_version": api_version,
+            "capabilities": capabilities,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(
+            negotiate(&desc(1, &[]), None).unwrap().len(),
+            CAPABILITIES.len()
+        );
+        let granted = negotiate(&desc(2, &["ui", "clipboard", "shell"]), None).unwrap();
+        assert_eq!(
+            granted,
+            HashSet::from(["ui".to_owned(), "clipboard".to_owned()])
+        );
+        let granted = negotiate(&desc(2, &["ui", "clipboard"]), Some(&["ui".to_owned()])).unwrap();
+        assert_eq!(granted, HashSet::from(["ui".to_owned()]));
+        assert!(negotiate(&desc(3, &[]), None).is_err());
+        assert!(negotiate(&desc(0, &[]), None).is_err());
+    }
+}
@@ -217,6 +217,9 @@ impl PeerConfig {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PluginStatus {
     pub enabled: bool,
+    // The capabilities granted by the admin, all the requested ones if not set.
+    #[serde(default)]
+    pub capabilities: Option<Vec<String>>,
 }
 
 const MANAGER_VERSION: &str = "0.1.0";
@@ -277,6 +280,11 @@ impl ManagerConfig {
                     .to_string();
                 Some(enabled)
             }
+            "capabilities" => lock
+                .plugins
+                .get(id)
+                .and_then(|status| status.capabilities.as_ref())
+                .map(|c| c.join(",")),
             _ => None,
         }
     }
@@ -286,11 +294,36 @@ impl ManagerConfig {
         if let Some(status) = lock.plugins.get_mut(id) {
             status.enabled = enabled;
         } else {
-            lock.plugins.insert(id.to_owned(), PluginStatus { enabled });
+            lock.plugins.insert(
+                id.to_owned(),
+                PluginStatus {
+                    enabled,
+                    capabilities: None,
+                },
+            );
         }
         hbb_common::config::store_path(Self::path(), &*lock)
     }
 
+    fn set_plugin_option_capabilities(id: &str, capabilities: Vec<String>) -> ResultType<()> {
+        let mut lock = CONFIG_MANAGER.lock().unwrap();
+        match lock.plugins.get_mut(id) {
+            Some(status) => status.capabilities = Some(capabilities),
+            None => bail!("No such plugin {}", id),
+        }
+        hbb_common::config::store_path(Self::path(), &*lock)
+    }
+
+    #[inline]
+    pub(super) fn get_plugin_capabilities(id: &str) -> Option<Vec<String>> {
+        CONFIG_MANAGER
+            .lock()
+            .unwrap()
+            .plugins
+            .get(id)
+            .and_then(|status| status.capabilities.clone())
+    }
+
     pub fn set_plugin_option(id: &str, key: &str, value: &str) {
         match key {
             "enabled" => {
@@ -302,6 +335,17 @@ impl ManagerConfig {
                     super::unload_plugin(id);
                 }
             }
+            "capabilities" => {
+                let capabilities = value
+                    .split(',')
+                    .map(|c| c.trim())
+                    .filter(|c| !c.is_empty())
+                    .map(|c| c.to_owned())
+                    .collect();
+                allow_err!(Self::set_plugin_option_capabilities(id, capabilities));
+                // The plugin is told the granted capabilities on init.
+                allow_err!(super::reload_plugin(id));
+            }
             _ => log::error!("No such option {}", key),
         }
     }
@@ -310,7 +354,12 @@ impl ManagerConfig {
     pub fn add_plugin(id: &str) -> ResultType<()> {
         let mut lock = CONFIG_MANAGER.lock().unwrap();
         lock.plugins
-            .insert(id.to_owned(), PluginStatus { enabled: true });
+            .entry(id.to_owned())
+            .or_insert(PluginStatus {
+                enabled: true,
+                capabilities: None,
+            })
+            .enabled = true;
         hbb_common::config::store_path(Self::path(), &*lock)
     }
 
@@ -74,6 +74,15 @@ pub struct Desc {
     location: Location,
     config: Config,
     listen_events: Vec<String>,
+    #[serde(default = "default_api_version")]
+    api_version: u32,
+    #[serde(default)]
+    capabilities: Vec<String>,
+}
+
+// The descriptions before the versioned API.
+fn default_api_version() -> u32 {
+    1
 }
 
 impl Desc {
@@ -97,4 +106,12 @@ impl Desc {
     pub fn listen_events(&self) -> &Vec<String> {
         &self.listen_events
     }
+
+    pub fn api_version(&self) -> u32 {
+        self.api_version
+    }
+
+    pub fn capabilities(&self) -> &Vec<String> {
+        &self.capabilities
+    }
 }
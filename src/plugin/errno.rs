@@ -26,6 +26,8 @@ pub const ERR_PEER_ID_MISMATCH: i32 = 10302;
 pub const ERR_CALL_CONFIG_VALUE: i32 = 10303;
 // no handlers on calling
 pub const ERR_NOT_HANDLED: i32 = 10401;
+// trapped, WASM only
+pub const ERR_PLUGIN_TRAPPED: i32 = 10501;
 
 // ======================================================
 // Errors from RustDesk callbacks.
@@ -37,6 +39,7 @@ pub const ERR_CALLBACK_INVALID_MSG: i32 = 20003;
 pub const ERR_CALLBACK_TARGET: i32 = 20004;
 pub const ERR_CALLBACK_TARGET_TYPE: i32 = 20005;
 pub const ERR_CALLBACK_PEER_NOT_FOUND: i32 = 20006;
+pub const ERR_CALLBACK_CAPABILITY: i32 = 20007;
 
 pub const ERR_CALLBACK_FAILED: i32 = 21001;
 
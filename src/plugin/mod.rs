@@ -9,6 +9,7 @@ use std::{
 
 mod callback_ext;
 mod callback_msg;
+pub mod capability;
 mod config;
 pub mod desc;
 mod errno;
@@ -18,6 +19,8 @@ pub mod native;
 pub mod native_handlers;
 mod plog;
 mod plugins;
+#[cfg(feature = "plugin_wasm")]
+mod wasm;
 
 pub use manager::{
     install::{change_uninstall_plugin, install_plugin_with_url},
@@ -116,6 +119,8 @@ pub fn init() {
             log::error!("Failed to load plugins: {}", e);
         }
     }
+    #[cfg(feature = "plugin_wasm")]
+    wasm::start_hot_reload();
 }
 
 #[inline]
@@ -3,13 +3,14 @@ use super::{desc::Desc, errno::*, *};
 use crate::common::is_server;
 use crate::flutter;
 use hbb_common::{
-    bail,
+    bail, config as hbb_config,
     dlopen::symbor::Library,
     lazy_static, log,
     message_proto::{Message, Misc, PluginFailure, PluginRequest},
+    sodiumoxide::crypto::sign,
     ResultType,
 };
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     ffi::{c_char, c_void},
@@ -145,6 +146,9 @@ struct Callbacks {
 #[repr(C)]
 struct InitInfo {
     is_server: bool,
+    api_version: u32,
+    // The granted capabilities.
+    capabilities: Vec<String>,
 }
 
 /// The plugin initialize data.
@@ -308,7 +312,12 @@ fn load_plugin_dir(dir: &Path) {
                     if path.is_file() {
                         let filename = entry.file_name();
                         let filename = filename.to_str().unwrap_or("");
-                        if filename.starts_with("plugin_") && filename.ends_with(DYLIB_SUFFIX) {
+                        #[cfg(feature = "plugin_wasm")]
+                        let is_plugin_file =
+                            filename.ends_with(DYLIB_SUFFIX) || super::wasm::is_wasm_path(filename);
+                        #[cfg(not(feature = "plugin_wasm"))]
+                        let is_plugin_file = filename.ends_with(DYLIB_SUFFIX);
+                        if filename.starts_with("plugin_") && is_plugin_file {
                             if let Some(path) = path.to_str() {
                                 if let Err(e) = load_plugin_path(path) {
                                     log::error!("Failed to load plugin {}, {}", filename, e);
@@ -332,6 +341,9 @@ fn load_plugin_dir(dir: &Path) {
 pub fn unload_plugin(id: &str) {
     log::info!("Plugin {} unloaded", id);
     PLUGINS.write().unwrap().remove(id);
+    #[cfg(feature = "plugin_wasm")]
+    super::wasm::remove(id);
+    super::capability::remove_granted(id);
 }
 
 pub(super) fn mark_uninstalled(id: &str, uninstalled: bool) {
@@ -352,26 +364,124 @@ pub fn reload_plugin(id: &str) -> ResultType<()> {
     load_plugin_path(&path)
 }
 
-fn load_plugin_path(path: &str) -> ResultType<()> {
-    log::info!("Begin load plugin {}", path);
+const SIGNING_FILE_NAME: &str = "plugins.toml";
 
-    let plugin = Plugin::new(path)?;
-    let desc = plugin.desc()?;
+/// The signing settings of the plugins, in the system config directory.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct Signing {
+    public_key: String,
+    /// For the development of the plugins of API version 2, which must be signed.
+    allow_unsigned: bool,
+}
 
-    // to-do validate plugin
-    // to-do check the plugin id (make sure it does not use another plugin's id)
+// Only from the file owned by the admin, never from the options or the key of the
+// rendezvous server, which the users can set.
+fn get_signing() -> Signing {
+    let path = crate::deploy_config::system_config_dir().join(SIGNING_FILE_NAME);
+    let Some(text) = crate::deploy_config::read_system_file(&path) else {
+        return Default::default();
+    };
+    toml::from_str(&text).unwrap_or_else(|err| {
+        log::error!("Invalid {:?}: {}", path, err);
+        Default::default()
+    })
+}
 
+/// Verifies `<plugin file>.sig`, the detached signature of the plugin file by the
+/// `public-key` of `plugins.toml`, or the built-in key if not set.
+///
+/// Returns whether the plugin is signed. An invalid signature is an error.
+fn verify_signature(path: &str) -> ResultType<bool> {
+    let sig_path = format!("{}.sig", path);
+    let Ok(sig) = std::fs::read_to_string(&sig_path) else {
+        return Ok(false);
+    };
+    let mut key = get_signing().public_key;
+    if key.is_empty() {
+        key = hbb_config::RS_PUB_KEY.to_owned();
+    }
+    let Some(pk) = crate::common::get_rs_pk(&key) else {
+        bail!("Invalid public key of the plugins");
+    };
+    let Ok(sig) = sign::Signature::from_bytes(&crate::decode64(sig.trim())?) else {
+        bail!("Invalid signature {}", sig_path);
+    };
+    if !sign::verify_detached(&sig, &std::fs::read(path)?, &pk) {
+        bail!("Signature mismatch of {}", path);
+    }
+    Ok(true)
+}
+
+// Checks the plugin and registers its info, returns the init info.
+fn register(desc: &Desc, path: &str, signed: bool) -> ResultType<String> {
+    // to-do check the plugin id (make sure it does not use another plugin's id)
     let id = desc.meta().id.clone();
+    if desc.api_version() >= 2 && !signed && !get_signing().allow_unsigned {
+        bail!("Plugin {} is not signed", id);
+    }
+    let grants = super::config::ManagerConfig::get_plugin_capabilities(&id);
+    let capabilities = super::capability::negotiate(desc, grants.as_deref())?;
+    log::info!(
+        "Plugin {} uses API version {}, granted {:?}",
+        id,
+        desc.api_version(),
+        capabilities
+    );
+    let mut init_capabilities: Vec<String> = capabilities.iter().cloned().collect();
+    init_capabilities.sort();
+    super::capability::set_granted(&id, capabilities);
+
     let plugin_info = PluginInfo {
         path: path.to_string(),
         uninstalled: false,
         desc: desc.clone(),
     };
-    PLUGIN_INFO.write().unwrap().insert(id.clone(), plugin_info);
+    PLUGIN_INFO.write().unwrap().insert(id, plugin_info);
 
-    let init_info = serde_json::to_string(&InitInfo {
+    Ok(serde_json::to_string(&InitInfo {
         is_server: super::is_server_running(),
-    })?;
+        api_version: super::capability::API_VERSION,
+        capabilities: init_capabilities,
+    })?)
+}
+
+fn after_init(desc: &Desc) -> ResultType<()> {
+    if super::is_server_running() {
+        super::config::ManagerConfig::add_plugin(&desc.meta().id)?;
+    }
+
+    // update ui
+    // Ui may be not ready now, so we need to update again once ui is ready.
+    reload_ui(desc, None);
+    Ok(())
+}
+
+fn load_plugin_path(path: &str) -> ResultType<()> {
+    log::info!("Begin load plugin {}", path);
+
+    let signed = verify_signature(path)?;
+
+    #[cfg(feature = "plugin_wasm")]
+    if super::wasm::is_wasm_path(path) {
+        let plugin = super::wasm::WasmPlugin::new(path)?;
+        let desc = plugin.desc()?;
+        let init_info = register(&desc, path, signed)?;
+        if let Err(e) = plugin.init(&init_info) {
+            log::error!("Failed to init plugin '{}', {}", desc.meta().id, e);
+        }
+        after_init(&desc)?;
+        let id = desc.meta().id.clone();
+        super::wasm::insert(id.clone(), plugin);
+        log::info!("Plugin {} loaded, {}", id, path);
+        return Ok(());
+    }
+
+    let plugin = Plugin::new(path)?;
+    let desc = plugin.desc()?;
+    let init_info = register(&desc, path, signed)?;
+    let id = desc.meta().id.clone();
+
     let init_data = InitData {
         version: str_to_cstr_ret(crate::VERSION),
         info: str_to_cstr_ret(&init_info) as _,
@@ -388,13 +498,7 @@ fn load_plugin_path(path: &str) -> ResultType<()> {
         log::error!("Failed to init plugin '{}', {}", desc.meta().id, e);
     }
 
-    if super::is_server_running() {
-        super::config::ManagerConfig::add_plugin(&desc.meta().id)?;
-    }
-
-    // update ui
-    // Ui may be not ready now, so we need to update again once ui is ready.
-    reload_ui(&desc, None);
+    after_init(&desc)?;
 
     // add plugins
     PLUGINS.write().unwrap().insert(id.clone(), plugin);
@@ -445,6 +549,14 @@ pub fn plugin_call_get_return(
     peer: &str,
     event: &[u8],
 ) -> ResultType<PluginReturn> {
+    #[cfg(feature = "plugin_wasm")]
+    if let Some((code, out)) = super::wasm::call(id, method, peer.as_bytes(), event) {
+        if code == ERR_SUCCESS {
+            return Ok(PluginReturn::success());
+        }
+        let msg = String::from_utf8_lossy(&out.unwrap_or_default()).to_string();
+        return Ok(PluginReturn::new(code, &msg));
+    }
     match PLUGINS.read().unwrap().get(id) {
         Some(plugin) => Ok((plugin.call)(
             method.as_ptr() as _,
@@ -463,6 +575,9 @@ pub fn handle_ui_event(id: &str, peer: &str, event: &[u8]) -> ResultType<()> {
 
 #[inline]
 pub fn handle_server_event(id: &str, peer: &str, event: &[u8]) -> ResultType<()> {
+    if !super::capability::is_granted(id, super::capability::CAP_PEER_MESSAGE) {
+        bail!("Plugin {} is not granted the peer messages", id);
+    }
     handle_event(METHOD_HANDLE_PEER, id, peer, event)
 }
 
@@ -486,14 +601,8 @@ fn _handle_listen_event(event: String, peer: String) {
         let mut peer: String = peer.to_owned();
         peer.push('\0');
         for id in plugins {
-            match PLUGINS.read().unwrap().get(&id) {
-                Some(plugin) => {
-                    let mut ret = (plugin.call)(
-                        METHOD_HANDLE_LISTEN_EVENT.as_ptr() as _,
-                        peer.as_ptr() as _,
-                        evt_bytes.as_ptr() as _,
-                        evt_bytes.len(),
-                    );
+            match plugin_call_get_return(&id, METHOD_HANDLE_LISTEN_EVENT, &peer, &evt_bytes) {
+                Ok(mut ret) => {
                     if !ret.is_success() {
                         let (code, msg) = ret.get_code_msg(&id);
                         log::error!(
@@ -505,7 +614,7 @@ fn _handle_listen_event(event: String, peer: String) {
                         );
                     }
                 }
-                None => {
+                Err(_) => {
                     log::error!("Plugin {} not found when handle_listen_event", id);
                 }
             }
@@ -520,8 +629,25 @@ pub fn handle_listen_event(event: String, peer: String) {
 
 #[inline]
 pub fn handle_client_event(id: &str, peer: &str, event: &[u8]) -> Message {
+    if !super::capability::is_granted(id, super::capability::CAP_PEER_MESSAGE) {
+        return make_plugin_failure(id, "", "Plugin capability is not granted");
+    }
     let mut peer: String = peer.to_owned();
     peer.push('\0');
+    #[cfg(feature = "plugin_wasm")]
+    if let Some((code, out)) = super::wasm::call(id, METHOD_HANDLE_PEER, peer.as_bytes(), event) {
+        let out = out.unwrap_or_default();
+        return if code > ERR_RUSTDESK_HANDLE_BASE && code < ERR_PLUGIN_HANDLE_BASE {
+            log::debug!(
+                "Plugin {} failed to handle client event, code: {}",
+                id,
+                code
+            );
+            make_plugin_failure(id, "", &String::from_utf8_lossy(&out))
+        } else {
+            make_plugin_request(id, out.as_ptr() as _, out.len())
+        };
+    }
     match PLUGINS.read().unwrap().get(id) {
         Some(plugin) => {
             let mut out = std::ptr::null_mut();
@@ -606,6 +732,9 @@ fn make_plugin_failure(id: &str, name: &str, msg: &str) -> Message {
 }
 
 fn reload_ui(desc: &Desc, sync_to: Option<&str>) {
+    if !super::capability::is_granted(&desc.meta().id, super::capability::CAP_UI) {
+        return;
+    }
     for (location, ui) in desc.location().ui.iter() {
         if let Ok(ui) = serde_json::to_string(&ui) {
             let make_event = |ui: &str| {
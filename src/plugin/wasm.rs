@@ -0,0 +1,440 @@
+//! WASM plugins, `plugin_<name>.wasm` in the plugin directory, run by the `wasmi` interpreter.
+//!
+//! A WASM plugin has no access to the system but the host functions of the module `rustdesk`,
+//! which are checked against its capabilities, and it is limited in memory and in fuel per call.
+//! It must be signed, see `plugins::verify_signature`, and it is reloaded when its file changes.
+//!
+//! The strings and the buffers are passed as a pointer and a length in the linear memory,
+//! a returned buffer is packed in an `i64` as `ptr << 32 | len`, 0 if none.
+//!
+//! Exports of the plugin:
+//! - `memory`
+//! - `rd_alloc(len: i32) -> i32`: memory for the host to pass the arguments, never freed by it
+//! - `rd_desc() -> i64`: the description JSON, see `desc::Desc`, with `"api_version": 2`
+//! - `rd_init(info: i32, info_len: i32) -> i32`: 0 on success, the info is the JSON of
+//!   `plugins::InitInfo` with the granted capabilities
+//! - `rd_call(method, method_len, peer, peer_len, args, args_len) -> i32`: the native `call`,
+//!   0 on success, the output or the error message is set by `set_output`
+//!
+//! Imports from `rustdesk`:
+//! - `log(level: i32, msg: i32, msg_len: i32)`: 1 error, 2 warn, 3 info, 4 debug, 5 trace
+//! - `send_msg(peer, peer_len, target, target_len, content, content_len) -> i32`: the native
+//!   `msg` callback, the code of `errno`
+//! - `get_conf(peer, peer_len, key, key_len) -> i64`: the shared config if the peer is empty
+//! - `get_id() -> i64`
+//! - `get_clipboard() -> i64`, `set_clipboard(text, text_len) -> i32`: `clipboard`
+//! - `set_output(data: i32, data_len: i32)`
+
+use super::{capability, errno, *};
+use hbb_common::{bail, lazy_static, log, ResultType};
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    sync::{Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+use wasmi::{
+    Caller, Config as EngineConfig, Engine, Extern, Instance, Linker, Memory, Module, Store,
+    StoreLimits, StoreLimitsBuilder,
+};
+
+const WASM_SUFFIX: &str = ".wasm";
+const MAX_MEMORY: usize = 64 * 1024 * 1024;
+const FUEL_PER_CALL: u64 = 1_000_000_000;
+const HOT_RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+lazy_static::lazy_static! {
+    static ref PLUGINS: RwLock<HashMap<String, WasmPlugin>> = Default::default();
+}
+
+struct HostState {
+    id: String,
+    limits: StoreLimits,
+    output: Option<Vec<u8>>,
+}
+
+pub(super) struct WasmPlugin {
+    path: String,
+    modified: Option<SystemTime>,
+    instance: Instance,
+    memory: Memory,
+    store: Mutex<Store<HostState>>,
+}
+
+#[inline]
+fn pack(ptr: i32, len: usize) -> i64 {
+    ((ptr as u32 as i64) << 32) | (len as u32 as i64)
+}
+
+#[inline]
+fn unpack(v: i64) -> (usize, usize) {
+    ((v as u64 >> 32) as usize, (v as u64 & 0xffff_ffff) as usize)
+}
+
+fn read(data: &[u8], ptr: i32, len: i32) -> Option<Vec<u8>> {
+    let start = ptr as u32 as usize;
+    data.get(start..start.checked_add(len as u32 as usize)?)
+        .map(|s| s.to_vec())
+}
+
+fn read_string(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> Option<String> {
+    let memory = caller.get_export("memory").and_then(Extern::into_memory)?;
+    String::from_utf8(read(memory.data(caller), ptr, len)?).ok()
+}
+
+// Copies the data to the memory allocated by the plugin.
+fn write_to_guest(caller: &mut Caller<'_, HostState>, data: &[u8]) -> i64 {
+    let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+        return 0;
+    };
+    let Some(alloc) = caller
+        .get_export("rd_alloc")
+        .and_then(Extern::into_func)
+        .and_then(|f| f.typed::<i32, i32>(&*caller).ok())
+    else {
+        return 0;
+    };
+    match alloc.call(&mut *caller, data.len() as _) {
+        Ok(ptr)
+            if memory
+                .write(&mut *caller, ptr as u32 as usize, data)
+                .is_ok() =>
+        {
+            pack(ptr, data.len())
+        }
+        _ => 0,
+    }
+}
+
+fn send_msg(id: &str, peer: &str, target: &str, content: &[u8]) -> i32 {
+    let (Ok(id), Ok(peer), Ok(target)) =
+        (CString::new(id), CString::new(peer), CString::new(target))
+    else {
+        return errno::ERR_CALLBACK_INVALID_ARGS;
+    };
+    let mut ret = super::callback_msg::cb_msg(
+        peer.as_ptr(),
+        target.as_ptr(),
+        id.as_ptr(),
+        content.as_ptr() as _,
+        content.len(),
+    );
+    let (code, msg) = ret.get_code_msg(&id.to_string_lossy());
+    if code != errno::ERR_SUCCESS {
+        log::debug!("Plugin message failed, code: {}, msg: {}", code, msg);
+    }
+    code
+}
+
+fn get_conf(id: &str, peer: &str, key: &str) -> Option<String> {
+    let id = CString::new(id).ok()?;
+    let peer = CString::new(peer).ok()?;
+    let key = CString::new(key).ok()?;
+    let peer_ptr = if peer.as_bytes().is_empty() {
+        std::ptr::null()
+    } else {
+        peer.as_ptr()
+    };
+    let value = super::config::cb_get_conf(peer_ptr, id.as_ptr(), key.as_ptr());
+    if value.is_null() {
+        return None;
+    }
+    let s = cstr_to_string(value).ok();
+    free_c_ptr(value as _);
+    s
+}
+
+fn linker(engine: &Engine) -> ResultType<Linker<HostState>> {
+    let mut linker = Linker::<HostState>::new(engine);
+    linker.func_wrap(
+        "rustdesk",
+        "log",
+        |caller: Caller<'_, HostState>, level: i32, ptr: i32, len: i32| {
+            let msg = read_string(&caller, ptr, len).unwrap_or_default();
+            let id = &caller.data().id;
+            match level {
+                1 => log::error!("[plugin {}] {}", id, msg),
+                2 => log::warn!("[plugin {}] {}", id, msg),
+                3 => log::info!("[plugin {}] {}", id, msg),
+                4 => log::debug!("[plugin {}] {}", id, msg),
+                _ => log::trace!("[plugin {}] {}", id, msg),
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "rustdesk",
+        "send_msg",
+        |caller: Caller<'_, HostState>,
+         peer: i32,
+         peer_len: i32,
+         target: i32,
+         target_len: i32,
+         content: i32,
+         content_len: i32|
+         -> i32 {
+            let (Some(peer), Some(target)) = (
+                read_string(&caller, peer, peer_len),
+                read_string(&caller, target, target_len),
+            ) else {
+                return errno::ERR_CALLBACK_INVALID_ARGS;
+            };
+            let Some(content) = caller
+                .get_export("memory")
+                .and_then(Extern::into_memory)
+                .and_then(|m| read(m.data(&caller), content, content_len))
+            else {
+                return errno::ERR_CALLBACK_INVALID_ARGS;
+            };
+            send_msg(&caller.data().id, &peer, &target, &content)
+        },
+    )?;
+    linker.func_wrap(
+        "rustdesk",
+        "get_conf",
+        |mut caller: Caller<'_, HostState>, peer: i32, peer_len: i32, key: i32, key_len: i32| {
+            let (Some(peer), Some(key)) = (
+                read_string(&caller, peer, peer_len),
+                read_string(&caller, key, key_len),
+            ) else {
+                return 0;
+            };
+            match get_conf(&caller.data().id.clone(), &peer, &key) {
+                Some(value) => write_to_guest(&mut caller, value.as_bytes()),
+                None => 0,
+            }
+        },
+    )?;
+    linker.func_wrap("rustdesk", "get_id", |mut caller: Caller<'_, HostState>| {
+        write_to_guest(&mut caller, crate::ui_interface::get_id().as_bytes())
+    })?;
+    linker.func_wrap(
+        "rustdesk",
+        "get_clipboard",
+        |mut caller: Caller<'_, HostState>| {
+            if !capability::is_granted(&caller.data().id, capability::CAP_CLIPBOARD) {
+                return 0;
+            }
+            match arboard::Clipboard::new().and_then(|mut c| c.get_text()) {
+                Ok(text) => write_to_guest(&mut caller, text.as_bytes()),
+                Err(_) => 0,
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "rustdesk",
+        "set_clipboard",
+        |caller: Caller<'_, HostState>, ptr: i32, len: i32| -> i32 {
+            if !capability::is_granted(&caller.data().id, capability::CAP_CLIPBOARD) {
+                return errno::ERR_CALLBACK_CAPABILITY;
+            }
+            let Some(text) = read_string(&caller, ptr, len) else {
+                return errno::ERR_CALLBACK_INVALID_ARGS;
+            };
+            match arboard::Clipboard::new().and_then(|mut c| c.set_text(text)) {
+                Ok(_) => errno::ERR_SUCCESS,
+                Err(_) => errno::ERR_CALLBACK_FAILED,
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "rustdesk",
+        "set_output",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            let data = caller
+                .get_export("memory")
+                .and_then(Extern::into_memory)
+                .and_then(|m| read(m.data(&caller), ptr, len));
+            caller.data_mut().output = data;
+        },
+    )?;
+    Ok(linker)
+}
+
+// Each call gets the same fuel, so a plugin can not hang the caller.
+fn refuel(store: &mut Store<HostState>) -> ResultType<()> {
+    let left = store.consume_fuel(0)?;
+    store.consume_fuel(left)?;
+    store.add_fuel(FUEL_PER_CALL)?;
+    Ok(())
+}
+
+impl WasmPlugin {
+    pub(super) fn new(path: &str) -> ResultType<Self> {
+        let data = std::fs::read(path)?;
+        let mut config = EngineConfig::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &data[..])?;
+        let mut store = Store::new(
+            &engine,
+            HostState {
+                id: path.to_owned(),
+                limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build(),
+                output: None,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        refuel(&mut store)?;
+        let instance = linker(&engine)?
+            .instantiate(&mut store, &module)?
+            .start(&mut store)?;
+        let Some(memory) = instance.get_memory(&store, "memory") else {
+            bail!("No memory exported by {}", path);
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+            instance,
+            memory,
+            store: Mutex::new(store),
+        })
+    }
+
+    fn write_arg(&self, store: &mut Store<HostState>, data: &[u8]) -> ResultType<(i32, i32)> {
+        let alloc = self
+            .instance
+            .get_typed_func::<i32, i32>(&*store, "rd_alloc")?;
+        let ptr = alloc.call(&mut *store, data.len() as _)?;
+        self.memory.write(&mut *store, ptr as u32 as usize, data)?;
+        Ok((ptr, data.len() as _))
+    }
+
+    fn read_packed(&self, store: &Store<HostState>, v: i64) -> Option<Vec<u8>> {
+        let (ptr, len) = unpack(v);
+        read(self.memory.data(store), ptr as _, len as _)
+    }
+
+    pub(super) fn desc(&self) -> ResultType<desc::Desc> {
+        let mut store = self.store.lock().unwrap();
+        refuel(&mut store)?;
+        let f = self
+            .instance
+            .get_typed_func::<(), i64>(&*store, "rd_desc")?;
+        let v = f.call(&mut *store, ())?;
+        let Some(data) = self.read_packed(&store, v) else {
+            bail!("Invalid description of {}", self.path);
+        };
+        let desc: desc::Desc = serde_json::from_slice(&data)?;
+        if desc.api_version() < 2 {
+            bail!(
+                "WASM plugin {} must use the API version 2 or later",
+                self.path
+            );
+        }
+        store.data_mut().id = desc.meta().id.clone();
+        Ok(desc)
+    }
+
+    pub(super) fn init(&self, info: &str) -> ResultType<()> {
+        let mut store = self.store.lock().unwrap();
+        refuel(&mut store)?;
+        let (ptr, len) = self.write_arg(&mut store, info.as_bytes())?;
+        let f = self
+            .instance
+            .get_typed_func::<(i32, i32), i32>(&*store, "rd_init")?;
+        let code = f.call(&mut *store, (ptr, len))?;
+        if code != errno::ERR_SUCCESS {
+            bail!("Failed to init plugin {}, code: {}", self.path, code);
+        }
+        Ok(())
+    }
+
+    /// The code and the output set by the plugin, as `PluginFuncCallWithOutData`.
+    pub(super) fn call(
+        &self,
+        method: &str,
+        peer: &str,
+        args: &[u8],
+    ) -> ResultType<(i32, Option<Vec<u8>>)> {
+        let mut store = self.store.lock().unwrap();
+        refuel(&mut store)?;
+        store.data_mut().output = None;
+        let method = self.write_arg(&mut store, method.as_bytes())?;
+        let peer = self.write_arg(&mut store, peer.as_bytes())?;
+        let args = self.write_arg(&mut store, args)?;
+        let f = self
+            .instance
+            .get_typed_func::<(i32, i32, i32, i32, i32, i32), i32>(&*store, "rd_call")?;
+        let code = f.call(
+            &mut *store,
+            (method.0, method.1, peer.0, peer.1, args.0, args.1),
+        )?;
+        Ok((code, store.data_mut().output.take()))
+    }
+}
+
+// The methods and the peers of the native API end with a nul.
+#[inline]
+fn trim_nul(s: &[u8]) -> String {
+    String::from_utf8_lossy(s.strip_suffix(&[0]).unwrap_or(s)).to_string()
+}
+
+pub(super) fn insert(id: String, plugin: WasmPlugin) {
+    PLUGINS.write().unwrap().insert(id, plugin);
+}
+
+pub(super) fn remove(id: &str) {
+    PLUGINS.write().unwrap().remove(id);
+}
+
+/// Calls the plugin if it is a WASM one, as `plugins::plugin_call_get_return`.
+pub(super) fn call(
+    id: &str,
+    method: &[u8],
+    peer: &[u8],
+    args: &[u8],
+) -> Option<(i32, Option<Vec<u8>>)> {
+    let plugins = PLUGINS.read().unwrap();
+    let plugin = plugins.get(id)?;
+    match plugin.call(&trim_nul(method), &trim_nul(peer), args) {
+        Ok(ret) => Some(ret),
+        Err(e) => Some((
+            errno::ERR_PLUGIN_TRAPPED,
+            Some(format!("Plugin {} trapped, {}", id, e).into_bytes()),
+        )),
+    }
+}
+
+/// Reloads the WASM plugins whose files are changed, e.g. rebuilt in development.
+pub(super) fn start_hot_reload() {
+    std::thread::spawn(|| loop {
+        std::thread::sleep(HOT_RELOAD_INTERVAL);
+        let changed: Vec<String> = PLUGINS
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, p)| {
+                std::fs::metadata(&p.path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .map_or(false, |t| Some(t) != p.modified)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in changed {
+            log::info!("Plugin {} changed, reloading", id);
+            if let Err(e) = super::plugins::reload_plugin(&id) {
+                log::error!("Failed to reload plugin {}, {}", id, e);
+            }
+        }
+    });
+}
+
+pub(super) fn is_wasm_path(path: &str) -> bool {
+    path.ends_with(WASM_SUFFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack() {
+        assert_eq!(unpack(pack(0x1234, 56)), (0x1234, 56));
+        assert_eq!(unpack(pack(-1, 1)), (0xffff_ffff, 1));
+        assert_eq!(read(b"abcdef", 2, 3), Some(b"cde".to_vec()));
+        assert_eq!(read(b"abcdef", 4, 3), None);
+        assert_eq!(read(b"abcdef", -1, 3), None);
+        assert_eq!(trim_nul(b"handle_ui\0"), "handle_ui");
+    }
+}
@@ -0,0 +1,196 @@
+//! Settings enforced centrally, e.g. by an MSP over a fleet.
+//!
+//! The policy is a JSON signed with a key pair dedicated to the policies, in the format of
+//! `id_ed25519` of hbbs, and encoded in base64, like `custom.txt`. Its public key is read only
+//! from `policy.pub` in the system config directory, put there by the admin or the installer,
+//! no policy is applied without it. The policy is read from `policy.txt` in the same directory,
+//! only if the file is owned by the admin, or pushed by the API server in the heartbeat
+//! response, which the service saves to that file. Each process loads it on start.
+//!
+//! The `version` has to be increased with every new policy. The highest version applied is
+//! kept in `policy.version`, a policy with a lower one is rejected, so that an older signed
+//! policy can not be replayed to undo a newer one.
+//!
+//! ```json
+//! {
+//!   "version": 2,
+//!   "settings": {"enable-file-transfer": "N", "custom-rendezvous-server": "hbbs.example.com"},
+//!   "local-settings": {},
+//!   "display-settings": {"force-always-relay": "Y"}
+//! }
+//! ```
+//!
+//! The values go to the override settings of `Config`, which win over the local and the user
+//! values, and are shown read-only in the UI as the fixed options of a custom client.
+
+use hbb_common::{bail, config, log, sodiumoxide::crypto::sign, ResultType};
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+const POLICY_FILE_NAME: &str = "policy.txt";
+const POLICY_KEY_FILE_NAME: &str = "policy.pub";
+const POLICY_VERSION_FILE_NAME: &str = "policy.version";
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Policy {
+    pub version: u64,
+    pub settings: HashMap<String, String>,
+    pub local_settings: HashMap<String, String>,
+    pub display_settings: HashMap<String, String>,
+}
+
+type Overrides = [HashMap<String, String>; 3];
+
+lazy_static::lazy_static! {
+    // The override settings before the first policy, i.e. of the custom client.
+    static ref BASE: Mutex<Option<Overrides>> = Default::default();
+    static ref CURRENT: Mutex<Option<String>> = Default::default();
+}
+
+pub fn policy_path() -> PathBuf {
+    crate::deploy_config::system_config_dir().join(POLICY_FILE_NAME)
+}
+
+// Never the key of the rendezvous server, which the users can set.
+fn get_key() -> ResultType<String> {
+    let path = crate::deploy_config::system_config_dir().join(POLICY_KEY_FILE_NAME);
+    match crate::deploy_config::read_system_file(&path) {
+        Some(key) if !key.trim().is_empty() => Ok(key.trim().to_owned()),
+        _ => bail!("No public key of the policy in {:?}", path),
+    }
+}
+
+fn version_path() -> PathBuf {
+    crate::deploy_config::system_config_dir().join(POLICY_VERSION_FILE_NAME)
+}
+
+// The highest version of the policies applied.
+fn applied_version() -> u64 {
+    crate::deploy_config::read_system_file(&version_path())
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or_default()
+}
+
+// Verifies the policy with the key of the policies, and that it is not older than the one
+// applied.
+fn check(signed: &str) -> ResultType<Policy> {
+    let policy = verify(signed, &get_key()?)?;
+    let applied = applied_version();
+    if policy.version < applied {
+        bail!("Version {} older than {}", policy.version, applied);
+    }
+    Ok(policy)
+}
+
+/// Verifies the base64 signed policy with the public key of the policies.
+pub fn verify(signed: &str, key: &str) -> ResultType<Policy> {
+    let Some(pk) = crate::common::get_rs_pk(key) else {
+        bail!("Invalid public key");
+    };
+    let data = crate::decode64(signed.trim())?;
+    let Ok(data) = sign::verify(&data, &pk) else {
+        bail!("Signature mismatch");
+    };
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Signs the policy JSON with the base64 secret key of the policies.
+pub fn sign_policy(json: &str, secret_key: &str) -> ResultType<String> {
+    let policy: Policy = serde_json::from_str(json)?;
+    let Some(sk) = sign::SecretKey::from_slice(&crate::decode64(secret_key.trim())?) else {
+        bail!("Invalid secret key");
+    };
+    let data = serde_json::to_vec(&policy)?;
+    Ok(crate::encode64(sign::sign(&data, &sk)))
+}
+
+fn apply(policy: &Policy) {
+    let mut display = config::OVERWRITE_DISPLAY_SETTINGS.write().unwrap();
+    let mut local = config::OVERWRITE_LOCAL_SETTINGS.write().unwrap();
+    let mut server = config::OVERWRITE_SETTINGS.write().unwrap();
+    let mut base = BASE.lock().unwrap();
+    let base = base.get_or_insert_with(|| [server.clone(), local.clone(), display.clone()]);
+    *server = base[0].clone();
+    *local = base[1].clone();
+    *display = base[2].clone();
+    server.extend(policy.settings.clone());
+    local.extend(policy.local_settings.clone());
+    display.extend(policy.display_settings.clone());
+}
+
+/// Loads the policy file, the previous policy is removed if the file is gone or invalid.
+pub fn load() {
+    let path = policy_path();
+    let signed = crate::deploy_config::read_system_file(&path).unwrap_or_default();
+    let mut current = CURRENT.lock().unwrap();
+    if current.as_deref().unwrap_or_default() == signed {
+        return;
+    }
+    let policy = if signed.is_empty() {
+        Policy::default()
+    } else {
+        match check(&signed) {
+            Ok(policy) => {
+                log::info!("Policy loaded from {:?}", path);
+                policy
+            }
+            Err(err) => {
+                log::error!("Failed to load the policy {:?}: {}", path, err);
+                Policy::default()
+            }
+        }
+    };
+    apply(&policy);
+    *current = Some(signed);
+}
+
+/// The policy pushed by the API server, saved if valid and different.
+pub fn update(signed: &str) {
+    if CURRENT.lock().unwrap().as_deref() == Some(signed) {
+        return;
+    }
+    let policy = match check(signed) {
+        Ok(policy) => policy,
+        Err(err) => {
+            log::error!("Rejected the policy from the API server: {}", err);
+            return;
+        }
+    };
+    let path = policy_path();
+    let res = crate::deploy_config::create_system_config_dir().and_then(|_| {
+        std::fs::write(&path, signed)?;
+        Ok(std::fs::write(version_path(), policy.version.to_string())?)
+    });
+    if let Err(err) = res {
+        log::error!("Failed to save the policy to {:?}: {}", path, err);
+        return;
+    }
+    log::info!("Policy updated by the API server");
+    load();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_verify() {
+        let (pk, sk) = sign::gen_keypair();
+        let json = r#"{"version": 3, "settings": {"enable-file-transfer": "N"}}"#;
+        let signed = sign_policy(json, &crate::encode64(&sk.0[..])).unwrap();
+        let policy = verify(&signed, &crate::encode64(&pk.0[..])).unwrap();
+        assert_eq!(
+            policy
+                .settings
+                .get("enable-file-transfer")
+                .map(|x| x.as_str()),
+            Some("N")
+        );
+        assert_eq!(policy.version, 3);
+        assert!(policy.local_settings.is_empty());
+
+        let (other, _) = sign::gen_keypair();
+        assert!(verify(&signed, &crate::encode64(&other.0[..])).is_err());
+    }
+}
@@ -157,7 +157,9 @@ async fn connect_and_login(
                         Some(message::Union::TestDelay(t)) => {
                             interface.handle_test_delay(t, &mut stream).await;
                         }
-                        _ => {}
+                        _ => {
+                            crate::auth_security_key::answer(&msg_in, &mut stream).await?;
+                        }
                     }
                 }
                 Ok(Some(Err(err))) => {
@@ -28,6 +28,11 @@ mod win_virtual_display;
 #[cfg(windows)]
 pub use win_virtual_display::restore_reg_connectivity;
 
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+
 pub const INVALID_PRIVACY_MODE_CONN_ID: i32 = 0;
 pub const OCCUPIED: &'static str = "Privacy occupied by another one.";
 pub const TURN_OFF_OTHER_ID: &'static str =
@@ -38,6 +43,8 @@ pub const PRIVACY_MODE_IMPL_WIN_MAG: &str = "privacy_mode_impl_mag";
 pub const PRIVACY_MODE_IMPL_WIN_EXCLUDE_FROM_CAPTURE: &str =
     "privacy_mode_impl_exclude_from_capture";
 pub const PRIVACY_MODE_IMPL_WIN_VIRTUAL_DISPLAY: &str = "privacy_mode_impl_virtual_display";
+pub const PRIVACY_MODE_IMPL_LINUX_X11: &str = "privacy_mode_impl_linux_x11";
+pub const PRIVACY_MODE_IMPL_MACOS: &str = "privacy_mode_impl_macos";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "t", content = "c")]
@@ -103,7 +110,19 @@ lazy_static::lazy_static! {
                 }
             }.to_owned()
         }
-        #[cfg(not(windows))]
+        #[cfg(target_os = "linux")]
+        {
+            if linux::is_supported() {
+                PRIVACY_MODE_IMPL_LINUX_X11
+            } else {
+                ""
+            }.to_owned()
+        }
+        #[cfg(target_os = "macos")]
+        {
+            PRIVACY_MODE_IMPL_MACOS.to_owned()
+        }
+        #[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
         {
             "".to_owned()
         }
@@ -126,9 +145,9 @@ lazy_static::lazy_static! {
 pub type PrivacyModeCreator = fn(impl_key: &str) -> Box<dyn PrivacyMode>;
 lazy_static::lazy_static! {
     static ref PRIVACY_MODE_CREATOR: Arc<Mutex<HashMap<&'static str, PrivacyModeCreator>>> = {
-        #[cfg(not(windows))]
+        #[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
         let map: HashMap<&'static str, PrivacyModeCreator> = HashMap::new();
-        #[cfg(windows)]
+        #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
         let mut map: HashMap<&'static str, PrivacyModeCreator> = HashMap::new();
         #[cfg(windows)]
         {
@@ -146,6 +165,16 @@ lazy_static::lazy_static! {
                     Box::new(win_virtual_display::PrivacyModeImpl::new(impl_key))
                 });
         }
+        #[cfg(target_os = "linux")]
+        if linux::is_supported() {
+            map.insert(linux::PRIVACY_MODE_IMPL, |impl_key: &str| {
+                Box::new(linux::PrivacyModeImpl::new(impl_key))
+            });
+        }
+        #[cfg(target_os = "macos")]
+        map.insert(macos::PRIVACY_MODE_IMPL, |impl_key: &str| {
+            Box::new(macos::PrivacyModeImpl::new(impl_key))
+        });
         Arc::new(Mutex::new(map))
     };
 }
@@ -333,7 +362,18 @@ pub fn get_supported_privacy_mode_impl() -> Vec<(&'static str, &'static str)> {
 
         vec_impls
     }
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        if DEFAULT_PRIVACY_MODE_IMPL.is_empty() {
+            Vec::new()
+        } else {
+            vec![(
+                DEFAULT_PRIVACY_MODE_IMPL.as_str(),
+                "privacy_mode_impl_mag_tip",
+            )]
+        }
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
     {
         Vec::new()
     }
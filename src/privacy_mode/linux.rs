@@ -0,0 +1,165 @@
+use super::{PrivacyMode, PrivacyModeState, INVALID_PRIVACY_MODE_CONN_ID};
+use crate::platform::linux::{is_x11, run_cmds};
+use hbb_common::{allow_err, bail, log, ResultType};
+
+pub(super) const PRIVACY_MODE_IMPL: &str = super::PRIVACY_MODE_IMPL_LINUX_X11;
+
+// The X server keeps rendering to the framebuffer, so the capture is not affected by the
+// gamma and DPMS of the outputs.
+// The input is injected with XTest, whose devices are not disabled.
+const XTEST_DEVICE: &str = "XTEST";
+
+pub(super) fn is_supported() -> bool {
+    is_x11() && which("xrandr") && which("xinput")
+}
+
+fn which(cmd: &str) -> bool {
+    run_cmds(&format!("which {}", cmd)).map_or(false, |output| !output.trim().is_empty())
+}
+
+pub struct PrivacyModeImpl {
+    impl_key: String,
+    conn_id: i32,
+    // The active outputs, with the brightness to restore.
+    outputs: Vec<(String, String)>,
+    // The ids of the physical input devices disabled.
+    devices: Vec<String>,
+}
+
+impl PrivacyMode for PrivacyModeImpl {
+    fn is_async_privacy_mode(&self) -> bool {
+        false
+    }
+
+    fn init(&self) -> ResultType<()> {
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        allow_err!(self.turn_off_privacy(self.conn_id, None));
+    }
+
+    fn turn_on_privacy(&mut self, conn_id: i32) -> ResultType<bool> {
+        if self.check_on_conn_id(conn_id)? {
+            log::debug!("Privacy mode of conn {} is already on", conn_id);
+            return Ok(true);
+        }
+
+        let outputs = get_active_outputs()?;
+        if outputs.is_empty() {
+            bail!(super::NO_PHYSICAL_DISPLAYS);
+        }
+        for (output, _) in outputs.iter() {
+            if let Err(e) = run_cmds(&format!("xrandr --output {} --brightness 0", output)) {
+                self.outputs = outputs;
+                self.restore();
+                bail!("Failed to blank output {}: {}", output, e);
+            }
+        }
+        self.outputs = outputs;
+        self.devices = get_physical_input_devices();
+        for id in self.devices.iter() {
+            allow_err!(run_cmds(&format!("xinput disable {}", id)));
+        }
+        allow_err!(run_cmds("xset dpms force off"));
+        self.conn_id = conn_id;
+        Ok(true)
+    }
+
+    fn turn_off_privacy(
+        &mut self,
+        conn_id: i32,
+        _state: Option<PrivacyModeState>,
+    ) -> ResultType<()> {
+        self.check_off_conn_id(conn_id)?;
+        self.restore();
+        self.conn_id = INVALID_PRIVACY_MODE_CONN_ID;
+        Ok(())
+    }
+
+    #[inline]
+    fn pre_conn_id(&self) -> i32 {
+        self.conn_id
+    }
+
+    #[inline]
+    fn get_impl_key(&self) -> &str {
+        &self.impl_key
+    }
+}
+
+impl PrivacyModeImpl {
+    pub fn new(impl_key: &str) -> Self {
+        Self {
+            impl_key: impl_key.to_owned(),
+            conn_id: INVALID_PRIVACY_MODE_CONN_ID,
+            outputs: Vec::new(),
+            devices: Vec::new(),
+        }
+    }
+
+    fn restore(&mut self) {
+        let blanked = !self.outputs.is_empty();
+        for id in self.devices.drain(..) {
+            allow_err!(run_cmds(&format!("xinput enable {}", id)));
+        }
+        for (output, brightness) in self.outputs.drain(..) {
+            allow_err!(run_cmds(&format!(
+                "xrandr --output {} --brightness {}",
+                output, brightness
+            )));
+        }
+        if blanked {
+            allow_err!(run_cmds("xset dpms force on"));
+        }
+    }
+}
+
+impl Drop for PrivacyModeImpl {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+// Parses `xrandr --verbose`, the active outputs have a geometry, e.g.
+// "HDMI-1 connected primary 1920x1080+0+0 (0x48) normal ...", followed by "\tBrightness: 1.0".
+fn get_active_outputs() -> ResultType<Vec<(String, String)>> {
+    let output = run_cmds("xrandr --verbose")?;
+    let mut outputs = Vec::new();
+    let mut cur: Option<String> = None;
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            cur = None;
+            let mut fields = line.split_whitespace();
+            if let (Some(name), Some("connected")) = (fields.next(), fields.next()) {
+                if fields.any(|f| f.contains('x') && f.contains('+')) {
+                    cur = Some(name.to_owned());
+                }
+            }
+        } else if let Some(name) = cur.as_ref() {
+            if let Some(brightness) = line.trim().strip_prefix("Brightness:") {
+                outputs.push((name.clone(), brightness.trim().to_owned()));
+                cur = None;
+            }
+        }
+    }
+    Ok(outputs)
+}
+
+// Parses `xinput list --short`, e.g.
+// "⎜   ↳ SynPS/2 Synaptics TouchPad    id=12    [slave  pointer  (2)]".
+// The disabled devices are "[floating slave]".
+fn get_physical_input_devices() -> Vec<String> {
+    let Ok(output) = run_cmds("xinput list --short") else {
+        return Vec::new();
+    };
+    output
+        .lines()
+        .filter(|line| line.contains("[slave") && !line.contains(XTEST_DEVICE))
+        .filter_map(|line| {
+            line.split_whitespace()
+                .find_map(|f| f.strip_prefix("id="))
+                .map(|id| id.to_owned())
+        })
+        .collect()
+}
@@ -0,0 +1,73 @@
+use super::{PrivacyMode, PrivacyModeState, INVALID_PRIVACY_MODE_CONN_ID};
+use hbb_common::{allow_err, bail, log, ResultType};
+
+pub(super) const PRIVACY_MODE_IMPL: &str = super::PRIVACY_MODE_IMPL_MACOS;
+
+// Implemented in `src/platform/macos_privacy.mm`.
+extern "C" {
+    fn MacPrivacyModeOn() -> bool;
+    fn MacPrivacyModeOff();
+}
+
+pub struct PrivacyModeImpl {
+    impl_key: String,
+    conn_id: i32,
+}
+
+impl PrivacyMode for PrivacyModeImpl {
+    fn is_async_privacy_mode(&self) -> bool {
+        false
+    }
+
+    fn init(&self) -> ResultType<()> {
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        allow_err!(self.turn_off_privacy(self.conn_id, None));
+    }
+
+    fn turn_on_privacy(&mut self, conn_id: i32) -> ResultType<bool> {
+        if self.check_on_conn_id(conn_id)? {
+            log::debug!("Privacy mode of conn {} is already on", conn_id);
+            return Ok(true);
+        }
+        if !unsafe { MacPrivacyModeOn() } {
+            bail!("Failed to block the local input, please check the accessibility permission");
+        }
+        self.conn_id = conn_id;
+        Ok(true)
+    }
+
+    fn turn_off_privacy(
+        &mut self,
+        conn_id: i32,
+        _state: Option<PrivacyModeState>,
+    ) -> ResultType<()> {
+        self.check_off_conn_id(conn_id)?;
+        if self.conn_id != INVALID_PRIVACY_MODE_CONN_ID {
+            unsafe { MacPrivacyModeOff() };
+            self.conn_id = INVALID_PRIVACY_MODE_CONN_ID;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn pre_conn_id(&self) -> i32 {
+        self.conn_id
+    }
+
+    #[inline]
+    fn get_impl_key(&self) -> &str {
+        &self.impl_key
+    }
+}
+
+impl PrivacyModeImpl {
+    pub fn new(impl_key: &str) -> Self {
+        Self {
+            impl_key: impl_key.to_owned(),
+            conn_id: INVALID_PRIVACY_MODE_CONN_ID,
+        }
+    }
+}
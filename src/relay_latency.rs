@@ -0,0 +1,151 @@
+//! Latency based selection among multiple relay servers.
+//!
+//! The relay server setting, and the relay server provided by the rendezvous server,
+//! may be a comma-separated list. The TCP connect time to every relay is measured
+//! in the background, the results are cached for `PROBE_INTERVAL`, and each connection
+//! uses the reachable relay with the lowest latency known so far.
+
+use hbb_common::{
+    config::{Config, RELAY_PORT},
+    futures::future::join_all,
+    log,
+    socket_client::connect_tcp,
+    tokio,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(60);
+const PROBE_TIMEOUT: u64 = 3_000;
+
+lazy_static::lazy_static! {
+    // relay server -> (latency in ms, None if unreachable; probe time)
+    static ref LATENCIES: Mutex<HashMap<String, (Option<u32>, Instant)>> = Default::default();
+    // The lists of relay servers being probed.
+    static ref PROBING: Mutex<HashSet<Vec<String>>> = Default::default();
+}
+
+pub fn split_servers(relay_server: &str) -> Vec<String> {
+    let mut servers: Vec<String> = Vec::new();
+    for s in relay_server.split(',').map(|x| x.trim()) {
+        if !s.is_empty() && !servers.iter().any(|x| x == s) {
+            servers.push(s.to_owned());
+        }
+    }
+    servers
+}
+
+async fn probe(server: String) -> (String, Option<u32>) {
+    let tm = Instant::now();
+    let latency = match connect_tcp(crate::check_port(&server, RELAY_PORT), PROBE_TIMEOUT).await {
+        Ok(_) => Some(tm.elapsed().as_millis() as u32),
+        Err(err) => {
+            log::debug!("relay server {} is unreachable: {}", server, err);
+            None
+        }
+    };
+    (server, latency)
+}
+
+pub async fn probe_all(servers: &[String]) {
+    let results = join_all(servers.iter().cloned().map(probe)).await;
+    let now = Instant::now();
+    let mut lock = LATENCIES.lock().unwrap();
+    for (server, latency) in results {
+        lock.insert(server, (latency, now));
+    }
+}
+
+fn is_fresh(servers: &[String]) -> bool {
+    let lock = LATENCIES.lock().unwrap();
+    servers.iter().all(|s| {
+        lock.get(s)
+            .map(|(_, tm)| tm.elapsed() < PROBE_INTERVAL)
+            .unwrap_or(false)
+    })
+}
+
+// The reachable server with the lowest latency, the first one if none is reachable.
+fn best(servers: &[String], latencies: &HashMap<String, (Option<u32>, Instant)>) -> String {
+    servers
+        .iter()
+        .filter_map(|s| latencies.get(s).and_then(|(l, _)| l.map(|l| (l, s))))
+        .min_by_key(|(l, _)| *l)
+        .map(|(_, s)| s.clone())
+        .unwrap_or_else(|| servers.first().cloned().unwrap_or_default())
+}
+
+/// Pick one relay server out of a comma-separated list, without waiting for the probes.
+///
+/// The latencies which are missing or expired are refreshed in the background for the
+/// next connections, this one gets the best relay known so far, or the first one.
+pub fn select(relay_server: &str) -> String {
+    let servers = split_servers(relay_server);
+    if servers.len() <= 1 {
+        return servers.into_iter().next().unwrap_or_default();
+    }
+    if !is_fresh(&servers) && PROBING.lock().unwrap().insert(servers.clone()) {
+        let servers = servers.clone();
+        tokio::spawn(async move {
+            probe_all(&servers).await;
+            PROBING.lock().unwrap().remove(&servers);
+        });
+    }
+    let selected = best(&servers, &LATENCIES.lock().unwrap());
+    log::info!("selected relay server {} out of {:?}", selected, servers);
+    selected
+}
+
+/// The measured latencies of the configured relay servers, -1 if unreachable.
+pub fn get_latencies() -> HashMap<String, i64> {
+    let lock = LATENCIES.lock().unwrap();
+    split_servers(&Config::get_option("relay-server"))
+        .into_iter()
+        .filter_map(|s| {
+            lock.get(&s)
+                .map(|(l, _)| (s.clone(), l.map(|l| l as i64).unwrap_or(-1)))
+        })
+        .collect()
+}
+
+async fn probe_configured() {
+    let servers = split_servers(&Config::get_option("relay-server"));
+    if servers.len() > 1 {
+        probe_all(&servers).await;
+    }
+}
+
+/// Keep the latencies of the configured relay servers up to date.
+pub async fn probe_periodically() {
+    loop {
+        probe_configured().await;
+        tokio::time::sleep(PROBE_INTERVAL).await;
+    }
+}
+
+/// Probe the configured relay servers now, for the settings page.
+#[tokio::main(flavor = "current_thread")]
+pub async fn probe_now() {
+    probe_configured().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_best() {
+        let servers = split_servers("a, b,,c,a");
+        assert_eq!(servers, vec!["a", "b", "c"]);
+        let now = Instant::now();
+        let mut latencies = HashMap::new();
+        assert_eq!(best(&servers, &latencies), "a");
+        latencies.insert("a".to_owned(), (None, now));
+        latencies.insert("b".to_owned(), (Some(80), now));
+        latencies.insert("c".to_owned(), (Some(20), now));
+        assert_eq!(best(&servers, &latencies), "c");
+    }
+}
@@ -40,6 +40,14 @@ lazy_static::lazy_static! {
 }
 static SHOULD_EXIT: AtomicBool = AtomicBool::new(false);
 static MANUAL_RESTARTED: AtomicBool = AtomicBool::new(false);
+// Whether the UDP registration over IPv4 and IPv6 is alive.
+static REGISTERED: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+// Set once the registration over IPv4 fails, until it works again. We register over IPv6
+// only meanwhile, the rendezvous server keeps one address per id, which would otherwise
+// flip between both.
+static IPV4_FAILED: AtomicBool = AtomicBool::new(false);
+// The longest wait for the TURN allocation, to answer a punch hole.
+const TURN_WAIT: Duration = Duration::from_millis(1_000);
 
 #[derive(Clone)]
 pub struct RendezvousMediator {
@@ -47,6 +55,9 @@ pub struct RendezvousMediator {
     host: String,
     host_prefix: String,
     keep_alive: i32,
+    // Set if registered over IPv6 in addition to IPv4, the connections back to the
+    // rendezvous server must use the same address family.
+    addr_v6: Option<SocketAddr>,
 }
 
 impl RendezvousMediator {
@@ -65,7 +76,10 @@ impl RendezvousMediator {
         }
         crate::hbbs_http::sync::start();
         #[cfg(target_os = "windows")]
-        if crate::platform::is_installed() && crate::is_server() && !crate::is_custom_client() {
+        if crate::platform::is_installed()
+            && crate::is_server()
+            && (!crate::is_custom_client() || crate::updater::manifest::has_update_url())
+        {
             crate::updater::start_auto_update();
         }
         check_zombie();
@@ -77,6 +91,7 @@ impl RendezvousMediator {
         tokio::spawn(async move {
             direct_server(server_cloned).await;
         });
+        tokio::spawn(crate::relay_latency::probe_periodically());
         #[cfg(target_os = "android")]
         let start_lan_listening = true;
         #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -153,15 +168,27 @@ impl RendezvousMediator {
             .unwrap_or(host.to_owned())
     }
 
-    pub async fn start_udp(server: ServerPtr, host: String) -> ResultType<()> {
+    pub async fn start_udp(
+        server: ServerPtr,
+        host: String,
+        addr_v6: Option<SocketAddr>,
+    ) -> ResultType<()> {
         let host = check_port(&host, RENDEZVOUS_PORT);
-        log::info!("start udp: {host}");
-        let (mut socket, mut addr) = new_udp_for(&host, CONNECT_TIMEOUT).await?;
+        log::info!("start udp: {host}, ipv6: {:?}", addr_v6);
+        let (mut socket, mut addr) = if let Some(addr_v6) = addr_v6 {
+            (
+                FramedSocket::new(Config::get_any_listen_addr(false)).await?,
+                addr_v6.into_target_addr()?,
+            )
+        } else {
+            new_udp_for(&host, CONNECT_TIMEOUT).await?
+        };
         let mut rz = Self {
             addr: addr.clone(),
             host: host.clone(),
             host_prefix: Self::get_host_prefix(&host),
             keep_alive: crate::DEFAULT_KEEP_ALIVE,
+            addr_v6,
         };
 
         let mut timer = crate::rustdesk_interval(interval(crate::TIMER_OUT));
@@ -177,8 +204,17 @@ impl RendezvousMediator {
         let mut last_dns_check = Instant::now();
         let mut old_latency = 0;
         let mut ema_latency = 0;
+        // Registered over both address families, the failures of one are not reported
+        // as long as the other one works.
+        let family = addr_v6.is_some() as usize;
+        let other_registered = || REGISTERED[1 - family].load(Ordering::SeqCst);
+        REGISTERED[family].store(false, Ordering::SeqCst);
         loop {
             let mut update_latency = || {
+                REGISTERED[family].store(true, Ordering::SeqCst);
+                if family == 0 {
+                    IPV4_FAILED.store(false, Ordering::SeqCst);
+                }
                 last_register_resp = Some(Instant::now());
                 fails = 0;
                 reg_timeout = MIN_REG_TIMEOUT;
@@ -235,23 +271,42 @@ impl RendezvousMediator {
                             reg_timeout += MIN_REG_TIMEOUT;
                         }
                     }
-                    if timeout || (last_register_sent.is_none() && expired) {
+                    if family == 1 && !IPV4_FAILED.load(Ordering::SeqCst) {
+                        // Standby, registered over IPv4.
+                        REGISTERED[family].store(false, Ordering::SeqCst);
+                        (last_register_resp, last_register_sent, fails) = (None, None, 0);
+                    } else if timeout || (last_register_sent.is_none() && expired) {
                         if timeout {
                             fails += 1;
+                            if fails >= MAX_FAILS1 {
+                                REGISTERED[family].store(false, Ordering::SeqCst);
+                                if family == 0 {
+                                    IPV4_FAILED.store(true, Ordering::SeqCst);
+                                }
+                            }
                             if fails >= MAX_FAILS2 {
-                                Config::update_latency(&host, -1);
+                                if !other_registered() {
+                                    Config::update_latency(&host, -1);
+                                }
                                 old_latency = 0;
                                 if last_dns_check.elapsed().as_millis() as i64 > DNS_INTERVAL {
                                     // in some case of network reconnect (dial IP network),
                                     // old UDP socket not work any more after network recover
-                                    if let Some((s, new_addr)) = socket_client::rebind_udp_for(&rz.host).await? {
+                                    if addr_v6.is_some() {
+                                        if let (_, Some(new_addr)) = crate::resolve_dual_stack(&rz.host).await {
+                                            socket = FramedSocket::new(Config::get_any_listen_addr(false)).await?;
+                                            rz.addr_v6 = Some(new_addr);
+                                            rz.addr = new_addr.into_target_addr()?;
+                                            addr = rz.addr.clone();
+                                        }
+                                    } else if let Some((s, new_addr)) = socket_client::rebind_udp_for(&rz.host).await? {
                                         socket = s;
                                         rz.addr = new_addr.clone();
                                         addr = new_addr;
                                     }
                                     last_dns_check = Instant::now();
                                 }
-                            } else if fails >= MAX_FAILS1 {
+                            } else if fails >= MAX_FAILS1 && !other_registered() {
                                 Config::update_latency(&host, 0);
                                 old_latency = 0;
                             }
@@ -341,7 +396,7 @@ impl RendezvousMediator {
     pub async fn start_tcp(server: ServerPtr, host: String) -> ResultType<()> {
         let host = check_port(&host, RENDEZVOUS_PORT);
         log::info!("start tcp: {}", hbb_common::websocket::check_ws(&host));
-        let mut conn = connect_tcp(host.clone(), CONNECT_TIMEOUT).await?;
+        let mut conn = crate::tls_pin::connect_tcp(host.clone(), CONNECT_TIMEOUT).await?;
         let key = crate::get_key(true).await;
         crate::secure_tcp(&mut conn, &key).await?;
         let mut rz = Self {
@@ -349,6 +404,7 @@ impl RendezvousMediator {
             host: host.clone(),
             host_prefix: Self::get_host_prefix(&host),
             keep_alive: crate::DEFAULT_KEEP_ALIVE,
+            addr_v6: None,
         };
         let mut timer = crate::rustdesk_interval(interval(crate::TIMER_OUT));
         let mut last_register_sent: Option<Instant> = None;
@@ -406,7 +462,53 @@ impl RendezvousMediator {
         {
             Self::start_tcp(server, host).await
         } else {
-            Self::start_udp(server, host).await
+            Self::start_udp_dual_stack(server, host).await
+        }
+    }
+
+    // Register over IPv6 too if the rendezvous server is reachable over both address
+    // families, so that the peers on IPv6-only networks (with NAT64) can still reach us,
+    // and so that we keep online if one of the address families stops working. We only
+    // register over IPv6 while IPv4 does not work, see `IPV4_FAILED`.
+    async fn start_udp_dual_stack(server: ServerPtr, host: String) -> ResultType<()> {
+        let host = check_port(&host, RENDEZVOUS_PORT);
+        let (Some(_), Some(addr_v6)) = crate::resolve_dual_stack(&host).await else {
+            return Self::start_udp(server, host, None).await;
+        };
+        IPV4_FAILED.store(false, Ordering::SeqCst);
+        let v4 = Self::start_udp(server.clone(), host.clone(), None);
+        let v6 = Self::start_udp(server, host, Some(addr_v6));
+        // Keep going as long as one of them works, e.g. IPv4 fails at once without IPv4 route.
+        let (res_v4, res_v6) = tokio::join!(
+            async {
+                let res = v4.await;
+                REGISTERED[0].store(false, Ordering::SeqCst);
+                IPV4_FAILED.store(true, Ordering::SeqCst);
+                if let Err(err) = res.as_ref() {
+                    log::warn!("rendezvous mediator over IPv4 exits: {err}");
+                }
+                res
+            },
+            async {
+                let res = v6.await;
+                REGISTERED[1].store(false, Ordering::SeqCst);
+                if let Err(err) = res.as_ref() {
+                    log::warn!("rendezvous mediator over IPv6 exits: {err}");
+                }
+                res
+            }
+        );
+        res_v4.and(res_v6)
+    }
+
+    // Where to connect back to the rendezvous server for `peer_addr`, over IPv6 only if
+    // we registered over IPv6 and either the peer is on IPv6 too or IPv4 does not work.
+    fn rendezvous_target(&self, peer_addr: &SocketAddr) -> String {
+        match self.addr_v6 {
+            Some(addr) if peer_addr.is_ipv6() || !REGISTERED[0].load(Ordering::SeqCst) => {
+                addr.to_string()
+            }
+            _ => self.host.clone(),
         }
     }
 
@@ -450,7 +552,8 @@ impl RendezvousMediator {
             secure,
         );
 
-        let mut socket = connect_tcp(&*self.host, CONNECT_TIMEOUT).await?;
+        let mut socket = connect_tcp(self.rendezvous_target(&peer_addr), CONNECT_TIMEOUT).await?;
+        let ipv4 = socket.local_addr().is_ipv4();
 
         let mut msg_out = Message::new();
         let mut rr = RelayResponse {
@@ -466,15 +569,7 @@ impl RendezvousMediator {
         }
         msg_out.set_relay_response(rr);
         socket.send(&msg_out).await?;
-        crate::create_relay_connection(
-            server,
-            relay_server,
-            uuid,
-            peer_addr,
-            secure,
-            is_ipv4(&self.addr),
-        )
-        .await;
+        crate::create_relay_connection(server, relay_server, uuid, peer_addr, secure, ipv4).await;
         Ok(())
     }
 
@@ -487,7 +582,8 @@ impl RendezvousMediator {
             return Ok(());
         }
         let peer_addr_v6 = hbb_common::AddrMangle::decode(&fla.socket_addr_v6);
-        let relay_server = self.get_relay_server(fla.relay_server.clone());
+        let relay_server =
+            crate::relay_latency::select(&self.get_relay_server(fla.relay_server.clone()));
         let relay = use_ws() || Config::is_proxy();
         let mut socket_addr_v6 = Default::default();
         if peer_addr_v6.port() > 0 && !relay {
@@ -530,7 +626,7 @@ impl RendezvousMediator {
     ) -> ResultType<()> {
         let peer_addr = AddrMangle::decode(&fla.socket_addr);
         log::debug!("Handle intranet from {:?}", peer_addr);
-        let mut socket = connect_tcp(&*self.host, CONNECT_TIMEOUT).await?;
+        let mut socket = connect_tcp(self.rendezvous_target(&peer_addr), CONNECT_TIMEOUT).await?;
         let local_addr = socket.local_addr();
         // we saw invalid local_addr while using proxy, local_addr.ip() == "::1"
         let local_addr: SocketAddr =
@@ -561,11 +657,22 @@ impl RendezvousMediator {
         }
         let peer_addr_v6 = hbb_common::AddrMangle::decode(&ph.socket_addr_v6);
         let relay = use_ws() || Config::is_proxy() || ph.force_relay;
+        // The TURN allocation takes a few round trips to the TURN server, it runs meanwhile.
+        let turn = (!relay && crate::ice::turn::is_configured())
+            .then(|| tokio::spawn(crate::ice::turn::relay_for(peer_addr)));
         let mut socket_addr_v6 = Default::default();
         if peer_addr_v6.port() > 0 && !relay {
             socket_addr_v6 = start_ipv6(peer_addr_v6, peer_addr, server.clone()).await;
         }
-        let relay_server = self.get_relay_server(ph.relay_server);
+        let relay_server = crate::relay_latency::select(&self.get_relay_server(ph.relay_server));
+        if let Some(turn) = turn {
+            if socket_addr_v6.is_empty() {
+                socket_addr_v6 = start_turn(turn, peer_addr, server.clone()).await;
+            } else {
+                // The allocation is released once its socket is dropped with the result.
+                turn.abort();
+            }
+        }
         // for ensure, websocket go relay directly
         if ph.nat_type.enum_value() == Ok(NatType::SYMMETRIC)
             || Config::get_nat_type() == NatType::SYMMETRIC as i32
@@ -603,7 +710,7 @@ impl RendezvousMediator {
         }
         log::debug!("Punch tcp hole to {:?}", peer_addr);
         let mut socket = {
-            let socket = connect_tcp(&*self.host, CONNECT_TIMEOUT).await?;
+            let socket = connect_tcp(self.rendezvous_target(&peer_addr), CONNECT_TIMEOUT).await?;
             let local_addr = socket.local_addr();
             // key important here for punch hole to tell my gateway incoming peer is safe.
             // it can not be async here, because local_addr can not be reused, we must close the connection before use it again.
@@ -626,7 +733,7 @@ impl RendezvousMediator {
     ) -> ResultType<()> {
         let mut msg_out = Message::new();
         msg_out.set_punch_hole_sent(msg_punch);
-        let (socket, addr) = new_direct_udp_for(&self.host).await?;
+        let (socket, addr) = new_direct_udp_for(&self.rendezvous_target(&peer_addr)).await?;
         let data = msg_out.write_to_bytes()?;
         socket.send_to(&data, addr).await?;
         let socket_cloned = socket.clone();
@@ -821,6 +928,36 @@ async fn start_ipv6(
     Default::default()
 }
 
+// The relay candidate goes to the peer in the slot of the IPv6 candidate, which is only
+// filled if the peer has IPv6. The punch hole is not held up by a slow TURN server.
+async fn start_turn(
+    mut turn: tokio::task::JoinHandle<
+        ResultType<(SocketAddr, Arc<tokio::net::UdpSocket>, SocketAddr)>,
+    >,
+    peer_addr: SocketAddr,
+    server: ServerPtr,
+) -> bytes::Bytes {
+    let res = match tokio::time::timeout(TURN_WAIT, &mut turn).await {
+        Ok(res) => res.map_err(|e| e.into()).and_then(|x| x),
+        Err(_) => {
+            turn.abort();
+            Err(anyhow::anyhow!("timeout"))
+        }
+    };
+    match res {
+        Ok((relayed_addr, socket, proxy_addr)) => {
+            tokio::spawn(async move {
+                allow_err!(udp_nat_listen(socket, proxy_addr, peer_addr, server).await);
+            });
+            AddrMangle::encode(relayed_addr).into()
+        }
+        Err(err) => {
+            log::warn!("Failed to allocate TURN relay for {}: {}", peer_addr, err);
+            Default::default()
+        }
+    }
+}
+
 async fn udp_nat_listen(
     socket: Arc<tokio::net::UdpSocket>,
     peer_addr: SocketAddr,
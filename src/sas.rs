@@ -0,0 +1,51 @@
+//! Short Authentication String of the secure channel.
+//!
+//! Both sides hash the key exchange, the ephemeral public key signed by the controlled
+//! side, the ephemeral public key of the controlling side and the sealed session key,
+//! into a few emojis. A man in the middle has to run a key exchange with each side, so
+//! the two sides show different emojis.
+//!
+//! Once the user confirms the emojis match, the fingerprint of the long-term key of the
+//! peer is stored in the peer config, and a different key on later connections is warned.
+
+use sha2::{Digest, Sha256};
+
+pub const PEER_OPTION_VERIFIED_KEY: &str = "verified-key";
+const SAS_LEN: usize = 7;
+
+const EMOJIS: [&str; 64] = [
+    "🐶", "🐱", "🦁", "🐎", "🦄", "🐷", "🐘", "🐰", "🐼", "🐓", "🐧", "🐢", "🐟", "🐙", "🦋", "🌷",
+    "🌳", "🌵", "🍄", "🌏", "🌙", "☁️", "🔥", "🍌", "🍎", "🍓", "🌽", "🍕", "🎂", "❤️", "😀", "🤖",
+    "🎩", "👓", "🔧", "🎅", "👍", "☂️", "⌛", "⏰", "🎁", "💡", "📕", "✏️", "📎", "✂️", "🔒", "🔑",
+    "🔨", "☎️", "🏁", "🚂", "🚲", "✈️", "🚀", "🏆", "⚽", "🎸", "🎺", "🔔", "⚓", "🎧", "📁", "📌",
+];
+
+/// The emojis separated by spaces, 6 bits of the transcript hash each.
+pub fn compute(server_pk: &[u8], client_pk: &[u8], sealed_key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"rustdesk-sas");
+    hasher.update(server_pk);
+    hasher.update(client_pk);
+    hasher.update(sealed_key);
+    let digest = hasher.finalize();
+    let mut bits = u64::from_be_bytes(digest[..8].try_into().unwrap_or_default());
+    let mut emojis = Vec::with_capacity(SAS_LEN);
+    for _ in 0..SAS_LEN {
+        emojis.push(EMOJIS[(bits >> 58) as usize]);
+        bits <<= 6;
+    }
+    emojis.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute() {
+        let a = compute(&[1; 32], &[2; 32], &[3; 48]);
+        assert_eq!(a.split(' ').count(), SAS_LEN);
+        assert_eq!(a, compute(&[1; 32], &[2; 32], &[3; 48]));
+        assert_ne!(a, compute(&[1; 32], &[4; 32], &[3; 48]));
+    }
+}
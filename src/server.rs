@@ -30,7 +30,7 @@ use service::ServiceTmpl;
 use service::{EmptyExtraFieldService, GenericService, Service, Subscriber};
 use video_service::VideoSource;
 
-use crate::ipc::Data;
+use crate::{client::bonding, ipc::Data};
 
 pub mod audio_service;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -47,7 +47,11 @@ pub mod uinput;
 #[cfg(target_os = "linux")]
 pub mod rdp_input;
 #[cfg(target_os = "linux")]
+pub mod ei_input;
+#[cfg(target_os = "linux")]
 pub mod dbus;
+#[cfg(target_os = "linux")]
+pub mod vm_console;
 #[cfg(not(target_os = "android"))]
 pub mod input_service;
 } else {
@@ -64,13 +68,30 @@ pub mod input_service {
     pub const NAME_WINDOW_FOCUS: &'static str = "";
 }
 
+pub mod access_list;
+mod audit_log;
+mod bandwidth;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod block_input;
 mod connection;
 pub mod display_service;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod local_api;
+pub mod lockout;
+pub mod metrics;
+pub mod one_time_password;
+mod peer_profile;
 #[cfg(windows)]
 pub mod portable_service;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod power_state;
+pub mod record_signing;
 mod service;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod session_hook;
 mod video_qos;
 pub mod video_service;
+pub mod webhook;
 
 #[cfg(all(target_os = "windows", feature = "flutter"))]
 pub mod printer_service;
@@ -175,7 +196,59 @@ pub async fn create_tcp_connection(
     secure: bool,
 ) -> ResultType<()> {
     let mut stream = stream;
+    let (mut sas, mut hello) = secure_incoming(&mut stream, secure).await?;
+    // A bonded connection may be a file stream as well.
+    loop {
+        let accepted = match hello.take() {
+            Some(hello) => bonding::accept_hello(stream, hello).await?,
+            None if crate::file_stream::is_pending() => bonding::accept(stream).await?,
+            None => break,
+        };
+        match accepted {
+            bonding::Accepted::Plain(s) => {
+                stream = s;
+                break;
+            }
+            bonding::Accepted::Bonded(s) => {
+                stream = s;
+                (sas, hello) = secure_incoming(&mut stream, secure).await?;
+            }
+            bonding::Accepted::Attached => return Ok(()),
+            bonding::Accepted::FileStream(token, s) => {
+                return crate::file_stream::attach(&token, s).await;
+            }
+        }
+    }
     let id = server.write().unwrap().get_new_id();
+
+    #[cfg(target_os = "macos")]
+    {
+        use std::process::Command;
+        if let Ok(task) = Command::new("/usr/bin/caffeinate")
+            .arg("-u")
+            .arg("-t 5")
+            .spawn()
+        {
+            super::CHILD_PROCESS.lock().unwrap().push(task);
+        }
+        log::info!("wake up macos");
+    }
+    let conn = Connection::start(addr, stream, id, Arc::downgrade(&server), sas);
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    crate::logging::scope(id, conn).await;
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    conn.await;
+    Ok(())
+}
+
+/// Returns the short authentication string if the connection is secured.
+// Returns the short authentication string, and the bonding hello sent before the public key.
+async fn secure_incoming(
+    stream: &mut Stream,
+    secure: bool,
+) -> ResultType<(Option<String>, Option<Bytes>)> {
+    let mut sas = None;
+    let mut hello = None;
     let (sk, pk) = Config::get_key_pair();
     if secure && pk.len() == sign::PUBLICKEYBYTES && sk.len() == sign::SECRETKEYBYTES {
         let mut sk_ = [0u8; sign::SECRETKEYBYTES];
@@ -198,7 +271,14 @@ pub async fn create_tcp_connection(
             ..Default::default()
         });
         timeout(CONNECT_TIMEOUT, stream.send(&msg_out)).await??;
-        match timeout(CONNECT_TIMEOUT, stream.next()).await? {
+        let mut res = timeout(CONNECT_TIMEOUT, stream.next()).await?;
+        if let Some(Ok(bytes)) = &res {
+            if bonding::is_hello(bytes) {
+                hello = Some(bytes.clone().freeze());
+                res = timeout(CONNECT_TIMEOUT, stream.next()).await?;
+            }
+        }
+        match res {
             Some(res) => {
                 let bytes = res?;
                 if let Ok(msg_in) = Message::parse_from_bytes(&bytes) {
@@ -209,6 +289,11 @@ pub async fn create_tcp_connection(
                                 &pk.asymmetric_value,
                                 &our_sk_b,
                             )?);
+                            sas = Some(crate::sas::compute(
+                                &our_pk_b.0,
+                                &pk.asymmetric_value,
+                                &pk.symmetric_value,
+                            ));
                         } else if pk.asymmetric_value.is_empty() {
                             Config::set_key_confirmed(false);
                             log::info!("Force to update pk");
@@ -227,21 +312,7 @@ pub async fn create_tcp_connection(
             }
         }
     }
-
-    #[cfg(target_os = "macos")]
-    {
-        use std::process::Command;
-        if let Ok(task) = Command::new("/usr/bin/caffeinate")
-            .arg("-u")
-            .arg("-t 5")
-            .spawn()
-        {
-            super::CHILD_PROCESS.lock().unwrap().push(task);
-        }
-        log::info!("wake up macos");
-    }
-    Connection::start(addr, stream, id, Arc::downgrade(&server)).await;
-    Ok(())
+    Ok((sas, hello))
 }
 
 pub async fn accept_connection(
@@ -283,7 +354,7 @@ async fn create_relay_connection_(
     secure: bool,
     ipv4: bool,
 ) -> ResultType<()> {
-    let mut stream = socket_client::connect_tcp(
+    let mut stream = crate::tls_pin::connect_tcp(
         socket_client::ipv4_to_ipv6(crate::check_port(relay_server, RELAY_PORT), ipv4),
         CONNECT_TIMEOUT,
     )
@@ -344,6 +415,13 @@ impl Server {
         self.connections.insert(conn.id(), conn);
     }
 
+    pub fn add_audio_connection(&mut self, conn: ConnInner) {
+        if let Some(s) = self.services.get(audio_service::NAME) {
+            s.on_subscribe(conn.clone());
+        }
+        self.connections.insert(conn.id(), conn);
+    }
+
     pub fn add_connection(&mut self, conn: ConnInner, noperms: &Vec<&'static str>) {
         let primary_video_service_name = video_service::get_service_name(
             VideoSource::Monitor,
@@ -432,6 +510,13 @@ impl Server {
         }
     }
 
+    fn is_subed(&self, name: &str, conn_id: i32) -> bool {
+        self.services
+            .get(name)
+            .map(|s| s.is_subed(conn_id))
+            .unwrap_or(false)
+    }
+
     fn get_subbed_displays_count(&self, conn_id: i32) -> usize {
         self.services
             .keys()
@@ -570,8 +655,14 @@ pub async fn start_server(is_server: bool, no_server: bool) {
         tokio::spawn(async { sync_and_watch_config_dir().await });
         #[cfg(target_os = "windows")]
         crate::platform::try_kill_broker();
+        #[cfg(target_os = "windows")]
+        if crate::platform::privilege_broker::is_worker() {
+            tokio::spawn(crate::platform::privilege_broker::run_worker());
+        }
         #[cfg(feature = "hwcodec")]
         scrap::hwcodec::start_check_process();
+        tokio::spawn(local_api::start());
+        tokio::spawn(crate::crash_report::upload());
         crate::RendezvousMediator::start_all().await;
     } else {
         match crate::ipc::connect(1000, "").await {
@@ -0,0 +1,264 @@
+//! Access control list evaluated before the login request is processed.
+//!
+//! One rule per line (or separated by ';'), the first active rule matching the peer
+//! decides, e.g.
+//!
+//! ```text
+//! deny 203.0.113.0/24
+//! allow 10.0.0.0/8
+//! allow 192.168.1.0/24 mon-fri 08:00-18:00
+//! ```
+//!
+//! The target is an IP address, a CIDR range, a peer ID or `*`. The optional days
+//! (`mon-fri`, `sat,sun`) and time window (`22:00-06:00` spans midnight) are in local
+//! time. If there is any allow rule, a peer matching no rule is rejected.
+//!
+//! The IP address is checked when the connection is opened and the ID once the login
+//! request arrives, so an ID rule defers the decision to the latter.
+//!
+//! The ID is the one the peer claims in its login request, it is not authenticated. So the
+//! ID rules are no allowlist: any peer can pass `allow 123456789` by claiming that ID and
+//! avoid `deny 123456789` by claiming another one, only the IP rules restrict who can reach
+//! the password check.
+//!
+//! A list with an invalid rule is rejected when it is saved, and if one is found anyway, e.g.
+//! in a config of an older version, every connection is rejected rather than the rule skipped.
+
+use chrono::{Datelike, Local, Timelike};
+use cidr_utils::cidr::IpCidr;
+use hbb_common::{config::Config, log};
+use std::{net::IpAddr, str::FromStr};
+
+pub const OPTION_ACCESS_CONTROL_LIST: &str = "access-control-list";
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+#[derive(PartialEq)]
+enum Action {
+    Allow,
+    Deny,
+}
+
+enum Target {
+    Any,
+    Ip(IpCidr),
+    Id(String),
+}
+
+struct TimeWindow {
+    // Bit n for the day n days from Monday.
+    days: u8,
+    // Minutes of the day, `None` for the whole day.
+    time: Option<(u32, u32)>,
+}
+
+struct Rule {
+    text: String,
+    action: Action,
+    target: Target,
+    window: Option<TimeWindow>,
+}
+
+fn parse_day(s: &str) -> Option<u32> {
+    DAYS.iter()
+        .position(|d| *d == s.to_lowercase())
+        .map(|x| x as u32)
+}
+
+fn parse_days(s: &str) -> Option<u8> {
+    let mut days = 0u8;
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (parse_day(from)?, parse_day(to)?);
+                let mut d = from;
+                loop {
+                    days |= 1 << d;
+                    if d == to {
+                        break;
+                    }
+                    d = (d + 1) % 7;
+                }
+            }
+            None => days |= 1 << parse_day(part)?,
+        }
+    }
+    Some(days)
+}
+
+fn parse_minutes(s: &str) -> Option<u32> {
+    let (h, m) = s.split_once(':')?;
+    let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+    if h > 24 || m > 59 || (h == 24 && m > 0) {
+        return None;
+    }
+    Some(h * 60 + m)
+}
+
+fn parse_time(s: &str) -> Option<(u32, u32)> {
+    let (start, end) = s.split_once('-')?;
+    Some((parse_minutes(start)?, parse_minutes(end)?))
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    let mut it = line.split_whitespace();
+    let action = match it.next()?.to_lowercase().as_str() {
+        "allow" => Action::Allow,
+        "deny" => Action::Deny,
+        _ => return None,
+    };
+    let target = match it.next()? {
+        "*" => Target::Any,
+        // Peer IDs may be all digits, which must not be taken as an address.
+        x if x.contains(|c| c == '.' || c == ':') => Target::Ip(IpCidr::from_str(x).ok()?),
+        x => Target::Id(x.to_owned()),
+    };
+    let mut window: Option<TimeWindow> = None;
+    for x in it {
+        let w = window.get_or_insert(TimeWindow {
+            days: 0x7f,
+            time: None,
+        });
+        if x.contains(':') {
+            w.time = Some(parse_time(x)?);
+        } else {
+            w.days = parse_days(x)?;
+        }
+    }
+    Some(Rule {
+        text: line.to_owned(),
+        action,
+        target,
+        window,
+    })
+}
+
+fn lines(s: &str) -> impl Iterator<Item = &str> {
+    s.split(|c| c == '\n' || c == ';')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+}
+
+// Err with the first invalid rule.
+fn parse(s: &str) -> Result<Vec<Rule>, String> {
+    lines(s)
+        .map(|x| parse_rule(x).ok_or_else(|| x.to_owned()))
+        .collect()
+}
+
+impl TimeWindow {
+    // `now` is (days from Monday, minutes of the day).
+    fn contains(&self, (day, minutes): (u32, u32)) -> bool {
+        let Some((start, end)) = self.time else {
+            return self.days & (1 << day) != 0;
+        };
+        if start <= end {
+            self.days & (1 << day) != 0 && minutes >= start && minutes < end
+        } else if minutes >= start {
+            self.days & (1 << day) != 0
+        } else {
+            // The part after midnight belongs to the window started the day before.
+            minutes < end && self.days & (1 << ((day + 6) % 7)) != 0
+        }
+    }
+}
+
+/// Returns the text of the rejecting rule, or of the reason, if the peer is rejected.
+/// `id` is `None` before the login request, in which case the check is only final
+/// if no ID rule is involved.
+fn evaluate(
+    rules: &[Rule],
+    ip: Option<IpAddr>,
+    id: Option<&str>,
+    now: (u32, u32),
+) -> Result<(), String> {
+    for rule in rules {
+        if !rule.window.as_ref().map_or(true, |w| w.contains(now)) {
+            continue;
+        }
+        let matched = match (&rule.target, id) {
+            (Target::Any, _) => true,
+            (Target::Ip(cidr), _) => ip.map_or(false, |ip| cidr.contains(ip)),
+            (Target::Id(x), Some(id)) => x == id,
+            (Target::Id(_), None) => return Ok(()),
+        };
+        if matched {
+            return match rule.action {
+                Action::Allow => Ok(()),
+                Action::Deny => Err(rule.text.clone()),
+            };
+        }
+    }
+    if rules.iter().any(|r| r.action == Action::Allow) {
+        return Err("no allow rule matched".to_owned());
+    }
+    Ok(())
+}
+
+/// The first invalid rule, for the settings page.
+pub fn first_invalid(s: &str) -> Option<String> {
+    parse(s).err()
+}
+
+pub fn check(ip: Option<IpAddr>, id: Option<&str>) -> Result<(), String> {
+    let rules = match parse(&Config::get_option(OPTION_ACCESS_CONTROL_LIST)) {
+        Ok(rules) => rules,
+        Err(rule) => {
+            log::error!(
+                "Invalid access rule, all connections are rejected: {}",
+                rule
+            );
+            return Err(format!("invalid rule {}", rule));
+        }
+    };
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let now = Local::now();
+    evaluate(
+        &rules,
+        ip,
+        id,
+        (
+            now.weekday().num_days_from_monday(),
+            now.hour() * 60 + now.minute(),
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let rules = parse(
+            "deny 10.0.0.5\nallow 10.0.0.0/8; allow 123456789\n# comment\nallow 192.168.1.0/24 mon-fri 08:00-18:00",
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 4);
+        assert_eq!(
+            first_invalid("allow 10.0.0.0/8\ninvalid\ndeny *").as_deref(),
+            Some("invalid")
+        );
+        assert_eq!(first_invalid("allow 10.0.0.0/8\n# comment"), None);
+        let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+        let monday_noon = (0, 12 * 60);
+        let sunday_noon = (6, 12 * 60);
+        assert!(evaluate(&rules, ip("10.0.0.5"), None, monday_noon).is_err());
+        assert!(evaluate(&rules, ip("10.1.2.3"), Some("1"), monday_noon).is_ok());
+        // Deferred to the login request by the ID rule.
+        assert!(evaluate(&rules, ip("172.16.0.1"), None, monday_noon).is_ok());
+        assert!(evaluate(&rules, ip("172.16.0.1"), Some("123456789"), monday_noon).is_ok());
+        assert!(evaluate(&rules, ip("172.16.0.1"), Some("1"), monday_noon).is_err());
+        assert!(evaluate(&rules, ip("192.168.1.9"), Some("1"), monday_noon).is_ok());
+        assert!(evaluate(&rules, ip("192.168.1.9"), Some("1"), sunday_noon).is_err());
+
+        let night = parse_rule("deny * fri 22:00-06:00").unwrap();
+        let w = night.window.unwrap();
+        assert!(w.contains((4, 23 * 60)));
+        assert!(w.contains((5, 60)));
+        assert!(!w.contains((5, 23 * 60)));
+        assert_eq!(parse_days("sat-mon"), Some(0b110_0001));
+    }
+}
@@ -15,15 +15,27 @@
 use super::*;
 #[cfg(not(any(target_os = "linux", target_os = "android")))]
 use hbb_common::anyhow::anyhow;
-use magnum_opus::{Application::*, Channels::*, Encoder};
-use std::sync::atomic::{AtomicBool, Ordering};
+use magnum_opus::{Application::*, Bitrate, Channels, Channels::*, Encoder};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
 
 pub const NAME: &'static str = "audio";
 pub const AUDIO_DATA_SIZE_U8: usize = 960 * 4; // 10ms in 48000 stereo
+/// Plays the microphone of the controlling side without a voice call.
+/// Nobody accepts it on this side, so it is off by default.
+pub const OPTION_ALLOW_MIC_PASSTHROUGH: &str = "allow-mic-passthrough";
 static RESTARTING: AtomicBool = AtomicBool::new(false);
+const TUNE_INTERVAL: Duration = Duration::from_secs(1);
+const PTS_INTERVAL: Duration = Duration::from_secs(1);
+// The frames are not continuous after a longer gap, e.g. the zero gate.
+const PTS_MAX_GAP: Duration = Duration::from_millis(100);
 
 lazy_static::lazy_static! {
     static ref VOICE_CALL_INPUT_DEVICE: Arc::<Mutex::<Option<String>>> = Default::default();
+    // The device selected by the controlling side, it is shared by all the connections.
+    static ref PEER_AUDIO_DEVICE: Arc::<Mutex::<Option<String>>> = Default::default();
 }
 
 #[cfg(not(any(target_os = "linux", target_os = "android")))]
@@ -40,6 +52,14 @@ pub fn new() -> GenericService {
     svc.sp
 }
 
+#[inline]
+pub fn is_mic_passthrough_allowed() -> bool {
+    hbb_common::config::option2bool(
+        OPTION_ALLOW_MIC_PASSTHROUGH,
+        &Config::get_option(OPTION_ALLOW_MIC_PASSTHROUGH),
+    )
+}
+
 #[inline]
 pub fn get_voice_call_input_device() -> Option<String> {
     VOICE_CALL_INPUT_DEVICE.lock().unwrap().clone()
@@ -58,15 +78,137 @@ pub fn set_voice_call_input_device(device: Option<String>, set_if_present: bool)
     restart();
 }
 
+#[inline]
+pub fn get_peer_audio_device() -> Option<String> {
+    PEER_AUDIO_DEVICE.lock().unwrap().clone()
+}
+
+#[inline]
+pub fn set_peer_audio_device(device: Option<String>) {
+    if *PEER_AUDIO_DEVICE.lock().unwrap() == device {
+        return;
+    }
+    *PEER_AUDIO_DEVICE.lock().unwrap() = device;
+    restart();
+}
+
+/// Lists the devices which can be captured, the output devices are captured by loopback on Windows.
+pub fn get_audio_devices() -> Vec<String> {
+    #[cfg(target_os = "linux")]
+    return crate::platform::linux::get_pa_sources()
+        .drain(..)
+        .map(|x| x.1)
+        .collect();
+    #[cfg(target_os = "android")]
+    return vec![];
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    return cpal_impl::get_devices();
+}
+
 #[inline]
 fn get_audio_input() -> String {
     VOICE_CALL_INPUT_DEVICE
         .lock()
         .unwrap()
         .clone()
+        .or_else(get_peer_audio_device)
         .unwrap_or(Config::get_option("audio-input"))
 }
 
+/// Opus encoder following the audio quality of the video qos.
+///
+/// The input is always 10ms frames, they are encoded together if the frame size is longer.
+struct AudioEncoder {
+    encoder: Encoder,
+    quality: Option<video_qos::AudioQuality>,
+    tune_instant: Option<Instant>,
+    pending: Vec<f32>,
+    pts_instant: Option<Instant>,
+    last_frame: Option<Instant>,
+}
+
+impl AudioEncoder {
+    fn new(sample_rate: u32, channels: Channels) -> ResultType<Self> {
+        Ok(Self {
+            encoder: Encoder::new(sample_rate, channels, LowDelay)?,
+            quality: None,
+            tune_instant: None,
+            pending: vec![],
+            pts_instant: None,
+            last_frame: None,
+        })
+    }
+
+    /// The timestamp of the next frame of `frame_ms`, sent periodically and after a gap.
+    /// The peer counts the samples in between.
+    fn pts_msg(&mut self, frame_ms: u32) -> Option<Message> {
+        let now = Instant::now();
+        let gap = self
+            .last_frame
+            .map_or(true, |t| now.duration_since(t) > PTS_MAX_GAP);
+        self.last_frame = Some(now);
+        if !gap
+            && self
+                .pts_instant
+                .map_or(false, |t| t.elapsed() < PTS_INTERVAL)
+        {
+            return None;
+        }
+        self.pts_instant = Some(now);
+        // The frame is encoded when its last sample is captured.
+        let pts = video_service::media_time_ms() - frame_ms as i64;
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: crate::client::AUDIO_PTS_PLUGIN_ID.to_owned(),
+            content: pts.to_string().into_bytes().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        Some(msg)
+    }
+
+    fn tune(&mut self) {
+        if let Some(instant) = self.tune_instant {
+            if instant.elapsed() < TUNE_INTERVAL {
+                return;
+            }
+        }
+        self.tune_instant = Some(Instant::now());
+        let quality = video_service::VIDEO_QOS.lock().unwrap().audio_quality();
+        if self.quality == Some(quality) {
+            return;
+        }
+        allow_err!(self
+            .encoder
+            .set_bitrate(Bitrate::Bits(quality.kbps as i32 * 1000)));
+        allow_err!(self.encoder.set_inband_fec(quality.fec));
+        allow_err!(self
+            .encoder
+            .set_packet_loss_perc(quality.loss_percent as i32));
+        log::debug!("audio quality: {:?}", quality);
+        self.quality = Some(quality);
+    }
+
+    fn encode(&mut self, data: &[f32]) -> Option<Vec<u8>> {
+        self.tune();
+        let frames = self.quality.map(|q| q.frame_ms / 10).unwrap_or(1).max(1) as usize;
+        if frames == 1 && self.pending.is_empty() {
+            return self.encoder.encode_vec_float(data, data.len() * 6).ok();
+        }
+        self.pending.extend_from_slice(data);
+        if self.pending.len() < data.len() * frames {
+            return None;
+        }
+        let res = self
+            .encoder
+            .encode_vec_float(&self.pending, self.pending.len() * 6)
+            .ok();
+        self.pending.clear();
+        res
+    }
+}
+
 pub fn restart() {
     log::info!("restart the audio service, freezing now...");
     if RESTARTING.load(Ordering::SeqCst) {
@@ -100,7 +242,7 @@ mod pa_impl {
         unsafe {
             AUDIO_ZERO_COUNT = 0;
         }
-        let mut encoder = Encoder::new(crate::platform::PA_SAMPLE_RATE, Stereo, LowDelay)?;
+        let mut encoder = AudioEncoder::new(crate::platform::PA_SAMPLE_RATE, Stereo)?;
         #[cfg(target_os = "linux")]
         allow_err!(
             stream
@@ -240,7 +382,7 @@ mod cpal_impl {
         sample_rate: u32,
         device_channel: u16,
         encode_channel: u16,
-        encoder: &mut Encoder,
+        encoder: &mut AudioEncoder,
         sp: &GenericService,
     ) {
         let mut data = data;
@@ -307,6 +449,28 @@ mod cpal_impl {
         get_audio_input(&audio_input)
     }
 
+    pub fn get_devices() -> Vec<String> {
+        let mut out = vec![];
+        if let Ok(devices) = HOST.devices() {
+            for device in devices {
+                let capturable = device.default_input_config().is_ok()
+                    || (cfg!(windows) && device.default_output_config().is_ok());
+                if !capturable {
+                    continue;
+                }
+                if let Ok(name) = device.name() {
+                    if !out.contains(&name) {
+                        out.push(name);
+                    }
+                }
+            }
+        }
+        // The applications playing audio, captured one by one.
+        #[cfg(windows)]
+        out.extend(crate::platform::win_audio::get_audio_apps());
+        out
+    }
+
     fn get_audio_input(audio_input: &str) -> ResultType<(Device, SupportedStreamConfig)> {
         let mut device = None;
         #[cfg(feature = "screencapturekit")]
@@ -338,8 +502,11 @@ mod cpal_impl {
                 .with_context(|| "Failed to get default input device for loopback")?,
         );
         log::info!("Input device: {}", device.name().unwrap_or("".to_owned()));
-        let format = device
-            .default_input_config()
+        let format = device.default_input_config();
+        // An output device, captured by loopback.
+        #[cfg(windows)]
+        let format = format.or_else(|_| device.default_output_config());
+        let format = format
             .map_err(|e| anyhow!(e))
             .with_context(|| "Failed to get default input format")?;
         log::info!("Default input format: {:?}", format);
@@ -348,6 +515,12 @@ mod cpal_impl {
 
     fn play(sp: &GenericService) -> ResultType<(Box<dyn StreamTrait>, Arc<Message>)> {
         use cpal::SampleFormat::*;
+        #[cfg(windows)]
+        if let Some(app) =
+            super::get_audio_input().strip_prefix(crate::platform::win_audio::AUDIO_INPUT_PREFIX)
+        {
+            return play_app(app, sp);
+        }
         let (device, config) = get_device()?;
         let sp = sp.clone();
         // Sample rate must be one of 8000, 12000, 16000, 24000, or 48000.
@@ -384,6 +557,35 @@ mod cpal_impl {
         ))
     }
 
+    // Captures the audio of one application only, instead of a device.
+    #[cfg(windows)]
+    fn play_app(
+        app: &str,
+        sp: &GenericService,
+    ) -> ResultType<(Box<dyn StreamTrait>, Arc<Message>)> {
+        use crate::platform::win_audio;
+        let pid = win_audio::find_process(app)
+            .with_context(|| format!("Failed to find the process of {}", app))?;
+        let sp = sp.clone();
+        let mut encoder = AudioEncoder::new(win_audio::SAMPLE_RATE, Stereo)?;
+        let frame_len = win_audio::SAMPLE_RATE as usize / 100 * win_audio::CHANNELS as usize; // 10 ms
+        let mut buffer = Vec::new();
+        let stream = win_audio::ProcessLoopback::start(pid, move |data| {
+            buffer.extend_from_slice(data);
+            while buffer.len() >= frame_len {
+                let frame: Vec<f32> = buffer.drain(0..frame_len).collect();
+                send_f32(&frame, &mut encoder, &sp);
+            }
+        })?;
+        Ok((
+            Box::new(stream),
+            Arc::new(create_format_msg(
+                win_audio::SAMPLE_RATE,
+                win_audio::CHANNELS,
+            )),
+        ))
+    }
+
     fn build_input_stream<T>(
         device: cpal::Device,
         config: &cpal::SupportedStreamConfig,
@@ -404,7 +606,7 @@ mod cpal_impl {
             AUDIO_ZERO_COUNT = 0;
         }
         let device_channel = config.channels();
-        let mut encoder = Encoder::new(sample_rate, encode_channel, LowDelay)?;
+        let mut encoder = AudioEncoder::new(sample_rate, encode_channel)?;
         // https://www.opus-codec.org/docs/html_api/group__opusencoder.html#gace941e4ef26ed844879fde342ffbe546
         // https://chromium.googlesource.com/chromium/deps/opus/+/1.1.1/include/opus.h
         // Do not set `frame_size = sample_rate as usize / 100;`
@@ -465,7 +667,7 @@ fn create_format_msg(sample_rate: u32, channels: u16) -> Message {
 const MAX_AUDIO_ZERO_COUNT: u16 = 800;
 static mut AUDIO_ZERO_COUNT: u16 = 0;
 
-fn send_f32(data: &[f32], encoder: &mut Encoder, sp: &GenericService) {
+fn send_f32(data: &[f32], encoder: &mut AudioEncoder, sp: &GenericService) {
     if data.iter().filter(|x| **x != 0.).next().is_some() {
         unsafe {
             AUDIO_ZERO_COUNT = 0;
@@ -488,14 +690,20 @@ fn send_f32(data: &[f32], encoder: &mut Encoder, sp: &GenericService) {
         // if data size is bigger than BATCH_SIZE, AND is an integer multiple of BATCH_SIZE
         // then upload in batches
         const BATCH_SIZE: usize = 960;
+        // The frame size is fixed by the batch size, only the bitrate and FEC are tuned.
+        encoder.tune();
         let input_size = data.len();
         if input_size > BATCH_SIZE && input_size % BATCH_SIZE == 0 {
             let n = input_size / BATCH_SIZE;
             for i in 0..n {
                 match encoder
+                    .encoder
                     .encode_vec_float(&data[i * BATCH_SIZE..(i + 1) * BATCH_SIZE], BATCH_SIZE)
                 {
                     Ok(data) => {
+                        if let Some(msg) = encoder.pts_msg(10) {
+                            sp.send(msg);
+                        }
                         let mut msg_out = Message::new();
                         msg_out.set_audio_frame(AudioFrame {
                             data: data.into(),
@@ -513,15 +721,16 @@ fn send_f32(data: &[f32], encoder: &mut Encoder, sp: &GenericService) {
     }
 
     #[cfg(not(target_os = "android"))]
-    match encoder.encode_vec_float(data, data.len() * 6) {
-        Ok(data) => {
-            let mut msg_out = Message::new();
-            msg_out.set_audio_frame(AudioFrame {
-                data: data.into(),
-                ..Default::default()
-            });
-            sp.send(msg_out);
+    if let Some(data) = encoder.encode(data) {
+        let frame_ms = encoder.quality.map(|q| q.frame_ms).unwrap_or(10);
+        if let Some(msg) = encoder.pts_msg(frame_ms) {
+            sp.send(msg);
         }
-        Err(_) => {}
+        let mut msg_out = Message::new();
+        msg_out.set_audio_frame(AudioFrame {
+            data: data.into(),
+            ..Default::default()
+        });
+        sp.send(msg_out);
     }
 }
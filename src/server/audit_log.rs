@@ -0,0 +1,200 @@
+//! Local audit log of the incoming sessions.
+//!
+//! If allowed, every event is appended as one JSON line to `audit.jsonl` in the log directory,
+//! which is rotated by size. If pushing is allowed, the conn, file and alarm audits of the
+//! connections are sent to their endpoints of the API server in order from here, and kept for
+//! a retry with backoff if that fails, instead of being posted once.
+
+use hbb_common::{
+    config::{self, Config},
+    get_time, log,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+pub const OPTION_ALLOW_AUDIT_LOG: &str = "allow-audit-log";
+pub const OPTION_ALLOW_PUSH_AUDIT_LOG: &str = "allow-push-audit-log";
+const FILE_STEM: &str = "audit";
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const MAX_FILES: usize = 5;
+const MAX_PENDING: usize = 10_000;
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(600);
+
+lazy_static::lazy_static! {
+    static ref SENDER: Mutex<Option<mpsc::Sender<Item>>> = Default::default();
+}
+
+enum Item {
+    Record(Value),
+    // The url of the audit endpoint and the body.
+    Push(String, Value),
+}
+
+fn is_enabled() -> bool {
+    config::option2bool(
+        OPTION_ALLOW_AUDIT_LOG,
+        &Config::get_option(OPTION_ALLOW_AUDIT_LOG),
+    )
+}
+
+pub fn is_push_allowed() -> bool {
+    config::option2bool(
+        OPTION_ALLOW_PUSH_AUDIT_LOG,
+        &Config::get_option(OPTION_ALLOW_PUSH_AUDIT_LOG),
+    )
+}
+
+pub fn dir() -> PathBuf {
+    Config::log_path().join(FILE_STEM)
+}
+
+fn file_path(dir: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        dir.join(format!("{}.jsonl", FILE_STEM))
+    } else {
+        dir.join(format!("{}.{}.jsonl", FILE_STEM, index))
+    }
+}
+
+// audit.jsonl -> audit.1.jsonl -> ... -> audit.{MAX_FILES - 1}.jsonl, the oldest is dropped.
+fn rotate(dir: &Path) {
+    fs::remove_file(file_path(dir, MAX_FILES - 1)).ok();
+    for i in (0..MAX_FILES - 1).rev() {
+        let from = file_path(dir, i);
+        if from.exists() {
+            if let Err(err) = fs::rename(&from, file_path(dir, i + 1)) {
+                log::error!("Failed to rotate {:?}: {}", from, err);
+            }
+        }
+    }
+}
+
+fn append(dir: &Path, line: &str) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = file_path(dir, 0);
+    if fs::metadata(&path).map_or(false, |m| m.len() >= MAX_FILE_SIZE) {
+        rotate(dir);
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    file.write_all(b"\n")
+}
+
+/// Record an event of a connection. `info` is an object with the event specific fields.
+pub fn record(event: &str, conn_id: i32, peer_id: &str, ip: &str, info: Value) {
+    if !is_enabled() {
+        return;
+    }
+    let mut v = json!({
+        "time": get_time(),
+        "event": event,
+        "conn_id": conn_id,
+        "peer_id": peer_id,
+        "ip": ip,
+    });
+    if let (Some(v), Value::Object(info)) = (v.as_object_mut(), info) {
+        v.extend(info);
+    }
+    send(Item::Record(v));
+}
+
+/// Post `v` to the audit endpoint `url`, e.g. of `get_audit_server(.., "conn")`, after the
+/// ones pushed before, retried until it succeeds. For the callers if `is_push_allowed`.
+pub fn push(url: String, v: Value) {
+    send(Item::Push(url, v));
+}
+
+fn send(item: Item) {
+    let mut lock = SENDER.lock().unwrap();
+    let item = match lock.as_ref() {
+        Some(tx) => match tx.send(item) {
+            Ok(_) => return,
+            Err(mpsc::SendError(item)) => item,
+        },
+        None => item,
+    };
+    let (tx, rx) = mpsc::channel();
+    tx.send(item).ok();
+    *lock = Some(tx);
+    std::thread::spawn(move || run(rx));
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn run(rx: mpsc::Receiver<Item>) {
+    let dir = dir();
+    let mut pending: VecDeque<(String, Value)> = VecDeque::new();
+    let mut retry_interval = RETRY_INTERVAL;
+    let mut retry_at = Instant::now();
+    loop {
+        match rx.recv_timeout(RETRY_INTERVAL) {
+            Ok(Item::Record(v)) => {
+                if let Err(err) = append(&dir, &v.to_string()) {
+                    log::error!("Failed to write audit log: {}", err);
+                }
+            }
+            Ok(Item::Push(url, v)) => {
+                pending.push_back((url, v));
+                if pending.len() > MAX_PENDING {
+                    pending.pop_front();
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if Instant::now() < retry_at {
+            continue;
+        }
+        while let Some((url, v)) = pending.front() {
+            match crate::post_request(url.clone(), v.to_string(), "").await {
+                Ok(_) => {
+                    pending.pop_front();
+                    retry_interval = RETRY_INTERVAL;
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Failed to push audit to {}, retry in {:?}: {}",
+                        url,
+                        retry_interval,
+                        err
+                    );
+                    retry_at = Instant::now() + retry_interval;
+                    retry_interval = (retry_interval * 2).min(MAX_RETRY_INTERVAL);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("rustdesk-audit-test-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        for i in 0..MAX_FILES + 1 {
+            append(&dir, &i.to_string()).unwrap();
+            rotate(&dir);
+        }
+        assert!(!file_path(&dir, 0).exists());
+        assert!(!file_path(&dir, MAX_FILES).exists());
+        assert_eq!(
+            fs::read_to_string(file_path(&dir, 1)).unwrap(),
+            format!("{}\n", MAX_FILES)
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+}
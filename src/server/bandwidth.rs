@@ -0,0 +1,269 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/*
+BBR-like bandwidth estimation of the video send path, one estimator per connection.
+
+delivery rate:
+    Bytes written to the stream in a sample interval divided by its duration.
+    When the writes were blocked most of the time the socket is the bottleneck and the
+    sample is the bottleneck bandwidth, otherwise the encoder is the bottleneck
+    (application limited) and the sample is only used if it raises the estimate.
+
+BtlBw: windowed max of the delivery rate samples over the last BW_WINDOW.
+RTprop: windowed min of the TestDelay RTT over the last RTT_WINDOW.
+
+state:
+    Startup => target 2 * BtlBw until BtlBw stops growing by 25% for 3 rounds
+    Drain => target 0.75 * BtlBw for one round, to drain the queue built in Startup
+    ProbeBW => cycle the gains [1.25, 0.75, 1, 1, 1, 1, 1, 1], one gain per round
+
+delay gradient:
+    If the latest RTT exceeds RTprop by far, a queue is building up,
+    so use the drain gain in the current round whatever the cycle says.
+*/
+
+const BW_WINDOW: Duration = Duration::from_secs(10);
+const RTT_WINDOW: Duration = Duration::from_secs(10);
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+const MIN_ROUND: Duration = Duration::from_millis(200);
+const BLOCKED_PERCENT: u32 = 50;
+const STARTUP_GAIN: f32 = 2.0;
+const DRAIN_GAIN: f32 = 0.75;
+const PROBE_BW_GAINS: [f32; 8] = [1.25, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+const FULL_BW_GROWTH: f32 = 1.25;
+const FULL_BW_ROUNDS: u32 = 3;
+// Keep some room for audio, cursor and other messages.
+const HEADROOM: f32 = 0.9;
+const QUEUE_DELAY_MS: u32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Startup,
+    Drain,
+    ProbeBw(usize),
+}
+
+#[derive(Debug, Clone)]
+struct WindowedFilter {
+    window: Duration,
+    samples: VecDeque<(Instant, f32)>,
+    is_max: bool,
+}
+
+impl WindowedFilter {
+    fn new(window: Duration, is_max: bool) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+            is_max,
+        }
+    }
+
+    fn update(&mut self, now: Instant, v: f32) {
+        while let Some((tm, _)) = self.samples.front() {
+            if now.duration_since(*tm) > self.window {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+        // Samples which can never be the result any more are dropped.
+        let is_max = self.is_max;
+        while let Some((_, last)) = self.samples.back() {
+            if (is_max && *last <= v) || (!is_max && *last >= v) {
+                self.samples.pop_back();
+            } else {
+                break;
+            }
+        }
+        self.samples.push_back((now, v));
+    }
+
+    fn get(&self) -> Option<f32> {
+        self.samples.front().map(|(_, v)| *v)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BandwidthEstimator {
+    state: State,
+    btl_bw: WindowedFilter,  // kbps
+    rt_prop: WindowedFilter, // ms
+    latest_rtt: Option<u32>,
+    sample_start: Instant,
+    sample_bytes: usize,
+    sample_blocked: Duration,
+    round_start: Instant,
+    full_bw: f32,
+    full_bw_rounds: u32,
+}
+
+impl Default for BandwidthEstimator {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+impl BandwidthEstimator {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            state: State::Startup,
+            btl_bw: WindowedFilter::new(BW_WINDOW, true),
+            rt_prop: WindowedFilter::new(RTT_WINDOW, false),
+            latest_rtt: None,
+            sample_start: now,
+            sample_bytes: 0,
+            sample_blocked: Duration::ZERO,
+            round_start: now,
+            full_bw: 0.0,
+            full_bw_rounds: 0,
+        }
+    }
+
+    // A video frame of `bytes` has been written, and the write took `send_time`.
+    pub fn on_sent(&mut self, now: Instant, bytes: usize, send_time: Duration) {
+        self.sample_bytes += bytes;
+        self.sample_blocked += send_time;
+        let elapsed = now.duration_since(self.sample_start);
+        if elapsed >= SAMPLE_INTERVAL {
+            let kbps = (self.sample_bytes * 8) as f32 / elapsed.as_millis() as f32;
+            let blocked = self.sample_blocked.as_millis() as u32 * 100
+                >= elapsed.as_millis() as u32 * BLOCKED_PERCENT;
+            if blocked || kbps > self.btl_bw.get().unwrap_or(0.0) {
+                self.btl_bw.update(now, kbps);
+            }
+            self.sample_start = now;
+            self.sample_bytes = 0;
+            self.sample_blocked = Duration::ZERO;
+        }
+        if now.duration_since(self.round_start) >= self.round() {
+            self.round_start = now;
+            self.on_round();
+        }
+    }
+
+    pub fn on_rtt(&mut self, now: Instant, rtt: u32) {
+        self.latest_rtt = Some(rtt);
+        self.rt_prop.update(now, rtt as _);
+    }
+
+    fn round(&self) -> Duration {
+        self.rt_prop
+            .get()
+            .map(|rtt| Duration::from_millis(rtt as _))
+            .unwrap_or(MIN_ROUND)
+            .max(MIN_ROUND)
+    }
+
+    fn on_round(&mut self) {
+        let bw = self.btl_bw.get().unwrap_or(0.0);
+        self.state = match self.state {
+            State::Startup => {
+                if bw >= self.full_bw * FULL_BW_GROWTH {
+                    self.full_bw = bw;
+                    self.full_bw_rounds = 0;
+                    State::Startup
+                } else {
+                    self.full_bw_rounds += 1;
+                    if self.full_bw_rounds >= FULL_BW_ROUNDS {
+                        State::Drain
+                    } else {
+                        State::Startup
+                    }
+                }
+            }
+            State::Drain => State::ProbeBw(0),
+            State::ProbeBw(i) => State::ProbeBw((i + 1) % PROBE_BW_GAINS.len()),
+        };
+    }
+
+    fn is_queue_building(&self) -> bool {
+        match (self.latest_rtt, self.rt_prop.get()) {
+            (Some(rtt), Some(min)) => rtt > (min as u32) * 3 / 2 + QUEUE_DELAY_MS,
+            _ => false,
+        }
+    }
+
+    fn pacing_gain(&self) -> f32 {
+        if self.is_queue_building() {
+            return DRAIN_GAIN;
+        }
+        match self.state {
+            State::Startup => STARTUP_GAIN,
+            State::Drain => DRAIN_GAIN,
+            State::ProbeBw(i) => PROBE_BW_GAINS[i],
+        }
+    }
+
+    /// Estimated bottleneck bandwidth in kbps.
+    pub fn bandwidth(&self) -> Option<u32> {
+        self.btl_bw.get().map(|v| v as u32)
+    }
+
+    pub fn stats(&self) -> crate::client::BweStats {
+        crate::client::BweStats {
+            bandwidth: self.bandwidth(),
+            state: match self.state {
+                State::Startup => "Startup",
+                State::Drain => "Drain",
+                State::ProbeBw(_) => "ProbeBW",
+            }
+            .to_owned(),
+        }
+    }
+
+    /// Bitrate the encoder should target in kbps, `None` before the first sample.
+    pub fn target_bitrate(&self) -> Option<u32> {
+        let bw = self.btl_bw.get()?;
+        Some((bw * self.pacing_gain() * HEADROOM) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feed `kbps` for `ms` milliseconds in frames of 10ms.
+    fn feed(bwe: &mut BandwidthEstimator, now: &mut Instant, kbps: usize, ms: u64, blocked: bool) {
+        for _ in 0..ms / 10 {
+            *now += Duration::from_millis(10);
+            let send_time = Duration::from_millis(if blocked { 8 } else { 1 });
+            bwe.on_sent(*now, kbps * 10 / 8, send_time);
+        }
+    }
+
+    #[test]
+    fn test_startup_exits_when_bandwidth_stops_growing() {
+        let mut now = Instant::now();
+        let mut bwe = BandwidthEstimator::new(now);
+        assert_eq!(bwe.target_bitrate(), None);
+        feed(&mut bwe, &mut now, 1000, 900, true);
+        assert_eq!(bwe.state, State::Drain);
+        assert_eq!(bwe.bandwidth(), Some(1000));
+        feed(&mut bwe, &mut now, 1000, 200, true);
+        assert!(matches!(bwe.state, State::ProbeBw(_)));
+    }
+
+    #[test]
+    fn test_app_limited_samples_do_not_lower_estimate() {
+        let mut now = Instant::now();
+        let mut bwe = BandwidthEstimator::new(now);
+        feed(&mut bwe, &mut now, 2000, 400, true);
+        feed(&mut bwe, &mut now, 200, 2000, false);
+        assert_eq!(bwe.bandwidth(), Some(2000));
+    }
+
+    #[test]
+    fn test_queue_building_drains() {
+        let mut now = Instant::now();
+        let mut bwe = BandwidthEstimator::new(now);
+        feed(&mut bwe, &mut now, 1000, 400, true);
+        bwe.on_rtt(now, 40);
+        let normal = bwe.target_bitrate().unwrap();
+        bwe.on_rtt(now, 200);
+        assert!(bwe.target_bitrate().unwrap() < normal);
+    }
+}
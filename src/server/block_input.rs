@@ -0,0 +1,187 @@
+//! Blocks the local input for the controlling sides.
+//!
+//! One thread owns the block of the platform, Windows only lets the thread that blocked
+//! unblock, and it applies the block again every `INTERVAL`, e.g. after the desktop
+//! switch on Windows or for the devices plugged in later on Linux.
+//!
+//! The block of a connection closed without unblocking, e.g. a dropped network, lingers
+//! for `LINGER`, so the local user can not take over before the peer reconnects and
+//! takes the block back with [`resume`]. Unblocking, revoking the permission or closing
+//! the connection on this side ends the block at once with [`release`].
+
+use hbb_common::log;
+use std::{
+    collections::HashMap,
+    sync::{mpsc as std_mpsc, Mutex},
+    time::{Duration, Instant},
+};
+
+const INTERVAL: Duration = Duration::from_millis(500);
+const LINGER: Duration = Duration::from_secs(60);
+
+type Reply = std_mpsc::Sender<(bool, String)>;
+
+#[derive(Default)]
+struct State {
+    // Connection id to peer id, the connections blocking the input.
+    conns: HashMap<i32, String>,
+    // Peer id to the end of the block of a closed connection.
+    lingering: HashMap<String, Instant>,
+    tx: Option<std_mpsc::Sender<Reply>>,
+}
+
+impl State {
+    fn should_block(&mut self, now: Instant) -> bool {
+        self.lingering.retain(|_, end| *end > now);
+        !self.conns.is_empty() || !self.lingering.is_empty()
+    }
+
+    fn close(&mut self, conn_id: i32, now: Instant) {
+        if let Some(peer_id) = self.conns.remove(&conn_id) {
+            self.lingering.insert(peer_id, now + LINGER);
+        }
+    }
+
+    fn resume(&mut self, conn_id: i32, peer_id: &str) -> bool {
+        if self.lingering.remove(peer_id).is_some() {
+            self.conns.insert(conn_id, peer_id.to_owned());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn release(&mut self, conn_id: i32) {
+        if let Some(peer_id) = self.conns.remove(&conn_id) {
+            self.lingering.remove(&peer_id);
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref STATE: Mutex<State> = Default::default();
+}
+
+/// Blocks or unblocks the input for the connection, returns the result of the platform.
+pub fn set(conn_id: i32, peer_id: &str, on: bool) -> (bool, String) {
+    {
+        let mut state = STATE.lock().unwrap();
+        if on {
+            state.conns.insert(conn_id, peer_id.to_owned());
+        } else {
+            state.release(conn_id);
+        }
+    }
+    update()
+}
+
+/// Takes over the lingering block of the peer, returns whether there was one.
+pub fn resume(conn_id: i32, peer_id: &str) -> bool {
+    let resumed = STATE.lock().unwrap().resume(conn_id, peer_id);
+    if resumed {
+        log::info!("Resumed the input block of {}", peer_id);
+    }
+    resumed
+}
+
+/// The connection is closed by the peer or the network, the block lingers.
+pub fn close(conn_id: i32) {
+    STATE.lock().unwrap().close(conn_id, Instant::now());
+}
+
+/// Ends the block of the connection at once.
+pub fn release(conn_id: i32) {
+    STATE.lock().unwrap().release(conn_id);
+    update();
+}
+
+fn update() -> (bool, String) {
+    let (tx_reply, rx_reply) = std_mpsc::channel();
+    {
+        let mut state = STATE.lock().unwrap();
+        let sent = state
+            .tx
+            .as_ref()
+            .map(|tx| tx.send(tx_reply.clone()).is_ok());
+        if sent != Some(true) {
+            let (tx, rx) = std_mpsc::channel();
+            tx.send(tx_reply).ok();
+            state.tx = Some(tx);
+            std::thread::spawn(move || run(rx));
+        }
+    }
+    // No reply if the thread exits, it exits only if the input is not blocked.
+    rx_reply.recv().unwrap_or((true, "".to_owned()))
+}
+
+fn run(rx: std_mpsc::Receiver<Reply>) {
+    let mut blocked = false;
+    loop {
+        let reply = match rx.recv_timeout(INTERVAL) {
+            Ok(reply) => Some(reply),
+            Err(std_mpsc::RecvTimeoutError::Timeout) => None,
+            Err(std_mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        let on = STATE.lock().unwrap().should_block(Instant::now());
+        let res = if on || blocked {
+            let res = crate::platform::block_input(on);
+            if res.0 {
+                if on != blocked {
+                    log::info!("Input {}", if on { "blocked" } else { "unblocked" });
+                }
+                blocked = on;
+            }
+            res
+        } else {
+            (true, "".to_owned())
+        };
+        if let Some(reply) = reply {
+            reply.send(res).ok();
+        }
+        if !blocked {
+            let mut state = STATE.lock().unwrap();
+            if !state.should_block(Instant::now()) {
+                state.tx = None;
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linger() {
+        let now = Instant::now();
+        let mut state = State::default();
+        state.conns.insert(1, "a".to_owned());
+        assert!(state.should_block(now));
+        state.close(1, now);
+        assert!(state.should_block(now));
+        assert!(!state.should_block(now + LINGER));
+
+        state.conns.insert(1, "a".to_owned());
+        state.close(1, now);
+        assert!(!state.resume(2, "b"));
+        assert!(state.resume(2, "a"));
+        assert!(state.lingering.is_empty());
+        state.release(2);
+        assert!(!state.should_block(now));
+    }
+
+    #[test]
+    fn test_release() {
+        let now = Instant::now();
+        let mut state = State::default();
+        state.conns.insert(1, "a".to_owned());
+        state.conns.insert(2, "b".to_owned());
+        state.release(1);
+        assert!(state.should_block(now));
+        state.release(2);
+        // Closing after releasing does not linger.
+        state.close(2, now);
+        assert!(!state.should_block(now));
+    }
+}
@@ -165,6 +165,10 @@ impl Handler {
                                 .collect(),
                             ..Default::default()
                         };
+                        let multi_clipboards = crate::clipboard_policy::outgoing(
+                            multi_clipboards,
+                            ClipboardSide::Host,
+                        )?;
                         msg.set_multi_clipboards(multi_clipboards);
                         return Some(msg);
                     }
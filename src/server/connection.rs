@@ -2,7 +2,7 @@ use super::{input_service::*, *};
 #[cfg(feature = "unix-file-copy-paste")]
 use crate::clipboard::try_empty_clipboard_files;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-use crate::clipboard::{update_clipboard, ClipboardSide};
+use crate::clipboard::{update_clipboard_of_peer, ClipboardSide};
 #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
 use crate::clipboard_file::*;
 #[cfg(target_os = "android")]
@@ -50,7 +50,7 @@ use serde_json::{json, value::Value};
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 use std::sync::atomic::Ordering;
 use std::{
-    net::Ipv6Addr,
+    net::{IpAddr, Ipv6Addr},
     num::NonZeroI64,
     path::PathBuf,
     str::FromStr,
@@ -144,8 +144,10 @@ enum MessageInput {
     Key((KeyEvent, bool)),
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     Pointer((PointerDeviceEvent, i32)),
-    BlockOn,
-    BlockOff,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Pen(crate::pen::PenEvent),
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    Touch(crate::touch::TouchFrame),
     #[cfg(all(feature = "flutter", feature = "plugin_framework"))]
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     BlockOnPlugin(String),
@@ -183,6 +185,7 @@ pub enum AuthConnType {
     PortForward,
     ViewCamera,
     Terminal,
+    AudioOnly,
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -212,6 +215,7 @@ pub struct Connection {
     read_jobs: Vec<fs::TransferJob>,
     timer: crate::RustDeskInterval,
     file_timer: crate::RustDeskInterval,
+    throttle: crate::transfer_throttle::Throttle,
     file_transfer: Option<(String, bool)>,
     view_camera: bool,
     terminal: bool,
@@ -220,6 +224,16 @@ pub struct Connection {
     tx_to_cm: mpsc::UnboundedSender<ipc::Data>,
     authorized: bool,
     require_2fa: Option<totp_rs::TOTP>,
+    require_security_key: bool,
+    security_key_challenge: Option<Vec<u8>>,
+    sas: String,
+    // How the peer got authorized, for the audit log.
+    auth_methods: Vec<&'static str>,
+    input_log: Option<record_signing::InputLog>,
+    // The permission profile of the one-time password used to log in.
+    one_time_profile: Option<peer_profile::PeerProfile>,
+    // The one-time password to consume once all the factors pass.
+    one_time_password_id: Option<String>,
     keyboard: bool,
     clipboard: bool,
     audio: bool,
@@ -258,11 +272,24 @@ pub struct Connection {
     session_last_recv_time: Option<Arc<Mutex<Instant>>>,
     chat_unanswered: bool,
     file_transferred: bool,
+    // The uploads of the files dropped onto a remote desktop session are accepted.
+    file_drop: bool,
+    file_stream: crate::file_stream::FileStream,
+    file_compressor: crate::file_compression::Compressor,
+    transfer_broker: crate::transfer_broker::Jobs,
+    // The transfer jobs whose metadata may be read or applied, by the id.
+    metadata_jobs: HashMap<i32, crate::transfer_metadata::PeerJob>,
     #[cfg(windows)]
     portable: PortableState,
     from_switch: bool,
     voice_call_request_timestamp: Option<NonZeroI64>,
     voice_calling: bool,
+    mic_passthrough: bool,
+    // Requested before the login, the session has the audio service only.
+    audio_only: bool,
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    gamepads: Option<crate::gamepad::VirtualGamepads>,
+    audio_device_selected: bool,
     options_in_login: Option<OptionMessage>,
     #[cfg(not(any(target_os = "ios")))]
     pressed_modifiers: HashSet<rdev::Key>,
@@ -272,6 +299,11 @@ pub struct Connection {
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     start_cm_ipc_para: Option<StartCmIpcPara>,
     auto_disconnect_timer: Option<(Instant, u64)>,
+    auto_disconnect_warned: bool,
+    max_duration_timer: Option<(Instant, u64)>,
+    max_duration_warned: bool,
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    last_power_state: super::power_state::PowerState,
     authed_conn_id: Option<self::raii::AuthedConnID>,
     file_remove_log_control: FileRemoveLogControl,
     last_supported_encoding: Option<SupportedEncoding>,
@@ -295,6 +327,9 @@ pub struct Connection {
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     terminal_user_token: Option<TerminalUserToken>,
     terminal_generic_service: Option<Box<GenericService>>,
+    tunnel: Option<crate::tunnel::Tunnel>,
+    // The peer shows the stats of the bandwidth estimation.
+    bwe_stats: bool,
 }
 
 impl ConnInner {
@@ -345,6 +380,7 @@ impl Connection {
         stream: super::Stream,
         id: i32,
         server: super::ServerPtrWeak,
+        sas: Option<String>,
     ) {
         let _raii_id = raii::ConnectionID::new(id);
         let hash = Hash {
@@ -374,8 +410,6 @@ impl Connection {
             Self::post_seq_loop(rx_post_seq).await;
         });
 
-        #[cfg(not(any(target_os = "android", target_os = "ios")))]
-        let tx_cloned = tx.clone();
         let mut conn = Self {
             inner: ConnInner {
                 id,
@@ -383,6 +417,13 @@ impl Connection {
                 tx_video: Some(tx_video),
             },
             require_2fa: crate::auth_2fa::get_2fa(None),
+            require_security_key: crate::auth_security_key::is_required(),
+            security_key_challenge: None,
+            sas: sas.unwrap_or_default(),
+            auth_methods: Vec::new(),
+            input_log: None,
+            one_time_profile: None,
+            one_time_password_id: None,
             display_idx: *display_service::PRIMARY_DISPLAY_IDX,
             stream,
             server,
@@ -390,6 +431,7 @@ impl Connection {
             read_jobs: Vec::new(),
             timer: crate::rustdesk_interval(time::interval(SEC30)),
             file_timer: crate::rustdesk_interval(time::interval(SEC30)),
+            throttle: Default::default(),
             file_transfer: None,
             view_camera: false,
             terminal: false,
@@ -429,12 +471,22 @@ impl Connection {
             session_last_recv_time: None,
             chat_unanswered: false,
             file_transferred: false,
+            file_drop: false,
+            file_stream: Default::default(),
+            file_compressor: Default::default(),
+            transfer_broker: Default::default(),
+            metadata_jobs: Default::default(),
             #[cfg(windows)]
             portable: Default::default(),
             from_switch: false,
             audio_sender: None,
             voice_call_request_timestamp: None,
             voice_calling: false,
+            mic_passthrough: false,
+            audio_only: false,
+            #[cfg(any(target_os = "windows", target_os = "linux"))]
+            gamepads: None,
+            audio_device_selected: false,
             options_in_login: None,
             #[cfg(not(any(target_os = "ios")))]
             pressed_modifiers: Default::default(),
@@ -449,6 +501,11 @@ impl Connection {
                 tx_cm_stream_ready,
             }),
             auto_disconnect_timer: None,
+            auto_disconnect_warned: false,
+            max_duration_timer: None,
+            max_duration_warned: false,
+            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+            last_power_state: Default::default(),
             authed_conn_id: None,
             file_remove_log_control: FileRemoveLogControl::new(id),
             last_supported_encoding: None,
@@ -464,6 +521,8 @@ impl Connection {
             #[cfg(not(any(target_os = "android", target_os = "ios")))]
             terminal_user_token: None,
             terminal_generic_service: None,
+            tunnel: None,
+            bwe_stats: false,
         };
         let addr = hbb_common::try_into_v4(addr);
         if !conn.on_open(addr).await {
@@ -512,7 +571,7 @@ impl Connection {
         );
 
         #[cfg(not(any(target_os = "android", target_os = "ios")))]
-        std::thread::spawn(move || Self::handle_input(_rx_input, tx_cloned));
+        std::thread::spawn(move || Self::handle_input(_rx_input, id));
         let mut second_timer = crate::rustdesk_interval(time::interval(Duration::from_secs(1)));
 
         #[cfg(feature = "unix-file-copy-paste")]
@@ -544,7 +603,9 @@ impl Connection {
                 Some(data) = rx_from_cm.recv() => {
                     match data {
                         ipc::Data::Authorize => {
+                            conn.auth_methods.push("click");
                             conn.require_2fa.take();
+                            conn.require_security_key = false;
                             conn.send_logon_response().await;
                             if conn.port_forward_socket.is_some() {
                                 break;
@@ -577,8 +638,17 @@ impl Connection {
                         }
                         ipc::Data::SwitchPermission{name, enabled} => {
                             log::info!("Change permission {} -> {}", name, enabled);
+                            conn.audit("permission", json!({"name": name, "enabled": enabled}));
                             if &name == "keyboard" {
                                 conn.keyboard = enabled;
+                                #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                                if !enabled {
+                                    conn.release_block_input().await;
+                                }
+                                #[cfg(any(target_os = "windows", target_os = "linux"))]
+                                if !enabled {
+                                    conn.gamepads = None;
+                                }
                                 conn.send_permission(Permission::Keyboard, enabled).await;
                                 if let Some(s) = conn.server.upgrade() {
                                     s.write().unwrap().subscribe(
@@ -604,6 +674,7 @@ impl Connection {
                                 }
                             } else if &name == "audio" {
                                 conn.audio = enabled;
+                                conn.mic_passthrough &= enabled;
                                 conn.send_permission(Permission::Audio, enabled).await;
                                 if conn.authorized {
                                     if let Some(s) = conn.server.upgrade() {
@@ -643,6 +714,10 @@ impl Connection {
                                 conn.send_permission(Permission::Recording, enabled).await;
                             } else if &name == "block_input" {
                                 conn.block_input = enabled;
+                                #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                                if !enabled {
+                                    conn.release_block_input().await;
+                                }
                                 conn.send_permission(Permission::BlockInput, enabled).await;
                             }
                         }
@@ -728,6 +803,7 @@ impl Connection {
                                 break;
                             },
                             Ok(bytes) => {
+                                metrics::on_received(bytes.len());
                                 last_recv_time = Instant::now();
                                 conn.session_last_recv_time.as_mut().map(|t| *t.lock().unwrap() = Instant::now());
                                 if let Ok(msg_in) = Message::parse_from_bytes(&bytes) {
@@ -751,8 +827,15 @@ impl Connection {
                 },
                 _ = conn.file_timer.tick() => {
                     if !conn.read_jobs.is_empty() {
+                        if !conn.throttle.ready(Self::throttle_options) {
+                            continue;
+                        }
                         conn.send_to_cm(ipc::Data::FileTransferLog(("transfer".to_string(), fs::serialize_transfer_jobs(&conn.read_jobs))));
-                        match fs::handle_read_jobs(&mut conn.read_jobs, &mut conn.stream).await {
+                        let before = crate::transfer_throttle::finished_size(&conn.read_jobs);
+                        let res = conn.file_compressor.handle_read_jobs(&mut conn.read_jobs, conn.file_stream.writer(&mut conn.stream)).await;
+                        let after = crate::transfer_throttle::finished_size(&conn.read_jobs);
+                        conn.throttle.pay(after.saturating_sub(before));
+                        match res {
                             Ok(log) => {
                                 if !log.is_empty() {
                                     conn.send_to_cm(ipc::Data::FileTransferLog(("transfer".to_string(), log)));
@@ -764,9 +847,32 @@ impl Connection {
                             }
                         }
                     } else {
+                        conn.file_stream.on_idle();
                         conn.file_timer = crate::rustdesk_interval(time::interval_at(Instant::now() + SEC30, SEC30));
                     }
                 }
+                event = conn.file_stream.recv() => {
+                    match event {
+                        crate::file_stream::Event::Attached => {
+                            if conn.read_jobs.is_empty() {
+                                conn.file_stream.on_idle();
+                            }
+                        }
+                        crate::file_stream::Event::Message(bytes) => {
+                            last_recv_time = Instant::now();
+                            if let Ok(msg_in) = Message::parse_from_bytes(&bytes) {
+                                if !conn.on_message(msg_in).await {
+                                    break;
+                                }
+                            }
+                        }
+                        // The blocks sent on it are lost.
+                        crate::file_stream::Event::Closed(err) => {
+                            conn.on_close(&format!("File stream: {}", err), false).await;
+                            break;
+                        }
+                    }
+                }
                 Ok(conns) = hbbs_rx.recv() => {
                     if conns.contains(&id) {
                         conn.send_close_reason_no_retry("Closed manually by web console").await;
@@ -780,10 +886,18 @@ impl Connection {
                             video_service::notify_video_frame_fetched(vf.display as usize, id, Some(instant.into()));
                         }
                     }
+                    let send_instant = Instant::now();
                     if let Err(err) = conn.stream.send(&value as &Message).await {
                         conn.on_close(&err.to_string(), false).await;
                         break;
                     }
+                    let size = value.compute_size() as usize;
+                    metrics::on_sent(size);
+                    video_service::VIDEO_QOS.lock().unwrap().user_video_sent(
+                        id,
+                        size,
+                        send_instant.elapsed(),
+                    );
                 },
                 Some((instant, value)) = rx.recv() => {
                     let latency = instant.elapsed().as_millis() as i64;
@@ -828,6 +942,7 @@ impl Connection {
                             }
                         }
                         Some(message::Union::MultiClipboards(_multi_clipboards)) => {
+                            conn.audit("clipboard", json!({"direction": "send"}));
                             #[cfg(not(target_os = "ios"))]
                             if let Some(msg_out) = crate::clipboard::get_msg_if_not_support_multi_clip(&conn.lr.version, &conn.lr.my_platform, _multi_clipboards) {
                                 if let Err(err) = conn.stream.send(&msg_out).await {
@@ -845,6 +960,7 @@ impl Connection {
                         conn.on_close(&err.to_string(), false).await;
                         break;
                     }
+                    metrics::on_sent(msg.compute_size() as _);
                 },
                 Some(data) = rx_from_authed.recv() => {
                     match data {
@@ -867,11 +983,26 @@ impl Connection {
                             conn.send_close_reason_no_retry("Connection failed due to inactivity").await;
                             conn.on_close("auto disconnect", true).await;
                             break;
+                        } else if !conn.auto_disconnect_warned && Self::should_warn_timeout(instant, *minute) {
+                            conn.auto_disconnect_warned = true;
+                            conn.send_timeout_warning("idle-timeout-warning-tip").await;
+                        }
+                    }
+                    if let Some((instant, minute)) = conn.max_duration_timer.as_ref() {
+                        if instant.elapsed().as_secs() > minute * 60 {
+                            conn.send_close_reason_no_retry("Session duration limit reached").await;
+                            conn.on_close("max session duration", true).await;
+                            break;
+                        } else if !conn.max_duration_warned && Self::should_warn_timeout(instant, *minute) {
+                            conn.max_duration_warned = true;
+                            conn.send_timeout_warning("max-session-duration-warning-tip").await;
                         }
                     }
                     conn.file_remove_log_control.on_timer().drain(..).map(|x| conn.send_to_cm(x)).count();
                     #[cfg(feature = "hwcodec")]
                     conn.update_supported_encoding();
+                    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                    conn.check_power_state().await;
                 }
                 _ = test_delay_timer.tick() => {
                     if last_recv_time.elapsed() >= SEC30 {
@@ -889,6 +1020,20 @@ impl Connection {
                         });
                         conn.send(msg_out.into()).await;
                     }
+                    if conn.bwe_stats {
+                        let stats = video_service::VIDEO_QOS.lock().unwrap().bwe_stats(id);
+                        if let Some(stats) = stats {
+                            let mut misc = Misc::new();
+                            misc.set_plugin_request(PluginRequest {
+                                id: crate::client::BWE_STATS_PLUGIN_ID.to_owned(),
+                                content: serde_json::to_vec(&stats).unwrap_or_default().into(),
+                                ..Default::default()
+                            });
+                            let mut msg_out = Message::new();
+                            msg_out.set_misc(misc);
+                            conn.send(msg_out.into()).await;
+                        }
+                    }
                     if conn.is_authed_remote_conn() || conn.view_camera {
                         if let Some(last_test_delay) = conn.last_test_delay {
                             video_service::VIDEO_QOS.lock().unwrap().user_delay_response_elapsed(id, last_test_delay.elapsed().as_millis());
@@ -938,19 +1083,25 @@ impl Connection {
         conn.post_conn_audit(json!({
             "action": "close",
         }));
+        conn.audit("disconnect", json!({}));
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        if conn.authorized {
+            session_hook::on_ended(conn.session_hook_env(&conn.lr));
+        }
         if let Some(s) = conn.server.upgrade() {
             let mut s = s.write().unwrap();
             s.remove_connection(&conn.inner);
             #[cfg(not(any(target_os = "android", target_os = "ios")))]
             try_stop_record_cursor_pos();
         }
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        super::block_input::close(conn.inner.id());
         conn.on_close("End", true).await;
         log::info!("#{} connection loop exited", id);
     }
 
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
-    fn handle_input(receiver: std_mpsc::Receiver<MessageInput>, tx: Sender) {
-        let mut block_input_mode = false;
+    fn handle_input(receiver: std_mpsc::Receiver<MessageInput>, _conn_id: i32) {
         #[cfg(any(target_os = "windows", target_os = "macos"))]
         {
             rdev::set_mouse_extra_info(enigo::ENIGO_INPUT_EXTRA_VALUE);
@@ -958,6 +1109,11 @@ impl Connection {
         }
         #[cfg(target_os = "macos")]
         reset_input_ondisconn();
+        // Created on the first pen event, not retried if it fails.
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        let mut pen: Option<ResultType<crate::pen::VirtualPen>> = None;
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        let mut touch: Option<ResultType<crate::touch::VirtualTouch>> = None;
         loop {
             match receiver.recv_timeout(std::time::Duration::from_millis(500)) {
                 Ok(v) => match v {
@@ -986,62 +1142,50 @@ impl Connection {
                     MessageInput::Pointer((msg, id)) => {
                         handle_pointer(&msg, id);
                     }
-                    MessageInput::BlockOn => {
-                        let (ok, msg) = crate::platform::block_input(true);
-                        if ok {
-                            block_input_mode = true;
-                        } else {
-                            Self::send_block_input_error(
-                                &tx,
-                                back_notification::BlockInputState::BlkOnFailed,
-                                msg,
-                            );
+                    #[cfg(any(target_os = "windows", target_os = "linux"))]
+                    MessageInput::Pen(evt) => {
+                        let pen = pen.get_or_insert_with(|| {
+                            let res = crate::pen::VirtualPen::new();
+                            if let Err(e) = &res {
+                                log::error!("Failed to create the virtual pen: {}", e);
+                            }
+                            res
+                        });
+                        if let Ok(pen) = pen {
+                            if let Err(e) = pen.handle(&evt) {
+                                log::debug!("Failed to inject the pen event: {}", e);
+                            }
                         }
                     }
-                    MessageInput::BlockOff => {
-                        let (ok, msg) = crate::platform::block_input(false);
-                        if ok {
-                            block_input_mode = false;
-                        } else {
-                            Self::send_block_input_error(
-                                &tx,
-                                back_notification::BlockInputState::BlkOffFailed,
-                                msg,
-                            );
+                    #[cfg(any(target_os = "windows", target_os = "linux"))]
+                    MessageInput::Touch(frame) => {
+                        let touch = touch.get_or_insert_with(|| {
+                            let res = crate::touch::VirtualTouch::new();
+                            if let Err(e) = &res {
+                                log::error!("Failed to create the virtual touch screen: {}", e);
+                            }
+                            res
+                        });
+                        if let Ok(touch) = touch {
+                            if let Err(e) = touch.handle(&frame) {
+                                log::debug!("Failed to inject the touch frame: {}", e);
+                            }
                         }
                     }
                     #[cfg(all(feature = "flutter", feature = "plugin_framework"))]
                     #[cfg(not(any(target_os = "android", target_os = "ios")))]
-                    MessageInput::BlockOnPlugin(_peer) => {
-                        let (ok, _msg) = crate::platform::block_input(true);
-                        if ok {
-                            block_input_mode = true;
-                        }
-                        let _r = PLUGIN_BLOCK_INPUT_TX_RX
-                            .0
-                            .lock()
-                            .unwrap()
-                            .send(block_input_mode);
+                    MessageInput::BlockOnPlugin(peer) => {
+                        let (ok, _msg) = super::block_input::set(_conn_id, &peer, true);
+                        let _r = PLUGIN_BLOCK_INPUT_TX_RX.0.lock().unwrap().send(ok);
                     }
                     #[cfg(all(feature = "flutter", feature = "plugin_framework"))]
                     #[cfg(not(any(target_os = "android", target_os = "ios")))]
-                    MessageInput::BlockOffPlugin(_peer) => {
-                        let (ok, _msg) = crate::platform::block_input(false);
-                        if ok {
-                            block_input_mode = false;
-                        }
-                        let _r = PLUGIN_BLOCK_INPUT_TX_RX
-                            .0
-                            .lock()
-                            .unwrap()
-                            .send(block_input_mode);
+                    MessageInput::BlockOffPlugin(peer) => {
+                        let (ok, _msg) = super::block_input::set(_conn_id, &peer, false);
+                        let _r = PLUGIN_BLOCK_INPUT_TX_RX.0.lock().unwrap().send(!ok);
                     }
                 },
                 Err(err) => {
-                    #[cfg(not(any(target_os = "android", target_os = "ios")))]
-                    if block_input_mode {
-                        let _ = crate::platform::block_input(true);
-                    }
                     if std_mpsc::RecvTimeoutError::Disconnected == err {
                         break;
                     }
@@ -1167,11 +1311,33 @@ impl Connection {
         true
     }
 
+    async fn check_access_list(&mut self, ip: Option<IpAddr>, id: Option<&str>) -> bool {
+        if let Err(rule) = access_list::check(ip, id) {
+            log::warn!(
+                "Connection from {:?}, id {:?}, rejected by the access control list: {}",
+                ip,
+                id,
+                rule
+            );
+            self.send_login_error("Access denied by the access control list of the peer")
+                .await;
+            Self::post_alarm_audit(
+                AlarmAuditType::IpWhitelist,
+                json!({ "ip": ip, "id": id, "rule": rule }),
+            );
+            return false;
+        }
+        true
+    }
+
     async fn on_open(&mut self, addr: SocketAddr) -> bool {
         log::debug!("#{} Connection opened from {}.", self.inner.id, addr);
         if !self.check_whitelist(&addr).await {
             return false;
         }
+        if !self.check_access_list(Some(addr.ip()), None).await {
+            return false;
+        }
         #[cfg(not(any(target_os = "android", target_os = "ios")))]
         if crate::is_server() && Config::get_option("allow-only-conn-window-open") == "Y" {
             if !crate::check_process("", !crate::platform::is_root()) {
@@ -1188,6 +1354,7 @@ impl Connection {
             "ip": addr.ip(),
             "action": "new",
         }));
+        self.audit("connect", json!({}));
         true
     }
 
@@ -1214,9 +1381,52 @@ impl Connection {
         v["uuid"] = json!(crate::encode64(hbb_common::get_uuid()));
         v["conn_id"] = json!(self.inner.id);
         v["session_id"] = json!(self.lr.session_id);
+        if audit_log::is_push_allowed() {
+            audit_log::push(url, v);
+            return;
+        }
         allow_err!(self.tx_post_seq.send((url, v)));
     }
 
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    fn session_hook_env(&self, lr: &LoginRequest) -> session_hook::Env {
+        let conn_type = match lr.union {
+            Some(login_request::Union::FileTransfer(_)) => "file_transfer",
+            Some(login_request::Union::PortForward(_)) => "port_forward",
+            Some(login_request::Union::ViewCamera(_)) => "view_camera",
+            Some(login_request::Union::Terminal(_)) => "terminal",
+            _ => "remote",
+        };
+        let permissions = [
+            ("keyboard", self.keyboard),
+            ("clipboard", self.clipboard),
+            ("audio", self.audio),
+            ("file", self.file),
+            ("restart", self.restart),
+            ("recording", self.recording),
+            ("block_input", self.block_input),
+        ]
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect();
+        session_hook::Env {
+            conn_id: self.inner.id,
+            peer_id: lr.my_id.clone(),
+            peer_name: lr.my_name.clone(),
+            ip: self.ip.clone(),
+            conn_type,
+            permissions,
+        }
+    }
+
+    fn audit(&self, event: &str, info: Value) {
+        // A session ends only if it started.
+        if event != "disconnect" || self.authorized {
+            webhook::post(event, self.inner.id, &self.lr.my_id, &self.ip, &info);
+        }
+        audit_log::record(event, self.inner.id, &self.lr.my_id, &self.ip, info);
+    }
+
     fn get_files_for_audit(job_type: fs::JobType, mut files: Vec<FileEntry>) -> Vec<(String, i64)> {
         files
             .drain(..)
@@ -1240,6 +1450,10 @@ impl Connection {
         files: Vec<(String, i64)>,
         info: Value,
     ) {
+        self.audit(
+            "file",
+            json!({"type": r#type as i8, "path": path, "files": files.iter().take(100).collect::<Vec<_>>(), "num": files.len()}),
+        );
         if self.server_audit_file.is_empty() {
             return;
         }
@@ -1263,6 +1477,10 @@ impl Connection {
             "is_file":is_file,
             "info":json!(info).to_string(),
         });
+        if audit_log::is_push_allowed() {
+            audit_log::push(url, v);
+            return;
+        }
         tokio::spawn(async move {
             allow_err!(Self::post_audit_async(url, v).await);
         });
@@ -1282,6 +1500,10 @@ impl Connection {
         v["uuid"] = json!(crate::encode64(hbb_common::get_uuid()));
         v["typ"] = json!(typ as i8);
         v["info"] = serde_json::Value::String(info.to_string());
+        if audit_log::is_push_allowed() {
+            audit_log::push(url, v);
+            return;
+        }
         tokio::spawn(async move {
             allow_err!(Self::post_audit_async(url, v).await);
         });
@@ -1292,6 +1514,31 @@ impl Connection {
         crate::post_request(url, v.to_string(), "").await
     }
 
+    async fn handle_security_key_assertion(&mut self, content: &[u8]) {
+        let Some(challenge) = self.security_key_challenge.take() else {
+            return;
+        };
+        let (failure, res) = self.check_failure(1).await;
+        if !res {
+            return;
+        }
+        if crate::auth_security_key::verify(&challenge, content) {
+            self.update_failure(failure, true, 1);
+            self.auth_methods.push("security-key");
+            self.require_security_key = false;
+            self.send_logon_response().await;
+            self.try_start_cm(
+                self.lr.my_id.to_owned(),
+                self.lr.my_name.to_owned(),
+                self.authorized,
+            );
+        } else {
+            self.update_failure(failure, false, 1);
+            self.send_login_error(crate::client::LOGIN_MSG_SECURITY_KEY_WRONG)
+                .await;
+        }
+    }
+
     async fn send_logon_response(&mut self) {
         if self.authorized {
             return;
@@ -1327,6 +1574,28 @@ impl Connection {
             self.send_login_error(crate::client::REQUIRE_2FA).await;
             return;
         }
+        if self.require_security_key && !self.from_switch {
+            let (challenge, msg) = crate::auth_security_key::new_challenge();
+            self.security_key_challenge = Some(challenge);
+            // The challenge goes after the error, so the prompt does not hide a signing failure.
+            self.send_login_error(crate::client::REQUIRE_SECURITY_KEY)
+                .await;
+            self.send(msg).await;
+            return;
+        }
+        // Only after the authentication, not to run a command for every login request.
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        if !session_hook::on_requested(&self.session_hook_env(&self.lr)).await {
+            self.send_login_error("Denied by the session hook").await;
+            return;
+        }
+        if let Some(id) = self.one_time_password_id.take() {
+            if !one_time_password::consume(&id) {
+                self.send_login_error(crate::client::LOGIN_MSG_PASSWORD_WRONG)
+                    .await;
+                return;
+            }
+        }
         self.authorized = true;
         let (conn_type, auth_conn_type) = if self.file_transfer.is_some() {
             (1, AuthConnType::FileTransfer)
@@ -1336,6 +1605,8 @@ impl Connection {
             (3, AuthConnType::ViewCamera)
         } else if self.terminal {
             (4, AuthConnType::Terminal)
+        } else if self.audio_only {
+            (5, AuthConnType::AudioOnly)
         } else {
             (0, AuthConnType::Remote)
         };
@@ -1354,6 +1625,25 @@ impl Connection {
         self.post_conn_audit(
             json!({"peer": ((&self.lr.my_id, &self.lr.my_name)), "type": conn_type}),
         );
+        self.audit(
+            "login",
+            json!({"name": self.lr.my_name, "type": conn_type, "auth": self.auth_methods}),
+        );
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        session_hook::on_accepted(self.session_hook_env(&self.lr));
+        if auth_conn_type == AuthConnType::Remote && record_signing::is_enabled() {
+            #[cfg(windows)]
+            let root = crate::platform::is_root();
+            #[cfg(not(windows))]
+            let root = false;
+            match record_signing::InputLog::new(
+                &crate::ui_interface::video_save_directory(root),
+                json!({"peer_id": self.lr.my_id, "name": self.lr.my_name, "ip": self.ip, "conn_id": self.inner.id}),
+            ) {
+                Ok(log) => self.input_log = Some(log),
+                Err(err) => log::error!("Failed to create input log: {}", err),
+            }
+        }
         #[allow(unused_mut)]
         let mut username = crate::platform::get_active_username();
         let mut res = LoginResponse::new();
@@ -1373,13 +1663,7 @@ impl Connection {
             pi.hostname = DEVICE_NAME.lock().unwrap().clone();
             pi.platform = "Android".into();
         }
-        #[cfg(all(target_os = "macos", not(feature = "unix-file-copy-paste")))]
-        let platform_additions = serde_json::Map::new();
-        #[cfg(any(
-            target_os = "windows",
-            target_os = "linux",
-            all(target_os = "macos", feature = "unix-file-copy-paste")
-        ))]
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
         let mut platform_additions = serde_json::Map::new();
         #[cfg(target_os = "linux")]
         {
@@ -1406,6 +1690,36 @@ impl Connection {
                 "supported_privacy_mode_impl".into(),
                 json!(privacy_mode::get_supported_privacy_mode_impl()),
             );
+            // The user sessions that can be switched to during the session, see `SelectedSid`.
+            let sessions = crate::platform::get_available_sessions(true);
+            if crate::platform::is_installed()
+                && crate::platform::is_share_rdp()
+                && sessions.len() > 1
+            {
+                let sessions: Vec<_> = sessions
+                    .iter()
+                    .map(|s| json!({ "sid": s.sid.to_string(), "name": s.name }))
+                    .collect();
+                platform_additions.insert("windows_sessions".into(), json!(sessions));
+                if let Some(sid) = crate::platform::get_current_process_session_id() {
+                    platform_additions.insert("windows_current_sid".into(), json!(sid.to_string()));
+                }
+            }
+        }
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            platform_additions.insert(
+                "is_installed".into(),
+                json!(crate::platform::is_installed()),
+            );
+            if crate::platform::is_installed() {
+                platform_additions
+                    .extend(crate::platform::virtual_display::get_platform_additions());
+            }
+            platform_additions.insert(
+                "supported_privacy_mode_impl".into(),
+                json!(privacy_mode::get_supported_privacy_mode_impl()),
+            );
         }
 
         #[cfg(any(target_os = "windows", feature = "unix-file-copy-paste"))]
@@ -1431,6 +1745,107 @@ impl Connection {
             platform_additions.insert("support_view_camera".into(), json!(true));
         }
 
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        if crate::client::bonding::is_allowed() {
+            platform_additions.insert("support_bonding".into(), json!(true));
+        }
+
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        platform_additions.insert("support_fec".into(), json!(true));
+
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        platform_additions.insert(crate::delta_sync::PLATFORM_ADDITION_KEY.into(), json!(true));
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        platform_additions.insert(
+            crate::transfer_integrity::PLATFORM_ADDITION_KEY.into(),
+            json!(true),
+        );
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        platform_additions.insert(
+            crate::transfer_metadata::PLATFORM_ADDITION_KEY.into(),
+            json!(true),
+        );
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        platform_additions.insert(crate::file_ops::PLATFORM_ADDITION_KEY.into(), json!(true));
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        platform_additions.insert(crate::file_drop::PLATFORM_ADDITION_KEY.into(), json!(true));
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        platform_additions.insert(
+            crate::file_stream::PLATFORM_ADDITION_KEY.into(),
+            json!(true),
+        );
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        platform_additions.insert(
+            crate::transfer_broker::PLATFORM_ADDITION_KEY.into(),
+            json!(true),
+        );
+        platform_additions.insert(
+            crate::file_compression::PLATFORM_ADDITION_KEY.into(),
+            json!(true),
+        );
+
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        if self.audio {
+            platform_additions.insert("support_audio_device".into(), json!(true));
+        }
+        if self.audio {
+            platform_additions.insert("support_audio_qos".into(), json!(true));
+        }
+
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        if crate::audio_service::is_mic_passthrough_allowed() && self.audio {
+            platform_additions.insert("support_mic_passthrough".into(), json!(true));
+        }
+
+        platform_additions.insert("support_av1_svc".into(), json!(true));
+        platform_additions.insert("support_bwe_stats".into(), json!(true));
+        #[cfg(windows)]
+        platform_additions.insert("support_hdr".into(), json!(true));
+
+        if let Some(v) = crate::patch_level::get_if_allowed() {
+            platform_additions.insert(crate::patch_level::KEY.into(), v);
+        }
+
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        if crate::tunnel::is_allowed() && self.tunnel_permission() {
+            platform_additions.insert("support_tunnel".into(), json!(true));
+        }
+
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        if self.keyboard && self.gamepad_permission() {
+            platform_additions.insert("support_gamepad".into(), json!(true));
+        }
+
+        #[cfg(any(target_os = "windows", target_os = "linux"))]
+        if self.keyboard {
+            platform_additions.insert("support_pen".into(), json!(true));
+            platform_additions.insert("support_touch".into(), json!(true));
+        }
+
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        if self.keyboard {
+            platform_additions.insert("support_block_input".into(), json!(true));
+            platform_additions.insert("support_relative_mouse".into(), json!(true));
+            platform_additions.insert(
+                crate::special_keys::PLATFORM_ADDITION_KEY.into(),
+                json!(crate::special_keys::supported()),
+            );
+        }
+
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        if let Some(layout) = crate::keyboard_layout::get() {
+            platform_additions.insert(
+                crate::keyboard_layout::PLATFORM_ADDITION_KEY.into(),
+                json!(layout),
+            );
+        }
+
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        if crate::lan::is_wol_relay_allowed() {
+            platform_additions.insert("mac_addresses".into(), json!(crate::lan::get_local_macs()));
+            platform_additions.insert("support_wol_relay".into(), json!(true));
+        }
+
         #[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
         if !platform_additions.is_empty() {
             pi.platform_additions = serde_json::to_string(&platform_additions).unwrap_or("".into());
@@ -1511,7 +1926,7 @@ impl Connection {
         if !self.terminal {
             self.handle_windows_specific_session(&mut pi, &mut wait_session_id_confirm);
         }
-        if self.file_transfer.is_some() || self.terminal {
+        if self.file_transfer.is_some() || self.terminal || self.audio_only {
             res.set_peer_info(pi);
         } else if self.view_camera {
             let supported_encoding = scrap::codec::Encoder::supported_encoding();
@@ -1613,10 +2028,29 @@ impl Connection {
             }
             self.keyboard = false;
             self.send_permission(Permission::Keyboard, false).await;
+        } else if self.audio_only {
+            self.keyboard = false;
+            self.send_permission(Permission::Keyboard, false).await;
+            self.try_sub_audio_service();
         } else if sub_service {
             if !wait_session_id_confirm {
                 self.try_sub_monitor_services();
             }
+            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+            if self.keyboard
+                && self.block_input
+                && super::block_input::resume(self.inner.id(), &self.lr.my_id)
+            {
+                self.send_to_cm(ipc::Data::InputBlocked(true));
+                let mut misc = Misc::new();
+                let mut back_notification = BackNotification::new();
+                back_notification
+                    .set_block_input_state(back_notification::BlockInputState::BlkOnSucceeded);
+                misc.set_back_notification(back_notification);
+                let mut msg_out = Message::new();
+                msg_out.set_misc(misc);
+                self.send(msg_out).await;
+            }
         }
     }
 
@@ -1629,12 +2063,25 @@ impl Connection {
         }
     }
 
+    fn try_sub_audio_service(&mut self) {
+        if self.services_subed || !self.audio_enabled() {
+            return;
+        }
+        self.services_subed = true;
+        if let Some(s) = self.server.upgrade() {
+            self.auto_disconnect_timer = Self::get_auto_disconenct_timer();
+            self.max_duration_timer = Self::get_max_duration_timer();
+            s.write().unwrap().add_audio_connection(self.inner.clone());
+        }
+    }
+
     #[inline]
     fn is_remote(&self) -> bool {
         self.file_transfer.is_none()
             && self.port_forward_socket.is_none()
             && !self.view_camera
             && !self.terminal
+            && !self.audio_only
     }
 
     fn try_sub_monitor_services(&mut self) {
@@ -1666,6 +2113,7 @@ impl Connection {
                 #[cfg(not(any(target_os = "android", target_os = "ios")))]
                 let _h = try_start_record_cursor_pos();
                 self.auto_disconnect_timer = Self::get_auto_disconenct_timer();
+                self.max_duration_timer = Self::get_max_duration_timer();
                 s.try_add_primay_video_service();
                 s.add_connection(self.inner.clone(), &noperms);
             }
@@ -1770,6 +2218,7 @@ impl Connection {
             recording: self.recording,
             block_input: self.block_input,
             from_switch: self.from_switch,
+            sas: self.sas.clone(),
         });
     }
 
@@ -1783,6 +2232,177 @@ impl Connection {
         self.send_to_cm(ipc::Data::FS(data));
     }
 
+    // Reads the files of the downloads here, the connection manager writes the uploads.
+    fn handle_delta_sync(&mut self, content: &[u8]) {
+        use crate::delta_sync::DeltaMessage;
+        let Some(msg) = DeltaMessage::parse(content) else {
+            return;
+        };
+        match msg {
+            DeltaMessage::Signatures {
+                id,
+                file_num,
+                block_size,
+                weak,
+                strong,
+            } => {
+                let Some(path) = fs::get_job(id, &mut self.read_jobs)
+                    .and_then(|job| crate::delta_sync::file_path(job, file_num))
+                else {
+                    return;
+                };
+                let inner = self.inner.clone();
+                crate::delta_sync::start_read(
+                    id,
+                    file_num,
+                    path,
+                    block_size,
+                    weak,
+                    strong,
+                    move |msg| inner.clone().send(Arc::new(msg)),
+                );
+            }
+            DeltaMessage::Ack { id, file_num } => crate::delta_sync::on_ack(id, file_num),
+            DeltaMessage::Request { .. } | DeltaMessage::Ops { .. } | DeltaMessage::End { .. } => {
+                self.send_fs(ipc::FS::DeltaSync(content.to_vec()));
+            }
+        }
+    }
+
+    // Hashes the files of the job done in a thread, the blocks of the uploads are written by the
+    // connection manager before it reports the job done.
+    fn handle_transfer_integrity(&mut self, content: &[u8]) {
+        use crate::transfer_integrity::IntegrityMessage;
+        let Some(IntegrityMessage::Request { id, files }) = IntegrityMessage::parse(content) else {
+            return;
+        };
+        let mut inner = self.inner.clone();
+        std::thread::spawn(move || {
+            let hashes = crate::transfer_integrity::hash_files(&files);
+            let msg = IntegrityMessage::Hashes { id, hashes }.to_message();
+            inner.send(Arc::new(msg));
+        });
+    }
+
+    // The file operations run by the connection manager, like the other ones of the file manager.
+    async fn handle_file_ops(&mut self, content: &[u8]) {
+        use crate::file_ops::FileOpMessage;
+        let Some(msg) = FileOpMessage::parse(content) else {
+            return;
+        };
+        if let Some(id) = msg.modifying_id() {
+            if crate::get_builtin_option(keys::OPTION_ONE_WAY_FILE_TRANSFER) == "Y" {
+                let error = Some("one-way-file-transfer-tip".to_owned());
+                self.send(FileOpMessage::Done { id, error }.to_message())
+                    .await;
+                return;
+            }
+        }
+        self.send_fs(ipc::FS::FileOps(content.to_vec()));
+    }
+
+    // The connection manager finds the folder under the cursor, the uploads of the session are
+    // accepted from then on.
+    async fn handle_file_drop(&mut self, content: &[u8]) {
+        use crate::file_drop::FileDropMessage;
+        let Some(FileDropMessage::Query { id }) = FileDropMessage::parse(content) else {
+            return;
+        };
+        let error = if !self.file_transfer_enabled() {
+            "No permission of file transfer"
+        } else if crate::get_builtin_option(keys::OPTION_ONE_WAY_FILE_TRANSFER) == "Y" {
+            "one-way-file-transfer-tip"
+        } else {
+            self.file_drop = true;
+            self.send_fs(ipc::FS::FileDrop(content.to_vec()));
+            return;
+        };
+        self.send(FileDropMessage::error(id, error).to_message())
+            .await;
+    }
+
+    // Issues the token of the file stream, if the session may transfer files.
+    async fn handle_file_stream(&mut self, content: &[u8]) {
+        use crate::file_stream::FileStreamMessage;
+        let Some(FileStreamMessage::Request) = FileStreamMessage::parse(content) else {
+            return;
+        };
+        let token = if self.file_transfer.is_some() || self.file_transfer_enabled() {
+            self.file_stream.new_token().to_vec()
+        } else {
+            Vec::new()
+        };
+        self.send(FileStreamMessage::Token { token }.to_message())
+            .await;
+    }
+
+    // Issues the ticket of the sending peer as the receiving one, or sends the files to the
+    // receiving peer from here as the sending one.
+    async fn handle_transfer_broker(&mut self, content: &[u8]) {
+        use crate::transfer_broker::BrokerMessage;
+        let Some(msg) = BrokerMessage::parse(content) else {
+            return;
+        };
+        match msg {
+            BrokerMessage::Ticket { id } => {
+                let msg = if !self.file_transfer_enabled() {
+                    BrokerMessage::issue_error(id, "No permission of file transfer")
+                } else if crate::get_builtin_option(keys::OPTION_ONE_WAY_FILE_TRANSFER) == "Y" {
+                    BrokerMessage::issue_error(id, "one-way-file-transfer-tip")
+                } else {
+                    crate::transfer_broker::issue_ticket(id)
+                };
+                self.send(msg.to_message()).await;
+            }
+            BrokerMessage::Send { id, .. } => {
+                if !self.file_transfer_enabled() {
+                    let error = Some("No permission of file transfer".to_owned());
+                    self.send(BrokerMessage::Done { id, error }.to_message())
+                        .await;
+                    return;
+                }
+                let inner = self.inner.clone();
+                self.transfer_broker.start(msg, move |m| {
+                    inner.clone().send(Arc::new(m.to_message()));
+                });
+            }
+            BrokerMessage::Cancel { id } => self.transfer_broker.cancel(id),
+            _ => {}
+        }
+    }
+
+    // The metadata of the files of the download done is listed, or the one of the upload done is
+    // applied, by the connection manager, not here as root, and only for the files of the job.
+    fn handle_transfer_metadata(&mut self, content: &[u8]) {
+        use crate::transfer_metadata::MetadataMessage;
+        let Some(msg) = MetadataMessage::parse(content) else {
+            return;
+        };
+        let id = match &msg {
+            MetadataMessage::Request { id, .. } => *id,
+            MetadataMessage::Records { id, .. } => {
+                if crate::get_builtin_option(keys::OPTION_ONE_WAY_FILE_TRANSFER) == "Y" {
+                    return;
+                }
+                *id
+            }
+        };
+        let Some(mut job) = self.metadata_jobs.remove(&id) else {
+            return;
+        };
+        if matches!(msg, MetadataMessage::Request { .. }) {
+            job.done = !self.read_jobs.iter().any(|j| j.id() == id);
+        }
+        if !job.accepts(&msg) {
+            log::warn!(
+                "Ignored the transfer metadata of job {}, not of its files",
+                id
+            );
+            return;
+        }
+        self.send_fs(ipc::FS::TransferMetadata(content.to_vec()));
+    }
+
     async fn send_login_error<T: std::string::ToString>(&mut self, err: T) {
         let mut msg_out = Message::new();
         let mut res = LoginResponse::new();
@@ -1794,6 +2414,43 @@ impl Connection {
         self.send(msg_out).await;
     }
 
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    async fn set_block_input(&mut self, on: bool) {
+        let conn_id = self.inner.id();
+        let peer_id = self.lr.my_id.clone();
+        let (ok, msg) =
+            tokio::task::spawn_blocking(move || super::block_input::set(conn_id, &peer_id, on))
+                .await
+                .unwrap_or_else(|e| (false, e.to_string()));
+        if ok {
+            self.send_to_cm(ipc::Data::InputBlocked(on));
+        } else if let Some(tx) = &self.inner.tx {
+            let state = if on {
+                back_notification::BlockInputState::BlkOnFailed
+            } else {
+                back_notification::BlockInputState::BlkOffFailed
+            };
+            Self::send_block_input_error(tx, state, msg);
+        }
+    }
+
+    // The permission is revoked on this side.
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    async fn release_block_input(&mut self) {
+        let conn_id = self.inner.id();
+        tokio::task::spawn_blocking(move || super::block_input::release(conn_id))
+            .await
+            .ok();
+        self.send_to_cm(ipc::Data::InputBlocked(false));
+        if let Some(tx) = &self.inner.tx {
+            Self::send_block_input_error(
+                tx,
+                back_notification::BlockInputState::BlkOffSucceeded,
+                "".to_owned(),
+            );
+        }
+    }
+
     #[inline]
     pub fn send_block_input_error(
         s: &Sender,
@@ -1858,8 +2515,12 @@ impl Connection {
         let mut hasher = Sha256::new();
         hasher.update(password);
         hasher.update(&self.hash.salt);
+        self.validate_salted_password(&hasher.finalize()[..])
+    }
+
+    fn validate_salted_password(&self, salted: &[u8]) -> bool {
         let mut hasher2 = Sha256::new();
-        hasher2.update(&hasher.finalize()[..]);
+        hasher2.update(salted);
         hasher2.update(&self.hash.challenge);
         hasher2.finalize()[..] == self.lr.password[..]
     }
@@ -1880,6 +2541,22 @@ impl Connection {
             if self.validate_one_password(Config::get_permanent_password()) {
                 return true;
             }
+            // Deployed as the salted hash, without the password.
+            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+            if let Some(salted) = crate::deploy_config::get_permanent_password_hash() {
+                if self.validate_salted_password(&salted) {
+                    return true;
+                }
+            }
+        }
+        if !self.lr.password.is_empty() {
+            if let Some((id, profile)) =
+                one_time_password::find(|salted| self.validate_salted_password(salted))
+            {
+                self.one_time_password_id = Some(id);
+                self.one_time_profile = Some(profile);
+                return true;
+            }
         }
         false
     }
@@ -1947,24 +2624,132 @@ impl Connection {
 
     async fn handle_login_request_without_validation(&mut self, lr: &LoginRequest) {
         self.lr = lr.clone();
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        crate::logging::set_peer_id(&lr.my_id);
         self.peer_argb = crate::str2color(&format!("{}{}", &lr.my_id, &lr.my_platform), 0xff);
         if let Some(o) = lr.option.as_ref() {
             self.options_in_login = Some(o.clone());
         }
-        if self.require_2fa.is_some() && !lr.hwid.is_empty() && Self::enable_trusted_devices() {
-            let devices = Config::get_trusted_devices();
-            if let Some(device) = devices.iter().find(|d| d.hwid == lr.hwid) {
-                if !device.outdate()
-                    && device.id == lr.my_id
-                    && device.name == lr.my_name
-                    && device.platform == lr.my_platform
-                {
-                    log::info!("2FA bypassed by trusted devices");
-                    self.require_2fa = None;
-                }
+        if self.require_2fa.is_some() && !lr.hwid.is_empty() && Self::enable_trusted_devices() {
+            let devices = Config::get_trusted_devices();
+            if let Some(device) = devices.iter().find(|d| d.hwid == lr.hwid) {
+                if !device.outdate()
+                    && device.id == lr.my_id
+                    && device.name == lr.my_name
+                    && device.platform == lr.my_platform
+                {
+                    log::info!("2FA bypassed by trusted devices");
+                    self.auth_methods.push("trusted-device");
+                    self.require_2fa = None;
+                }
+            }
+        }
+        self.video_ack_required = lr.video_ack_required;
+        if let Some(profile) = peer_profile::get(&lr.my_id) {
+            self.apply_peer_profile(&profile, true).await;
+        }
+    }
+
+    // The profile of the peer id, which the peer only claims, can only turn the permissions
+    // off, the one of the one-time password, bound to the password, also on.
+    async fn apply_peer_profile(&mut self, profile: &peer_profile::PeerProfile, narrow_only: bool) {
+        use peer_profile::Capability;
+        log::info!(
+            "Apply the permission profile of {}: {:?}",
+            self.lr.my_id,
+            profile
+        );
+        let allows = |capability| {
+            profile
+                .allows(capability)
+                .filter(|enabled| !narrow_only || !enabled)
+        };
+        if let Some(enabled) = allows(Capability::Keyboard) {
+            if self.keyboard != enabled {
+                self.keyboard = enabled;
+                self.send_permission(Permission::Keyboard, enabled).await;
+            }
+        }
+        if let Some(enabled) = allows(Capability::Clipboard) {
+            if self.clipboard != enabled {
+                self.clipboard = enabled;
+                self.send_permission(Permission::Clipboard, enabled).await;
+            }
+        }
+        if let Some(enabled) = allows(Capability::Audio) {
+            if self.audio != enabled {
+                self.audio = enabled;
+                self.send_permission(Permission::Audio, enabled).await;
+            }
+        }
+        if let Some(enabled) = allows(Capability::File) {
+            if self.file != enabled {
+                self.file = enabled;
+                self.send_permission(Permission::File, enabled).await;
+            }
+        }
+    }
+
+    // As `apply_peer_profile`, for the permissions checked when used.
+    fn profile_permission(&self, capability: peer_profile::Capability, global: bool) -> bool {
+        if let Some(profile) = self.one_time_profile.as_ref() {
+            return profile.allows(capability).unwrap_or(global);
+        }
+        global
+            && peer_profile::get(&self.lr.my_id)
+                .and_then(|p| p.allows(capability))
+                .unwrap_or(true)
+    }
+
+    // The type of the session was checked before the password, so before the profile of the
+    // one-time password was known.
+    fn check_one_time_profile(&self) -> Option<&'static str> {
+        if self.file_transfer.is_some() && !self.file {
+            Some("No permission of file transfer")
+        } else if self.port_forward_socket.is_some() && !self.tunnel_permission() {
+            Some("No permission of IP tunneling")
+        } else if self.audio_only && !self.audio {
+            Some("No permission of audio")
+        } else {
+            None
+        }
+    }
+
+    fn tunnel_permission(&self) -> bool {
+        self.profile_permission(
+            peer_profile::Capability::Tunnel,
+            Self::permission("enable-tunnel"),
+        )
+    }
+
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    fn gamepad_permission(&self) -> bool {
+        self.profile_permission(
+            peer_profile::Capability::Gamepad,
+            crate::gamepad::is_allowed(),
+        )
+    }
+
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    fn handle_gamepad(&mut self, content: &[u8]) {
+        if !self.keyboard || !self.gamepad_permission() {
+            self.gamepads = None;
+            return;
+        }
+        let state = match serde_json::from_slice::<crate::gamepad::GamepadState>(content) {
+            Ok(state) => state,
+            Err(e) => {
+                log::error!("Invalid gamepad state: {}", e);
+                return;
             }
+        };
+        if let Err(e) = self
+            .gamepads
+            .get_or_insert_with(Default::default)
+            .handle(&state)
+        {
+            log::error!("Failed to replay gamepad {}: {}", state.index, e);
         }
-        self.video_ack_required = lr.video_ack_required;
     }
 
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -2006,20 +2791,49 @@ impl Connection {
             // Move the CloseReason forward, as this message needs to be received when unauthorized, especially for kcp.
             if let Some(misc::Union::CloseReason(s)) = &misc.union {
                 log::info!("receive close reason: {}", s);
+                #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                super::block_input::release(self.inner.id());
                 self.on_close("Peer close", true).await;
                 raii::AuthedConnID::check_remove_session(self.inner.id(), self.session_key());
                 return false;
             }
+            if let Some(misc::Union::PluginRequest(p)) = &misc.union {
+                if p.id == crate::auth_security_key::PLUGIN_ID {
+                    if !self.authorized {
+                        self.handle_security_key_assertion(&p.content).await;
+                    }
+                    return true;
+                }
+                if p.id == crate::client::AUDIO_ONLY_PLUGIN_ID {
+                    if !self.authorized {
+                        self.audio_only = true;
+                    }
+                    return true;
+                }
+            }
         }
         // After handling CloseReason messages, proceed to process other message types
         if let Some(message::Union::LoginRequest(lr)) = msg.union {
+            if !self
+                .check_access_list(self.ip.parse().ok(), Some(&lr.my_id))
+                .await
+            {
+                sleep(1.).await;
+                return false;
+            }
+            if !crate::patch_level::is_client_version_allowed(&lr.version) {
+                self.send_login_error(crate::patch_level::LOGIN_MSG_OUTDATED_CLIENT)
+                    .await;
+                sleep(1.).await;
+                return false;
+            }
             self.handle_login_request_without_validation(&lr).await;
             if self.authorized {
                 return true;
             }
             match lr.union {
                 Some(login_request::Union::FileTransfer(ft)) => {
-                    if !Connection::permission(keys::OPTION_ENABLE_FILE_TRANSFER) {
+                    if !self.file {
                         self.send_login_error("No permission of file transfer")
                             .await;
                         sleep(1.).await;
@@ -2085,7 +2899,7 @@ impl Connection {
                     }
                 }
                 Some(login_request::Union::PortForward(mut pf)) => {
-                    if !Connection::permission("enable-tunnel") {
+                    if !self.tunnel_permission() {
                         self.send_login_error("No permission of IP tunneling").await;
                         sleep(1.).await;
                         return false;
@@ -2118,6 +2932,13 @@ impl Connection {
                         }
                     }
                 }
+                _ if self.audio_only => {
+                    if !self.audio {
+                        self.send_login_error("No permission of audio").await;
+                        sleep(1.).await;
+                        return false;
+                    }
+                }
                 _ => {
                     if !self.check_privacy_mode_on().await {
                         return false;
@@ -2184,6 +3005,7 @@ impl Connection {
                 }
                 return true;
             } else if self.is_recent_session(false) {
+                self.auth_methods.push("recent-session");
                 if err_msg.is_empty() {
                     #[cfg(target_os = "linux")]
                     self.linux_headless_handle.wait_desktop_cm_ready().await;
@@ -2220,6 +3042,17 @@ impl Connection {
                     }
                 } else {
                     self.update_failure(failure, true, 0);
+                    if let Some(profile) = self.one_time_profile.clone() {
+                        self.auth_methods.push("one-time-password");
+                        self.apply_peer_profile(&profile, false).await;
+                        if let Some(err) = self.check_one_time_profile() {
+                            self.send_login_error(err).await;
+                            sleep(1.).await;
+                            return false;
+                        }
+                    } else {
+                        self.auth_methods.push("password");
+                    }
                     if err_msg.is_empty() {
                         #[cfg(target_os = "linux")]
                         self.linux_headless_handle.wait_desktop_cm_ready().await;
@@ -2239,6 +3072,7 @@ impl Connection {
                 if let Ok(res) = totp.check_current(&tfa.code) {
                     if res {
                         self.update_failure(failure, true, 1);
+                        self.auth_methods.push("2fa");
                         self.require_2fa.take();
                         raii::AuthedConnID::set_session_2fa(self.session_key());
                         self.send_logon_response().await;
@@ -2276,6 +3110,12 @@ impl Connection {
                         .lock()
                         .unwrap()
                         .user_network_delay(self.inner.id(), new_delay);
+                    if self.file_transfer.is_none()
+                        && self.port_forward_socket.is_none()
+                        && !self.terminal
+                    {
+                        self.throttle.on_video_delay(new_delay);
+                    }
                     self.network_delay = new_delay;
                 }
             }
@@ -2292,6 +3132,7 @@ impl Connection {
                     if let Some((_instant, uuid_old)) = uuid_old {
                         if uuid == uuid_old {
                             self.from_switch = true;
+                            self.auth_methods.push("switch-sides");
                             self.send_logon_response().await;
                             self.try_start_cm(
                                 lr.my_id.clone(),
@@ -2308,6 +3149,9 @@ impl Connection {
             if self.port_forward_socket.is_some() {
                 return true;
             }
+            if let Some(input_log) = self.input_log.as_mut() {
+                input_log.log_message(&msg);
+            }
             match msg.union {
                 #[allow(unused_mut)]
                 Some(message::Union::MouseEvent(mut me)) => {
@@ -2508,8 +3352,9 @@ impl Connection {
                 }
                 Some(message::Union::Clipboard(cb)) => {
                     if self.clipboard {
+                        self.audit("clipboard", json!({"direction": "receive"}));
                         #[cfg(not(any(target_os = "android", target_os = "ios")))]
-                        update_clipboard(vec![cb], ClipboardSide::Host);
+                        update_clipboard_of_peer(vec![cb], ClipboardSide::Host);
                         // ios as the controlled side is actually not supported for now.
                         // The following code is only used to preserve the logic of handling text clipboard on mobile.
                         #[cfg(target_os = "ios")]
@@ -2535,9 +3380,12 @@ impl Connection {
                     }
                 }
                 Some(message::Union::MultiClipboards(_mcb)) => {
+                    if self.clipboard {
+                        self.audit("clipboard", json!({"direction": "receive"}));
+                    }
                     #[cfg(not(any(target_os = "android", target_os = "ios")))]
                     if self.clipboard {
-                        update_clipboard(_mcb.clipboards, ClipboardSide::Host);
+                        update_clipboard_of_peer(_mcb.clipboards, ClipboardSide::Host);
                     }
                     #[cfg(target_os = "android")]
                     crate::clipboard::handle_msg_multi_clipboards(_mcb);
@@ -2625,6 +3473,14 @@ impl Connection {
                             }
                         }
                     }
+                    if !handle_fa && self.file_drop && self.file_transfer_enabled() {
+                        handle_fa = matches!(
+                            fa.union,
+                            Some(file_action::Union::Receive(_))
+                                | Some(file_action::Union::SendConfirm(_))
+                                | Some(file_action::Union::Cancel(_))
+                        );
+                    }
                     if handle_fa {
                         if self.delayed_read_dir.is_some() {
                             if let Some(file_action::Union::ReadDir(rd)) = fa.union {
@@ -2725,6 +3581,15 @@ impl Connection {
                                         job.is_remote = true;
                                         job.conn_id = self.inner.id();
                                         let job_type = job.r#type;
+                                        if job_type == fs::JobType::Generic {
+                                            self.metadata_jobs.insert(
+                                                id,
+                                                crate::transfer_metadata::PeerJob::new(
+                                                    &s.path,
+                                                    files.iter().map(|f| f.name.clone()).collect(),
+                                                ),
+                                            );
+                                        }
                                         self.read_jobs.push(job);
                                         self.file_timer =
                                             crate::rustdesk_interval(time::interval(MILLI1));
@@ -2749,6 +3614,13 @@ impl Connection {
                                 let od = can_enable_overwrite_detection(get_version_number(
                                     &self.lr.version,
                                 ));
+                                self.metadata_jobs.insert(
+                                    r.id,
+                                    crate::transfer_metadata::PeerJob::new(
+                                        &r.path,
+                                        r.files.iter().map(|f| f.name.clone()).collect(),
+                                    ),
+                                );
                                 self.send_fs(ipc::FS::NewWrite {
                                     path: r.path.clone(),
                                     id: r.id,
@@ -2805,6 +3677,7 @@ impl Connection {
                             }
                             Some(file_action::Union::Cancel(c)) => {
                                 self.send_fs(ipc::FS::CancelWrite { id: c.id });
+                                crate::delta_sync::cancel_read(c.id);
                                 if let Some(job) = fs::remove_job(c.id, &mut self.read_jobs) {
                                     self.send_to_cm(ipc::Data::FileTransferLog((
                                         "transfer".to_string(),
@@ -2851,6 +3724,9 @@ impl Connection {
                         });
                     }
                     Some(file_response::Union::Done(d)) => {
+                        if let Some(job) = self.metadata_jobs.get_mut(&d.id) {
+                            job.done = true;
+                        }
                         self.send_fs(ipc::FS::WriteDone {
                             id: d.id,
                             file_num: d.file_num,
@@ -2883,7 +3759,7 @@ impl Connection {
                         let set = displays.set.iter().map(|d| *d as usize).collect::<Vec<_>>();
                         self.capture_displays(&add, &sub, &set).await;
                     }
-                    #[cfg(windows)]
+                    #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
                     Some(misc::Union::ToggleVirtualDisplay(t)) => {
                         self.toggle_virtual_display(t).await;
                     }
@@ -2947,7 +3823,7 @@ impl Connection {
                         _ => {}
                     },
                     Some(misc::Union::AudioFormat(format)) => {
-                        if !self.disable_audio {
+                        if !self.disable_audio && (self.voice_calling || self.mic_passthrough) {
                             // Drop the audio sender previously.
                             drop(std::mem::replace(&mut self.audio_sender, None));
                             self.audio_sender = Some(start_audio_thread());
@@ -2976,6 +3852,177 @@ impl Connection {
                     Some(misc::Union::ChangeDisplayResolution(dr)) => {
                         self.change_resolution(Some(dr.display as _), &dr.resolution)
                     }
+                    Some(misc::Union::PluginRequest(p))
+                        if crate::tunnel::is_tunnel_request(&p.id) =>
+                    {
+                        if !self.authorized || !self.tunnel_permission() {
+                            // The permission may be revoked during the session, dropping the
+                            // tunnel closes its listeners and streams.
+                            if self.tunnel.take().is_some() {
+                                log::info!("tunnel closed, no permission");
+                            }
+                        } else {
+                            if self.tunnel.is_none() {
+                                if let Some(tx) = self.inner.tx.clone() {
+                                    self.tunnel = Some(crate::tunnel::Tunnel::new(
+                                        false,
+                                        Arc::new(move |msg| {
+                                            tx.send((Instant::now(), Arc::new(msg))).ok();
+                                        }),
+                                    ));
+                                }
+                            }
+                            if let Some(tunnel) = self.tunnel.as_ref() {
+                                tunnel.handle(&p.content).await;
+                            }
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::client::AV1_SVC_PLUGIN_ID =>
+                    {
+                        video_service::VIDEO_QOS
+                            .lock()
+                            .unwrap()
+                            .user_av1_svc(self.inner.id(), true);
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::client::BWE_STATS_PLUGIN_ID =>
+                    {
+                        self.bwe_stats = true;
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == crate::client::HDR_PLUGIN_ID => {
+                        video_service::VIDEO_QOS
+                            .lock()
+                            .unwrap()
+                            .user_hdr(self.inner.id(), true);
+                    }
+                    #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::client::VIRTUAL_DISPLAY_MODE_PLUGIN_ID =>
+                    {
+                        self.plug_in_virtual_display_mode(&p.content).await;
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::client::AUDIO_DEVICE_PLUGIN_ID =>
+                    {
+                        self.handle_audio_device_request(&p.content).await;
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::client::AUDIO_LOSS_PLUGIN_ID =>
+                    {
+                        if let Ok(percent) = String::from_utf8_lossy(&p.content).parse::<u32>() {
+                            video_service::VIDEO_QOS
+                                .lock()
+                                .unwrap()
+                                .user_audio_loss(self.inner.id(), percent);
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::client::MIC_PASSTHROUGH_PLUGIN_ID =>
+                    {
+                        let on = p.content.as_ref() == b"Y";
+                        self.mic_passthrough = on
+                            && self.authorized
+                            && self.audio
+                            && crate::audio_service::is_mic_passthrough_allowed();
+                        log::info!(
+                            "mic passthrough: {}, requested: {}",
+                            self.mic_passthrough,
+                            on
+                        );
+                    }
+                    #[cfg(any(target_os = "windows", target_os = "linux"))]
+                    Some(misc::Union::PluginRequest(p)) if p.id == crate::pen::PLUGIN_ID => {
+                        if self.peer_keyboard_enabled() && !self.is_authed_view_camera_conn() {
+                            match serde_json::from_slice::<crate::pen::PenEvent>(&p.content) {
+                                Ok(evt) => {
+                                    self.tx_input.send(MessageInput::Pen(evt)).ok();
+                                    self.update_auto_disconnect_timer();
+                                }
+                                Err(e) => log::error!("Invalid pen event: {}", e),
+                            }
+                        }
+                    }
+                    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::special_keys::PLUGIN_ID =>
+                    {
+                        if self.peer_keyboard_enabled() && !self.is_authed_view_camera_conn() {
+                            let name = String::from_utf8_lossy(&p.content);
+                            match crate::special_keys::key_events(&name) {
+                                Some(events) => {
+                                    for evt in events {
+                                        self.input_key(evt, false);
+                                    }
+                                    self.update_auto_disconnect_timer();
+                                }
+                                None => log::error!("Unknown special key: {}", name),
+                            }
+                        }
+                    }
+                    #[cfg(any(target_os = "windows", target_os = "linux"))]
+                    Some(misc::Union::PluginRequest(p)) if p.id == crate::touch::PLUGIN_ID => {
+                        if self.peer_keyboard_enabled() && !self.is_authed_view_camera_conn() {
+                            match serde_json::from_slice::<crate::touch::TouchFrame>(&p.content) {
+                                Ok(frame) => {
+                                    self.tx_input.send(MessageInput::Touch(frame)).ok();
+                                    self.update_auto_disconnect_timer();
+                                }
+                                Err(e) => log::error!("Invalid touch frame: {}", e),
+                            }
+                        }
+                    }
+                    #[cfg(any(target_os = "windows", target_os = "linux"))]
+                    Some(misc::Union::PluginRequest(p)) if p.id == crate::gamepad::PLUGIN_ID => {
+                        self.handle_gamepad(&p.content);
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == crate::delta_sync::PLUGIN_ID => {
+                        self.handle_delta_sync(&p.content);
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::transfer_integrity::PLUGIN_ID =>
+                    {
+                        if self.file_transfer.is_some() {
+                            self.handle_transfer_integrity(&p.content);
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::transfer_metadata::PLUGIN_ID =>
+                    {
+                        if self.file_transfer.is_some() {
+                            self.handle_transfer_metadata(&p.content);
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == crate::file_drop::PLUGIN_ID => {
+                        self.handle_file_drop(&p.content).await;
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::file_stream::PLUGIN_ID =>
+                    {
+                        self.handle_file_stream(&p.content).await;
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::transfer_broker::PLUGIN_ID =>
+                    {
+                        self.handle_transfer_broker(&p.content).await;
+                    }
+                    Some(misc::Union::PluginRequest(p))
+                        if p.id == crate::file_compression::PLUGIN_ID =>
+                    {
+                        if self.file_transfer.is_some() || self.file_transfer_enabled() {
+                            self.file_compressor.enable();
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == crate::file_ops::PLUGIN_ID => {
+                        if self.file_transfer.is_some() {
+                            self.handle_file_ops(&p.content).await;
+                        }
+                    }
+                    Some(misc::Union::PluginRequest(p)) if p.id == crate::lan::WOL_PLUGIN_ID => {
+                        if self.authorized && crate::lan::is_wol_relay_allowed() {
+                            crate::lan::handle_wol_request(&p.content);
+                        }
+                    }
                     #[cfg(all(feature = "flutter", feature = "plugin_framework"))]
                     #[cfg(not(any(target_os = "android", target_os = "ios")))]
                     Some(misc::Union::PluginRequest(p)) => {
@@ -3009,6 +4056,23 @@ impl Connection {
                                 });
                                 return false;
                             }
+                            // The server is restarted in the selected session, which would close
+                            // the other connections.
+                            if current_process_sid != sid
+                                && raii::AuthedConnID::non_port_forward_conn_count() > 1
+                                && sessions.iter().any(|e| e.sid == sid)
+                            {
+                                let mut msg_out = Message::new();
+                                msg_out.set_message_box(MessageBox {
+                                    msgtype: "custom-nook-nocancel-hasclose".to_owned(),
+                                    title: "Switch Windows session".to_owned(),
+                                    text: "switch_windows_session_other_connections_tip".to_owned(),
+                                    link: "".to_owned(),
+                                    ..Default::default()
+                                });
+                                self.send(msg_out).await;
+                                return true;
+                            }
                             if self.file_transfer.is_some() {
                                 if let Some((dir, show_hidden)) = self.delayed_read_dir.take() {
                                     self.read_dir(&dir, show_hidden);
@@ -3032,7 +4096,7 @@ impl Connection {
                     _ => {}
                 },
                 Some(message::Union::AudioFrame(frame)) => {
-                    if !self.disable_audio {
+                    if !self.disable_audio && (self.voice_calling || self.mic_passthrough) {
                         if let Some(sender) = &self.audio_sender {
                             allow_err!(sender.send(MediaData::AudioFrame(Box::new(frame))));
                         } else {
@@ -3058,14 +4122,7 @@ impl Connection {
                     // TODO: Maybe we can do a voice call from cm directly.
                 }
                 Some(message::Union::ScreenshotRequest(request)) => {
-                    if let Some(tx) = self.inner.tx.clone() {
-                        crate::video_service::set_take_screenshot(
-                            request.display as _,
-                            request.sid.clone(),
-                            tx,
-                        );
-                        self.refresh_video_display(Some(request.display as usize));
-                    }
+                    self.take_screenshot(request);
                 }
                 Some(message::Union::TerminalAction(action)) => {
                     #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -3217,6 +4274,7 @@ impl Connection {
         }
         let map_mutex = &LOGIN_FAILURES[i];
         if remove {
+            lockout::on_success(&self.ip, &self.lr.my_id);
             if failure.0 != 0 {
                 if let Some((p64, p56, p48)) = self.get_ipv6_prefixes() {
                     let mut m = map_mutex.lock().unwrap();
@@ -3230,6 +4288,12 @@ impl Connection {
             }
             return;
         }
+        lockout::on_failure(&self.ip, &self.lr.my_id);
+        metrics::on_auth_failed(i);
+        self.audit(
+            "login-failure",
+            json!({"name": self.lr.my_name, "factor": if i == 0 { "password" } else { "second" }}),
+        );
         // Bump the prefixes, fetching existing values
         if let Some((p64, p56, p48)) = self.get_ipv6_prefixes() {
             let mut m = map_mutex.lock().unwrap();
@@ -3284,6 +4348,18 @@ impl Connection {
     async fn check_failure(&mut self, i: usize) -> (((i32, i32, i32), i32), bool) {
         let time = (get_time() / 60_000) as i32;
 
+        // Persistent across restarts, unlike the counters below.
+        if let Some(remaining) = lockout::locked(&self.ip, &self.lr.my_id) {
+            log::warn!(
+                "Login from {} ({}) rejected, locked out for {} more seconds",
+                self.ip,
+                self.lr.my_id,
+                remaining / 1000
+            );
+            self.send_login_error("Too many wrong attempts").await;
+            return (((0, 0, 0), time), false);
+        }
+
         // IPv6 addresses are cheap to make so we check prefix/netblock as well
         if let Some((p64, p56, p48)) = self.get_ipv6_prefixes() {
             if let Some(res) = self.check_failure_ipv6_prefix(i, time, &p64, 64, 60).await {
@@ -3344,6 +4420,32 @@ impl Connection {
         });
     }
 
+    fn take_screenshot(&self, request: ScreenshotRequest) {
+        // Only the connections which can watch the screen.
+        if self.file_transfer.is_some() || self.port_forward_socket.is_some() || self.terminal {
+            return;
+        }
+        let Some(tx) = self.inner.tx.clone() else {
+            return;
+        };
+        let display = request.display as usize;
+        let service_name = video_service::get_service_name(self.video_source(), display);
+        let is_streaming = self
+            .server
+            .upgrade()
+            .map(|s| s.read().unwrap().is_subed(&service_name, self.inner.id()))
+            .unwrap_or(false);
+        // Wayland only supports one capturer, take it from the video stream.
+        #[cfg(target_os = "linux")]
+        let is_streaming = is_streaming || !crate::platform::linux::is_x11();
+        if is_streaming {
+            video_service::set_take_screenshot(display, request.sid, tx);
+            self.refresh_video_display(Some(display));
+        } else {
+            video_service::take_screenshot_once(self.video_source(), display, request.sid, tx);
+        }
+    }
+
     async fn handle_switch_display(&mut self, s: SwitchDisplay) {
         let display_idx = s.display as usize;
         if self.display_idx != display_idx {
@@ -3462,7 +4564,7 @@ impl Connection {
         }
     }
 
-    #[cfg(windows)]
+    #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
     async fn toggle_virtual_display(&mut self, t: ToggleVirtualDisplay) {
         let make_msg = |text: String| {
             let mut msg_out = Message::new();
@@ -3478,12 +4580,24 @@ impl Connection {
         };
 
         if t.on {
-            if !virtual_display_manager::is_virtual_display_supported() {
-                self.send(make_msg("idd_not_support_under_win10_2004_tip".to_string()))
-                    .await;
+            #[cfg(windows)]
+            let (supported, not_supported_tip) = (
+                virtual_display_manager::is_virtual_display_supported(),
+                "idd_not_support_under_win10_2004_tip",
+            );
+            #[cfg(not(windows))]
+            let (supported, not_supported_tip) = (
+                crate::platform::virtual_display::is_supported(),
+                "virtual_display_not_supported_tip",
+            );
+            if !supported {
+                self.send(make_msg(not_supported_tip.to_string())).await;
             } else {
-                if let Err(e) = virtual_display_manager::plug_in_monitor(t.display as _, Vec::new())
-                {
+                #[cfg(windows)]
+                let res = virtual_display_manager::plug_in_monitor(t.display as _, Vec::new());
+                #[cfg(not(windows))]
+                let res = crate::platform::virtual_display::plug_in_monitor(t.display as _);
+                if let Err(e) = res {
                     log::error!("Failed to plug in virtual display: {}", e);
                     self.send(make_msg(format!(
                         "Failed to plug in virtual display: {}",
@@ -3493,7 +4607,11 @@ impl Connection {
                 }
             }
         } else {
-            if let Err(e) = virtual_display_manager::plug_out_monitor(t.display, false, true) {
+            #[cfg(windows)]
+            let res = virtual_display_manager::plug_out_monitor(t.display, false, true);
+            #[cfg(not(windows))]
+            let res = crate::platform::virtual_display::plug_out_monitor(t.display);
+            if let Err(e) = res {
                 log::error!("Failed to plug out virtual display {}: {}", t.display, e);
                 self.send(make_msg(format!(
                     "Failed to plug out virtual displays: {}",
@@ -3504,6 +4622,49 @@ impl Connection {
         }
     }
 
+    #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
+    async fn plug_in_virtual_display_mode(&mut self, content: &[u8]) {
+        let mode = match serde_json::from_slice::<crate::client::VirtualDisplayMode>(content) {
+            Ok(mode) if mode.is_valid() => mode,
+            Ok(mode) => {
+                log::error!("Invalid virtual display mode: {:?}", mode);
+                return;
+            }
+            Err(e) => {
+                log::error!("Invalid virtual display mode request: {}", e);
+                return;
+            }
+        };
+        #[cfg(windows)]
+        let res = virtual_display_manager::plug_in_monitor_mode(
+            mode.index,
+            virtual_display::MonitorMode {
+                width: mode.width,
+                height: mode.height,
+                sync: mode.refresh,
+            },
+        );
+        #[cfg(not(windows))]
+        let res = crate::platform::virtual_display::plug_in_monitor_mode(
+            mode.index,
+            mode.width,
+            mode.height,
+            mode.refresh,
+        );
+        if let Err(e) = res {
+            log::error!("Failed to plug in virtual display {:?}: {}", mode, e);
+            let mut msg_out = Message::new();
+            msg_out.set_message_box(MessageBox {
+                msgtype: "nook-nocancel-hasclose".to_owned(),
+                title: "Virtual display".to_owned(),
+                text: format!("Failed to plug in virtual display: {}", e),
+                link: "".to_owned(),
+                ..Default::default()
+            });
+            self.send(msg_out).await;
+        }
+    }
+
     async fn toggle_privacy_mode(&mut self, t: TogglePrivacyMode) {
         if t.on {
             self.turn_on_privacy(t.impl_key).await;
@@ -3514,6 +4675,11 @@ impl Connection {
 
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     fn change_resolution(&mut self, d: Option<usize>, r: &Resolution) {
+        // The resolution of the VM console is changed by the guest.
+        #[cfg(target_os = "linux")]
+        if super::vm_console::is_enabled() {
+            return;
+        }
         if self.keyboard {
             if let Ok(displays) = display_service::try_get_displays() {
                 let display_idx = d.unwrap_or(self.display_idx);
@@ -3529,6 +4695,16 @@ impl Connection {
                     {
                         return;
                     }
+                    #[cfg(any(target_os = "linux", target_os = "macos"))]
+                    if let Some(_ok) =
+                        crate::platform::virtual_display::change_resolution_if_is_virtual_display(
+                            &name,
+                            r.width as _,
+                            r.height as _,
+                        )
+                    {
+                        return;
+                    }
                     #[allow(unused_mut)]
                     let mut record_changed = true;
                     #[cfg(windows)]
@@ -3594,6 +4770,40 @@ impl Connection {
         }
     }
 
+    // Lists the audio devices to the peer, or captures the one selected by the peer.
+    async fn handle_audio_device_request(&mut self, content: &[u8]) {
+        if !self.authorized || !self.audio {
+            return;
+        }
+        if !content.is_empty() {
+            match serde_json::from_slice::<crate::client::AudioDevices>(content) {
+                Ok(req) => {
+                    let device = (!req.current.is_empty()).then_some(req.current);
+                    log::info!("Peer selects the audio device: {:?}", device);
+                    crate::audio_service::set_peer_audio_device(device);
+                    self.audio_device_selected = true;
+                }
+                Err(e) => {
+                    log::error!("Invalid audio device request: {}", e);
+                    return;
+                }
+            }
+        }
+        let devices = crate::client::AudioDevices {
+            devices: crate::audio_service::get_audio_devices(),
+            current: crate::audio_service::get_peer_audio_device().unwrap_or_default(),
+        };
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: crate::client::AUDIO_DEVICE_PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(&devices).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        self.send(msg).await;
+    }
+
     pub async fn close_voice_call(&mut self) {
         crate::audio_service::set_voice_call_input_device(None, true);
         // Notify the connection manager that the voice call has been closed.
@@ -3777,13 +4987,10 @@ impl Connection {
         }
         if let Ok(q) = o.block_input.enum_value() {
             if self.keyboard && self.block_input {
+                #[cfg(not(any(target_os = "android", target_os = "ios")))]
                 match q {
-                    BoolOption::Yes => {
-                        self.tx_input.send(MessageInput::BlockOn).ok();
-                    }
-                    BoolOption::No => {
-                        self.tx_input.send(MessageInput::BlockOff).ok();
-                    }
+                    BoolOption::Yes => self.set_block_input(true).await,
+                    BoolOption::No => self.set_block_input(false).await,
                     _ => {}
                 }
             } else {
@@ -3982,6 +5189,9 @@ impl Connection {
         // We can add a (Vec<conn_id>, input device) to avoid this.
         // But it's not necessary now and we have to consider two audio services(client, server).
         crate::audio_service::set_voice_call_input_device(None, true);
+        if self.audio_device_selected {
+            crate::audio_service::set_peer_audio_device(None);
+        }
         log::info!("#{} Connection closed: {}", self.inner.id(), reason);
         if lock && self.lock_after_session_end && self.keyboard {
             #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -4001,6 +5211,9 @@ impl Connection {
 
     // The `reason` should be consistent with `check_if_retry` if not empty
     async fn send_close_reason_no_retry(&mut self, reason: &str) {
+        // Closed on purpose, the input block does not wait for the reconnection.
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        super::block_input::release(self.inner.id());
         let mut misc = Misc::new();
         if reason.is_empty() {
             misc.set_close_reason("Closed manually by the peer".to_string());
@@ -4031,6 +5244,7 @@ impl Connection {
 
     #[inline]
     async fn send(&mut self, msg: Message) {
+        metrics::on_sent(msg.compute_size() as _);
         allow_err!(self.stream.send(&msg).await);
     }
 
@@ -4058,7 +5272,13 @@ impl Connection {
                 msg.set_misc(misc);
                 self.inner.send(msg.into());
             }
-            let uac = crate::video_service::IS_UAC_RUNNING.lock().unwrap().clone();
+            // The lock screen and the Ctrl+Alt+Del screen are on the secure desktop too,
+            // which needs the elevation as the UAC prompt.
+            let uac = crate::video_service::IS_UAC_RUNNING.lock().unwrap().clone()
+                || crate::video_service::IS_SECURE_DESKTOP
+                    .lock()
+                    .unwrap()
+                    .clone();
             if p.last_uac != uac {
                 p.last_uac = uac;
                 if !uac || !running {
@@ -4094,6 +5314,15 @@ impl Connection {
         self.pressed_modifiers.clear();
     }
 
+    // The limit of the controlled side is shared by its connections.
+    fn throttle_options() -> crate::transfer_throttle::Options {
+        crate::transfer_throttle::Options::new(
+            "",
+            &Config::get_option(crate::transfer_throttle::OPTION_RATE_LIMIT),
+            &Config::get_option(crate::transfer_throttle::OPTION_PAUSE_FOR_VIDEO),
+        )
+    }
+
     fn get_auto_disconenct_timer() -> Option<(Instant, u64)> {
         if Config::get_option("allow-auto-disconnect") == "Y" {
             let mut minute: u64 = Config::get_option("auto-disconnect-timeout")
@@ -4112,6 +5341,60 @@ impl Connection {
         self.auto_disconnect_timer
             .as_mut()
             .map(|t| t.0 = Instant::now());
+        self.auto_disconnect_warned = false;
+    }
+
+    fn get_max_duration_timer() -> Option<(Instant, u64)> {
+        if Config::get_option("allow-max-session-duration") == "Y" {
+            let mut minute: u64 = Config::get_option("max-session-duration")
+                .parse()
+                .unwrap_or(60);
+            if minute == 0 {
+                minute = 60;
+            }
+            Some((Instant::now(), minute))
+        } else {
+            None
+        }
+    }
+
+    // Warn one minute before, unless the timeout is too short for that to be useful.
+    fn should_warn_timeout(instant: &Instant, minute: u64) -> bool {
+        minute > 2 && instant.elapsed().as_secs() + 60 >= minute * 60
+    }
+
+    async fn send_timeout_warning(&mut self, text: &str) {
+        let mut msg_out = Message::new();
+        msg_out.set_message_box(MessageBox {
+            msgtype: "custom-nook-nocancel-hasclose".to_owned(),
+            title: "Session timeout".to_owned(),
+            text: text.to_owned(),
+            link: "".to_owned(),
+            ..Default::default()
+        });
+        self.send(msg_out).await;
+    }
+
+    // The initial state is sent only if it is not the default one.
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    async fn check_power_state(&mut self) {
+        if !self.is_authed_remote_conn() {
+            return;
+        }
+        let state = super::power_state::get();
+        if state == self.last_power_state {
+            return;
+        }
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: crate::client::POWER_STATE_PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(&state).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        self.send(msg).await;
+        self.last_power_state = state;
     }
 
     #[cfg(feature = "hwcodec")]
@@ -4652,6 +5935,35 @@ fn start_wakelock_thread() -> std::sync::mpsc::Sender<(usize, usize)> {
     tx
 }
 
+/// The connection id, type, peer id and peer name of the authorized connections.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn get_authed_conns() -> Vec<(i32, AuthConnType, String, String)> {
+    AUTHED_CONNS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            (
+                c.conn_id,
+                c.conn_type,
+                c.session_key.peer_id.clone(),
+                c.session_key.name.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Closes the authorized connection as the connection manager does, false if not found.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn close_authed_conn(conn_id: i32) -> bool {
+    AUTHED_CONNS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|c| c.conn_id == conn_id)
+        .map_or(false, |c| c.sender.send(ipc::Data::Close).is_ok())
+}
+
 #[cfg(all(target_os = "windows", feature = "flutter"))]
 pub fn on_printer_data(data: Vec<u8>) {
     crate::server::AUTHED_CONNS
@@ -4778,6 +6090,10 @@ impl Retina {
             // x and y are always 0, +1 or -1
             return;
         }
+        if evt_type == crate::input::MOUSE_TYPE_MOVE_RELATIVE {
+            // x and y are deltas, not positions on the display
+            return;
+        }
         let Some(d) = self.displays.get(current) else {
             return;
         };
@@ -5001,6 +6317,8 @@ mod raii {
                 display_service::restore_resolutions();
                 #[cfg(windows)]
                 let _ = virtual_display_manager::reset_all();
+                #[cfg(any(target_os = "linux", target_os = "macos"))]
+                let _ = crate::platform::virtual_display::reset_all();
                 #[cfg(target_os = "linux")]
                 scrap::wayland::pipewire::try_close_session();
             }
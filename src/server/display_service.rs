@@ -96,7 +96,7 @@ pub(super) fn check_display_changed(
     #[cfg(target_os = "linux")]
     {
         // wayland do not support changing display for now
-        if !is_x11() {
+        if !is_x11() && !super::vm_console::is_enabled() {
             return None;
         }
     }
@@ -188,6 +188,11 @@ fn displays_to_msg(displays: Vec<DisplayInfo>) -> Message {
         let m = crate::virtual_display_manager::get_platform_additions();
         pi.platform_additions = serde_json::to_string(&m).unwrap_or_default();
     }
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    if crate::platform::is_installed() {
+        let m = crate::platform::virtual_display::get_platform_additions();
+        pi.platform_additions = serde_json::to_string(&m).unwrap_or_default();
+    }
 
     // current_display should not be used in server.
     // It is set to 0 for compatibility with old clients.
@@ -200,6 +205,10 @@ fn displays_to_msg(displays: Vec<DisplayInfo>) -> Message {
 fn check_get_displays_changed_msg() -> Option<Message> {
     #[cfg(target_os = "linux")]
     {
+        if super::vm_console::is_enabled() {
+            check_update_vm_console_displays().ok()?;
+            return get_displays_msg();
+        }
         if !is_x11() {
             return get_displays_msg();
         }
@@ -211,6 +220,9 @@ fn check_get_displays_changed_msg() -> Option<Message> {
 pub fn check_displays_changed() -> ResultType<()> {
     #[cfg(target_os = "linux")]
     {
+        if super::vm_console::is_enabled() {
+            return check_update_vm_console_displays();
+        }
         // Currently, wayland need to call wayland::clear() before call Display::all(), otherwise it will cause
         // block, or even crash here, https://github.com/rustdesk/rustdesk/blob/0bb4d43e9ea9d9dfb9c46c8d27d1a97cd0ad6bea/libs/scrap/src/wayland/pipewire.rs#L235
         if !is_x11() {
@@ -257,7 +269,10 @@ pub(super) fn get_original_resolution(
     #[cfg(windows)]
     let is_rustdesk_virtual_display =
         crate::virtual_display_manager::rustdesk_idd::is_virtual_display(&display_name);
-    #[cfg(not(windows))]
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    let is_rustdesk_virtual_display =
+        crate::platform::virtual_display::is_virtual_display(&display_name);
+    #[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
     let is_rustdesk_virtual_display = false;
     Some(if is_rustdesk_virtual_display {
         Resolution {
@@ -337,6 +352,14 @@ pub(super) fn check_update_displays(all: &Vec<Display>) {
     SYNC_DISPLAYS.lock().unwrap().check_changed(displays);
 }
 
+// The displays of this device are replaced by the console of the VM.
+#[cfg(target_os = "linux")]
+fn check_update_vm_console_displays() -> ResultType<()> {
+    let displays = super::vm_console::get_displays()?;
+    SYNC_DISPLAYS.lock().unwrap().check_changed(displays);
+    Ok(())
+}
+
 pub fn is_inited_msg() -> Option<Message> {
     #[cfg(target_os = "linux")]
     if !is_x11() {
@@ -348,6 +371,10 @@ pub fn is_inited_msg() -> Option<Message> {
 pub async fn update_get_sync_displays_on_login() -> ResultType<Vec<DisplayInfo>> {
     #[cfg(target_os = "linux")]
     {
+        if super::vm_console::is_enabled() {
+            check_update_vm_console_displays()?;
+            return Ok(SYNC_DISPLAYS.lock().unwrap().displays.clone());
+        }
         if !is_x11() {
             return super::wayland::get_displays().await;
         }
@@ -364,6 +391,9 @@ pub async fn update_get_sync_displays_on_login() -> ResultType<Vec<DisplayInfo>>
 pub fn get_primary() -> usize {
     #[cfg(target_os = "linux")]
     {
+        if super::vm_console::is_enabled() {
+            return 0;
+        }
         if !is_x11() {
             return match super::wayland::get_primary() {
                 Ok(n) => n,
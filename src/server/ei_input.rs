@@ -0,0 +1,357 @@
+// Input injection through libei, the RemoteDesktop portal hands out a socket to the EIS
+// (emulated input server) of the compositor with `ConnectToEIS`, since portal version 2.
+//
+// The events are sent in batches (frames) on the socket instead of one D-Bus call per event,
+// which is what `rdp_input` does. The context is owned by a thread, which also handles the
+// devices added, resumed and paused by the compositor.
+use super::rdp_input::client::get_raw_evdev_keycode;
+use crate::uinput::service::map_key;
+use dbus::{blocking::SyncConnection, Path};
+use enigo::{Key, KeyboardControllable, MouseButton, MouseControllable};
+use hbb_common::{bail, log, ResultType};
+use reis::{
+    ei,
+    event::{DeviceCapability, EiEvent},
+    PendingRequestResult,
+};
+use scrap::wayland::pipewire::get_portal;
+use scrap::wayland::remote_desktop_portal::OrgFreedesktopPortalRemoteDesktop as remote_desktop_portal;
+use std::{
+    collections::HashMap,
+    os::unix::net::UnixStream,
+    sync::{
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc,
+    },
+    time::Duration,
+};
+
+pub mod client {
+    use super::*;
+
+    const EVDEV_MOUSE_LEFT: u32 = 272;
+    const EVDEV_MOUSE_RIGHT: u32 = 273;
+    const EVDEV_MOUSE_MIDDLE: u32 = 274;
+
+    // ConnectToEIS is available since version 2 of the RemoteDesktop portal.
+    const MIN_PORTAL_VERSION: u32 = 2;
+    const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+    enum Command {
+        Key(u32, bool),
+        Button(u32, bool),
+        MoveTo(i32, i32),
+        MoveRelative(i32, i32),
+        Scroll(i32, i32),
+    }
+
+    pub struct EiKeyboard {
+        tx: Sender<Command>,
+    }
+
+    pub struct EiMouse {
+        tx: Sender<Command>,
+    }
+
+    /// Connects to the EIS of the RemoteDesktop session.
+    ///
+    /// `resolution` is the size of the captured screen, the coordinates of `mouse_move_to()`
+    /// are mapped from it to the region of the absolute pointer.
+    pub fn connect(
+        conn: Arc<SyncConnection>,
+        session: Path<'static>,
+        resolution: (usize, usize),
+    ) -> ResultType<(EiKeyboard, EiMouse)> {
+        let portal = get_portal(&conn);
+        let version = remote_desktop_portal::version(&portal)?;
+        if version < MIN_PORTAL_VERSION {
+            bail!(
+                "RemoteDesktop portal version {} has no ConnectToEIS",
+                version
+            );
+        }
+        let fd = remote_desktop_portal::connect_to_eis(&portal, &session, HashMap::new())?;
+        let stream = UnixStream::from(std::os::fd::OwnedFd::from(fd.into_fd()));
+        let context = ei::Context::new(stream)?;
+        let resp = reis::handshake::ei_handshake_blocking(
+            &context,
+            "rustdesk",
+            ei::handshake::ContextType::Sender,
+        )?;
+        context.connection_socket().set_nonblocking(true)?;
+        let (tx, rx) = channel::<Command>();
+        std::thread::spawn(move || {
+            let converter = reis::event::EiEventConverter::new(&context, resp);
+            let mut emulator = Emulator {
+                context,
+                converter,
+                devices: Vec::new(),
+                resolution,
+            };
+            loop {
+                if let Err(e) = emulator.dispatch() {
+                    log::error!("libei: {}", e);
+                    break;
+                }
+                match rx.recv_timeout(POLL_INTERVAL) {
+                    Ok(cmd) => emulator.handle(cmd),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            log::info!("libei input stopped");
+        });
+        Ok((EiKeyboard { tx: tx.clone() }, EiMouse { tx }))
+    }
+
+    struct Emulator {
+        context: ei::Context,
+        converter: reis::event::EiEventConverter,
+        // The devices resumed, with the serial to emulate on them.
+        devices: Vec<(reis::event::Device, u32)>,
+        resolution: (usize, usize),
+    }
+
+    impl Emulator {
+        fn dispatch(&mut self) -> ResultType<()> {
+            match self.context.read() {
+                Ok(0) => bail!("EIS disconnected"),
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+            while let Some(result) = self.context.pending_event() {
+                match result {
+                    PendingRequestResult::Request(event) => {
+                        if let Err(e) = self.converter.handle_event(event) {
+                            bail!("{}", e);
+                        }
+                    }
+                    PendingRequestResult::ParseError(e) => bail!("{}", e),
+                    PendingRequestResult::InvalidObject(_) => {}
+                }
+            }
+            while let Some(event) = self.converter.next_event() {
+                match event {
+                    EiEvent::SeatAdded(evt) => {
+                        evt.seat.bind_capabilities(&[
+                            DeviceCapability::Pointer,
+                            DeviceCapability::PointerAbsolute,
+                            DeviceCapability::Button,
+                            DeviceCapability::Scroll,
+                            DeviceCapability::Keyboard,
+                        ]);
+                    }
+                    EiEvent::DeviceResumed(evt) => {
+                        evt.device
+                            .device()
+                            .start_emulating(evt.serial, self.converter.connection().serial());
+                        self.devices.retain(|(d, _)| d != &evt.device);
+                        self.devices.push((evt.device, evt.serial));
+                    }
+                    EiEvent::DevicePaused(evt) => {
+                        self.devices.retain(|(d, _)| d != &evt.device);
+                    }
+                    EiEvent::DeviceRemoved(evt) => {
+                        self.devices.retain(|(d, _)| d != &evt.device);
+                    }
+                    EiEvent::Disconnected(evt) => {
+                        bail!("EIS disconnected: {:?}", evt.reason);
+                    }
+                    _ => {}
+                }
+            }
+            self.context.flush()?;
+            Ok(())
+        }
+
+        fn device(&self, capability: DeviceCapability) -> Option<&(reis::event::Device, u32)> {
+            self.devices
+                .iter()
+                .find(|(d, _)| d.has_capability(capability))
+        }
+
+        fn handle(&mut self, cmd: Command) {
+            let capability = match cmd {
+                Command::Key(..) => DeviceCapability::Keyboard,
+                Command::Button(..) => DeviceCapability::Button,
+                Command::MoveTo(..) => DeviceCapability::PointerAbsolute,
+                Command::MoveRelative(..) => DeviceCapability::Pointer,
+                Command::Scroll(..) => DeviceCapability::Scroll,
+            };
+            let Some((device, serial)) = self.device(capability) else {
+                log::debug!("libei: no device for {:?}", capability);
+                return;
+            };
+            match cmd {
+                Command::Key(key, down) => {
+                    if let Some(keyboard) = device.interface::<ei::Keyboard>() {
+                        let state = if down {
+                            ei::keyboard::KeyState::Press
+                        } else {
+                            ei::keyboard::KeyState::Released
+                        };
+                        keyboard.key(key, state);
+                    }
+                }
+                Command::Button(button, down) => {
+                    if let Some(b) = device.interface::<ei::Button>() {
+                        let state = if down {
+                            ei::button::ButtonState::Press
+                        } else {
+                            ei::button::ButtonState::Released
+                        };
+                        b.button(button, state);
+                    }
+                }
+                Command::MoveTo(x, y) => {
+                    if let Some(pointer) = device.interface::<ei::PointerAbsolute>() {
+                        let (x, y) = map_to_region(device, self.resolution, x, y);
+                        pointer.motion_absolute(x, y);
+                    }
+                }
+                Command::MoveRelative(x, y) => {
+                    if let Some(pointer) = device.interface::<ei::Pointer>() {
+                        pointer.motion_relative(x as f32, y as f32);
+                    }
+                }
+                Command::Scroll(x, y) => {
+                    if let Some(scroll) = device.interface::<ei::Scroll>() {
+                        // 120 is one detent of the wheel.
+                        scroll.scroll_discrete(x * 120, y * 120);
+                    }
+                }
+            }
+            device.device().frame(*serial, now_us());
+            if let Err(e) = self.context.flush() {
+                log::error!("libei flush: {}", e);
+            }
+        }
+    }
+
+    // The regions are in the logical coordinate space of the compositor, the first one is the
+    // screen captured, as the RemoteDesktop session has one stream.
+    fn map_to_region(
+        device: &reis::event::Device,
+        resolution: (usize, usize),
+        x: i32,
+        y: i32,
+    ) -> (f32, f32) {
+        let Some(region) = device.regions().first() else {
+            return (x as f32, y as f32);
+        };
+        if resolution.0 == 0 || resolution.1 == 0 {
+            return (
+                region.offset_x as f32 + x as f32,
+                region.offset_y as f32 + y as f32,
+            );
+        }
+        (
+            region.offset_x as f32 + x as f32 * region.width as f32 / resolution.0 as f32,
+            region.offset_y as f32 + y as f32 * region.height as f32 / resolution.1 as f32,
+        )
+    }
+
+    fn now_us() -> u64 {
+        nix::time::clock_gettime(nix::time::ClockId::CLOCK_MONOTONIC)
+            .map(|t| t.tv_sec() as u64 * 1_000_000 + t.tv_nsec() as u64 / 1_000)
+            .unwrap_or_default()
+    }
+
+    fn send_key(tx: &Sender<Command>, down: bool, key: Key) {
+        match key {
+            Key::Raw(key) => {
+                let _ = tx.send(Command::Key(get_raw_evdev_keycode(key) as _, down));
+            }
+            _ => {
+                if let Ok((key, is_shift)) = map_key(&key) {
+                    if is_shift {
+                        let _ = tx.send(Command::Key(evdev::Key::KEY_LEFTSHIFT.code() as _, down));
+                    }
+                    let _ = tx.send(Command::Key(key.code() as _, down));
+                }
+            }
+        }
+    }
+
+    fn send_button(tx: &Sender<Command>, down: bool, button: MouseButton) {
+        let button = match button {
+            MouseButton::Left => EVDEV_MOUSE_LEFT,
+            MouseButton::Right => EVDEV_MOUSE_RIGHT,
+            MouseButton::Middle => EVDEV_MOUSE_MIDDLE,
+            _ => {
+                return;
+            }
+        };
+        let _ = tx.send(Command::Button(button, down));
+    }
+
+    impl KeyboardControllable for EiKeyboard {
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn as_mut_any(&mut self) -> &mut dyn std::any::Any {
+            self
+        }
+
+        fn get_key_state(&mut self, _: Key) -> bool {
+            // no api for this
+            false
+        }
+
+        fn key_sequence(&mut self, s: &str) {
+            for c in s.chars() {
+                let key = Key::Layout(c);
+                send_key(&self.tx, true, key);
+                send_key(&self.tx, false, key);
+            }
+        }
+
+        fn key_down(&mut self, key: Key) -> enigo::ResultType {
+            send_key(&self.tx, true, key);
+            Ok(())
+        }
+        fn key_up(&mut self, key: Key) {
+            send_key(&self.tx, false, key);
+        }
+        fn key_click(&mut self, key: Key) {
+            send_key(&self.tx, true, key);
+            send_key(&self.tx, false, key);
+        }
+    }
+
+    impl MouseControllable for EiMouse {
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn as_mut_any(&mut self) -> &mut dyn std::any::Any {
+            self
+        }
+
+        fn mouse_move_to(&mut self, x: i32, y: i32) {
+            let _ = self.tx.send(Command::MoveTo(x, y));
+        }
+        fn mouse_move_relative(&mut self, x: i32, y: i32) {
+            let _ = self.tx.send(Command::MoveRelative(x, y));
+        }
+        fn mouse_down(&mut self, button: MouseButton) -> enigo::ResultType {
+            send_button(&self.tx, true, button);
+            Ok(())
+        }
+        fn mouse_up(&mut self, button: MouseButton) {
+            send_button(&self.tx, false, button);
+        }
+        fn mouse_click(&mut self, button: MouseButton) {
+            send_button(&self.tx, true, button);
+            send_button(&self.tx, false, button);
+        }
+        fn mouse_scroll_x(&mut self, length: i32) {
+            let _ = self.tx.send(Command::Scroll(length, 0));
+        }
+        fn mouse_scroll_y(&mut self, length: i32) {
+            let _ = self.tx.send(Command::Scroll(0, length));
+        }
+    }
+}
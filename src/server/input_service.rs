@@ -592,6 +592,27 @@ pub async fn setup_rdp_input() -> ResultType<(), Box<dyn std::error::Error>> {
     let rdp_info_lock = RDP_SESSION_INFO.lock()?;
     let rdp_info = rdp_info_lock.as_ref().ok_or("RDP session is None")?;
 
+    // Prefer libei, and fall back to the D-Bus calls of the portal if the compositor has no EIS.
+    let resolution = rdp_info
+        .resolution
+        .lock()
+        .unwrap()
+        .or(rdp_info.streams.first().map(|s| s.get_size()))
+        .unwrap_or_default();
+    match super::ei_input::client::connect(
+        rdp_info.conn.clone(),
+        rdp_info.session.clone(),
+        resolution,
+    ) {
+        Ok((keyboard, mouse)) => {
+            en.set_custom_keyboard(Box::new(keyboard));
+            en.set_custom_mouse(Box::new(mouse));
+            log::info!("libei keyboard and mouse created");
+            return Ok(());
+        }
+        Err(e) => log::info!("libei is not available, use RdpInput: {}", e),
+    }
+
     let keyboard = RdpInputKeyboard::new(rdp_info.conn.clone(), rdp_info.session.clone())?;
     en.set_custom_keyboard(Box::new(keyboard));
     log::info!("RdpInput keyboard created");
@@ -1007,6 +1028,15 @@ pub fn handle_mouse_(
 }
 
 pub fn handle_mouse_simulation_(evt: &MouseEvent, conn: i32) {
+    // The local cursor does not follow the input of the VM console.
+    #[cfg(target_os = "linux")]
+    if super::vm_console::is_enabled() {
+        if !EXITING.load(Ordering::SeqCst) {
+            super::vm_console::handle_mouse(evt);
+        }
+        return;
+    }
+
     if !active_mouse_(conn) {
         return;
     }
@@ -1054,6 +1084,9 @@ pub fn handle_mouse_simulation_(evt: &MouseEvent, conn: i32) {
                 y: evt.y,
             };
         }
+        MOUSE_TYPE_MOVE_RELATIVE => {
+            en.mouse_move_relative(evt.x, evt.y);
+        }
         MOUSE_TYPE_DOWN => match buttons {
             MOUSE_BUTTON_LEFT => {
                 allow_err!(en.mouse_down(MouseButton::Left));
@@ -1750,6 +1783,12 @@ pub fn handle_key_(evt: &KeyEvent) {
         return;
     }
 
+    #[cfg(target_os = "linux")]
+    if super::vm_console::is_enabled() {
+        super::vm_console::handle_key(evt);
+        return;
+    }
+
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     let mut _lock_mode_handler = None;
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -1814,14 +1853,37 @@ async fn lock_screen_2() {
 #[tokio::main(flavor = "current_thread")]
 async fn send_sas() -> ResultType<()> {
     if crate::platform::is_physical_console_session().unwrap_or(true) {
-        let mut stream = crate::ipc::connect(1000, crate::POSTFIX_SERVICE).await?;
-        timeout(1000, stream.send(&crate::ipc::Data::SAS)).await??;
-    } else {
-        crate::platform::send_sas();
-    };
+        match send_sas_by_service().await {
+            Ok(()) => return Ok(()),
+            // No service, e.g. the portable service running as system.
+            Err(e) if crate::platform::is_root() => {
+                log::warn!("Failed to send SAS by the service: {}, send it directly", e);
+            }
+            Err(e) => bail!("Failed to send SAS by the service: {}", e),
+        }
+    }
+    crate::platform::send_sas();
     Ok(())
 }
 
+// The service may be restarting the server, it is tried twice.
+#[cfg(windows)]
+async fn send_sas_by_service() -> ResultType<()> {
+    let mut res = Ok(());
+    for _ in 0..2 {
+        res = async {
+            let mut stream = crate::ipc::connect(1000, crate::POSTFIX_SERVICE).await?;
+            timeout(1000, stream.send(&crate::ipc::Data::SAS)).await??;
+            Ok::<(), hbb_common::anyhow::Error>(())
+        }
+        .await;
+        if res.is_ok() {
+            break;
+        }
+    }
+    res
+}
+
 #[inline]
 #[cfg(target_os = "linux")]
 pub fn wayland_use_uinput() -> bool {
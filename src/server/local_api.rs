@@ -0,0 +1,353 @@
+//! Local HTTP API of the service, for the monitoring agents and the admin scripts which
+//! should not speak the IPC protocol.
+//!
+//! Disabled by default. It listens on 127.0.0.1 only, on `local-api-port` (21120 by default),
+//! once `allow-local-api` is `Y` and `local-api-token` is set, e.g.
+//! `rustdesk --set-option allow-local-api=Y local-api-token=<token>`. Every request carries
+//! `Authorization: Bearer <token>`, the responses are JSON.
+//!
+//! ```text
+//! GET  /status                          id, version, number of connections
+//! GET  /metrics                         the Prometheus metrics, see `metrics.rs`
+//! GET  /connections                     the authorized connections
+//! POST /connections/<conn-id>/disconnect
+//! GET  /options/<name>                  {"value": "..."}
+//! PUT  /options/<name>                  {"value": "..."}, an empty value removes the option
+//! ```
+//!
+//! The secrets, e.g. the token itself or the hash of the admin password, cannot be accessed.
+
+use hbb_common::{
+    config::{self, Config, RENDEZVOUS_PORT},
+    log, sleep,
+    tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    },
+    ResultType,
+};
+use serde_json::{json, Value};
+
+pub const OPTION_ALLOW_LOCAL_API: &str = "allow-local-api";
+pub const OPTION_LOCAL_API_TOKEN: &str = "local-api-token";
+pub const OPTION_LOCAL_API_PORT: &str = "local-api-port";
+
+const SECRET_OPTIONS: &[&str] = &[
+    OPTION_LOCAL_API_TOKEN,
+    crate::settings_guard::OPTION_SETTINGS_ADMIN_PASSWORD,
+    crate::deploy_config::OPTION_PERMANENT_PASSWORD_HASH,
+    crate::server::webhook::OPTION_WEBHOOK_SECRET,
+    "2fa",
+];
+
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+const MAX_BODY_SIZE: usize = 64 * 1024;
+const READ_TIMEOUT: u64 = 5_000;
+
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    token: String,
+    content_length: usize,
+}
+
+fn get_port() -> u16 {
+    Config::get_option(OPTION_LOCAL_API_PORT)
+        .parse::<u16>()
+        .ok()
+        .filter(|p| *p > 0)
+        .unwrap_or((RENDEZVOUS_PORT + 4) as _)
+}
+
+fn is_enabled() -> bool {
+    config::option2bool(
+        OPTION_ALLOW_LOCAL_API,
+        &Config::get_option(OPTION_ALLOW_LOCAL_API),
+    ) && !Config::get_option(OPTION_LOCAL_API_TOKEN).is_empty()
+}
+
+/// Listens while enabled, rebinds if the port changes, called by the server process.
+pub async fn start() {
+    let mut listener = None;
+    let mut port = 0;
+    loop {
+        let enabled = is_enabled();
+        if enabled && listener.is_none() {
+            port = get_port();
+            match TcpListener::bind(("127.0.0.1", port)).await {
+                Ok(l) => {
+                    log::info!("Local API listening on: {:?}", l.local_addr());
+                    listener = Some(l);
+                }
+                Err(err) => {
+                    log::error!(
+                        "Failed to start local API on port: {}, error: {}",
+                        port,
+                        err
+                    );
+                    while is_enabled() && port == get_port() {
+                        sleep(1.).await;
+                    }
+                    continue;
+                }
+            }
+        }
+        if let Some(l) = listener.as_mut() {
+            if !enabled || port != get_port() {
+                log::info!("Exit local API listen");
+                listener = None;
+                continue;
+            }
+            if let Ok(Ok((stream, _))) = hbb_common::timeout(1000, l.accept()).await {
+                tokio::spawn(async move {
+                    if let Err(err) = handle(stream).await {
+                        log::debug!("Local API request failed: {}", err);
+                    }
+                });
+            }
+        } else {
+            sleep(1.).await;
+        }
+    }
+}
+
+// The request line and the headers we care about, None if malformed.
+fn parse_request(head: &str) -> Option<Request> {
+    let mut lines = head.split("\r\n");
+    let mut first = lines.next()?.split(' ');
+    let method = first.next()?.to_owned();
+    let path = first.next()?.to_owned();
+    if !first.next()?.starts_with("HTTP/1.") {
+        return None;
+    }
+    let mut token = String::new();
+    let mut content_length = 0;
+    for line in lines {
+        let Some((k, v)) = line.split_once(':') else {
+            continue;
+        };
+        let v = v.trim();
+        if k.eq_ignore_ascii_case("authorization") {
+            if let Some(t) = v.strip_prefix("Bearer ") {
+                token = t.trim().to_owned();
+            }
+        } else if k.eq_ignore_ascii_case("content-length") {
+            content_length = v.parse().ok()?;
+        }
+    }
+    Some(Request {
+        method,
+        path,
+        token,
+        content_length,
+    })
+}
+
+// Not to leak the length of the matching prefix by the time.
+fn token_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+async fn read_request(stream: &mut TcpStream) -> ResultType<Option<(Request, Vec<u8>)>> {
+    let mut buf = Vec::new();
+    let mut tmp = [0u8; 1024];
+    let head_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_HEAD_SIZE {
+            return Ok(None);
+        }
+        let n = hbb_common::timeout(READ_TIMEOUT, stream.read(&mut tmp)).await??;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&tmp[..n]);
+    };
+    let Some(req) = parse_request(&String::from_utf8_lossy(&buf[..head_end])) else {
+        return Ok(None);
+    };
+    if req.content_length > MAX_BODY_SIZE {
+        return Ok(None);
+    }
+    let mut body = buf.split_off(head_end + 4);
+    while body.len() < req.content_length {
+        let n = hbb_common::timeout(READ_TIMEOUT, stream.read(&mut tmp)).await??;
+        if n == 0 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&tmp[..n]);
+    }
+    body.truncate(req.content_length);
+    Ok(Some((req, body)))
+}
+
+async fn handle(mut stream: TcpStream) -> ResultType<()> {
+    let mut content_type = "application/json";
+    let (status, body) = match read_request(&mut stream).await? {
+        None => (400, json!({ "error": "Bad request" }).to_string()),
+        Some((req, body)) => {
+            let token = Config::get_option(OPTION_LOCAL_API_TOKEN);
+            if token.is_empty() || !token_eq(&req.token, &token) {
+                log::warn!(
+                    "Local API request with a wrong token: {} {}",
+                    req.method,
+                    req.path
+                );
+                (401, json!({ "error": "Unauthorized" }).to_string())
+            } else if req.method == "GET" && req.path.split('?').next() == Some("/metrics") {
+                content_type = "text/plain; version=0.0.4";
+                (200, super::metrics::render())
+            } else {
+                let (status, value) = route(&req, &body).await;
+                (status, value.to_string())
+            }
+        }
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    };
+    let resp = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(resp.as_bytes()).await?;
+    stream.shutdown().await.ok();
+    Ok(())
+}
+
+async fn route(req: &Request, body: &[u8]) -> (u16, Value) {
+    let path = req.path.split('?').next().unwrap_or_default();
+    let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    match (req.method.as_str(), parts.as_slice()) {
+        ("GET", ["status"]) => (
+            200,
+            json!({
+                "id": Config::get_id(),
+                "version": crate::VERSION,
+                "stopped": config::option2bool("stop-service", &Config::get_option("stop-service")),
+                "connections": super::get_authed_conns().len(),
+            }),
+        ),
+        ("GET", ["connections"]) => {
+            let conns: Vec<_> = super::get_authed_conns()
+                .into_iter()
+                .map(|(conn_id, conn_type, peer_id, name)| {
+                    json!({
+                        "conn_id": conn_id,
+                        "type": format!("{:?}", conn_type),
+                        "peer_id": peer_id,
+                        "name": name,
+                    })
+                })
+                .collect();
+            (200, json!(conns))
+        }
+        ("POST", ["connections", id, "disconnect"]) => {
+            match id.parse().ok().filter(|id| super::close_authed_conn(*id)) {
+                Some(id) => {
+                    log::info!("Connection {} closed by the local API", id);
+                    (200, json!({}))
+                }
+                None => (404, json!({ "error": "No such connection" })),
+            }
+        }
+        (_, ["options", name]) if SECRET_OPTIONS.contains(name) => {
+            (403, json!({ "error": "The option cannot be accessed" }))
+        }
+        ("GET", ["options", name]) => (200, json!({ "value": Config::get_option(name) })),
+        ("PUT", ["options", name]) => {
+            let Some(value) = serde_json::from_slice::<Value>(body)
+                .ok()
+                .and_then(|v| v.get("value")?.as_str().map(|s| s.to_owned()))
+            else {
+                return (400, json!({ "error": "Expected {\"value\": \"...\"}" }));
+            };
+            match set_option(name, value).await {
+                Ok(true) => (200, json!({})),
+                Ok(false) => (403, json!({ "error": "The settings are locked" })),
+                Err(err) => (500, json!({ "error": err.to_string() })),
+            }
+        }
+        _ => (404, json!({ "error": "Not found" })),
+    }
+}
+
+// By IPC as the UI does, so the service reacts to the change, e.g. restarts the rendezvous.
+async fn set_option(name: &str, value: String) -> ResultType<bool> {
+    let mut options = Config::get_options();
+    if value.is_empty() {
+        options.remove(name);
+    } else {
+        options.insert(name.to_owned(), value);
+    }
+    if !crate::settings_guard::allow_options(&options) {
+        return Ok(false);
+    }
+    let mut c = crate::ipc::connect(1000, "").await?;
+    c.send(&crate::ipc::Data::Options(Some(options))).await?;
+    c.next_timeout(1000).await.ok();
+    log::info!("Option {} set by the local API", name);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request(
+            "PUT /options/approve-mode HTTP/1.1\r\nHost: 127.0.0.1\r\nauthorization: Bearer abc \r\nContent-Length: 20",
+        )
+        .unwrap();
+        assert_eq!(
+            req,
+            Request {
+                method: "PUT".to_owned(),
+                path: "/options/approve-mode".to_owned(),
+                token: "abc".to_owned(),
+                content_length: 20,
+            }
+        );
+        assert_eq!(
+            parse_request("GET /status HTTP/1.0").map(|r| r.token),
+            Some("".to_owned())
+        );
+        assert!(parse_request("GET /status").is_none());
+        assert!(parse_request("GET /status HTTP/1.1\r\nContent-Length: x").is_none());
+        assert!(token_eq("abc", "abc"));
+        assert!(!token_eq("abc", "abd"));
+        assert!(!token_eq("abc", "ab"));
+    }
+
+    #[tokio::test]
+    async fn test_secret_options() {
+        for name in SECRET_OPTIONS {
+            for method in ["GET", "PUT"] {
+                let req = Request {
+                    method: method.to_owned(),
+                    path: format!("/options/{}", name),
+                    token: Default::default(),
+                    content_length: 0,
+                };
+                assert_eq!(route(&req, br#"{"value": "x"}"#).await.0, 403);
+            }
+        }
+    }
+}
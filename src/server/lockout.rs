@@ -0,0 +1,203 @@
+//! Persistent brute-force lockout.
+//!
+//! Failed attempts are counted per source, the IP address alone and the peer ID from
+//! that IP address, and stored on disk so that restarting the service does not reset
+//! them. Every `threshold` failures lock the source out, twice as long as the previous
+//! lockout, up to `MAX_LOCKOUT`. A source without failure for `FORGET_AFTER` is forgotten.
+//! The unlocks of the protected settings by the local users are counted the same way, as the
+//! single source `SETTINGS_UNLOCK_SOURCE`.
+
+use hbb_common::{
+    config::{self, Config},
+    get_time, log,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+const FILE_NAME: &str = "lockouts.toml";
+// Peer IDs are cheap to change, so the IP address gets a higher threshold of its own.
+const ID_THRESHOLD: u32 = 5;
+const IP_THRESHOLD: u32 = 20;
+const SETTINGS_UNLOCK_SOURCE: &str = "settings-unlock";
+const SETTINGS_UNLOCK_THRESHOLD: u32 = 5;
+const BASE_LOCKOUT: i64 = 60_000;
+const MAX_LOCKOUT: i64 = 24 * 3600_000;
+const FORGET_AFTER: i64 = 24 * 3600_000;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Record {
+    #[serde(default)]
+    pub failures: u32,
+    #[serde(default)]
+    pub lockouts: u32,
+    #[serde(default)]
+    pub locked_until: i64,
+    #[serde(default)]
+    pub last_failure: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Lockouts {
+    #[serde(default)]
+    sources: HashMap<String, Record>,
+}
+
+lazy_static::lazy_static! {
+    static ref LOCKOUTS: Mutex<Option<Lockouts>> = Default::default();
+}
+
+fn path() -> PathBuf {
+    Config::path(FILE_NAME)
+}
+
+fn with_lockouts<R>(f: impl FnOnce(&mut Lockouts) -> (R, bool)) -> R {
+    let mut lock = LOCKOUTS.lock().unwrap();
+    let lockouts = lock.get_or_insert_with(|| config::load_path(path()));
+    let now = get_time();
+    let len = lockouts.sources.len();
+    lockouts
+        .sources
+        .retain(|_, r| now - r.last_failure < FORGET_AFTER || r.locked_until > now);
+    let (res, changed) = f(lockouts);
+    if changed || len != lockouts.sources.len() {
+        if let Err(err) = config::store_path(path(), &*lockouts) {
+            log::error!("Failed to store {}: {}", FILE_NAME, err);
+        }
+    }
+    res
+}
+
+fn sources(ip: &str, id: &str) -> Vec<(String, u32)> {
+    let mut v = vec![(ip.to_owned(), IP_THRESHOLD)];
+    if !id.is_empty() {
+        v.push((format!("{}@{}", id, ip), ID_THRESHOLD));
+    }
+    v
+}
+
+fn lockout_duration(lockouts: u32) -> i64 {
+    BASE_LOCKOUT
+        .saturating_mul(1i64 << lockouts.saturating_sub(1).min(20))
+        .min(MAX_LOCKOUT)
+}
+
+fn bump(record: &mut Record, threshold: u32, now: i64) {
+    record.failures += 1;
+    record.last_failure = now;
+    if record.failures >= threshold {
+        record.failures = 0;
+        record.lockouts += 1;
+        record.locked_until = now + lockout_duration(record.lockouts);
+    }
+}
+
+fn locked_sources(sources: Vec<(String, u32)>) -> Option<i64> {
+    let now = get_time();
+    with_lockouts(|l| {
+        let remaining = sources
+            .iter()
+            .filter_map(|(s, _)| l.sources.get(s))
+            .map(|r| r.locked_until - now)
+            .filter(|x| *x > 0)
+            .max();
+        (remaining, false)
+    })
+}
+
+fn on_failure_sources(sources: Vec<(String, u32)>) {
+    let now = get_time();
+    with_lockouts(|l| {
+        for (source, threshold) in sources {
+            let record = l.sources.entry(source.clone()).or_default();
+            bump(record, threshold, now);
+            if record.locked_until > now && record.failures == 0 {
+                log::warn!(
+                    "{} locked out for {} seconds",
+                    source,
+                    (record.locked_until - now) / 1000
+                );
+            }
+        }
+        ((), true)
+    })
+}
+
+fn on_success_sources(sources: Vec<(String, u32)>) {
+    with_lockouts(|l| {
+        let mut changed = false;
+        for (source, _) in sources {
+            changed |= l.sources.remove(&source).is_some();
+        }
+        ((), changed)
+    })
+}
+
+/// The remaining lockout in milliseconds, `None` if the source is not locked out.
+pub fn locked(ip: &str, id: &str) -> Option<i64> {
+    locked_sources(sources(ip, id))
+}
+
+pub fn on_failure(ip: &str, id: &str) {
+    on_failure_sources(sources(ip, id))
+}
+
+pub fn on_success(ip: &str, id: &str) {
+    on_success_sources(sources(ip, id))
+}
+
+fn settings_unlock_sources() -> Vec<(String, u32)> {
+    vec![(SETTINGS_UNLOCK_SOURCE.to_owned(), SETTINGS_UNLOCK_THRESHOLD)]
+}
+
+/// As `locked`, for the unlocks of the protected settings.
+pub fn settings_unlock_locked() -> Option<i64> {
+    locked_sources(settings_unlock_sources())
+}
+
+pub fn on_settings_unlock(ok: bool) {
+    if ok {
+        on_success_sources(settings_unlock_sources())
+    } else {
+        on_failure_sources(settings_unlock_sources())
+    }
+}
+
+/// The tracked sources as JSON, for the settings page.
+pub fn get_json() -> String {
+    with_lockouts(|l| (serde_json::to_string(&l.sources).unwrap_or_default(), false))
+}
+
+/// Clear the given sources, all if empty.
+/// The lockout of the settings unlock is kept while the settings are locked, or it could be
+/// cleared by the one it locks out.
+pub fn clear(sources: &[String]) {
+    let keep_settings_unlock = crate::settings_guard::is_locked();
+    with_lockouts(|l| {
+        l.sources.retain(|s, _| {
+            (keep_settings_unlock && s == SETTINGS_UNLOCK_SOURCE)
+                || !(sources.is_empty() || sources.contains(s))
+        });
+        ((), true)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bump() {
+        let mut r = Record::default();
+        for _ in 0..ID_THRESHOLD - 1 {
+            bump(&mut r, ID_THRESHOLD, 0);
+        }
+        assert_eq!(r.locked_until, 0);
+        bump(&mut r, ID_THRESHOLD, 0);
+        assert_eq!(r.locked_until, BASE_LOCKOUT);
+        for _ in 0..ID_THRESHOLD {
+            bump(&mut r, ID_THRESHOLD, 0);
+        }
+        assert_eq!(r.locked_until, 2 * BASE_LOCKOUT);
+        assert_eq!(lockout_duration(100), MAX_LOCKOUT);
+    }
+}
@@ -0,0 +1,192 @@
+//! Counters and gauges of the controlled side in the Prometheus text format, served by the
+//! local API at `GET /metrics`, e.g.
+//!
+//! ```yaml
+//! scrape_configs:
+//!   - job_name: rustdesk
+//!     authorization:
+//!       credentials: <local-api-token>
+//!     static_configs:
+//!       - targets: ["127.0.0.1:21120"]
+//! ```
+//!
+//! The bytes are of the messages of the sessions, the blocks of the file transfers which are
+//! written by `fs::handle_read_jobs` directly are not counted.
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use std::fmt::Write;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static FRAMES_ENCODED: AtomicU64 = AtomicU64::new(0);
+static ENCODE_FAILURES: AtomicU64 = AtomicU64::new(0);
+static ENCODE_MICROS: AtomicU64 = AtomicU64::new(0);
+// Of the password and of the second factor.
+static AUTH_FAILURES: [AtomicU64; 2] = [AtomicU64::new(0), AtomicU64::new(0)];
+
+#[inline]
+pub fn on_sent(bytes: usize) {
+    BYTES_SENT.fetch_add(bytes as _, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn on_received(bytes: usize) {
+    BYTES_RECEIVED.fetch_add(bytes as _, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn on_frame_encoded(elapsed: Duration) {
+    FRAMES_ENCODED.fetch_add(1, Ordering::Relaxed);
+    ENCODE_MICROS.fetch_add(elapsed.as_micros() as _, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn on_encode_failed() {
+    ENCODE_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// `i` is 0 for the password, 1 for the second factor, as `LOGIN_FAILURES`.
+#[inline]
+pub fn on_auth_failed(i: usize) {
+    if let Some(c) = AUTH_FAILURES.get(i) {
+        c.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn conn_type_label(t: super::AuthConnType) -> &'static str {
+    match t {
+        super::AuthConnType::Remote => "remote",
+        super::AuthConnType::FileTransfer => "file_transfer",
+        super::AuthConnType::PortForward => "port_forward",
+        super::AuthConnType::ViewCamera => "view_camera",
+        super::AuthConnType::Terminal => "terminal",
+        super::AuthConnType::AudioOnly => "audio_only",
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, values: &[(&str, f64)]) {
+    writeln!(out, "# HELP {} {}", name, help).ok();
+    writeln!(out, "# TYPE {} {}", name, kind).ok();
+    for (labels, v) in values {
+        if labels.is_empty() {
+            writeln!(out, "{} {}", name, v).ok();
+        } else {
+            writeln!(out, "{}{{{}}} {}", name, labels, v).ok();
+        }
+    }
+}
+
+/// The metrics in the Prometheus text exposition format.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn render() -> String {
+    use super::AuthConnType::*;
+
+    let load = |c: &AtomicU64| c.load(Ordering::Relaxed) as f64;
+    let conns = super::get_authed_conns();
+    let sessions: Vec<_> = [
+        Remote,
+        FileTransfer,
+        PortForward,
+        ViewCamera,
+        Terminal,
+        AudioOnly,
+    ]
+    .into_iter()
+    .map(|t| {
+        (
+            format!("type=\"{}\"", conn_type_label(t)),
+            conns.iter().filter(|c| c.1 == t).count() as f64,
+        )
+    })
+    .collect();
+    let sessions: Vec<_> = sessions.iter().map(|(l, v)| (l.as_str(), *v)).collect();
+
+    let mut out = String::new();
+    write_metric(
+        &mut out,
+        "rustdesk_sessions",
+        "gauge",
+        "Active authorized sessions.",
+        &sessions,
+    );
+    write_metric(
+        &mut out,
+        "rustdesk_sent_bytes_total",
+        "counter",
+        "Bytes of the messages sent to the peers.",
+        &[("", load(&BYTES_SENT))],
+    );
+    write_metric(
+        &mut out,
+        "rustdesk_received_bytes_total",
+        "counter",
+        "Bytes of the messages received from the peers.",
+        &[("", load(&BYTES_RECEIVED))],
+    );
+    write_metric(
+        &mut out,
+        "rustdesk_frames_encoded_total",
+        "counter",
+        "Video frames encoded.",
+        &[("", load(&FRAMES_ENCODED))],
+    );
+    write_metric(
+        &mut out,
+        "rustdesk_encode_failures_total",
+        "counter",
+        "Video frames failed to encode.",
+        &[("", load(&ENCODE_FAILURES))],
+    );
+    write_metric(
+        &mut out,
+        "rustdesk_encode_seconds",
+        "summary",
+        "Time spent encoding the video frames.",
+        &[],
+    );
+    writeln!(
+        out,
+        "rustdesk_encode_seconds_sum {}\nrustdesk_encode_seconds_count {}",
+        load(&ENCODE_MICROS) / 1_000_000.,
+        load(&FRAMES_ENCODED)
+    )
+    .ok();
+    write_metric(
+        &mut out,
+        "rustdesk_auth_failures_total",
+        "counter",
+        "Failed login attempts.",
+        &[
+            ("factor=\"password\"", load(&AUTH_FAILURES[0])),
+            ("factor=\"second\"", load(&AUTH_FAILURES[1])),
+        ],
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_metric() {
+        let mut out = String::new();
+        write_metric(
+            &mut out,
+            "x_total",
+            "counter",
+            "Help.",
+            &[("", 1.), ("a=\"b\"", 2.5)],
+        );
+        assert_eq!(
+            out,
+            "# HELP x_total Help.\n# TYPE x_total counter\nx_total 1\nx_total{a=\"b\"} 2.5\n"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Single-use passwords with scoped permissions.
+//!
+//! A one-time password is consumed by the first login using it, or expires after its TTL.
+//! It carries a permission profile which is applied to the connection it authorizes.
+//! Only `sha256(password || salt)` is stored, which is all the login check needs. The salt is
+//! the one of the device which the peers hash their passwords with, so changing it, e.g. with
+//! the `salt` of `deploy_config`, invalidates the pending one-time passwords.
+//! They cannot be added, revoked, nor the lockouts cleared, while the settings are protected,
+//! see `settings_guard`.
+//! It is only consumed once all the factors of the login pass, e.g. 2FA, under a lock of the
+//! server process, so concurrent logins with the same password cannot both succeed.
+
+use super::peer_profile::PeerProfile;
+use hbb_common::{
+    config::{self, Config},
+    get_time, log,
+    rand::{self, Rng},
+};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{path::PathBuf, sync::Mutex};
+
+const FILE_NAME: &str = "one_time_passwords.toml";
+
+/// The request to add a password, as sent by the UI.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct NewPassword {
+    pub password: String,
+    // Seconds, 0 for no expiry.
+    #[serde(default)]
+    pub ttl: i64,
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub profile: PeerProfile,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: String,
+    #[serde(default)]
+    pub hash: String,
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub created: i64,
+    // Milliseconds since epoch, 0 for no expiry.
+    #[serde(default)]
+    pub expires: i64,
+    #[serde(default)]
+    pub profile: PeerProfile,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Entries {
+    #[serde(default)]
+    entries: Vec<Entry>,
+}
+
+lazy_static::lazy_static! {
+    static ref ENTRIES: Mutex<Option<Entries>> = Default::default();
+}
+
+fn path() -> PathBuf {
+    Config::path(FILE_NAME)
+}
+
+fn with_entries<R>(f: impl FnOnce(&mut Vec<Entry>) -> (R, bool)) -> R {
+    let mut lock = ENTRIES.lock().unwrap();
+    let entries = lock.get_or_insert_with(|| config::load_path(path()));
+    let len = entries.entries.len();
+    purge_expired(&mut entries.entries, get_time());
+    let (res, changed) = f(&mut entries.entries);
+    if changed || len != entries.entries.len() {
+        if let Err(err) = config::store_path(path(), &*entries) {
+            log::error!("Failed to store {}: {}", FILE_NAME, err);
+        }
+    }
+    res
+}
+
+fn purge_expired(entries: &mut Vec<Entry>, now: i64) {
+    entries.retain(|e| e.expires == 0 || e.expires > now);
+}
+
+fn hash(password: &str, salt: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(password);
+    hasher.update(salt);
+    hasher.finalize().to_vec()
+}
+
+/// The first entry whose hash passes `check`.
+fn find_matching(entries: &[Entry], check: impl Fn(&[u8]) -> bool) -> Option<&Entry> {
+    entries.iter().find(|e| {
+        crate::decode64(&e.hash)
+            .map(|h| check(&h))
+            .unwrap_or_default()
+    })
+}
+
+fn remove(entries: &mut Vec<Entry>, id: &str) -> Option<Entry> {
+    let i = entries.iter().position(|e| e.id == id)?;
+    Some(entries.remove(i))
+}
+
+pub fn add(json: &str) {
+    let new: NewPassword = match serde_json::from_str(json) {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("Invalid one-time password: {}", err);
+            return;
+        }
+    };
+    if new.password.is_empty() {
+        return;
+    }
+    let now = get_time();
+    let entry = Entry {
+        id: format!("{:08x}", rand::thread_rng().gen::<u32>()),
+        hash: crate::encode64(hash(&new.password, &Config::get_salt())),
+        note: new.note,
+        created: now,
+        expires: if new.ttl > 0 { now + new.ttl * 1000 } else { 0 },
+        profile: new.profile,
+    };
+    with_entries(|entries| {
+        entries.push(entry);
+        ((), true)
+    })
+}
+
+/// The id and the permission profile of the password whose salted hash passes `check`, not
+/// consumed yet.
+pub fn find(check: impl Fn(&[u8]) -> bool) -> Option<(String, PeerProfile)> {
+    with_entries(|entries| {
+        let res = find_matching(entries, check).map(|e| (e.id.clone(), e.profile.clone()));
+        (res, false)
+    })
+}
+
+/// Consume the password found once the login is authorized, false if it was used by another
+/// login or revoked meanwhile.
+pub fn consume(id: &str) -> bool {
+    with_entries(|entries| {
+        let res = remove(entries, id).is_some();
+        if res {
+            log::info!("One-time password {} consumed", id);
+        }
+        (res, res)
+    })
+}
+
+/// The pending passwords without their hashes, for the settings page.
+pub fn get_json() -> String {
+    with_entries(|entries| {
+        let v: Vec<Entry> = entries
+            .iter()
+            .map(|e| Entry {
+                hash: Default::default(),
+                ..e.clone()
+            })
+            .collect();
+        (serde_json::to_string(&v).unwrap_or_default(), false)
+    })
+}
+
+/// Revoke the given passwords, all if empty.
+pub fn revoke(ids: &[String]) {
+    with_entries(|entries| {
+        if ids.is_empty() {
+            entries.clear();
+        } else {
+            entries.retain(|e| !ids.contains(&e.id));
+        }
+        ((), true)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_matching() {
+        let entry = |id: &str, password: &str, expires: i64| Entry {
+            id: id.to_owned(),
+            hash: crate::encode64(hash(password, "salt")),
+            expires,
+            ..Default::default()
+        };
+        let mut entries = vec![entry("a", "111", 0), entry("b", "222", 100)];
+        purge_expired(&mut entries, 50);
+        assert_eq!(entries.len(), 2);
+        let check = |password: &str| {
+            let h = hash(password, "salt");
+            move |x: &[u8]| x == &h[..]
+        };
+        assert_eq!(find_matching(&entries, check("222")).unwrap().id, "b");
+        // Not consumed by the check.
+        assert_eq!(find_matching(&entries, check("222")).unwrap().id, "b");
+        assert!(remove(&mut entries, "b").is_some());
+        // Single use
+        assert!(remove(&mut entries, "b").is_none());
+        assert!(find_matching(&entries, check("222")).is_none());
+        assert!(find_matching(&entries, check("333")).is_none());
+        purge_expired(&mut entries, 200);
+        assert_eq!(entries.len(), 1);
+    }
+}
@@ -0,0 +1,89 @@
+//! Per-peer permission profiles.
+//!
+//! The owner of the controlled side can pre-configure the capabilities of a known peer
+//! ID. As the ID is only claimed by the peer, a capability set in the profile can only turn
+//! the global permission off, an unset one follows it. The profile of a one-time password,
+//! bound to the password, overrides the global permission.
+
+use hbb_common::{config::Config, log};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const OPTION_PEER_PROFILES: &str = "peer-profiles";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Keyboard,
+    Clipboard,
+    File,
+    Audio,
+    Tunnel,
+    Gamepad,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerProfile {
+    #[serde(default)]
+    pub view_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyboard: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipboard: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tunnel: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gamepad: Option<bool>,
+}
+
+impl PeerProfile {
+    /// The overridden permission, `None` to follow the global one.
+    pub fn allows(&self, capability: Capability) -> Option<bool> {
+        match capability {
+            Capability::Keyboard if self.view_only => Some(false),
+            Capability::Keyboard => self.keyboard,
+            Capability::Clipboard => self.clipboard,
+            Capability::File => self.file,
+            Capability::Audio => self.audio,
+            Capability::Tunnel => self.tunnel,
+            Capability::Gamepad => self.gamepad,
+        }
+    }
+}
+
+fn parse(s: &str) -> HashMap<String, PeerProfile> {
+    if s.is_empty() {
+        return Default::default();
+    }
+    serde_json::from_str(s).unwrap_or_else(|err| {
+        log::error!("Invalid {}: {}", OPTION_PEER_PROFILES, err);
+        Default::default()
+    })
+}
+
+pub fn get(peer_id: &str) -> Option<PeerProfile> {
+    if peer_id.is_empty() {
+        return None;
+    }
+    parse(&Config::get_option(OPTION_PEER_PROFILES)).remove(peer_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let profiles =
+            parse(r#"{"123456789": {"view_only": true, "file": false, "tunnel": true}}"#);
+        let p = profiles.get("123456789").unwrap();
+        assert_eq!(p.allows(Capability::Keyboard), Some(false));
+        assert_eq!(p.allows(Capability::File), Some(false));
+        assert_eq!(p.allows(Capability::Tunnel), Some(true));
+        assert_eq!(p.allows(Capability::Clipboard), None);
+        assert!(parse("not json").is_empty());
+    }
+}
@@ -534,6 +534,8 @@ pub mod client {
     pub enum StartPara {
         Direct,
         Logon(String, String),
+        // Launched as SYSTEM by the service, for the unprivileged server.
+        Broker,
     }
 
     pub(crate) fn start_portable_service(para: StartPara) -> ResultType<()> {
@@ -624,6 +626,14 @@ pub mod client {
                     bail!("Failed to run portable service process: {}", e);
                 }
             }
+            StartPara::Broker => {
+                if let Err(e) = crate::platform::privilege_broker::request(
+                    crate::platform::privilege_broker::CAP_SECURE_DESKTOP_CAPTURE,
+                ) {
+                    *SHMEM.lock().unwrap() = None;
+                    bail!("Failed to run portable service process: {}", e);
+                }
+            }
         }
         let _sender = SENDER.lock().unwrap();
         Ok(())
@@ -0,0 +1,183 @@
+// The power state of this device, the capture rate is reduced on battery and when the CPU is
+// throttled by the heat, and the state is sent to the controlling side.
+//
+// The state is refreshed by a thread, reading it may run a command (macOS), which is too slow
+// for the video loop.
+pub use crate::client::PowerState;
+use hbb_common::{
+    config::{self, Config},
+    log,
+};
+use std::{
+    sync::{Mutex, Once},
+    time::Duration,
+};
+
+pub const OPTION_ENABLE_POWER_SAVING_CAPTURE: &str = "enable-power-saving-capture";
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+const BATTERY_MAX_FPS: u32 = 15;
+const THERMAL_MAX_FPS: u32 = 10;
+
+lazy_static::lazy_static! {
+    static ref STATE: Mutex<PowerState> = Default::default();
+}
+
+fn is_enabled() -> bool {
+    config::option2bool(
+        OPTION_ENABLE_POWER_SAVING_CAPTURE,
+        &Config::get_option(OPTION_ENABLE_POWER_SAVING_CAPTURE),
+    )
+}
+
+fn start_refresh_thread() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        std::thread::spawn(|| loop {
+            let mut state = query();
+            state.throttled = is_enabled() && (state.on_battery || state.thermal_throttled);
+            let mut lock = STATE.lock().unwrap();
+            if *lock != state {
+                log::info!("Power state changed: {:?}", state);
+                *lock = state;
+            }
+            drop(lock);
+            std::thread::sleep(REFRESH_INTERVAL);
+        });
+    });
+}
+
+pub fn get() -> PowerState {
+    start_refresh_thread();
+    STATE.lock().unwrap().clone()
+}
+
+/// The highest capture rate for the power state, None if not throttled.
+pub fn max_fps() -> Option<u32> {
+    let state = get();
+    if !state.throttled {
+        None
+    } else if state.thermal_throttled {
+        Some(THERMAL_MAX_FPS)
+    } else {
+        Some(BATTERY_MAX_FPS)
+    }
+}
+
+#[cfg(windows)]
+fn query() -> PowerState {
+    use winapi::um::winbase::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    const AC_LINE_OFFLINE: u8 = 0;
+    const BATTERY_FLAG_NO_BATTERY: u8 = 128;
+    const BATTERY_PERCENT_UNKNOWN: u8 = 255;
+
+    let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+    if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+        return PowerState::default();
+    }
+    let has_battery = status.BatteryFlag & BATTERY_FLAG_NO_BATTERY == 0;
+    PowerState {
+        on_battery: has_battery && status.ACLineStatus == AC_LINE_OFFLINE,
+        battery_percent: (has_battery && status.BatteryLifePercent != BATTERY_PERCENT_UNKNOWN)
+            .then(|| status.BatteryLifePercent as u32),
+        // No thermal state without WMI.
+        thermal_throttled: false,
+        throttled: false,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn query() -> PowerState {
+    use std::fs::{read_dir, read_to_string};
+    use std::path::Path;
+
+    let read = |path: &Path, name: &str| {
+        read_to_string(path.join(name))
+            .map(|s| s.trim().to_owned())
+            .unwrap_or_default()
+    };
+
+    let mut state = PowerState::default();
+    let mut mains_online = false;
+    if let Ok(dir) = read_dir("/sys/class/power_supply") {
+        for entry in dir.flatten() {
+            let path = entry.path();
+            match read(&path, "type").as_str() {
+                "Mains" => mains_online |= read(&path, "online") == "1",
+                // The batteries of the mice and keyboards are in the scope "Device".
+                "Battery" if read(&path, "scope") != "Device" => {
+                    state.battery_percent = read(&path, "capacity").parse().ok();
+                    state.on_battery |= read(&path, "status") == "Discharging";
+                }
+                _ => {}
+            }
+        }
+    }
+    state.on_battery &= !mains_online;
+
+    // A zone is throttled from its lowest passive trip point.
+    if let Ok(dir) = read_dir("/sys/class/thermal") {
+        for entry in dir.flatten() {
+            let path = entry.path();
+            if !entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("thermal_zone")
+            {
+                continue;
+            }
+            let Ok(temp) = read(&path, "temp").parse::<i64>() else {
+                continue;
+            };
+            let passive = (0..16)
+                .filter(|i| read(&path, &format!("trip_point_{}_type", i)) == "passive")
+                .filter_map(|i| {
+                    read(&path, &format!("trip_point_{}_temp", i))
+                        .parse::<i64>()
+                        .ok()
+                })
+                .filter(|t| *t > 0)
+                .min();
+            if matches!(passive, Some(passive) if temp >= passive) {
+                state.thermal_throttled = true;
+            }
+        }
+    }
+    state
+}
+
+#[cfg(target_os = "macos")]
+fn query() -> PowerState {
+    let pmset = |arg: &str| {
+        std::process::Command::new("pmset")
+            .args(["-g", arg])
+            .output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+            .unwrap_or_default()
+    };
+
+    // Now drawing from 'Battery Power'
+    //  -InternalBattery-0 (id=1234567)	85%; discharging; 4:12 remaining present: true
+    let batt = pmset("batt");
+    let battery_percent = batt
+        .lines()
+        .find(|l| l.contains("InternalBattery"))
+        .and_then(|l| l.split_whitespace().find(|f| f.ends_with("%;")))
+        .and_then(|f| f.trim_end_matches("%;").parse().ok());
+    // CPU_Speed_Limit 	= 70
+    let therm = pmset("therm");
+    let thermal_throttled = therm.lines().any(|l| {
+        let mut kv = l.split('=');
+        matches!(
+            (kv.next().map(|k| k.trim()), kv.next().and_then(|v| v.trim().parse::<u32>().ok())),
+            (Some("CPU_Speed_Limit"), Some(limit)) if limit < 100
+        )
+    });
+    PowerState {
+        on_battery: batt.contains("'Battery Power'"),
+        battery_percent,
+        thermal_throttled,
+        throttled: false,
+    }
+}
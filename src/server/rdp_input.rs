@@ -191,7 +191,7 @@ pub mod client {
         }
     }
 
-    fn get_raw_evdev_keycode(key: u16) -> i32 {
+    pub(super) fn get_raw_evdev_keycode(key: u16) -> i32 {
         // 8 is the offset between xkb and evdev
         let mut key = key as i32 - 8;
         // fix for right_meta key
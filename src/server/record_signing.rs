@@ -0,0 +1,533 @@
+//! Tamper-evident recordings of incoming sessions.
+//!
+//! If enabled, every incoming remote desktop session is recorded, together with a log of
+//! the input events of the peer. The files are hashed in chunks and each chunk is signed
+//! with the device key, chained to the signature of the previous chunk, into `<file>.sig`.
+//! A modified, truncated or reordered file does not verify against it.
+//!
+//! The chunks are signed as soon as they are written, into a partial manifest, so that a
+//! recording cut short, e.g. by a crash or a power loss, is still signed up to its last
+//! chunk. Once the file is finished, it is signed again as a whole, because the muxer
+//! rewrites the header of the video at the end, but only if the chunks signed so far
+//! are unchanged, apart from that header.
+
+use hbb_common::{
+    bail, chrono,
+    config::{self, Config},
+    get_time, log,
+    message_proto::{message, Message},
+    sodiumoxide::crypto::sign,
+    ResultType,
+};
+use scrap::record::RecordState;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, Sender},
+};
+
+pub const OPTION_SIGN_INCOMING_RECORDING: &str = "allow-sign-incoming-recording";
+const CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+// The muxers only rewrite the start of the video when finishing it, e.g. the duration of
+// webm or the size of the mdat box of mp4.
+const VIDEO_HEADER_SIZE: u64 = 4096;
+// Mouse event type in the lower 3 bits of the mask, the buttons above.
+const MOUSE_TYPE_MOVE: i32 = 0;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Chunk {
+    pub offset: u64,
+    pub len: u64,
+    pub sha256: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub file: String,
+    pub size: u64,
+    pub time: i64,
+    pub public_key: String,
+    pub chunks: Vec<Chunk>,
+    /// Only the first `size` bytes are signed, the file was not finished.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    pub signature: String,
+}
+
+pub fn is_enabled() -> bool {
+    config::option2bool(
+        OPTION_SIGN_INCOMING_RECORDING,
+        &Config::get_option(OPTION_SIGN_INCOMING_RECORDING),
+    )
+}
+
+fn sig_path(path: &Path) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(".sig");
+    PathBuf::from(s)
+}
+
+fn chunk_message(file: &str, index: usize, chunk: &Chunk, prev: &str) -> Vec<u8> {
+    format!(
+        "{}|{}|{}|{}|{}|{}",
+        file, index, chunk.offset, chunk.len, chunk.sha256, prev
+    )
+    .into_bytes()
+}
+
+fn manifest_message(m: &Manifest) -> Vec<u8> {
+    let mut s = format!(
+        "{}|{}|{}|{}|{}|{}",
+        m.file,
+        m.size,
+        m.time,
+        m.public_key,
+        m.chunks.len(),
+        m.chunks.last().map_or("", |c| &c.signature)
+    );
+    if m.partial {
+        s += "|partial";
+    }
+    s.into_bytes()
+}
+
+fn sign_bytes(m: &[u8], sk: &sign::SecretKey) -> String {
+    let signed = sign::sign(m, sk);
+    crate::encode64(&signed[..sign::SIGNATUREBYTES])
+}
+
+fn verify_bytes(m: &[u8], signature: &str, pk: &sign::PublicKey) -> bool {
+    let Ok(mut signed) = crate::decode64(signature) else {
+        return false;
+    };
+    if signed.len() != sign::SIGNATUREBYTES {
+        return false;
+    }
+    signed.extend_from_slice(m);
+    sign::verify(&signed, pk).is_ok()
+}
+
+// Calls `f` with each chunk of at most `CHUNK_SIZE` bytes.
+fn for_each_chunk(mut reader: impl Read, mut f: impl FnMut(&[u8])) -> ResultType<()> {
+    loop {
+        let mut buf = Vec::new();
+        (&mut reader).take(CHUNK_SIZE).read_to_end(&mut buf)?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        f(&buf);
+    }
+}
+
+fn new_manifest(file: &str, pk: &[u8]) -> Manifest {
+    Manifest {
+        file: file.to_owned(),
+        time: get_time(),
+        public_key: crate::encode64(pk),
+        ..Default::default()
+    }
+}
+
+// Appends the chunk signed after the previous one.
+fn push_chunk(m: &mut Manifest, buf: &[u8], sk: &sign::SecretKey) {
+    let mut chunk = Chunk {
+        offset: m.size,
+        len: buf.len() as _,
+        sha256: crate::encode64(Sha256::digest(buf)),
+        signature: Default::default(),
+    };
+    let prev = m.chunks.last().map_or("", |c| &c.signature);
+    chunk.signature = sign_bytes(&chunk_message(&m.file, m.chunks.len(), &chunk, prev), sk);
+    m.size += chunk.len;
+    m.chunks.push(chunk);
+}
+
+fn sign_reader(
+    file: &str,
+    reader: impl Read,
+    sk: &sign::SecretKey,
+    pk: &[u8],
+) -> ResultType<Manifest> {
+    let mut m = new_manifest(file, pk);
+    for_each_chunk(reader, |buf| push_chunk(&mut m, buf, sk))?;
+    m.signature = sign_bytes(&manifest_message(&m), sk);
+    Ok(m)
+}
+
+fn verify_reader(m: &Manifest, reader: impl Read, pk: &sign::PublicKey) -> ResultType<()> {
+    if crate::decode64(&m.public_key)? != pk.0 {
+        bail!("signed by another key");
+    }
+    if !verify_bytes(&manifest_message(m), &m.signature, pk) {
+        bail!("invalid manifest signature");
+    }
+    // The data written after the last signed chunk of a partial manifest is not covered.
+    let reader = reader.take(if m.partial { m.size } else { u64::MAX });
+    let (mut index, mut size, mut err) = (0, 0, None);
+    for_each_chunk(reader, |buf| {
+        if err.is_some() {
+            return;
+        }
+        let Some(chunk) = m.chunks.get(index) else {
+            err = Some(format!("unexpected data after chunk {}", index));
+            return;
+        };
+        let prev = if index == 0 {
+            ""
+        } else {
+            &m.chunks[index - 1].signature
+        };
+        if chunk.offset != size
+            || chunk.len != buf.len() as u64
+            || chunk.sha256 != crate::encode64(Sha256::digest(buf))
+            || !verify_bytes(
+                &chunk_message(&m.file, index, chunk, prev),
+                &chunk.signature,
+                pk,
+            )
+        {
+            err = Some(format!("chunk {} modified", index));
+        }
+        index += 1;
+        size += buf.len() as u64;
+    })?;
+    if let Some(err) = err {
+        bail!(err);
+    }
+    if index != m.chunks.len() || size != m.size {
+        bail!("file truncated");
+    }
+    Ok(())
+}
+
+fn device_key() -> ResultType<(sign::SecretKey, Vec<u8>)> {
+    let (sk, pk) = Config::get_key_pair();
+    let Some(sk) = sign::SecretKey::from_slice(&sk) else {
+        bail!("invalid device key");
+    };
+    Ok((sk, pk))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Signs the chunks of a file being written as soon as they are complete, into a partial
+/// manifest until the file is finished and signed as a whole by [`ChunkSigner::finish`].
+struct ChunkSigner {
+    path: PathBuf,
+    sk: sign::SecretKey,
+    pk: Vec<u8>,
+    m: Manifest,
+    // The size of the header which may be rewritten once the file is finished.
+    header_size: u64,
+    // The hash of the first chunk after that header.
+    first_chunk_body: String,
+}
+
+impl ChunkSigner {
+    fn new(path: &Path, header_size: u64) -> ResultType<Self> {
+        let (sk, pk) = device_key()?;
+        let mut m = new_manifest(&file_name(path), &pk);
+        m.partial = true;
+        Ok(Self {
+            path: path.to_owned(),
+            sk,
+            pk,
+            m,
+            header_size,
+            first_chunk_body: Default::default(),
+        })
+    }
+
+    fn push_chunk(&mut self, buf: &[u8]) {
+        if self.m.chunks.is_empty() {
+            self.first_chunk_body =
+                crate::encode64(Sha256::digest(&buf[self.header_size as usize..]));
+        }
+        push_chunk(&mut self.m, buf, &self.sk);
+    }
+
+    // Signs the complete chunks written since the last call.
+    fn update(&mut self, len: u64) -> ResultType<()> {
+        if len < self.m.size + CHUNK_SIZE {
+            return Ok(());
+        }
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.m.size))?;
+        let mut buf = vec![0u8; CHUNK_SIZE as usize];
+        while self.m.size + CHUNK_SIZE <= len {
+            file.read_exact(&mut buf)?;
+            self.push_chunk(&buf);
+        }
+        self.m.signature = sign_bytes(&manifest_message(&self.m), &self.sk);
+        std::fs::write(sig_path(&self.path), serde_json::to_vec_pretty(&self.m)?)?;
+        Ok(())
+    }
+
+    // Checks that the chunks signed while writing are unchanged, apart from the header.
+    fn check_signed_chunks(&self, mut reader: impl Read) -> ResultType<()> {
+        let mut buf = vec![0u8; CHUNK_SIZE as usize];
+        for (index, chunk) in self.m.chunks.iter().enumerate() {
+            reader.read_exact(&mut buf)?;
+            let unchanged = if index == 0 {
+                self.first_chunk_body
+                    == crate::encode64(Sha256::digest(&buf[self.header_size as usize..]))
+            } else {
+                chunk.sha256 == crate::encode64(Sha256::digest(&buf))
+            };
+            if !unchanged {
+                bail!("chunk {} modified after being signed", index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Signs the finished file as a whole, the partial manifest is kept if the chunks
+    /// signed while writing were modified since.
+    fn finish(self) -> ResultType<()> {
+        self.check_signed_chunks(File::open(&self.path)?)?;
+        let m = sign_reader(&self.m.file, File::open(&self.path)?, &self.sk, &self.pk)?;
+        std::fs::write(sig_path(&self.path), serde_json::to_vec_pretty(&m)?)?;
+        Ok(())
+    }
+}
+
+/// Verify the file against its `<file>.sig` with the public key of the recording device,
+/// returns whether the whole file is signed, not only the chunks of a recording which was
+/// not finished.
+pub fn verify_file(path: &Path, pk: &[u8]) -> ResultType<bool> {
+    let Some(pk) = sign::PublicKey::from_slice(pk) else {
+        bail!("invalid public key");
+    };
+    let m: Manifest = serde_json::from_slice(&std::fs::read(sig_path(path))?)?;
+    if path.file_name().map(|x| x.to_string_lossy().to_string()) != Some(m.file.clone()) {
+        bail!("file name mismatch");
+    }
+    verify_reader(&m, File::open(path)?, &pk)?;
+    Ok(!m.partial)
+}
+
+/// Sign the video files as the recorder writes and finishes them, then pass the states on.
+pub fn run(rx: Receiver<RecordState>, forward: Option<Sender<RecordState>>) {
+    std::thread::spawn(move || {
+        let mut current: Option<ChunkSigner> = None;
+        while let Ok(state) = rx.recv() {
+            match &state {
+                RecordState::NewFile(path) => {
+                    current = ChunkSigner::new(Path::new(path), VIDEO_HEADER_SIZE)
+                        .map_err(|err| log::error!("Failed to sign {}: {}", path, err))
+                        .ok();
+                }
+                RecordState::NewFrame => {
+                    if let Some(signer) = current.as_mut() {
+                        let res = std::fs::metadata(&signer.path)
+                            .map_err(|e| e.into())
+                            .and_then(|meta| signer.update(meta.len()));
+                        if let Err(err) = res {
+                            log::error!("Failed to sign the chunk of {:?}: {}", signer.path, err);
+                            current = None;
+                        }
+                    }
+                }
+                RecordState::WriteTail => {
+                    if let Some(signer) = current.take() {
+                        let path = signer.path.clone();
+                        if let Err(err) = signer.finish() {
+                            log::error!("Failed to sign {:?}: {}", path, err);
+                        }
+                    }
+                }
+                RecordState::RemoveFile => {
+                    if let Some(signer) = current.take() {
+                        std::fs::remove_file(sig_path(&signer.path)).ok();
+                    }
+                }
+            }
+            if let Some(tx) = forward.as_ref() {
+                tx.send(state).ok();
+            }
+        }
+    });
+}
+
+/// Input events of the peer, one JSON object per line, signed chunk by chunk and as a whole
+/// when dropped.
+pub struct InputLog {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    signer: Option<ChunkSigner>,
+    written: u64,
+}
+
+impl InputLog {
+    pub fn new(dir: &str, header: Value) -> ResultType<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = Path::new(dir).join(format!(
+            "incoming_{}{}input.jsonl",
+            Config::get_id(),
+            chrono::Local::now().format("_%Y%m%d%H%M%S%3f_")
+        ));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let header = format!("{}\n", header);
+        writer.write_all(header.as_bytes())?;
+        let signer = ChunkSigner::new(&path, 0)?;
+        Ok(Self {
+            path,
+            writer: Some(writer),
+            signer: Some(signer),
+            written: header.len() as _,
+        })
+    }
+
+    pub fn log_message(&mut self, msg: &Message) {
+        let v = match &msg.union {
+            Some(message::Union::MouseEvent(me)) => {
+                if me.mask & 0x7 == MOUSE_TYPE_MOVE {
+                    return;
+                }
+                json!({"t": get_time(), "mouse": me.mask & 0x7, "buttons": me.mask >> 3, "x": me.x, "y": me.y})
+            }
+            Some(message::Union::KeyEvent(ke)) => json!({
+                "t": get_time(),
+                "key": format!("{:?}", ke.union),
+                "down": ke.down,
+                "press": ke.press,
+                "modifiers": ke.modifiers.iter().map(|m| m.value()).collect::<Vec<_>>(),
+            }),
+            Some(message::Union::PointerDeviceEvent(pde)) => {
+                json!({"t": get_time(), "pointer": format!("{:?}", pde.union)})
+            }
+            _ => return,
+        };
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        let line = format!("{}\n", v);
+        if let Err(err) = writer.write_all(line.as_bytes()) {
+            log::error!("Failed to write input log: {}", err);
+            self.writer = None;
+            return;
+        }
+        self.written += line.len() as u64;
+        if let Some(signer) = self.signer.as_mut() {
+            if self.written >= signer.m.size + CHUNK_SIZE {
+                let res = writer
+                    .flush()
+                    .map_err(|e| e.into())
+                    .and_then(|_| signer.update(self.written));
+                if let Err(err) = res {
+                    log::error!("Failed to sign the chunk of {:?}: {}", self.path, err);
+                    self.signer = None;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for InputLog {
+    fn drop(&mut self) {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush().ok();
+        }
+        if let Some(signer) = self.signer.take() {
+            if let Err(err) = signer.finish() {
+                log::error!("Failed to sign {:?}: {}", self.path, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_verify() {
+        let (pk, sk) = sign::gen_keypair();
+        let data = vec![7u8; CHUNK_SIZE as usize * 2 + 10];
+        let m = sign_reader("a.webm", &data[..], &sk, &pk.0).unwrap();
+        assert_eq!(m.chunks.len(), 3);
+        assert!(verify_reader(&m, &data[..], &pk).is_ok());
+
+        let mut modified = data.clone();
+        modified[CHUNK_SIZE as usize + 1] = 0;
+        assert!(verify_reader(&m, &modified[..], &pk).is_err());
+        assert!(verify_reader(&m, &data[..CHUNK_SIZE as usize], &pk).is_err());
+        let mut appended = data.clone();
+        appended.push(0);
+        assert!(verify_reader(&m, &appended[..], &pk).is_err());
+
+        let mut m = m;
+        m.chunks.swap(0, 1);
+        assert!(verify_reader(&m, &data[..], &pk).is_err());
+    }
+
+    #[test]
+    fn test_partial() {
+        let (pk, sk) = sign::gen_keypair();
+        let data = vec![7u8; CHUNK_SIZE as usize * 2 + 10];
+        let mut m = new_manifest("a.webm", &pk.0);
+        m.partial = true;
+        push_chunk(&mut m, &data[..CHUNK_SIZE as usize], &sk);
+        m.signature = sign_bytes(&manifest_message(&m), &sk);
+        // The data written after the signed chunks is not covered.
+        assert!(verify_reader(&m, &data[..], &pk).is_ok());
+        assert!(verify_reader(&m, &data[..10], &pk).is_err());
+        let mut modified = data.clone();
+        modified[1] = 0;
+        assert!(verify_reader(&m, &modified[..], &pk).is_err());
+        // Not to be passed off as the whole file.
+        m.partial = false;
+        assert!(verify_reader(&m, &data[..CHUNK_SIZE as usize], &pk).is_err());
+    }
+
+    #[test]
+    fn test_other_key() {
+        let (pk, sk) = sign::gen_keypair();
+        let (other_pk, other_sk) = sign::gen_keypair();
+        let data = vec![7u8; 10];
+        let m = sign_reader("a.webm", &data[..], &sk, &pk.0).unwrap();
+        assert!(verify_reader(&m, &data[..], &other_pk).is_err());
+        // Signed by another key, which claims to be ours.
+        let mut m = sign_reader("a.webm", &data[..], &other_sk, &other_pk.0).unwrap();
+        m.public_key = crate::encode64(pk.0);
+        assert!(verify_reader(&m, &data[..], &pk).is_err());
+    }
+
+    #[test]
+    fn test_check_signed_chunks() {
+        let (pk, sk) = sign::gen_keypair();
+        let mut signer = ChunkSigner {
+            path: PathBuf::from("a.webm"),
+            sk,
+            pk: pk.0.to_vec(),
+            m: new_manifest("a.webm", &pk.0),
+            header_size: VIDEO_HEADER_SIZE,
+            first_chunk_body: Default::default(),
+        };
+        let data = vec![7u8; CHUNK_SIZE as usize * 2 + 10];
+        signer.push_chunk(&data[..CHUNK_SIZE as usize]);
+        signer.push_chunk(&data[CHUNK_SIZE as usize..CHUNK_SIZE as usize * 2]);
+        assert!(signer.check_signed_chunks(&data[..]).is_ok());
+        // The header rewritten by the muxer.
+        let mut modified = data.clone();
+        modified[1] = 0;
+        assert!(signer.check_signed_chunks(&modified[..]).is_ok());
+        modified[VIDEO_HEADER_SIZE as usize] = 0;
+        assert!(signer.check_signed_chunks(&modified[..]).is_err());
+        let mut modified = data.clone();
+        modified[CHUNK_SIZE as usize + 1] = 0;
+        assert!(signer.check_signed_chunks(&modified[..]).is_err());
+        assert!(signer
+            .check_signed_chunks(&data[..CHUNK_SIZE as usize])
+            .is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//! Local commands run by the service around the incoming sessions, for custom gating and logging.
+//!
+//! The command lines are read from `session-hooks.toml` in the system config directory, only if
+//! it is owned by the admin, never from the options which the users can set, as the service runs
+//! them as root or SYSTEM:
+//!
+//! ```toml
+//! requested = "/usr/local/bin/rustdesk-gate"
+//! accepted = ""
+//! ended = ""
+//! timeout = 10
+//! ```
+//!
+//! They are run by `sh -c`, or `cmd /C` on Windows, with the environment:
+//!
+//! - `RUSTDESK_HOOK`: `requested`, `accepted` or `ended`
+//! - `RUSTDESK_CONN_ID`, `RUSTDESK_PEER_ID`, `RUSTDESK_PEER_NAME`, `RUSTDESK_PEER_IP`
+//! - `RUSTDESK_CONN_TYPE`: `remote`, `file_transfer`, `port_forward`, `view_camera`, `terminal`
+//! - `RUSTDESK_PERMISSIONS`: the permissions of the session separated by `,`, e.g. `keyboard,file`
+//!
+//! The requested hook runs when a login request is authenticated, by the password, 2FA or the
+//! click of the user, before the session starts: exit code 0 allows it, any other code denies
+//! it. A hook which fails to run or does not exit within `timeout` seconds (10 by default) is
+//! killed and denies. The exit codes of the other hooks are only logged, they run in the
+//! background.
+
+use hbb_common::{
+    log,
+    tokio::{self, process::Command},
+    toml,
+};
+use serde_derive::Deserialize;
+use std::{process::Stdio, time::Duration};
+
+const HOOKS_FILE_NAME: &str = "session-hooks.toml";
+const DEFAULT_TIMEOUT: u64 = 10;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Hooks {
+    requested: String,
+    accepted: String,
+    ended: String,
+    timeout: Option<u64>,
+}
+
+impl Hooks {
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(DEFAULT_TIMEOUT))
+    }
+}
+
+/// The session passed to the hooks.
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    pub conn_id: i32,
+    pub peer_id: String,
+    pub peer_name: String,
+    pub ip: String,
+    pub conn_type: &'static str,
+    pub permissions: Vec<&'static str>,
+}
+
+// Read on every session, so the changes take effect without a restart.
+fn get_hooks() -> Hooks {
+    let path = crate::deploy_config::system_config_dir().join(HOOKS_FILE_NAME);
+    let Some(text) = crate::deploy_config::read_system_file(&path) else {
+        return Hooks::default();
+    };
+    toml::from_str(&text).unwrap_or_else(|err| {
+        log::error!("Invalid {:?}: {}", path, err);
+        Hooks::default()
+    })
+}
+
+fn command(cmd: &str, hook: &str, env: &Env) -> Command {
+    #[cfg(windows)]
+    let mut c = {
+        let mut c = Command::new("cmd");
+        c.args(["/C", cmd]).creation_flags(0x08000000); // CREATE_NO_WINDOW
+        c
+    };
+    #[cfg(not(windows))]
+    let mut c = {
+        let mut c = Command::new("sh");
+        c.args(["-c", cmd]);
+        c
+    };
+    c.env("RUSTDESK_HOOK", hook)
+        .env("RUSTDESK_CONN_ID", env.conn_id.to_string())
+        .env("RUSTDESK_PEER_ID", &env.peer_id)
+        .env("RUSTDESK_PEER_NAME", &env.peer_name)
+        .env("RUSTDESK_PEER_IP", &env.ip)
+        .env("RUSTDESK_CONN_TYPE", env.conn_type)
+        .env("RUSTDESK_PERMISSIONS", env.permissions.join(","))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    c
+}
+
+// Whether the hook exits with 0, true if it is not set.
+async fn run(cmd: &str, timeout: Duration, hook: &str, env: &Env) -> bool {
+    if cmd.is_empty() {
+        return true;
+    }
+    let mut child = match command(cmd, hook, env).spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            log::error!("Failed to run the {} session hook: {}", hook, err);
+            return false;
+        }
+    };
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(Ok(status)) => {
+            log::info!(
+                "#{} The {} session hook exited with {}",
+                env.conn_id,
+                hook,
+                status
+            );
+            status.success()
+        }
+        Ok(Err(err)) => {
+            log::error!("Failed to wait for the {} session hook: {}", hook, err);
+            false
+        }
+        Err(_) => {
+            log::error!("The {} session hook timed out", hook);
+            child.kill().await.ok();
+            false
+        }
+    }
+}
+
+/// Whether the requested hook allows the authenticated login request.
+pub async fn on_requested(env: &Env) -> bool {
+    let hooks = get_hooks();
+    run(&hooks.requested, hooks.timeout(), "requested", env).await
+}
+
+pub fn on_accepted(env: Env) {
+    let hooks = get_hooks();
+    if !hooks.accepted.is_empty() {
+        tokio::spawn(async move { run(&hooks.accepted, hooks.timeout(), "accepted", &env).await });
+    }
+}
+
+pub fn on_ended(env: Env) {
+    let hooks = get_hooks();
+    if !hooks.ended.is_empty() {
+        tokio::spawn(async move { run(&hooks.ended, hooks.timeout(), "ended", &env).await });
+    }
+}
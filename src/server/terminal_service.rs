@@ -1,8 +1,5 @@
 use super::*;
-use hbb_common::{
-    anyhow::{anyhow, Context, Result},
-    compress,
-};
+use hbb_common::anyhow::{anyhow, Context, Result};
 use portable_pty::{Child, CommandBuilder, PtySize};
 use std::{
     collections::{HashMap, VecDeque},
@@ -1059,8 +1056,7 @@ impl TerminalServiceProxy {
 
                     // Compress data if it exceeds threshold
                     if data.len() > COMPRESS_THRESHOLD {
-                        let compressed = compress::compress(&data);
-                        if compressed.len() < data.len() {
+                        if let Some(compressed) = crate::compression::compress(&data) {
                             terminal_data.data = bytes::Bytes::from(compressed);
                             terminal_data.compressed = true;
                         } else {
@@ -173,6 +173,24 @@ pub mod client {
         let _ = conn.next().await?;
         Ok(())
     }
+
+    /// Blocks the input devices in the service, which runs as root.
+    #[tokio::main(flavor = "current_thread")]
+    pub async fn block_input(v: bool) -> ResultType<()> {
+        let mut conn = ipc::connect(IPC_CONN_TIMEOUT, IPC_POSTFIX_CONTROL).await?;
+        conn.send(&Data::Control(ipc::DataControl::BlockInput(v)))
+            .await?;
+        match conn.next_timeout(IPC_REQUEST_TIMEOUT).await? {
+            Some(Data::Control(ipc::DataControl::BlockInputResult(err))) => {
+                if err.is_empty() {
+                    Ok(())
+                } else {
+                    bail!(err)
+                }
+            }
+            _ => bail!("Unexpected response of the uinput service"),
+        }
+    }
 }
 
 pub mod service {
@@ -702,6 +720,14 @@ pub mod service {
                                             *RESOLUTION.lock().unwrap() = ((minx, maxx), (miny, maxy));
                                             allow_err!(stream.send(&Data::Empty).await);
                                         }
+                                        ipc::DataControl::BlockInput(v) => {
+                                            let err = crate::platform::linux::grab_input_devices(v)
+                                                .err()
+                                                .map(|e| e.to_string())
+                                                .unwrap_or_default();
+                                            allow_err!(stream.send(&Data::Control(ipc::DataControl::BlockInputResult(err))).await);
+                                        }
+                                        ipc::DataControl::BlockInputResult(_) => {}
                                     }
                                     _ => {
                                     }
@@ -1,4 +1,4 @@
-use super::*;
+use super::{bandwidth::BandwidthEstimator, *};
 use scrap::codec::{Quality, BR_BALANCED, BR_BEST, BR_SPEED};
 use std::{
     collections::VecDeque,
@@ -25,6 +25,17 @@ adjust between FPS and ratio:
 
 delay:
     use delay minus RTT as the actual network delay
+
+motion:
+    Each display captures at MOTION_IDLE_FPS while its content is static, and up to the fps above while it moves
+
+audio:
+    Opus bitrate is a share of the bandwidth estimation, or follows the network delay without it.
+    Frames get longer when the bitrate is low, to save the packet overhead.
+    In-band FEC is on when any user reports concealed audio frames, the loss is passed to the encoder.
+
+power:
+    The fps is capped on battery and when the CPU is throttled by the heat, see `power_state`
 */
 
 // Constants
@@ -41,8 +52,23 @@ const MAX_BR_MULTIPLE: f32 = 1.0;
 
 const HISTORY_DELAY_LEN: usize = 2;
 const ADJUST_RATIO_INTERVAL: usize = 3; // Adjust quality ratio every 3 seconds
+const ADJUST_RATIO_INTERVAL_BWE_MS: u128 = 1000; // Bandwidth estimation reacts faster
+const MIN_KBITS_PER_FRAME: u32 = 10; // Lower fps rather than starving each frame
 const DYNAMIC_SCREEN_THRESHOLD: usize = 2; // Allow increase quality ratio if encode more than 2 times in one second
 const DELAY_THRESHOLD_150MS: u32 = 150; // 150ms is the threshold for good network condition
+const AV1_SVC_TEMPORAL_LAYERS: u32 = 3; // Frame rate 1/4, 1/2 and full
+const CHROMA_FALLBACK_SECS: u64 = 5; // Fall back to 4:2:0 after the ratio is at the minimum for 5s
+const CHROMA_RECOVER_SECS: u64 = 30; // Back to 4:4:4 after the ratio is at the target for 30s
+const MOTION_IDLE_FPS: u32 = 5; // Capture rate of a static screen
+const MOTION_IDLE_MS: u128 = 1000; // The screen is static if unchanged for 1s
+const MOTION_HIGH_PERCENT: u32 = 10; // Video or scrolling if more than 10% of the screen changed
+const MOTION_SAMPLE_STEP: usize = 97; // Prime, not aligned with rows or blocks
+const AUDIO_MAX_KBPS: u32 = 96;
+const AUDIO_MIN_KBPS: u32 = 16;
+const AUDIO_BWE_PERCENT: u32 = 10; // Audio takes at most 10% of the estimated bandwidth
+const AUDIO_LOW_KBPS: u32 = 24; // 40ms frames at or below it
+const AUDIO_MEDIUM_KBPS: u32 = 48; // 20ms frames at or below it
+const AUDIO_MAX_LOSS_PERCENT: u32 = 30;
 
 #[derive(Default, Debug, Clone)]
 struct UserDelay {
@@ -93,12 +119,26 @@ struct UserData {
     quality: Option<(i64, Quality)>, // (time, quality)
     delay: UserDelay,
     record: bool,
+    bwe: BandwidthEstimator,
+    av1_svc: bool,
+    hdr: bool,
+    audio_loss: u32,
 }
 
 #[derive(Default, Debug, Clone)]
 struct DisplayData {
     send_counter: usize, // Number of times encode during period
     support_changing_quality: bool,
+    bitrate: u32,
+}
+
+/// Opus encoder settings, shared by all users of the audio service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioQuality {
+    pub kbps: u32,
+    pub frame_ms: u32,
+    pub fec: bool,
+    pub loss_percent: u32,
 }
 
 // Main QoS controller structure
@@ -107,10 +147,12 @@ pub struct VideoQoS {
     ratio: f32,
     users: HashMap<i32, UserData>,
     displays: HashMap<String, DisplayData>,
-    bitrate_store: u32,
     adjust_ratio_instant: Instant,
     abr_config: bool,
+    bwe_config: bool,
     new_user_instant: Instant,
+    chroma_fallback: bool,
+    chroma_state_since: Option<Instant>,
 }
 
 impl Default for VideoQoS {
@@ -120,10 +162,12 @@ impl Default for VideoQoS {
             ratio: BR_BALANCED,
             users: Default::default(),
             displays: Default::default(),
-            bitrate_store: 0,
             adjust_ratio_instant: Instant::now(),
             abr_config: true,
+            bwe_config: true,
             new_user_instant: Instant::now(),
+            chroma_fallback: false,
+            chroma_state_since: None,
         }
     }
 }
@@ -135,24 +179,31 @@ impl VideoQoS {
         Duration::from_secs_f32(1. / (self.fps() as f32))
     }
 
-    // Get current FPS within valid range
+    // Get current FPS within valid range, capped on battery or under thermal throttling
     pub fn fps(&self) -> u32 {
         let fps = self.fps;
-        if fps >= MIN_FPS && fps <= MAX_FPS {
+        let fps = if fps >= MIN_FPS && fps <= MAX_FPS {
             fps
         } else {
             FPS
+        };
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        if let Some(max_fps) = super::power_state::max_fps() {
+            return fps.min(max_fps);
         }
+        fps
     }
 
-    // Store bitrate for later use
-    pub fn store_bitrate(&mut self, bitrate: u32) {
-        self.bitrate_store = bitrate;
+    // Store the bitrate of a display for later use
+    pub fn store_bitrate(&mut self, video_service_name: &str, bitrate: u32) {
+        if let Some(display) = self.displays.get_mut(video_service_name) {
+            display.bitrate = bitrate;
+        }
     }
 
-    // Get stored bitrate
+    // Total bitrate of all displays, which share the bandwidth of a session
     pub fn bitrate(&self) -> u32 {
-        self.bitrate_store
+        self.displays.iter().map(|d| d.1.bitrate).sum()
     }
 
     // Get current bitrate ratio with bounds checking
@@ -168,6 +219,61 @@ impl VideoQoS {
         self.users.iter().any(|u| u.1.record)
     }
 
+    // Temporal layers of AV1, more than 1 if any user can drop layers under congestion
+    pub fn av1_temporal_layers(&self) -> u32 {
+        if self.users.iter().any(|u| u.1.av1_svc) {
+            AV1_SVC_TEMPORAL_LAYERS
+        } else {
+            1
+        }
+    }
+
+    // 4:4:4 needs more bandwidth than 4:2:0 for the same quality, it's not used while the
+    // bandwidth keeps the ratio at the minimum
+    pub fn chroma_fallback(&self) -> bool {
+        self.chroma_fallback
+    }
+
+    // HDR frames are encoded only if all users can tone-map them
+    pub fn hdr(&self) -> bool {
+        !self.users.is_empty() && self.users.iter().all(|u| u.1.hdr)
+    }
+
+    // Audio settings for the worst network of all users
+    pub fn audio_quality(&self) -> AudioQuality {
+        let max_delay = self
+            .users
+            .iter()
+            .map(|u| u.1.delay.avg_delay())
+            .max()
+            .unwrap_or(0);
+        let mut kbps = if max_delay < DELAY_THRESHOLD_150MS {
+            AUDIO_MAX_KBPS
+        } else if max_delay < 300 {
+            AUDIO_MEDIUM_KBPS
+        } else {
+            AUDIO_LOW_KBPS
+        };
+        if let Some(target) = self.bwe_target_bitrate() {
+            kbps = kbps.min(target * AUDIO_BWE_PERCENT / 100);
+        }
+        let kbps = kbps.clamp(AUDIO_MIN_KBPS, AUDIO_MAX_KBPS);
+        let loss_percent = self.users.iter().map(|u| u.1.audio_loss).max().unwrap_or(0);
+        let frame_ms = if kbps <= AUDIO_LOW_KBPS {
+            40
+        } else if kbps <= AUDIO_MEDIUM_KBPS || loss_percent > 0 {
+            20
+        } else {
+            10
+        };
+        AudioQuality {
+            kbps,
+            frame_ms,
+            fec: loss_percent > 0,
+            loss_percent,
+        }
+    }
+
     pub fn set_support_changing_quality(&mut self, video_service_name: &str, support: bool) {
         if let Some(display) = self.displays.get_mut(video_service_name) {
             display.support_changing_quality = support;
@@ -178,6 +284,19 @@ impl VideoQoS {
     pub fn in_vbr_state(&self) -> bool {
         self.abr_config && self.displays.iter().all(|e| e.1.support_changing_quality)
     }
+
+    // Target bitrate of the bandwidth estimation, the lowest of all users,
+    // None until every user has an estimate.
+    fn bwe_target_bitrate(&self) -> Option<u32> {
+        if !self.bwe_config || self.users.is_empty() {
+            return None;
+        }
+        self.users
+            .iter()
+            .map(|u| u.1.bwe.target_bitrate())
+            .collect::<Option<Vec<_>>>()
+            .and_then(|v| v.into_iter().min())
+    }
 }
 
 // User session management
@@ -186,6 +305,7 @@ impl VideoQoS {
     pub fn on_connection_open(&mut self, id: i32) {
         self.users.insert(id, UserData::default());
         self.abr_config = Config::get_option("enable-abr") != "N";
+        self.bwe_config = Config::get_option("enable-bwe") != "N";
         self.new_user_instant = Instant::now();
     }
 
@@ -243,6 +363,30 @@ impl VideoQoS {
         }
     }
 
+    pub fn user_av1_svc(&mut self, id: i32, v: bool) {
+        if let Some(user) = self.users.get_mut(&id) {
+            user.av1_svc = v;
+        }
+    }
+
+    pub fn bwe_stats(&self, id: i32) -> Option<crate::client::BweStats> {
+        let user = self.users.get(&id)?;
+        if self.bwe_config {
+            Some(user.bwe.stats())
+        } else {
+            Some(crate::client::BweStats {
+                state: "Off".to_owned(),
+                ..Default::default()
+            })
+        }
+    }
+
+    pub fn user_hdr(&mut self, id: i32, v: bool) {
+        if let Some(user) = self.users.get_mut(&id) {
+            user.hdr = v;
+        }
+    }
+
     pub fn user_network_delay(&mut self, id: i32, delay: u32) {
         let highest_fps = self.highest_fps();
         let target_ratio = self.latest_quality().ratio();
@@ -262,6 +406,7 @@ impl VideoQoS {
         let mut adjust_ratio = false;
         if let Some(user) = self.users.get_mut(&id) {
             let delay = delay.max(10);
+            user.bwe.on_rtt(Instant::now(), delay);
             let old_avg_delay = user.delay.avg_delay();
             user.delay.add_delay(delay);
             let mut avg_delay = user.delay.avg_delay();
@@ -333,6 +478,19 @@ impl VideoQoS {
         }
     }
 
+    // Percent of the audio frames the user had to conceal recently
+    pub fn user_audio_loss(&mut self, id: i32, percent: u32) {
+        if let Some(user) = self.users.get_mut(&id) {
+            user.audio_loss = percent.min(AUDIO_MAX_LOSS_PERCENT);
+        }
+    }
+
+    pub fn user_video_sent(&mut self, id: i32, bytes: usize, send_time: Duration) {
+        if let Some(user) = self.users.get_mut(&id) {
+            user.bwe.on_sent(Instant::now(), bytes, send_time);
+        }
+    }
+
     pub fn user_delay_response_elapsed(&mut self, id: i32, elapsed: u128) {
         if let Some(user) = self.users.get_mut(&id) {
             user.delay.response_delayed = elapsed > 2000;
@@ -362,7 +520,12 @@ impl VideoQoS {
         self.adjust_fps();
         let abr_enabled = self.in_vbr_state();
         if abr_enabled {
-            if self.adjust_ratio_instant.elapsed().as_secs() >= ADJUST_RATIO_INTERVAL as u64 {
+            let interval_ms = if self.bwe_target_bitrate().is_some() {
+                ADJUST_RATIO_INTERVAL_BWE_MS
+            } else {
+                ADJUST_RATIO_INTERVAL as u128 * 1000
+            };
+            if self.adjust_ratio_instant.elapsed().as_millis() >= interval_ms {
                 let dynamic_screen = self
                     .displays
                     .iter()
@@ -427,17 +590,19 @@ impl VideoQoS {
         let target_ratio = self.latest_quality().ratio();
         let current_ratio = self.ratio;
         let current_bitrate = self.bitrate();
+        // The thresholds below are per display
+        let display_bitrate = current_bitrate / self.displays.len().max(1) as u32;
 
         // Calculate minimum ratio for high resolution (1Mbps baseline)
-        let ratio_1mbps = if current_bitrate > 0 {
-            Some((current_ratio * 1000.0 / current_bitrate as f32).max(BR_MIN_HIGH_RESOLUTION))
+        let ratio_1mbps = if display_bitrate > 0 {
+            Some((current_ratio * 1000.0 / display_bitrate as f32).max(BR_MIN_HIGH_RESOLUTION))
         } else {
             None
         };
 
         // Calculate ratio for adding 150kbps bandwidth
-        let ratio_add_150kbps = if current_bitrate > 0 {
-            Some((current_bitrate + 150) as f32 * current_ratio / current_bitrate as f32)
+        let ratio_add_150kbps = if display_bitrate > 0 {
+            Some((display_bitrate + 150) as f32 * current_ratio / display_bitrate as f32)
         } else {
             None
         };
@@ -470,41 +635,71 @@ impl VideoQoS {
 
         let mut v = current_ratio;
 
-        // Adjust ratio based on network delay thresholds
-        if max_delay < 50 {
-            if dynamic_screen {
-                v = current_ratio * 1.15;
-            }
-        } else if max_delay < 100 {
-            if dynamic_screen {
-                v = current_ratio * 1.1;
-            }
-        } else if max_delay < DELAY_THRESHOLD_150MS {
-            if dynamic_screen {
-                v = current_ratio * 1.05;
-            }
-        } else if max_delay < 200 {
-            v = current_ratio * 0.95;
-        } else if max_delay < 300 {
-            v = current_ratio * 0.9;
-        } else if max_delay < 500 {
-            v = current_ratio * 0.85;
+        if let (Some(target), true) = (self.bwe_target_bitrate(), current_bitrate > 0) {
+            // The encoder bitrate is proportional to the ratio
+            v = current_ratio * target as f32 / current_bitrate as f32;
+            log::debug!(
+                "bwe target bitrate: {}kbps, bandwidth: {:?}kbps",
+                target,
+                self.users.iter().filter_map(|u| u.1.bwe.bandwidth()).min()
+            );
         } else {
-            v = current_ratio * 0.8;
-        }
+            // Adjust ratio based on network delay thresholds
+            if max_delay < 50 {
+                if dynamic_screen {
+                    v = current_ratio * 1.15;
+                }
+            } else if max_delay < 100 {
+                if dynamic_screen {
+                    v = current_ratio * 1.1;
+                }
+            } else if max_delay < DELAY_THRESHOLD_150MS {
+                if dynamic_screen {
+                    v = current_ratio * 1.05;
+                }
+            } else if max_delay < 200 {
+                v = current_ratio * 0.95;
+            } else if max_delay < 300 {
+                v = current_ratio * 0.9;
+            } else if max_delay < 500 {
+                v = current_ratio * 0.85;
+            } else {
+                v = current_ratio * 0.8;
+            }
 
-        // Limit quality increase rate for better stability
-        if let Some(ratio_add_150kbps) = ratio_add_150kbps {
-            if v > ratio_add_150kbps
-                && ratio_add_150kbps > current_ratio
-                && current_ratio >= BR_SPEED
-            {
-                v = ratio_add_150kbps;
+            // Limit quality increase rate for better stability
+            if let Some(ratio_add_150kbps) = ratio_add_150kbps {
+                if v > ratio_add_150kbps
+                    && ratio_add_150kbps > current_ratio
+                    && current_ratio >= BR_SPEED
+                {
+                    v = ratio_add_150kbps;
+                }
             }
         }
 
         self.ratio = v.clamp(min, max);
         self.adjust_ratio_instant = Instant::now();
+        self.update_chroma_fallback(v <= min, v >= max);
+    }
+
+    // Switch chroma only after the bandwidth stays low or high, to avoid switching back and forth
+    fn update_chroma_fallback(&mut self, at_min: bool, at_max: bool) {
+        let (changing, secs) = if self.chroma_fallback {
+            (at_max, CHROMA_RECOVER_SECS)
+        } else {
+            (at_min, CHROMA_FALLBACK_SECS)
+        };
+        if !changing {
+            self.chroma_state_since = None;
+            return;
+        }
+        let since = *self.chroma_state_since.get_or_insert_with(Instant::now);
+        if since.elapsed() >= Duration::from_secs(secs) {
+            self.chroma_fallback = !self.chroma_fallback;
+            self.chroma_state_since = None;
+            log::info!("chroma fallback: {}", self.chroma_fallback);
+        }
     }
 
     // Adjust fps based on network delay and user response time
@@ -524,6 +719,12 @@ impl VideoQoS {
             }
         }
 
+        // Do not send more frames than the estimated bandwidth can carry, for every display
+        if let Some(target) = self.bwe_target_bitrate() {
+            let displays = self.displays.len().max(1) as u32;
+            fps = fps.min((target / displays / MIN_KBITS_PER_FRAME).max(MIN_FPS));
+        }
+
         // For new connections (within 1 second), cap fps to INIT_FPS to ensure stability
         if self.new_user_instant.elapsed().as_secs() < 1 {
             if fps > INIT_FPS {
@@ -536,6 +737,73 @@ impl VideoQoS {
     }
 }
 
+/// Capture rate of a display by the motion of its content.
+///
+/// Static screens are captured at `MOTION_IDLE_FPS`, large changes like video or scrolling
+/// switch to the fps of the qos at once, small changes like typing double the rate each frame.
+pub struct MotionFps {
+    fps: u32,
+    last_change: Instant,
+    samples: Vec<u8>,
+}
+
+impl Default for MotionFps {
+    fn default() -> Self {
+        Self {
+            fps: MAX_FPS,
+            last_change: Instant::now(),
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl MotionFps {
+    /// Percent of the sampled bytes changed since the last frame.
+    pub fn changed_percent(&mut self, data: &[u8]) -> u32 {
+        let count = data.len() / MOTION_SAMPLE_STEP;
+        if count == 0 {
+            return 100;
+        }
+        if self.samples.len() != count {
+            self.samples = (0..count).map(|i| data[i * MOTION_SAMPLE_STEP]).collect();
+            return 100;
+        }
+        let mut changed = 0;
+        for (i, s) in self.samples.iter_mut().enumerate() {
+            let v = data[i * MOTION_SAMPLE_STEP];
+            if *s != v {
+                *s = v;
+                changed += 1;
+            }
+        }
+        (changed * 100 / count) as u32
+    }
+
+    /// Updates with the changed percent of the new frame, None if no new frame.
+    pub fn update(&mut self, changed_percent: Option<u32>) {
+        match changed_percent {
+            Some(p) if p >= MOTION_HIGH_PERCENT => {
+                self.last_change = Instant::now();
+                self.fps = MAX_FPS;
+            }
+            Some(_) => {
+                self.last_change = Instant::now();
+                self.fps = (self.fps * 2).min(MAX_FPS);
+            }
+            None => {
+                if self.last_change.elapsed().as_millis() >= MOTION_IDLE_MS {
+                    self.fps = MOTION_IDLE_FPS;
+                }
+            }
+        }
+    }
+
+    /// The seconds per frame, not less than `spf` of the qos.
+    pub fn spf(&self, spf: Duration) -> Duration {
+        spf.max(Duration::from_secs_f32(1. / self.fps as f32))
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 struct RttCalculator {
     min_rtt: Option<u32>,        // Historical minimum RTT ever observed
@@ -18,7 +18,12 @@
 // to-do:
 // https://slhck.info/video/2017/03/01/rate-control.html
 
-use super::{display_service::check_display_changed, service::ServiceTmpl, video_qos::VideoQoS, *};
+use super::{
+    display_service::check_display_changed,
+    service::ServiceTmpl,
+    video_qos::{MotionFps, VideoQoS},
+    *,
+};
 #[cfg(target_os = "linux")]
 use crate::common::SimpleCallOnReturn;
 #[cfg(target_os = "linux")]
@@ -61,6 +66,7 @@ use std::{
 };
 
 pub const OPTION_REFRESH: &'static str = "refresh";
+const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(3);
 
 type FrameFetchedNotifierSender = UnboundedSender<(i32, Option<Instant>)>;
 type FrameFetchedNotifierReceiver = Arc<TokioMutex<UnboundedReceiver<(i32, Option<Instant>)>>>;
@@ -77,7 +83,17 @@ lazy_static::lazy_static! {
     pub static ref VIDEO_QOS: Arc<Mutex<VideoQoS>> = Default::default();
     pub static ref IS_UAC_RUNNING: Arc<Mutex<bool>> = Default::default();
     pub static ref IS_FOREGROUND_WINDOW_ELEVATED: Arc<Mutex<bool>> = Default::default();
+    pub static ref IS_SECURE_DESKTOP: Arc<Mutex<bool>> = Default::default();
     static ref SCREENSHOTS: Mutex<HashMap<usize, Screenshot>> = Default::default();
+    // The clock of the timestamps of both the video and the audio frames.
+    static ref MEDIA_EPOCH: Instant = Instant::now();
+}
+
+/// Milliseconds of the media clock, the audio frames are timestamped with it
+/// for the peer to sync them with the video.
+#[inline]
+pub fn media_time_ms() -> i64 {
+    MEDIA_EPOCH.elapsed().as_millis() as _
 }
 
 struct Screenshot {
@@ -390,6 +406,9 @@ fn get_capturer_monitor(
 ) -> ResultType<CapturerInfo> {
     #[cfg(target_os = "linux")]
     {
+        if super::vm_console::is_enabled() {
+            return super::vm_console::get_capturer_for_display(current);
+        }
         if !is_x11() {
             return super::wayland::get_capturer_for_display(current);
         }
@@ -537,7 +556,9 @@ fn run(vs: VideoService) -> ResultType<()> {
     // to-do: wayland ensure_inited should pass current display index.
     // But for now, we do not support multi-screen capture on wayland.
     #[cfg(target_os = "linux")]
-    super::wayland::ensure_inited()?;
+    if !super::vm_console::is_enabled() {
+        super::wayland::ensure_inited()?;
+    }
     #[cfg(target_os = "linux")]
     let _wayland_call_on_ret = {
         // Increment active display count when starting
@@ -574,14 +595,24 @@ fn run(vs: VideoService) -> ResultType<()> {
     let record_incoming = config::option2bool(
         "allow-auto-record-incoming",
         &Config::get_option("allow-auto-record-incoming"),
-    );
+    ) || super::record_signing::is_enabled();
     let client_record = video_qos.record();
+    let temporal_layers = video_qos.av1_temporal_layers();
+    let chroma_fallback = video_qos.chroma_fallback();
+    // None if the capturer is SDR
+    #[cfg(windows)]
+    let hdr = c.is_hdr().then(|| video_qos.hdr());
+    #[cfg(not(windows))]
+    let hdr: Option<bool> = None;
     drop(video_qos);
     let (mut encoder, encoder_cfg, codec_format, use_i444, recorder) = match setup_encoder(
         &c,
         sp.name(),
         quality,
         client_record,
+        temporal_layers,
+        hdr == Some(true),
+        chroma_fallback,
         record_incoming,
         last_portable_service_running,
         vs.source,
@@ -602,6 +633,9 @@ fn run(vs: VideoService) -> ResultType<()> {
                 sp.name(),
                 quality,
                 client_record,
+                temporal_layers,
+                hdr == Some(true),
+                chroma_fallback,
                 record_incoming,
                 last_portable_service_running,
                 vs.source,
@@ -611,6 +645,13 @@ fn run(vs: VideoService) -> ResultType<()> {
     };
     #[cfg(feature = "vram")]
     c.set_output_texture(encoder.input_texture());
+    #[cfg(windows)]
+    if !encoder.input_texture() {
+        let pixfmt = encoder.yuvfmt().pixfmt;
+        let nv12 =
+            c.set_output_nv12(pixfmt == scrap::Pixfmt::NV12 || pixfmt == scrap::Pixfmt::I420);
+        log::info!("gpu nv12: {nv12}, encoder pixfmt: {pixfmt:?}");
+    }
     #[cfg(target_os = "android")]
     if vs.source.is_monitor() {
         if let Err(e) = check_change_scale(encoder.is_hardware()) {
@@ -618,7 +659,10 @@ fn run(vs: VideoService) -> ResultType<()> {
             bail!(e);
         }
     }
-    VIDEO_QOS.lock().unwrap().store_bitrate(encoder.bitrate());
+    VIDEO_QOS
+        .lock()
+        .unwrap()
+        .store_bitrate(&sp.name(), encoder.bitrate());
     VIDEO_QOS
         .lock()
         .unwrap()
@@ -631,7 +675,6 @@ fn run(vs: VideoService) -> ResultType<()> {
 
     let mut frame_controller = VideoFrameController::new(display_idx);
 
-    let start = time::Instant::now();
     let mut last_check_displays = time::Instant::now();
     #[cfg(windows)]
     let mut try_gdi = 1;
@@ -651,6 +694,7 @@ fn run(vs: VideoService) -> ResultType<()> {
     let capture_width = c.width;
     let capture_height = c.height;
     let (mut second_instant, mut send_counter) = (Instant::now(), 0);
+    let mut motion_fps = MotionFps::default();
 
     while sp.ok() {
         #[cfg(windows)]
@@ -660,6 +704,9 @@ fn run(vs: VideoService) -> ResultType<()> {
             &mut quality,
             &mut spf,
             client_record,
+            temporal_layers,
+            hdr,
+            Encoder::use_i444(&encoder_cfg).then_some(chroma_fallback),
             &mut send_counter,
             &mut second_instant,
             &sp.name(),
@@ -684,7 +731,7 @@ fn run(vs: VideoService) -> ResultType<()> {
             log::info!("switch due to portable service running changed");
             bail!("SWITCH");
         }
-        if Encoder::use_i444(&encoder_cfg) != use_i444 {
+        if (Encoder::use_i444(&encoder_cfg) && !chroma_fallback) != use_i444 {
             log::info!("switch due to i444 changed");
             bail!("SWITCH");
         }
@@ -702,7 +749,17 @@ fn run(vs: VideoService) -> ResultType<()> {
             if crate::platform::windows::desktop_changed()
                 && !crate::portable_service::client::running()
             {
-                bail!("Desktop changed");
+                // Follow the input desktop in place, e.g. the secure desktop of UAC,
+                // instead of waiting for the error timeout of the service.
+                if crate::platform::windows::try_change_desktop() {
+                    log::info!("Capture the new input desktop");
+                    bail!("SWITCH");
+                }
+                // Without the rights to switch to the secure desktop, keep the capturer
+                // rather than recreating it in loop, the peer is asked to elevate.
+                if !crate::platform::windows::is_secure_desktop() {
+                    bail!("Desktop changed");
+                }
             }
         }
         let now = time::Instant::now();
@@ -715,9 +772,10 @@ fn run(vs: VideoService) -> ResultType<()> {
 
         frame_controller.reset();
 
-        let time = now - start;
+        let time = now - *MEDIA_EPOCH;
         let ms = (time.as_secs() * 1000 + time.subsec_millis() as u64) as i64;
-        let res = match c.frame(spf) {
+        let mut changed_percent = None;
+        let res = match c.frame(motion_fps.spf(spf)) {
             Ok(frame) => {
                 repeat_encode_counter = 0;
                 if frame.valid() {
@@ -765,6 +823,12 @@ fn run(vs: VideoService) -> ResultType<()> {
                     }
 
                     let frame = frame.to(encoder.yuvfmt(), &mut yuv, &mut mid_data)?;
+                    changed_percent = Some(match &frame {
+                        EncodeInput::YUV(yuv) => motion_fps.changed_percent(yuv),
+                        _ => 100,
+                    });
+                    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                    encoder.set_cursor_pos(cursor_pos_in_display(&c));
                     let send_conn_ids = handle_one_frame(
                         display_idx,
                         &sp,
@@ -879,6 +943,8 @@ fn run(vs: VideoService) -> ResultType<()> {
         }
         DISPLAY_CONN_IDS.lock().unwrap().remove(&display_idx);
 
+        motion_fps.update(changed_percent);
+        let spf = motion_fps.spf(spf);
         let elapsed = now.elapsed();
         // may need to enable frame(timeout)
         log::trace!("{:?} {:?}", time::Instant::now(), elapsed);
@@ -927,6 +993,9 @@ fn setup_encoder(
     name: String,
     quality: f32,
     client_record: bool,
+    temporal_layers: u32,
+    hdr: bool,
+    chroma_fallback: bool,
     record_incoming: bool,
     last_portable_service_running: bool,
     source: VideoSource,
@@ -943,13 +1012,16 @@ fn setup_encoder(
         name.to_string(),
         quality,
         client_record || record_incoming,
+        temporal_layers,
+        hdr,
         last_portable_service_running,
         source,
     );
     Encoder::set_fallback(&encoder_cfg);
     let codec_format = Encoder::negotiated_codec();
     let recorder = get_recorder(record_incoming, display_idx, source == VideoSource::Camera);
-    let use_i444 = Encoder::use_i444(&encoder_cfg);
+    // 4:4:4 if all peers prefer and decode it, unless the bandwidth is insufficient
+    let use_i444 = Encoder::use_i444(&encoder_cfg) && !chroma_fallback;
     let encoder = Encoder::new(encoder_cfg.clone(), use_i444)?;
     Ok((encoder, encoder_cfg, codec_format, use_i444, recorder))
 }
@@ -959,12 +1031,19 @@ fn get_encoder_config(
     _name: String,
     quality: f32,
     record: bool,
+    temporal_layers: u32,
+    hdr: bool,
     _portable_service: bool,
     _source: VideoSource,
 ) -> EncoderCfg {
     #[cfg(all(windows, feature = "vram"))]
-    if _portable_service || c.is_gdi() || _source == VideoSource::Camera {
-        log::info!("gdi:{}, portable:{}", c.is_gdi(), _portable_service);
+    if _portable_service || c.is_gdi() || c.is_hdr() || _source == VideoSource::Camera {
+        log::info!(
+            "gdi:{}, portable:{}, hdr:{}",
+            c.is_gdi(),
+            _portable_service,
+            c.is_hdr()
+        );
         VRamEncoder::set_not_use(_name, true);
     }
     #[cfg(feature = "vram")]
@@ -1015,12 +1094,30 @@ fn get_encoder_config(
             },
             keyframe_interval,
         }),
-        CodecFormat::AV1 => EncoderCfg::AOM(AomEncoderConfig {
-            width: c.width as _,
-            height: c.height as _,
-            quality,
-            keyframe_interval,
-        }),
+        CodecFormat::AV1 => {
+            // Hardware first, temporal layers and 10-bit HDR are only encoded by libaom.
+            #[cfg(feature = "hwcodec")]
+            if !hdr {
+                if let Some(hw) = HwRamEncoder::try_get(negotiated_codec) {
+                    return EncoderCfg::HWRAM(HwRamEncoderConfig {
+                        name: hw.name,
+                        mc_name: hw.mc_name,
+                        width: c.width,
+                        height: c.height,
+                        quality,
+                        keyframe_interval,
+                    });
+                }
+            }
+            EncoderCfg::AOM(AomEncoderConfig {
+                width: c.width as _,
+                height: c.height as _,
+                quality,
+                keyframe_interval,
+                temporal_layers,
+                hdr,
+            })
+        }
         _ => EncoderCfg::VPX(VpxEncoderConfig {
             width: c.width as _,
             height: c.height as _,
@@ -1043,13 +1140,18 @@ fn get_recorder(
     let recorder = if record_incoming {
         use crate::hbbs_http::record_upload;
 
-        let tx = if record_upload::is_enable() {
+        let mut tx = if record_upload::is_enable() {
             let (tx, rx) = std::sync::mpsc::channel();
             record_upload::run(rx);
             Some(tx)
         } else {
             None
         };
+        if super::record_signing::is_enabled() {
+            let (tx_sign, rx) = std::sync::mpsc::channel();
+            super::record_signing::run(rx, tx);
+            tx = Some(tx_sign);
+        }
         Recorder::new(RecorderContext {
             server: true,
             id: Config::get_id(),
@@ -1057,6 +1159,7 @@ fn get_recorder(
             display_idx,
             camera,
             tx,
+            audio: None,
         })
         .map_or(Default::default(), |r| Arc::new(Mutex::new(Some(r))))
     } else {
@@ -1125,6 +1228,17 @@ fn check_privacy_mode_changed(
     Ok(())
 }
 
+// The cursor position relative to the captured display, None if it's on another display.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn cursor_pos_in_display(c: &CapturerInfo) -> Option<(usize, usize)> {
+    let (x, y) = crate::get_cursor_pos()?;
+    let (x, y) = (x - c.origin.0, y - c.origin.1);
+    if x < 0 || y < 0 || x as usize >= c.width || y as usize >= c.height {
+        return None;
+    }
+    Some((x as _, y as _))
+}
+
 #[inline]
 fn handle_one_frame(
     display: usize,
@@ -1150,8 +1264,10 @@ fn handle_one_frame(
     let mut send_conn_ids: HashSet<i32> = Default::default();
     let first = *first_frame;
     *first_frame = false;
+    let encode_instant = Instant::now();
     match encoder.encode_to_message(frame, ms) {
         Ok(mut vf) => {
+            super::metrics::on_frame_encoded(encode_instant.elapsed());
             *encode_fail_counter = 0;
             vf.display = display as _;
             let mut msg = Message::new();
@@ -1164,6 +1280,7 @@ fn handle_one_frame(
             send_conn_ids = sp.send_video_frame(msg);
         }
         Err(e) => {
+            super::metrics::on_encode_failed();
             *encode_fail_counter += 1;
             // Encoding errors are not frequent except on Android
             if !cfg!(target_os = "android") {
@@ -1213,6 +1330,7 @@ fn start_uac_elevation_check() {
                 if let Ok(uac) = is_process_consent_running() {
                     *IS_UAC_RUNNING.lock().unwrap() = uac;
                 }
+                *IS_SECURE_DESKTOP.lock().unwrap() = crate::platform::windows::is_secure_desktop();
                 if !crate::platform::is_elevated(None).unwrap_or(false) {
                     if let Ok(elevated) = crate::platform::is_foreground_window_elevated() {
                         *IS_FOREGROUND_WINDOW_ELEVATED.lock().unwrap() = elevated;
@@ -1312,6 +1430,9 @@ fn check_qos(
     ratio: &mut f32,
     spf: &mut Duration,
     client_record: bool,
+    temporal_layers: u32,
+    hdr: Option<bool>,
+    chroma_fallback: Option<bool>,
     send_counter: &mut usize,
     second_instant: &mut Instant,
     name: &str,
@@ -1322,7 +1443,7 @@ fn check_qos(
         *ratio = video_qos.ratio();
         if encoder.support_changing_quality() {
             allow_err!(encoder.set_quality(*ratio));
-            video_qos.store_bitrate(encoder.bitrate());
+            video_qos.store_bitrate(name, encoder.bitrate());
         } else {
             // Now only vaapi doesn't support changing quality
             if !video_qos.in_vbr_state() && !video_qos.latest_quality().is_custom() {
@@ -1335,6 +1456,23 @@ fn check_qos(
         log::info!("switch due to record changed");
         bail!("SWITCH");
     }
+    if temporal_layers != video_qos.av1_temporal_layers()
+        && Encoder::negotiated_codec() == CodecFormat::AV1
+    {
+        log::info!("switch due to av1 temporal layers changed");
+        bail!("SWITCH");
+    }
+    if hdr.map_or(false, |hdr| hdr != video_qos.hdr())
+        && Encoder::negotiated_codec() == CodecFormat::AV1
+    {
+        log::info!("switch due to hdr changed");
+        bail!("SWITCH");
+    }
+    // None if 4:4:4 is not preferred
+    if chroma_fallback.map_or(false, |fallback| fallback != video_qos.chroma_fallback()) {
+        log::info!("switch due to chroma fallback changed");
+        bail!("SWITCH");
+    }
     if second_instant.elapsed() > Duration::from_secs(1) {
         *second_instant = Instant::now();
         video_qos.update_display_data(&name, *send_counter);
@@ -1355,6 +1493,56 @@ pub fn set_take_screenshot(display_idx: usize, sid: String, tx: Sender) {
     );
 }
 
+/// Takes a screenshot by a new capturer, for the display which is not streamed to the peer.
+pub fn take_screenshot_once(source: VideoSource, display_idx: usize, sid: String, tx: Sender) {
+    std::thread::spawn(move || {
+        let screenshot = Screenshot {
+            sid,
+            tx,
+            restore_vram: false,
+        };
+        let (msg, w, h, data) = match capture_once(source, display_idx) {
+            Ok((w, h, data)) => ("".to_owned(), w, h, data),
+            Err(e) => {
+                log::error!(
+                    "Failed to take screenshot of display {}, {}",
+                    display_idx,
+                    e
+                );
+                (e.to_string(), 0, 0, vec![])
+            }
+        };
+        handle_screenshot(screenshot, msg, w, h, data);
+    });
+}
+
+fn capture_once(source: VideoSource, display_idx: usize) -> ResultType<(usize, usize, Vec<u8>)> {
+    #[cfg(windows)]
+    let portable_service_running = crate::portable_service::client::running();
+    #[cfg(not(windows))]
+    let portable_service_running = false;
+    let mut c = get_capturer(source, display_idx, portable_service_running)?;
+    let start = Instant::now();
+    // The first frames may be empty, e.g. the camera is warming up.
+    while start.elapsed() < SCREENSHOT_TIMEOUT {
+        match c.frame(Duration::from_millis(100)) {
+            Ok(frame) => match &frame {
+                scrap::Frame::PixelBuffer(f) if frame.valid() => {
+                    return Ok((f.width(), f.height(), get_rgba_from_pixelbuf(f)?));
+                }
+                scrap::Frame::Texture(_) => {
+                    bail!("Please change codec and try again.");
+                }
+                _ => {}
+            },
+            Err(e) if e.kind() == WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+        std::thread::sleep(Duration::from_millis(30));
+    }
+    bail!("Timeout")
+}
+
 // We need to this function, because the `stride` may be larger than `width * 4`.
 fn get_rgba_from_pixelbuf<'a>(pixbuf: &scrap::PixelBuffer<'a>) -> ResultType<Vec<u8>> {
     let w = pixbuf.width();
@@ -1364,7 +1552,7 @@ fn get_rgba_from_pixelbuf<'a>(pixbuf: &scrap::PixelBuffer<'a>) -> ResultType<Vec
         bail!("Invalid pixel buf stride.")
     };
 
-    if *s == w * 4 {
+    if *s == w * 4 || pixbuf.pixfmt() != scrap::Pixfmt::BGRA {
         let mut rgba = vec![];
         scrap::convert(pixbuf, scrap::Pixfmt::RGBA, &mut rgba)?;
         Ok(rgba)
@@ -0,0 +1,816 @@
+// VM console mode, the displays and the input of this device are replaced by the console of a
+// local virtual machine, so the hypervisor console gets the NAT traversal, the encryption and
+// the client UI of RustDesk.
+//
+// The console is read from the VNC server of QEMU/KVM, on localhost or a unix socket, e.g.
+// `-vnc unix:/run/rustdesk-vm100.vnc` in the `args:` of a Proxmox VM, then
+// `rustdesk --option vm-console /run/rustdesk-vm100.vnc`.
+//
+// Only the security type "None" is supported, the socket is expected to be reachable by root only.
+// The keys are sent with the QEMU extended key event (physical keys) if the server supports it,
+// otherwise as keysyms, so the map mode requires QEMU.
+use super::video_service::CapturerInfo;
+use hbb_common::{
+    bail,
+    config::Config,
+    log,
+    message_proto::{
+        key_event, ControlKey, DisplayInfo, KeyEvent, KeyboardMode, MouseEvent, Resolution,
+    },
+    ResultType,
+};
+use scrap::{Frame, PixelBuffer, Pixfmt, TraitCapturer};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+pub const OPTION_VM_CONSOLE: &str = "vm-console";
+
+const RFB_VERSION: &[u8; 12] = b"RFB 003.008\n";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const SECURITY_NONE: u8 = 1;
+
+const ENCODING_RAW: i32 = 0;
+const ENCODING_COPY_RECT: i32 = 1;
+const ENCODING_DESKTOP_SIZE: i32 = -223;
+const ENCODING_QEMU_EXTENDED_KEY_EVENT: i32 = -258;
+const ENCODING_EXTENDED_DESKTOP_SIZE: i32 = -308;
+
+const BYTES_PER_PIXEL: usize = 4;
+const DISPLAY_NAME: &str = "VM console";
+
+// RFB button mask.
+const BUTTON_LEFT: u8 = 1 << 0;
+const BUTTON_MIDDLE: u8 = 1 << 1;
+const BUTTON_RIGHT: u8 = 1 << 2;
+const BUTTON_WHEEL_UP: u8 = 1 << 3;
+const BUTTON_WHEEL_DOWN: u8 = 1 << 4;
+const BUTTON_WHEEL_LEFT: u8 = 1 << 5;
+const BUTTON_WHEEL_RIGHT: u8 = 1 << 6;
+
+lazy_static::lazy_static! {
+    static ref CONSOLE: Mutex<Option<Arc<Console>>> = Default::default();
+}
+
+#[inline]
+pub fn get_address() -> String {
+    Config::get_option(OPTION_VM_CONSOLE).trim().to_owned()
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    !get_address().is_empty()
+}
+
+#[derive(Default)]
+struct Framebuffer {
+    width: usize,
+    height: usize,
+    // BGRA, see `set_pixel_format()`.
+    data: Vec<u8>,
+    // Increased on every update, the capturer skips the unchanged frames.
+    seq: u64,
+}
+
+impl Framebuffer {
+    fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.data = vec![0; width * height * BYTES_PER_PIXEL];
+        self.seq += 1;
+    }
+}
+
+struct Pointer {
+    buttons: u8,
+    x: i32,
+    y: i32,
+}
+
+struct Console {
+    addr: String,
+    writer: Mutex<Stream>,
+    fb: Mutex<Framebuffer>,
+    pointer: Mutex<Pointer>,
+    qemu_key_event: AtomicBool,
+    alive: AtomicBool,
+}
+
+// Gets the connected console, or connects to the console of the option.
+fn get_console() -> ResultType<Arc<Console>> {
+    let addr = get_address();
+    if addr.is_empty() {
+        bail!("VM console is not enabled");
+    }
+    let mut lock = CONSOLE.lock().unwrap();
+    if let Some(console) = lock.as_ref() {
+        if console.addr == addr && console.alive.load(Ordering::SeqCst) {
+            return Ok(console.clone());
+        }
+    }
+    let console = Console::connect(addr)?;
+    *lock = Some(console.clone());
+    Ok(console)
+}
+
+enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Stream {
+    fn connect(addr: &str) -> io::Result<Self> {
+        if addr.starts_with('/') {
+            Ok(Self::Unix(UnixStream::connect(addr)?))
+        } else {
+            let stream = TcpStream::connect(addr)?;
+            stream.set_nodelay(true).ok();
+            Ok(Self::Tcp(stream))
+        }
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Self::Unix(s) => Self::Unix(s.try_clone()?),
+            Self::Tcp(s) => Self::Tcp(s.try_clone()?),
+        })
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Unix(s) => s.set_read_timeout(timeout),
+            Self::Tcp(s) => s.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Unix(s) => s.read(buf),
+            Self::Tcp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Unix(s) => s.write(buf),
+            Self::Tcp(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Unix(s) => s.flush(),
+            Self::Tcp(s) => s.flush(),
+        }
+    }
+}
+
+impl Console {
+    fn connect(addr: String) -> ResultType<Arc<Self>> {
+        let mut writer = Stream::connect(&addr)?;
+        writer.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let mut reader = writer.try_clone()?;
+        let (width, height, name) = handshake(&mut reader, &mut writer)?;
+        reader.set_read_timeout(None)?;
+        log::info!(
+            "VM console {} connected, name: {}, {}x{}",
+            addr,
+            name,
+            width,
+            height
+        );
+        set_pixel_format(&mut writer)?;
+        set_encodings(&mut writer)?;
+        let mut fb = Framebuffer::default();
+        fb.resize(width, height);
+        let console = Arc::new(Self {
+            addr,
+            writer: Mutex::new(writer),
+            fb: Mutex::new(fb),
+            pointer: Mutex::new(Pointer {
+                buttons: 0,
+                x: 0,
+                y: 0,
+            }),
+            qemu_key_event: AtomicBool::new(false),
+            alive: AtomicBool::new(true),
+        });
+        console.request_update(false)?;
+        let cloned = console.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = cloned.read_loop(reader) {
+                log::error!("VM console {} disconnected: {}", cloned.addr, e);
+            }
+            cloned.alive.store(false, Ordering::SeqCst);
+        });
+        Ok(console)
+    }
+
+    fn send(&self, buf: &[u8]) -> io::Result<()> {
+        let res = self.writer.lock().unwrap().write_all(buf);
+        if res.is_err() {
+            self.alive.store(false, Ordering::SeqCst);
+        }
+        res
+    }
+
+    fn request_update(&self, incremental: bool) -> io::Result<()> {
+        let (width, height) = {
+            let fb = self.fb.lock().unwrap();
+            (fb.width as u16, fb.height as u16)
+        };
+        let mut buf = vec![3, incremental as u8, 0, 0, 0, 0];
+        buf.extend_from_slice(&width.to_be_bytes());
+        buf.extend_from_slice(&height.to_be_bytes());
+        self.send(&buf)
+    }
+
+    fn read_loop(&self, mut reader: Stream) -> ResultType<()> {
+        loop {
+            match read_u8(&mut reader)? {
+                // FramebufferUpdate
+                0 => {
+                    read_u8(&mut reader)?;
+                    let n = read_u16(&mut reader)?;
+                    for _ in 0..n {
+                        self.read_rect(&mut reader)?;
+                    }
+                    self.fb.lock().unwrap().seq += 1;
+                    self.request_update(true)?;
+                }
+                // SetColourMapEntries, not used with the true colour format.
+                1 => {
+                    read_u8(&mut reader)?;
+                    read_u16(&mut reader)?;
+                    let n = read_u16(&mut reader)?;
+                    skip(&mut reader, n as usize * 6)?;
+                }
+                // Bell
+                2 => {}
+                // ServerCutText
+                3 => {
+                    skip(&mut reader, 3)?;
+                    let len = read_u32(&mut reader)?;
+                    skip(&mut reader, len as usize)?;
+                }
+                t => bail!("Unknown server message type {}", t),
+            }
+            if !self.alive.load(Ordering::SeqCst) {
+                bail!("closed");
+            }
+        }
+    }
+
+    fn read_rect(&self, reader: &mut Stream) -> ResultType<()> {
+        let x = read_u16(reader)? as usize;
+        let y = read_u16(reader)? as usize;
+        let w = read_u16(reader)? as usize;
+        let h = read_u16(reader)? as usize;
+        let encoding = read_u32(reader)? as i32;
+        match encoding {
+            ENCODING_RAW => {
+                let mut data = vec![0u8; w * h * BYTES_PER_PIXEL];
+                reader.read_exact(&mut data)?;
+                let mut fb = self.fb.lock().unwrap();
+                if x + w > fb.width || y + h > fb.height {
+                    bail!("Rect out of the framebuffer");
+                }
+                let stride = fb.width * BYTES_PER_PIXEL;
+                let row = w * BYTES_PER_PIXEL;
+                for i in 0..h {
+                    let dst = (y + i) * stride + x * BYTES_PER_PIXEL;
+                    fb.data[dst..dst + row].copy_from_slice(&data[i * row..(i + 1) * row]);
+                }
+            }
+            ENCODING_COPY_RECT => {
+                let src_x = read_u16(reader)? as usize;
+                let src_y = read_u16(reader)? as usize;
+                let mut fb = self.fb.lock().unwrap();
+                if x + w > fb.width
+                    || y + h > fb.height
+                    || src_x + w > fb.width
+                    || src_y + h > fb.height
+                {
+                    bail!("Rect out of the framebuffer");
+                }
+                let stride = fb.width * BYTES_PER_PIXEL;
+                let row = w * BYTES_PER_PIXEL;
+                let copy_row = |fb: &mut Framebuffer, i: usize| {
+                    let src = (src_y + i) * stride + src_x * BYTES_PER_PIXEL;
+                    let dst = (y + i) * stride + x * BYTES_PER_PIXEL;
+                    fb.data.copy_within(src..src + row, dst);
+                };
+                // The rows may overlap.
+                if y > src_y {
+                    (0..h).rev().for_each(|i| copy_row(&mut fb, i));
+                } else {
+                    (0..h).for_each(|i| copy_row(&mut fb, i));
+                }
+            }
+            ENCODING_DESKTOP_SIZE => {
+                log::info!("VM console resized to {}x{}", w, h);
+                self.fb.lock().unwrap().resize(w, h);
+                self.request_update(false)?;
+            }
+            ENCODING_EXTENDED_DESKTOP_SIZE => {
+                let n = read_u8(reader)?;
+                skip(reader, 3 + n as usize * 16)?;
+                // x is the reason, 0 is the change of the server.
+                let changed = {
+                    let fb = self.fb.lock().unwrap();
+                    fb.width != w || fb.height != h
+                };
+                if changed {
+                    log::info!("VM console resized to {}x{}, reason {}", w, h, x);
+                    self.fb.lock().unwrap().resize(w, h);
+                    self.request_update(false)?;
+                }
+            }
+            ENCODING_QEMU_EXTENDED_KEY_EVENT => {
+                self.qemu_key_event.store(true, Ordering::SeqCst);
+            }
+            _ => bail!("Unsupported encoding {}", encoding),
+        }
+        Ok(())
+    }
+
+    fn send_pointer(&self, pointer: &Pointer) -> io::Result<()> {
+        let mut buf = vec![5, pointer.buttons];
+        buf.extend_from_slice(&(pointer.x as u16).to_be_bytes());
+        buf.extend_from_slice(&(pointer.y as u16).to_be_bytes());
+        self.send(&buf)
+    }
+
+    fn send_keysym(&self, keysym: u32, down: bool) -> io::Result<()> {
+        let mut buf = vec![4, down as u8, 0, 0];
+        buf.extend_from_slice(&keysym.to_be_bytes());
+        self.send(&buf)
+    }
+
+    // The keycode is the XT scancode of QEMU, with 0x80 for the 0xE0 prefix.
+    fn send_qemu_key(&self, keycode: u32, down: bool) -> io::Result<()> {
+        let mut buf = vec![255, 0];
+        buf.extend_from_slice(&(down as u16).to_be_bytes());
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        buf.extend_from_slice(&keycode.to_be_bytes());
+        self.send(&buf)
+    }
+}
+
+// Returns the size and the name of the desktop.
+fn handshake(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+) -> ResultType<(usize, usize, String)> {
+    let mut version = [0u8; 12];
+    reader.read_exact(&mut version)?;
+    if !version.starts_with(b"RFB 003.") {
+        bail!("Not a VNC server");
+    }
+    let minor = String::from_utf8_lossy(&version[8..11])
+        .parse::<u32>()
+        .unwrap_or(0);
+    if minor < 7 {
+        // 3.3, the server decides the security type.
+        writer.write_all(b"RFB 003.003\n")?;
+        if read_u32(reader)? != SECURITY_NONE as u32 {
+            bail!("Only the VNC security type \"None\" is supported");
+        }
+    } else {
+        writer.write_all(if minor >= 8 {
+            RFB_VERSION
+        } else {
+            b"RFB 003.007\n"
+        })?;
+        let n = read_u8(reader)?;
+        if n == 0 {
+            bail!("VNC connection failed: {}", read_reason(reader)?);
+        }
+        let mut types = vec![0u8; n as usize];
+        reader.read_exact(&mut types)?;
+        if !types.contains(&SECURITY_NONE) {
+            bail!(
+                "Only the VNC security type \"None\" is supported, the server offers {:?}",
+                types
+            );
+        }
+        writer.write_all(&[SECURITY_NONE])?;
+        if minor >= 8 && read_u32(reader)? != 0 {
+            bail!("VNC security failed: {}", read_reason(reader)?);
+        }
+    }
+    // Shared, the other viewers of the console, e.g. the web console of Proxmox, are kept.
+    writer.write_all(&[1])?;
+    let width = read_u16(reader)? as usize;
+    let height = read_u16(reader)? as usize;
+    skip(reader, 16)?;
+    let len = read_u32(reader)?;
+    let mut name = vec![0u8; len as usize];
+    reader.read_exact(&mut name)?;
+    Ok((width, height, String::from_utf8_lossy(&name).to_string()))
+}
+
+// 32 bits little endian true colour, which is BGRA in memory.
+fn set_pixel_format(writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(&[
+        0, 0, 0, 0, // SetPixelFormat, padding
+        32, 24, 0, 1, // bits-per-pixel, depth, big-endian-flag, true-colour-flag
+        0, 255, 0, 255, 0, 255, // red-max, green-max, blue-max
+        16, 8, 0, // red-shift, green-shift, blue-shift
+        0, 0, 0, // padding
+    ])
+}
+
+fn set_encodings(writer: &mut impl Write) -> io::Result<()> {
+    let encodings = [
+        ENCODING_COPY_RECT,
+        ENCODING_RAW,
+        ENCODING_DESKTOP_SIZE,
+        ENCODING_EXTENDED_DESKTOP_SIZE,
+        ENCODING_QEMU_EXTENDED_KEY_EVENT,
+    ];
+    let mut buf = vec![2, 0];
+    buf.extend_from_slice(&(encodings.len() as u16).to_be_bytes());
+    for e in encodings {
+        buf.extend_from_slice(&e.to_be_bytes());
+    }
+    writer.write_all(&buf)
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_reason(reader: &mut impl Read) -> io::Result<String> {
+    let len = read_u32(reader)?;
+    let mut reason = vec![0u8; len as usize];
+    reader.read_exact(&mut reason)?;
+    Ok(String::from_utf8_lossy(&reason).to_string())
+}
+
+fn skip(reader: &mut impl Read, n: usize) -> io::Result<()> {
+    io::copy(&mut reader.take(n as _), &mut io::sink())?;
+    Ok(())
+}
+
+pub(super) fn get_displays() -> ResultType<Vec<DisplayInfo>> {
+    let console = get_console()?;
+    let fb = console.fb.lock().unwrap();
+    let (width, height) = (fb.width as i32, fb.height as i32);
+    Ok(vec![DisplayInfo {
+        x: 0,
+        y: 0,
+        name: DISPLAY_NAME.to_owned(),
+        width,
+        height,
+        online: true,
+        cursor_embedded: false,
+        scale: 1.0,
+        original_resolution: Some(Resolution {
+            width,
+            height,
+            ..Default::default()
+        })
+        .into(),
+        ..Default::default()
+    }])
+}
+
+pub(super) fn get_capturer_for_display(current: usize) -> ResultType<CapturerInfo> {
+    if current != 0 {
+        bail!(
+            "Failed to get display {}, the VM console has one display",
+            current
+        );
+    }
+    let console = get_console()?;
+    let (width, height) = {
+        let fb = console.fb.lock().unwrap();
+        (fb.width, fb.height)
+    };
+    log::debug!("VM console capturer, width={}, height={}", width, height);
+    Ok(CapturerInfo {
+        origin: (0, 0),
+        width,
+        height,
+        ndisplay: 1,
+        current,
+        privacy_mode_id: 0,
+        _capturer_privacy_mode_id: 0,
+        capturer: Box::new(VmConsoleCapturer {
+            console,
+            width,
+            height,
+            data: Vec::new(),
+            seq: 0,
+        }),
+    })
+}
+
+struct VmConsoleCapturer {
+    console: Arc<Console>,
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+    seq: u64,
+}
+
+impl TraitCapturer for VmConsoleCapturer {
+    fn frame<'a>(&'a mut self, timeout: Duration) -> io::Result<Frame<'a>> {
+        if !self.console.alive.load(Ordering::SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "VM console disconnected",
+            ));
+        }
+        {
+            let fb = self.console.fb.lock().unwrap();
+            // The size change is handled by the display service, the capturer is recreated.
+            if fb.seq != self.seq && fb.width == self.width && fb.height == self.height {
+                self.seq = fb.seq;
+                self.data.clear();
+                self.data.extend_from_slice(&fb.data);
+            } else {
+                drop(fb);
+                std::thread::sleep(timeout.min(Duration::from_millis(10)));
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+        }
+        Ok(Frame::PixelBuffer(PixelBuffer::new(
+            &self.data,
+            Pixfmt::BGRA,
+            self.width,
+            self.height,
+        )))
+    }
+}
+
+pub fn handle_mouse(evt: &MouseEvent) {
+    use crate::input::*;
+
+    let Ok(console) = get_console() else {
+        return;
+    };
+    let buttons = evt.mask >> 3;
+    let evt_type = evt.mask & 0x7;
+    let (width, height) = {
+        let fb = console.fb.lock().unwrap();
+        (fb.width as i32, fb.height as i32)
+    };
+    let mut pointer = console.pointer.lock().unwrap();
+    let mask = |buttons: i32| {
+        let mut mask = 0;
+        if buttons & MOUSE_BUTTON_LEFT != 0 {
+            mask |= BUTTON_LEFT;
+        }
+        if buttons & MOUSE_BUTTON_RIGHT != 0 {
+            mask |= BUTTON_RIGHT;
+        }
+        if buttons & MOUSE_BUTTON_WHEEL != 0 {
+            mask |= BUTTON_MIDDLE;
+        }
+        mask
+    };
+    match evt_type {
+        MOUSE_TYPE_MOVE => {
+            pointer.x = evt.x.clamp(0, (width - 1).max(0));
+            pointer.y = evt.y.clamp(0, (height - 1).max(0));
+        }
+        MOUSE_TYPE_MOVE_RELATIVE => {
+            pointer.x = (pointer.x + evt.x).clamp(0, (width - 1).max(0));
+            pointer.y = (pointer.y + evt.y).clamp(0, (height - 1).max(0));
+        }
+        MOUSE_TYPE_DOWN => pointer.buttons |= mask(buttons),
+        MOUSE_TYPE_UP => pointer.buttons &= !mask(buttons),
+        MOUSE_TYPE_WHEEL | MOUSE_TYPE_TRACKPAD => {
+            // A wheel button is clicked for each step, the trackpad deltas are in pixels.
+            let steps = |delta: i32| {
+                if evt_type == MOUSE_TYPE_TRACKPAD {
+                    delta.signum()
+                } else {
+                    delta
+                }
+            };
+            let (x, y) = (steps(evt.x), steps(evt.y));
+            let wheel_y = if y > 0 {
+                BUTTON_WHEEL_UP
+            } else {
+                BUTTON_WHEEL_DOWN
+            };
+            let wheel_x = if x > 0 {
+                BUTTON_WHEEL_LEFT
+            } else {
+                BUTTON_WHEEL_RIGHT
+            };
+            for (wheel, n) in [(wheel_y, y.abs()), (wheel_x, x.abs())] {
+                for _ in 0..n {
+                    pointer.buttons |= wheel;
+                    console.send_pointer(&pointer).ok();
+                    pointer.buttons &= !wheel;
+                    console.send_pointer(&pointer).ok();
+                }
+            }
+            return;
+        }
+        _ => return,
+    }
+    console.send_pointer(&pointer).ok();
+}
+
+pub fn handle_key(evt: &KeyEvent) {
+    let Ok(console) = get_console() else {
+        return;
+    };
+    let is_legacy = evt.mode.enum_value_or(KeyboardMode::Legacy) == KeyboardMode::Legacy;
+    match &evt.union {
+        // The keycode of the map and translate modes is the X keycode, the evdev code + 8.
+        Some(key_event::Union::Chr(code)) if !is_legacy => {
+            if console.qemu_key_event.load(Ordering::SeqCst) {
+                if let Some(keycode) = evdev_to_qemu_keycode(code.saturating_sub(8)) {
+                    console.send_qemu_key(keycode, evt.down).ok();
+                }
+            } else {
+                log::debug!("The VM console does not support the physical keys");
+            }
+        }
+        Some(key_event::Union::Chr(chr)) => {
+            if let Some(c) = std::char::from_u32(*chr) {
+                send_legacy_key(&console, evt, char_to_keysym(c));
+            }
+        }
+        Some(key_event::Union::ControlKey(ck)) => {
+            if let Some(keysym) = control_key_to_keysym(ck.enum_value_or(ControlKey::Unknown)) {
+                send_legacy_key(&console, evt, keysym);
+            }
+        }
+        Some(key_event::Union::Unicode(u)) => {
+            if let Some(c) = std::char::from_u32(*u) {
+                send_legacy_key(&console, evt, char_to_keysym(c));
+            }
+        }
+        Some(key_event::Union::Seq(seq)) => {
+            for c in seq.chars() {
+                let keysym = char_to_keysym(c);
+                console.send_keysym(keysym, true).ok();
+                console.send_keysym(keysym, false).ok();
+            }
+        }
+        _ => {}
+    }
+}
+
+// A "press" is a click with the modifiers of the event.
+fn send_legacy_key(console: &Console, evt: &KeyEvent, keysym: u32) {
+    if !evt.press {
+        console.send_keysym(keysym, evt.down).ok();
+        return;
+    }
+    let modifiers: Vec<u32> = evt
+        .modifiers
+        .iter()
+        .filter_map(|m| match m.enum_value_or(ControlKey::Unknown) {
+            ControlKey::CapsLock | ControlKey::NumLock => None,
+            m => control_key_to_keysym(m),
+        })
+        .collect();
+    for m in modifiers.iter() {
+        console.send_keysym(*m, true).ok();
+    }
+    console.send_keysym(keysym, true).ok();
+    console.send_keysym(keysym, false).ok();
+    for m in modifiers.iter().rev() {
+        console.send_keysym(*m, false).ok();
+    }
+}
+
+// https://www.cl.cam.ac.uk/~mgk25/ucs/keysyms.txt
+fn char_to_keysym(c: char) -> u32 {
+    let c = c as u32;
+    match c {
+        0x08 => 0xff08,
+        0x09 => 0xff09,
+        0x0a | 0x0d => 0xff0d,
+        0x1b => 0xff1b,
+        0x20..=0x7e | 0xa0..=0xff => c,
+        _ => 0x0100_0000 | c,
+    }
+}
+
+fn control_key_to_keysym(key: ControlKey) -> Option<u32> {
+    Some(match key {
+        ControlKey::Alt => 0xffe9,
+        ControlKey::RAlt => 0xffea,
+        ControlKey::Backspace => 0xff08,
+        ControlKey::CapsLock => 0xffe5,
+        ControlKey::Control => 0xffe3,
+        ControlKey::RControl => 0xffe4,
+        ControlKey::Delete => 0xffff,
+        ControlKey::DownArrow => 0xff54,
+        ControlKey::End => 0xff57,
+        ControlKey::Escape => 0xff1b,
+        ControlKey::F1 => 0xffbe,
+        ControlKey::F2 => 0xffbf,
+        ControlKey::F3 => 0xffc0,
+        ControlKey::F4 => 0xffc1,
+        ControlKey::F5 => 0xffc2,
+        ControlKey::F6 => 0xffc3,
+        ControlKey::F7 => 0xffc4,
+        ControlKey::F8 => 0xffc5,
+        ControlKey::F9 => 0xffc6,
+        ControlKey::F10 => 0xffc7,
+        ControlKey::F11 => 0xffc8,
+        ControlKey::F12 => 0xffc9,
+        ControlKey::Home => 0xff50,
+        ControlKey::LeftArrow => 0xff51,
+        ControlKey::Meta => 0xffeb,
+        ControlKey::RWin => 0xffec,
+        ControlKey::PageDown => 0xff56,
+        ControlKey::PageUp => 0xff55,
+        ControlKey::Return => 0xff0d,
+        ControlKey::RightArrow => 0xff53,
+        ControlKey::Shift => 0xffe1,
+        ControlKey::RShift => 0xffe2,
+        ControlKey::Space => 0x20,
+        ControlKey::Tab => 0xff09,
+        ControlKey::UpArrow => 0xff52,
+        ControlKey::Numpad0 => 0xffb0,
+        ControlKey::Numpad1 => 0xffb1,
+        ControlKey::Numpad2 => 0xffb2,
+        ControlKey::Numpad3 => 0xffb3,
+        ControlKey::Numpad4 => 0xffb4,
+        ControlKey::Numpad5 => 0xffb5,
+        ControlKey::Numpad6 => 0xffb6,
+        ControlKey::Numpad7 => 0xffb7,
+        ControlKey::Numpad8 => 0xffb8,
+        ControlKey::Numpad9 => 0xffb9,
+        ControlKey::Multiply => 0xffaa,
+        ControlKey::Add => 0xffab,
+        ControlKey::Subtract => 0xffad,
+        ControlKey::Decimal => 0xffae,
+        ControlKey::Divide => 0xffaf,
+        ControlKey::NumpadEnter => 0xff8d,
+        ControlKey::NumLock => 0xff7f,
+        ControlKey::Scroll => 0xff14,
+        ControlKey::Pause => 0xff13,
+        ControlKey::Insert => 0xff63,
+        ControlKey::Snapshot => 0xff61,
+        ControlKey::Apps => 0xff67,
+        _ => return None,
+    })
+}
+
+// The evdev codes up to KEY_F12 are the XT scancodes.
+fn evdev_to_qemu_keycode(code: u32) -> Option<u32> {
+    Some(match code {
+        1..=88 => code,
+        96 => 0x9c,  // KEY_KPENTER
+        97 => 0x9d,  // KEY_RIGHTCTRL
+        98 => 0xb5,  // KEY_KPSLASH
+        99 => 0xb7,  // KEY_SYSRQ
+        100 => 0xb8, // KEY_RIGHTALT
+        102 => 0xc7, // KEY_HOME
+        103 => 0xc8, // KEY_UP
+        104 => 0xc9, // KEY_PAGEUP
+        105 => 0xcb, // KEY_LEFT
+        106 => 0xcd, // KEY_RIGHT
+        107 => 0xcf, // KEY_END
+        108 => 0xd0, // KEY_DOWN
+        109 => 0xd1, // KEY_PAGEDOWN
+        110 => 0xd2, // KEY_INSERT
+        111 => 0xd3, // KEY_DELETE
+        113 => 0xa0, // KEY_MUTE
+        114 => 0xae, // KEY_VOLUMEDOWN
+        115 => 0xb0, // KEY_VOLUMEUP
+        117 => 0x59, // KEY_KPEQUAL
+        119 => 0xc6, // KEY_PAUSE
+        125 => 0xdb, // KEY_LEFTMETA
+        126 => 0xdc, // KEY_RIGHTMETA
+        127 => 0xdd, // KEY_COMPOSE
+        _ => return None,
+    })
+}
@@ -0,0 +1,167 @@
+//! Events of the incoming sessions posted to the webhooks of the admin, e.g. Slack or a SIEM,
+//! without an API server.
+//!
+//! `webhook-urls` is the list of the URLs separated by `,` or new lines. Every event in
+//! `webhook-events` (`login,disconnect,login-failure,file` by default) is posted to each URL
+//! as the JSON of the audit log with the device id and a `text` summary for the chat tools:
+//!
+//! ```json
+//! {"event": "login", "time": 1700000000000, "id": "123456789", "conn_id": 1, "peer_id": "987654321",
+//!  "ip": "1.2.3.4", "name": "admin", "text": "123456789: login of 987654321 (admin) from 1.2.3.4"}
+//! ```
+//!
+//! If `webhook-secret` is set, the body is signed by HMAC-SHA256 with it, in the header
+//! `X-RustDesk-Signature: sha256=<hex>`. A failed post is retried with backoff, then dropped.
+
+use hbb_common::{
+    bail,
+    config::Config,
+    get_time, log,
+    tokio::{self, sync::mpsc},
+    ResultType,
+};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::{sync::Mutex, time::Duration};
+
+pub const OPTION_WEBHOOK_URLS: &str = "webhook-urls";
+pub const OPTION_WEBHOOK_SECRET: &str = "webhook-secret";
+pub const OPTION_WEBHOOK_EVENTS: &str = "webhook-events";
+const DEFAULT_EVENTS: &str = "login,disconnect,login-failure,file";
+const SIGNATURE_HEADER: &str = "X-RustDesk-Signature";
+const MAX_ATTEMPTS: u32 = 4;
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+const TIMEOUT: Duration = Duration::from_secs(12);
+
+lazy_static::lazy_static! {
+    static ref SENDER: Mutex<Option<mpsc::UnboundedSender<Value>>> = Default::default();
+}
+
+fn split(s: &str) -> impl Iterator<Item = &str> {
+    s.split(|c| c == ',' || c == '\n')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+}
+
+fn is_subscribed(event: &str) -> bool {
+    let events = Config::get_option(OPTION_WEBHOOK_EVENTS);
+    let events = if events.is_empty() {
+        DEFAULT_EVENTS
+    } else {
+        &events
+    };
+    split(events).any(|e| e == event || e == "*")
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key length");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn summary(v: &Value) -> String {
+    let s = |k: &str| v[k].as_str().unwrap_or_default().to_owned();
+    let mut text = format!("{}: {} of {}", s("id"), s("event"), s("peer_id"));
+    if !s("name").is_empty() {
+        text += &format!(" ({})", s("name"));
+    }
+    text + &format!(" from {}", s("ip"))
+}
+
+/// Posts an event of a connection to the webhooks if it is subscribed, as `audit_log::record`.
+pub fn post(event: &str, conn_id: i32, peer_id: &str, ip: &str, info: &Value) {
+    if Config::get_option(OPTION_WEBHOOK_URLS).is_empty() || !is_subscribed(event) {
+        return;
+    }
+    let mut v = json!({
+        "event": event,
+        "time": get_time(),
+        "id": Config::get_id(),
+        "conn_id": conn_id,
+        "peer_id": peer_id,
+        "ip": ip,
+    });
+    if let (Some(v), Value::Object(info)) = (v.as_object_mut(), info) {
+        v.extend(info.clone());
+    }
+    v["text"] = summary(&v).into();
+    let mut lock = SENDER.lock().unwrap();
+    if let Some(tx) = lock.as_ref() {
+        if tx.send(v.clone()).is_ok() {
+            return;
+        }
+    }
+    let (tx, rx) = mpsc::unbounded_channel();
+    tx.send(v).ok();
+    *lock = Some(tx);
+    std::thread::spawn(move || run(rx));
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn run(mut rx: mpsc::UnboundedReceiver<Value>) {
+    while let Some(v) = rx.recv().await {
+        let body = v.to_string();
+        let secret = Config::get_option(OPTION_WEBHOOK_SECRET);
+        let signature = (!secret.is_empty()).then(|| sign(&secret, &body));
+        for url in split(&Config::get_option(OPTION_WEBHOOK_URLS)) {
+            tokio::spawn(deliver(url.to_owned(), body.clone(), signature.clone()));
+        }
+    }
+}
+
+async fn post_once(url: &str, body: &str, signature: &Option<String>) -> ResultType<()> {
+    let mut req = crate::hbbs_http::create_http_client_async()
+        .post(url)
+        .header("Content-Type", "application/json");
+    if let Some(signature) = signature {
+        req = req.header(SIGNATURE_HEADER, signature);
+    }
+    let resp = req.body(body.to_owned()).timeout(TIMEOUT).send().await?;
+    if !resp.status().is_success() {
+        bail!("{}", resp.status());
+    }
+    Ok(())
+}
+
+async fn deliver(url: String, body: String, signature: Option<String>) {
+    let mut interval = RETRY_INTERVAL;
+    for attempt in 1..=MAX_ATTEMPTS {
+        match post_once(&url, &body, &signature).await {
+            Ok(_) => return,
+            Err(err) if attempt < MAX_ATTEMPTS => {
+                log::warn!(
+                    "Failed to post the webhook {}, retry in {:?}: {}",
+                    url,
+                    interval,
+                    err
+                );
+                tokio::time::sleep(interval).await;
+                interval *= 2;
+            }
+            Err(err) => log::error!("Failed to post the webhook {}, dropped: {}", url, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2.
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let v = json!({"id": "1", "event": "login", "peer_id": "2", "ip": "1.2.3.4", "name": "a"});
+        assert_eq!(summary(&v), "1: login of 2 (a) from 1.2.3.4");
+        let v = json!({"id": "1", "event": "disconnect", "peer_id": "2", "ip": "1.2.3.4"});
+        assert_eq!(summary(&v), "1: disconnect of 2 from 1.2.3.4");
+    }
+}
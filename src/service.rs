@@ -6,6 +6,6 @@ fn main() {}
 #[cfg(target_os = "macos")]
 fn main() {
     crate::common::load_custom_client();
-    hbb_common::init_log(false, "service");
+    crate::logging::init("service");
     crate::start_os_service();
 }
@@ -0,0 +1,273 @@
+//! Protection of the security settings of the controlled side.
+//!
+//! If enabled, changes of the security-relevant options, of the permanent password and of
+//! the one-time passwords are rejected by the IPC server unless the settings were unlocked with the 2FA code or
+//! the admin password recently. The check is done in the service, so a local user cannot
+//! bypass it by talking to IPC directly instead of using the UI. The failed unlocks lock the
+//! unlock out as the failed logins do, see `server::lockout`, and the admin password is stored
+//! as PBKDF2-HMAC-SHA256 with a random salt, not to be brute-forced from the config.
+
+use hbb_common::{
+    config::{self, Config},
+    log,
+    rand::{self, Rng},
+    sodiumoxide::utils::memcmp,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+pub const OPTION_PROTECT_SETTINGS: &str = "protect-settings";
+pub const OPTION_SETTINGS_ADMIN_PASSWORD: &str = "settings-admin-password";
+// Not an option, the name of the permanent password in the protected snapshot.
+const PERMANENT_PASSWORD: &str = "permanent-password";
+/// Not an option, the name of the one-time passwords for [`allow_config`].
+pub const ONE_TIME_PASSWORDS: &str = "one-time-passwords";
+/// Not an option, the name of the login lockouts for [`allow_config`].
+pub const LOCKOUTS: &str = "lockouts";
+// Give access as the permanent password does, or lift the protection against brute force.
+const ACCESS_CONFIGS: &[&str] = &[
+    PERMANENT_PASSWORD,
+    "unlock-pin",
+    ONE_TIME_PASSWORDS,
+    LOCKOUTS,
+];
+const UNLOCK_DURATION: Duration = Duration::from_secs(300);
+const KDF_PREFIX: &str = "pbkdf2-sha256";
+const KDF_ITERATIONS: u32 = 600_000;
+
+const PROTECTED_OPTIONS: &[&str] = &[
+    OPTION_PROTECT_SETTINGS,
+    OPTION_SETTINGS_ADMIN_PASSWORD,
+    "2fa",
+    "approve-mode",
+    "verification-method",
+    "stop-service",
+    "whitelist",
+    "direct-server",
+    "allow-remote-config-modification",
+    "allow-only-conn-window-open",
+    crate::auth_security_key::OPTION_REQUIRE_SECURITY_KEY,
+    crate::auth_security_key::OPTION_SECURITY_KEYS,
+    "access-control-list",
+    "peer-profiles",
+    "allow-audit-log",
+    "allow-push-audit-log",
+    "allow-sign-incoming-recording",
+    crate::tls_pin::OPTION_SERVER_KEY_PINS,
+    "allow-privilege-separation",
+    crate::lan::OPTION_ALLOW_WOL_RELAY,
+    crate::patch_level::OPTION_MIN_CLIENT_VERSION,
+    crate::server::webhook::OPTION_WEBHOOK_URLS,
+    crate::server::webhook::OPTION_WEBHOOK_SECRET,
+    crate::server::webhook::OPTION_WEBHOOK_EVENTS,
+    crate::tunnel::OPTION_ALLOW_REMOTE_TUNNEL,
+    crate::tunnel::OPTION_ALLOW_TUNNEL_PUBLIC_LISTEN,
+    "allow-auto-disconnect",
+    "auto-disconnect-timeout",
+    "allow-max-session-duration",
+    "max-session-duration",
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    crate::deploy_config::OPTION_PERMANENT_PASSWORD_HASH,
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    crate::server::local_api::OPTION_ALLOW_LOCAL_API,
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    crate::server::local_api::OPTION_LOCAL_API_TOKEN,
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    crate::crash_report::OPTION_ALLOW_UPLOAD_CRASH_REPORTS,
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    crate::crash_report::OPTION_CRASH_REPORT_URL,
+];
+
+lazy_static::lazy_static! {
+    static ref UNLOCKED_AT: Mutex<Option<Instant>> = Default::default();
+}
+
+// `pbkdf2-sha256$<iterations>$<base64 salt>$<base64 hash>`
+fn hash_admin_password(password: &str) -> String {
+    let salt: [u8; 16] = rand::thread_rng().gen();
+    let hash = pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(password.as_bytes(), &salt, KDF_ITERATIONS);
+    format!(
+        "{}${}${}${}",
+        KDF_PREFIX,
+        KDF_ITERATIONS,
+        crate::encode64(salt),
+        crate::encode64(hash)
+    )
+}
+
+fn check_admin_password(password: &str, stored: &str) -> bool {
+    let parts: Vec<&str> = stored.split('$').collect();
+    match parts[..] {
+        [KDF_PREFIX, iterations, salt, hash] => {
+            let (Ok(iterations), Ok(salt), Ok(hash)) = (
+                iterations.parse::<u32>(),
+                crate::decode64(salt),
+                crate::decode64(hash),
+            ) else {
+                return false;
+            };
+            let res =
+                pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(password.as_bytes(), &salt, iterations);
+            memcmp(&res, &hash)
+        }
+        // The salted SHA-256 of the earlier versions.
+        [legacy] => {
+            let mut hasher = Sha256::new();
+            hasher.update(Config::get_salt());
+            hasher.update(password);
+            memcmp(
+                crate::encode64(hasher.finalize()).as_bytes(),
+                legacy.as_bytes(),
+            )
+        }
+        _ => false,
+    }
+}
+
+fn has_admin_password() -> bool {
+    !Config::get_option(OPTION_SETTINGS_ADMIN_PASSWORD).is_empty()
+}
+
+fn has_2fa() -> bool {
+    crate::auth_2fa::get_2fa(None).is_some()
+}
+
+/// Enabled, and there is something to unlock with.
+pub fn is_protected() -> bool {
+    config::option2bool(
+        OPTION_PROTECT_SETTINGS,
+        &Config::get_option(OPTION_PROTECT_SETTINGS),
+    ) && (has_admin_password() || has_2fa())
+}
+
+fn is_unlocked() -> bool {
+    UNLOCKED_AT
+        .lock()
+        .unwrap()
+        .map_or(false, |t| t.elapsed() < UNLOCK_DURATION)
+}
+
+pub fn is_locked() -> bool {
+    is_protected() && !is_unlocked()
+}
+
+/// Unlock with the current 2FA code or the admin password, slow as it runs the KDF.
+pub fn unlock(code: &str) -> bool {
+    if let Some(remaining) = crate::server::lockout::settings_unlock_locked() {
+        log::warn!(
+            "The unlock of the protected settings is locked out for {} seconds",
+            remaining / 1000
+        );
+        return false;
+    }
+    let stored = Config::get_option(OPTION_SETTINGS_ADMIN_PASSWORD);
+    let by_password = has_admin_password() && check_admin_password(code, &stored);
+    let ok = by_password
+        || crate::auth_2fa::get_2fa(None)
+            .map_or(false, |totp| totp.check_current(code).unwrap_or_default());
+    crate::server::lockout::on_settings_unlock(ok);
+    if ok {
+        *UNLOCKED_AT.lock().unwrap() = Some(Instant::now());
+        // Upgraded from the hash of the earlier versions.
+        if by_password && !stored.starts_with(KDF_PREFIX) {
+            set_admin_password(code);
+        }
+    } else {
+        log::warn!("Failed to unlock the protected settings");
+    }
+    ok
+}
+
+/// The status as JSON, for the settings page.
+pub fn get_status_json() -> String {
+    serde_json::json!({
+        "protected": is_protected(),
+        "locked": is_locked(),
+        "has_admin_password": has_admin_password(),
+        "has_2fa": has_2fa(),
+        "locked_out": crate::server::lockout::settings_unlock_locked().unwrap_or_default() / 1000,
+    })
+    .to_string()
+}
+
+/// Whether the options can be replaced by `new`, i.e. no protected option changes while locked.
+pub fn allow_options(new: &HashMap<String, String>) -> bool {
+    if !is_locked() {
+        return true;
+    }
+    let changed = PROTECTED_OPTIONS
+        .iter()
+        .find(|k| new.get(**k).map(|x| x.as_str()).unwrap_or_default() != Config::get_option(k));
+    if let Some(k) = changed {
+        log::warn!("Rejected the change of the protected option {}", k);
+        return false;
+    }
+    true
+}
+
+/// Whether the config `name` can be set, e.g. the permanent password or the one-time passwords.
+pub fn allow_config(name: &str) -> bool {
+    allow_config_(name, is_locked())
+}
+
+fn allow_config_(name: &str, locked: bool) -> bool {
+    if ACCESS_CONFIGS.contains(&name) && locked {
+        log::warn!("Rejected the change of {}", name);
+        return false;
+    }
+    true
+}
+
+pub fn set_admin_password(password: &str) {
+    let v = if password.is_empty() {
+        "".to_owned()
+    } else {
+        hash_admin_password(password)
+    };
+    Config::set_option(OPTION_SETTINGS_ADMIN_PASSWORD.to_owned(), v);
+}
+
+/// The protected values, to be restored after a whole config is synced while locked.
+pub fn snapshot() -> Option<Vec<(&'static str, String)>> {
+    if !is_locked() {
+        return None;
+    }
+    let mut v: Vec<_> = PROTECTED_OPTIONS
+        .iter()
+        .map(|k| (*k, Config::get_option(k)))
+        .collect();
+    v.push((PERMANENT_PASSWORD, Config::get_permanent_password()));
+    Some(v)
+}
+
+pub fn restore(snapshot: Option<Vec<(&'static str, String)>>) {
+    for (k, v) in snapshot.unwrap_or_default() {
+        if k == PERMANENT_PASSWORD {
+            if Config::get_permanent_password() != v {
+                log::warn!("Restored the protected permanent password");
+                Config::set_permanent_password(&v);
+            }
+        } else if Config::get_option(k) != v {
+            log::warn!("Restored the protected option {}", k);
+            Config::set_option(k.to_owned(), v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allow_config_locked() {
+        for name in [PERMANENT_PASSWORD, ONE_TIME_PASSWORDS, LOCKOUTS] {
+            assert!(!allow_config_(name, true));
+            assert!(allow_config_(name, false));
+        }
+        assert!(allow_config_("custom-rendezvous-server", true));
+    }
+}
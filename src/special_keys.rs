@@ -0,0 +1,135 @@
+//! The special keys of the controlled side, the ones the local keyboard can not send or the
+//! local system takes, e.g. PrintScreen, the media keys or Alt+Tab.
+//!
+//! The controlled side lists the keys of its platform in the platform additions, the
+//! controlling side sends the name of one, then the keys are pressed in order and released
+//! in reverse, as the raw codes of the platform in the map mode.
+//!
+//! Ctrl+Alt+Del and the lock are not listed, they have their control keys.
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use hbb_common::message_proto::{KeyEvent, KeyboardMode};
+use hbb_common::message_proto::{Message, Misc, PluginRequest};
+
+/// The content is the name of the key.
+pub const PLUGIN_ID: &str = "__special_key";
+/// The names of the keys of the controlled side in the platform additions.
+pub const PLATFORM_ADDITION_KEY: &str = "special_keys";
+
+// Scan codes.
+#[cfg(target_os = "windows")]
+const KEYS: &[(&str, &[u32])] = &[
+    ("print_screen", &[0xE037]),
+    ("alt_print_screen", &[0x38, 0xE037]),
+    ("alt_tab", &[0x38, 0x0F]),
+    ("alt_f4", &[0x38, 0x3E]),
+    ("win", &[0xE05B]),
+    ("win_d", &[0xE05B, 0x20]),
+    ("win_r", &[0xE05B, 0x13]),
+    ("task_manager", &[0x1D, 0x2A, 0x01]),
+    ("play_pause", &[0xE022]),
+    ("stop", &[0xE024]),
+    ("previous_track", &[0xE010]),
+    ("next_track", &[0xE019]),
+    ("volume_mute", &[0xE020]),
+    ("volume_down", &[0xE02E]),
+    ("volume_up", &[0xE030]),
+];
+
+// Xorg key codes, the evdev ones plus 8.
+#[cfg(target_os = "linux")]
+const KEYS: &[(&str, &[u32])] = &[
+    ("print_screen", &[107]),
+    ("alt_print_screen", &[64, 107]),
+    ("alt_tab", &[64, 23]),
+    ("alt_f4", &[64, 70]),
+    ("super", &[133]),
+    ("play_pause", &[172]),
+    ("stop", &[174]),
+    ("previous_track", &[173]),
+    ("next_track", &[171]),
+    ("volume_mute", &[121]),
+    ("volume_down", &[122]),
+    ("volume_up", &[123]),
+    ("switch_vt_1", &[37, 64, 67]),
+    ("switch_vt_2", &[37, 64, 68]),
+    ("switch_vt_3", &[37, 64, 69]),
+    ("switch_vt_7", &[37, 64, 73]),
+];
+
+// Virtual key codes. The media keys are system events, not key events, on macOS.
+#[cfg(target_os = "macos")]
+const KEYS: &[(&str, &[u32])] = &[
+    ("screenshot", &[0x37, 0x38, 0x14]),
+    ("screenshot_selection", &[0x37, 0x38, 0x15]),
+    ("cmd_tab", &[0x37, 0x30]),
+    ("cmd_q", &[0x37, 0x0C]),
+    ("spotlight", &[0x37, 0x31]),
+    ("mission_control", &[0x3B, 0x7E]),
+    ("force_quit", &[0x37, 0x3A, 0x35]),
+];
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+const KEYS: &[(&str, &[u32])] = &[];
+
+/// The names of the keys of this platform.
+pub fn supported() -> Vec<&'static str> {
+    KEYS.iter().map(|(name, _)| *name).collect()
+}
+
+/// The events pressing the keys then releasing them, None if the key is not of this platform.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn key_events(name: &str) -> Option<Vec<KeyEvent>> {
+    let (_, codes) = KEYS.iter().find(|(n, _)| *n == name)?;
+    let event = |code: u32, down: bool| {
+        let mut evt = KeyEvent::new();
+        evt.set_chr(code);
+        evt.down = down;
+        evt.mode = KeyboardMode::Map.into();
+        evt
+    };
+    let mut events: Vec<KeyEvent> = codes.iter().map(|c| event(*c, true)).collect();
+    events.extend(codes.iter().rev().map(|c| event(*c, false)));
+    Some(events)
+}
+
+pub fn to_message(name: &str) -> Message {
+    let mut misc = Misc::new();
+    misc.set_plugin_request(PluginRequest {
+        id: PLUGIN_ID.to_owned(),
+        content: name.as_bytes().to_vec().into(),
+        ..Default::default()
+    });
+    let mut msg = Message::new();
+    msg.set_misc(misc);
+    msg
+}
+
+#[cfg(test)]
+#[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_events() {
+        assert!(key_events("unknown").is_none());
+        for name in supported() {
+            let events = key_events(name).unwrap();
+            let n = events.len() / 2;
+            assert!(n > 0);
+            for i in 0..n {
+                assert!(events[i].down);
+                assert!(!events[events.len() - 1 - i].down);
+                assert_eq!(events[i].chr(), events[events.len() - 1 - i].chr());
+            }
+        }
+    }
+
+    #[test]
+    fn test_unique_names() {
+        let names = supported();
+        for (i, name) in names.iter().enumerate() {
+            assert!(!names[i + 1..].contains(name), "{}", name);
+        }
+    }
+}
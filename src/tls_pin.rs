@@ -0,0 +1,374 @@
+//! Pinning of the TLS keys of the custom rendezvous, relay and API servers.
+//!
+//! A pin is the base64 SHA-256 of the SubjectPublicKeyInfo of the server certificate, as
+//! in HPKP (`sha256/<base64>`). If pins are set, the TLS connections to a custom server are
+//! refused if the key matches none of them, whatever the CA says. Several pins can be set at
+//! once, so a new key can be added before the server switches to it and the old one removed
+//! afterwards.
+//!
+//! The HTTPS clients of `hbbs_http` check the key in the handshake of the connection itself,
+//! by [`client_config`]. The WebSocket over TLS to the rendezvous and relay servers is set up
+//! by `hbb_common`, which takes no verifier, so [`connect_tcp`] checks the key right before it
+//! on every connection, without a cache.
+
+use crate::hbbs_http::create_http_client_async_with_tls_info;
+use hbb_common::{
+    bail,
+    config::{self, Config},
+    log, socket_client, ResultType, Stream,
+};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    client::WebPkiServerVerifier,
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+use std::{sync::Arc, time::Duration};
+
+pub const OPTION_SERVER_KEY_PINS: &str = "server-key-pins";
+const PIN_PREFIX: &str = "sha256/";
+const CHECK_TIMEOUT: Duration = Duration::from_secs(12);
+
+/// The configured pins, without the `sha256/` prefix.
+pub fn get_pins() -> Vec<String> {
+    parse_pins(&Config::get_option(OPTION_SERVER_KEY_PINS))
+}
+
+fn parse_pins(v: &str) -> Vec<String> {
+    v.split(|c: char| c == ',' || c.is_whitespace())
+        .map(|x| x.trim().trim_start_matches(PIN_PREFIX).to_owned())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+// The host of an url or a `host:port` endpoint.
+fn host_of(url: &str) -> String {
+    let s = url.split("://").last().unwrap_or_default();
+    let s = s.split(|c| c == '/' || c == '?').next().unwrap_or_default();
+    let s = s.rsplit('@').next().unwrap_or_default();
+    if let Some(s) = s.strip_prefix('[') {
+        return s.split(']').next().unwrap_or_default().to_lowercase();
+    }
+    if s.matches(':').count() > 1 {
+        // bare IPv6 address
+        return s.to_lowercase();
+    }
+    s.split(':').next().unwrap_or_default().to_lowercase()
+}
+
+fn custom_hosts() -> Vec<String> {
+    ["custom-rendezvous-server", "relay-server", "api-server"]
+        .iter()
+        .flat_map(|k| {
+            Config::get_option(k)
+                .split(',')
+                .map(host_of)
+                .collect::<Vec<_>>()
+        })
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+// The https url to reach `target` with TLS, if it is reached with TLS at all.
+fn https_url(target: &str) -> Option<String> {
+    let url = if target.contains("://") {
+        target.to_owned()
+    } else if config::use_ws() {
+        hbb_common::websocket::check_ws(target)
+    } else {
+        return None;
+    };
+    url.strip_prefix("wss://")
+        .or_else(|| url.strip_prefix("https://"))
+        .map(|x| format!("https://{}", x))
+}
+
+// Reads a DER element, returns (the whole element, its content, the rest).
+fn der_element(d: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let b = *d.get(1)? as usize;
+    let (header, len) = if b < 0x80 {
+        (2, b)
+    } else {
+        let n = b & 0x7f;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let mut len = 0usize;
+        for i in 0..n {
+            len = (len << 8) | *d.get(2 + i)? as usize;
+        }
+        (2 + n, len)
+    };
+    let end = header.checked_add(len)?;
+    if end > d.len() {
+        return None;
+    }
+    Some((&d[..end], &d[header..end], &d[end..]))
+}
+
+// The SubjectPublicKeyInfo of a DER certificate.
+fn spki_of_cert(cert: &[u8]) -> Option<&[u8]> {
+    let (_, cert, _) = der_element(cert)?;
+    let (_, mut tbs, _) = der_element(cert)?;
+    // Optional explicit version [0]
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_element(tbs)?.2;
+    }
+    // serialNumber, signature, issuer, validity, subject
+    for _ in 0..5 {
+        tbs = der_element(tbs)?.2;
+    }
+    Some(der_element(tbs)?.0)
+}
+
+pub fn pin_of_cert(cert: &[u8]) -> Option<String> {
+    spki_of_cert(cert).map(|x| crate::encode64(Sha256::digest(x)))
+}
+
+// The CA check of the system roots, then the pins for the custom servers.
+#[derive(Debug)]
+struct PinVerifier {
+    // None if the roots failed to load, all the custom servers are refused then.
+    inner: Option<Arc<WebPkiServerVerifier>>,
+    provider: Arc<CryptoProvider>,
+    pins: Vec<String>,
+}
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let Some(inner) = self.inner.as_ref() else {
+            return Err(rustls::Error::General("No root certificates".to_owned()));
+        };
+        inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        let host = server_name.to_str().to_lowercase();
+        if !custom_hosts().contains(&host) {
+            return Ok(ServerCertVerified::assertion());
+        }
+        match pin_of_cert(end_entity) {
+            Some(pin) if self.pins.contains(&pin) => Ok(ServerCertVerified::assertion()),
+            pin => {
+                log::error!(
+                    "Key of {} not pinned: {}{}",
+                    host,
+                    PIN_PREFIX,
+                    pin.unwrap_or_default()
+                );
+                Err(rustls::Error::General(format!(
+                    "The key of the server {} does not match the pinned keys",
+                    host
+                )))
+            }
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// The TLS config of the HTTPS clients which checks the pins, none without pins.
+pub fn client_config() -> Option<rustls::ClientConfig> {
+    let pins = get_pins();
+    if pins.is_empty() {
+        return None;
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut roots = rustls::RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for err in native.errors {
+        log::warn!("Failed to load a root certificate: {}", err);
+    }
+    roots.add_parsable_certificates(native.certs);
+    let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .map_err(|err| log::error!("Failed to verify the server certificates: {}", err))
+        .ok();
+    let verifier = PinVerifier {
+        inner,
+        provider: provider.clone(),
+        pins,
+    };
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|err| log::error!("Failed to config TLS: {}", err))
+        .ok()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    Some(config)
+}
+
+/// The pin of the key the server at `url` presents now.
+pub async fn fetch_pin(url: &str) -> ResultType<String> {
+    let Some(url) = https_url(url) else {
+        bail!("Not a TLS server: {}", url);
+    };
+    let resp = create_http_client_async_with_tls_info()
+        .get(&url)
+        .timeout(CHECK_TIMEOUT)
+        .send()
+        .await?;
+    let Some(cert) = resp
+        .extensions()
+        .get::<reqwest::tls::TlsInfo>()
+        .and_then(|x| x.peer_certificate())
+    else {
+        bail!("No certificate from {}", url);
+    };
+    let Some(pin) = pin_of_cert(cert) else {
+        bail!("Invalid certificate from {}", url);
+    };
+    Ok(pin)
+}
+
+// Fails if `target` is a custom server reached with TLS and its key matches none of the pins.
+async fn check(target: &str) -> ResultType<()> {
+    let pins = get_pins();
+    if pins.is_empty() {
+        return Ok(());
+    }
+    let host = host_of(target);
+    if !custom_hosts().contains(&host) {
+        return Ok(());
+    }
+    let Some(url) = https_url(target) else {
+        return Ok(());
+    };
+    let pin = match fetch_pin(&url).await {
+        Ok(pin) => pin,
+        Err(err) => bail!("Failed to check the key of {}: {}", host, err),
+    };
+    if !pins.contains(&pin) {
+        log::error!("Key of {} not pinned: {}{}", host, PIN_PREFIX, pin);
+        bail!(
+            "The key of the server {} does not match the pinned keys",
+            host
+        );
+    }
+    Ok(())
+}
+
+/// `socket_client::connect_tcp` to a rendezvous or relay server, after the pin check.
+pub async fn connect_tcp(target: String, ms_timeout: u64) -> ResultType<Stream> {
+    check(&target).await?;
+    socket_client::connect_tcp(target, ms_timeout).await
+}
+
+/// The current pins of the custom servers, as json, to show them before rotating the pins.
+pub async fn get_server_pins_json() -> String {
+    let mut v = Vec::new();
+    for k in ["custom-rendezvous-server", "relay-server", "api-server"] {
+        for server in Config::get_option(k).split(',') {
+            let server = server.trim();
+            if server.is_empty() {
+                continue;
+            }
+            let target = match k {
+                "api-server" => server.to_owned(),
+                "relay-server" => crate::check_port(server, config::RELAY_PORT),
+                _ => crate::check_port(server, config::RENDEZVOUS_PORT),
+            };
+            let (pin, error) = match fetch_pin(&target).await {
+                Ok(pin) => (format!("{}{}", PIN_PREFIX, pin), "".to_owned()),
+                Err(err) => ("".to_owned(), err.to_string()),
+            };
+            v.push(serde_json::json!({
+                "server": server,
+                "pin": pin,
+                "error": error,
+            }));
+        }
+    }
+    serde_json::json!({
+        "pins": get_pins().iter().map(|x| format!("{}{}", PIN_PREFIX, x)).collect::<Vec<_>>(),
+        "servers": v,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut v = vec![tag];
+        if content.len() < 0x80 {
+            v.push(content.len() as u8);
+        } else {
+            v.extend([0x82, (content.len() >> 8) as u8, content.len() as u8]);
+        }
+        v.extend(content);
+        v
+    }
+
+    #[test]
+    fn test_spki_of_cert() {
+        let spki = der(0x30, &[0x42; 200]);
+        let mut tbs = der(0xa0, &der(0x02, &[2]));
+        for x in [
+            der(0x02, &[1, 2, 3]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+        ] {
+            tbs.extend(x);
+        }
+        tbs.extend(&spki);
+        tbs.extend(der(0xa3, &[0; 10]));
+        let mut cert = der(0x30, &tbs);
+        cert.extend(der(0x30, &[]));
+        cert.extend(der(0x03, &[0; 64]));
+        let cert = der(0x30, &cert);
+        assert_eq!(spki_of_cert(&cert), Some(&spki[..]));
+        assert_eq!(spki_of_cert(&cert[..cert.len() - 1]), None);
+    }
+
+    #[test]
+    fn test_host_of() {
+        assert_eq!(host_of("wss://Example.com:21118/ws/id"), "example.com");
+        assert_eq!(host_of("https://user@example.com"), "example.com");
+        assert_eq!(host_of("example.com:21116"), "example.com");
+        assert_eq!(host_of("[::1]:21116"), "::1");
+        assert_eq!(parse_pins("sha256/a=, b= \n"), vec!["a=", "b="]);
+    }
+}
@@ -0,0 +1,344 @@
+//! Multi-touch input.
+//!
+//! The controlling side sends the fingers on the remote view as frames of contacts, in the
+//! coordinates of the mouse events. The controlled side injects them on a synthetic touch
+//! screen, `InjectTouchInput` on Windows and a uinput multitouch device on Linux, for the
+//! remote applications to get the pinch and the rotation instead of the mouse wheel.
+//!
+//! The frames are carried by `PluginRequest` with a reserved id, no protocol change is needed.
+
+use hbb_common::message_proto::{Message, Misc, PluginRequest};
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+use hbb_common::ResultType;
+use serde_derive::{Deserialize, Serialize};
+
+/// The content is a [`TouchFrame`] in json.
+pub const PLUGIN_ID: &str = "__touch";
+/// The contacts after this number are ignored.
+pub const MAX_CONTACTS: usize = 10;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TouchContact {
+    /// The pointer of the controlling side, the same during the contact.
+    pub id: i64,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// The contacts on the surface, the ones of the last frame missing in it are lifted.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TouchFrame {
+    #[serde(default)]
+    pub contacts: Vec<TouchContact>,
+}
+
+impl TouchFrame {
+    pub fn to_message(&self) -> Message {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(self).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        msg
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContactState {
+    Down,
+    Move,
+    Up,
+}
+
+/// A contact of a frame, on its slot of the device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactChange {
+    pub slot: usize,
+    pub x: i32,
+    pub y: i32,
+    pub state: ContactState,
+}
+
+/// The contacts of the device by slot, the slots are reused once the fingers are lifted.
+#[derive(Debug, Default)]
+pub struct Contacts {
+    slots: [Option<TouchContact>; MAX_CONTACTS],
+}
+
+impl Contacts {
+    /// Applies a frame, the changes are the lifted contacts, then all the ones on the surface.
+    pub fn update(&mut self, frame: &TouchFrame) -> Vec<ContactChange> {
+        let mut changes = vec![];
+        for (slot, c) in self.slots.iter_mut().enumerate() {
+            if let Some(last) = c {
+                if !frame.contacts.iter().any(|f| f.id == last.id) {
+                    changes.push(ContactChange {
+                        slot,
+                        x: last.x,
+                        y: last.y,
+                        state: ContactState::Up,
+                    });
+                    *c = None;
+                }
+            }
+        }
+        for contact in &frame.contacts {
+            let (slot, state) = match self
+                .slots
+                .iter()
+                .position(|c| c.map(|c| c.id) == Some(contact.id))
+            {
+                Some(slot) => (slot, ContactState::Move),
+                None => match self.slots.iter().position(|c| c.is_none()) {
+                    Some(slot) => (slot, ContactState::Down),
+                    None => continue,
+                },
+            };
+            self.slots[slot] = Some(*contact);
+            changes.push(ContactChange {
+                slot,
+                x: contact.x,
+                y: contact.y,
+                state,
+            });
+        }
+        changes
+    }
+
+    /// Lifts all the contacts.
+    pub fn clear(&mut self) -> Vec<ContactChange> {
+        self.update(&Default::default())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(|c| c.is_none())
+    }
+}
+
+/// The synthetic touch screen of a connection, the contacts are lifted when dropped.
+#[cfg(target_os = "windows")]
+pub struct VirtualTouch {
+    contacts: Contacts,
+}
+
+#[cfg(target_os = "windows")]
+impl VirtualTouch {
+    pub fn new() -> ResultType<Self> {
+        use windows::Win32::UI::Input::Pointer::{
+            InitializeTouchInjection, TOUCH_FEEDBACK_DEFAULT,
+        };
+        // Once per process, the later calls succeed with the same arguments.
+        unsafe { InitializeTouchInjection(MAX_CONTACTS as _, TOUCH_FEEDBACK_DEFAULT)? };
+        Ok(Self {
+            contacts: Default::default(),
+        })
+    }
+
+    pub fn handle(&mut self, frame: &TouchFrame) -> ResultType<()> {
+        let changes = self.contacts.update(frame);
+        Self::inject(&changes)
+    }
+
+    fn inject(changes: &[ContactChange]) -> ResultType<()> {
+        use windows::Win32::{
+            Foundation::POINT,
+            UI::{Input::Pointer::*, WindowsAndMessaging::PT_TOUCH},
+        };
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let infos: Vec<POINTER_TOUCH_INFO> = changes
+            .iter()
+            .map(|c| POINTER_TOUCH_INFO {
+                pointerInfo: POINTER_INFO {
+                    pointerType: PT_TOUCH,
+                    pointerId: c.slot as _,
+                    pointerFlags: match c.state {
+                        ContactState::Down => {
+                            POINTER_FLAG_DOWN | POINTER_FLAG_INRANGE | POINTER_FLAG_INCONTACT
+                        }
+                        ContactState::Move => {
+                            POINTER_FLAG_UPDATE | POINTER_FLAG_INRANGE | POINTER_FLAG_INCONTACT
+                        }
+                        ContactState::Up => POINTER_FLAG_UP,
+                    },
+                    ptPixelLocation: POINT { x: c.x, y: c.y },
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .collect();
+        crate::platform::windows::try_change_desktop();
+        unsafe { InjectTouchInput(&infos)? };
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for VirtualTouch {
+    fn drop(&mut self) {
+        Self::inject(&self.contacts.clear()).ok();
+    }
+}
+
+/// The uinput touch screen of a connection, removed when dropped.
+#[cfg(target_os = "linux")]
+pub struct VirtualTouch {
+    device: evdev::uinput::VirtualDevice,
+    // The origin of the displays, the axes of the device start from 0.
+    origin: (i32, i32),
+    contacts: Contacts,
+    // The tracking ids of the contacts, a new one for each finger down.
+    next_tracking_id: i32,
+}
+
+#[cfg(target_os = "linux")]
+impl VirtualTouch {
+    // Pixels per millimeter, libinput requires a resolution for the touch screens.
+    const RESOLUTION: i32 = 4;
+    const MAX_TRACKING_ID: i32 = 0xFFFF;
+
+    pub fn new() -> ResultType<Self> {
+        use evdev::{AbsInfo, AbsoluteAxisType, AttributeSet, Key, PropType, UinputAbsSetup};
+        let (min_x, min_y, max_x, max_y) = crate::pen::display_bounds()?;
+        let x = AbsInfo::new(0, 0, max_x - min_x - 1, 0, 0, Self::RESOLUTION);
+        let y = AbsInfo::new(0, 0, max_y - min_y - 1, 0, 0, Self::RESOLUTION);
+        let mut keys = AttributeSet::<Key>::new();
+        keys.insert(Key::BTN_TOUCH);
+        let mut props = AttributeSet::<PropType>::new();
+        props.insert(PropType::DIRECT);
+        let device = evdev::uinput::VirtualDeviceBuilder::new()?
+            .name("RustDesk Virtual Touch")
+            .with_keys(&keys)?
+            .with_properties(&props)?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_X, x))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, y))?
+            .with_absolute_axis(&UinputAbsSetup::new(
+                AbsoluteAxisType::ABS_MT_SLOT,
+                AbsInfo::new(0, 0, MAX_CONTACTS as i32 - 1, 0, 0, 0),
+            ))?
+            .with_absolute_axis(&UinputAbsSetup::new(
+                AbsoluteAxisType::ABS_MT_TRACKING_ID,
+                AbsInfo::new(0, 0, Self::MAX_TRACKING_ID, 0, 0, 0),
+            ))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_MT_POSITION_X, x))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_MT_POSITION_Y, y))?
+            .build()?;
+        Ok(Self {
+            device,
+            origin: (min_x, min_y),
+            contacts: Default::default(),
+            next_tracking_id: 0,
+        })
+    }
+
+    pub fn handle(&mut self, frame: &TouchFrame) -> ResultType<()> {
+        use evdev::{AbsoluteAxisType, EventType, InputEvent, Key};
+        let was_empty = self.contacts.is_empty();
+        let changes = self.contacts.update(frame);
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let abs = |axis: AbsoluteAxisType, value: i32| {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        };
+        let mut events = vec![];
+        for c in &changes {
+            events.push(abs(AbsoluteAxisType::ABS_MT_SLOT, c.slot as _));
+            let (x, y) = (c.x - self.origin.0, c.y - self.origin.1);
+            match c.state {
+                ContactState::Down => {
+                    events.push(abs(
+                        AbsoluteAxisType::ABS_MT_TRACKING_ID,
+                        self.next_tracking_id,
+                    ));
+                    self.next_tracking_id = (self.next_tracking_id + 1) % Self::MAX_TRACKING_ID;
+                    events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_X, x));
+                    events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, y));
+                }
+                ContactState::Move => {
+                    events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_X, x));
+                    events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, y));
+                }
+                ContactState::Up => {
+                    events.push(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1));
+                }
+            }
+        }
+        // The single touch axes follow the first contact on the surface.
+        if let Some(c) = changes.iter().find(|c| c.state != ContactState::Up) {
+            events.push(abs(AbsoluteAxisType::ABS_X, c.x - self.origin.0));
+            events.push(abs(AbsoluteAxisType::ABS_Y, c.y - self.origin.1));
+        }
+        let is_empty = self.contacts.is_empty();
+        if was_empty != is_empty {
+            events.push(InputEvent::new(
+                EventType::KEY,
+                Key::BTN_TOUCH.code(),
+                (!is_empty) as _,
+            ));
+        }
+        self.device.emit(&events)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(contacts: &[(i64, i32, i32)]) -> TouchFrame {
+        TouchFrame {
+            contacts: contacts
+                .iter()
+                .map(|&(id, x, y)| TouchContact { id, x, y })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let mut contacts = Contacts::default();
+        let changes = contacts.update(&frame(&[(7, 10, 10)]));
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].slot, changes[0].state), (0, ContactState::Down));
+
+        let changes = contacts.update(&frame(&[(7, 11, 10), (9, 50, 50)]));
+        assert_eq!(
+            changes
+                .iter()
+                .map(|c| (c.slot, c.state))
+                .collect::<Vec<_>>(),
+            vec![(0, ContactState::Move), (1, ContactState::Down)]
+        );
+
+        // The slot of the lifted finger is reused.
+        let changes = contacts.update(&frame(&[(9, 51, 50), (3, 0, 0)]));
+        assert_eq!(
+            changes
+                .iter()
+                .map(|c| (c.slot, c.state, c.x))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, ContactState::Up, 11),
+                (1, ContactState::Move, 51),
+                (0, ContactState::Down, 0)
+            ]
+        );
+
+        let changes = contacts.clear();
+        assert!(changes.iter().all(|c| c.state == ContactState::Up));
+        assert_eq!(changes.len(), 2);
+        assert!(contacts.is_empty());
+    }
+
+    #[test]
+    fn test_max_contacts() {
+        let mut contacts = Contacts::default();
+        let many: Vec<_> = (0..MAX_CONTACTS as i64 + 2).map(|i| (i, 0, 0)).collect();
+        assert_eq!(contacts.update(&frame(&many)).len(), MAX_CONTACTS);
+    }
+}
@@ -0,0 +1,525 @@
+//! The transfers between two peers of this device, without the files going through this device.
+//!
+//! The controlling side, connected to both peers, asks the receiving one for a ticket with
+//! [`BrokerMessage::Ticket`]. That peer issues a one-time password, which expires in a minute and
+//! only allows a view-only session with the file transfer permission, and replies with its id.
+//! The controlling side then asks the sending peer to upload the files with
+//! [`BrokerMessage::Send`]. The sending peer logs in to the receiving one with the password from
+//! its service, uploads the files like a file transfer session and reports the progress back
+//! until the job is done. Only the peers with [`PLATFORM_ADDITION_KEY`] are asked.
+
+use crate::client::{
+    handle_hash, handle_login_error, handle_login_from_ui, handle_test_delay, Client, Data,
+    Interface, LoginConfigHandler, MILLI1,
+};
+use async_trait::async_trait;
+use hbb_common::{
+    bail,
+    config::{LocalConfig, READ_TIMEOUT},
+    fs,
+    futures::StreamExt,
+    log,
+    message_proto::*,
+    protobuf::Message as _,
+    rendezvous_proto::ConnType,
+    timeout,
+    tokio::{
+        self,
+        sync::{mpsc, oneshot},
+        time::{self, Duration, Instant},
+    },
+    ResultType, Stream,
+};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+pub const PLUGIN_ID: &str = "__transfer_broker";
+pub const PLATFORM_ADDITION_KEY: &str = "support_transfer_broker";
+
+// Seconds, the sending peer logs in right after the ticket is issued.
+const TICKET_TTL: i64 = 60;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static::lazy_static! {
+    // ticket -> the job to send once issued, and the sender of the session of the sending peer.
+    static ref PENDING: Mutex<HashMap<i32, (BrokerMessage, Box<dyn Fn(BrokerMessage) + Send>)>> = Default::default();
+}
+static NEXT_TICKET: AtomicI32 = AtomicI32::new(1);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", rename_all = "snake_case")]
+pub enum BrokerMessage {
+    /// To the receiving peer.
+    Ticket {
+        id: i32,
+    },
+    /// The id and the one-time password of the receiving peer, empty with the error.
+    Issued {
+        id: i32,
+        peer_id: String,
+        password: String,
+        error: Option<String>,
+    },
+    /// To the sending peer, the files are uploaded into the folder `to`.
+    Send {
+        id: i32,
+        peer_id: String,
+        password: String,
+        paths: Vec<String>,
+        to: String,
+    },
+    Progress {
+        id: i32,
+        finished_size: u64,
+        total_size: u64,
+        speed: f64,
+    },
+    Done {
+        id: i32,
+        error: Option<String>,
+    },
+    Cancel {
+        id: i32,
+    },
+}
+
+impl BrokerMessage {
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        serde_json::from_slice(content)
+            .map_err(|e| log::error!("Invalid transfer broker message: {}", e))
+            .ok()
+    }
+
+    pub fn to_message(&self) -> Message {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(self).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        msg
+    }
+
+    pub fn issue_error(id: i32, error: &str) -> Self {
+        Self::Issued {
+            id,
+            peer_id: String::new(),
+            password: String::new(),
+            error: Some(error.to_owned()),
+        }
+    }
+}
+
+/// Whether the peer of a session takes part in the brokered transfers.
+pub fn is_supported(pi: Option<&PeerInfo>) -> bool {
+    pi.and_then(|pi| {
+        serde_json::from_str::<HashMap<String, serde_json::Value>>(&pi.platform_additions).ok()
+    })
+    .map_or(false, |m| {
+        m.get(PLATFORM_ADDITION_KEY) == Some(&json!(true))
+    })
+}
+
+/// Asks for the ticket of the receiving peer with the returned message, the job is then sent
+/// with `send` to the session of the sending peer.
+pub fn request(
+    id: i32,
+    paths: Vec<String>,
+    to: String,
+    send: impl Fn(BrokerMessage) + Send + 'static,
+) -> BrokerMessage {
+    let ticket = NEXT_TICKET.fetch_add(1, Ordering::Relaxed);
+    let job = BrokerMessage::Send {
+        id,
+        peer_id: String::new(),
+        password: String::new(),
+        paths,
+        to,
+    };
+    PENDING
+        .lock()
+        .unwrap()
+        .insert(ticket, (job, Box::new(send)));
+    BrokerMessage::Ticket { id: ticket }
+}
+
+/// The ticket issued by the receiving peer, the job is failed without it.
+pub fn on_issued(msg: BrokerMessage) {
+    let BrokerMessage::Issued {
+        id: ticket,
+        peer_id,
+        password,
+        error,
+    } = msg
+    else {
+        return;
+    };
+    let Some((BrokerMessage::Send { id, paths, to, .. }, send)) =
+        PENDING.lock().unwrap().remove(&ticket)
+    else {
+        return;
+    };
+    if error.is_some() {
+        send(BrokerMessage::Done { id, error });
+    } else {
+        send(BrokerMessage::Send {
+            id,
+            peer_id,
+            password,
+            paths,
+            to,
+        });
+    }
+}
+
+/// Issues the one-time password of the sending peer, in the service.
+pub fn issue_ticket(id: i32) -> BrokerMessage {
+    let password = uuid::Uuid::new_v4().simple().to_string();
+    let new = json!({
+        "password": password,
+        "ttl": TICKET_TTL,
+        "note": "Transfer broker",
+        "profile": {
+            "view_only": true,
+            "keyboard": false,
+            "clipboard": false,
+            "file": true,
+            "audio": false,
+            "tunnel": false,
+            "gamepad": false,
+        },
+    });
+    crate::server::one_time_password::add(&new.to_string());
+    BrokerMessage::Issued {
+        id,
+        peer_id: hbb_common::config::Config::get_id(),
+        password,
+        error: None,
+    }
+}
+
+/// The jobs sent by the sending peer for a connection, cancelled once it is closed.
+#[derive(Default)]
+pub struct Jobs {
+    cancels: HashMap<i32, oneshot::Sender<()>>,
+}
+
+impl Jobs {
+    pub fn start(
+        &mut self,
+        msg: BrokerMessage,
+        reply: impl Fn(BrokerMessage) + Send + Sync + 'static,
+    ) {
+        let BrokerMessage::Send {
+            id,
+            peer_id,
+            password,
+            paths,
+            to,
+        } = msg
+        else {
+            return;
+        };
+        self.cancels.retain(|_, tx| !tx.is_closed());
+        let (tx, rx) = oneshot::channel();
+        self.cancels.insert(id, tx);
+        tokio::spawn(async move {
+            log::info!("Brokered transfer {} to {}: {:?}", id, peer_id, paths);
+            let error = tokio::select! {
+                res = send(id, &peer_id, password, &paths, &to, &reply) => res.err().map(|e| e.to_string()),
+                _ = rx => Some("Cancelled".to_owned()),
+            };
+            log::info!("Brokered transfer {} done: {:?}", id, error);
+            reply(BrokerMessage::Done { id, error });
+        });
+    }
+
+    pub fn cancel(&mut self, id: i32) {
+        self.cancels.remove(&id);
+    }
+}
+
+// The session of the sending peer, logged in with the ticket.
+#[derive(Clone)]
+struct Handler {
+    lc: Arc<RwLock<LoginConfigHandler>>,
+    sender: mpsc::UnboundedSender<Data>,
+    password: String,
+}
+
+impl Handler {
+    fn new(peer_id: &str, password: String, sender: mpsc::UnboundedSender<Data>) -> Self {
+        let handler = Self {
+            lc: Default::default(),
+            sender,
+            password,
+        };
+        handler.lc.write().unwrap().initialize(
+            peer_id.to_owned(),
+            ConnType::FILE_TRANSFER,
+            None,
+            false,
+            None,
+            None,
+            None,
+        );
+        handler
+    }
+}
+
+#[async_trait]
+impl Interface for Handler {
+    fn get_lch(&self) -> Arc<RwLock<LoginConfigHandler>> {
+        self.lc.clone()
+    }
+
+    fn msgbox(&self, msgtype: &str, title: &str, text: &str, _link: &str) {
+        if msgtype == "input-password" {
+            let login = Data::Login((String::new(), String::new(), self.password.clone(), false));
+            self.sender.send(login).ok();
+        } else {
+            log::info!("{}: {}: {}", msgtype, title, text);
+        }
+    }
+
+    fn handle_login_error(&self, err: &str) -> bool {
+        handle_login_error(self.lc.clone(), err, self);
+        // The ticket is single-use, no retry.
+        false
+    }
+
+    fn handle_peer_info(&self, pi: PeerInfo) {
+        self.lc.write().unwrap().handle_peer_info(&pi);
+    }
+
+    fn set_multiple_windows_session(&self, _sessions: Vec<WindowsSession>) {}
+
+    async fn handle_hash(&self, pass: &str, hash: Hash, peer: &mut Stream) {
+        handle_hash(self.lc.clone(), pass, hash, self, peer).await;
+    }
+
+    async fn handle_login_from_ui(
+        &self,
+        os_username: String,
+        os_password: String,
+        password: String,
+        remember: bool,
+        peer: &mut Stream,
+    ) {
+        handle_login_from_ui(
+            self.lc.clone(),
+            os_username,
+            os_password,
+            password,
+            remember,
+            peer,
+        )
+        .await;
+    }
+
+    async fn handle_test_delay(&self, t: TestDelay, peer: &mut Stream) {
+        handle_test_delay(t, peer).await;
+    }
+
+    fn send(&self, data: Data) {
+        self.sender.send(data).ok();
+    }
+}
+
+// The folder `to` of the receiving peer joined with the name of `path`.
+pub(crate) fn target_path(path: &str, to: &str, is_windows: bool) -> String {
+    let sep = if is_windows { '\\' } else { '/' };
+    let name = Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    format!("{}{}{}", to.trim_end_matches(sep), sep, name)
+}
+
+// Uploads the files to the receiving peer, one job per path.
+async fn send(
+    id: i32,
+    peer_id: &str,
+    password: String,
+    paths: &[String],
+    to: &str,
+    reply: &impl Fn(BrokerMessage),
+) -> ResultType<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Data>();
+    let handler = Handler::new(peer_id, password, sender);
+    let key = crate::get_key(true).await;
+    let token = LocalConfig::get_option("access_token");
+    let ((mut stream, ..), _) = Client::start(
+        peer_id,
+        &key,
+        &token,
+        ConnType::FILE_TRANSFER,
+        handler.clone(),
+    )
+    .await?;
+    let mut jobs: Vec<fs::TransferJob> = Vec::new();
+    // The jobs not done by the receiving peer yet.
+    let mut pending = HashSet::new();
+    let mut total_size = 0;
+    let mut started = false;
+    let mut timer = crate::rustdesk_interval(time::interval(MILLI1));
+    let mut last_progress = (Instant::now(), 0);
+    loop {
+        tokio::select! {
+            res = timeout(READ_TIMEOUT, stream.next()) => {
+                let bytes = match res {
+                    Err(_) => bail!("Timeout"),
+                    Ok(Some(Ok(bytes))) => bytes,
+                    Ok(Some(Err(err))) => bail!("Connection closed: {}", err),
+                    Ok(None) => bail!("Reset by the peer"),
+                };
+                let msg_in = Message::parse_from_bytes(&bytes)?;
+                match msg_in.union {
+                    Some(message::Union::Hash(hash)) => {
+                        handler.handle_hash(&handler.password, hash, &mut stream).await;
+                    }
+                    Some(message::Union::LoginResponse(lr)) => match lr.union {
+                        Some(login_response::Union::Error(err)) => {
+                            handler.handle_login_error(&err);
+                            bail!("{}", err);
+                        }
+                        Some(login_response::Union::PeerInfo(pi)) if !started => {
+                            started = true;
+                            let is_windows = pi.platform == "Windows";
+                            handler.handle_peer_info(pi);
+                            for (i, path) in paths.iter().enumerate() {
+                                let job_id = i as i32 + 1;
+                                let to = target_path(path, to, is_windows);
+                                let job = fs::TransferJob::new_read(
+                                    job_id,
+                                    fs::JobType::Generic,
+                                    to.clone(),
+                                    fs::DataSource::FilePath(PathBuf::from(path)),
+                                    0,
+                                    false,
+                                    false,
+                                    true,
+                                )?;
+                                #[cfg(not(windows))]
+                                let files = job.files().clone();
+                                #[cfg(windows)]
+                                let mut files = job.files().clone();
+                                #[cfg(windows)]
+                                if !is_windows {
+                                    fs::transform_windows_path(&mut files);
+                                }
+                                total_size += job.total_size();
+                                stream
+                                    .send(&fs::new_receive(job_id, to, 0, files, job.total_size()))
+                                    .await?;
+                                jobs.push(job);
+                                pending.insert(job_id);
+                            }
+                            if pending.is_empty() {
+                                return Ok(());
+                            }
+                        }
+                        _ => {}
+                    },
+                    Some(message::Union::FileResponse(fr)) => match fr.union {
+                        // The file differs from the one of the receiving peer, overwritten.
+                        Some(file_response::Union::Digest(digest)) if digest.is_upload => {
+                            if let Some(job) = fs::get_job(digest.id, &mut jobs) {
+                                let req = FileTransferSendConfirmRequest {
+                                    id: digest.id,
+                                    file_num: digest.file_num,
+                                    union: Some(
+                                        file_transfer_send_confirm_request::Union::OffsetBlk(0),
+                                    ),
+                                    ..Default::default()
+                                };
+                                job.confirm(&req).await;
+                                stream.send(&fs::new_send_confirm(req)).await?;
+                            }
+                        }
+                        Some(file_response::Union::Done(d)) => {
+                            pending.remove(&d.id);
+                            if pending.is_empty() {
+                                return Ok(());
+                            }
+                        }
+                        Some(file_response::Union::Error(e)) => {
+                            bail!("{}", e.error);
+                        }
+                        _ => {}
+                    },
+                    Some(message::Union::FileAction(action)) => {
+                        if let Some(file_action::Union::SendConfirm(c)) = action.union {
+                            if let Some(job) = fs::get_job(c.id, &mut jobs) {
+                                job.confirm(&c).await;
+                            }
+                        }
+                    }
+                    Some(message::Union::TestDelay(t)) => {
+                        handler.handle_test_delay(t, &mut stream).await;
+                    }
+                    _ => {}
+                }
+            }
+            d = receiver.recv() => {
+                if let Some(Data::Login((os_username, os_password, password, remember))) = d {
+                    handler
+                        .handle_login_from_ui(os_username, os_password, password, remember, &mut stream)
+                        .await;
+                }
+            }
+            _ = timer.tick(), if !jobs.is_empty() => {
+                fs::handle_read_jobs(&mut jobs, &mut stream).await?;
+                let elapsed = last_progress.0.elapsed();
+                if elapsed >= PROGRESS_INTERVAL {
+                    // The jobs read are removed, their files are sent.
+                    let remaining: u64 = jobs
+                        .iter()
+                        .map(|j| j.total_size().saturating_sub(j.finished_size()))
+                        .sum();
+                    let finished_size = total_size - remaining.min(total_size);
+                    let speed = (finished_size - last_progress.1) as f64 / elapsed.as_secs_f64();
+                    reply(BrokerMessage::Progress {
+                        id,
+                        finished_size,
+                        total_size,
+                        speed,
+                    });
+                    last_progress = (Instant::now(), finished_size);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let msg = BrokerMessage::issue_error(1, "No permission of file transfer");
+        let content = serde_json::to_vec(&msg).unwrap();
+        assert_eq!(BrokerMessage::parse(&content), Some(msg));
+        assert_eq!(
+            BrokerMessage::parse(br#"{"t":"cancel","id":2}"#),
+            Some(BrokerMessage::Cancel { id: 2 })
+        );
+    }
+
+    #[test]
+    fn test_target_path() {
+        assert_eq!(target_path("/a/b.txt", "/c/", false), "/c/b.txt");
+        assert_eq!(target_path("/a/d", "C:\\e", true), "C:\\e\\d");
+    }
+}
@@ -0,0 +1,297 @@
+//! Checkpoints of the files being written by the file transfer, so an interrupted transfer
+//! resumes from the last verified block instead of trusting the size of the partial file.
+//!
+//! The writing side, the controlling side for a download or the connection manager for an
+//! upload, records the Adler-32 checksum of every completed `BLOCK_SIZE` block of the partial
+//! file, with the size and the modification time of the source. The checkpoints are stored
+//! on disk, one file per destination, so they survive a restart of either side.
+//!
+//! When a job is resumed, the partial file is read against the checksums, cut after the last
+//! block matching, and the offset sent to the reading side is the verified one.
+
+use hbb_common::{
+    config::{self, Config},
+    fs::{self, get_string},
+    get_time, log,
+    message_proto::FileTransferDigest,
+};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+    sync::{Mutex, Once},
+};
+
+const DIR: &str = "transfer_checkpoints";
+const BLOCK_SIZE: u64 = 1 << 20;
+const FORGET_AFTER: i64 = 7 * 24 * 3600_000;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    pub file_size: u64,
+    #[serde(default)]
+    pub last_modified: u64,
+    /// The checksums of the completed blocks of the partial file, in order.
+    #[serde(default)]
+    pub checksums: Vec<u32>,
+    #[serde(default)]
+    pub updated: i64,
+}
+
+impl Checkpoint {
+    fn is_of(&self, file_size: u64, last_modified: u64) -> bool {
+        self.file_size == file_size && self.last_modified == last_modified
+    }
+
+    // The number of leading blocks of the data matching the checksums.
+    fn verified_blocks(&self, mut read_block: impl FnMut(u64) -> Option<Vec<u8>>) -> usize {
+        self.checksums
+            .iter()
+            .enumerate()
+            .take_while(|(i, checksum)| {
+                read_block(*i as u64).map(|data| adler32(&data)) == Some(**checksum)
+            })
+            .count()
+    }
+}
+
+lazy_static::lazy_static! {
+    // The checkpoints of the files being written, by the destination path.
+    static ref CHECKPOINTS: Mutex<HashMap<String, Checkpoint>> = Default::default();
+}
+
+/// The rolling checksum of rsync.
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the most bytes summed before `b` may overflow.
+    for chunk in data.chunks(5552) {
+        for x in chunk {
+            a += *x as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+// The partial file of `hbb_common::fs`, renamed to the destination once complete.
+fn download_path(path: &str) -> String {
+    format!("{}.download", path)
+}
+
+fn checkpoint_path(path: &str) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(path.as_bytes());
+    let name = hasher.finalize()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    Config::path(DIR).join(format!("{}.toml", name))
+}
+
+fn load(path: &str) -> Option<Checkpoint> {
+    let checkpoint: Checkpoint = config::load_path(checkpoint_path(path));
+    (checkpoint.path == path).then_some(checkpoint)
+}
+
+fn store(checkpoint: &Checkpoint) {
+    let path = checkpoint_path(&checkpoint.path);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).ok();
+    }
+    if let Err(err) = config::store_path(path, checkpoint) {
+        log::error!("Failed to store the transfer checkpoint: {}", err);
+    }
+}
+
+fn remove(path: &str) {
+    if CHECKPOINTS.lock().unwrap().remove(path).is_some() || load(path).is_some() {
+        std::fs::remove_file(checkpoint_path(path)).ok();
+    }
+}
+
+// Removes the checkpoints not updated for `FORGET_AFTER`, of the jobs never resumed.
+fn prune() {
+    let Ok(entries) = std::fs::read_dir(Config::path(DIR)) else {
+        return;
+    };
+    let now = get_time();
+    for entry in entries.flatten() {
+        let checkpoint: Checkpoint = config::load_path(entry.path());
+        if now - checkpoint.updated > FORGET_AFTER {
+            std::fs::remove_file(entry.path()).ok();
+        }
+    }
+}
+
+fn read_block(file: &mut File, index: u64, len: u64) -> Option<Vec<u8>> {
+    let offset = index * BLOCK_SIZE;
+    if offset + BLOCK_SIZE > len {
+        return None;
+    }
+    let mut data = vec![0u8; BLOCK_SIZE as usize];
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    file.read_exact(&mut data).ok()?;
+    Some(data)
+}
+
+// The destination path, the size and the modification time of the source of the file.
+fn destination(job: &fs::TransferJob, file_num: i32) -> Option<(String, u64, u64)> {
+    let file = job.files().get(file_num as usize)?;
+    let fs::DataSource::FilePath(p) = &job.data_source else {
+        return None;
+    };
+    Some((
+        get_string(&fs::TransferJob::join(p, &file.name)),
+        file.size,
+        file.modified_time,
+    ))
+}
+
+/// Records the blocks completed by the last write of the file.
+pub fn on_written(job: &fs::TransferJob, file_num: i32) {
+    static PRUNE: Once = Once::new();
+    PRUNE.call_once(|| {
+        std::thread::spawn(prune);
+    });
+    let Some((path, file_size, last_modified)) = destination(job, file_num) else {
+        return;
+    };
+    let Ok(mut file) = File::open(download_path(&path)) else {
+        // Complete, or not started.
+        remove(&path);
+        return;
+    };
+    let len = file.metadata().map(|m| m.len()).unwrap_or_default();
+    let mut checkpoints = CHECKPOINTS.lock().unwrap();
+    let checkpoint = checkpoints
+        .entry(path.clone())
+        .or_insert_with(|| load(&path).unwrap_or_default());
+    if checkpoint.path != path || !checkpoint.is_of(file_size, last_modified) {
+        *checkpoint = Checkpoint {
+            path,
+            file_size,
+            last_modified,
+            ..Default::default()
+        };
+    }
+    let completed = (len / BLOCK_SIZE) as usize;
+    // The partial file was written again from the start.
+    let mut changed = checkpoint.checksums.len() > completed;
+    checkpoint.checksums.truncate(completed);
+    while checkpoint.checksums.len() < completed {
+        let Some(data) = read_block(&mut file, checkpoint.checksums.len() as u64, len) else {
+            break;
+        };
+        checkpoint.checksums.push(adler32(&data));
+        changed = true;
+    }
+    if changed {
+        checkpoint.updated = get_time();
+        store(checkpoint);
+    }
+}
+
+/// The job is cancelled, its partial files are removed.
+pub fn on_cancelled(job: &fs::TransferJob) {
+    for file_num in 0..job.files().len() {
+        if let Some((path, _, _)) = destination(job, file_num as _) {
+            remove(&path);
+        }
+    }
+}
+
+/// Sets the size transferred of the digest of a resumed job to the end of the last block
+/// verified, and cuts the partial file there.
+pub fn verify(path: &str, digest: &mut FileTransferDigest) {
+    if digest.transferred_size == 0 {
+        return;
+    }
+    let download_path = download_path(path);
+    let checkpoint = CHECKPOINTS
+        .lock()
+        .unwrap()
+        .remove(path)
+        .or_else(|| load(path))
+        .filter(|c| c.is_of(digest.file_size, digest.last_modified));
+    let verified = match (checkpoint, File::open(&download_path)) {
+        (Some(mut checkpoint), Ok(mut file)) => {
+            let len = file.metadata().map(|m| m.len()).unwrap_or_default();
+            let blocks = checkpoint.verified_blocks(|i| read_block(&mut file, i, len));
+            checkpoint.checksums.truncate(blocks);
+            checkpoint.updated = get_time();
+            store(&checkpoint);
+            (blocks as u64 * BLOCK_SIZE).min(digest.transferred_size)
+        }
+        _ => 0,
+    };
+    if verified < digest.transferred_size {
+        let cut = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&download_path)
+            .and_then(|f| f.set_len(verified));
+        if let Err(err) = cut {
+            log::error!("Failed to cut {} to the checkpoint: {}", download_path, err);
+            digest.transferred_size = 0;
+            return;
+        }
+        log::info!(
+            "Resume {} from the checkpoint {} instead of {}",
+            path,
+            verified,
+            digest.transferred_size
+        );
+    }
+    digest.transferred_size = verified;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        let data = vec![0xffu8; 100_000];
+        let (mut a, mut b) = (1u64, 0u64);
+        for x in &data {
+            a = (a + *x as u64) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(adler32(&data), ((b << 16) | a) as u32);
+    }
+
+    #[test]
+    fn test_verified_blocks() {
+        let blocks: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 16]).collect();
+        let checkpoint = Checkpoint {
+            checksums: blocks.iter().map(|b| adler32(b)).collect(),
+            ..Default::default()
+        };
+        assert_eq!(
+            checkpoint.verified_blocks(|i| blocks.get(i as usize).cloned()),
+            4
+        );
+        // The partial file is shorter than the checkpoint.
+        assert_eq!(
+            checkpoint.verified_blocks(|i| blocks.get(i as usize).filter(|_| i < 2).cloned()),
+            2
+        );
+        // A torn block and the ones after it are not verified.
+        let mut torn = blocks.clone();
+        torn[1][3] = 0xff;
+        assert_eq!(
+            checkpoint.verified_blocks(|i| torn.get(i as usize).cloned()),
+            1
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! The history of the transfer jobs of the sessions of this device.
+//!
+//! A session sends the record of every job done, failed or cancelled to the service with
+//! `ipc::Data::AddTransferRecord`, which appends it as one JSON line to `transfer_history.jsonl`
+//! in the config directory, rotated by size, and answers the queries of the ui with
+//! `get_config("transfer-history")`. The records are kept by the session itself if the service
+//! can not be reached. The session also notifies the user of the jobs which failed or took a
+//! while, and the records can be exported as CSV or JSON.
+
+use hbb_common::{config::Config, fs, get_time, log, tokio::time::Instant};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// Local option, notify the user of the jobs which failed or took a while.
+pub const OPTION_ENABLE_TRANSFER_NOTIFICATION: &str = "enable-transfer-notification";
+const FILE_NAME: &str = "transfer_history.jsonl";
+const OLD_FILE_NAME: &str = "transfer_history.1.jsonl";
+const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+// The newest records returned by the queries.
+const MAX_RECORDS: usize = 5000;
+// Milliseconds, the jobs done quicker are not notified.
+#[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+const NOTIFY_MIN_DURATION: u64 = 10_000;
+
+lazy_static::lazy_static! {
+    static ref LOCK: Mutex<()> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Record {
+    /// Milliseconds since the epoch, when the job started.
+    pub time: i64,
+    pub peer_id: String,
+    /// From this device to the peer.
+    pub upload: bool,
+    pub from: String,
+    pub to: String,
+    pub files: usize,
+    pub bytes: u64,
+    /// Milliseconds.
+    pub duration: u64,
+    /// None if done.
+    pub error: Option<String>,
+}
+
+fn path() -> PathBuf {
+    Config::path(FILE_NAME)
+}
+
+/// Appends the record to the history, in the service.
+pub fn append(record: &Record) {
+    let _lock = LOCK.lock().unwrap();
+    let path = path();
+    if std::fs::metadata(&path).map_or(false, |m| m.len() >= MAX_FILE_SIZE) {
+        std::fs::rename(&path, Config::path(OLD_FILE_NAME)).ok();
+    }
+    let res = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut f| {
+            let line = serde_json::to_string(record).unwrap_or_default();
+            f.write_all(format!("{}\n", line).as_bytes())
+        });
+    if let Err(err) = res {
+        log::error!("Failed to append to {}: {}", FILE_NAME, err);
+    }
+}
+
+/// Appends the record sent by a session.
+pub fn append_json(json: &str) {
+    match serde_json::from_str::<Record>(json) {
+        Ok(record) => append(&record),
+        Err(err) => log::error!("Invalid transfer record: {}", err),
+    }
+}
+
+/// The records, the newest first.
+pub fn read() -> Vec<Record> {
+    let _lock = LOCK.lock().unwrap();
+    let mut records = Vec::new();
+    for path in [Config::path(OLD_FILE_NAME), path()] {
+        let Ok(file) = File::open(path) else {
+            continue;
+        };
+        records.extend(
+            BufReader::new(file)
+                .lines()
+                .filter_map(|l| serde_json::from_str::<Record>(&l.ok()?).ok()),
+        );
+    }
+    records.reverse();
+    records.truncate(MAX_RECORDS);
+    records
+}
+
+pub fn get_json() -> String {
+    serde_json::to_string(&read()).unwrap_or_default()
+}
+
+fn csv_field(v: &str) -> String {
+    if v.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", v.replace('"', "\"\""))
+    } else {
+        v.to_owned()
+    }
+}
+
+pub fn to_csv(records: &[Record]) -> String {
+    let mut csv = "time,peer_id,direction,from,to,files,bytes,duration_ms,result\n".to_owned();
+    for r in records {
+        let row = [
+            r.time.to_string(),
+            csv_field(&r.peer_id),
+            if r.upload { "upload" } else { "download" }.to_owned(),
+            csv_field(&r.from),
+            csv_field(&r.to),
+            r.files.to_string(),
+            r.bytes.to_string(),
+            r.duration.to_string(),
+            csv_field(r.error.as_deref().unwrap_or("ok")),
+        ];
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Exports the records in JSON, as CSV if `path` ends with ".csv".
+pub fn export(records: &[Record], path: &str) -> std::io::Result<()> {
+    let content = if path.to_lowercase().ends_with(".csv") {
+        to_csv(records)
+    } else {
+        serde_json::to_string_pretty(records).unwrap_or_default()
+    };
+    std::fs::write(path, content)
+}
+
+#[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+fn notify(record: &Record) {
+    use crate::lang::translate;
+    use hbb_common::config::{self, LocalConfig};
+    let enabled = config::option2bool(
+        OPTION_ENABLE_TRANSFER_NOTIFICATION,
+        &LocalConfig::get_option(OPTION_ENABLE_TRANSFER_NOTIFICATION),
+    );
+    if !enabled || (record.error.is_none() && record.duration < NOTIFY_MIN_DURATION) {
+        return;
+    }
+    let summary = translate(
+        if record.error.is_none() {
+            "Transfer done"
+        } else {
+            "Transfer failed"
+        }
+        .to_owned(),
+    );
+    let mut body = format!("{}: {} -> {}", record.peer_id, record.from, record.to);
+    if let Some(err) = &record.error {
+        body = format!("{}\n{}", body, translate(err.clone()));
+    }
+    if let Err(err) = notify_rust::Notification::new()
+        .appname(&crate::get_app_name())
+        .summary(&summary)
+        .body(&body)
+        .show()
+    {
+        log::warn!("Failed to show the transfer notification: {}", err);
+    }
+}
+
+/// Notifies the user and sends the record to the service, in a thread.
+pub fn add(record: Record) {
+    std::thread::spawn(move || {
+        #[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+        notify(&record);
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        {
+            let json = serde_json::to_string(&record).unwrap_or_default();
+            if crate::ipc::set_data(&crate::ipc::Data::AddTransferRecord(json)).is_ok() {
+                return;
+            }
+        }
+        append(&record);
+    });
+}
+
+/// The jobs of a session, recorded once finished.
+#[derive(Default)]
+pub struct Jobs {
+    jobs: HashMap<i32, (Record, Instant)>,
+}
+
+impl Jobs {
+    /// Starts the record of the job, the printer jobs are not recorded.
+    pub fn start(&mut self, peer_id: &str, job: &fs::TransferJob) {
+        let fs::DataSource::FilePath(p) = &job.data_source else {
+            return;
+        };
+        let local = fs::get_string(p);
+        let (from, to) = if job.is_remote {
+            (job.remote.clone(), local)
+        } else {
+            (local, job.remote.clone())
+        };
+        let record = Record {
+            time: get_time(),
+            peer_id: peer_id.to_owned(),
+            upload: !job.is_remote,
+            from,
+            to,
+            files: job.files().len(),
+            bytes: job.total_size(),
+            ..Default::default()
+        };
+        self.jobs.insert(job.id(), (record, Instant::now()));
+    }
+
+    /// The files of the download, known once the peer has listed them.
+    pub fn update(&mut self, job: &fs::TransferJob) {
+        if let Some((record, _)) = self.jobs.get_mut(&job.id()) {
+            record.files = job.files().len();
+            record.bytes = job.total_size();
+        }
+    }
+
+    pub fn finish(&mut self, id: i32, error: Option<String>) {
+        if let Some((mut record, start)) = self.jobs.remove(&id) {
+            record.duration = start.elapsed().as_millis() as _;
+            record.error = error;
+            add(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv() {
+        let records = vec![
+            Record {
+                time: 1,
+                peer_id: "123".to_owned(),
+                upload: true,
+                from: "/a,b".to_owned(),
+                to: "C:\\\"c\"".to_owned(),
+                files: 2,
+                bytes: 3,
+                duration: 4,
+                error: None,
+            },
+            Record {
+                error: Some("Failed".to_owned()),
+                ..Default::default()
+            },
+        ];
+        let csv = to_csv(&records);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "1,123,upload,\"/a,b\",\"C:\\\"\"c\"\"\",2,3,4,ok");
+        assert_eq!(lines[2], "0,,download,,,0,0,0,Failed");
+    }
+
+    #[test]
+    fn test_record() {
+        let record: Record = serde_json::from_str(r#"{"peer_id":"1","bytes":5}"#).unwrap();
+        assert_eq!(record.bytes, 5);
+        assert!(record.error.is_none());
+    }
+}
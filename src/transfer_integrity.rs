@@ -0,0 +1,338 @@
+//! End-to-end verification of the files transferred, with BLAKE3.
+//!
+//! Once a job is done, the controlling side asks the peer for the hashes of the files of the job
+//! with [`IntegrityMessage::Request`], and hashes its own copies in a thread. The files whose
+//! hashes differ, or which can not be read on either side, are reported to the ui, which marks
+//! the job as failed and offers to transfer them again. The files skipped by the confirmations
+//! are not compared.
+//!
+//! The blocks are read and written by `hbb_common::fs`, so the hashes are streamed from the
+//! files once they are complete rather than from the blocks while they are transferred.
+//! Only the peers with [`PLATFORM_ADDITION_KEY`] are asked.
+
+use hbb_common::{
+    fs::{self, get_string},
+    log,
+    message_proto::{
+        file_transfer_send_confirm_request, FileTransferSendConfirmRequest, Message, Misc,
+        PluginRequest,
+    },
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, Read},
+};
+
+pub const PLUGIN_ID: &str = "__transfer_integrity";
+pub const PLATFORM_ADDITION_KEY: &str = "support_transfer_integrity";
+
+const READ_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", rename_all = "snake_case")]
+pub enum IntegrityMessage {
+    /// The paths on the peer of the files of the job, by their file numbers.
+    Request { id: i32, files: Vec<(i32, String)> },
+    /// The hex hashes of the files, empty for the ones which can not be read.
+    Hashes { id: i32, hashes: Vec<(i32, String)> },
+}
+
+impl IntegrityMessage {
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        serde_json::from_slice(content)
+            .map_err(|e| log::error!("Invalid transfer integrity message: {}", e))
+            .ok()
+    }
+
+    pub fn to_message(&self) -> Message {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(self).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        msg
+    }
+}
+
+fn hash_file(path: &str) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buf = vec![0u8; READ_SIZE];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// The hashes of the files, empty for the ones which can not be read.
+pub fn hash_files(files: &[(i32, String)]) -> Vec<(i32, String)> {
+    files
+        .iter()
+        .map(|(file_num, path)| {
+            let hash = hash_file(path).unwrap_or_else(|e| {
+                log::warn!("Failed to hash {}: {}", path, e);
+                String::new()
+            });
+            (*file_num, hash)
+        })
+        .collect()
+}
+
+/// A file of a job, both of its paths.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub file_num: i32,
+    pub local: String,
+    pub peer: String,
+}
+
+/// The file of the job joined to the root on the peer, with the separator of the peer.
+fn peer_path(root: &str, name: &str, sep: &str) -> String {
+    let name = if sep == "\\" {
+        name.replace('/', sep)
+    } else if cfg!(windows) {
+        name.replace('\\', sep)
+    } else {
+        name.to_owned()
+    };
+    if root.ends_with(sep) {
+        format!("{}{}", root, name)
+    } else {
+        format!("{}{}{}", root, sep, name)
+    }
+}
+
+/// The files of the job, `sep` is the path separator of the peer.
+pub fn entries(job: &fs::TransferJob, sep: &str) -> Vec<Entry> {
+    let fs::DataSource::FilePath(p) = &job.data_source else {
+        return Vec::new();
+    };
+    job.files()
+        .iter()
+        .enumerate()
+        .map(|(i, f)| Entry {
+            file_num: i as _,
+            local: get_string(&fs::TransferJob::join(p, &f.name)),
+            peer: peer_path(&job.remote, &f.name, sep),
+        })
+        .collect()
+}
+
+struct Verification {
+    is_upload: bool,
+    files: Vec<Entry>,
+    local: Option<HashMap<i32, String>>,
+    remote: Option<HashMap<i32, String>>,
+}
+
+/// A file whose copies differ, to be sent again from `from` to `to`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Corrupted {
+    pub file_num: i32,
+    pub from: String,
+    pub to: String,
+}
+
+/// The verifications of the jobs of a session, on the controlling side.
+#[derive(Default)]
+pub struct Verifier {
+    // The files of the uploads, since their jobs are removed before the peer is done.
+    uploads: HashMap<i32, Vec<Entry>>,
+    skipped: HashSet<(i32, i32)>,
+    verifying: HashMap<i32, Verification>,
+}
+
+impl Verifier {
+    pub fn add_upload(&mut self, id: i32, files: Vec<Entry>) {
+        self.uploads.insert(id, files);
+    }
+
+    /// Records the files skipped by the confirmation, their copies are not the same.
+    pub fn on_confirm(&mut self, req: &FileTransferSendConfirmRequest) {
+        if let Some(file_transfer_send_confirm_request::Union::Skip(true)) = req.union {
+            self.skipped.insert((req.id, req.file_num));
+        }
+    }
+
+    /// The file numbers of the job skipped so far.
+    pub fn skipped(&self, id: i32) -> HashSet<i32> {
+        self.skipped
+            .iter()
+            .filter(|(i, _)| *i == id)
+            .map(|(_, file_num)| *file_num)
+            .collect()
+    }
+
+    pub fn cancel(&mut self, id: i32) {
+        self.uploads.remove(&id);
+        self.skipped.retain(|(i, _)| *i != id);
+        self.verifying.remove(&id);
+    }
+
+    /// Starts the verification of the job done, the download of `files` or the upload added.
+    /// Returns the files to hash locally and the request of the hashes of the peer, none if
+    /// there is nothing to verify.
+    pub fn start(
+        &mut self,
+        id: i32,
+        files: Option<Vec<Entry>>,
+    ) -> Option<(Vec<(i32, String)>, Message)> {
+        let upload = self.uploads.remove(&id);
+        let is_upload = files.is_none();
+        let skipped = self.skipped(id);
+        self.skipped.retain(|(i, _)| *i != id);
+        let files: Vec<Entry> = files
+            .or(upload)?
+            .into_iter()
+            .filter(|f| !skipped.contains(&f.file_num))
+            .collect();
+        if files.is_empty() {
+            return None;
+        }
+        let local = files
+            .iter()
+            .map(|f| (f.file_num, f.local.clone()))
+            .collect();
+        let msg = IntegrityMessage::Request {
+            id,
+            files: files.iter().map(|f| (f.file_num, f.peer.clone())).collect(),
+        }
+        .to_message();
+        self.verifying.insert(
+            id,
+            Verification {
+                is_upload,
+                files,
+                local: None,
+                remote: None,
+            },
+        );
+        Some((local, msg))
+    }
+
+    /// The hashes of the local files, returns the result once both sides are hashed.
+    pub fn on_local(&mut self, id: i32, hashes: Vec<(i32, String)>) -> Option<Vec<Corrupted>> {
+        self.verifying.get_mut(&id)?.local = Some(hashes.into_iter().collect());
+        self.finish(id)
+    }
+
+    /// The hashes of the peer, returns the result once both sides are hashed.
+    pub fn on_remote(&mut self, id: i32, hashes: Vec<(i32, String)>) -> Option<Vec<Corrupted>> {
+        self.verifying.get_mut(&id)?.remote = Some(hashes.into_iter().collect());
+        self.finish(id)
+    }
+
+    fn finish(&mut self, id: i32) -> Option<Vec<Corrupted>> {
+        let v = self.verifying.get(&id)?;
+        let (Some(local), Some(remote)) = (&v.local, &v.remote) else {
+            return None;
+        };
+        let corrupted = v
+            .files
+            .iter()
+            .filter(|f| {
+                let l = local.get(&f.file_num).filter(|h| !h.is_empty());
+                l.is_none() || l != remote.get(&f.file_num)
+            })
+            .map(|f| {
+                let (from, to) = if v.is_upload {
+                    (f.local.clone(), f.peer.clone())
+                } else {
+                    (f.peer.clone(), f.local.clone())
+                };
+                Corrupted {
+                    file_num: f.file_num,
+                    from,
+                    to,
+                }
+            })
+            .collect::<Vec<_>>();
+        self.verifying.remove(&id);
+        if corrupted.is_empty() {
+            log::info!("Transfer job {} verified", id);
+        } else {
+            log::error!("Transfer job {}, {} files corrupted", id, corrupted.len());
+        }
+        Some(corrupted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file_num: i32) -> Entry {
+        Entry {
+            file_num,
+            local: format!("/local/{}", file_num),
+            peer: format!("/peer/{}", file_num),
+        }
+    }
+
+    #[test]
+    fn test_message() {
+        let msg = IntegrityMessage::Hashes {
+            id: 1,
+            hashes: vec![(0, "ab".to_owned()), (2, "".to_owned())],
+        };
+        let content = serde_json::to_vec(&msg).unwrap();
+        assert_eq!(IntegrityMessage::parse(&content), Some(msg));
+    }
+
+    #[test]
+    fn test_peer_path() {
+        assert_eq!(peer_path("C:\\a\\", "b/c.txt", "\\"), "C:\\a\\b\\c.txt");
+        assert_eq!(peer_path("/a", "b/c.txt", "/"), "/a/b/c.txt");
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let path = std::env::temp_dir().join("rustdesk_test_transfer_integrity");
+        std::fs::write(&path, b"abc").unwrap();
+        let hash = hash_file(&path.to_string_lossy()).unwrap();
+        assert_eq!(hash, blake3::hash(b"abc").to_hex().to_string());
+        std::fs::remove_file(&path).ok();
+        assert!(hash_file(&path.to_string_lossy()).is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut verifier = Verifier::default();
+        verifier.add_upload(1, vec![entry(0), entry(1), entry(2), entry(3)]);
+        verifier.on_confirm(&FileTransferSendConfirmRequest {
+            id: 1,
+            file_num: 3,
+            union: Some(file_transfer_send_confirm_request::Union::Skip(true)),
+            ..Default::default()
+        });
+        let (local, _) = verifier.start(1, None).unwrap();
+        assert_eq!(local.len(), 3);
+        let hashes = |h: [&str; 3]| (0..3).map(|i| (i, h[i as usize].to_owned())).collect();
+        assert_eq!(verifier.on_local(1, hashes(["a", "b", ""])), None);
+        let corrupted = verifier.on_remote(1, hashes(["a", "c", ""])).unwrap();
+        assert_eq!(
+            corrupted.iter().map(|c| c.file_num).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(corrupted[0].from, "/local/1");
+        assert_eq!(corrupted[0].to, "/peer/1");
+        assert!(verifier.on_remote(1, vec![]).is_none());
+        // A download of files all skipped.
+        verifier.on_confirm(&FileTransferSendConfirmRequest {
+            id: 2,
+            file_num: 0,
+            union: Some(file_transfer_send_confirm_request::Union::Skip(true)),
+            ..Default::default()
+        });
+        assert!(verifier.start(2, Some(vec![entry(0)])).is_none());
+    }
+}
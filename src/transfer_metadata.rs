@@ -0,0 +1,584 @@
+//! The metadata of the files transferred: the mode bits, the modification times, the symbolic
+//! links and optionally the extended attributes.
+//!
+//! The blocks only carry the content and the modification time of the files, so once a job is
+//! done the sending side lists the metadata of the files and of their folders in
+//! [`MetadataMessage::Records`], and the receiving side applies it. For an upload the controlling
+//! side sends the records with the root of the job on the peer, for a download it asks for them
+//! with [`MetadataMessage::Request`]. The files skipped by the confirmations are left as they
+//! were.
+//!
+//! The links to files are copied as their targets by the transfer and replaced by links here,
+//! only if they point inside the root of the job, the links to folders are followed by the
+//! listing of the job and their content is kept. The modes and the links are only sent from
+//! unix and applied on unix, without the setuid, setgid and sticky bits, Windows only keeps
+//! the read-only flag. Only the peers with [`PLATFORM_ADDITION_KEY`] are sent the records.
+//!
+//! The controlled side only reads or applies the metadata of the files of a job of the session
+//! once it is done, checked by [`PeerJob`], and does it in the connection manager, as the user,
+//! like the other file operations.
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use hbb_common::base64::{engine::general_purpose::STANDARD, Engine as _};
+use hbb_common::{
+    fs::{self, get_string},
+    log,
+    message_proto::{Message, Misc, PluginRequest},
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
+
+pub const PLUGIN_ID: &str = "__transfer_metadata";
+pub const PLATFORM_ADDITION_KEY: &str = "support_transfer_metadata";
+/// A local option of the controlling side, "Y" to transfer the extended attributes.
+pub const OPTION_TRANSFER_XATTRS: &str = "transfer-xattrs";
+
+// The larger attributes are not sent, like the resource forks of macOS.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const MAX_XATTR_SIZE: usize = 64 << 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// Relative to the root of the job and separated by `/`, empty for the root itself.
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// The seconds and the nanoseconds since the epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<(i64, u32)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub xattrs: Vec<(String, String)>,
+    #[serde(default)]
+    pub is_dir: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", rename_all = "snake_case")]
+pub enum MetadataMessage {
+    /// The names of the files of the download done under the root of the peer.
+    Request {
+        id: i32,
+        root: String,
+        names: Vec<String>,
+        xattrs: bool,
+    },
+    /// The root is where to apply the records for an upload, empty for a download.
+    Records {
+        id: i32,
+        root: String,
+        records: Vec<Record>,
+    },
+}
+
+impl MetadataMessage {
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        serde_json::from_slice(content)
+            .map_err(|e| log::error!("Invalid transfer metadata message: {}", e))
+            .ok()
+    }
+
+    pub fn to_message(&self) -> Message {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(self).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        msg
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.replace('\\', "/")
+}
+
+/// The path of the record under the root, none if the name leaves the root.
+fn join(root: &str, name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::from(root);
+    for part in name.split('/').filter(|p| !p.is_empty()) {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(c)), None) => path.push(c),
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+/// Whether the relative target of the link `name` stays under the root.
+#[cfg(unix)]
+fn link_in_root(name: &str, target: &str) -> bool {
+    let mut depth = name.split('/').filter(|p| !p.is_empty()).count();
+    if depth == 0 {
+        return false;
+    }
+    // The folder of the link.
+    depth -= 1;
+    for component in Path::new(target).components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(_) => depth += 1,
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+// The folders of the files, the deepest first, and the root.
+fn folders(names: &[String]) -> Vec<String> {
+    let mut folders = BTreeSet::new();
+    for name in names {
+        let mut name = name.as_str();
+        while let Some(i) = name.rfind('/') {
+            name = &name[..i];
+            folders.insert(name.to_owned());
+        }
+    }
+    let mut folders: Vec<String> = folders.into_iter().collect();
+    folders.sort_by_key(|f| std::cmp::Reverse(f.matches('/').count()));
+    folders.push(String::new());
+    folders
+}
+
+fn record(root: &str, name: &str, is_dir: bool, xattrs: bool) -> Option<Record> {
+    let path = join(root, name)?;
+    // The links to folders are followed by the listing.
+    let meta = if is_dir {
+        std::fs::metadata(&path).ok().filter(|m| m.is_dir())?
+    } else {
+        std::fs::symlink_metadata(&path).ok()?
+    };
+    let mtime = filetime::FileTime::from_last_modification_time(&meta);
+    #[allow(unused_mut)]
+    let mut record = Record {
+        name: name.to_owned(),
+        mtime: Some((mtime.unix_seconds(), mtime.nanoseconds())),
+        is_dir,
+        ..Default::default()
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if meta.file_type().is_symlink() {
+            record.symlink = std::fs::read_link(&path)
+                .ok()
+                .map(|t| t.to_string_lossy().to_string());
+        } else {
+            record.mode = Some(meta.permissions().mode() & 0o777);
+        }
+    }
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    if xattrs && record.symlink.is_none() {
+        record.xattrs = read_xattrs(&path);
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    let _ = xattrs;
+    Some(record)
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn read_xattrs(path: &Path) -> Vec<(String, String)> {
+    let Ok(names) = xattr::list(path) else {
+        return Vec::new();
+    };
+    names
+        .filter_map(|name| {
+            let name = name.to_string_lossy().to_string();
+            // The other namespaces of linux need privileges, or are the acls.
+            if cfg!(target_os = "linux") && !name.starts_with("user.") {
+                return None;
+            }
+            let value = xattr::get(path, &name).ok()??;
+            if value.len() > MAX_XATTR_SIZE {
+                return None;
+            }
+            Some((name, STANDARD.encode(value)))
+        })
+        .collect()
+}
+
+/// The records of the files of the job under `root` and of their folders, the folders last so
+/// their times are applied after their content changed.
+pub fn collect(root: &str, names: &[String], xattrs: bool) -> Vec<Record> {
+    let names: Vec<String> = names.iter().map(|n| normalize(n)).collect();
+    let mut records: Vec<Record> = names
+        .iter()
+        .filter_map(|name| record(root, name, false, xattrs))
+        .collect();
+    let files: HashSet<&String> = names.iter().collect();
+    records.extend(
+        folders(&names)
+            .iter()
+            .filter(|f| !files.contains(f))
+            .filter_map(|f| record(root, f, true, xattrs)),
+    );
+    records
+}
+
+fn apply_record(path: &Path, record: &Record) -> std::io::Result<()> {
+    let meta = std::fs::symlink_metadata(path)?;
+    if meta.is_dir() != record.is_dir {
+        return Ok(());
+    }
+    #[cfg(unix)]
+    if let Some(target) = &record.symlink {
+        if !link_in_root(&record.name, target) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("link to {} out of the root", target),
+            ));
+        }
+        if meta.file_type().is_file() {
+            std::fs::remove_file(path)?;
+            std::os::unix::fs::symlink(target, path)?;
+        }
+        if let Some((secs, nanos)) = record.mtime {
+            let t = filetime::FileTime::from_unix_time(secs, nanos);
+            filetime::set_symlink_file_times(path, t, t)?;
+        }
+        return Ok(());
+    }
+    #[cfg(not(unix))]
+    if record.symlink.is_some() {
+        return Ok(());
+    }
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    for (name, value) in &record.xattrs {
+        if let Ok(value) = STANDARD.decode(value) {
+            if let Err(e) = xattr::set(path, name, &value) {
+                log::debug!("Failed to set {} of {:?}: {}", name, path, e);
+            }
+        }
+    }
+    if let Some((secs, nanos)) = record.mtime {
+        filetime::set_file_mtime(path, filetime::FileTime::from_unix_time(secs, nanos))?;
+    }
+    if let Some(mode) = record.mode {
+        // Never the setuid, setgid and sticky bits.
+        let mode = mode & 0o777;
+        let mut permissions = meta.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.set_mode(mode);
+        }
+        #[cfg(not(unix))]
+        permissions.set_readonly(mode & 0o200 == 0);
+        std::fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+/// Applies the records to the files under `root`, except the ones `skipped`. Returns the
+/// number of the records which failed.
+pub fn apply(root: &str, records: &[Record], skipped: &HashSet<String>) -> usize {
+    let mut failed = 0;
+    for record in records.iter().filter(|r| !skipped.contains(&r.name)) {
+        let Some(path) = join(root, &record.name) else {
+            log::warn!("Ignored the metadata of {}, out of the root", record.name);
+            failed += 1;
+            continue;
+        };
+        if let Err(e) = apply_record(&path, record) {
+            log::warn!("Failed to apply the metadata of {:?}: {}", path, e);
+            failed += 1;
+        }
+    }
+    failed
+}
+
+/// A transfer job of the controlled side, its root and the names of its files.
+#[derive(Debug, Clone)]
+pub struct PeerJob {
+    root: String,
+    names: Vec<String>,
+    pub done: bool,
+}
+
+impl PeerJob {
+    pub fn new(root: &str, names: Vec<String>) -> Self {
+        Self {
+            root: root.to_owned(),
+            names: names.iter().map(|n| normalize(n)).collect(),
+            done: false,
+        }
+    }
+
+    /// Whether the message is of this job done, only of its files and their folders.
+    pub fn accepts(&self, msg: &MetadataMessage) -> bool {
+        if !self.done {
+            return false;
+        }
+        match msg {
+            MetadataMessage::Request { root, names, .. } => {
+                *root == self.root && names.iter().all(|n| self.names.contains(&normalize(n)))
+            }
+            MetadataMessage::Records { root, records, .. } => {
+                let folders = folders(&self.names);
+                *root == self.root
+                    && records
+                        .iter()
+                        .all(|r| self.names.contains(&r.name) || folders.contains(&r.name))
+            }
+        }
+    }
+}
+
+/// A job of the controlling side, its roots on both sides and the names of its files.
+#[derive(Debug, Clone)]
+pub struct Job {
+    local: String,
+    peer: String,
+    names: Vec<String>,
+}
+
+impl Job {
+    pub fn new(job: &fs::TransferJob) -> Option<Self> {
+        let fs::DataSource::FilePath(p) = &job.data_source else {
+            return None;
+        };
+        Some(Self {
+            local: get_string(p),
+            peer: job.remote.clone(),
+            names: job.files().iter().map(|f| f.name.clone()).collect(),
+        })
+    }
+
+    fn without(mut self, skipped: &HashSet<i32>) -> Self {
+        self.names = self
+            .names
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !skipped.contains(&(*i as i32)))
+            .map(|(_, n)| n)
+            .collect();
+        self
+    }
+}
+
+/// What to do once a job is done.
+pub enum Done {
+    /// Collect the records of the upload and send them to the peer.
+    Upload(Job),
+    /// Ask the peer for the records of the download.
+    Request(Message),
+}
+
+/// The metadata of the jobs of a session, on the controlling side.
+#[derive(Default)]
+pub struct Jobs {
+    // The uploads, since their jobs are removed before the peer is done.
+    uploads: HashMap<i32, Job>,
+    // The downloads waiting for their records, with the names skipped.
+    downloads: HashMap<i32, (String, HashSet<String>)>,
+}
+
+impl Jobs {
+    pub fn add_upload(&mut self, id: i32, job: Job) {
+        self.uploads.insert(id, job);
+    }
+
+    pub fn cancel(&mut self, id: i32) {
+        self.uploads.remove(&id);
+        self.downloads.remove(&id);
+    }
+
+    /// The job done, the download or the upload added, without the files `skipped`.
+    pub fn on_done(
+        &mut self,
+        id: i32,
+        download: Option<Job>,
+        skipped: &HashSet<i32>,
+        xattrs: bool,
+    ) -> Option<Done> {
+        let upload = self.uploads.remove(&id);
+        let Some(job) = download else {
+            return Some(Done::Upload(upload?.without(skipped)));
+        };
+        let skipped_names = job
+            .names
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| skipped.contains(&(*i as i32)))
+            .map(|(_, n)| normalize(n))
+            .collect();
+        let job = job.without(skipped);
+        if job.names.is_empty() {
+            return None;
+        }
+        self.downloads
+            .insert(id, (job.local.clone(), skipped_names));
+        Some(Done::Request(
+            MetadataMessage::Request {
+                id,
+                root: job.peer,
+                names: job.names,
+                xattrs,
+            }
+            .to_message(),
+        ))
+    }
+
+    /// The records of the download, applied to the local files.
+    pub fn on_records(&mut self, id: i32, records: &[Record]) {
+        if let Some((root, skipped)) = self.downloads.remove(&id) {
+            let failed = apply(&root, records, &skipped);
+            if failed > 0 {
+                log::warn!("Transfer job {}, {} records not applied", id, failed);
+            }
+        }
+    }
+}
+
+/// The records message of the upload, to apply under its root on the peer.
+pub fn upload_records(id: i32, job: &Job, xattrs: bool) -> Message {
+    MetadataMessage::Records {
+        id,
+        root: job.peer.clone(),
+        records: collect(&job.local, &job.names, xattrs),
+    }
+    .to_message()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join() {
+        let root = std::env::temp_dir();
+        let root = root.to_string_lossy();
+        assert!(join(&root, "a/b.txt").is_some());
+        assert_eq!(join(&root, "").unwrap(), PathBuf::from(&*root));
+        assert!(join(&root, "a/../../b").is_none());
+        assert!(join(&root, "/etc/passwd").is_some_and(|p| p.starts_with(&*root)));
+    }
+
+    #[test]
+    fn test_peer_job() {
+        let mut job = PeerJob::new("/tmp/a", vec!["b\\c.txt".to_owned(), "d.txt".to_owned()]);
+        let records = |root: &str, names: &[&str]| MetadataMessage::Records {
+            id: 1,
+            root: root.to_owned(),
+            records: names
+                .iter()
+                .map(|n| Record {
+                    name: n.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        let msg = records("/tmp/a", &["b/c.txt", "d.txt", "b", ""]);
+        assert!(!job.accepts(&msg));
+        job.done = true;
+        assert!(job.accepts(&msg));
+        assert!(!job.accepts(&records("/etc", &["d.txt"])));
+        assert!(!job.accepts(&records("/tmp/a", &["e.txt"])));
+        let request = |names: &[&str]| MetadataMessage::Request {
+            id: 1,
+            root: "/tmp/a".to_owned(),
+            names: names.iter().map(|n| n.to_string()).collect(),
+            xattrs: false,
+        };
+        assert!(job.accepts(&request(&["b/c.txt"])));
+        assert!(!job.accepts(&request(&["../e.txt"])));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_in_root() {
+        assert!(link_in_root("lib.so", "lib.so.1"));
+        assert!(link_in_root("a/b/c", "../d/./e"));
+        assert!(link_in_root("a/b/c", "../../d"));
+        assert!(!link_in_root("a/b/c", "../../../d"));
+        assert!(!link_in_root("a", "b/../../c"));
+        assert!(!link_in_root("a", "/etc/passwd"));
+        assert!(!link_in_root("", "a"));
+    }
+
+    #[test]
+    fn test_folders() {
+        let names = vec!["a/b/c.txt".to_owned(), "a/d.txt".to_owned(), "e".to_owned()];
+        assert_eq!(folders(&names), vec!["a/b", "a", ""]);
+    }
+
+    #[test]
+    fn test_message() {
+        let msg = MetadataMessage::Records {
+            id: 1,
+            root: "/tmp".to_owned(),
+            records: vec![Record {
+                name: "a".to_owned(),
+                mode: Some(0o755),
+                ..Default::default()
+            }],
+        };
+        let content = serde_json::to_vec(&msg).unwrap();
+        assert_eq!(MetadataMessage::parse(&content), Some(msg));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_apply() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join("rustdesk_test_transfer_metadata");
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        std::fs::remove_dir_all(&dir).ok();
+        for root in [&src, &dst] {
+            std::fs::create_dir_all(root.join("bin")).unwrap();
+            std::fs::write(root.join("bin/run"), b"#!/bin/sh").unwrap();
+            std::fs::write(root.join("lib.so.1"), b"lib").unwrap();
+            std::fs::write(root.join("lib.so"), b"lib").unwrap();
+        }
+        std::fs::set_permissions(src.join("bin/run"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        std::fs::remove_file(src.join("lib.so")).unwrap();
+        std::os::unix::fs::symlink("lib.so.1", src.join("lib.so")).unwrap();
+        std::fs::set_permissions(
+            src.join("lib.so.1"),
+            std::fs::Permissions::from_mode(0o4755),
+        )
+        .unwrap();
+        let names = vec![
+            "bin/run".to_owned(),
+            "lib.so".to_owned(),
+            "lib.so.1".to_owned(),
+        ];
+        let records = collect(&src.to_string_lossy(), &names, false);
+        assert_eq!(records.len(), 5);
+        let failed = apply(&dst.to_string_lossy(), &records, &HashSet::new());
+        assert_eq!(failed, 0);
+        let mode = std::fs::metadata(dst.join("bin/run"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+        let mode = std::fs::metadata(dst.join("lib.so.1"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o755);
+        assert_eq!(
+            std::fs::read_link(dst.join("lib.so")).unwrap(),
+            PathBuf::from("lib.so.1")
+        );
+        let escape = Record {
+            name: "lib.so.1".to_owned(),
+            symlink: Some("../../etc/passwd".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(apply(&dst.to_string_lossy(), &[escape], &HashSet::new()), 1);
+        assert!(std::fs::symlink_metadata(dst.join("lib.so.1"))
+            .unwrap()
+            .is_file());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
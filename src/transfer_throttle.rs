@@ -0,0 +1,239 @@
+//! Rate limit of the blocks sent by the file transfer, and the pause of the transfers while a
+//! remote session needs the bandwidth.
+//!
+//! The sending side, the controlling side for an upload or the connection for a download, has
+//! a token bucket per session, and the sessions of the process share the global one. The read
+//! jobs send their next blocks only when neither bucket is in debt, and the blocks sent are paid
+//! after, so the limits hold on average whatever the size of the blocks.
+//!
+//! The remote desktop sessions of the process report their round trip delays. While one of them
+//! is over `CONGESTED_DELAY`, the transfers are paused if the option is set, and they resume
+//! after `RESUME_AFTER` without congestion.
+
+use hbb_common::log;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// The limit in KB/s, empty or 0 for none. A local option of the controlling side, a peer
+/// option for the limit of a session, and an option of the controlled side.
+pub const OPTION_RATE_LIMIT: &str = "file-transfer-rate-limit";
+pub const OPTION_PAUSE_FOR_VIDEO: &str = "pause-file-transfer-for-video";
+
+const CONGESTED_DELAY: u32 = 300;
+const RESUME_AFTER: Duration = Duration::from_secs(3);
+// The most a bucket saves while idle, in seconds of its rate.
+const BURST: f64 = 0.5;
+const REFRESH: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Options {
+    /// In bytes per second, 0 for no limit.
+    pub session_rate: u64,
+    pub global_rate: u64,
+    pub pause_for_video: bool,
+}
+
+impl Options {
+    pub fn new(session_rate: &str, global_rate: &str, pause_for_video: &str) -> Self {
+        Self {
+            session_rate: parse_rate(session_rate),
+            global_rate: parse_rate(global_rate),
+            pause_for_video: pause_for_video == "Y",
+        }
+    }
+}
+
+fn parse_rate(kbps: &str) -> u64 {
+    kbps.trim().parse::<u64>().unwrap_or_default() * 1024
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(now: Instant) -> Self {
+        Self {
+            rate: 0,
+            tokens: 0.,
+            last: now,
+        }
+    }
+
+    fn set_rate(&mut self, rate: u64) {
+        if rate != self.rate {
+            self.rate = rate;
+            self.tokens = self.tokens.min(rate as f64 * BURST);
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        if self.rate == 0 {
+            self.tokens = 0.;
+        } else {
+            let max = self.rate as f64 * BURST;
+            self.tokens = (self.tokens + elapsed * self.rate as f64).min(max);
+        }
+    }
+
+    fn ready(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.rate == 0 || self.tokens >= 0.
+    }
+
+    fn pay(&mut self, bytes: u64) {
+        if self.rate > 0 {
+            self.tokens -= bytes as f64;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Video {
+    // The last delays of the remote desktop sessions, by the id of their throttle.
+    delays: HashMap<usize, u32>,
+    last_congested: Option<Instant>,
+}
+
+impl Video {
+    fn needs_bandwidth(&mut self, now: Instant) -> bool {
+        if self.delays.values().any(|d| *d > CONGESTED_DELAY) {
+            self.last_congested = Some(now);
+        }
+        self.last_congested
+            .map_or(false, |t| now.saturating_duration_since(t) < RESUME_AFTER)
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref GLOBAL: Mutex<Bucket> = Mutex::new(Bucket::new(Instant::now()));
+    static ref VIDEO: Mutex<Video> = Default::default();
+}
+
+/// The throttle of the read jobs of a session.
+pub struct Throttle {
+    id: usize,
+    bucket: Bucket,
+    options: Options,
+    refreshed: Option<Instant>,
+    paused: bool,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            bucket: Bucket::new(Instant::now()),
+            options: Default::default(),
+            refreshed: None,
+            paused: false,
+        }
+    }
+}
+
+impl Drop for Throttle {
+    fn drop(&mut self) {
+        VIDEO.lock().unwrap().delays.remove(&self.id);
+    }
+}
+
+impl Throttle {
+    /// Whether the read jobs may send their next blocks, `options` is read once per `REFRESH`.
+    pub fn ready(&mut self, options: impl FnOnce() -> Options) -> bool {
+        let now = Instant::now();
+        if self
+            .refreshed
+            .map_or(true, |t| now.saturating_duration_since(t) >= REFRESH)
+        {
+            self.refreshed = Some(now);
+            self.options = options();
+            self.bucket.set_rate(self.options.session_rate);
+        }
+        let paused = self.options.pause_for_video && VIDEO.lock().unwrap().needs_bandwidth(now);
+        if paused != self.paused {
+            self.paused = paused;
+            log::info!(
+                "File transfer {} for the remote session",
+                if paused { "paused" } else { "resumed" }
+            );
+        }
+        if paused || !self.bucket.ready(now) {
+            return false;
+        }
+        let mut global = GLOBAL.lock().unwrap();
+        global.set_rate(self.options.global_rate);
+        global.ready(now)
+    }
+
+    /// Pays for the bytes sent by the read jobs.
+    pub fn pay(&mut self, bytes: u64) {
+        self.bucket.pay(bytes);
+        GLOBAL.lock().unwrap().pay(bytes);
+    }
+
+    /// The round trip delay of the remote desktop session, in ms.
+    pub fn on_video_delay(&self, delay: u32) {
+        VIDEO.lock().unwrap().delays.insert(self.id, delay);
+    }
+}
+
+/// The total size finished by the jobs, the bytes sent are its change by
+/// `fs::handle_read_jobs`.
+pub fn finished_size(jobs: &[hbb_common::fs::TransferJob]) -> u64 {
+    jobs.iter().map(|j| j.finished_size()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(now);
+        bucket.pay(1 << 20);
+        assert!(bucket.ready(now));
+        bucket.set_rate(1000);
+        assert!(bucket.ready(now));
+        bucket.pay(1500);
+        assert!(!bucket.ready(now + Duration::from_millis(1000)));
+        assert!(bucket.ready(now + Duration::from_millis(1500)));
+        // Idle time saves at most `BURST`.
+        assert!(bucket.ready(now + Duration::from_secs(60)));
+        bucket.pay(1000);
+        assert!(!bucket.ready(now + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_video() {
+        let now = Instant::now();
+        let mut video = Video::default();
+        video.delays.insert(1, 50);
+        assert!(!video.needs_bandwidth(now));
+        video.delays.insert(2, 500);
+        assert!(video.needs_bandwidth(now));
+        video.delays.remove(&2);
+        assert!(video.needs_bandwidth(now + Duration::from_secs(1)));
+        assert!(!video.needs_bandwidth(now + RESUME_AFTER));
+    }
+
+    #[test]
+    fn test_options() {
+        let options = Options::new("100", " ", "Y");
+        assert_eq!(options.session_rate, 100 * 1024);
+        assert_eq!(options.global_rate, 0);
+        assert!(options.pause_for_video);
+    }
+}
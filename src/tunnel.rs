@@ -0,0 +1,677 @@
+//! TCP tunnels multiplexed over an established session.
+//!
+//! The frames are carried in `Misc::PluginRequest` with the reserved id `PLUGIN_ID`,
+//! so that no extra connection to the peer is needed.
+//!
+//! local forward:
+//!     The controlling side listens on `127.0.0.1:local_port`, every accepted connection
+//!     is opened as a stream to `remote_host:remote_port` on the controlled side.
+//! reverse forward:
+//!     The controlling side asks the controlled side to listen on
+//!     `remote_host:remote_port`, every accepted connection is opened as a stream back to
+//!     `127.0.0.1:local_port` on the controlling side.
+//!
+//! socks:
+//!     The controlling side runs a SOCKS5 proxy on `127.0.0.1:local_port`, every CONNECT
+//!     request is opened as a stream to the requested address on the controlled side.
+//!
+//! Opening a stream to an address on the controlled side, and listening there, needs
+//! `allow-remote-tunnel` to be enabled on the controlled side, and the tunnel permission of
+//! the connection. The reverse forwards listen on `127.0.0.1` if `remote_host` is empty, an
+//! address which is not loopback also needs `allow-tunnel-public-listen` there.
+//!
+//! Stream ids are even on the controlling side and odd on the controlled side.
+//! The data of a stream is flow controlled by a window, the receiver acks what it has
+//! written to the socket, and closes the stream if the peer sends more than the window.
+
+use hbb_common::{
+    allow_err, bail,
+    config::{self, Config},
+    log,
+    message_proto::{Message, Misc, PluginRequest},
+    tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::{mpsc, oneshot, Semaphore},
+        task::JoinHandle,
+    },
+    ResultType,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+mod socks5;
+
+pub const PLUGIN_ID: &str = "__tunnel";
+pub const OPTION_ALLOW_REMOTE_TUNNEL: &str = "allow-remote-tunnel";
+pub const OPTION_ALLOW_TUNNEL_PUBLIC_LISTEN: &str = "allow-tunnel-public-listen";
+pub const PEER_OPTION_TUNNELS: &str = "tunnels";
+pub const KIND_LOCAL: &str = "local";
+pub const KIND_REVERSE: &str = "reverse";
+pub const KIND_SOCKS: &str = "socks";
+
+const WINDOW: usize = 256 * 1024;
+const CHUNK: usize = 16 * 1024;
+
+const FRAME_OPEN: u8 = 1;
+const FRAME_CLOSE: u8 = 2;
+const FRAME_DATA: u8 = 3;
+const FRAME_ACK: u8 = 4;
+const FRAME_LISTEN: u8 = 5;
+const FRAME_LISTEN_RESULT: u8 = 6;
+const FRAME_UNLISTEN: u8 = 7;
+const FRAME_OPEN_OK: u8 = 8;
+
+pub type Sink = Arc<dyn Fn(Message) + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Frame {
+    // `target` is "host:port", or "#listen_id" for a connection accepted by a reverse forward.
+    Open { id: u32, target: String },
+    Close { id: u32, reason: String },
+    Data { id: u32, data: Vec<u8> },
+    Ack { id: u32, len: u32 },
+    Listen { id: u32, addr: String },
+    // `err` is empty on success.
+    ListenResult { id: u32, err: String },
+    Unlisten { id: u32 },
+    // The controlled side has connected to the target of the stream.
+    OpenOk { id: u32 },
+}
+
+impl Frame {
+    fn encode(&self) -> Vec<u8> {
+        let (typ, id, payload): (u8, u32, &[u8]) = match self {
+            Frame::Open { id, target } => (FRAME_OPEN, *id, target.as_bytes()),
+            Frame::Close { id, reason } => (FRAME_CLOSE, *id, reason.as_bytes()),
+            Frame::Data { id, data } => (FRAME_DATA, *id, data),
+            Frame::Ack { id, len } => {
+                let mut v = vec![FRAME_ACK];
+                v.extend(id.to_be_bytes());
+                v.extend(len.to_be_bytes());
+                return v;
+            }
+            Frame::Listen { id, addr } => (FRAME_LISTEN, *id, addr.as_bytes()),
+            Frame::ListenResult { id, err } => (FRAME_LISTEN_RESULT, *id, err.as_bytes()),
+            Frame::Unlisten { id } => (FRAME_UNLISTEN, *id, &[]),
+            Frame::OpenOk { id } => (FRAME_OPEN_OK, *id, &[]),
+        };
+        let mut v = Vec::with_capacity(5 + payload.len());
+        v.push(typ);
+        v.extend(id.to_be_bytes());
+        v.extend_from_slice(payload);
+        v
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 5 {
+            return None;
+        }
+        let id = u32::from_be_bytes(buf[1..5].try_into().ok()?);
+        let payload = &buf[5..];
+        let text = || String::from_utf8_lossy(payload).to_string();
+        Some(match buf[0] {
+            FRAME_OPEN => Frame::Open { id, target: text() },
+            FRAME_CLOSE => Frame::Close { id, reason: text() },
+            FRAME_DATA => Frame::Data {
+                id,
+                data: payload.to_vec(),
+            },
+            FRAME_ACK => Frame::Ack {
+                id,
+                len: u32::from_be_bytes(payload.get(..4)?.try_into().ok()?),
+            },
+            FRAME_LISTEN => Frame::Listen { id, addr: text() },
+            FRAME_LISTEN_RESULT => Frame::ListenResult { id, err: text() },
+            FRAME_UNLISTEN => Frame::Unlisten { id },
+            FRAME_OPEN_OK => Frame::OpenOk { id },
+            _ => return None,
+        })
+    }
+}
+
+/// A tunnel persisted in the peer config.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TunnelConfig {
+    pub kind: String,
+    pub local_port: u16,
+    pub remote_host: String,
+    pub remote_port: u16,
+}
+
+impl TunnelConfig {
+    pub fn key(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            self.kind, self.local_port, self.remote_host, self.remote_port
+        )
+    }
+
+    fn remote_addr(&self) -> String {
+        let host = if self.remote_host.is_empty() {
+            "127.0.0.1"
+        } else {
+            &self.remote_host
+        };
+        if host.contains(':') && !host.starts_with('[') {
+            format!("[{}]:{}", host, self.remote_port)
+        } else {
+            format!("{}:{}", host, self.remote_port)
+        }
+    }
+}
+
+pub fn parse_tunnels(s: &str) -> Vec<TunnelConfig> {
+    serde_json::from_str(s).unwrap_or_default()
+}
+
+#[inline]
+pub fn is_allowed() -> bool {
+    config::option2bool(
+        OPTION_ALLOW_REMOTE_TUNNEL,
+        &Config::get_option(OPTION_ALLOW_REMOTE_TUNNEL),
+    )
+}
+
+// Only an IP address is checked, a host name may resolve to anything.
+fn is_listen_allowed(addr: &str) -> bool {
+    addr.parse::<std::net::SocketAddr>()
+        .map_or(false, |a| a.ip().is_loopback())
+        || config::option2bool(
+            OPTION_ALLOW_TUNNEL_PUBLIC_LISTEN,
+            &Config::get_option(OPTION_ALLOW_TUNNEL_PUBLIC_LISTEN),
+        )
+}
+
+pub fn is_tunnel_request(id: &str) -> bool {
+    id == PLUGIN_ID
+}
+
+struct StreamHandle {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    // The data received and not acked yet, at most the window.
+    queued: Arc<AtomicUsize>,
+    window: Arc<Semaphore>,
+    task: Option<JoinHandle<()>>,
+    // Notified on `OpenOk`, dropped on `Close`.
+    opened: Option<oneshot::Sender<()>>,
+}
+
+enum Forward {
+    Local(JoinHandle<()>),
+    Reverse(u32),
+}
+
+struct Inner {
+    is_client: bool,
+    sink: Sink,
+    next_id: AtomicU32,
+    streams: Mutex<HashMap<u32, StreamHandle>>,
+    // Accept loops, of the reverse forwards on the controlled side.
+    listeners: Mutex<HashMap<u32, JoinHandle<()>>>,
+    // Local targets of the reverse forwards on the controlling side.
+    reverse_targets: Mutex<HashMap<u32, String>>,
+    forwards: Mutex<HashMap<String, Forward>>,
+}
+
+pub struct Tunnel {
+    inner: Arc<Inner>,
+}
+
+impl Tunnel {
+    pub fn new(is_client: bool, sink: Sink) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                is_client,
+                sink,
+                next_id: AtomicU32::new(if is_client { 2 } else { 1 }),
+                streams: Default::default(),
+                listeners: Default::default(),
+                reverse_targets: Default::default(),
+                forwards: Default::default(),
+            }),
+        }
+    }
+
+    /// Start a forward on the controlling side.
+    pub async fn add(&self, t: &TunnelConfig) -> ResultType<()> {
+        let key = t.key();
+        if self.inner.forwards.lock().unwrap().contains_key(&key) {
+            return Ok(());
+        }
+        let forward = match t.kind.as_str() {
+            KIND_SOCKS => {
+                let listener = TcpListener::bind(("127.0.0.1", t.local_port)).await?;
+                log::info!("socks5 proxy listening on {:?}", listener.local_addr());
+                let inner = self.inner.clone();
+                Forward::Local(tokio::spawn(async move {
+                    while let Ok((socket, _)) = listener.accept().await {
+                        let inner = inner.clone();
+                        tokio::spawn(async move {
+                            allow_err!(Inner::run_socks_stream(inner, socket).await);
+                        });
+                    }
+                }))
+            }
+            KIND_LOCAL => {
+                let listener = TcpListener::bind(("127.0.0.1", t.local_port)).await?;
+                log::info!("tunnel listening on {:?} to {}", listener.local_addr(), key);
+                let inner = self.inner.clone();
+                let target = t.remote_addr();
+                Forward::Local(tokio::spawn(async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((socket, addr)) => {
+                                log::debug!("tunnel connection from {}", addr);
+                                let id = inner.new_id();
+                                inner.send(Frame::Open {
+                                    id,
+                                    target: target.clone(),
+                                });
+                                Inner::start_stream(&inner, id, socket);
+                            }
+                            Err(err) => {
+                                log::error!("tunnel accept failed: {}", err);
+                                break;
+                            }
+                        }
+                    }
+                }))
+            }
+            KIND_REVERSE => {
+                let id = self.inner.new_id();
+                self.inner
+                    .reverse_targets
+                    .lock()
+                    .unwrap()
+                    .insert(id, format!("127.0.0.1:{}", t.local_port));
+                self.inner.send(Frame::Listen {
+                    id,
+                    addr: t.remote_addr(),
+                });
+                Forward::Reverse(id)
+            }
+            _ => bail!("unknown tunnel kind {}", t.kind),
+        };
+        self.inner.forwards.lock().unwrap().insert(key, forward);
+        Ok(())
+    }
+
+    /// Stop a forward on the controlling side, the open streams are kept.
+    pub fn remove(&self, t: &TunnelConfig) {
+        match self.inner.forwards.lock().unwrap().remove(&t.key()) {
+            Some(Forward::Local(task)) => task.abort(),
+            Some(Forward::Reverse(id)) => {
+                self.inner.reverse_targets.lock().unwrap().remove(&id);
+                self.inner.send(Frame::Unlisten { id });
+            }
+            None => {}
+        }
+    }
+
+    /// Handle the content of a tunnel plugin request from the peer.
+    /// Returns an error to be shown to the user.
+    pub async fn handle(&self, content: &[u8]) -> Option<String> {
+        let Some(frame) = Frame::decode(content) else {
+            log::error!("invalid tunnel frame");
+            return None;
+        };
+        let inner = &self.inner;
+        match frame {
+            Frame::Data { id, data } => {
+                let mut streams = inner.streams.lock().unwrap();
+                if let Some(s) = streams.get(&id) {
+                    if s.queued.fetch_add(data.len(), Ordering::SeqCst) + data.len() > WINDOW {
+                        log::error!("tunnel stream {} exceeded the window", id);
+                        streams.remove(&id);
+                        drop(streams);
+                        inner.send(Frame::Close {
+                            id,
+                            reason: "window exceeded".to_owned(),
+                        });
+                    } else {
+                        s.tx.send(data).ok();
+                    }
+                }
+            }
+            Frame::Ack { id, len } => {
+                if let Some(s) = inner.streams.lock().unwrap().get(&id) {
+                    s.window.add_permits(len as _);
+                }
+            }
+            Frame::Close { id, reason } => {
+                if !reason.is_empty() {
+                    log::info!("tunnel stream {} closed by peer: {}", id, reason);
+                }
+                // Dropping the sender ends the stream task after the queued data is written.
+                inner.streams.lock().unwrap().remove(&id);
+            }
+            Frame::Open { id, target } => {
+                let target = if let Some(listen_id) = target.strip_prefix('#') {
+                    let listen_id = listen_id.parse::<u32>().unwrap_or_default();
+                    inner
+                        .reverse_targets
+                        .lock()
+                        .unwrap()
+                        .get(&listen_id)
+                        .cloned()
+                } else if !inner.is_client && is_allowed() {
+                    Some(target)
+                } else {
+                    None
+                };
+                let Some(target) = target else {
+                    inner.send(Frame::Close {
+                        id,
+                        reason: "not allowed".to_owned(),
+                    });
+                    return None;
+                };
+                // Register before connecting, the data received meanwhile is queued.
+                let (rx, queued, window) = inner.register(id, None);
+                let inner2 = inner.clone();
+                let task = tokio::spawn(async move {
+                    match TcpStream::connect(&target).await {
+                        Ok(socket) => {
+                            inner2.send(Frame::OpenOk { id });
+                            Inner::run_stream(inner2, id, socket, rx, queued, window).await
+                        }
+                        Err(err) => {
+                            log::error!("tunnel failed to connect {}: {}", target, err);
+                            if inner2.streams.lock().unwrap().remove(&id).is_some() {
+                                inner2.send(Frame::Close {
+                                    id,
+                                    reason: err.to_string(),
+                                });
+                            }
+                        }
+                    }
+                });
+                inner.set_task(id, task);
+            }
+            Frame::Listen { id, addr } => {
+                let res = if inner.is_client || !is_allowed() {
+                    Err(hbb_common::anyhow::anyhow!("not allowed"))
+                } else if !is_listen_allowed(&addr) {
+                    Err(hbb_common::anyhow::anyhow!(
+                        "listening on {} is not allowed, only on the loopback address",
+                        addr
+                    ))
+                } else {
+                    TcpListener::bind(&addr).await.map_err(|e| e.into())
+                };
+                match res {
+                    Ok(listener) => {
+                        log::info!("tunnel listening on {:?}", listener.local_addr());
+                        let inner2 = inner.clone();
+                        let task = tokio::spawn(async move {
+                            while let Ok((socket, _)) = listener.accept().await {
+                                let stream_id = inner2.new_id();
+                                inner2.send(Frame::Open {
+                                    id: stream_id,
+                                    target: format!("#{}", id),
+                                });
+                                Inner::start_stream(&inner2, stream_id, socket);
+                            }
+                        });
+                        if let Some(old) = inner.listeners.lock().unwrap().insert(id, task) {
+                            old.abort();
+                        }
+                        inner.send(Frame::ListenResult {
+                            id,
+                            err: "".to_owned(),
+                        });
+                    }
+                    Err(err) => {
+                        log::error!("tunnel failed to listen on {}: {}", addr, err);
+                        inner.send(Frame::ListenResult {
+                            id,
+                            err: err.to_string(),
+                        });
+                    }
+                }
+            }
+            Frame::ListenResult { id, err } => {
+                if !err.is_empty() {
+                    inner.reverse_targets.lock().unwrap().remove(&id);
+                    inner
+                        .forwards
+                        .lock()
+                        .unwrap()
+                        .retain(|_, f| !matches!(f, Forward::Reverse(x) if *x == id));
+                    return Some(err);
+                }
+            }
+            Frame::OpenOk { id } => {
+                if let Some(s) = inner.streams.lock().unwrap().get_mut(&id) {
+                    if let Some(opened) = s.opened.take() {
+                        opened.send(()).ok();
+                    }
+                }
+            }
+            Frame::Unlisten { id } => {
+                if let Some(task) = inner.listeners.lock().unwrap().remove(&id) {
+                    task.abort();
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        for (_, f) in self.inner.forwards.lock().unwrap().drain() {
+            if let Forward::Local(task) = f {
+                task.abort();
+            }
+        }
+        for (_, task) in self.inner.listeners.lock().unwrap().drain() {
+            task.abort();
+        }
+        for (_, s) in self.inner.streams.lock().unwrap().drain() {
+            if let Some(task) = s.task {
+                task.abort();
+            }
+        }
+    }
+}
+
+impl Inner {
+    fn new_id(&self) -> u32 {
+        self.next_id.fetch_add(2, Ordering::SeqCst)
+    }
+
+    fn send(&self, frame: Frame) {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: PLUGIN_ID.to_owned(),
+            content: frame.encode().into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        (self.sink)(msg);
+    }
+
+    fn set_task(&self, id: u32, task: JoinHandle<()>) {
+        if let Some(s) = self.streams.lock().unwrap().get_mut(&id) {
+            s.task = Some(task);
+        }
+    }
+
+    fn register(
+        &self,
+        id: u32,
+        opened: Option<oneshot::Sender<()>>,
+    ) -> (
+        mpsc::UnboundedReceiver<Vec<u8>>,
+        Arc<AtomicUsize>,
+        Arc<Semaphore>,
+    ) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let window = Arc::new(Semaphore::new(WINDOW));
+        self.streams.lock().unwrap().insert(
+            id,
+            StreamHandle {
+                tx,
+                queued: queued.clone(),
+                window: window.clone(),
+                task: None,
+                opened,
+            },
+        );
+        (rx, queued, window)
+    }
+
+    fn start_stream(inner: &Arc<Inner>, id: u32, socket: TcpStream) {
+        let (rx, queued, window) = inner.register(id, None);
+        let task = tokio::spawn(Self::run_stream(
+            inner.clone(),
+            id,
+            socket,
+            rx,
+            queued,
+            window,
+        ));
+        inner.set_task(id, task);
+    }
+
+    async fn run_socks_stream(inner: Arc<Inner>, mut socket: TcpStream) -> ResultType<()> {
+        let target = socks5::handshake(&mut socket).await?;
+        let id = inner.new_id();
+        let (opened_tx, opened_rx) = oneshot::channel();
+        let (rx, queued, window) = inner.register(id, Some(opened_tx));
+        inner.send(Frame::Open {
+            id,
+            target: target.clone(),
+        });
+        if opened_rx.await.is_err() {
+            socks5::reply(&mut socket, socks5::REP_CONNECTION_REFUSED)
+                .await
+                .ok();
+            bail!("socks5 failed to connect {}", target);
+        }
+        socks5::reply(&mut socket, socks5::REP_SUCCEEDED).await?;
+        Self::run_stream(inner, id, socket, rx, queued, window).await;
+        Ok(())
+    }
+
+    async fn run_stream(
+        inner: Arc<Inner>,
+        id: u32,
+        socket: TcpStream,
+        mut rx: mpsc::UnboundedReceiver<Vec<u8>>,
+        queued: Arc<AtomicUsize>,
+        window: Arc<Semaphore>,
+    ) {
+        socket.set_nodelay(true).ok();
+        let (mut reader, mut writer) = socket.into_split();
+        let inner2 = inner.clone();
+        let read = async move {
+            let mut buf = vec![0u8; CHUNK];
+            loop {
+                let n = reader.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                window.acquire_many(n as _).await?.forget();
+                inner2.send(Frame::Data {
+                    id,
+                    data: buf[..n].to_vec(),
+                });
+            }
+            ResultType::Ok(())
+        };
+        let inner2 = inner.clone();
+        let write = async move {
+            while let Some(data) = rx.recv().await {
+                writer.write_all(&data).await?;
+                queued.fetch_sub(data.len(), Ordering::SeqCst);
+                inner2.send(Frame::Ack {
+                    id,
+                    len: data.len() as _,
+                });
+            }
+            writer.shutdown().await.ok();
+            ResultType::Ok(())
+        };
+        let res = tokio::select! {
+            res = read => res,
+            res = write => res,
+        };
+        if inner.streams.lock().unwrap().remove(&id).is_some() {
+            let reason = match res {
+                Ok(()) => "".to_owned(),
+                Err(err) => err.to_string(),
+            };
+            inner.send(Frame::Close { id, reason });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame() {
+        let frames = vec![
+            Frame::Open {
+                id: 2,
+                target: "example.com:22".to_owned(),
+            },
+            Frame::Open {
+                id: 3,
+                target: "#4".to_owned(),
+            },
+            Frame::Close {
+                id: 5,
+                reason: "".to_owned(),
+            },
+            Frame::Data {
+                id: u32::MAX,
+                data: vec![0, 1, 2],
+            },
+            Frame::Ack { id: 6, len: 1024 },
+            Frame::Listen {
+                id: 8,
+                addr: "[::1]:8080".to_owned(),
+            },
+            Frame::ListenResult {
+                id: 8,
+                err: "denied".to_owned(),
+            },
+            Frame::Unlisten { id: 8 },
+            Frame::OpenOk { id: 9 },
+        ];
+        for f in frames {
+            assert_eq!(Frame::decode(&f.encode()), Some(f));
+        }
+        assert_eq!(Frame::decode(&[FRAME_ACK, 0, 0, 0, 1]), None);
+        assert_eq!(Frame::decode(&[0, 0, 0, 0, 1]), None);
+    }
+
+    #[test]
+    fn test_remote_addr() {
+        let mut t = TunnelConfig {
+            kind: KIND_LOCAL.to_owned(),
+            local_port: 1,
+            remote_host: "".to_owned(),
+            remote_port: 22,
+        };
+        assert_eq!(t.remote_addr(), "127.0.0.1:22");
+        t.remote_host = "::1".to_owned();
+        assert_eq!(t.remote_addr(), "[::1]:22");
+        t.remote_host = "host".to_owned();
+        assert_eq!(t.remote_addr(), "host:22");
+    }
+}
@@ -0,0 +1,130 @@
+//! The server part of SOCKS5 (RFC 1928), CONNECT without authentication only.
+
+use hbb_common::{
+    bail,
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    },
+    ResultType,
+};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+const VERSION: u8 = 5;
+const METHOD_NO_AUTH: u8 = 0;
+const METHOD_NOT_ACCEPTABLE: u8 = 0xff;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+pub const REP_SUCCEEDED: u8 = 0;
+pub const REP_CONNECTION_REFUSED: u8 = 5;
+const REP_COMMAND_NOT_SUPPORTED: u8 = 7;
+const REP_ADDRESS_NOT_SUPPORTED: u8 = 8;
+
+/// Negotiate the method and read the request, returns the target as "host:port".
+pub async fn handshake(socket: &mut TcpStream) -> ResultType<String> {
+    let mut head = [0u8; 2];
+    socket.read_exact(&mut head).await?;
+    if head[0] != VERSION {
+        bail!("unsupported socks version {}", head[0]);
+    }
+    let mut methods = vec![0u8; head[1] as usize];
+    socket.read_exact(&mut methods).await?;
+    if !methods.contains(&METHOD_NO_AUTH) {
+        socket.write_all(&[VERSION, METHOD_NOT_ACCEPTABLE]).await?;
+        bail!("socks client requires authentication");
+    }
+    socket.write_all(&[VERSION, METHOD_NO_AUTH]).await?;
+
+    let mut req = [0u8; 4];
+    socket.read_exact(&mut req).await?;
+    if req[1] != CMD_CONNECT {
+        reply(socket, REP_COMMAND_NOT_SUPPORTED).await?;
+        bail!("unsupported socks command {}", req[1]);
+    }
+    let addr = match req[3] {
+        ATYP_IPV4 => {
+            let mut addr = vec![0u8; 4];
+            socket.read_exact(&mut addr).await?;
+            addr
+        }
+        ATYP_IPV6 => {
+            let mut addr = vec![0u8; 16];
+            socket.read_exact(&mut addr).await?;
+            addr
+        }
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            socket.read_exact(&mut len).await?;
+            let mut addr = vec![0u8; len[0] as usize];
+            socket.read_exact(&mut addr).await?;
+            addr
+        }
+        _ => {
+            reply(socket, REP_ADDRESS_NOT_SUPPORTED).await?;
+            bail!("unsupported socks address type {}", req[3]);
+        }
+    };
+    let mut port = [0u8; 2];
+    socket.read_exact(&mut port).await?;
+    match target(req[3], &addr, u16::from_be_bytes(port)) {
+        Some(target) => Ok(target),
+        None => {
+            reply(socket, REP_ADDRESS_NOT_SUPPORTED).await?;
+            bail!("invalid socks address");
+        }
+    }
+}
+
+/// The bound address is not meaningful for a tunnel, so it is always zero.
+pub async fn reply(socket: &mut TcpStream, rep: u8) -> ResultType<()> {
+    socket
+        .write_all(&[VERSION, rep, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+        .await?;
+    Ok(())
+}
+
+fn target(atyp: u8, addr: &[u8], port: u16) -> Option<String> {
+    match atyp {
+        ATYP_IPV4 => {
+            let ip: [u8; 4] = addr.try_into().ok()?;
+            Some(format!("{}:{}", Ipv4Addr::from(ip), port))
+        }
+        ATYP_IPV6 => {
+            let ip: [u8; 16] = addr.try_into().ok()?;
+            Some(format!("[{}]:{}", Ipv6Addr::from(ip), port))
+        }
+        ATYP_DOMAIN => {
+            let host = std::str::from_utf8(addr).ok()?;
+            if host.is_empty() {
+                return None;
+            }
+            Some(format!("{}:{}", host, port))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target() {
+        assert_eq!(
+            target(ATYP_IPV4, &[10, 0, 0, 1], 22),
+            Some("10.0.0.1:22".to_owned())
+        );
+        let mut v6 = [0u8; 16];
+        v6[15] = 1;
+        assert_eq!(target(ATYP_IPV6, &v6, 80), Some("[::1]:80".to_owned()));
+        assert_eq!(
+            target(ATYP_DOMAIN, b"example.com", 443),
+            Some("example.com:443".to_owned())
+        );
+        assert_eq!(target(ATYP_DOMAIN, b"", 443), None);
+        assert_eq!(target(ATYP_IPV4, &[1, 2, 3], 1), None);
+    }
+}
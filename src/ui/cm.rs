@@ -70,6 +70,8 @@ impl InvokeUiCM for SciterHandler {
         );
     }
 
+    fn update_input_blocked_state(&self, _client: &crate::ui_cm_interface::Client) {}
+
     fn file_transfer_log(&self, _action: &str, _log: &str) {}
 }
 
@@ -61,8 +61,10 @@ pub struct Client {
     pub recording: bool,
     pub block_input: bool,
     pub from_switch: bool,
+    pub sas: String,
     pub in_voice_call: bool,
     pub incoming_voice_call: bool,
+    pub input_blocked: bool,
     #[serde(skip)]
     #[cfg(not(any(target_os = "ios")))]
     tx: UnboundedSender<Data>,
@@ -109,6 +111,8 @@ pub trait InvokeUiCM: Send + Clone + 'static + Sized {
 
     fn update_voice_call_state(&self, client: &Client);
 
+    fn update_input_blocked_state(&self, client: &Client);
+
     fn file_transfer_log(&self, action: &str, log: &str);
 }
 
@@ -145,6 +149,7 @@ impl<T: InvokeUiCM> ConnectionManager<T> {
         recording: bool,
         block_input: bool,
         from_switch: bool,
+        sas: String,
         #[cfg(not(any(target_os = "ios")))] tx: mpsc::UnboundedSender<Data>,
     ) {
         let client = Client {
@@ -165,10 +170,12 @@ impl<T: InvokeUiCM> ConnectionManager<T> {
             recording,
             block_input,
             from_switch,
+            sas,
             #[cfg(not(any(target_os = "ios")))]
             tx,
             in_voice_call: false,
             incoming_voice_call: false,
+            input_blocked: false,
         };
         CLIENTS
             .write()
@@ -247,6 +254,14 @@ impl<T: InvokeUiCM> ConnectionManager<T> {
         }
     }
 
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    fn input_blocked(&self, id: i32, blocked: bool) {
+        if let Some(client) = CLIENTS.write().unwrap().get_mut(&id) {
+            client.input_blocked = blocked;
+            self.ui_handler.update_input_blocked_state(client);
+        }
+    }
+
     #[cfg(not(target_os = "ios"))]
     fn voice_call_closed(&self, id: i32, _reason: &str) {
         if let Some(client) = CLIENTS.write().unwrap().get_mut(&id) {
@@ -409,9 +424,9 @@ impl<T: InvokeUiCM> IpcTaskRunner<T> {
                         }
                         Ok(Some(data)) => {
                             match data {
-                                Data::Login{id, is_file_transfer, is_view_camera, is_terminal, port_forward, peer_id, name, authorized, keyboard, clipboard, audio, file, file_transfer_enabled: _file_transfer_enabled, restart, recording, block_input, from_switch} => {
+                                Data::Login{id, is_file_transfer, is_view_camera, is_terminal, port_forward, peer_id, name, authorized, keyboard, clipboard, audio, file, file_transfer_enabled: _file_transfer_enabled, restart, recording, block_input, from_switch, sas} => {
                                     log::debug!("conn_id: {}", id);
-                                    self.cm.add_connection(id, is_file_transfer, is_view_camera, is_terminal, port_forward, peer_id, name, authorized, keyboard, clipboard, audio, file, restart, recording, block_input, from_switch, self.tx.clone());
+                                    self.cm.add_connection(id, is_file_transfer, is_view_camera, is_terminal, port_forward, peer_id, name, authorized, keyboard, clipboard, audio, file, restart, recording, block_input, from_switch, sas, self.tx.clone());
                                     self.conn_id = id;
                                     #[cfg(target_os = "windows")]
                                     {
@@ -502,6 +517,9 @@ impl<T: InvokeUiCM> IpcTaskRunner<T> {
                                 Data::CloseVoiceCall(reason) => {
                                     self.cm.voice_call_closed(self.conn_id, reason.as_str());
                                 }
+                                Data::InputBlocked(blocked) => {
+                                    self.cm.input_blocked(self.conn_id, blocked);
+                                }
                                 #[cfg(target_os = "windows")]
                                 Data::ClipboardNonFile(_) => {
                                     match crate::clipboard::check_clipboard_cm() {
@@ -693,6 +711,7 @@ pub async fn start_listen<T: InvokeUiCM>(
                 recording,
                 block_input,
                 from_switch,
+                sas,
                 ..
             }) => {
                 current_id = id;
@@ -713,6 +732,7 @@ pub async fn start_listen<T: InvokeUiCM>(
                     recording,
                     block_input,
                     from_switch,
+                    sas,
                     tx.clone(),
                 );
             }
@@ -814,7 +834,9 @@ async fn handle_fs(
             write_jobs.push(job);
         }
         ipc::FS::CancelWrite { id } => {
+            crate::delta_sync::cancel_write(id);
             if let Some(job) = fs::remove_job(id, write_jobs) {
+                crate::transfer_checkpoint::on_cancelled(&job);
                 job.remove_download_file();
                 tx_log.map(|tx: &UnboundedSender<String>| {
                     tx.send(serialize_transfer_job(&job, false, true, ""))
@@ -853,6 +875,7 @@ async fn handle_fs(
                 {
                     send_raw(fs::new_error(id, err, file_num), &tx);
                 }
+                crate::transfer_checkpoint::on_written(job, file_num);
             }
         }
         ipc::FS::CheckDigest {
@@ -890,6 +913,9 @@ async fn handle_fs(
                                         send_raw(msg_out, &tx);
                                     }
                                     DigestCheckResult::NeedConfirm(mut digest) => {
+                                        if is_resume {
+                                            crate::transfer_checkpoint::verify(&path, &mut digest);
+                                        }
                                         // upload to server, but server has the same file, request
                                         digest.is_upload = is_upload;
                                         let mut msg_out = Message::new();
@@ -919,9 +945,89 @@ async fn handle_fs(
                 }
             }
         }
+        ipc::FS::DeltaSync(content) => {
+            use crate::delta_sync::DeltaMessage;
+            match DeltaMessage::parse(&content) {
+                Some(DeltaMessage::Request { id, file_num }) => {
+                    if let Some(job) = fs::get_job(id, write_jobs) {
+                        let tx_delta = tx.clone();
+                        if !crate::delta_sync::start_write(job, file_num, move |msg| {
+                            send_raw(msg, &tx_delta)
+                        }) {
+                            let req = crate::delta_sync::confirm(id, file_num, false);
+                            job.confirm(&req).await;
+                            send_raw(new_send_confirm(req), tx);
+                        }
+                    }
+                }
+                Some(DeltaMessage::Ops { id, file_num, ops }) => {
+                    send_raw(crate::delta_sync::on_ops(id, file_num, &ops), tx);
+                }
+                Some(DeltaMessage::End { id, file_num, hash }) => {
+                    let req = crate::delta_sync::on_end(id, file_num, &hash);
+                    if let Some(job) = fs::get_job(id, write_jobs) {
+                        job.confirm(&req).await;
+                    }
+                    send_raw(new_send_confirm(req), tx);
+                }
+                _ => {}
+            }
+        }
         ipc::FS::Rename { id, path, new_name } => {
             rename_file(path, new_name, id, tx).await;
         }
+        ipc::FS::FileOps(content) => {
+            if let Some(msg) = crate::file_ops::FileOpMessage::parse(&content) {
+                let tx = tx.clone();
+                crate::file_ops::handle(msg, move |m| send_raw(m.to_message(), &tx));
+            }
+        }
+        ipc::FS::FileDrop(content) => {
+            use crate::file_drop::FileDropMessage;
+            if let Some(FileDropMessage::Query { id }) = FileDropMessage::parse(&content) {
+                let msg = match spawn_blocking(crate::file_drop::target_dir).await {
+                    Ok(Some(dir)) => FileDropMessage::Target {
+                        id,
+                        dir,
+                        error: None,
+                    },
+                    _ => FileDropMessage::error(id, "Failed to get the folder"),
+                };
+                send_raw(msg.to_message(), tx);
+            }
+        }
+        ipc::FS::TransferMetadata(content) => {
+            use crate::transfer_metadata::MetadataMessage;
+            match MetadataMessage::parse(&content) {
+                Some(MetadataMessage::Request {
+                    id,
+                    root,
+                    names,
+                    xattrs,
+                }) => {
+                    let tx = tx.clone();
+                    spawn_blocking(move || {
+                        let records = crate::transfer_metadata::collect(&root, &names, xattrs);
+                        let msg = MetadataMessage::Records {
+                            id,
+                            root: String::new(),
+                            records,
+                        };
+                        send_raw(msg.to_message(), &tx);
+                    });
+                }
+                Some(MetadataMessage::Records { id, root, records }) => {
+                    spawn_blocking(move || {
+                        let failed =
+                            crate::transfer_metadata::apply(&root, &records, &Default::default());
+                        if failed > 0 {
+                            log::warn!("Transfer job {}, {} records not applied", id, failed);
+                        }
+                    });
+                }
+                None => {}
+            }
+        }
         _ => {}
     }
 }
@@ -955,7 +1061,14 @@ async fn read_dir(dir: &str, include_hidden: bool, tx: &UnboundedSender<Data>) {
             fs::get_path(dir)
         }
     };
-    if let Ok(Ok(fd)) = spawn_blocking(move || fs::read_dir(&path, include_hidden)).await {
+    if let Ok(Ok(fd)) = spawn_blocking(move || {
+        let res = fs::read_dir(&path, include_hidden);
+        #[cfg(target_os = "android")]
+        let res = add_storage_volumes(&path, res);
+        res
+    })
+    .await
+    {
         let mut msg_out = Message::new();
         let mut file_response = FileResponse::new();
         file_response.set_dir(fd);
@@ -964,6 +1077,51 @@ async fn read_dir(dir: &str, include_hidden: bool, tx: &UnboundedSender<Data>) {
     }
 }
 
+// The parents of the storage volumes, e.g. "/storage" of the SD card "/storage/1234-5678",
+// are not readable by the app. Their entries leading to the volumes are added,
+// so the volumes can be browsed from the remote.
+#[cfg(target_os = "android")]
+fn add_storage_volumes(
+    path: &std::path::Path,
+    res: hbb_common::ResultType<FileDirectory>,
+) -> hbb_common::ResultType<FileDirectory> {
+    let volumes = scrap::android::call_main_service_get_by_name("storage_volumes")
+        .ok()
+        .and_then(|v| serde_json::from_str::<Vec<String>>(&v).ok())
+        .unwrap_or_default();
+    let mut names: Vec<String> = vec![];
+    for volume in volumes.iter() {
+        if let Ok(rest) = std::path::Path::new(volume).strip_prefix(path) {
+            if let Some(name) = rest.iter().next() {
+                let name = name.to_string_lossy().to_string();
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+    if names.is_empty() {
+        return res;
+    }
+    let mut fd = match res {
+        Ok(fd) => fd,
+        Err(_) => FileDirectory {
+            path: get_string(&path.to_path_buf()),
+            ..Default::default()
+        },
+    };
+    for name in names {
+        if !fd.entries.iter().any(|e| e.name == name) {
+            fd.entries.push(FileEntry {
+                entry_type: FileType::Dir.into(),
+                name,
+                ..Default::default()
+            });
+        }
+    }
+    Ok(fd)
+}
+
 #[cfg(not(any(target_os = "ios")))]
 async fn handle_result<F: std::fmt::Display, S: std::fmt::Display>(
     res: std::result::Result<std::result::Result<(), F>, S>,
@@ -348,6 +348,8 @@ pub fn get_sound_inputs() -> Vec<String> {
                     }
                 }
             }
+            #[cfg(windows)]
+            out.extend(crate::platform::win_audio::get_audio_apps());
             out
         }
 
@@ -715,6 +717,12 @@ pub fn discover() {
 #[cfg(feature = "flutter")]
 pub fn peer_to_map(id: String, p: PeerConfig) -> HashMap<&'static str, String> {
     use hbb_common::sodiumoxide::base64;
+    let patch_level: serde_json::Value = p
+        .options
+        .get(crate::patch_level::PEER_OPTION)
+        .and_then(|x| serde_json::from_str(x).ok())
+        .unwrap_or_default();
+    let patch_level = |k: &str| patch_level[k].as_str().unwrap_or_default().to_owned();
     HashMap::<&str, String>::from_iter([
         ("id", id),
         ("username", p.info.username.clone()),
@@ -728,6 +736,13 @@ pub fn peer_to_map(id: String, p: PeerConfig) -> HashMap<&'static str, String> {
             "hash",
             base64::encode(p.password, base64::Variant::Original),
         ),
+        (
+            "os_version",
+            format!("{} {}", patch_level("os"), patch_level("os_version"))
+                .trim()
+                .to_owned(),
+        ),
+        ("version", patch_level("version")),
     ])
 }
 
@@ -963,7 +978,7 @@ pub fn has_vram() -> bool {
 
 #[cfg(feature = "flutter")]
 #[inline]
-pub fn supported_hwdecodings() -> (bool, bool) {
+pub fn supported_hwdecodings() -> (bool, bool, bool) {
     let decoding =
         scrap::codec::Decoder::supported_decodings(None, use_texture_render(), None, &vec![]);
     #[allow(unused_mut)]
@@ -979,7 +994,12 @@ pub fn supported_hwdecodings() -> (bool, bool) {
             h265 = true;
         }
     }
-    (h264, h265)
+    // AV1 is always decodable in software, only report hardware decoding.
+    #[cfg(feature = "hwcodec")]
+    let av1 = scrap::hwcodec::HwRamDecoder::try_get(scrap::CodecFormat::AV1).is_some();
+    #[cfg(not(feature = "hwcodec"))]
+    let av1 = false;
+    (h264, h265, av1)
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -1461,6 +1481,46 @@ pub fn verify_bot(token: String) -> String {
     }
 }
 
+pub fn check_access_control_list(rules: String) -> String {
+    #[cfg(not(target_os = "ios"))]
+    return crate::server::access_list::first_invalid(&rules).unwrap_or_default();
+    #[cfg(target_os = "ios")]
+    {
+        let _ = rules;
+        "".to_owned()
+    }
+}
+
+/// Register a security key of this device, as JSON with either "credential" or "error".
+pub fn register_security_key() -> String {
+    match crate::auth_security_key::register() {
+        Ok(credential) => serde_json::json!({ "credential": credential }),
+        Err(err) => serde_json::json!({ "error": err.to_string() }),
+    }
+    .to_string()
+}
+
+/// Verify a recording signed by this device, as JSON with either the "fingerprint" of its
+/// key and whether the recording is "complete", or "error".
+pub fn verify_recording(path: String) -> String {
+    let pk = Config::get_key_pair().1;
+    #[cfg(not(target_os = "ios"))]
+    let res = crate::server::record_signing::verify_file(std::path::Path::new(&path), &pk);
+    #[cfg(target_os = "ios")]
+    let res: hbb_common::ResultType<bool> = {
+        let _ = path;
+        Err(hbb_common::anyhow::anyhow!("Not supported"))
+    };
+    match res {
+        Ok(complete) => {
+            let fingerprint = crate::common::pk_to_fingerprint(pk);
+            serde_json::json!({ "fingerprint": fingerprint, "complete": complete })
+        }
+        Err(err) => serde_json::json!({ "error": err.to_string() }),
+    }
+    .to_string()
+}
+
 pub fn check_hwcodec() {
     #[cfg(feature = "hwcodec")]
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -1523,6 +1583,130 @@ pub fn clear_trusted_devices() {
     ipc::clear_trusted_devices();
 }
 
+#[cfg(feature = "flutter")]
+pub fn get_lockouts() -> String {
+    #[cfg(target_os = "ios")]
+    return "".to_owned();
+    #[cfg(target_os = "android")]
+    return crate::server::lockout::get_json();
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    return ipc::get_lockouts();
+}
+
+#[cfg(feature = "flutter")]
+pub fn clear_lockouts(json: &str) {
+    if is_settings_locked() {
+        return;
+    }
+    let sources = serde_json::from_str::<Vec<String>>(json).unwrap_or_default();
+    #[cfg(target_os = "android")]
+    crate::server::lockout::clear(&sources);
+    #[cfg(target_os = "ios")]
+    let _ = sources;
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    ipc::clear_lockouts(sources);
+}
+
+pub fn get_settings_protection() -> String {
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    return crate::settings_guard::get_status_json();
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    return ipc::get_settings_protection();
+}
+
+pub fn unlock_settings(code: String) -> bool {
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    return crate::settings_guard::unlock(&code);
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    return ipc::unlock_settings(code).unwrap_or_default();
+}
+
+pub fn set_settings_admin_password(password: String) {
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    crate::settings_guard::set_admin_password(&password);
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    allow_err!(ipc::set_config(
+        crate::settings_guard::OPTION_SETTINGS_ADMIN_PASSWORD,
+        password
+    ));
+}
+
+pub fn get_one_time_passwords() -> String {
+    #[cfg(target_os = "ios")]
+    return "".to_owned();
+    #[cfg(target_os = "android")]
+    return crate::server::one_time_password::get_json();
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    return ipc::get_one_time_passwords();
+}
+
+// As checked by the service, to tell the UI that the change is rejected.
+#[cfg(feature = "flutter")]
+fn is_settings_locked() -> bool {
+    serde_json::from_str::<serde_json::Value>(&get_settings_protection())
+        .map_or(false, |v| v["locked"] == true)
+}
+
+/// Generate a one-time password with the TTL and permission profile in `json`, returns
+/// the password, which is not retrievable afterwards, empty if the settings are locked.
+#[cfg(feature = "flutter")]
+pub fn add_one_time_password(json: &str) -> String {
+    if is_settings_locked() {
+        return "".to_owned();
+    }
+    let mut v: serde_json::Value = serde_json::from_str(json).unwrap_or_default();
+    let password = Config::get_auto_password(10);
+    v["password"] = serde_json::json!(password);
+    let json = v.to_string();
+    #[cfg(target_os = "android")]
+    crate::server::one_time_password::add(&json);
+    #[cfg(target_os = "ios")]
+    let _ = json;
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    ipc::add_one_time_password(json);
+    password
+}
+
+#[cfg(feature = "flutter")]
+pub fn revoke_one_time_passwords(json: &str) {
+    if is_settings_locked() {
+        return;
+    }
+    let ids = serde_json::from_str::<Vec<String>>(json).unwrap_or_default();
+    #[cfg(target_os = "android")]
+    crate::server::one_time_password::revoke(&ids);
+    #[cfg(target_os = "ios")]
+    let _ = ids;
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    ipc::revoke_one_time_passwords(ids);
+}
+
+#[cfg(feature = "flutter")]
+pub fn get_transfer_history() -> String {
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    return crate::transfer_history::get_json();
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    return ipc::get_transfer_history();
+}
+
+/// Exports the transfer history to `path`, returns the error if failed.
+#[cfg(feature = "flutter")]
+pub fn export_transfer_history(path: &str) -> String {
+    let records =
+        serde_json::from_str::<Vec<crate::transfer_history::Record>>(&get_transfer_history())
+            .unwrap_or_default();
+    match crate::transfer_history::export(&records, path) {
+        Ok(()) => "".to_owned(),
+        Err(err) => err.to_string(),
+    }
+}
+
+/// The configured server key pins and the current pins of the custom servers, as JSON.
+#[tokio::main(flavor = "current_thread")]
+pub async fn get_server_key_pins() -> String {
+    crate::tls_pin::get_server_pins_json().await
+}
+
 #[cfg(feature = "flutter")]
 pub fn max_encrypt_len() -> usize {
     hbb_common::config::ENCRYPT_MAX_LEN
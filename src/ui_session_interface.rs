@@ -10,7 +10,7 @@ use hbb_common::config::keys;
 #[cfg(not(feature = "flutter"))]
 use hbb_common::fs;
 use hbb_common::{
-    allow_err,
+    allow_err, bail,
     config::{Config, LocalConfig, PeerConfig},
     get_version_number, log,
     message_proto::*,
@@ -20,17 +20,21 @@ use hbb_common::{
         sync::mpsc,
         time::{Duration as TokioDuration, Instant},
     },
-    whoami, Stream,
+    whoami, ResultType, Stream,
 };
 use rdev::{Event, EventType::*, KeyCode};
+use scrap::record::RecordAudio;
 #[cfg(all(feature = "vram", feature = "flutter"))]
 use std::ffi::c_void;
 use std::{
     collections::HashMap,
     ops::{Deref, DerefMut},
     str::FromStr,
-    sync::{Arc, Mutex, RwLock},
-    time::SystemTime,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime},
 };
 use uuid::Uuid;
 
@@ -46,6 +50,7 @@ use crate::keyboard;
 use crate::{client::Data, client::Interface};
 
 const CHANGE_RESOLUTION_VALID_TIMEOUT_SECS: u64 = 15;
+const LAST_DISPLAY_OPTION: &str = "last-display";
 
 #[derive(Clone, Default)]
 pub struct Session<T: InvokeUiSession> {
@@ -61,6 +66,16 @@ pub struct Session<T: InvokeUiSession> {
     pub last_change_display: Arc<Mutex<ChangeDisplayRecord>>,
     pub connection_round_state: Arc<Mutex<ConnectionRoundState>>,
     pub printer_names: Arc<RwLock<HashMap<i32, String>>>,
+    pub record_audio: Arc<Mutex<RecordAudio>>,
+    pub mic_passthrough: Arc<RwLock<bool>>,
+    pub input_recorder: Arc<Mutex<Option<crate::input_script::Recorder>>>,
+    // The abort flag of the running input replay.
+    pub input_replay: Arc<Mutex<Option<Arc<AtomicBool>>>>,
+    // The recent copies of the peer, in the manual clipboard sync.
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    pub remote_clips: Arc<Mutex<crate::clipboard_history::Clips>>,
+    // Started to show the given displays, e.g. in a new window.
+    pub start_with_displays: Arc<AtomicBool>,
 }
 
 #[derive(Clone)]
@@ -264,8 +279,45 @@ impl<T: InvokeUiSession> Session<T> {
         }
     }
 
+    // The map mode if the keyboard layouts of the two sides are the same, the characters are
+    // sent by the translate mode or the legacy mode otherwise.
+    fn get_auto_keyboard_mode(&self) -> Option<KeyboardMode> {
+        #[cfg(any(target_os = "android", target_os = "ios"))]
+        return None;
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        {
+            if self.get_toggle_option(
+                crate::keyboard_layout::PEER_OPTION_DISABLE_AUTO_KEYBOARD_MODE.to_owned(),
+            ) {
+                return None;
+            }
+            let mut modes = vec![];
+            if self.lc.read().unwrap().keyboard_layout_mismatch {
+                // The keys are grabbed by flutter, no translate mode.
+                #[cfg(feature = "flutter")]
+                let translate = crate::keyboard::input_source::get_cur_session_input_source()
+                    != crate::keyboard::input_source::CONFIG_INPUT_SOURCE_2;
+                #[cfg(not(feature = "flutter"))]
+                let translate = true;
+                if translate {
+                    modes.push(KeyboardMode::Translate);
+                }
+                modes.push(KeyboardMode::Legacy);
+            }
+            modes.push(KeyboardMode::Map);
+            let peer_version = self.get_peer_version();
+            let platform = self.peer_platform();
+            modes
+                .into_iter()
+                .find(|mode| is_keyboard_mode_supported(mode, peer_version, &platform))
+        }
+    }
+
     // Caution: This function must be called after peer info is received.
     pub fn get_keyboard_mode(&self) -> String {
+        if let Some(mode) = self.get_auto_keyboard_mode() {
+            return mode.to_string();
+        }
         let mode = self.lc.read().unwrap().keyboard_mode.clone();
         let keyboard_mode = KeyboardMode::from_str(&mode);
 
@@ -358,7 +410,9 @@ impl<T: InvokeUiSession> Session<T> {
         if name == keys::OPTION_ENABLE_FILE_COPY_PASTE {
             self.send(Data::ToggleClipboardFile);
         }
-        if let Some(msg) = msg {
+        if name == crate::gamepad::PEER_OPTION_FORWARD_GAMEPAD {
+            self.send(Data::ForwardGamepad(self.get_toggle_option(name)));
+        } else if let Some(msg) = msg {
             self.send(Data::Message(msg));
         }
     }
@@ -421,6 +475,18 @@ impl<T: InvokeUiSession> Session<T> {
         self.send(Data::Message(msg_out));
     }
 
+    pub fn plug_in_virtual_display_mode(&self, mode: crate::client::VirtualDisplayMode) {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: crate::client::VIRTUAL_DISPLAY_MODE_PLUGIN_ID.to_owned(),
+            content: serde_json::to_vec(&mode).unwrap_or_default().into(),
+            ..Default::default()
+        });
+        let mut msg_out = Message::new();
+        msg_out.set_misc(misc);
+        self.send(Data::Message(msg_out));
+    }
+
     #[cfg(not(feature = "flutter"))]
     pub fn refresh_video(&self, _display: i32) {
         self.send(Data::Message(LoginConfigHandler::refresh()));
@@ -438,6 +504,100 @@ impl<T: InvokeUiSession> Session<T> {
         self.send(Data::TakeScreenshot((display, sid)));
     }
 
+    /// Asks the peer for the folder to upload the files dropped into, see `crate::file_drop`.
+    pub fn query_file_drop(&self, id: i32) {
+        self.send(Data::QueryFileDrop(id));
+    }
+
+    pub fn is_transfer_broker_supported(&self) -> bool {
+        crate::transfer_broker::is_supported(self.lc.read().unwrap().peer_info.as_ref())
+    }
+
+    /// Sends the files of the peer to the peer of `receiver`, which issues the ticket, see
+    /// `crate::transfer_broker`.
+    pub fn broker_transfer(
+        &self,
+        id: i32,
+        paths: Vec<String>,
+        to: String,
+        receiver: Option<&Self>,
+    ) {
+        use crate::transfer_broker::{self, BrokerMessage};
+        let Some(receiver) = receiver else {
+            let error = Some("Not connected to the peer".to_owned());
+            self.send(Data::TransferBroker(BrokerMessage::Done { id, error }));
+            return;
+        };
+        let session = self.clone();
+        let ticket = transfer_broker::request(id, paths, to, move |msg| {
+            session.send(Data::TransferBroker(msg))
+        });
+        receiver.send(Data::TransferBroker(ticket));
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    pub fn is_manual_clipboard_sync(&self) -> bool {
+        self.lc
+            .read()
+            .unwrap()
+            .get_toggle_option(crate::clipboard_history::OPTION_MANUAL_CLIPBOARD_SYNC)
+    }
+
+    /// The recent copies of the peer if `remote`, of this device otherwise.
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    pub fn get_clipboard_history(&self, remote: bool) -> String {
+        if remote {
+            self.remote_clips.lock().unwrap().to_json()
+        } else {
+            crate::clipboard_history::LOCAL.lock().unwrap().to_json()
+        }
+    }
+
+    /// Sends a recent copy of this device to the peer.
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    pub fn push_clip(&self, id: i32) {
+        if !self.is_text_clipboard_required() {
+            return;
+        }
+        let Some(clipboards) = crate::clipboard_history::LOCAL.lock().unwrap().get(id) else {
+            return;
+        };
+        let multi_clipboards = MultiClipboards {
+            clipboards,
+            ..Default::default()
+        };
+        let (version, platform) = self
+            .lc
+            .read()
+            .unwrap()
+            .peer_info
+            .as_ref()
+            .map(|pi| (pi.version.clone(), pi.platform.clone()))
+            .unwrap_or_default();
+        let msg = crate::clipboard::get_msg_if_not_support_multi_clip(
+            &version,
+            &platform,
+            &multi_clipboards,
+        )
+        .unwrap_or_else(|| {
+            let mut msg = Message::new();
+            msg.set_multi_clipboards(multi_clipboards);
+            msg
+        });
+        self.send(Data::Message(msg));
+    }
+
+    /// Sets a recent copy of the peer on this device.
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    pub fn pull_clip(&self, id: i32) {
+        if let Some(clipboards) = self.remote_clips.lock().unwrap().get(id) {
+            crate::clipboard::update_clipboard_of_peer(
+                clipboards,
+                crate::clipboard::ClipboardSide::Client,
+            );
+        }
+    }
+
     pub fn is_recording(&self) -> bool {
         self.lc.read().unwrap().record_state
     }
@@ -601,6 +761,80 @@ impl<T: InvokeUiSession> Session<T> {
         self.send(Data::AddPortForward(pf));
     }
 
+    pub fn is_wol_relay_supported(&self) -> bool {
+        self.lc.read().unwrap().support_wol_relay
+    }
+
+    // Let the peer of this session wake up another peer on its LAN.
+    pub fn send_wol(&self, id: &str) -> ResultType<()> {
+        if !self.is_wol_relay_supported() {
+            bail!("Wake-on-LAN relaying is not allowed by the peer");
+        }
+        let macs = crate::lan::get_wol_macs(id);
+        if macs.is_empty() {
+            bail!("The MAC address of {} is unknown", id);
+        }
+        self.send(Data::Message(crate::lan::make_wol_request(&macs)));
+        Ok(())
+    }
+
+    pub fn get_tunnels(&self) -> Vec<crate::tunnel::TunnelConfig> {
+        crate::tunnel::parse_tunnels(
+            &self.get_option(crate::tunnel::PEER_OPTION_TUNNELS.to_owned()),
+        )
+    }
+
+    fn save_tunnels(&self, tunnels: &Vec<crate::tunnel::TunnelConfig>) {
+        self.set_option(
+            crate::tunnel::PEER_OPTION_TUNNELS.to_owned(),
+            serde_json::to_string(tunnels).unwrap_or_default(),
+        );
+    }
+
+    pub fn add_tunnel(&self, t: crate::tunnel::TunnelConfig) {
+        let mut tunnels = self.get_tunnels();
+        if tunnels.iter().any(|x| x.key() == t.key()) {
+            return;
+        }
+        tunnels.push(t.clone());
+        self.save_tunnels(&tunnels);
+        self.send(Data::AddTunnel(t));
+    }
+
+    pub fn remove_tunnel(&self, t: crate::tunnel::TunnelConfig) {
+        let mut tunnels = self.get_tunnels();
+        tunnels.retain(|x| x.key() != t.key());
+        self.save_tunnels(&tunnels);
+        self.send(Data::RemoveTunnel(t));
+    }
+
+    /// The short authentication string of the secure channel, empty if not secured.
+    pub fn get_sas(&self) -> String {
+        self.lc.read().unwrap().sas.clone().unwrap_or_default()
+    }
+
+    pub fn is_peer_verified(&self) -> bool {
+        let lc = self.lc.read().unwrap();
+        !lc.key_fingerprint.is_empty()
+            && lc.get_option(crate::sas::PEER_OPTION_VERIFIED_KEY) == lc.key_fingerprint
+    }
+
+    /// The peer was verified before, with a different key.
+    pub fn is_peer_key_changed(&self) -> bool {
+        let lc = self.lc.read().unwrap();
+        let verified = lc.get_option(crate::sas::PEER_OPTION_VERIFIED_KEY);
+        !verified.is_empty() && !lc.key_fingerprint.is_empty() && verified != lc.key_fingerprint
+    }
+
+    /// Remember the current key of the peer once the user confirmed the SAS matches.
+    pub fn mark_peer_verified(&self) {
+        let mut lc = self.lc.write().unwrap();
+        let fingerprint = lc.key_fingerprint.clone();
+        if lc.sas.is_some() && !fingerprint.is_empty() {
+            lc.set_option(crate::sas::PEER_OPTION_VERIFIED_KEY.to_owned(), fingerprint);
+        }
+    }
+
     pub fn get_option(&self, k: String) -> String {
         if k.eq("remote_dir") {
             return self.lc.read().unwrap().get_remote_dir();
@@ -741,6 +975,7 @@ impl<T: InvokeUiSession> Session<T> {
 
     pub fn send_key_event(&self, evt: &KeyEvent) {
         // mode: legacy(0), map(1), translate(2), auto(3)
+        self.abort_input_replay("Local input");
 
         let mut msg = evt.clone();
         self.swap_modifier_key(&mut msg);
@@ -824,6 +1059,9 @@ impl<T: InvokeUiSession> Session<T> {
     }
 
     pub fn switch_display(&self, display: i32) {
+        // Flutter, see `session_switch_display()`.
+        #[cfg(not(feature = "flutter"))]
+        self.set_last_display(Some(display));
         let (w, h) = match self.lc.read().unwrap().get_custom_resolution(display) {
             Some((w, h)) => (w, h),
             None => (0, 0),
@@ -853,6 +1091,7 @@ impl<T: InvokeUiSession> Session<T> {
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     pub fn leave(&self, keyboard_mode: String) {
         keyboard::client::change_grab_status(GrabState::Wait, &keyboard_mode);
+        self.abort_input_replay("The remote window lost the focus");
     }
 
     // flutter only TODO new input
@@ -1012,6 +1251,11 @@ impl<T: InvokeUiSession> Session<T> {
         lock_modes: i32,
         down_or_up: bool,
     ) {
+        let event = Self::key_event_from_usb_hid(character, usb_hid, down_or_up);
+        keyboard::client::process_event_with_session(keyboard_mode, &event, Some(lock_modes), self);
+    }
+
+    fn key_event_from_usb_hid(character: &str, usb_hid: i32, down_or_up: bool) -> Event {
         let key = rdev::usb_hid_key_from_code(usb_hid as _);
 
         #[cfg(any(target_os = "android", target_os = "ios"))]
@@ -1045,7 +1289,7 @@ impl<T: InvokeUiSession> Session<T> {
         } else {
             KeyRelease(key)
         };
-        let event = Event {
+        Event {
             time: SystemTime::now(),
             unicode: if character.is_empty() {
                 None
@@ -1066,8 +1310,53 @@ impl<T: InvokeUiSession> Session<T> {
             usb_hid: 0,
             #[cfg(any(target_os = "windows", target_os = "macos"))]
             extra_data: 0,
+        }
+    }
+
+    /// Applies the key remaps and the macros of the peer, None if the key is taken by a macro.
+    pub fn remap_key_event(&self, event: &Event) -> Option<Event> {
+        use crate::key_remap::{KeyRemap, PEER_OPTION_KEY_REMAP};
+        let (key, down) = match event.event_type {
+            KeyPress(key) => (key, true),
+            KeyRelease(key) => (key, false),
+            _ => return Some(event.clone()),
         };
-        keyboard::client::process_event_with_session(keyboard_mode, &event, Some(lock_modes), self);
+        let Some(remap) =
+            KeyRemap::parse(&self.lc.read().unwrap().get_option(PEER_OPTION_KEY_REMAP))
+        else {
+            return Some(event.clone());
+        };
+        if let Some(m) = remap.find_macro(key) {
+            if down {
+                self.run_macro(m.steps.clone());
+            }
+            return None;
+        }
+        if let Some(to) = remap.find_remap(key) {
+            return Some(Self::key_event_from_usb_hid("", to as _, down));
+        }
+        Some(event.clone())
+    }
+
+    fn run_macro(&self, steps: Vec<crate::key_remap::MacroStep>) {
+        use crate::key_remap::{parse_combo, MacroStep, MAX_MACRO_DELAY_MS};
+        let session = self.clone();
+        std::thread::spawn(move || {
+            for step in steps {
+                match step {
+                    MacroStep::Keys(keys) => match parse_combo(&keys) {
+                        Some(c) => {
+                            session.input_key(&c.key, true, true, c.alt, c.ctrl, c.shift, c.command)
+                        }
+                        None => log::warn!("Invalid keys of the macro: {}", keys),
+                    },
+                    MacroStep::Text(text) => session.input_string(&text),
+                    MacroStep::Delay(ms) => {
+                        std::thread::sleep(Duration::from_millis(ms.min(MAX_MACRO_DELAY_MS)))
+                    }
+                }
+            }
+        });
     }
 
     // flutter only TODO new input
@@ -1183,6 +1472,100 @@ impl<T: InvokeUiSession> Session<T> {
         xy
     }
 
+    // The pen of the remote view, a [`crate::pen::PenEvent`] in json.
+    pub fn send_pen(&self, evt: String) {
+        match serde_json::from_str::<crate::pen::PenEvent>(&evt) {
+            Ok(evt) => self.send(Data::Message(evt.to_message())),
+            Err(e) => log::error!("Invalid pen event: {}", e),
+        }
+    }
+
+    // The fingers on the remote view, a [`crate::touch::TouchFrame`] in json.
+    pub fn send_touch(&self, frame: String) {
+        match serde_json::from_str::<crate::touch::TouchFrame>(&frame) {
+            Ok(frame) => self.send(Data::Message(frame.to_message())),
+            Err(e) => log::error!("Invalid touch frame: {}", e),
+        }
+    }
+
+    pub fn start_input_recording(&self) -> ResultType<()> {
+        let displays = match self.lc.read().unwrap().peer_info.as_ref() {
+            Some(pi) => crate::input_script::display_sizes(pi),
+            None => bail!("Not connected"),
+        };
+        let mut recorder = self.input_recorder.lock().unwrap();
+        if recorder.is_some() {
+            bail!("Already recording");
+        }
+        *recorder = Some(crate::input_script::Recorder::new(displays));
+        log::info!("Start recording the input");
+        Ok(())
+    }
+
+    pub fn stop_input_recording(&self, path: &str) -> ResultType<()> {
+        let Some(recorder) = self.input_recorder.lock().unwrap().take() else {
+            bail!("Not recording");
+        };
+        let script = recorder.finish();
+        script.save(path)?;
+        log::info!("Saved {} input events to {}", script.events.len(), path);
+        Ok(())
+    }
+
+    pub fn is_input_recording(&self) -> bool {
+        self.input_recorder.lock().unwrap().is_some()
+    }
+
+    /// Replays the script in the background, until the end or [`Self::abort_input_replay`].
+    pub fn replay_input(&self, path: &str) -> ResultType<()> {
+        let script = crate::input_script::InputScript::load(path)?;
+        match self.lc.read().unwrap().peer_info.as_ref() {
+            Some(pi) => script.check_displays(&crate::input_script::display_sizes(pi))?,
+            None => bail!("Not connected"),
+        }
+        let abort = Arc::new(AtomicBool::new(false));
+        {
+            let mut replay = self.input_replay.lock().unwrap();
+            if replay.is_some() {
+                bail!("Already replaying");
+            }
+            *replay = Some(abort.clone());
+        }
+        log::info!("Replay {} input events of {}", script.events.len(), path);
+        let session = self.clone();
+        std::thread::spawn(move || {
+            let res = crate::input_script::replay(&script, &abort, |msg| {
+                session.send(Data::Message(msg));
+            });
+            session.input_replay.lock().unwrap().take();
+            match res {
+                Ok(()) => log::info!("Input replay done"),
+                Err(e) => {
+                    log::warn!("Input replay stopped: {}", e);
+                    session.msgbox(
+                        "custom-nook-nocancel-hasclose",
+                        "Input replay stopped",
+                        &e.to_string(),
+                        "",
+                    );
+                }
+            }
+        });
+        Ok(())
+    }
+
+    pub fn abort_input_replay(&self, reason: &str) {
+        if let Some(abort) = self.input_replay.lock().unwrap().as_ref() {
+            if !abort.swap(true, Ordering::SeqCst) {
+                log::info!("Abort the input replay: {}", reason);
+            }
+        }
+    }
+
+    pub fn send_special_key(&self, name: String) {
+        self.send(Data::Message(crate::special_keys::to_message(&name)));
+    }
+
     pub fn send_mouse(
         &self,
         mut mask: i32,
@@ -1193,6 +1576,10 @@ impl<T: InvokeUiSession> Session<T> {
         shift: bool,
         command: bool,
     ) {
+        // The moves of the local mouse over the remote window do not stop the replay.
+        if mask != crate::input::MOUSE_TYPE_MOVE {
+            self.abort_input_replay("Local input");
+        }
         #[allow(unused_mut)]
         let mut command = command;
         #[cfg(windows)]
@@ -1369,6 +1756,7 @@ impl<T: InvokeUiSession> Session<T> {
     }
 
     pub fn close(&self) {
+        self.abort_input_replay("The session is closed");
         self.send(Data::Close);
     }
 
@@ -1475,6 +1863,7 @@ impl<T: InvokeUiSession> Session<T> {
 
     #[inline]
     pub fn handle_peer_switch_display(&self, display: &SwitchDisplay) {
+        self.abort_input_replay("The display changed");
         self.ui_handler.switch_display(display);
         self.set_custom_resolution(display);
     }
@@ -1486,6 +1875,50 @@ impl<T: InvokeUiSession> Session<T> {
         self.do_change_resolution(display, width, height);
     }
 
+    // The last display is only remembered and restored in a plain single window session,
+    // not in the multi-window ones, or when started with the given displays.
+    fn is_single_window_session(&self) -> bool {
+        #[cfg(feature = "flutter")]
+        if self.is_multi_ui_session() {
+            return false;
+        }
+        !self.start_with_displays.load(Ordering::SeqCst)
+    }
+
+    // Restored on reconnect, see `handle_peer_info()`. `None` if all displays are shown.
+    // The caller checks that there is no other window of the session.
+    pub fn set_last_display(&self, display: Option<i32>) {
+        if self.start_with_displays.load(Ordering::SeqCst) {
+            return;
+        }
+        let value = display.map(|d| d.to_string()).unwrap_or_default();
+        self.lc
+            .write()
+            .unwrap()
+            .set_option(LAST_DISPLAY_OPTION.to_owned(), value);
+    }
+
+    // The display shown last time, if it is not the current one and still exists.
+    fn get_last_display(&self, pi: &PeerInfo) -> Option<i32> {
+        let display = self
+            .lc
+            .read()
+            .unwrap()
+            .get_option(LAST_DISPLAY_OPTION)
+            .parse::<i32>()
+            .ok()?;
+        if display == pi.current_display || display < 0 || display as usize >= pi.displays.len() {
+            return None;
+        }
+        Some(display)
+    }
+
+    // The remote audio to mux into the recordings, if enabled.
+    pub fn get_record_audio(&self) -> Option<Arc<Mutex<RecordAudio>>> {
+        LocalConfig::get_bool_option(crate::client::OPTION_RECORD_AUDIO)
+            .then(|| self.record_audio.clone())
+    }
+
     #[inline]
     fn try_change_init_resolution(&self, display: i32) {
         let Some((w, h)) = self.lc.read().unwrap().get_custom_resolution(display) else {
@@ -1527,6 +1960,55 @@ impl<T: InvokeUiSession> Session<T> {
         self.send(Data::CloseVoiceCall);
     }
 
+    pub fn query_audio_devices(&self) {
+        self.send_audio_device_request(vec![]);
+    }
+
+    // Captures the audio device of the peer, empty for the default one, remembered for the peer.
+    pub fn select_audio_device(&self, device: String) {
+        self.lc.write().unwrap().set_option(
+            crate::client::PEER_OPTION_AUDIO_DEVICE.to_owned(),
+            device.clone(),
+        );
+        let req = crate::client::AudioDevices {
+            devices: vec![],
+            current: device,
+        };
+        self.send_audio_device_request(serde_json::to_vec(&req).unwrap_or_default());
+    }
+
+    fn send_audio_device_request(&self, content: Vec<u8>) {
+        let mut misc = Misc::new();
+        misc.set_plugin_request(PluginRequest {
+            id: crate::client::AUDIO_DEVICE_PLUGIN_ID.to_owned(),
+            content: content.into(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_misc(misc);
+        self.send(Data::Message(msg));
+    }
+
+    // Receives only the audio of the peer, before the session starts.
+    pub fn set_audio_only(&self) {
+        self.lc.write().unwrap().audio_only = true;
+    }
+
+    // Plays the microphone on the peer without a voice call, the peer has to allow it.
+    pub fn set_mic_passthrough(&self, on: bool) {
+        #[cfg(target_os = "linux")]
+        if on {
+            std::thread::spawn(crate::ipc::start_pa);
+        }
+        *self.mic_passthrough.write().unwrap() = on;
+        self.send(Data::MicPassthrough(on));
+    }
+
+    #[inline]
+    pub fn get_mic_passthrough(&self) -> bool {
+        *self.mic_passthrough.read().unwrap()
+    }
+
     pub fn send_selected_session_id(&self, sid: String) {
         if let Ok(sid) = sid.parse::<u32>() {
             self.lc.write().unwrap().selected_windows_session_id = Some(sid);
@@ -1665,6 +2147,12 @@ pub trait InvokeUiSession: Send + Sync + Clone + 'static + Sized + Default {
     fn is_multi_ui_session(&self) -> bool;
     fn update_record_status(&self, start: bool);
     fn update_empty_dirs(&self, _res: ReadEmptyDirsResponse) {}
+    fn update_audio_devices(&self, _devices: Vec<String>, _current: String) {}
+    fn update_peer_power_state(&self, _state: &str) {}
+    fn integrity_failed(&self, _id: i32, _files: &str) {}
+    fn file_op_response(&self, _is_local: bool, _content: &str) {}
+    fn file_drop_target(&self, _content: &str) {}
+    fn clipboard_paste(&self, _content: &str) {}
     fn printer_request(&self, id: i32, path: String);
     fn handle_screenshot_resp(&self, sid: String, msg: String);
     fn handle_terminal_response(&self, response: TerminalResponse);
@@ -1693,6 +2181,11 @@ impl<T: InvokeUiSession> Interface for Session<T> {
     }
 
     fn send(&self, data: Data) {
+        if let Data::Message(msg) = &data {
+            if let Some(recorder) = self.input_recorder.lock().unwrap().as_mut() {
+                recorder.record(msg);
+            }
+        }
         if let Some(sender) = self.sender.read().unwrap().as_ref() {
             sender.send(data).ok();
         }
@@ -1740,6 +2233,12 @@ impl<T: InvokeUiSession> Interface for Session<T> {
                 self.msgbox("error", "Error", msg, "");
                 return;
             }
+            if !self.is_view_camera() && self.is_single_window_session() {
+                if let Some(display) = self.get_last_display(&pi) {
+                    pi.current_display = display;
+                    self.switch_display(display);
+                }
+            }
             self.try_change_init_resolution(pi.current_display);
             let p = self.lc.read().unwrap().should_auto_login();
             if !p.is_empty() {
@@ -1861,14 +2360,14 @@ impl<T: InvokeUiSession> Session<T> {
 
 #[tokio::main(flavor = "current_thread")]
 pub async fn io_loop<T: InvokeUiSession>(handler: Session<T>, round: u32) {
-    #[cfg(any(target_os = "android", target_os = "ios"))]
+    #[cfg(target_os = "android")]
     let (sender, receiver) = mpsc::unbounded_channel::<Data>();
-    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    #[cfg(not(target_os = "android"))]
     let (sender, mut receiver) = mpsc::unbounded_channel::<Data>();
     *handler.sender.write().unwrap() = Some(sender.clone());
     let token = LocalConfig::get_option("access_token");
     let key = crate::get_key(false).await;
-    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    #[cfg(not(target_os = "android"))]
     if handler.is_port_forward() {
         if handler.is_rdp() {
             let port = handler
@@ -1959,7 +2458,7 @@ pub async fn io_loop<T: InvokeUiSession>(handler: Session<T>, round: u32) {
     remote.sync_jobs_status_to_local().await;
 }
 
-#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[cfg(not(target_os = "android"))]
 async fn start_one_port_forward<T: InvokeUiSession>(
     handler: Session<T>,
     port: i32,
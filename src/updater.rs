@@ -1,5 +1,8 @@
 use crate::{common::do_check_software_update, hbbs_http::create_http_client};
 use hbb_common::{bail, config, log, ResultType};
+#[cfg(target_os = "windows")]
+pub mod manifest;
+
 use std::{
     io::Write,
     path::PathBuf,
@@ -18,6 +21,8 @@ enum UpdateMsg {
 
 lazy_static::lazy_static! {
     static ref TX_MSG : Mutex<Sender<UpdateMsg>> = Mutex::new(start_auto_update_check());
+    // The verified package of the update URL waiting for an idle period: is msi, version, path.
+    static ref PENDING: Mutex<Option<(bool, String, PathBuf)>> = Default::default();
 }
 
 static CONTROLLING_SESSION_COUNT: AtomicUsize = AtomicUsize::new(0);
@@ -78,7 +83,7 @@ fn has_no_controlling_conns() -> bool {
 fn start_auto_update_check() -> Sender<UpdateMsg> {
     let (tx, rx) = channel();
     std::thread::spawn(move || start_auto_update_check_(rx));
-    return rx;
+    return tx;
 }
 
 fn start_auto_update_check_(rx_msg: Receiver<UpdateMsg>) {
@@ -89,12 +94,21 @@ fn start_auto_update_check_(rx_msg: Receiver<UpdateMsg>) {
 
     const MIN_INTERVAL: Duration = Duration::from_secs(60 * 10);
     const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 30);
+    const PENDING_INTERVAL: Duration = Duration::from_secs(60);
     let mut last_check_time = Instant::now();
     let mut check_interval = DUR_ONE_DAY;
     loop {
-        let recv_res = rx_msg.recv_timeout(check_interval);
+        let has_pending = PENDING.lock().unwrap().is_some();
+        let recv_res = rx_msg.recv_timeout(if has_pending {
+            check_interval.min(PENDING_INTERVAL)
+        } else {
+            check_interval
+        });
         match &recv_res {
             Ok(UpdateMsg::CheckUpdate) | Err(_) => {
+                if has_pending {
+                    install_pending();
+                }
                 if last_check_time.elapsed() < MIN_INTERVAL {
                     // log::debug!("Update check skipped due to minimum interval.");
                     continue;
@@ -123,6 +137,14 @@ fn check_update(manually: bool) -> ResultType<()> {
     if !(manually || config::Config::get_bool_option(config::keys::OPTION_ALLOW_AUTO_UPDATE)) {
         return Ok(());
     }
+    #[cfg(target_os = "windows")]
+    if manifest::has_update_url() {
+        if let Some((version, file_path)) = manifest::check(is_msi)? {
+            *PENDING.lock().unwrap() = Some((is_msi, version, file_path));
+            install_pending();
+        }
+        return Ok(());
+    }
     if !do_check_software_update().is_ok() {
         // ignore
         return Ok(());
@@ -196,6 +218,19 @@ fn check_update(manually: bool) -> ResultType<()> {
     Ok(())
 }
 
+// Installs the package of the update URL once no one is connected, or it waits for the next check.
+fn install_pending() {
+    if !has_no_active_conns() {
+        log::debug!("Update deferred until no active connections.");
+        return;
+    }
+    let _pending = PENDING.lock().unwrap().take();
+    #[cfg(target_os = "windows")]
+    if let Some((is_msi, version, file_path)) = _pending {
+        update_new_version(is_msi, &version, &file_path);
+    }
+}
+
 #[cfg(target_os = "windows")]
 fn update_new_version(is_msi: bool, version: &str, file_path: &PathBuf) {
     log::debug!(
@@ -0,0 +1,330 @@
+//! Updates from a self-hosted update URL instead of the GitHub releases.
+//!
+//! The source is `update.toml` in the system config directory, only read if it is owned by the
+//! admin, never the options which the users can set:
+//!
+//! ```toml
+//! url = "https://example.com/rustdesk.json"
+//! public-key = "<base64 ed25519 public key of the updates>"
+//! ```
+//!
+//! `url` points to a JSON manifest, `<url>.sig` is its detached signature:
+//!
+//! ```json
+//! {
+//!   "version": "1.4.4",
+//!   "rollout": 20,
+//!   "packages": {
+//!     "windows-x86_64-exe": {
+//!       "url": "https://example.com/rustdesk-1.4.4-x86_64.exe",
+//!       "size": 24117248,
+//!       "signature": "<base64 detached signature of the package>",
+//!       "deltas": [{"from": "1.4.3", "url": "https://example.com/1.4.3-1.4.4.delta", "block_size": 65536}]
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! The signatures are ed25519 by the key of `public-key`, which is required. `rollout` is the
+//! percentage of the devices which take the version, by the hash of their uuid and the version,
+//! so the same devices go first until it is raised to 100.
+//!
+//! A delta is the JSON list of the `delta_sync::Op` which build the package from the package
+//! of the running version, kept from the last update. The result is capped at `size` of the
+//! package and checked by its signature as a full download is.
+//!
+//! The downloads are streamed to the disk and stopped once they exceed `size`, so a
+//! compromised update server can not make the service run out of memory.
+
+use crate::{delta_sync::Op, hbbs_http::create_http_client};
+use hbb_common::{
+    bail, config::Config, get_version_number, log, sodiumoxide::crypto::sign, toml, ResultType,
+};
+use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+const SOURCE_FILE_NAME: &str = "update.toml";
+const CACHE_DIR: &str = "updates";
+// The manifest and its signature.
+const MAX_MANIFEST_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct Source {
+    url: String,
+    public_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    version: String,
+    #[serde(default = "default_rollout")]
+    rollout: u32,
+    #[serde(default)]
+    packages: HashMap<String, Package>,
+}
+
+fn default_rollout() -> u32 {
+    100
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    url: String,
+    size: u64,
+    signature: String,
+    #[serde(default)]
+    deltas: Vec<Delta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Delta {
+    from: String,
+    url: String,
+    block_size: u64,
+}
+
+fn get_source() -> Option<Source> {
+    let path = crate::deploy_config::system_config_dir().join(SOURCE_FILE_NAME);
+    let text = crate::deploy_config::read_system_file(&path)?;
+    match toml::from_str::<Source>(&text) {
+        Ok(source) if !source.url.is_empty() => Some(source),
+        Ok(_) => None,
+        Err(err) => {
+            log::error!("Invalid {:?}: {}", path, err);
+            None
+        }
+    }
+}
+
+pub fn has_update_url() -> bool {
+    get_source().is_some()
+}
+
+fn verify(data: &[u8], signature: &str, key: &str) -> ResultType<()> {
+    let Some(pk) = crate::common::get_rs_pk(key) else {
+        bail!("Invalid public key of the updates");
+    };
+    let Some(sig) = sign::Signature::from_bytes(&crate::decode64(signature.trim())?).ok() else {
+        bail!("Invalid signature");
+    };
+    if !sign::verify_detached(&sig, data, &pk) {
+        bail!("Signature mismatch");
+    }
+    Ok(())
+}
+
+fn in_rollout(uuid: &[u8], version: &str, rollout: u32) -> bool {
+    let mut hasher = Sha256::new();
+    hasher.update(uuid);
+    hasher.update(version);
+    let hash = hasher.finalize();
+    let bucket = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]) % 100;
+    bucket < rollout
+}
+
+// `size` is the size of the package, the delta can not build more.
+fn apply_delta(old: &[u8], block_size: u64, ops: &[Op], size: u64) -> ResultType<Vec<u8>> {
+    if block_size == 0 {
+        bail!("Invalid block size");
+    }
+    let mut new = Vec::with_capacity(size.min(old.len() as u64) as usize);
+    for op in ops {
+        let data = match op {
+            Op::Copy { block, count } => {
+                let Some((start, end)) = (*block as u64)
+                    .checked_mul(block_size)
+                    .zip((*count as u64).checked_mul(block_size))
+                    .and_then(|(start, len)| Some((start, start.checked_add(len)?)))
+                else {
+                    bail!("Block out of range");
+                };
+                if start >= old.len() as u64 {
+                    bail!("Block out of range");
+                }
+                &old[start as usize..end.min(old.len() as u64) as usize]
+            }
+            Op::Data(data) => data.as_slice(),
+        };
+        if new.len() as u64 + data.len() as u64 > size {
+            bail!("The delta builds more than {} bytes", size);
+        }
+        new.extend_from_slice(data);
+    }
+    Ok(new)
+}
+
+fn package_key(is_msi: bool) -> String {
+    format!(
+        "{}-{}-{}",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        if is_msi { "msi" } else { "exe" }
+    )
+}
+
+fn cache_path(key: &str, version: &str, is_msi: bool) -> PathBuf {
+    Config::path(CACHE_DIR).join(format!(
+        "{}-{}.{}",
+        key,
+        version,
+        if is_msi { "msi" } else { "exe" }
+    ))
+}
+
+fn get_response(url: &str) -> ResultType<impl Read> {
+    let response = create_http_client().get(url).send()?;
+    if !response.status().is_success() {
+        bail!("Failed to get {}: {}", url, response.status());
+    }
+    Ok(response)
+}
+
+// At most `max` bytes.
+fn get(url: &str, max: u64) -> ResultType<Vec<u8>> {
+    let mut data = Vec::new();
+    get_response(url)?
+        .take(max.saturating_add(1))
+        .read_to_end(&mut data)?;
+    if data.len() as u64 > max {
+        bail!("{} is larger than {} bytes", url, max);
+    }
+    Ok(data)
+}
+
+// Exactly `size` bytes, streamed to `path`.
+fn download(url: &str, path: &Path, size: u64) -> ResultType<()> {
+    let mut file = File::create(path)?;
+    let len = std::io::copy(
+        &mut get_response(url)?.take(size.saturating_add(1)),
+        &mut file,
+    )?;
+    if len != size {
+        bail!("Size mismatch of {}", url);
+    }
+    Ok(())
+}
+
+// The package built from the delta of the running version, if any works.
+fn try_deltas(package: &Package, old: &PathBuf, key: &str) -> Option<Vec<u8>> {
+    let delta = package.deltas.iter().find(|d| d.from == crate::VERSION)?;
+    let old = std::fs::read(old).ok()?;
+    // The ops carry at most the data of the package in base64, besides the copies.
+    let max = (package.size.saturating_mul(4) / 3).saturating_add(MAX_MANIFEST_SIZE);
+    let res = get(&delta.url, max).and_then(|ops| {
+        let ops: Vec<Op> = serde_json::from_slice(&ops)?;
+        let new = apply_delta(&old, delta.block_size, &ops, package.size)?;
+        verify(&new, &package.signature, key)?;
+        Ok(new)
+    });
+    match res {
+        Ok(new) => {
+            log::info!("Update built from the delta {}", delta.url);
+            Some(new)
+        }
+        Err(err) => {
+            log::warn!("Failed to update by the delta {}: {}", delta.url, err);
+            None
+        }
+    }
+}
+
+/// Checks the update URL, returns the version and the verified package to install if any.
+pub fn check(is_msi: bool) -> ResultType<Option<(String, PathBuf)>> {
+    let Some(source) = get_source() else {
+        bail!("No update URL");
+    };
+    let (url, key) = (source.url, source.public_key);
+    if key.is_empty() {
+        bail!("No public key of the updates");
+    }
+    let data = get(&url, MAX_MANIFEST_SIZE)?;
+    let sig = String::from_utf8(get(&format!("{}.sig", url), MAX_MANIFEST_SIZE)?)?;
+    verify(&data, &sig, &key)?;
+    let manifest: Manifest = serde_json::from_slice(&data)?;
+    if get_version_number(&manifest.version) <= get_version_number(crate::VERSION) {
+        log::debug!("No update available.");
+        return Ok(None);
+    }
+    if !in_rollout(&hbb_common::get_uuid(), &manifest.version, manifest.rollout) {
+        log::info!(
+            "Update {} is not rolled out to this device yet, {}%",
+            manifest.version,
+            manifest.rollout
+        );
+        return Ok(None);
+    }
+    let package_key = package_key(is_msi);
+    let Some(package) = manifest.packages.get(&package_key) else {
+        bail!("No package {} of {}", package_key, manifest.version);
+    };
+    let path = cache_path(&package_key, &manifest.version, is_msi);
+    let old = cache_path(&package_key, crate::VERSION, is_msi);
+    let verified = std::fs::read(&path).map_or(false, |data| {
+        verify(&data, &package.signature, &key).is_ok()
+    });
+    if !verified {
+        std::fs::create_dir_all(Config::path(CACHE_DIR))?;
+        match try_deltas(package, &old, &key) {
+            Some(data) => std::fs::write(&path, data)?,
+            None => {
+                let res = download(&package.url, &path, package.size)
+                    .and_then(|_| verify(&std::fs::read(&path)?, &package.signature, &key));
+                if let Err(err) = res {
+                    std::fs::remove_file(&path).ok();
+                    return Err(err);
+                }
+            }
+        }
+    }
+    // Only the packages of the running and the new version are kept, the former for the deltas.
+    if let Ok(entries) = std::fs::read_dir(Config::path(CACHE_DIR)) {
+        for entry in entries.flatten() {
+            if entry.path() != path && entry.path() != old {
+                std::fs::remove_file(entry.path()).ok();
+            }
+        }
+    }
+    log::info!("Update {} downloaded and verified", manifest.version);
+    Ok(Some((manifest.version, path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_delta() {
+        let old = b"aaaabbbbcc".to_vec();
+        let ops = vec![
+            Op::Copy { block: 1, count: 1 },
+            Op::Data(b"xy".to_vec()),
+            Op::Copy { block: 2, count: 1 },
+        ];
+        assert_eq!(apply_delta(&old, 4, &ops, 8).unwrap(), b"bbbbxycc".to_vec());
+        assert!(apply_delta(&old, 4, &ops, 7).is_err());
+        assert!(apply_delta(&old, 4, &[Op::Copy { block: 3, count: 1 }], 8).is_err());
+        let ops = [Op::Copy {
+            block: 0,
+            count: u32::MAX,
+        }];
+        assert!(apply_delta(&old, u64::MAX, &ops, 8).is_err());
+        assert_eq!(apply_delta(&old, 1 << 20, &ops, 10).unwrap(), old);
+    }
+
+    #[test]
+    fn test_in_rollout() {
+        assert!(!in_rollout(b"uuid", "1.4.4", 0));
+        assert!(in_rollout(b"uuid", "1.4.4", 100));
+        let n = (0..1000u32)
+            .filter(|i| in_rollout(&i.to_be_bytes(), "1.4.4", 20))
+            .count();
+        assert!(n > 100 && n < 300);
+    }
+}
@@ -49,6 +49,7 @@ pub fn get_platform_additions() -> serde_json::Map<String, serde_json::Value> {
     map.insert("idd_impl".into(), serde_json::json!(IDD_IMPL));
     match IDD_IMPL {
         IDD_IMPL_RUSTDESK => {
+            map.insert("virtual_display_modes".into(), serde_json::json!(true));
             let virtual_displays = rustdesk_idd::get_virtual_displays();
             if !virtual_displays.is_empty() {
                 map.insert(
@@ -77,6 +78,14 @@ pub fn plug_in_monitor(idx: u32, modes: Vec<virtual_display::MonitorMode>) -> Re
     }
 }
 
+// Plugs in the virtual display with the mode, or changes the mode if it is already plugged in.
+pub fn plug_in_monitor_mode(idx: u32, mode: virtual_display::MonitorMode) -> ResultType<()> {
+    match IDD_IMPL {
+        IDD_IMPL_RUSTDESK => rustdesk_idd::plug_in_or_update_index_mode(idx, mode),
+        _ => bail!("Unsupported virtual display implementation."),
+    }
+}
+
 pub fn plug_out_monitor(index: i32, force_all: bool, force_one: bool) -> ResultType<()> {
     match IDD_IMPL {
         IDD_IMPL_RUSTDESK => {
@@ -162,6 +171,13 @@ pub mod rustdesk_idd {
         }
 
         fn install_update_driver(&mut self) -> ResultType<()> {
+            if crate::platform::privilege_broker::is_worker() {
+                crate::platform::privilege_broker::request(
+                    crate::platform::privilege_broker::CAP_INSTALL_DRIVER,
+                )?;
+                self.is_driver_installed = true;
+                return Ok(());
+            }
             if let Err(e) = virtual_display::create_device() {
                 if !e.to_string().contains("Device is already created") {
                     bail!("Create device failed {}", e);
@@ -282,6 +298,24 @@ pub mod rustdesk_idd {
         Ok(())
     }
 
+    pub fn plug_in_or_update_index_mode(
+        idx: u32,
+        mode: virtual_display::MonitorMode,
+    ) -> ResultType<()> {
+        let is_plugged_in = VIRTUAL_DISPLAY_MANAGER
+            .lock()
+            .unwrap()
+            .peer_index_name
+            .contains_key(&idx);
+        if !is_plugged_in {
+            return plug_in_index_modes(idx, vec![mode]);
+        }
+        if !change_mode(idx, mode) {
+            bail!("Failed to change the mode of virtual display {}", idx);
+        }
+        Ok(())
+    }
+
     pub fn reset_all() -> ResultType<()> {
         if super::is_virtual_display_supported() {
             return Ok(());
@@ -350,11 +384,18 @@ pub mod rustdesk_idd {
     }
 
     fn change_resolution(index: u32, w: u32, h: u32) -> bool {
-        let modes = [virtual_display::MonitorMode {
-            width: w,
-            height: h,
-            sync: 60,
-        }];
+        change_mode(
+            index,
+            virtual_display::MonitorMode {
+                width: w,
+                height: h,
+                sync: 60,
+            },
+        )
+    }
+
+    fn change_mode(index: u32, mode: virtual_display::MonitorMode) -> bool {
+        let modes = [mode];
         match virtual_display::update_monitor_modes(index, &modes) {
             Ok(_) => true,
             Err(e) => {